@@ -1,12 +1,15 @@
 use alloc::string::String;
 use axerrno::AxResult;
-use axfs::fops::{Directory, File};
+use axfs::fops::{Directory, File, Watcher};
+use core::time::Duration;
 
 pub use axfs::fops::DirEntry as AxDirEntry;
+pub use axfs::fops::EventKind as AxWatchEventKind;
 pub use axfs::fops::FileAttr as AxFileAttr;
 pub use axfs::fops::FilePerm as AxFilePerm;
 pub use axfs::fops::FileType as AxFileType;
 pub use axfs::fops::OpenOptions as AxOpenOptions;
+pub use axfs::fops::WatchEvent as AxWatchEvent;
 pub use axio::SeekFrom as AxSeekFrom;
 
 #[cfg(feature = "myfs")]
@@ -18,6 +21,10 @@ pub struct AxFileHandle(File);
 /// A handle to an opened directory.
 pub struct AxDirHandle(Directory);
 
+/// A handle to a watcher, whose clones share its watches and events.
+#[derive(Clone)]
+pub struct AxWatcherHandle(Watcher);
+
 pub fn ax_open_file(path: &str, opts: &AxOpenOptions) -> AxResult<AxFileHandle> {
     Ok(AxFileHandle(File::open(path, opts)?))
 }
@@ -58,6 +65,14 @@ pub fn ax_file_attr(file: &AxFileHandle) -> AxResult<AxFileAttr> {
     file.0.get_attr()
 }
 
+pub fn ax_set_file_times(
+    file: &AxFileHandle,
+    atime: Option<Duration>,
+    mtime: Option<Duration>,
+) -> AxResult {
+    file.0.set_times(atime, mtime)
+}
+
 pub fn ax_read_dir(dir: &mut AxDirHandle, dirents: &mut [AxDirEntry]) -> AxResult<usize> {
     dir.0.read_dir(dirents)
 }
@@ -78,6 +93,22 @@ pub fn ax_rename(old: &str, new: &str) -> AxResult {
     axfs::api::rename(old, new)
 }
 
+pub fn ax_symlink_attr(path: &str) -> AxResult<AxFileAttr> {
+    Ok(*axfs::api::symlink_metadata(path)?.raw_attr())
+}
+
+pub fn ax_read_link(path: &str) -> AxResult<String> {
+    axfs::api::read_link(path)
+}
+
+pub fn ax_symlink(original: &str, link: &str) -> AxResult {
+    axfs::api::symlink(original, link)
+}
+
+pub fn ax_hard_link(original: &str, link: &str) -> AxResult {
+    axfs::api::hard_link(original, link)
+}
+
 pub fn ax_current_dir() -> AxResult<String> {
     axfs::api::current_dir()
 }
@@ -85,3 +116,35 @@ pub fn ax_current_dir() -> AxResult<String> {
 pub fn ax_set_current_dir(path: &str) -> AxResult {
     axfs::api::set_current_dir(path)
 }
+
+pub fn ax_mount(source: &str, target: &str, fs_type: &str) -> AxResult {
+    axfs::api::mount(source, target, fs_type)
+}
+
+pub fn ax_mount_overlay(lower: &str, upper: &str, target: &str) -> AxResult {
+    axfs::api::mount_overlay(lower, upper, target)
+}
+
+pub fn ax_umount(target: &str, detach: bool) -> AxResult {
+    if detach {
+        axfs::api::umount_detach(target)
+    } else {
+        axfs::api::umount(target)
+    }
+}
+
+pub fn ax_create_watcher() -> AxWatcherHandle {
+    AxWatcherHandle(Watcher::new())
+}
+
+pub fn ax_add_watch(watcher: &AxWatcherHandle, path: &str, mask: u32) -> AxResult<usize> {
+    watcher.0.add_watch(path, mask)
+}
+
+pub fn ax_remove_watch(watcher: &AxWatcherHandle, wd: usize) -> AxResult {
+    watcher.0.remove_watch(wd)
+}
+
+pub fn ax_next_watch_event(watcher: &AxWatcherHandle, wait: bool) -> AxResult<AxWatchEvent> {
+    watcher.0.next_event(wait)
+}
@@ -45,4 +45,8 @@ pub use self::task::*;
 pub use self::time::*;
 
 pub use axhal::misc::terminate as ax_terminate;
+
+pub fn ax_fill_random(buf: &mut [u8]) -> crate::AxResult {
+    axhal::random::fill_bytes(buf).or_else(|e| axerrno::ax_err!(Unsupported, e))
+}
 pub use axio::PollState as AxPollState;
@@ -1,7 +1,15 @@
 use crate::io::AxPollState;
 use axerrno::AxResult;
 use axnet::{UdpSocket, TcpSocket};
-use core::net::{IpAddr, SocketAddr};
+use core::net::{IpAddr, Ipv4Addr, SocketAddr};
+use core::task::{Context, Poll};
+
+pub use axnet::BridgeStats as AxBridgeStats;
+pub use axnet::ForwardStats as AxForwardStats;
+pub use axnet::InterfaceConfig as AxNetIfConfig;
+pub use axnet::NatConnection as AxNatConnection;
+pub use axnet::NatProtocol as AxNatProtocol;
+pub use axnet::PortForward as AxPortForward;
 
 /// A handle to a TCP socket.
 pub struct AxTcpSocketHandle(TcpSocket);
@@ -30,6 +38,13 @@ pub fn ax_tcp_set_nonblocking(socket: &AxTcpSocketHandle, nonblocking: bool) ->
     Ok(())
 }
 
+pub fn ax_tcp_set_read_timeout(
+    socket: &AxTcpSocketHandle,
+    timeout: Option<core::time::Duration>,
+) -> AxResult {
+    socket.0.set_recv_timeout(timeout)
+}
+
 pub fn ax_tcp_connect(socket: &AxTcpSocketHandle, addr: SocketAddr) -> AxResult {
     socket.0.connect(addr)
 }
@@ -38,8 +53,8 @@ pub fn ax_tcp_bind(socket: &AxTcpSocketHandle, addr: SocketAddr) -> AxResult {
     socket.0.bind(addr)
 }
 
-pub fn ax_tcp_listen(socket: &AxTcpSocketHandle, _backlog: usize) -> AxResult {
-    socket.0.listen()
+pub fn ax_tcp_listen(socket: &AxTcpSocketHandle, backlog: usize) -> AxResult {
+    socket.0.listen(backlog)
 }
 
 pub fn ax_tcp_accept(socket: &AxTcpSocketHandle) -> AxResult<(AxTcpSocketHandle, SocketAddr)> {
@@ -60,6 +75,37 @@ pub fn ax_tcp_poll(socket: &AxTcpSocketHandle) -> AxResult<AxPollState> {
     socket.0.poll()
 }
 
+pub fn ax_tcp_poll_connected(socket: &AxTcpSocketHandle, cx: &mut Context<'_>) -> Poll<AxResult> {
+    socket.0.poll_connected(cx)
+}
+
+pub fn ax_tcp_poll_accept(
+    socket: &AxTcpSocketHandle,
+    cx: &mut Context<'_>,
+) -> Poll<AxResult<(AxTcpSocketHandle, SocketAddr)>> {
+    socket.0.poll_accept(cx).map(|res| {
+        let new_sock = res?;
+        let addr = new_sock.peer_addr()?;
+        Ok((AxTcpSocketHandle(new_sock), addr))
+    })
+}
+
+pub fn ax_tcp_poll_send(
+    socket: &AxTcpSocketHandle,
+    cx: &mut Context<'_>,
+    buf: &[u8],
+) -> Poll<AxResult<usize>> {
+    socket.0.poll_send(cx, buf)
+}
+
+pub fn ax_tcp_poll_recv(
+    socket: &AxTcpSocketHandle,
+    cx: &mut Context<'_>,
+    buf: &mut [u8],
+) -> Poll<AxResult<usize>> {
+    socket.0.poll_recv(cx, buf)
+}
+
 pub fn ax_tcp_shutdown(socket: &AxTcpSocketHandle) -> AxResult {
     socket.0.shutdown()
 }
@@ -117,6 +163,31 @@ pub fn ax_udp_poll(socket: &AxUdpSocketHandle) -> AxResult<AxPollState> {
     socket.0.poll()
 }
 
+pub fn ax_udp_poll_recv_from(
+    socket: &AxUdpSocketHandle,
+    cx: &mut Context<'_>,
+    buf: &mut [u8],
+) -> Poll<AxResult<(usize, SocketAddr)>> {
+    socket.0.poll_recv_from(cx, buf)
+}
+
+pub fn ax_udp_poll_send_to(
+    socket: &AxUdpSocketHandle,
+    cx: &mut Context<'_>,
+    buf: &[u8],
+    addr: SocketAddr,
+) -> Poll<AxResult<usize>> {
+    socket.0.poll_send_to(cx, buf, addr)
+}
+
+pub fn ax_udp_join_multicast_v4(socket: &AxUdpSocketHandle, multiaddr: Ipv4Addr) -> AxResult {
+    socket.0.join_multicast_v4(multiaddr)
+}
+
+pub fn ax_udp_leave_multicast_v4(socket: &AxUdpSocketHandle, multiaddr: Ipv4Addr) -> AxResult {
+    socket.0.leave_multicast_v4(multiaddr)
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // Miscellaneous
 ////////////////////////////////////////////////////////////////////////////////
@@ -125,7 +196,139 @@ pub fn ax_dns_query(domain_name: &str) -> AxResult<alloc::vec::Vec<IpAddr>> {
     axnet::dns_query(domain_name)
 }
 
+pub fn ax_dns_set_servers(servers: &[IpAddr]) -> AxResult {
+    axnet::dns_set_servers(servers);
+    Ok(())
+}
+
+pub fn ax_dns_servers() -> alloc::vec::Vec<IpAddr> {
+    axnet::dns_servers()
+}
+
+pub fn ax_dns_flush_cache() -> AxResult {
+    axnet::dns_flush_cache();
+    Ok(())
+}
+
 pub fn ax_poll_interfaces() -> AxResult {
     axnet::poll_interfaces();
     Ok(())
 }
+
+////////////////////////////////////////////////////////////////////////////////
+// Interfaces
+////////////////////////////////////////////////////////////////////////////////
+
+pub fn ax_net_add_vlan(
+    vid: u16,
+    ip: Ipv4Addr,
+    prefix_len: u8,
+    gateway: Option<Ipv4Addr>,
+) -> AxResult<alloc::string::String> {
+    axnet::add_vlan_interface(vid, ip, prefix_len, gateway)
+}
+
+pub fn ax_net_remove_vlan(vid: u16) -> AxResult {
+    axnet::remove_vlan_interface(vid)
+}
+
+pub fn ax_net_interfaces() -> alloc::vec::Vec<AxNetIfConfig> {
+    axnet::interfaces()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Bridge
+////////////////////////////////////////////////////////////////////////////////
+
+pub fn ax_net_bridge_start(flood_unknown: bool) -> AxResult {
+    axnet::bridge_start(flood_unknown)
+}
+
+pub fn ax_net_bridge_stop() -> AxResult {
+    axnet::bridge_stop()
+}
+
+pub fn ax_net_bridge_stats() -> Option<AxBridgeStats> {
+    axnet::bridge_stats()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// IP forwarding and NAT
+////////////////////////////////////////////////////////////////////////////////
+
+pub fn ax_ip_forward_start(addrs: &[(Ipv4Addr, u8)]) -> AxResult {
+    axnet::ip_forward_start(addrs)
+}
+
+pub fn ax_ip_forward_stop() -> AxResult {
+    axnet::ip_forward_stop()
+}
+
+pub fn ax_ip_forward_stats() -> Option<AxForwardStats> {
+    axnet::ip_forward_stats()
+}
+
+pub fn ax_nat_set_masquerade(enabled: bool) -> AxResult {
+    axnet::nat_set_masquerade(enabled)
+}
+
+pub fn ax_nat_add_port_forward(rule: AxPortForward) -> AxResult {
+    axnet::nat_add_port_forward(rule)
+}
+
+pub fn ax_nat_remove_port_forward(protocol: AxNatProtocol, external_port: u16) -> AxResult {
+    axnet::nat_remove_port_forward(protocol, external_port)
+}
+
+pub fn ax_nat_connections() -> alloc::vec::Vec<AxNatConnection> {
+    axnet::nat_connections()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// mDNS responder
+////////////////////////////////////////////////////////////////////////////////
+
+pub fn ax_mdns_start(hostname: &str) -> AxResult {
+    axnet::mdns_start(hostname)
+}
+
+pub fn ax_mdns_add_service(instance: &str, service: &str, port: u16, txt: &[&str]) -> AxResult {
+    use alloc::string::ToString;
+    axnet::mdns_add_service(axnet::MdnsService {
+        instance: instance.to_string(),
+        service: service.to_string(),
+        port,
+        txt: txt.iter().map(|s| s.to_string()).collect(),
+    })
+}
+
+pub fn ax_mdns_stop() -> AxResult {
+    axnet::mdns_stop()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Packet capture
+////////////////////////////////////////////////////////////////////////////////
+
+pub fn ax_net_capture_start(filter: &str, capacity: usize) -> AxResult {
+    let filter = axnet::CaptureFilter::parse(filter)?;
+    axnet::start_capture(filter, capacity);
+    Ok(())
+}
+
+pub fn ax_net_capture_stop() -> AxResult {
+    axnet::stop_capture();
+    Ok(())
+}
+
+pub fn ax_net_capture_dump(clear: bool) -> alloc::vec::Vec<u8> {
+    axnet::dump_capture(clear)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Benchmarks
+////////////////////////////////////////////////////////////////////////////////
+
+pub fn ax_net_bench_tcp_receive(port: u16, zero_copy: bool) -> AxResult {
+    axnet::bench_tcp_receive(port, zero_copy)
+}
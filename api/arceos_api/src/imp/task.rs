@@ -70,6 +70,20 @@ cfg_task! {
         }
     }
 
+    pub fn ax_spawn_async<F>(fut: F)
+    where
+        F: core::future::Future<Output = ()> + Send + 'static,
+    {
+        axtask::executor::spawn(fut)
+    }
+
+    pub fn ax_block_on<F>(fut: F)
+    where
+        F: core::future::Future<Output = ()>,
+    {
+        axtask::executor::block_on(fut)
+    }
+
     pub fn ax_wait_for_exit(task: AxTaskHandle) -> Option<i32> {
         task.inner.join()
     }
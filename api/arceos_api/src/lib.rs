@@ -32,6 +32,11 @@ pub mod sys {
     define_api! {
         /// Shutdown the whole system and all CPUs.
         pub fn ax_terminate() -> !;
+        /// Fills the buffer with cryptographically secure random bytes.
+        ///
+        /// Fails with [`AxError::Unsupported`] if there is no entropy source
+        /// to seed the random number generator.
+        pub fn ax_fill_random(buf: &mut [u8]) -> AxResult;
     }
 }
 
@@ -154,6 +159,13 @@ pub mod task {
         /// Waits for the given task to exit, and returns its exit code (the
         /// argument of [`ax_exit`]).
         pub fn ax_wait_for_exit(task: AxTaskHandle) -> Option<i32>;
+        /// Spawns a future onto the async executor.
+        ///
+        /// The future is run by tasks calling [`ax_block_on`].
+        pub fn ax_spawn_async(fut: impl core::future::Future<Output = ()> + Send + 'static);
+        /// Runs the async executor on the current task until the given future
+        /// completes.
+        pub fn ax_block_on(fut: impl core::future::Future<Output = ()>);
         /// Sets the priority of the current task.
         pub fn ax_set_current_priority(prio: isize) -> crate::AxResult;
         /// Sets the cpu affinity of the current task.
@@ -192,6 +204,9 @@ pub mod fs {
         pub type AxFilePerm;
         pub type AxDirEntry;
         pub type AxSeekFrom;
+        pub type AxWatcherHandle;
+        pub type AxWatchEvent;
+        pub type AxWatchEventKind;
         #[cfg(feature = "myfs")]
         pub type AxDisk;
         #[cfg(feature = "myfs")]
@@ -236,6 +251,13 @@ pub mod fs {
         pub fn ax_seek_file(file: &mut AxFileHandle, pos: AxSeekFrom) -> AxResult<u64>;
         /// Returns attributes of the file.
         pub fn ax_file_attr(file: &AxFileHandle) -> AxResult<AxFileAttr>;
+        /// Sets the access and modification times of the file, since the Unix
+        /// epoch. Those of `None` are left unchanged.
+        pub fn ax_set_file_times(
+            file: &AxFileHandle,
+            atime: Option<core::time::Duration>,
+            mtime: Option<core::time::Duration>,
+        ) -> AxResult;
 
         /// Reads directory entries starts from the current position into the
         /// given buffer, returns the number of entries read.
@@ -255,23 +277,68 @@ pub mod fs {
         ///
         /// It will delete the original file if `old` already exists.
         pub fn ax_rename(old: &str, new: &str) -> AxResult;
+        /// Returns attributes of the file at `path`, or of the symlink itself
+        /// if it's a symlink.
+        pub fn ax_symlink_attr(path: &str) -> AxResult<AxFileAttr>;
+        /// Reads the target of the symlink at `path`.
+        pub fn ax_read_link(path: &str) -> AxResult<alloc::string::String>;
+        /// Creates a symlink at `link` pointing to `original`.
+        pub fn ax_symlink(original: &str, link: &str) -> AxResult;
+        /// Creates a hard link at `link` to the file at `original`.
+        pub fn ax_hard_link(original: &str, link: &str) -> AxResult;
 
         /// Returns the current working directory.
         pub fn ax_current_dir() -> AxResult<alloc::string::String>;
         /// Changes the current working directory to the specified path.
         pub fn ax_set_current_dir(path: &str) -> AxResult;
+
+        /// Mounts the filesystem of `fs_type` from `source` at `target`.
+        ///
+        /// `source` selects a volume (e.g. `blk0p1` or `LABEL=data`) unless
+        /// `fs_type` is `ramfs`, and `fs_type` may be `auto` to detect it.
+        pub fn ax_mount(source: &str, target: &str, fs_type: &str) -> AxResult;
+        /// Mounts an overlay at `target`, which merges the directory `upper`
+        /// over the read-only directory `lower`.
+        pub fn ax_mount_overlay(lower: &str, upper: &str, target: &str) -> AxResult;
+        /// Unmounts the filesystem at `target`.
+        ///
+        /// If `detach` is false, it fails if the filesystem is busy. Otherwise
+        /// it's detached at once and unmounted when it's no longer in use.
+        pub fn ax_umount(target: &str, detach: bool) -> AxResult;
+
+        /// Creates a watcher, which queues events of the paths it watches.
+        pub fn ax_create_watcher() -> AxWatcherHandle;
+        /// Watches the file or directory at `path` for the kinds of events in
+        /// `mask`, returns the watch descriptor.
+        pub fn ax_add_watch(watcher: &AxWatcherHandle, path: &str, mask: u32) -> AxResult<usize>;
+        /// Removes the watch of the descriptor `wd`.
+        pub fn ax_remove_watch(watcher: &AxWatcherHandle, wd: usize) -> AxResult;
+        /// Takes the first queued event of the watcher, waiting for one if
+        /// `wait` is true.
+        pub fn ax_next_watch_event(watcher: &AxWatcherHandle, wait: bool) -> AxResult<AxWatchEvent>;
     }
 }
 
 /// Networking primitives for TCP/UDP communication.
 pub mod net {
     use crate::{io::AxPollState, AxResult};
-    use core::net::{IpAddr, SocketAddr};
+    use core::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use core::task::{Context, Poll};
+
+    /// Default size in bytes of the ring of [`ax_net_capture_start`].
+    #[cfg(feature = "net")]
+    pub use axnet::DEFAULT_CAPTURE_CAPACITY as AX_NET_CAPTURE_DEFAULT_CAPACITY;
 
     define_api_type! {
         @cfg "net";
         pub type AxTcpSocketHandle;
         pub type AxUdpSocketHandle;
+        pub type AxNetIfConfig;
+        pub type AxBridgeStats;
+        pub type AxForwardStats;
+        pub type AxNatProtocol;
+        pub type AxPortForward;
+        pub type AxNatConnection;
     }
 
     define_api! {
@@ -287,13 +354,20 @@ pub mod net {
         pub fn ax_tcp_peer_addr(socket: &AxTcpSocketHandle) -> AxResult<SocketAddr>;
         /// Moves this TCP socket into or out of nonblocking mode.
         pub fn ax_tcp_set_nonblocking(socket: &AxTcpSocketHandle, nonblocking: bool) -> AxResult;
+        /// Sets the timeout of blocking receives on the TCP socket, or `None`
+        /// to block forever.
+        pub fn ax_tcp_set_read_timeout(
+            socket: &AxTcpSocketHandle,
+            timeout: Option<core::time::Duration>,
+        ) -> AxResult;
 
         /// Connects the TCP socket to the given address and port.
         pub fn ax_tcp_connect(handle: &AxTcpSocketHandle, addr: SocketAddr) -> AxResult;
         /// Binds the TCP socket to the given address and port.
         pub fn ax_tcp_bind(socket: &AxTcpSocketHandle, addr: SocketAddr) -> AxResult;
-        /// Starts listening on the bound address and port.
-        pub fn ax_tcp_listen(socket: &AxTcpSocketHandle, _backlog: usize) -> AxResult;
+        /// Starts listening on the bound address and port, queueing at most
+        /// `backlog` pending connections.
+        pub fn ax_tcp_listen(socket: &AxTcpSocketHandle, backlog: usize) -> AxResult;
         /// Accepts a new connection on the TCP socket.
         ///
         /// This function will block the calling thread until a new TCP connection
@@ -307,6 +381,18 @@ pub mod net {
         pub fn ax_tcp_recv(socket: &AxTcpSocketHandle, buf: &mut [u8]) -> AxResult<usize>;
         /// Returns whether the TCP socket is readable or writable.
         pub fn ax_tcp_poll(socket: &AxTcpSocketHandle) -> AxResult<AxPollState>;
+        /// Polls whether the connection initiated by a nonblocking connect has
+        /// been established, registering the waker of `cx` if not.
+        pub fn ax_tcp_poll_connected(socket: &AxTcpSocketHandle, cx: &mut Context<'_>) -> Poll<AxResult>;
+        /// Attempts to accept a new connection on the TCP socket without
+        /// blocking, registering the waker of `cx` if none is ready.
+        pub fn ax_tcp_poll_accept(socket: &AxTcpSocketHandle, cx: &mut Context<'_>) -> Poll<AxResult<(AxTcpSocketHandle, SocketAddr)>>;
+        /// Attempts to transmit data on the TCP socket without blocking,
+        /// registering the waker of `cx` if the buffer is full.
+        pub fn ax_tcp_poll_send(socket: &AxTcpSocketHandle, cx: &mut Context<'_>, buf: &[u8]) -> Poll<AxResult<usize>>;
+        /// Attempts to receive data on the TCP socket without blocking,
+        /// registering the waker of `cx` if no data is available.
+        pub fn ax_tcp_poll_recv(socket: &AxTcpSocketHandle, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<AxResult<usize>>;
         /// Closes the connection on the TCP socket.
         pub fn ax_tcp_shutdown(socket: &AxTcpSocketHandle) -> AxResult;
 
@@ -345,16 +431,101 @@ pub mod net {
         pub fn ax_udp_recv(socket: &AxUdpSocketHandle, buf: &mut [u8]) -> AxResult<usize>;
         /// Returns whether the UDP socket is readable or writable.
         pub fn ax_udp_poll(socket: &AxUdpSocketHandle) -> AxResult<AxPollState>;
+        /// Attempts to receive a single datagram on the UDP socket without
+        /// blocking, registering the waker of `cx` if none is queued.
+        pub fn ax_udp_poll_recv_from(socket: &AxUdpSocketHandle, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<AxResult<(usize, SocketAddr)>>;
+        /// Attempts to send data on the UDP socket to the given address
+        /// without blocking, registering the waker of `cx` if the buffer is full.
+        pub fn ax_udp_poll_send_to(socket: &AxUdpSocketHandle, cx: &mut Context<'_>, buf: &[u8], addr: SocketAddr) -> Poll<AxResult<usize>>;
+        /// Joins the IPv4 multicast group `multiaddr` on the UDP socket.
+        pub fn ax_udp_join_multicast_v4(socket: &AxUdpSocketHandle, multiaddr: Ipv4Addr) -> AxResult;
+        /// Leaves the IPv4 multicast group `multiaddr` on the UDP socket.
+        pub fn ax_udp_leave_multicast_v4(socket: &AxUdpSocketHandle, multiaddr: Ipv4Addr) -> AxResult;
 
         // Miscellaneous
 
         /// Resolves the host name to a list of IP addresses.
         pub fn ax_dns_query(domain_name: &str) -> AxResult<alloc::vec::Vec<IpAddr>>;
+        /// Sets the DNS servers, which are queried in order.
+        pub fn ax_dns_set_servers(servers: &[IpAddr]) -> AxResult;
+        /// Returns the DNS servers.
+        pub fn ax_dns_servers() -> alloc::vec::Vec<IpAddr>;
+        /// Removes all cached DNS answers.
+        pub fn ax_dns_flush_cache() -> AxResult;
         /// Poll the network stack.
         ///
         /// It may receive packets from the NIC and process them, and transmit queued
         /// packets to the NIC.
         pub fn ax_poll_interfaces() -> AxResult;
+
+        // Interfaces
+
+        /// Creates the 802.1Q VLAN sub-interface with VLAN ID `vid` and the
+        /// given IPv4 configuration, returns its name.
+        pub fn ax_net_add_vlan(vid: u16, ip: Ipv4Addr, prefix_len: u8, gateway: Option<Ipv4Addr>) -> AxResult<alloc::string::String>;
+        /// Removes the VLAN sub-interface with VLAN ID `vid`.
+        pub fn ax_net_remove_vlan(vid: u16) -> AxResult;
+        /// Returns the configuration of all network interfaces.
+        pub fn ax_net_interfaces() -> alloc::vec::Vec<AxNetIfConfig>;
+
+        // Bridge
+
+        /// Starts bridging all NICs, flooding frames to unknown destinations
+        /// if `flood_unknown` is true.
+        pub fn ax_net_bridge_start(flood_unknown: bool) -> AxResult;
+        /// Stops bridging the NICs.
+        pub fn ax_net_bridge_stop() -> AxResult;
+        /// Returns the statistics of the bridge, or `None` if it is not started.
+        pub fn ax_net_bridge_stats() -> Option<AxBridgeStats>;
+
+        // IP forwarding and NAT
+
+        /// Starts forwarding IPv4 packets between `eth0` and the other NICs,
+        /// with the addresses and prefix lengths of `eth1`, `eth2` and so on.
+        pub fn ax_ip_forward_start(addrs: &[(Ipv4Addr, u8)]) -> AxResult;
+        /// Stops forwarding IPv4 packets.
+        pub fn ax_ip_forward_stop() -> AxResult;
+        /// Returns the statistics of IP forwarding, or `None` if it is not started.
+        pub fn ax_ip_forward_stats() -> Option<AxForwardStats>;
+        /// Enables or disables the source NAT of packets leaving through `eth0`.
+        pub fn ax_nat_set_masquerade(enabled: bool) -> AxResult;
+        /// Adds a rule forwarding a port of `eth0` to an inside address.
+        pub fn ax_nat_add_port_forward(rule: AxPortForward) -> AxResult;
+        /// Removes the rule forwarding the port of `eth0`.
+        pub fn ax_nat_remove_port_forward(protocol: AxNatProtocol, external_port: u16) -> AxResult;
+        /// Returns the connections tracked by NAT.
+        pub fn ax_nat_connections() -> alloc::vec::Vec<AxNatConnection>;
+
+        // mDNS responder
+
+        /// Starts answering mDNS queries for `<hostname>.local`.
+        pub fn ax_mdns_start(hostname: &str) -> AxResult;
+        /// Advertises the service `instance` of type `service` (e.g.
+        /// `_http._tcp`) on `port` with DNS-SD, with the given TXT strings.
+        pub fn ax_mdns_add_service(instance: &str, service: &str, port: u16, txt: &[&str]) -> AxResult;
+        /// Stops the mDNS responder.
+        pub fn ax_mdns_stop() -> AxResult;
+
+        // Packet capture
+
+        /// Starts capturing NIC frames matching the BPF-like `filter` expression
+        /// into an in-memory ring of `capacity` bytes.
+        pub fn ax_net_capture_start(filter: &str, capacity: usize) -> AxResult;
+        /// Stops capturing NIC frames, the captured frames are kept.
+        pub fn ax_net_capture_stop() -> AxResult;
+        /// Returns the captured frames in the pcap file format.
+        ///
+        /// If `clear` is true, the returned frames are removed from the ring.
+        pub fn ax_net_capture_dump(clear: bool) -> alloc::vec::Vec<u8>;
+
+        // Benchmarks
+
+        /// Accepts one TCP connection on `port` and receives from it until
+        /// the peer closes, logging the bandwidth every second.
+        ///
+        /// Data is consumed in place in the socket buffer if `zero_copy` is
+        /// true, or copied out otherwise, to compare the two receive paths.
+        pub fn ax_net_bench_tcp_receive(port: u16, zero_copy: bool) -> AxResult;
     }
 }
 
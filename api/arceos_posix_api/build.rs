@@ -53,6 +53,8 @@ typedef struct {{
             "clockid_t",
             "rlimit",
             "aibuf",
+            "flock",
+            "inotify_event",
         ];
         let allow_vars = [
             "CLOCK_.*",
@@ -68,6 +70,13 @@ typedef struct {{
             "RLIMIT_.*",
             "EAI_.*",
             "MAXADDRS",
+            "MS_.*",
+            "MNT_.*",
+            "UMOUNT_.*",
+            "UTIME_.*",
+            "AT_.*",
+            "LOCK_.*",
+            "IN_.*",
         ];
 
         #[derive(Debug)]
@@ -11,6 +11,8 @@ use crate::ctypes;
 
 pub const AX_FILE_LIMIT: usize = 1024;
 
+const FIONBIO: u32 = 0x5421;
+
 #[allow(dead_code)]
 pub trait FileLike: Send + Sync {
     fn read(&self, buf: &mut [u8]) -> LinuxResult<usize>;
@@ -19,6 +21,11 @@ pub trait FileLike: Send + Sync {
     fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync>;
     fn poll(&self) -> LinuxResult<PollState>;
     fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult;
+
+    /// Performs a device-specific request, only supported by device files.
+    fn ioctl(&self, _cmd: u32, _arg: usize) -> LinuxResult<c_int> {
+        Err(LinuxError::ENOTTY)
+    }
 }
 
 lazy_static::lazy_static! {
@@ -48,6 +55,8 @@ pub fn close_file_like(fd: c_int) -> LinuxResult {
         .write()
         .remove(fd as usize)
         .ok_or(LinuxError::EBADF)?;
+    #[cfg(feature = "fs")]
+    super::fs::release_record_locks(&f);
     drop(f);
     Ok(())
 }
@@ -101,8 +110,36 @@ pub fn sys_dup2(old_fd: c_int, new_fd: c_int) -> c_int {
     })
 }
 
+/// Manipulate the parameters of the device file indicated by `fd`.
+///
+/// `FIONBIO` is supported by all files, other requests are passed to the
+/// device.
+pub fn sys_ioctl(fd: c_int, request: c_int, arg: usize) -> c_int {
+    debug!(
+        "sys_ioctl <= fd: {} request: {:#x} arg: {:#x}",
+        fd, request, arg
+    );
+    syscall_body!(sys_ioctl, {
+        let f = get_file_like(fd)?;
+        match request as u32 {
+            FIONBIO => {
+                if arg == 0 {
+                    return Err(LinuxError::EFAULT);
+                }
+                let nonblocking = unsafe { *(arg as *const c_int) } != 0;
+                f.set_nonblocking(nonblocking)?;
+                Ok(0)
+            }
+            cmd => f.ioctl(cmd, arg),
+        }
+    })
+}
+
 /// Manipulate file descriptor.
 ///
+/// The record lock commands `F_GETLK`, `F_SETLK` and `F_SETLKW` are supported
+/// by files.
+///
 /// TODO: `SET/GET` command is ignored, hard-code stdin/stdout
 pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> c_int {
     debug!("sys_fcntl <= fd: {} cmd: {} arg: {}", fd, cmd, arg);
@@ -120,6 +157,10 @@ pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> c_int {
                 get_file_like(fd)?.set_nonblocking(arg & (ctypes::O_NONBLOCK as usize) > 0)?;
                 Ok(0)
             }
+            #[cfg(feature = "fs")]
+            ctypes::F_GETLK | ctypes::F_SETLK | ctypes::F_SETLKW => {
+                super::fs::fcntl_lock(fd, cmd as u32, arg)
+            }
             _ => {
                 warn!("unsupported fcntl parameters: cmd {}", cmd);
                 Ok(0)
@@ -1,8 +1,10 @@
 use alloc::sync::Arc;
-use core::ffi::{c_char, c_int};
+use core::ffi::{c_char, c_int, c_long, c_ulong, c_void};
+use core::time::Duration;
 
-use axerrno::{LinuxError, LinuxResult};
-use axfs::fops::OpenOptions;
+use axerrno::{AxError, LinuxError, LinuxResult};
+use axfs::api::FileTimes;
+use axfs::fops::{FileAttr, LockHandle, LockKind, OpenOptions, RangeLock};
 use axio::{PollState, SeekFrom};
 use axsync::Mutex;
 
@@ -11,11 +13,15 @@ use crate::{ctypes, utils::char_ptr_to_str};
 
 pub struct File {
     inner: Mutex<axfs::fops::File>,
+    /// Kept out of `inner` so that waiting for a lock doesn't block other
+    /// operations on the file.
+    locks: LockHandle,
 }
 
 impl File {
     fn new(inner: axfs::fops::File) -> Self {
         Self {
+            locks: inner.lock_handle(),
             inner: Mutex::new(inner),
         }
     }
@@ -42,21 +48,7 @@ impl FileLike for File {
     }
 
     fn stat(&self) -> LinuxResult<ctypes::stat> {
-        let metadata = self.inner.lock().get_attr()?;
-        let ty = metadata.file_type() as u8;
-        let perm = metadata.perm().bits() as u32;
-        let st_mode = ((ty as u32) << 12) | perm;
-        Ok(ctypes::stat {
-            st_ino: 1,
-            st_nlink: 1,
-            st_mode,
-            st_uid: 1000,
-            st_gid: 1000,
-            st_size: metadata.size() as _,
-            st_blocks: metadata.blocks() as _,
-            st_blksize: 512,
-            ..Default::default()
-        })
+        Ok(attr_to_stat(&self.inner.lock().get_attr()?))
     }
 
     fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
@@ -73,6 +65,44 @@ impl FileLike for File {
     fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
         Ok(())
     }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> LinuxResult<c_int> {
+        match self.inner.lock().ioctl(cmd, arg) {
+            Ok(ret) => Ok(ret as c_int),
+            Err(AxError::Unsupported) => Err(LinuxError::ENOTTY),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Convert lock errors, where a deadlock is reported as `ResourceBusy`.
+fn lock_error(e: AxError) -> LinuxError {
+    match e {
+        AxError::ResourceBusy => LinuxError::EDEADLK,
+        e => e.into(),
+    }
+}
+
+/// Convert file attributes to [`ctypes::stat`].
+fn attr_to_stat(attr: &FileAttr) -> ctypes::stat {
+    let ty = attr.file_type() as u8;
+    let perm = attr.perm().bits() as u32;
+    let st_mode = ((ty as u32) << 12) | perm;
+    ctypes::stat {
+        // files of filesystems without inode numbers have a fake one
+        st_ino: attr.ino().max(1) as _,
+        st_nlink: attr.nlink() as _,
+        st_mode,
+        st_uid: attr.uid(),
+        st_gid: attr.gid(),
+        st_size: attr.size() as _,
+        st_blocks: attr.blocks() as _,
+        st_blksize: 512,
+        st_atim: attr.atime().into(),
+        st_mtim: attr.mtime().into(),
+        st_ctim: attr.ctime().into(),
+        ..Default::default()
+    }
 }
 
 /// Convert open flags to [`OpenOptions`].
@@ -110,8 +140,14 @@ pub fn sys_open(filename: *const c_char, flags: c_int, mode: ctypes::mode_t) ->
     let filename = char_ptr_to_str(filename);
     debug!("sys_open <= {:?} {:#o} {:#o}", filename, flags, mode);
     syscall_body!(sys_open, {
+        let filename = filename?;
         let options = flags_to_options(flags, mode);
-        let file = axfs::fops::File::open(filename?, &options)?;
+        if flags as u32 & ctypes::O_NOFOLLOW != 0
+            && axfs::api::symlink_metadata(filename).is_ok_and(|md| md.is_symlink())
+        {
+            return Err(LinuxError::ELOOP);
+        }
+        let file = axfs::fops::File::open(filename, &options)?;
         File::new(file).add_to_fd_table()
     })
 }
@@ -177,11 +213,213 @@ pub unsafe fn sys_lstat(path: *const c_char, buf: *mut ctypes::stat) -> ctypes::
         if buf.is_null() {
             return Err(LinuxError::EFAULT);
         }
-        unsafe { *buf = Default::default() }; // TODO
+        let md = axfs::api::symlink_metadata(path?)?;
+        unsafe { *buf = attr_to_stat(md.raw_attr()) };
+        Ok(0)
+    })
+}
+
+const UTIME_NOW: c_long = ctypes::UTIME_NOW as _;
+const UTIME_OMIT: c_long = ctypes::UTIME_OMIT as _;
+
+/// Convert the `times` of `utimensat` and `futimens` to the access and
+/// modification times, where `None` leaves one unchanged.
+///
+/// A NULL `times` sets both to now, and `UTIME_NOW` or `UTIME_OMIT` in
+/// `tv_nsec` sets one to now or leaves it unchanged.
+unsafe fn timespecs_to_times(
+    times: *const ctypes::timespec,
+) -> LinuxResult<(Option<Duration>, Option<Duration>)> {
+    let now = axhal::time::wall_time();
+    if times.is_null() {
+        return Ok((Some(now), Some(now)));
+    }
+    let to_time = |ts: ctypes::timespec| match ts.tv_nsec {
+        UTIME_NOW => Ok(Some(now)),
+        UTIME_OMIT => Ok(None),
+        0..=999_999_999 if ts.tv_sec >= 0 => Ok(Some(Duration::from(ts))),
+        _ => Err(LinuxError::EINVAL),
+    };
+    unsafe { Ok((to_time(*times)?, to_time(*times.add(1))?)) }
+}
+
+/// Set the access and modification times of the file `path` relative to
+/// `dirfd`, or of the file `dirfd` itself if `path` is NULL. Only `AT_FDCWD`
+/// is supported as `dirfd` for relative paths.
+///
+/// With `AT_SYMLINK_NOFOLLOW` in `flags`, the times of a symlink itself are
+/// set.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub unsafe fn sys_utimensat(
+    dirfd: c_int,
+    path: *const c_char,
+    times: *const ctypes::timespec,
+    flags: c_int,
+) -> c_int {
+    debug!(
+        "sys_utimensat <= {} {:#x} {:#x} {:#x}",
+        dirfd, path as usize, times as usize, flags
+    );
+    syscall_body!(sys_utimensat, {
+        if flags as u32 & !ctypes::AT_SYMLINK_NOFOLLOW != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let (atime, mtime) = unsafe { timespecs_to_times(times)? };
+        if path.is_null() {
+            File::from_fd(dirfd)?.inner.lock().set_times(atime, mtime)?;
+            return Ok(0);
+        }
+        let path = char_ptr_to_str(path)?;
+        if !path.starts_with('/') && dirfd != ctypes::AT_FDCWD {
+            get_file_like(dirfd)?;
+            return Err(LinuxError::ENOTDIR);
+        }
+        let mut file_times = FileTimes::new();
+        if let Some(atime) = atime {
+            file_times = file_times.set_accessed(atime);
+        }
+        if let Some(mtime) = mtime {
+            file_times = file_times.set_modified(mtime);
+        }
+        if flags as u32 & ctypes::AT_SYMLINK_NOFOLLOW != 0 {
+            axfs::api::set_symlink_times(path, file_times)?;
+        } else {
+            axfs::api::set_times(path, file_times)?;
+        }
+        Ok(0)
+    })
+}
+
+/// Set the access and modification times of the file `fd`, like
+/// [`sys_utimensat`].
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub unsafe fn sys_futimens(fd: c_int, times: *const ctypes::timespec) -> c_int {
+    debug!("sys_futimens <= {} {:#x}", fd, times as usize);
+    unsafe { sys_utimensat(fd, core::ptr::null(), times, 0) }
+}
+
+/// Apply or remove an advisory lock on the whole file `fd`.
+///
+/// `operation` is `LOCK_SH` or `LOCK_EX`, optionally with `LOCK_NB` to fail
+/// with `EWOULDBLOCK` instead of waiting, or `LOCK_UN`. The lock belongs to
+/// the open file description, so it's shared by duplicated `fd`s and released
+/// when all of them are closed.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_flock(fd: c_int, operation: c_int) -> c_int {
+    debug!("sys_flock <= {} {:#x}", fd, operation);
+    syscall_body!(sys_flock, {
+        let file = File::from_fd(fd)?;
+        let wait = operation as u32 & ctypes::LOCK_NB == 0;
+        let kind = match operation as u32 & !ctypes::LOCK_NB {
+            ctypes::LOCK_SH => LockKind::Shared,
+            ctypes::LOCK_EX => LockKind::Exclusive,
+            ctypes::LOCK_UN => {
+                file.locks.unlock();
+                return Ok(0);
+            }
+            _ => return Err(LinuxError::EINVAL),
+        };
+        file.locks.lock(kind, wait).map_err(lock_error)?;
         Ok(0)
     })
 }
 
+/// Convert a [`ctypes::flock`] of the file to the start and end of its range.
+fn flock_to_range(file: &File, lock: &ctypes::flock) -> LinuxResult<(u64, u64)> {
+    let base = match lock.l_whence as c_int {
+        0 => 0,
+        1 => file.inner.lock().seek(SeekFrom::Current(0))? as i64,
+        2 => file.inner.lock().get_attr()?.size() as i64,
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let start = base as i128 + lock.l_start as i128;
+    let (start, end) = match lock.l_len as i128 {
+        0 => (start, u64::MAX as i128),
+        len if len > 0 => (start, start + len),
+        // the range before `start`
+        len => (start + len, start),
+    };
+    if start < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let max = u64::MAX as i128;
+    Ok((start.min(max) as u64, end.min(max) as u64))
+}
+
+/// Handle the record lock commands `F_GETLK`, `F_SETLK` and `F_SETLKW` of
+/// `fcntl` for the file `fd`, where `arg` points to a [`ctypes::flock`].
+///
+/// Record locks belong to the current task, are shared by all `fd`s of the
+/// same file, and are released when any of them is closed. `F_SETLKW` fails
+/// with `EDEADLK` if waiting would deadlock.
+pub(super) fn fcntl_lock(fd: c_int, cmd: u32, arg: usize) -> LinuxResult<c_int> {
+    let file = File::from_fd(fd)?;
+    if arg == 0 {
+        return Err(LinuxError::EFAULT);
+    }
+    let flock = unsafe { &mut *(arg as *mut ctypes::flock) };
+    let (start, end) = flock_to_range(&file, flock)?;
+    let owner = super::task::sys_getpid() as u64;
+    let kind = match flock.l_type as u32 {
+        ctypes::F_RDLCK => LockKind::Shared,
+        ctypes::F_WRLCK => LockKind::Exclusive,
+        ctypes::F_UNLCK if cmd != ctypes::F_GETLK => {
+            if start < end {
+                file.locks.unlock_range(owner, start, end);
+            }
+            return Ok(0);
+        }
+        _ => return Err(LinuxError::EINVAL),
+    };
+    if start >= end {
+        return Err(LinuxError::EINVAL);
+    }
+    let lock = RangeLock {
+        owner,
+        kind,
+        start,
+        end,
+    };
+    if cmd == ctypes::F_GETLK {
+        match file.locks.test_lock_range(&lock) {
+            Some(conflict) => {
+                flock.l_type = match conflict.kind {
+                    LockKind::Shared => ctypes::F_RDLCK,
+                    LockKind::Exclusive => ctypes::F_WRLCK,
+                } as _;
+                flock.l_whence = 0;
+                flock.l_start = conflict.start as _;
+                flock.l_len = match conflict.end {
+                    u64::MAX => 0,
+                    end => (end - conflict.start) as _,
+                };
+                flock.l_pid = conflict.owner as _;
+            }
+            None => flock.l_type = ctypes::F_UNLCK as _,
+        }
+        return Ok(0);
+    }
+    let wait = cmd == ctypes::F_SETLKW;
+    file.locks.lock_range(lock, wait).map_err(|e| match e {
+        // the file isn't open for the access of the lock
+        AxError::PermissionDenied => LinuxError::EBADF,
+        e => lock_error(e),
+    })?;
+    Ok(0)
+}
+
+/// Release the record locks of the current task on the file of `f`, if it's
+/// a file, as it's being closed.
+pub(super) fn release_record_locks(f: &Arc<dyn FileLike>) {
+    if let Ok(file) = f.clone().into_any().downcast::<File>() {
+        let owner = super::task::sys_getpid() as u64;
+        file.locks.unlock_range(owner, 0, u64::MAX);
+    }
+}
+
 /// Get the path of the current directory.
 pub fn sys_getcwd(buf: *mut c_char, size: usize) -> *mut c_char {
     debug!("sys_getcwd <= {:#x} {}", buf as usize, size);
@@ -202,6 +440,25 @@ pub fn sys_getcwd(buf: *mut c_char, size: usize) -> *mut c_char {
     })
 }
 
+/// Write the cached data of the file `fd` back to the device.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_fsync(fd: c_int) -> c_int {
+    debug!("sys_fsync <= {}", fd);
+    syscall_body!(sys_fsync, {
+        File::from_fd(fd)?.inner.lock().flush()?;
+        Ok(0)
+    })
+}
+
+/// Write the cached data of all filesystems back to the devices.
+pub fn sys_sync() {
+    debug!("sys_sync");
+    if let Err(e) = axfs::api::sync() {
+        warn!("sys_sync: {:?}", e);
+    }
+}
+
 /// Rename `old` to `new`
 /// If new exists, it is first removed.
 ///
@@ -215,3 +472,119 @@ pub fn sys_rename(old: *const c_char, new: *const c_char) -> c_int {
         Ok(0)
     })
 }
+
+/// Create a symbolic link `linkpath` pointing to `target`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_symlink(target: *const c_char, linkpath: *const c_char) -> c_int {
+    syscall_body!(sys_symlink, {
+        let target = char_ptr_to_str(target)?;
+        let linkpath = char_ptr_to_str(linkpath)?;
+        debug!(
+            "sys_symlink <= target: {:?}, linkpath: {:?}",
+            target, linkpath
+        );
+        axfs::api::symlink(target, linkpath)?;
+        Ok(0)
+    })
+}
+
+/// Read the target of the symbolic link `path` into `buf`, without a
+/// terminating NUL. The target is truncated if `buf` is too small.
+///
+/// Return the number of bytes placed in `buf`.
+pub fn sys_readlink(path: *const c_char, buf: *mut c_char, bufsiz: usize) -> ctypes::ssize_t {
+    syscall_body!(sys_readlink, {
+        let path = char_ptr_to_str(path)?;
+        debug!("sys_readlink <= {:?} {:#x} {}", path, buf as usize, bufsiz);
+        if buf.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let target = axfs::api::read_link(path)?;
+        let len = target.len().min(bufsiz);
+        let dst = unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, len) };
+        dst.copy_from_slice(&target.as_bytes()[..len]);
+        Ok(len as ctypes::ssize_t)
+    })
+}
+
+/// Create a hard link `newpath` to the file `oldpath`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_link(oldpath: *const c_char, newpath: *const c_char) -> c_int {
+    syscall_body!(sys_link, {
+        let oldpath = char_ptr_to_str(oldpath)?;
+        let newpath = char_ptr_to_str(newpath)?;
+        debug!("sys_link <= old: {:?}, new: {:?}", oldpath, newpath);
+        axfs::api::hard_link(oldpath, newpath)?;
+        Ok(0)
+    })
+}
+
+/// Mount the filesystem of `fstype` from `source` at `target`.
+///
+/// `source` selects a volume (e.g. `blk0p1` or `LABEL=data`), and `fstype`
+/// may be `auto` (or NULL) to detect the filesystem. For `ramfs` and `tmpfs`,
+/// `source` is only a name. Only flags that need no support from the
+/// filesystem are accepted, and `data` is ignored.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_mount(
+    source: *const c_char,
+    target: *const c_char,
+    fstype: *const c_char,
+    flags: c_ulong,
+    data: *const c_void,
+) -> c_int {
+    syscall_body!(sys_mount, {
+        let source = if source.is_null() {
+            ""
+        } else {
+            char_ptr_to_str(source)?
+        };
+        let target = char_ptr_to_str(target)?;
+        let fstype = if fstype.is_null() {
+            "auto"
+        } else {
+            char_ptr_to_str(fstype)?
+        };
+        debug!(
+            "sys_mount <= source: {:?}, target: {:?}, fstype: {:?}, flags: {:#x}, data: {:#x}",
+            source, target, fstype, flags, data as usize
+        );
+        let ignored = ctypes::MS_NOSUID
+            | ctypes::MS_NODEV
+            | ctypes::MS_NOEXEC
+            | ctypes::MS_NOATIME
+            | ctypes::MS_NODIRATIME
+            | ctypes::MS_SILENT;
+        if flags & !(ignored as c_ulong) != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        axfs::api::mount(source, target, fstype)?;
+        Ok(0)
+    })
+}
+
+/// Unmount the filesystem at `target`.
+///
+/// With `MNT_DETACH` in `flags`, the filesystem is detached at once even if
+/// it's busy, and unmounted when it's no longer in use.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_umount2(target: *const c_char, flags: c_int) -> c_int {
+    syscall_body!(sys_umount2, {
+        let target = char_ptr_to_str(target)?;
+        debug!("sys_umount2 <= target: {:?}, flags: {:#x}", target, flags);
+        let known = ctypes::MNT_FORCE | ctypes::MNT_DETACH | ctypes::UMOUNT_NOFOLLOW;
+        if flags as u32 & !known != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        if flags as u32 & ctypes::MNT_DETACH != 0 {
+            axfs::api::umount_detach(target)?;
+        } else {
+            axfs::api::umount(target)?;
+        }
+        Ok(0)
+    })
+}
@@ -0,0 +1,201 @@
+use alloc::sync::Arc;
+use core::ffi::{c_char, c_int};
+use core::mem::size_of;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use axerrno::{LinuxError, LinuxResult};
+use axfs::fops::{EventKind, WatchEvent, Watcher};
+use axio::PollState;
+use axsync::Mutex;
+
+use super::fd_ops::{add_file_like, get_file_like, FileLike};
+use crate::{ctypes, utils::char_ptr_to_str};
+
+/// The kinds of events reported for the `IN_*` bits of masks.
+const EVENT_BITS: [(u32, EventKind); 7] = [
+    (ctypes::IN_CREATE, EventKind::Create),
+    (ctypes::IN_MODIFY, EventKind::Modify),
+    (ctypes::IN_DELETE, EventKind::Delete),
+    (ctypes::IN_DELETE_SELF, EventKind::Delete),
+    (ctypes::IN_MOVED_FROM, EventKind::MovedFrom),
+    (ctypes::IN_MOVE_SELF, EventKind::MovedFrom),
+    (ctypes::IN_MOVED_TO, EventKind::MovedTo),
+];
+
+pub struct Inotify {
+    watcher: Watcher,
+    /// An event taken from the watcher that didn't fit in the last read.
+    pending: Mutex<Option<WatchEvent>>,
+    nonblocking: AtomicBool,
+}
+
+impl Inotify {
+    fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
+        get_file_like(fd)?
+            .into_any()
+            .downcast::<Self>()
+            .map_err(|_| LinuxError::EINVAL)
+    }
+
+    fn next_event(&self, wait: bool) -> LinuxResult<WatchEvent> {
+        if let Some(event) = self.pending.lock().take() {
+            return Ok(event);
+        }
+        Ok(self.watcher.next_event(wait)?)
+    }
+}
+
+/// Returns the `IN_*` bits of `event`.
+fn event_mask(event: &WatchEvent) -> u32 {
+    match (event.kind, event.name.is_empty()) {
+        (EventKind::Create, _) => ctypes::IN_CREATE,
+        (EventKind::Modify, _) => ctypes::IN_MODIFY,
+        (EventKind::Delete, true) => ctypes::IN_DELETE_SELF,
+        (EventKind::Delete, false) => ctypes::IN_DELETE,
+        (EventKind::MovedFrom, true) => ctypes::IN_MOVE_SELF,
+        (EventKind::MovedFrom, false) => ctypes::IN_MOVED_FROM,
+        (EventKind::MovedTo, _) => ctypes::IN_MOVED_TO,
+        (EventKind::Overflow, _) => ctypes::IN_Q_OVERFLOW,
+    }
+}
+
+/// Writes `event` as a [`ctypes::inotify_event`] followed by its name at the
+/// start of `buf`, where the name is padded with NULs. Returns the size
+/// written, or `None` if `buf` is too small.
+fn write_event(event: &WatchEvent, buf: &mut [u8]) -> Option<usize> {
+    let header_size = size_of::<ctypes::inotify_event>();
+    let name_len = match event.name.len() {
+        0 => 0,
+        len => (len + 1).next_multiple_of(size_of::<u32>()),
+    };
+    let dst = buf.get_mut(..header_size + name_len)?;
+    let header = ctypes::inotify_event {
+        wd: event.wd as _,
+        mask: event_mask(event),
+        cookie: event.cookie,
+        len: name_len as _,
+        ..Default::default()
+    };
+    unsafe { (dst.as_mut_ptr() as *mut ctypes::inotify_event).write_unaligned(header) };
+    let (name, padding) = dst[header_size..].split_at_mut(event.name.len());
+    name.copy_from_slice(event.name.as_bytes());
+    padding.fill(0);
+    Some(dst.len())
+}
+
+impl FileLike for Inotify {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        let nonblocking = self.nonblocking.load(Ordering::Acquire);
+        let mut len = 0;
+        loop {
+            // only wait for the first event
+            let event = match self.next_event(len == 0 && !nonblocking) {
+                Ok(event) => event,
+                Err(_) if len > 0 => return Ok(len),
+                Err(e) => return Err(e),
+            };
+            match write_event(&event, &mut buf[len..]) {
+                Some(n) => len += n,
+                None => {
+                    *self.pending.lock() = Some(event);
+                    return if len > 0 {
+                        Ok(len)
+                    } else {
+                        Err(LinuxError::EINVAL)
+                    };
+                }
+            }
+        }
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<ctypes::stat> {
+        Ok(ctypes::stat {
+            st_ino: 1,
+            st_nlink: 1,
+            st_mode: 0o600, // rw-------
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: self.pending.lock().is_some() || self.watcher.has_events(),
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// Create an inotify instance, whose events are read from the returned file
+/// descriptor, which is readable in `select` and `epoll` when there are any.
+///
+/// `flags` may have `IN_NONBLOCK`, and `IN_CLOEXEC` which is ignored.
+///
+/// Return the file descriptor if succeed, otherwise return -1.
+pub fn sys_inotify_init1(flags: c_int) -> c_int {
+    debug!("sys_inotify_init1 <= {:#x}", flags);
+    syscall_body!(sys_inotify_init1, {
+        if flags as u32 & !(ctypes::IN_NONBLOCK | ctypes::IN_CLOEXEC) != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let inotify = Inotify {
+            watcher: Watcher::new(),
+            pending: Mutex::new(None),
+            nonblocking: AtomicBool::new(flags as u32 & ctypes::IN_NONBLOCK != 0),
+        };
+        add_file_like(Arc::new(inotify))
+    })
+}
+
+/// Watch `path` with the inotify instance `fd` for the events in `mask`, or
+/// replace the mask if it's already watched.
+///
+/// Only `IN_CREATE`, `IN_MODIFY`, `IN_DELETE`, `IN_DELETE_SELF`,
+/// `IN_MOVED_FROM`, `IN_MOVED_TO` and `IN_MOVE_SELF` are reported, where the
+/// `*_SELF` ones are reported along with their kinds of events of entries,
+/// and the other bits are ignored. Symlinks are always followed.
+///
+/// Return the watch descriptor if succeed, otherwise return -1.
+pub fn sys_inotify_add_watch(fd: c_int, path: *const c_char, mask: u32) -> c_int {
+    let path = char_ptr_to_str(path);
+    debug!("sys_inotify_add_watch <= {} {:?} {:#x}", fd, path, mask);
+    syscall_body!(sys_inotify_add_watch, {
+        let inotify = Inotify::from_fd(fd)?;
+        let path = path?;
+        if mask & ctypes::IN_ONLYDIR != 0 && !axfs::api::metadata(path)?.is_dir() {
+            return Err(LinuxError::ENOTDIR);
+        }
+        let kinds = EVENT_BITS
+            .iter()
+            .filter(|(bit, _)| mask & bit != 0)
+            .fold(0, |kinds, (_, kind)| kinds | kind.mask());
+        Ok(inotify.watcher.add_watch(path, kinds)? as c_int)
+    })
+}
+
+/// Remove the watch `wd` from the inotify instance `fd`.
+///
+/// Return 0 if the operation succeeds, otherwise return -1.
+pub fn sys_inotify_rm_watch(fd: c_int, wd: c_int) -> c_int {
+    debug!("sys_inotify_rm_watch <= {} {}", fd, wd);
+    syscall_body!(sys_inotify_rm_watch, {
+        let inotify = Inotify::from_fd(fd)?;
+        if wd <= 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        inotify.watcher.remove_watch(wd as usize)?;
+        Ok(0)
+    })
+}
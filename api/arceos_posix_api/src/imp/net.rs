@@ -3,7 +3,7 @@ use core::ffi::{c_char, c_int, c_void};
 use core::mem::size_of;
 use core::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
 
-use axerrno::{LinuxError, LinuxResult};
+use axerrno::{AxError, LinuxError, LinuxResult};
 use axio::PollState;
 use axnet::{TcpSocket, UdpSocket};
 use axsync::Mutex;
@@ -97,10 +97,10 @@ impl Socket {
         }
     }
 
-    fn listen(&self) -> LinuxResult {
+    fn listen(&self, backlog: usize) -> LinuxResult {
         match self {
             Socket::Udp(_) => Err(LinuxError::EOPNOTSUPP),
-            Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().listen()?),
+            Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().listen(backlog)?),
         }
     }
 
@@ -389,13 +389,11 @@ pub fn sys_recv(
 /// Listen for connections on a socket
 ///
 /// Return 0 if success.
-pub fn sys_listen(
-    socket_fd: c_int,
-    backlog: c_int, // currently not used
-) -> c_int {
+pub fn sys_listen(socket_fd: c_int, backlog: c_int) -> c_int {
     debug!("sys_listen <= {} {}", socket_fd, backlog);
     syscall_body!(sys_listen, {
-        Socket::from_fd(socket_fd)?.listen()?;
+        // a negative backlog means the maximum, as Linux does
+        Socket::from_fd(socket_fd)?.listen(backlog as u32 as usize)?;
         Ok(0)
     })
 }
@@ -469,7 +467,11 @@ pub unsafe fn sys_getaddrinfo(
             if let Ok(a) = domain.parse::<IpAddr>() {
                 vec![a]
             } else {
-                axnet::dns_query(domain)?
+                match axnet::dns_query(domain) {
+                    Ok(addrs) => addrs,
+                    Err(AxError::NotFound) => return Ok(0),
+                    Err(e) => return Err(e.into()),
+                }
             }
         } else {
             vec![Ipv4Addr::LOCALHOST.into()]
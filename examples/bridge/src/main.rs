@@ -0,0 +1,46 @@
+#![cfg_attr(feature = "axstd", no_std)]
+#![cfg_attr(feature = "axstd", no_main)]
+
+#[macro_use]
+#[cfg(feature = "axstd")]
+extern crate axstd as std;
+
+#[cfg(feature = "axstd")]
+fn run() -> std::io::Result<()> {
+    use std::net::bridge;
+    use std::time::{Duration, Instant};
+
+    const STATS_INTERVAL: Duration = Duration::from_secs(5);
+
+    // forward frames to unknown destinations like a hub, until learned
+    bridge::start(true)?;
+    println!("Bridging all NICs, press Ctrl+A X to exit.");
+
+    let mut last_print = Instant::now();
+    loop {
+        bridge::poll()?;
+        if last_print.elapsed() >= STATS_INTERVAL {
+            last_print = Instant::now();
+            if let Some(stats) = bridge::stats() {
+                println!(
+                    "local: {}, forwarded: {}, flooded: {}, dropped: {}, learned: {}",
+                    stats.local, stats.forwarded, stats.flooded, stats.dropped, stats.learned
+                );
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "axstd"))]
+fn run() -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "the bridge is only available on ArceOS",
+    ))
+}
+
+#[cfg_attr(feature = "axstd", no_mangle)]
+fn main() {
+    println!("Hello, bridge!");
+    run().expect("bridge failed");
+}
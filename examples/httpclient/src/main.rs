@@ -6,11 +6,11 @@
 extern crate axstd as std;
 
 use std::io::{self, prelude::*};
-use std::net::{TcpStream, ToSocketAddrs};
+use std::net::TcpStream;
 
-#[cfg(feature = "dns")]
+#[cfg(all(feature = "dns", not(feature = "https")))]
 const DEST: &str = "ident.me:80";
-#[cfg(not(feature = "dns"))]
+#[cfg(not(any(feature = "dns", feature = "https")))]
 const DEST: &str = "49.12.234.183:80";
 
 const REQUEST: &str = "\
@@ -19,7 +19,35 @@ Host: ident.me\r\n\
 Accept: */*\r\n\
 \r\n";
 
+/// Root certificate trusted by the HTTPS client, read from the file system.
+#[cfg(feature = "https")]
+const CA_CERT: &str = "/etc/ssl/ca.pem";
+
+#[cfg(feature = "https")]
+const TLS_DEST: &str = "10.0.2.2:4433";
+
+/// Talks HTTPS to a server whose certificate is signed by [`CA_CERT`], e.g.,
+/// `openssl s_server -accept 4433 -cert server.pem -key server.key -www` on
+/// the host, see `scripts/net/test-tls.sh`.
+#[cfg(feature = "https")]
+fn tls_client() -> io::Result<()> {
+    use std::net::tls::{self, TlsStream};
+
+    let config = tls::client_config(tls::load_certs(CA_CERT)?)?;
+    let tcp = TcpStream::connect(TLS_DEST)?;
+    let mut stream = TlsStream::connect(tcp, "localhost", config)?;
+    stream.write_all(REQUEST.as_bytes())?;
+    let mut buf = [0; 2048];
+    let n = stream.read(&mut buf)?;
+    let response = core::str::from_utf8(&buf[..n]).unwrap();
+    println!("{}", response);
+    stream.shutdown()
+}
+
+#[cfg(not(feature = "https"))]
 fn client() -> io::Result<()> {
+    use std::net::ToSocketAddrs;
+
     for addr in DEST.to_socket_addrs()? {
         println!("dest: {} ({})", DEST, addr);
     }
@@ -36,5 +64,8 @@ fn client() -> io::Result<()> {
 #[cfg_attr(feature = "axstd", no_mangle)]
 fn main() {
     println!("Hello, simple http client!");
+    #[cfg(not(feature = "https"))]
     client().expect("test http client failed");
+    #[cfg(feature = "https")]
+    tls_client().expect("test https client failed");
 }
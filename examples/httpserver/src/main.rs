@@ -5,6 +5,14 @@
 //! ```
 //! ab -n 5000 -c 20 http://X.X.X.X:5555/
 //! ```
+//!
+//! Other routes can be tried with curl:
+//!
+//! ```
+//! curl http://X.X.X.X:5555/hello/arceos?times=3
+//! curl --data-binary @file -H "Transfer-Encoding: chunked" http://X.X.X.X:5555/echo
+//! curl -r 0-99 http://X.X.X.X:5555/static/index.html  # with the `fs` feature
+//! ```
 
 #![cfg_attr(feature = "axstd", no_std)]
 #![cfg_attr(feature = "axstd", no_main)]
@@ -13,24 +21,16 @@
 #[cfg(feature = "axstd")]
 extern crate axstd as std;
 
-use std::io::{self, prelude::*};
-use std::net::{TcpListener, TcpStream};
-use std::thread;
+use std::io;
+use std::net::http::{Request, Response, Router, Server};
+use std::string::String;
 
 const LOCAL_IP: &str = "0.0.0.0";
 const LOCAL_PORT: u16 = 5555;
 
-macro_rules! header {
-    () => {
-        "\
-HTTP/1.1 200 OK\r\n\
-Content-Type: text/html\r\n\
-Content-Length: {}\r\n\
-Connection: close\r\n\
-\r\n\
-{}"
-    };
-}
+/// Directory served at `/static/` with the `fs` feature.
+#[cfg(feature = "fs")]
+const STATIC_ROOT: &str = "/www";
 
 const CONTENT: &str = r#"<html>
 <head>
@@ -59,38 +59,41 @@ macro_rules! info {
     };
 }
 
-fn http_server(mut stream: TcpStream) -> io::Result<()> {
-    let mut buf = [0u8; 4096];
-    let _len = stream.read(&mut buf)?;
-
-    let response = format!(header!(), CONTENT.len(), CONTENT);
-    stream.write_all(response.as_bytes())?;
+fn hello(req: &Request) -> Response {
+    let name = req.param("name").unwrap_or("world");
+    let times: usize = req.query("times").and_then(|t| t.parse().ok()).unwrap_or(1);
+    let mut body = String::new();
+    for _ in 0..times.min(100) {
+        body += &format!("Hello, {}!\n", name);
+    }
+    Response::text(body)
+}
 
-    Ok(())
+fn echo(req: &Request) -> Response {
+    info!("echo {} bytes to {:?}", req.body().len(), req.peer_addr());
+    let content_type = req
+        .header("Content-Type")
+        .unwrap_or("application/octet-stream");
+    Response::new(200)
+        .with_header("Content-Type", content_type)
+        .with_body(req.body())
 }
 
-fn accept_loop() -> io::Result<()> {
-    let listener = TcpListener::bind((LOCAL_IP, LOCAL_PORT))?;
-    println!("listen on: http://{}/", listener.local_addr().unwrap());
+fn http_server() -> io::Result<()> {
+    let router = Router::new()
+        .get("/", |_| Response::html(CONTENT))
+        .get("/hello/:name", hello)
+        .post("/echo", echo);
+    #[cfg(feature = "fs")]
+    let router = router.files("/static", STATIC_ROOT);
 
-    let mut i = 0;
-    loop {
-        match listener.accept() {
-            Ok((stream, addr)) => {
-                info!("new client {}: {}", i, addr);
-                thread::spawn(move || match http_server(stream) {
-                    Err(e) => info!("client connection error: {:?}", e),
-                    Ok(()) => info!("client {} closed successfully", i),
-                });
-            }
-            Err(e) => return Err(e),
-        }
-        i += 1;
-    }
+    let server = Server::bind((LOCAL_IP, LOCAL_PORT), router)?;
+    println!("listen on: http://{}/", server.local_addr().unwrap());
+    server.run()
 }
 
 #[cfg_attr(feature = "axstd", no_mangle)]
 fn main() {
     println!("Hello, ArceOS HTTP server!");
-    accept_loop().expect("test HTTP server failed");
+    http_server().expect("test HTTP server failed");
 }
@@ -0,0 +1,58 @@
+#![cfg_attr(feature = "axstd", no_std)]
+#![cfg_attr(feature = "axstd", no_main)]
+
+#[macro_use]
+#[cfg(feature = "axstd")]
+extern crate axstd as std;
+
+#[cfg(feature = "axstd")]
+fn run() -> std::io::Result<()> {
+    use std::net::{router, Ipv4Addr, SocketAddrV4};
+    use std::time::{Duration, Instant};
+
+    const LAN_ADDR: Ipv4Addr = Ipv4Addr::new(192, 168, 100, 1);
+    const LAN_PREFIX_LEN: u8 = 24;
+    const LAN_SERVER: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(192, 168, 100, 2), 80);
+    const STATS_INTERVAL: Duration = Duration::from_secs(5);
+
+    // eth0 is the outside, and eth1 is the inside
+    router::start(&[(LAN_ADDR, LAN_PREFIX_LEN)])?;
+    router::set_masquerade(true)?;
+    router::add_port_forward(router::PortForward {
+        protocol: router::NatProtocol::Tcp,
+        external_port: 8080,
+        internal: LAN_SERVER,
+    })?;
+    println!(
+        "Routing {}/{} on eth1, press Ctrl+A X to exit.",
+        LAN_ADDR, LAN_PREFIX_LEN
+    );
+
+    let mut last_print = Instant::now();
+    loop {
+        router::poll()?;
+        if last_print.elapsed() >= STATS_INTERVAL {
+            last_print = Instant::now();
+            if let Some(stats) = router::stats() {
+                println!(
+                    "forwarded: {}, dropped: {}, ICMP errors: {}, NAT connections: {}",
+                    stats.forwarded, stats.dropped, stats.icmp_errors, stats.nat_connections
+                );
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "axstd"))]
+fn run() -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "the router is only available on ArceOS",
+    ))
+}
+
+#[cfg_attr(feature = "axstd", no_mangle)]
+fn main() {
+    println!("Hello, router!");
+    run().expect("router failed");
+}
@@ -30,7 +30,7 @@
 //!
 //! | Device Category | Cargo Feature | Description |
 //! |-|-|-|
-//! | Block | `ramdisk` | A RAM disk that stores data in a vector |
+//! | Block | `ramdisk` | A RAM disk that stores data in a vector, see [`ramdisk`] |
 //! | Block | `virtio-blk` | VirtIO block device |
 //! | Network | `virtio-net` | VirtIO network device |
 //! | Display | `virtio-gpu` | VirtIO graphics device |
@@ -61,7 +61,7 @@
 #[macro_use]
 extern crate log;
 
-#[cfg(feature = "dyn")]
+#[cfg(any(feature = "dyn", feature = "ramdisk"))]
 extern crate alloc;
 
 #[macro_use]
@@ -78,6 +78,9 @@ mod virtio;
 #[cfg(feature = "ixgbe")]
 mod ixgbe;
 
+#[cfg(feature = "ramdisk")]
+pub mod ramdisk;
+
 pub mod prelude;
 
 #[allow(unused_imports)]
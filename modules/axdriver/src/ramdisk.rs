@@ -0,0 +1,110 @@
+//! A RAM disk that stores data in a vector, with a configurable block size.
+
+use alloc::{vec, vec::Vec};
+
+use axdriver_base::{BaseDriverOps, DevError, DevResult, DeviceType};
+use axdriver_block::BlockDriverOps;
+
+/// The default block size of RAM disks.
+pub const DEFAULT_BLOCK_SIZE: usize = 512;
+
+/// A RAM disk that stores data in a vector.
+pub struct RamDisk {
+    block_size: usize,
+    data: Vec<u8>,
+}
+
+impl RamDisk {
+    /// Creates a new RAM disk of at least `size_hint` bytes, rounded up to
+    /// a multiple of the block size.
+    pub fn new(size_hint: usize) -> Self {
+        Self {
+            block_size: DEFAULT_BLOCK_SIZE,
+            data: vec![0; size_hint.next_multiple_of(DEFAULT_BLOCK_SIZE)],
+        }
+    }
+
+    /// Sets the block size of the disk to `block_size`, which must be a
+    /// power of two. The disk is padded with zeros to a multiple of the new
+    /// block size.
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        assert!(block_size.is_power_of_two(), "invalid block size");
+        self.block_size = block_size;
+        let size = self.data.len().next_multiple_of(block_size);
+        self.data.resize(size, 0);
+        self
+    }
+
+    /// Returns the size of the disk in bytes.
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns the byte range of `len` bytes from `block_id`.
+    fn range(&self, block_id: u64, len: usize) -> DevResult<core::ops::Range<usize>> {
+        if !len.is_multiple_of(self.block_size) {
+            return Err(DevError::InvalidParam);
+        }
+        let start = (block_id as usize)
+            .checked_mul(self.block_size)
+            .ok_or(DevError::Io)?;
+        match start.checked_add(len) {
+            Some(end) if end <= self.data.len() => Ok(start..end),
+            _ => Err(DevError::Io),
+        }
+    }
+}
+
+impl Default for RamDisk {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl<T: AsRef<[u8]> + ?Sized> From<&T> for RamDisk {
+    /// Creates a RAM disk with a copy of `data`.
+    fn from(data: &T) -> Self {
+        let data = data.as_ref();
+        let mut disk = Self::new(data.len());
+        disk.data[..data.len()].copy_from_slice(data);
+        disk
+    }
+}
+
+impl BaseDriverOps for RamDisk {
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Block
+    }
+
+    fn device_name(&self) -> &str {
+        "ramdisk"
+    }
+}
+
+impl BlockDriverOps for RamDisk {
+    #[inline]
+    fn num_blocks(&self) -> u64 {
+        (self.data.len() / self.block_size) as u64
+    }
+
+    #[inline]
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DevResult {
+        let range = self.range(block_id, buf.len())?;
+        buf.copy_from_slice(&self.data[range]);
+        Ok(())
+    }
+
+    fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DevResult {
+        let range = self.range(block_id, buf.len())?;
+        self.data[range].copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> DevResult {
+        Ok(())
+    }
+}
@@ -0,0 +1,20 @@
+use std::path::Path;
+
+fn main() {
+    // the archive embedded as the initramfs, see `src/initramfs.rs`
+    println!("cargo:rerun-if-env-changed=AX_INITRAMFS");
+    let out_path = Path::new(&std::env::var("OUT_DIR").unwrap()).join("initramfs.img");
+    let archive = std::env::var("AX_INITRAMFS").unwrap_or_default();
+    if std::env::var_os("CARGO_FEATURE_INITRAMFS").is_some() && !archive.is_empty() {
+        // a relative path would be resolved against this crate, not where the
+        // build is started, `make INITRAMFS=<path>` makes it absolute
+        if Path::new(&archive).is_relative() {
+            panic!("AX_INITRAMFS must be an absolute path: {:?}", archive);
+        }
+        println!("cargo:rerun-if-changed={}", archive);
+        std::fs::copy(&archive, out_path)
+            .unwrap_or_else(|e| panic!("failed to read initramfs {:?}: {}", archive, e));
+    } else {
+        std::fs::write(out_path, []).unwrap();
+    }
+}
@@ -0,0 +1,37 @@
+#!/bin/bash
+
+# Creates the ext4 image used by `tests/test_ext4fs.rs`, with the same files as
+# the FAT images plus symlinks and a directory large enough to be indexed.
+# Doesn't need root, as `mkfs.ext4 -d` copies the files into the image.
+# `make unittest` runs it before the tests, the image itself is not committed.
+
+CUR_DIR=`dirname $0`
+
+create_ext4_img() {
+	local name=$1
+	local blkcount=$2
+	local root=$(mktemp -d)
+	for i in $(seq 1 1000); do
+	  echo "Rust is cool!" >>"$root/long.txt"
+	done
+	echo "Rust is cool!" >>"$root/short.txt"
+	mkdir -p "$root/very/long/path"
+	echo "Rust is cool!" >>"$root/very/long/path/test.txt"
+	mkdir -p "$root/very-long-dir-name"
+	echo "Rust is cool!" >>"$root/very-long-dir-name/very-long-file-name.txt"
+	ln -s "very/long/path/test.txt" "$root/link.txt"
+	ln -s "/very-long-dir-name" "$root/abs-link"
+	mkdir -p "$root/many"
+	for i in $(seq 1 300); do
+	  echo "$i" >"$root/many/file-with-a-long-name-$i"
+	done
+
+	rm -f "$name"
+	mkfs.ext4 -q -b 1024 -L "Test!" -U 12345678-1234-1234-1234-123456789abc \
+		-E root_owner=0:0,hash_seed=87654321-4321-4321-4321-cba987654321 -d "$root" "$name" $blkcount
+	# index the large directory with an htree
+	e2fsck -fyD "$name" >/dev/null
+	rm -rf "$root"
+}
+
+create_ext4_img "$CUR_DIR/ext4.img" 4096
@@ -0,0 +1,32 @@
+#!/bin/bash
+
+# Creates the GPT image used by `tests/test_partitions.rs`: the FAT16 test image
+# as the first partition, and a small ext4 filesystem as the second one.
+# Run it after `create_test_img.sh`. `make unittest` runs it before the tests,
+# the image itself is not committed.
+
+CUR_DIR=`dirname $0`
+
+create_gpt_img() {
+	local name=$1
+	local root=$(mktemp -d)
+	local ext4=$(mktemp)
+	echo "Hello from the root partition!" >"$root/hello.txt"
+	mkfs.ext4 -q -F -b 1024 -L rootfs -U 87654321-4321-4321-4321-210987654321 \
+		-E root_owner=0:0 -d "$root" "$ext4" 1024
+
+	rm -f "$name"
+	dd if=/dev/zero of="$name" bs=1M count=6
+	sfdisk -q "$name" <<-END
+	label: gpt
+	label-id: 11111111-2222-3333-4444-555555555555
+	first-lba: 34
+	start=2048, size=5000, type=EBD0A0A2-B9E5-4433-87C0-68B6B72699C7, uuid=aaaaaaaa-0000-0000-0000-000000000001, name=boot
+	start=8192, size=2048, type=0FC63DAF-8483-4772-8E79-3D69D8477DE4, uuid=aaaaaaaa-0000-0000-0000-000000000002, name=rootfs
+	END
+	dd if="$CUR_DIR/fat16.img" of="$name" bs=512 seek=2048 conv=notrunc
+	dd if="$ext4" of="$name" bs=512 seek=8192 conv=notrunc
+	rm -rf "$root" "$ext4"
+}
+
+create_gpt_img "$CUR_DIR/gpt.img"
@@ -4,7 +4,7 @@ mod dir;
 mod file;
 
 pub use self::dir::{DirBuilder, DirEntry, ReadDir};
-pub use self::file::{File, FileType, Metadata, OpenOptions, Permissions};
+pub use self::file::{File, FileTimes, FileType, Metadata, OpenOptions, Permissions};
 
 use alloc::{string::String, vec::Vec};
 use axio::{self as io, prelude::*};
@@ -59,6 +59,48 @@ pub fn metadata(path: &str) -> io::Result<Metadata> {
     File::open(path)?.metadata()
 }
 
+/// Query the metadata about a file without following symlinks.
+pub fn symlink_metadata(path: &str) -> io::Result<Metadata> {
+    crate::fs::get_attr(&crate::root::lookup_no_follow(None, path)?).map(Metadata)
+}
+
+/// Changes the timestamps of the file at `path`.
+///
+/// Only ext4, FAT and ramfs keep timestamps. FAT keeps only the date of the
+/// last access, and can't change those of directories.
+pub fn set_times(path: &str, times: FileTimes) -> io::Result<()> {
+    let node = crate::root::lookup(None, path)?;
+    crate::fs::set_times(&node, times.accessed, times.modified)
+}
+
+/// Changes the timestamps of the file at `path` like [`set_times`], or of
+/// the symlink itself if it's a symlink.
+pub fn set_symlink_times(path: &str, times: FileTimes) -> io::Result<()> {
+    let node = crate::root::lookup_no_follow(None, path)?;
+    crate::fs::set_times(&node, times.accessed, times.modified)
+}
+
+/// Reads a symbolic link, returning the path that the link points to.
+pub fn read_link(path: &str) -> io::Result<String> {
+    crate::root::read_link(path)
+}
+
+/// Creates a new symbolic link at `link` pointing to `original`.
+///
+/// `original` is not checked, and a relative one is resolved from the
+/// directory of the link. Only ext4 and ramfs support symlinks.
+pub fn symlink(original: &str, link: &str) -> io::Result<()> {
+    crate::root::symlink(original, link)
+}
+
+/// Creates a new hard link at `link` to the file at `original`.
+///
+/// Both paths must be in the same filesystem, which is ext4 or ramfs.
+/// Directories can't be linked.
+pub fn hard_link(original: &str, link: &str) -> io::Result<()> {
+    crate::root::link(original, link)
+}
+
 /// Creates a new, empty directory at the provided path.
 pub fn create_dir(path: &str) -> io::Result<()> {
     DirBuilder::new().create(path)
@@ -87,3 +129,58 @@ pub fn remove_file(path: &str) -> io::Result<()> {
 pub fn rename(old: &str, new: &str) -> io::Result<()> {
     crate::root::rename(old, new)
 }
+
+/// Mounts the filesystem of `fs_type` from `source` at `target`, creating the
+/// mount point if it does not exist.
+///
+/// `fs_type` is one of:
+///
+/// - `"ramfs"` (or `"tmpfs"`): a new empty RAM filesystem, `source` is only
+///   used as its name in `/proc/mounts`.
+/// - `"auto"` (or `""`), `"vfat"`, `"ext4"`, ...: the filesystem on the volume
+///   selected by `source`, e.g. `blk0p1` or `LABEL=data`. See
+///   [`VolumeInfo::matches`](crate::VolumeInfo::matches) for the syntax.
+///
+/// Filesystems can be mounted under other mounted filesystems. Mounting on
+/// an existing mount point, or mounting a volume twice fails with
+/// [`ResourceBusy`](io::Error::ResourceBusy).
+pub fn mount(source: &str, target: &str, fs_type: &str) -> io::Result<()> {
+    crate::root::mount(source, target, fs_type)
+}
+
+/// Mounts an overlay at `target`, which merges the directory `upper` over the
+/// directory `lower`, creating the mount point if it does not exist.
+///
+/// Reads see the files of both layers, with those of `upper` first. Files of
+/// `lower` are copied up to `upper` before they are modified, so `lower` is
+/// never written, and removed ones are hidden by whiteouts in `upper`. The
+/// filesystems of the layers are busy until the overlay is unmounted.
+#[cfg(feature = "overlayfs")]
+pub fn mount_overlay(lower: &str, upper: &str, target: &str) -> io::Result<()> {
+    crate::root::mount_overlay(lower, upper, target)
+}
+
+/// Writes all cached data of the filesystems back to the devices.
+///
+/// Written data stays in the block cache until it's evicted or flushed, or
+/// the last opened file of a written file is closed. It's also called when
+/// the system terminates, but not if it's powered off otherwise.
+pub fn sync() -> io::Result<()> {
+    crate::volumes::sync()
+}
+
+/// Unmounts the filesystem at `target`.
+///
+/// It fails with [`ResourceBusy`](io::Error::ResourceBusy) if a file or
+/// directory of the filesystem is still open, the current directory is in
+/// it, or other filesystems are mounted under it.
+pub fn umount(target: &str) -> io::Result<()> {
+    crate::root::umount(target, false)
+}
+
+/// Detaches the filesystem at `target` and those mounted under it from the
+/// directory tree, even if they are busy. Each filesystem is unmounted once
+/// it's no longer in use.
+pub fn umount_detach(target: &str) -> io::Result<()> {
+    crate::root::umount(target, true)
+}
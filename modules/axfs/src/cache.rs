@@ -0,0 +1,247 @@
+//! The block cache between [`Disk`](crate::dev::Disk) and the block device.
+//!
+//! Recently used blocks are kept in memory and evicted in LRU order. Writes
+//! only change the cached blocks (write-back), which are written to the
+//! device when evicted or flushed, along with the consecutive dirty blocks in
+//! one device request. On a miss of the block after a cached one, the
+//! following blocks are read ahead by the same device request.
+
+use alloc::{boxed::Box, collections::BTreeMap, vec, vec::Vec};
+use axdriver::prelude::*;
+
+/// The default number of blocks in the cache of each device (512 KiB for
+/// 512-byte blocks).
+pub(crate) const CACHE_BLOCKS: usize = 1024;
+
+/// The maximum number of blocks read ahead for sequential reads.
+const READ_AHEAD_BLOCKS: usize = 32;
+
+/// Statistics of the block cache of a device.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Number of block accesses served from the cache.
+    pub hits: u64,
+    /// Number of block accesses that missed the cache.
+    pub misses: u64,
+    /// Number of read requests to the device.
+    pub dev_reads: u64,
+    /// Number of write requests to the device.
+    pub dev_writes: u64,
+    /// Number of blocks read ahead of the accesses.
+    pub read_ahead: u64,
+    /// Number of blocks evicted from the cache.
+    pub evictions: u64,
+    /// Number of cached blocks.
+    pub cached: usize,
+    /// Number of cached blocks that are not written to the device yet.
+    pub dirty: usize,
+}
+
+struct CachedBlock {
+    data: Box<[u8]>,
+    dirty: bool,
+    /// The time of the last access, the key in `BlockCache::lru`.
+    stamp: u64,
+}
+
+/// A block device with an LRU cache of its blocks.
+pub(crate) struct BlockCache {
+    dev: AxBlockDevice,
+    block_size: usize,
+    num_blocks: u64,
+    capacity: usize,
+    blocks: BTreeMap<u64, CachedBlock>,
+    /// Block IDs by the time of the last access.
+    lru: BTreeMap<u64, u64>,
+    clock: u64,
+    stats: CacheStats,
+}
+
+impl BlockCache {
+    /// Creates a cache of at most `capacity` blocks for `dev`.
+    pub fn new(dev: AxBlockDevice, capacity: usize) -> Self {
+        Self {
+            block_size: dev.block_size(),
+            num_blocks: dev.num_blocks(),
+            dev,
+            capacity: capacity.max(1),
+            blocks: BTreeMap::new(),
+            lru: BTreeMap::new(),
+            clock: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    pub fn num_blocks(&self) -> u64 {
+        self.num_blocks
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            cached: self.blocks.len(),
+            dirty: self.blocks.values().filter(|b| b.dirty).count(),
+            ..self.stats
+        }
+    }
+
+    /// Reads `buf` from `offset` of the block `block_id`, within the block.
+    pub fn read(&mut self, block_id: u64, offset: usize, buf: &mut [u8]) -> DevResult {
+        self.check(block_id, offset, buf.len())?;
+        if let Some(block) = self.touch(block_id) {
+            buf.copy_from_slice(&block.data[offset..offset + buf.len()]);
+            self.stats.hits += 1;
+            return Ok(());
+        }
+        self.stats.misses += 1;
+
+        // the access is likely sequential if the previous block is cached,
+        // even if other blocks (e.g. metadata) are accessed in between
+        let sequential = block_id > 0 && self.blocks.contains_key(&(block_id - 1));
+        let count = if sequential {
+            // read ahead until a cached block
+            let max = READ_AHEAD_BLOCKS.min(self.capacity) as u64;
+            let max = max.min(self.num_blocks - block_id);
+            (1..max)
+                .find(|i| self.blocks.contains_key(&(block_id + i)))
+                .unwrap_or(max)
+        } else {
+            1
+        };
+        let data = self.read_dev(block_id, count)?;
+        self.stats.read_ahead += count - 1;
+        buf.copy_from_slice(&data[offset..offset + buf.len()]);
+        for (i, chunk) in data.chunks_exact(self.block_size).enumerate() {
+            self.insert(block_id + i as u64, chunk.into(), false)?;
+        }
+        Ok(())
+    }
+
+    /// Writes `buf` to `offset` of the block `block_id`, within the block.
+    ///
+    /// The block is written to the device when it's evicted or flushed. If
+    /// only a part of an uncached block is written, the block is read first.
+    pub fn write(&mut self, block_id: u64, offset: usize, buf: &[u8]) -> DevResult {
+        self.check(block_id, offset, buf.len())?;
+        if let Some(block) = self.touch(block_id) {
+            block.data[offset..offset + buf.len()].copy_from_slice(buf);
+            block.dirty = true;
+            self.stats.hits += 1;
+            return Ok(());
+        }
+        self.stats.misses += 1;
+        let data = if buf.len() == self.block_size {
+            buf.into()
+        } else {
+            let mut data = self.read_dev(block_id, 1)?;
+            data[offset..offset + buf.len()].copy_from_slice(buf);
+            data
+        };
+        self.insert(block_id, data, true)
+    }
+
+    fn check(&self, block_id: u64, offset: usize, len: usize) -> DevResult {
+        if block_id >= self.num_blocks || offset + len > self.block_size {
+            return Err(DevError::InvalidParam);
+        }
+        Ok(())
+    }
+
+    /// Reads `count` blocks from `block_id` by one device request.
+    fn read_dev(&mut self, block_id: u64, count: u64) -> DevResult<Box<[u8]>> {
+        let mut data = vec![0; count as usize * self.block_size].into_boxed_slice();
+        self.dev.read_block(block_id, &mut data)?;
+        self.stats.dev_reads += 1;
+        Ok(data)
+    }
+
+    /// Writes all dirty blocks to the device, and flushes the device.
+    pub fn flush(&mut self) -> DevResult {
+        let dirty: Vec<u64> = self
+            .blocks
+            .iter()
+            .filter_map(|(&id, b)| b.dirty.then_some(id))
+            .collect();
+        for id in dirty {
+            if self.blocks[&id].dirty {
+                self.write_back(id)?;
+            }
+        }
+        self.dev.flush()
+    }
+
+    /// Writes all dirty blocks to the device, and drops all cached blocks so
+    /// that they are read from the device again.
+    pub fn invalidate(&mut self) -> DevResult {
+        self.flush()?;
+        self.blocks.clear();
+        self.lru.clear();
+        Ok(())
+    }
+
+    /// Writes the run of consecutive dirty blocks around the dirty block
+    /// `block_id` to the device at once.
+    fn write_back(&mut self, block_id: u64) -> DevResult {
+        let is_dirty = |id| self.blocks.get(&id).is_some_and(|b| b.dirty);
+        let mut start = block_id;
+        while start > 0 && is_dirty(start - 1) {
+            start -= 1;
+        }
+        let mut end = block_id + 1;
+        while is_dirty(end) {
+            end += 1;
+        }
+        let mut data = vec![0; (end - start) as usize * self.block_size];
+        for (id, chunk) in (start..end).zip(data.chunks_exact_mut(self.block_size)) {
+            chunk.copy_from_slice(&self.blocks[&id].data);
+        }
+        self.dev.write_block(start, &data)?;
+        self.stats.dev_writes += 1;
+        for id in start..end {
+            self.blocks.get_mut(&id).unwrap().dirty = false;
+        }
+        Ok(())
+    }
+
+    /// Returns the cached block and marks it as the most recently used.
+    fn touch(&mut self, block_id: u64) -> Option<&mut CachedBlock> {
+        let block = self.blocks.get_mut(&block_id)?;
+        self.clock += 1;
+        self.lru.remove(&block.stamp);
+        self.lru.insert(self.clock, block_id);
+        block.stamp = self.clock;
+        Some(block)
+    }
+
+    fn insert(&mut self, block_id: u64, data: Box<[u8]>, dirty: bool) -> DevResult {
+        while self.blocks.len() >= self.capacity {
+            self.evict()?;
+        }
+        self.clock += 1;
+        self.lru.insert(self.clock, block_id);
+        let block = CachedBlock {
+            data,
+            dirty,
+            stamp: self.clock,
+        };
+        self.blocks.insert(block_id, block);
+        Ok(())
+    }
+
+    /// Removes the least recently used block, writing it back if dirty.
+    fn evict(&mut self) -> DevResult {
+        let Some((_, &block_id)) = self.lru.first_key_value() else {
+            return Ok(());
+        };
+        if self.blocks[&block_id].dirty {
+            self.write_back(block_id)?;
+        }
+        self.lru.pop_first();
+        self.blocks.remove(&block_id);
+        self.stats.evictions += 1;
+        Ok(())
+    }
+}
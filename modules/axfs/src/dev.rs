@@ -1,92 +1,148 @@
+use alloc::sync::Arc;
 use axdriver::prelude::*;
+use axsync::Mutex;
 
-const BLOCK_SIZE: usize = 512;
+use crate::cache::{BlockCache, CacheStats, CACHE_BLOCKS};
 
 /// A disk device with a cursor.
+///
+/// Blocks are accessed through a [`BlockCache`], which is shared by all
+/// slices of the device, so written data may not reach the device until
+/// [`Disk::flush`] is called.
+///
+/// A disk may be a slice of a block device, e.g. a partition, in which case
+/// positions are relative to the start of the slice and accesses beyond its
+/// end read or write nothing.
+///
+/// Positions are in bytes, and blocks are of the size of the device (512
+/// or 4096 bytes, ...). Accesses within a block are done in the cache, by
+/// reading the whole block first if needed.
 pub struct Disk {
     block_id: u64,
     offset: usize,
-    dev: AxBlockDevice,
+    start: u64,
+    num_blocks: u64,
+    block_size: usize,
+    dev: Arc<Mutex<BlockCache>>,
 }
 
 impl Disk {
     /// Create a new disk.
     pub fn new(dev: AxBlockDevice) -> Self {
-        assert_eq!(BLOCK_SIZE, dev.block_size());
+        let block_size = dev.block_size();
+        assert!(
+            block_size.is_power_of_two() && block_size >= 512,
+            "unsupported block size {}",
+            block_size
+        );
         Self {
             block_id: 0,
             offset: 0,
-            dev,
+            start: 0,
+            num_blocks: dev.num_blocks(),
+            block_size,
+            dev: Arc::new(Mutex::new(BlockCache::new(dev, CACHE_BLOCKS))),
         }
     }
 
+    /// Create a disk of `num_blocks` blocks starting at block `start` of this
+    /// disk, sharing the same device.
+    pub fn slice(&self, start: u64, num_blocks: u64) -> Self {
+        let start = start.min(self.num_blocks);
+        Self {
+            block_id: 0,
+            offset: 0,
+            start: self.start + start,
+            num_blocks: num_blocks.min(self.num_blocks - start),
+            block_size: self.block_size,
+            dev: self.dev.clone(),
+        }
+    }
+
+    /// Get the size of a block in bytes.
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
     /// Get the size of the disk.
     pub fn size(&self) -> u64 {
-        self.dev.num_blocks() * BLOCK_SIZE as u64
+        self.num_blocks * self.block_size as u64
     }
 
     /// Get the position of the cursor.
     pub fn position(&self) -> u64 {
-        self.block_id * BLOCK_SIZE as u64 + self.offset as u64
+        self.block_id * self.block_size as u64 + self.offset as u64
     }
 
     /// Set the position of the cursor.
     pub fn set_position(&mut self, pos: u64) {
-        self.block_id = pos / BLOCK_SIZE as u64;
-        self.offset = pos as usize % BLOCK_SIZE;
+        self.block_id = pos / self.block_size as u64;
+        self.offset = (pos % self.block_size as u64) as usize;
     }
 
-    /// Read within one block, returns the number of bytes read.
-    pub fn read_one(&mut self, buf: &mut [u8]) -> DevResult<usize> {
-        let read_size = if self.offset == 0 && buf.len() >= BLOCK_SIZE {
-            // whole block
-            self.dev
-                .read_block(self.block_id, &mut buf[0..BLOCK_SIZE])?;
-            self.block_id += 1;
-            BLOCK_SIZE
-        } else {
-            // partial block
-            let mut data = [0u8; BLOCK_SIZE];
-            let start = self.offset;
-            let count = buf.len().min(BLOCK_SIZE - self.offset);
+    /// Write all cached data of the device to it.
+    pub fn flush(&self) -> DevResult {
+        self.dev.lock().flush()
+    }
 
-            self.dev.read_block(self.block_id, &mut data)?;
-            buf[..count].copy_from_slice(&data[start..start + count]);
+    /// Write all cached data of the device to it, and drop the cache.
+    pub fn invalidate(&self) -> DevResult {
+        self.dev.lock().invalidate()
+    }
 
-            self.offset += count;
-            if self.offset >= BLOCK_SIZE {
-                self.block_id += 1;
-                self.offset -= BLOCK_SIZE;
-            }
-            count
-        };
-        Ok(read_size)
+    /// Get the statistics of the block cache of the device.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.dev.lock().stats()
+    }
+
+    /// Read within one block, returns the number of bytes read.
+    pub fn read_one(&mut self, buf: &mut [u8]) -> DevResult<usize> {
+        if self.block_id >= self.num_blocks {
+            return Ok(0);
+        }
+        let count = buf.len().min(self.block_size - self.offset);
+        self.dev
+            .lock()
+            .read(self.start + self.block_id, self.offset, &mut buf[..count])?;
+        self.advance(count);
+        Ok(count)
     }
 
     /// Write within one block, returns the number of bytes written.
     pub fn write_one(&mut self, buf: &[u8]) -> DevResult<usize> {
-        let write_size = if self.offset == 0 && buf.len() >= BLOCK_SIZE {
-            // whole block
-            self.dev.write_block(self.block_id, &buf[0..BLOCK_SIZE])?;
-            self.block_id += 1;
-            BLOCK_SIZE
-        } else {
-            // partial block
-            let mut data = [0u8; BLOCK_SIZE];
-            let start = self.offset;
-            let count = buf.len().min(BLOCK_SIZE - self.offset);
-
-            self.dev.read_block(self.block_id, &mut data)?;
-            data[start..start + count].copy_from_slice(&buf[..count]);
-            self.dev.write_block(self.block_id, &data)?;
+        if self.block_id >= self.num_blocks {
+            return Ok(0);
+        }
+        let count = buf.len().min(self.block_size - self.offset);
+        self.dev
+            .lock()
+            .write(self.start + self.block_id, self.offset, &buf[..count])?;
+        self.advance(count);
+        Ok(count)
+    }
 
-            self.offset += count;
-            if self.offset >= BLOCK_SIZE {
-                self.block_id += 1;
-                self.offset -= BLOCK_SIZE;
+    fn advance(&mut self, count: usize) {
+        self.offset += count;
+        if self.offset >= self.block_size {
+            self.block_id += 1;
+            self.offset -= self.block_size;
+        }
+    }
+    /// Read exactly `buf.len()` bytes at `pos`, without moving the cursor.
+    pub(crate) fn read_exact_at(&mut self, pos: u64, mut buf: &mut [u8]) -> DevResult {
+        let saved = self.position();
+        self.set_position(pos);
+        let result = loop {
+            if buf.is_empty() {
+                break Ok(());
+            }
+            match self.read_one(buf) {
+                Ok(0) => break Err(DevError::Io),
+                Ok(n) => buf = &mut buf[n..],
+                Err(e) => break Err(e),
             }
-            count
         };
-        Ok(write_size)
+        self.set_position(saved);
+        result
     }
 }
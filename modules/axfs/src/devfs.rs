@@ -0,0 +1,345 @@
+//! Device nodes in `/dev`.
+//!
+//! Drivers make their devices accessible by path with [`register_char`] and
+//! [`register_block`]. Reads, writes and `ioctl`s of the opened device file
+//! are passed to its [`DeviceOps`], e.g.
+//!
+//! ```ignore
+//! struct Led;
+//!
+//! impl axfs::devfs::DeviceOps for Led {
+//!     fn write_at(&self, _offset: u64, buf: &[u8]) -> AxResult<usize> {
+//!         led_set(buf.first() == Some(&b'1'));
+//!         Ok(buf.len())
+//!     }
+//! }
+//!
+//! axfs::devfs::register_char("led0", Arc::new(Led))?;
+//! ```
+//!
+//! Besides `null`, `zero`, `random` and `urandom`, each block device and
+//! partition found by [`init_filesystems`](crate::init_filesystems) is
+//! registered as `sda`, `sda1`, `sdb`, ... Devices can be registered before
+//! or after the filesystems are initialized.
+
+use alloc::{format, string::String, sync::Arc};
+
+use axerrno::{ax_err, AxError, AxResult};
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
+use axsync::Mutex;
+
+use crate::dev::Disk;
+use crate::fs::pseudo::PseudoDir;
+use crate::volumes::Volume;
+
+static DEVFS_ROOT: Mutex<Option<Arc<PseudoDir>>> = Mutex::new(None);
+
+/// Operations of a device, which are called when its device file is
+/// accessed.
+pub trait DeviceOps: Send + Sync {
+    /// Reads data from the device at `offset`. Char devices may ignore the
+    /// offset.
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> AxResult<usize> {
+        ax_err!(InvalidInput)
+    }
+
+    /// Writes data to the device at `offset`. Char devices may ignore the
+    /// offset.
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> AxResult<usize> {
+        ax_err!(InvalidInput)
+    }
+
+    /// Performs a device-specific request `cmd`, where `arg` is an integer or
+    /// a pointer as in `ioctl(2)`. Returns a non-negative value on success.
+    ///
+    /// Unknown requests fail with [`Unsupported`], which is `ENOTTY` in the
+    /// POSIX API.
+    ///
+    /// [`Unsupported`]: AxError::Unsupported
+    fn ioctl(&self, _cmd: u32, _arg: usize) -> AxResult<usize> {
+        ax_err!(Unsupported)
+    }
+
+    /// Writes all buffered data to the device.
+    fn flush(&self) -> AxResult {
+        Ok(())
+    }
+
+    /// Returns the size of the device in bytes, or 0 if it has no size.
+    fn size(&self) -> u64 {
+        0
+    }
+}
+
+/// The filesystem mounted at `/dev`.
+pub(crate) struct DevFileSystem {
+    root: Arc<PseudoDir>,
+}
+
+impl DevFileSystem {
+    pub fn new() -> Self {
+        Self { root: root() }
+    }
+}
+
+impl VfsOps for DevFileSystem {
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
+
+fn root() -> Arc<PseudoDir> {
+    DEVFS_ROOT
+        .lock()
+        .get_or_insert_with(|| {
+            let root = PseudoDir::new(None);
+            root.add("null", Arc::new(crate::fs::devfs::NullDev));
+            root.add("zero", Arc::new(crate::fs::devfs::ZeroDev));
+            root.mkdir("foo")
+                .add("bar", Arc::new(crate::fs::devfs::ZeroDev));
+            let random = Arc::new(DeviceNode::new(VfsNodeType::CharDevice, Arc::new(Random)));
+            root.add("random", random.clone());
+            root.add("urandom", random);
+            root
+        })
+        .clone()
+}
+
+/// A device file.
+struct DeviceNode {
+    ty: VfsNodeType,
+    ops: Arc<dyn DeviceOps>,
+}
+
+impl DeviceNode {
+    fn new(ty: VfsNodeType, ops: Arc<dyn DeviceOps>) -> Self {
+        Self { ty, ops }
+    }
+}
+
+impl VfsNodeOps for DeviceNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let perm = match self.ty {
+            VfsNodeType::BlockDevice => 0o660,
+            _ => 0o666,
+        };
+        let size = self.ops.size();
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(perm),
+            self.ty,
+            size,
+            size.div_ceil(512),
+        ))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        self.ops.read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        self.ops.write_at(offset, buf)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        self.ops.flush()
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        // allow opening with `O_TRUNC`, which is ignored by devices
+        Ok(())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+fn register(path: &str, ty: VfsNodeType, ops: Arc<dyn DeviceOps>) -> AxResult {
+    let (dir, name) = root().parent_of(path, true)?;
+    if dir.try_add(name, Arc::new(DeviceNode::new(ty, ops))) {
+        Ok(())
+    } else {
+        ax_err!(AlreadyExists)
+    }
+}
+
+/// Registers a char device at `path` relative to `/dev`, e.g. `ttyS1` or
+/// `input/event0`.
+///
+/// Missing directories are created. It fails with [`AlreadyExists`] if the
+/// path is taken.
+///
+/// [`AlreadyExists`]: AxError::AlreadyExists
+pub fn register_char(path: &str, ops: Arc<dyn DeviceOps>) -> AxResult {
+    register(path, VfsNodeType::CharDevice, ops)
+}
+
+/// Registers a block device at `path` relative to `/dev`, like
+/// [`register_char`].
+pub fn register_block(path: &str, ops: Arc<dyn DeviceOps>) -> AxResult {
+    register(path, VfsNodeType::BlockDevice, ops)
+}
+
+/// Removes the device or directory at `path` relative to `/dev`.
+///
+/// Open files of the removed devices can still be used.
+pub fn unregister(path: &str) -> AxResult {
+    let (dir, name) = root().parent_of(path, false)?;
+    match dir.remove_entry(name) {
+        Some(_) => Ok(()),
+        None => ax_err!(NotFound),
+    }
+}
+
+/// Performs the `ioctl` request `cmd` on the device file `node`.
+pub(crate) fn ioctl(node: &VfsNodeRef, cmd: u32, arg: usize) -> AxResult<usize> {
+    match node.as_any().downcast_ref::<DeviceNode>() {
+        Some(dev) => dev.ops.ioctl(cmd, arg),
+        None => ax_err!(Unsupported),
+    }
+}
+
+/// `/dev/random` and `/dev/urandom`, which read from the generator of
+/// [`axhal::random`]. Reads fail with [`AxError::Unsupported`] if it has no
+/// entropy source, and data written is mixed into it without being credited
+/// as entropy.
+struct Random;
+
+impl DeviceOps for Random {
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+        axhal::random::fill_bytes(buf).or_else(|e| ax_err!(Unsupported, e))?;
+        Ok(buf.len())
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> AxResult<usize> {
+        axhal::random::add_randomness(buf);
+        Ok(buf.len())
+    }
+}
+
+const BLKFLSBUF: u32 = 0x1261;
+const BLKSSZGET: u32 = 0x1268;
+const BLKGETSIZE64: u32 = 0x8008_1272;
+
+/// A block device or a partition.
+struct VolumeDev {
+    disk: Mutex<Disk>,
+    size: u64,
+}
+
+impl DeviceOps for VolumeDev {
+    fn read_at(&self, offset: u64, mut buf: &mut [u8]) -> AxResult<usize> {
+        let mut disk = self.disk.lock();
+        disk.set_position(offset);
+        let mut read_len = 0;
+        while !buf.is_empty() {
+            match disk.read_one(buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    buf = &mut buf[n..];
+                    read_len += n;
+                }
+                Err(_) => return ax_err!(Io),
+            }
+        }
+        Ok(read_len)
+    }
+
+    fn write_at(&self, offset: u64, mut buf: &[u8]) -> AxResult<usize> {
+        let mut disk = self.disk.lock();
+        disk.set_position(offset);
+        let mut write_len = 0;
+        while !buf.is_empty() {
+            match disk.write_one(buf) {
+                Ok(0) if write_len == 0 => return ax_err!(StorageFull),
+                Ok(0) => break,
+                Ok(n) => {
+                    buf = &buf[n..];
+                    write_len += n;
+                }
+                Err(_) => return ax_err!(Io),
+            }
+        }
+        Ok(write_len)
+    }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> AxResult<usize> {
+        match cmd {
+            // flushes and drops the cache of the device, as in Linux
+            BLKFLSBUF => match self.disk.lock().invalidate() {
+                Ok(()) => Ok(0),
+                Err(_) => ax_err!(Io),
+            },
+            BLKSSZGET => {
+                let block_size = self.disk.lock().block_size() as i32;
+                // SAFETY: `arg` points to an `int` for `BLKSSZGET`.
+                unsafe { write_arg(arg, block_size) }
+            }
+            // SAFETY: `arg` points to a `u64` for `BLKGETSIZE64`.
+            BLKGETSIZE64 => unsafe { write_arg(arg, self.size) },
+            _ => ax_err!(Unsupported),
+        }
+    }
+
+    fn flush(&self) -> AxResult {
+        self.disk.lock().flush().map_err(|_| AxError::Io)
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// Writes the result of an `ioctl` request to the pointer `arg`.
+///
+/// It fails with [`AxError::BadAddress`] if `arg` is null or misaligned.
+///
+/// # Safety
+///
+/// Unless null, `arg` must be valid for writes of a `T`. As applications
+/// share the address space of the kernel, this can't be checked: it's up to
+/// the caller of `ioctl`, which passes a pointer to the `T` that the request
+/// `cmd` is documented to fill in.
+pub unsafe fn write_arg<T>(arg: usize, value: T) -> AxResult<usize> {
+    let ptr = arg as *mut T;
+    if ptr.is_null() || !ptr.is_aligned() {
+        return ax_err!(BadAddress);
+    }
+    // SAFETY: `ptr` is non-null, aligned, and valid for writes as required
+    // by the caller.
+    unsafe { ptr.write(value) };
+    Ok(0)
+}
+
+/// The Linux name of a block device: `sda`, ..., `sdz`, `sdaa`, ...
+fn sd_name(mut idx: usize) -> String {
+    let mut letters = String::new();
+    loop {
+        letters.insert(0, (b'a' + (idx % 26) as u8) as char);
+        if idx < 26 {
+            break;
+        }
+        idx = idx / 26 - 1;
+    }
+    format!("sd{}", letters)
+}
+
+/// Registers each volume as `sd<X>` or `sd<X><N>` for its `N`-th partition.
+pub(crate) fn add_volumes(volumes: &[Volume]) {
+    for vol in volumes {
+        let Some(rest) = vol.info.name.strip_prefix("blk") else {
+            continue;
+        };
+        let (idx, part) = rest.split_once('p').unwrap_or((rest, ""));
+        let Ok(idx) = idx.parse() else {
+            continue;
+        };
+        let name = format!("{}{}", sd_name(idx), part);
+        let dev = VolumeDev {
+            disk: Mutex::new(vol.disk.slice(0, u64::MAX)),
+            size: vol.info.size,
+        };
+        match register_block(&name, Arc::new(dev)) {
+            Ok(()) => info!("  {} is /dev/{}", vol.info.name, name),
+            Err(e) => warn!("cannot add /dev/{}: {:?}", name, e),
+        }
+    }
+}
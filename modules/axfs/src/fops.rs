@@ -1,10 +1,18 @@
 //! Low-level filesystem operations.
 
+use alloc::string::String;
 use axerrno::{ax_err, ax_err_type, AxError, AxResult};
-use axfs_vfs::{VfsError, VfsNodeRef};
+use axfs_vfs::{VfsError, VfsNodeAttr, VfsNodeRef};
 use axio::SeekFrom;
 use cap_access::{Cap, WithCap};
 use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::time::Duration;
+
+use crate::lock::{self, FileId};
+pub use crate::lock::{LockKind, RangeLock};
+use crate::watch;
+pub use crate::watch::{EventKind, WatchEvent, Watcher};
 
 #[cfg(feature = "myfs")]
 pub use crate::dev::Disk;
@@ -15,16 +23,51 @@ pub use crate::fs::myfs::MyFileSystemIf;
 pub type FileType = axfs_vfs::VfsNodeType;
 /// Alias of [`axfs_vfs::VfsDirEntry`].
 pub type DirEntry = axfs_vfs::VfsDirEntry;
-/// Alias of [`axfs_vfs::VfsNodeAttr`].
-pub type FileAttr = axfs_vfs::VfsNodeAttr;
 /// Alias of [`axfs_vfs::VfsNodePerm`].
 pub type FilePerm = axfs_vfs::VfsNodePerm;
 
+/// File attributes: those in [`axfs_vfs::VfsNodeAttr`], plus the inode
+/// number, owner, link count and timestamps.
+///
+/// ext4 keeps all of them, FAT and ramfs only the timestamps. Those not kept
+/// by the filesystem are zero, and the link count is one.
+#[derive(Debug, Clone, Copy)]
+pub struct FileAttr {
+    attr: VfsNodeAttr,
+    pub(crate) ino: u64,
+    pub(crate) uid: u32,
+    pub(crate) gid: u32,
+    pub(crate) nlink: u32,
+    pub(crate) atime: Duration,
+    pub(crate) mtime: Duration,
+    pub(crate) ctime: Duration,
+}
+
 /// An opened file object, with open permissions and a cursor.
 pub struct File {
     node: WithCap<VfsNodeRef>,
     is_append: bool,
     offset: u64,
+    /// The file for its locks.
+    id: FileId,
+    /// The owner of the whole-file lock of this opened file.
+    lock_owner: u64,
+    /// The absolute path for events of watches, if it's known.
+    path: Option<String>,
+    /// Whether the file was written through this opened file, so it's
+    /// flushed when the last opened file of it is closed.
+    written: AtomicBool,
+}
+
+/// A handle to lock the file of an opened [`File`], see
+/// [`File::lock_handle`].
+#[derive(Clone)]
+pub struct LockHandle {
+    id: FileId,
+    /// The owner of the whole-file lock.
+    owner: u64,
+    readable: bool,
+    writable: bool,
 }
 
 /// An opened directory object, with open permissions and a cursor for
@@ -111,19 +154,117 @@ impl OpenOptions {
     }
 }
 
+impl FileAttr {
+    /// Creates attributes of a file without an owner or timestamps.
+    pub const fn new(perm: FilePerm, ty: FileType, size: u64, blocks: u64) -> Self {
+        Self::from_vfs(VfsNodeAttr::new(perm, ty, size, blocks))
+    }
+
+    const fn from_vfs(attr: VfsNodeAttr) -> Self {
+        Self {
+            attr,
+            ino: 0,
+            uid: 0,
+            gid: 0,
+            nlink: 1,
+            atime: Duration::ZERO,
+            mtime: Duration::ZERO,
+            ctime: Duration::ZERO,
+        }
+    }
+
+    /// Returns the permissions of the file.
+    pub const fn perm(&self) -> FilePerm {
+        self.attr.perm()
+    }
+
+    /// Returns the type of the file.
+    pub const fn file_type(&self) -> FileType {
+        self.attr.file_type()
+    }
+
+    /// Whether the file is a regular file.
+    pub const fn is_file(&self) -> bool {
+        self.attr.is_file()
+    }
+
+    /// Whether the file is a directory.
+    pub const fn is_dir(&self) -> bool {
+        self.attr.is_dir()
+    }
+
+    /// Returns the size of the file, in bytes.
+    pub const fn size(&self) -> u64 {
+        self.attr.size()
+    }
+
+    /// Returns the number of blocks allocated to the file, in 512-byte units.
+    pub const fn blocks(&self) -> u64 {
+        self.attr.blocks()
+    }
+
+    /// Returns the inode number.
+    pub const fn ino(&self) -> u64 {
+        self.ino
+    }
+
+    /// Returns the user ID of the owner.
+    pub const fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    /// Returns the group ID of the owner.
+    pub const fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    /// Returns the number of hard links.
+    pub const fn nlink(&self) -> u32 {
+        self.nlink
+    }
+
+    /// Returns the time of the last access, since the Unix epoch.
+    pub const fn atime(&self) -> Duration {
+        self.atime
+    }
+
+    /// Returns the time of the last modification, since the Unix epoch.
+    pub const fn mtime(&self) -> Duration {
+        self.mtime
+    }
+
+    /// Returns the time of the last status change, since the Unix epoch.
+    pub const fn ctime(&self) -> Duration {
+        self.ctime
+    }
+}
+
+impl From<VfsNodeAttr> for FileAttr {
+    fn from(attr: VfsNodeAttr) -> Self {
+        Self::from_vfs(attr)
+    }
+}
+
 impl File {
     fn access_node(&self, cap: Cap) -> AxResult<&VfsNodeRef> {
         self.node.access_or_err(cap, AxError::PermissionDenied)
     }
 
+    /// Reports the modification of the file to the watches of it.
+    fn notify_modified(&self) {
+        if let Some(path) = &self.path {
+            watch::notify(EventKind::Modify, path, 0);
+        }
+    }
+
     fn _open_at(dir: Option<&VfsNodeRef>, path: &str, opts: &OpenOptions) -> AxResult<Self> {
         debug!("open file: {} {:?}", path, opts);
         if !opts.is_valid() {
             return ax_err!(InvalidInput);
         }
 
-        let node_option = crate::root::lookup(dir, path);
-        let node = if opts.create || opts.create_new {
+        let node_option = crate::root::lookup_with_path(dir, path);
+        let (node, abs_path) = if opts.create || opts.create_new {
             match node_option {
                 Ok(node) => {
                     // already exists
@@ -133,7 +274,10 @@ impl File {
                     node
                 }
                 // not exists, create new
-                Err(VfsError::NotFound) => crate::root::create_file(dir, path)?,
+                Err(VfsError::NotFound) => {
+                    crate::root::create_file(dir, path)?;
+                    crate::root::lookup_with_path(dir, path)?
+                }
                 Err(e) => return Err(e),
             }
         } else {
@@ -156,11 +300,21 @@ impl File {
         if opts.truncate {
             node.truncate(0)?;
         }
-        Ok(Self {
+        static NEXT_LOCK_OWNER: AtomicU64 = AtomicU64::new(1);
+        let file = Self {
+            id: crate::fs::file_id(&node, abs_path.clone()),
+            lock_owner: NEXT_LOCK_OWNER.fetch_add(1, Ordering::Relaxed),
+            path: abs_path,
             node: WithCap::new(node, access_cap),
             is_append: opts.append,
             offset: 0,
-        })
+            written: AtomicBool::new(opts.truncate),
+        };
+        lock::open(&file.id);
+        if opts.truncate {
+            file.notify_modified();
+        }
+        Ok(file)
     }
 
     /// Opens a file at the path relative to the current directory. Returns a
@@ -172,6 +326,8 @@ impl File {
     /// Truncates the file to the specified size.
     pub fn truncate(&self, size: u64) -> AxResult {
         self.access_node(Cap::WRITE)?.truncate(size)?;
+        self.written.store(true, Ordering::Relaxed);
+        self.notify_modified();
         Ok(())
     }
 
@@ -209,6 +365,10 @@ impl File {
         let node = self.access_node(Cap::WRITE)?;
         let write_len = node.write_at(offset, buf)?;
         self.offset = offset + write_len as u64;
+        if write_len > 0 {
+            self.written.store(true, Ordering::Relaxed);
+            self.notify_modified();
+        }
         Ok(write_len)
     }
 
@@ -219,6 +379,10 @@ impl File {
     pub fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize> {
         let node = self.access_node(Cap::WRITE)?;
         let write_len = node.write_at(offset, buf)?;
+        if write_len > 0 {
+            self.written.store(true, Ordering::Relaxed);
+            self.notify_modified();
+        }
         Ok(write_len)
     }
 
@@ -244,7 +408,99 @@ impl File {
 
     /// Gets the file attributes.
     pub fn get_attr(&self) -> AxResult<FileAttr> {
-        self.access_node(Cap::empty())?.get_attr()
+        crate::fs::get_attr(self.access_node(Cap::empty())?)
+    }
+
+    /// Sets the access and modification times of the file, leaving those of
+    /// `None` unchanged.
+    ///
+    /// It fails with [`PermissionDenied`](AxError::PermissionDenied) if the
+    /// filesystem doesn't keep timestamps.
+    pub fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> AxResult {
+        crate::fs::set_times(self.access_node(Cap::empty())?, atime, mtime)
+    }
+
+    /// Performs the device-specific request `cmd` with `arg` on a device
+    /// file, see [`DeviceOps::ioctl`](crate::devfs::DeviceOps::ioctl).
+    ///
+    /// It fails with [`Unsupported`](AxError::Unsupported) if the file is not
+    /// a device.
+    pub fn ioctl(&self, cmd: u32, arg: usize) -> AxResult<usize> {
+        let node = self.access_node(Cap::empty())?;
+        #[cfg(feature = "devfs")]
+        return crate::devfs::ioctl(node, cmd, arg);
+        #[cfg(not(feature = "devfs"))]
+        {
+            let _ = (node, cmd, arg);
+            ax_err!(Unsupported)
+        }
+    }
+
+    /// Returns a handle to lock the file, which can be used without
+    /// borrowing this opened file, e.g. while another task is waiting for a
+    /// lock with it.
+    pub fn lock_handle(&self) -> LockHandle {
+        LockHandle {
+            id: self.id.clone(),
+            owner: self.lock_owner,
+            readable: self.access_node(Cap::READ).is_ok(),
+            writable: self.access_node(Cap::WRITE).is_ok(),
+        }
+    }
+}
+
+impl LockHandle {
+    /// Acquires a lock of `kind` on the whole file for the opened file (like
+    /// `flock`), or converts the one it holds. The lock is released when the
+    /// opened file is closed.
+    ///
+    /// If other opened files hold conflicting locks, it waits for them to be
+    /// released if `wait` is true, or fails with
+    /// [`WouldBlock`](AxError::WouldBlock) otherwise.
+    pub fn lock(&self, kind: LockKind, wait: bool) -> AxResult {
+        lock::lock_whole(&self.id, self.owner, kind, wait)
+    }
+
+    /// Releases the whole-file lock of the opened file, if any.
+    pub fn unlock(&self) {
+        lock::unlock_whole(&self.id, self.owner)
+    }
+
+    /// Acquires the byte-range `lock` (like `fcntl` record locks), replacing
+    /// the locks of its owner in the range. Locks of an owner are shared by
+    /// all opened files of the same file, and are released when the last of
+    /// them is closed.
+    ///
+    /// Shared locks need read access, and exclusive ones need write access.
+    /// If other owners hold conflicting locks, it waits for them to be
+    /// released if `wait` is true, or fails with
+    /// [`WouldBlock`](AxError::WouldBlock) otherwise. It fails with
+    /// [`ResourceBusy`](AxError::ResourceBusy) if waiting would deadlock, as
+    /// the owners of the conflicting locks are waiting for those of the
+    /// owner, directly or not.
+    pub fn lock_range(&self, lock: RangeLock, wait: bool) -> AxResult {
+        if lock.start >= lock.end {
+            return ax_err!(InvalidInput);
+        }
+        let allowed = match lock.kind {
+            LockKind::Shared => self.readable,
+            LockKind::Exclusive => self.writable,
+        };
+        if !allowed {
+            return ax_err!(PermissionDenied);
+        }
+        lock::lock_range(&self.id, lock, wait)
+    }
+
+    /// Unlocks the bytes from `start` to `end` (exclusive) locked by `owner`.
+    pub fn unlock_range(&self, owner: u64, start: u64, end: u64) {
+        lock::unlock_range(&self.id, owner, start, end)
+    }
+
+    /// Returns a byte-range lock of another owner that conflicts with `lock`,
+    /// if any.
+    pub fn test_lock_range(&self, lock: &RangeLock) -> Option<RangeLock> {
+        lock::test_range(&self.id, lock)
     }
 }
 
@@ -349,7 +605,12 @@ impl Directory {
 
 impl Drop for File {
     fn drop(&mut self) {
-        unsafe { self.node.access_unchecked().release().ok() };
+        let written = *self.written.get_mut();
+        let node = unsafe { self.node.access_unchecked() };
+        if lock::close(&self.id, self.lock_owner, written) {
+            node.fsync().ok();
+        }
+        node.release().ok();
     }
 }
 
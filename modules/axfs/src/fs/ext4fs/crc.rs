@@ -0,0 +1,42 @@
+//! Checksums of ext4 metadata. Both are the raw CRCs without the final
+//! inversion, as computed by the kernel and e2fsprogs.
+
+const fn make_table(poly: u32) -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut j = 0;
+        while j < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ poly
+            } else {
+                crc >> 1
+            };
+            j += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static CRC32C_TABLE: [u32; 256] = make_table(0x82f6_3b78);
+static CRC16_TABLE: [u32; 256] = make_table(0xa001);
+
+/// CRC32C (Castagnoli), used when the `metadata_csum` feature is enabled.
+pub fn crc32c(mut crc: u32, data: &[u8]) -> u32 {
+    for &b in data {
+        crc = (crc >> 8) ^ CRC32C_TABLE[((crc ^ b as u32) & 0xff) as usize];
+    }
+    crc
+}
+
+/// CRC16 (ANSI), used for group descriptors with the `uninit_bg` feature.
+pub fn crc16(crc: u16, data: &[u8]) -> u16 {
+    let mut crc = crc as u32;
+    for &b in data {
+        crc = (crc >> 8) ^ CRC16_TABLE[((crc ^ b as u32) & 0xff) as usize];
+    }
+    crc as u16
+}
@@ -0,0 +1,518 @@
+//! Directories: linear blocks of entries, optionally indexed by a hash tree
+//! (htree) whose nodes are hidden in blocks that look empty to old drivers.
+//!
+//! Lookups in indexed directories use the tree. New entries go to the leaf
+//! block the tree points to; if that block is full, the index is dropped and
+//! the directory is used as a linear one, which is always valid.
+
+use alloc::{vec, vec::Vec};
+
+use axerrno::{ax_err, AxResult};
+
+use super::crc::crc32c;
+use super::hash::dirhash;
+use super::layout::*;
+use super::volume::{Mapping, Volume};
+
+const ENTRY_HEADER_SIZE: usize = 8;
+/// Size of the fake entry holding the checksum at the end of leaf blocks.
+const TAIL_SIZE: usize = 12;
+const TAIL_FILE_TYPE: u8 = 0xde;
+/// Offset of the `dx_root_info` in the first block of indexed directories.
+const DX_ROOT_INFO: usize = 0x18;
+const DX_ROOT_ENTRIES: usize = 0x20;
+const DX_NODE_ENTRIES: usize = 0x8;
+
+/// An entry of a directory.
+pub struct DirEntry {
+    pub ino: u32,
+    pub name: Vec<u8>,
+    /// One of the `FT_*` types, or [`FT_UNKNOWN`] if the filesystem doesn't
+    /// record types in directory entries.
+    pub file_type: u8,
+}
+
+struct RawEntry {
+    off: usize,
+    rec_len: usize,
+    ino: u32,
+    name_len: usize,
+}
+
+/// Where an entry is, for updating it.
+struct EntryLoc {
+    pblk: u64,
+    buf: Vec<u8>,
+    off: usize,
+    prev: Option<usize>,
+    file_type: u8,
+}
+
+/// The leaf blocks that may hold a name, found through the hash tree.
+struct DxLeaves {
+    blocks: Vec<u32>,
+    /// Whether the name can't be in other blocks.
+    complete: bool,
+}
+
+fn entry_size(name_len: usize) -> usize {
+    (ENTRY_HEADER_SIZE + name_len + 3) & !3
+}
+
+fn rec_len_from_disk(len: u16) -> usize {
+    if len == 0 || len == u16::MAX {
+        1 << 16
+    } else {
+        len as usize
+    }
+}
+
+fn rec_len_to_disk(len: usize) -> u16 {
+    if len >= 1 << 16 {
+        u16::MAX
+    } else {
+        len as u16
+    }
+}
+
+/// Whether a directory block ends with a checksum tail.
+fn has_tail(buf: &[u8]) -> bool {
+    let tail = buf.len() - TAIL_SIZE;
+    le32(buf, tail) == 0
+        && le16(buf, tail + 4) as usize == TAIL_SIZE
+        && buf[tail + 6] == 0
+        && buf[tail + 7] == TAIL_FILE_TYPE
+}
+
+impl Volume {
+    fn tail_size(&self) -> usize {
+        if self.has_metadata_csum() {
+            TAIL_SIZE
+        } else {
+            0
+        }
+    }
+
+    fn read_dir_block(&mut self, dir: &Inode, lblk: u32) -> AxResult<(u64, Vec<u8>)> {
+        match self.map(dir, lblk)? {
+            Mapping::Mapped {
+                pblk, init: true, ..
+            } => {
+                let mut buf = vec![0; self.block_size];
+                self.read_block(pblk, &mut buf)?;
+                Ok((pblk, buf))
+            }
+            _ => ax_err!(InvalidData, "hole in ext4 directory"),
+        }
+    }
+
+    /// Writes a leaf block of a directory, updating its checksum.
+    fn write_dir_block(&mut self, ino: u32, dir: &Inode, pblk: u64, buf: &mut [u8]) -> AxResult {
+        self.set_dir_block_csum(ino, dir, buf);
+        self.write_block(pblk, buf)
+    }
+
+    fn set_dir_block_csum(&self, ino: u32, dir: &Inode, buf: &mut [u8]) {
+        if !self.has_metadata_csum() {
+            return;
+        }
+        if !has_tail(buf) {
+            warn!("ext4: no checksum tail in directory block of inode {}", ino);
+            return;
+        }
+        let tail = buf.len() - TAIL_SIZE;
+        let csum = crc32c(self.inode_csum_seed(ino, dir), &buf[..tail]);
+        set_le32(buf, tail + 8, csum);
+    }
+
+    /// Initializes the checksum tail of an empty leaf block.
+    fn init_dir_tail(&self, buf: &mut [u8]) {
+        if self.has_metadata_csum() {
+            let tail = buf.len() - TAIL_SIZE;
+            buf[tail..].fill(0);
+            set_le16(buf, tail + 4, TAIL_SIZE as u16);
+            buf[tail + 7] = TAIL_FILE_TYPE;
+        }
+    }
+
+    /// Parses the entries of a directory block. Blocks of the htree index
+    /// parse as a few entries spanning the whole block, without a tail.
+    fn parse_entries(&self, buf: &[u8]) -> AxResult<Vec<RawEntry>> {
+        let end = if self.has_metadata_csum() && has_tail(buf) {
+            buf.len() - TAIL_SIZE
+        } else {
+            buf.len()
+        };
+        let mut entries = Vec::new();
+        let mut off = 0;
+        while off < end {
+            if off + ENTRY_HEADER_SIZE > end {
+                return ax_err!(InvalidData, "corrupted ext4 directory");
+            }
+            let rec_len = rec_len_from_disk(le16(buf, off + 4));
+            let name_len = if self.filetype {
+                buf[off + 6] as usize
+            } else {
+                le16(buf, off + 6) as usize
+            };
+            if rec_len < ENTRY_HEADER_SIZE
+                || !rec_len.is_multiple_of(4)
+                || off + rec_len > end
+                || name_len > MAX_NAME_LEN
+                || ENTRY_HEADER_SIZE + name_len > rec_len
+            {
+                return ax_err!(InvalidData, "corrupted ext4 directory");
+            }
+            entries.push(RawEntry {
+                off,
+                rec_len,
+                ino: le32(buf, off),
+                name_len,
+            });
+            off += rec_len;
+        }
+        Ok(entries)
+    }
+
+    fn put_entry(&self, buf: &mut [u8], off: usize, rec_len: usize, ino: u32, name: &[u8], ft: u8) {
+        set_le32(buf, off, ino);
+        set_le16(buf, off + 4, rec_len_to_disk(rec_len));
+        if self.filetype {
+            buf[off + 6] = name.len() as u8;
+            buf[off + 7] = ft;
+        } else {
+            set_le16(buf, off + 6, name.len() as u16);
+        }
+        buf[off + ENTRY_HEADER_SIZE..off + ENTRY_HEADER_SIZE + name.len()].copy_from_slice(name);
+    }
+
+    fn entry_type(&self, buf: &[u8], entry: &RawEntry) -> u8 {
+        if self.filetype {
+            buf[entry.off + 7]
+        } else {
+            FT_UNKNOWN
+        }
+    }
+
+    fn dir_blocks(&self, dir: &Inode) -> u32 {
+        (dir.size() / self.block_size as u64) as u32
+    }
+
+    /// Returns all entries of a directory, including `.` and `..`.
+    pub fn dir_entries(&mut self, dir: &Inode) -> AxResult<Vec<DirEntry>> {
+        let mut result = Vec::new();
+        for lblk in 0..self.dir_blocks(dir) {
+            let (_, buf) = self.read_dir_block(dir, lblk)?;
+            for entry in self.parse_entries(&buf)? {
+                if entry.ino == 0 {
+                    continue;
+                }
+                let name_off = entry.off + ENTRY_HEADER_SIZE;
+                result.push(DirEntry {
+                    ino: entry.ino,
+                    name: buf[name_off..name_off + entry.name_len].to_vec(),
+                    file_type: self.entry_type(&buf, &entry),
+                });
+            }
+        }
+        Ok(result)
+    }
+
+    /// Whether a directory has no entries other than `.` and `..`.
+    pub fn dir_is_empty(&mut self, dir: &Inode) -> AxResult<bool> {
+        Ok(self
+            .dir_entries(dir)?
+            .iter()
+            .all(|entry| entry.name == b"." || entry.name == b".."))
+    }
+
+    fn find_in_block(&mut self, dir: &Inode, lblk: u32, name: &[u8]) -> AxResult<Option<EntryLoc>> {
+        let (pblk, buf) = self.read_dir_block(dir, lblk)?;
+        let mut prev = None;
+        for entry in self.parse_entries(&buf)? {
+            let name_off = entry.off + ENTRY_HEADER_SIZE;
+            if entry.ino != 0 && &buf[name_off..name_off + entry.name_len] == name {
+                return Ok(Some(EntryLoc {
+                    file_type: self.entry_type(&buf, &entry),
+                    pblk,
+                    buf,
+                    off: entry.off,
+                    prev,
+                }));
+            }
+            prev = Some(entry.off);
+        }
+        Ok(None)
+    }
+
+    fn find_entry(&mut self, dir: &Inode, name: &[u8]) -> AxResult<Option<EntryLoc>> {
+        if name == b"." || name == b".." {
+            return self.find_in_block(dir, 0, name);
+        }
+        let mut searched = Vec::new();
+        if let Some(leaves) = self.dx_leaves(dir, name)? {
+            for &lblk in &leaves.blocks {
+                if let Some(loc) = self.find_in_block(dir, lblk, name)? {
+                    return Ok(Some(loc));
+                }
+            }
+            if leaves.complete {
+                return Ok(None);
+            }
+            searched = leaves.blocks;
+        }
+        for lblk in 0..self.dir_blocks(dir) {
+            if !searched.contains(&lblk) {
+                if let Some(loc) = self.find_in_block(dir, lblk, name)? {
+                    return Ok(Some(loc));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Looks up `name` in a directory, returning its inode and file type.
+    pub fn dir_lookup(&mut self, dir: &Inode, name: &[u8]) -> AxResult<Option<(u32, u8)>> {
+        Ok(self
+            .find_entry(dir, name)?
+            .map(|loc| (le32(&loc.buf, loc.off), loc.file_type)))
+    }
+
+    /// Adds an entry to a directory. The directory inode may change, and is
+    /// not written back.
+    pub fn dir_add(
+        &mut self,
+        ino: u32,
+        dir: &mut Inode,
+        name: &[u8],
+        child: u32,
+        ft: u8,
+    ) -> AxResult {
+        if name.is_empty() || name.len() > MAX_NAME_LEN {
+            return ax_err!(InvalidInput, "invalid file name");
+        }
+        if dir.flags() & INODE_FLAG_INDEX != 0 {
+            if let Some(leaves) = self.dx_leaves(dir, name)? {
+                let lblk = leaves.blocks[0];
+                if self.insert_in_block(ino, dir, lblk, name, child, ft)? {
+                    return Ok(());
+                }
+            }
+            self.dx_unindex(ino, dir)?;
+        }
+
+        let nblocks = self.dir_blocks(dir);
+        for lblk in 0..nblocks {
+            if self.insert_in_block(ino, dir, lblk, name, child, ft)? {
+                return Ok(());
+            }
+        }
+
+        // No room in existing blocks, append a new one.
+        let bs = self.block_size;
+        let mut buf = vec![0; bs];
+        self.put_entry(&mut buf, 0, bs - self.tail_size(), child, name, ft);
+        self.init_dir_tail(&mut buf);
+        self.set_dir_block_csum(ino, dir, &mut buf);
+        self.write_data(ino, dir, nblocks as u64 * bs as u64, &buf)?;
+        Ok(())
+    }
+
+    fn insert_in_block(
+        &mut self,
+        ino: u32,
+        dir: &Inode,
+        lblk: u32,
+        name: &[u8],
+        child: u32,
+        ft: u8,
+    ) -> AxResult<bool> {
+        let need = entry_size(name.len());
+        let (pblk, mut buf) = self.read_dir_block(dir, lblk)?;
+        for entry in self.parse_entries(&buf)? {
+            let used = if entry.ino == 0 {
+                0
+            } else {
+                entry_size(entry.name_len)
+            };
+            if entry.rec_len < used + need {
+                continue;
+            }
+            if used == 0 {
+                self.put_entry(&mut buf, entry.off, entry.rec_len, child, name, ft);
+            } else {
+                set_le16(&mut buf, entry.off + 4, used as u16);
+                let off = entry.off + used;
+                self.put_entry(&mut buf, off, entry.rec_len - used, child, name, ft);
+            }
+            self.write_dir_block(ino, dir, pblk, &mut buf)?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Removes the entry `name` from a directory, returning its inode.
+    pub fn dir_remove(&mut self, ino: u32, dir: &Inode, name: &[u8]) -> AxResult<u32> {
+        let Some(mut loc) = self.find_entry(dir, name)? else {
+            return ax_err!(NotFound);
+        };
+        let child = le32(&loc.buf, loc.off);
+        let rec_len = rec_len_from_disk(le16(&loc.buf, loc.off + 4));
+        match loc.prev {
+            Some(prev) => {
+                let prev_len = rec_len_from_disk(le16(&loc.buf, prev + 4));
+                set_le16(&mut loc.buf, prev + 4, rec_len_to_disk(prev_len + rec_len));
+            }
+            None => set_le32(&mut loc.buf, loc.off, 0),
+        }
+        self.write_dir_block(ino, dir, loc.pblk, &mut loc.buf)?;
+        Ok(child)
+    }
+
+    /// Points the existing entry `name` of a directory to another inode.
+    pub fn dir_set(&mut self, ino: u32, dir: &Inode, name: &[u8], child: u32, ft: u8) -> AxResult {
+        let Some(mut loc) = self.find_entry(dir, name)? else {
+            return ax_err!(NotFound);
+        };
+        set_le32(&mut loc.buf, loc.off, child);
+        if self.filetype {
+            loc.buf[loc.off + 7] = ft;
+        }
+        self.write_dir_block(ino, dir, loc.pblk, &mut loc.buf)
+    }
+
+    /// Writes the first block of a new directory, with `.` and `..`.
+    pub fn dir_init(&mut self, ino: u32, dir: &mut Inode, parent: u32) -> AxResult {
+        let bs = self.block_size;
+        let mut buf = vec![0; bs];
+        self.put_entry(&mut buf, 0, 12, ino, b".", FT_DIR);
+        self.put_entry(
+            &mut buf,
+            12,
+            bs - 12 - self.tail_size(),
+            parent,
+            b"..",
+            FT_DIR,
+        );
+        self.init_dir_tail(&mut buf);
+        self.set_dir_block_csum(ino, dir, &mut buf);
+        self.write_data(ino, dir, 0, &buf)?;
+        Ok(())
+    }
+
+    /// Returns the hash version and the depth of an indexed directory, or
+    /// `None` if it is not indexed or the index is not usable.
+    fn dx_root(&mut self, dir: &Inode) -> AxResult<Option<(u8, usize, Vec<u8>)>> {
+        if dir.flags() & INODE_FLAG_INDEX == 0 || self.dir_blocks(dir) == 0 {
+            return Ok(None);
+        }
+        let (_, buf) = self.read_dir_block(dir, 0)?;
+        let mut version = buf[DX_ROOT_INFO + 4];
+        let info_len = buf[DX_ROOT_INFO + 5];
+        let levels = buf[DX_ROOT_INFO + 6] as usize;
+        if le32(&buf, DX_ROOT_INFO) != 0 || info_len != 8 || levels > 2 {
+            warn!("ext4: unsupported directory index");
+            return Ok(None);
+        }
+        if self.unsigned_hash && version <= 2 {
+            version += 3;
+        }
+        Ok(Some((version, levels, buf)))
+    }
+
+    /// Parses the entries of an index node as (hash, logical block).
+    fn dx_entries(&self, buf: &[u8], off: usize) -> Option<Vec<(u32, u32)>> {
+        let limit = le16(buf, off) as usize;
+        let count = le16(buf, off + 2) as usize;
+        if count == 0 || count > limit || off + limit * 8 > buf.len() {
+            return None;
+        }
+        Some(
+            (0..count)
+                .map(|i| {
+                    let hash = if i == 0 { 0 } else { le32(buf, off + i * 8) };
+                    (hash, le32(buf, off + i * 8 + 4) & 0x0fff_ffff)
+                })
+                .collect(),
+        )
+    }
+
+    fn dx_leaves(&mut self, dir: &Inode, name: &[u8]) -> AxResult<Option<DxLeaves>> {
+        let Some((version, levels, root)) = self.dx_root(dir)? else {
+            return Ok(None);
+        };
+        let Some(hash) = dirhash(name, version, &self.hash_seed) else {
+            return Ok(None);
+        };
+        let Some(mut entries) = self.dx_entries(&root, DX_ROOT_ENTRIES) else {
+            return Ok(None);
+        };
+        // whether the path so far is the last one of the tree
+        let mut last_path = true;
+        for level in 0..=levels {
+            let idx = entries.partition_point(|&(h, _)| h <= hash).max(1) - 1;
+            if level == levels {
+                let mut blocks = vec![entries[idx].1];
+                let mut next = idx + 1;
+                // names with the same hash may continue in the next blocks
+                while next < entries.len() && entries[next].0 & !1 == hash {
+                    blocks.push(entries[next].1);
+                    next += 1;
+                }
+                return Ok(Some(DxLeaves {
+                    blocks,
+                    complete: next < entries.len() || last_path,
+                }));
+            }
+            last_path &= idx + 1 == entries.len();
+            let (_, node) = self.read_dir_block(dir, entries[idx].1)?;
+            match self.dx_entries(&node, DX_NODE_ENTRIES) {
+                Some(e) => entries = e,
+                None => return Ok(None),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Drops the index of a directory, turning its index nodes into empty
+    /// leaf blocks.
+    fn dx_unindex(&mut self, ino: u32, dir: &mut Inode) -> AxResult {
+        debug!("ext4: dropping the index of directory {}", ino);
+        let mut nodes = Vec::new();
+        if let Some((_, levels, root)) = self.dx_root(dir)? {
+            if levels > 0 {
+                let mut level = self.dx_entries(&root, DX_ROOT_ENTRIES).unwrap_or_default();
+                for _ in 1..levels {
+                    let mut next = Vec::new();
+                    for &(_, lblk) in &level {
+                        nodes.push(lblk);
+                        let (_, node) = self.read_dir_block(dir, lblk)?;
+                        next.extend(self.dx_entries(&node, DX_NODE_ENTRIES).unwrap_or_default());
+                    }
+                    level = next;
+                }
+                nodes.extend(level.iter().map(|&(_, lblk)| lblk));
+            }
+        }
+
+        let bs = self.block_size;
+        let end = bs - self.tail_size();
+        let (pblk, mut buf) = self.read_dir_block(dir, 0)?;
+        let parent = le32(&buf, 12);
+        buf.fill(0);
+        self.put_entry(&mut buf, 0, 12, ino, b".", FT_DIR);
+        self.put_entry(&mut buf, 12, end - 12, parent, b"..", FT_DIR);
+        self.init_dir_tail(&mut buf);
+        dir.set_flags(dir.flags() & !INODE_FLAG_INDEX);
+        self.write_dir_block(ino, dir, pblk, &mut buf)?;
+
+        for lblk in nodes {
+            let (pblk, mut buf) = self.read_dir_block(dir, lblk)?;
+            buf.fill(0);
+            set_le16(&mut buf, 4, rec_len_to_disk(end));
+            self.init_dir_tail(&mut buf);
+            self.write_dir_block(ino, dir, pblk, &mut buf)?;
+        }
+        Ok(())
+    }
+}
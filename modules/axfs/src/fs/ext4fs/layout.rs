@@ -0,0 +1,543 @@
+//! On-disk structures of ext2/3/4, kept as raw little-endian bytes so that the
+//! fields we don't know about survive a read-modify-write.
+
+use alloc::{vec, vec::Vec};
+use core::time::Duration;
+
+pub const SUPERBLOCK_OFFSET: u64 = 1024;
+pub const SUPERBLOCK_SIZE: usize = 1024;
+pub const EXT4_MAGIC: u16 = 0xef53;
+pub const ROOT_INO: u32 = 2;
+pub const GOOD_OLD_INODE_SIZE: usize = 128;
+pub const GOOD_OLD_FIRST_INO: u32 = 11;
+/// Number of 32-bit words in `i_block`.
+pub const N_BLOCKS: usize = 15;
+/// Targets shorter than this are stored in `i_block` of the symlink inode.
+pub const FAST_SYMLINK_MAX: usize = N_BLOCKS * 4 - 1;
+pub const MAX_NAME_LEN: usize = 255;
+
+pub const INCOMPAT_FILETYPE: u32 = 0x2;
+pub const INCOMPAT_RECOVER: u32 = 0x4;
+pub const INCOMPAT_EXTENTS: u32 = 0x40;
+pub const INCOMPAT_64BIT: u32 = 0x80;
+pub const INCOMPAT_FLEX_BG: u32 = 0x200;
+pub const INCOMPAT_EA_INODE: u32 = 0x400;
+pub const INCOMPAT_CSUM_SEED: u32 = 0x2000;
+pub const INCOMPAT_LARGEDIR: u32 = 0x4000;
+/// Incompatible features we know how to read and write.
+pub const INCOMPAT_SUPPORTED: u32 = INCOMPAT_FILETYPE
+    | INCOMPAT_RECOVER
+    | INCOMPAT_EXTENTS
+    | INCOMPAT_64BIT
+    | INCOMPAT_FLEX_BG
+    | INCOMPAT_EA_INODE
+    | INCOMPAT_CSUM_SEED
+    | INCOMPAT_LARGEDIR;
+
+pub const RO_COMPAT_SPARSE_SUPER: u32 = 0x1;
+pub const RO_COMPAT_LARGE_FILE: u32 = 0x2;
+pub const RO_COMPAT_BTREE_DIR: u32 = 0x4;
+pub const RO_COMPAT_HUGE_FILE: u32 = 0x8;
+pub const RO_COMPAT_GDT_CSUM: u32 = 0x10;
+pub const RO_COMPAT_DIR_NLINK: u32 = 0x20;
+pub const RO_COMPAT_EXTRA_ISIZE: u32 = 0x40;
+pub const RO_COMPAT_METADATA_CSUM: u32 = 0x400;
+/// Read-only compatible features we keep consistent when writing, the
+/// others (quota, bigalloc, verity, ...) make the filesystem read-only.
+pub const RO_COMPAT_SUPPORTED: u32 = RO_COMPAT_SPARSE_SUPER
+    | RO_COMPAT_LARGE_FILE
+    | RO_COMPAT_BTREE_DIR
+    | RO_COMPAT_HUGE_FILE
+    | RO_COMPAT_GDT_CSUM
+    | RO_COMPAT_DIR_NLINK
+    | RO_COMPAT_EXTRA_ISIZE
+    | RO_COMPAT_METADATA_CSUM;
+
+pub const BG_INODE_UNINIT: u16 = 0x1;
+pub const BG_BLOCK_UNINIT: u16 = 0x2;
+
+pub const S_IFMT: u16 = 0o170000;
+pub const S_IFIFO: u16 = 0o010000;
+pub const S_IFCHR: u16 = 0o020000;
+pub const S_IFDIR: u16 = 0o040000;
+pub const S_IFBLK: u16 = 0o060000;
+pub const S_IFREG: u16 = 0o100000;
+pub const S_IFLNK: u16 = 0o120000;
+pub const S_IFSOCK: u16 = 0o140000;
+
+pub const INODE_FLAG_INDEX: u32 = 0x1000;
+pub const INODE_FLAG_HUGE_FILE: u32 = 0x40000;
+pub const INODE_FLAG_EXTENTS: u32 = 0x80000;
+
+pub const FT_UNKNOWN: u8 = 0;
+pub const FT_REG_FILE: u8 = 1;
+pub const FT_DIR: u8 = 2;
+pub const FT_CHRDEV: u8 = 3;
+pub const FT_BLKDEV: u8 = 4;
+pub const FT_FIFO: u8 = 5;
+pub const FT_SOCK: u8 = 6;
+pub const FT_SYMLINK: u8 = 7;
+
+pub fn le16(buf: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes(buf[off..off + 2].try_into().unwrap())
+}
+
+pub fn le32(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(buf[off..off + 4].try_into().unwrap())
+}
+
+pub fn set_le16(buf: &mut [u8], off: usize, val: u16) {
+    buf[off..off + 2].copy_from_slice(&val.to_le_bytes());
+}
+
+pub fn set_le32(buf: &mut [u8], off: usize, val: u32) {
+    buf[off..off + 4].copy_from_slice(&val.to_le_bytes());
+}
+
+/// Reads a 64-bit value split into a low and a high 32-bit half.
+fn lo_hi(buf: &[u8], lo: usize, hi: Option<usize>) -> u64 {
+    let hi = hi.map_or(0, |hi| le32(buf, hi) as u64);
+    (hi << 32) | le32(buf, lo) as u64
+}
+
+fn set_lo_hi(buf: &mut [u8], lo: usize, hi: Option<usize>, val: u64) {
+    set_le32(buf, lo, val as u32);
+    if let Some(hi) = hi {
+        set_le32(buf, hi, (val >> 32) as u32);
+    }
+}
+
+/// The superblock.
+pub struct Superblock {
+    pub raw: Vec<u8>,
+}
+
+impl Superblock {
+    pub fn new(raw: Vec<u8>) -> Self {
+        Self { raw }
+    }
+
+    fn is_64bit(&self) -> bool {
+        self.feature_incompat() & INCOMPAT_64BIT != 0
+    }
+
+    fn hi(&self, off: usize) -> Option<usize> {
+        self.is_64bit().then_some(off)
+    }
+
+    pub fn inodes_count(&self) -> u32 {
+        le32(&self.raw, 0x0)
+    }
+
+    pub fn blocks_count(&self) -> u64 {
+        lo_hi(&self.raw, 0x4, self.hi(0x150))
+    }
+
+    pub fn free_blocks_count(&self) -> u64 {
+        lo_hi(&self.raw, 0xc, self.hi(0x158))
+    }
+
+    pub fn set_free_blocks_count(&mut self, val: u64) {
+        let hi = self.hi(0x158);
+        set_lo_hi(&mut self.raw, 0xc, hi, val)
+    }
+
+    pub fn free_inodes_count(&self) -> u32 {
+        le32(&self.raw, 0x10)
+    }
+
+    pub fn set_free_inodes_count(&mut self, val: u32) {
+        set_le32(&mut self.raw, 0x10, val)
+    }
+
+    pub fn first_data_block(&self) -> u32 {
+        le32(&self.raw, 0x14)
+    }
+
+    pub fn log_block_size(&self) -> u32 {
+        le32(&self.raw, 0x18)
+    }
+
+    pub fn blocks_per_group(&self) -> u32 {
+        le32(&self.raw, 0x20)
+    }
+
+    pub fn inodes_per_group(&self) -> u32 {
+        le32(&self.raw, 0x28)
+    }
+
+    pub fn set_wtime(&mut self, val: u32) {
+        set_le32(&mut self.raw, 0x30, val)
+    }
+
+    pub fn magic(&self) -> u16 {
+        le16(&self.raw, 0x38)
+    }
+
+    pub fn rev_level(&self) -> u32 {
+        le32(&self.raw, 0x4c)
+    }
+
+    pub fn first_ino(&self) -> u32 {
+        if self.rev_level() == 0 {
+            GOOD_OLD_FIRST_INO
+        } else {
+            le32(&self.raw, 0x54)
+        }
+    }
+
+    pub fn inode_size(&self) -> usize {
+        if self.rev_level() == 0 {
+            GOOD_OLD_INODE_SIZE
+        } else {
+            le16(&self.raw, 0x58) as usize
+        }
+    }
+
+    pub fn feature_incompat(&self) -> u32 {
+        le32(&self.raw, 0x60)
+    }
+
+    pub fn feature_ro_compat(&self) -> u32 {
+        le32(&self.raw, 0x64)
+    }
+
+    pub fn uuid(&self) -> &[u8] {
+        &self.raw[0x68..0x78]
+    }
+
+    pub fn reserved_gdt_blocks(&self) -> u32 {
+        le16(&self.raw, 0xce) as u32
+    }
+
+    pub fn hash_seed(&self) -> [u32; 4] {
+        core::array::from_fn(|i| le32(&self.raw, 0xec + i * 4))
+    }
+
+    pub fn def_hash_version(&self) -> u8 {
+        self.raw[0xfc]
+    }
+
+    pub fn desc_size(&self) -> usize {
+        if self.is_64bit() {
+            le16(&self.raw, 0xfe) as usize
+        } else {
+            32
+        }
+    }
+
+    /// Whether directory hashes treat names as unsigned chars.
+    pub fn unsigned_hash(&self) -> bool {
+        le32(&self.raw, 0x160) & 0x2 != 0
+    }
+
+    pub fn checksum_seed(&self) -> u32 {
+        le32(&self.raw, 0x270)
+    }
+
+    pub fn set_checksum(&mut self, val: u32) {
+        set_le32(&mut self.raw, 0x3fc, val)
+    }
+}
+
+/// A block group descriptor.
+pub struct GroupDesc<'a> {
+    raw: &'a mut [u8],
+}
+
+impl<'a> GroupDesc<'a> {
+    pub fn new(raw: &'a mut [u8]) -> Self {
+        Self { raw }
+    }
+
+    fn hi(&self, off: usize) -> Option<usize> {
+        (self.raw.len() >= 64).then_some(off)
+    }
+
+    fn lo_hi16(&self, lo: usize, hi: usize) -> u32 {
+        let hi = self.hi(hi).map_or(0, |hi| le16(self.raw, hi) as u32);
+        (hi << 16) | le16(self.raw, lo) as u32
+    }
+
+    fn set_lo_hi16(&mut self, lo: usize, hi: usize, val: u32) {
+        set_le16(self.raw, lo, val as u16);
+        if let Some(hi) = self.hi(hi) {
+            set_le16(self.raw, hi, (val >> 16) as u16);
+        }
+    }
+
+    pub fn block_bitmap(&self) -> u64 {
+        lo_hi(self.raw, 0x0, self.hi(0x20))
+    }
+
+    pub fn inode_bitmap(&self) -> u64 {
+        lo_hi(self.raw, 0x4, self.hi(0x24))
+    }
+
+    pub fn inode_table(&self) -> u64 {
+        lo_hi(self.raw, 0x8, self.hi(0x28))
+    }
+
+    pub fn free_blocks_count(&self) -> u32 {
+        self.lo_hi16(0xc, 0x2c)
+    }
+
+    pub fn set_free_blocks_count(&mut self, val: u32) {
+        self.set_lo_hi16(0xc, 0x2c, val)
+    }
+
+    pub fn free_inodes_count(&self) -> u32 {
+        self.lo_hi16(0xe, 0x2e)
+    }
+
+    pub fn set_free_inodes_count(&mut self, val: u32) {
+        self.set_lo_hi16(0xe, 0x2e, val)
+    }
+
+    pub fn used_dirs_count(&self) -> u32 {
+        self.lo_hi16(0x10, 0x30)
+    }
+
+    pub fn set_used_dirs_count(&mut self, val: u32) {
+        self.set_lo_hi16(0x10, 0x30, val)
+    }
+
+    pub fn flags(&self) -> u16 {
+        le16(self.raw, 0x12)
+    }
+
+    pub fn set_flags(&mut self, val: u16) {
+        set_le16(self.raw, 0x12, val)
+    }
+
+    pub fn set_block_bitmap_csum(&mut self, val: u32) {
+        self.set_lo_hi16(0x18, 0x38, val)
+    }
+
+    pub fn set_inode_bitmap_csum(&mut self, val: u32) {
+        self.set_lo_hi16(0x1a, 0x3a, val)
+    }
+
+    pub fn itable_unused(&self) -> u32 {
+        self.lo_hi16(0x1c, 0x32)
+    }
+
+    pub fn set_itable_unused(&mut self, val: u32) {
+        self.set_lo_hi16(0x1c, 0x32, val)
+    }
+
+    pub fn set_checksum(&mut self, val: u16) {
+        set_le16(self.raw, 0x1e, val)
+    }
+}
+
+/// An inode, with its extra fields if the inode size is larger than 128.
+#[derive(Clone)]
+pub struct Inode {
+    pub raw: Vec<u8>,
+}
+
+impl Inode {
+    pub fn new(size: usize) -> Self {
+        Self { raw: vec![0; size] }
+    }
+
+    fn has_extra(&self, end: usize) -> bool {
+        self.raw.len() > GOOD_OLD_INODE_SIZE
+            && GOOD_OLD_INODE_SIZE + self.extra_isize() as usize >= end
+    }
+
+    pub fn mode(&self) -> u16 {
+        le16(&self.raw, 0x0)
+    }
+
+    pub fn set_mode(&mut self, val: u16) {
+        set_le16(&mut self.raw, 0x0, val)
+    }
+
+    pub fn file_type(&self) -> u16 {
+        self.mode() & S_IFMT
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type() == S_IFDIR
+    }
+
+    pub fn uid(&self) -> u32 {
+        (le16(&self.raw, 0x78) as u32) << 16 | le16(&self.raw, 0x2) as u32
+    }
+
+    pub fn gid(&self) -> u32 {
+        (le16(&self.raw, 0x7a) as u32) << 16 | le16(&self.raw, 0x18) as u32
+    }
+
+    pub fn size(&self) -> u64 {
+        lo_hi(&self.raw, 0x4, Some(0x6c))
+    }
+
+    pub fn set_size(&mut self, val: u64) {
+        set_lo_hi(&mut self.raw, 0x4, Some(0x6c), val)
+    }
+
+    pub fn links_count(&self) -> u16 {
+        le16(&self.raw, 0x1a)
+    }
+
+    pub fn set_links_count(&mut self, val: u16) {
+        set_le16(&mut self.raw, 0x1a, val)
+    }
+
+    /// Number of 512-byte sectors used, in units of the filesystem block if
+    /// the inode has the huge file flag.
+    pub fn blocks(&self) -> u64 {
+        (le16(&self.raw, 0x74) as u64) << 32 | le32(&self.raw, 0x1c) as u64
+    }
+
+    pub fn set_blocks(&mut self, val: u64) {
+        set_le32(&mut self.raw, 0x1c, val as u32);
+        set_le16(&mut self.raw, 0x74, (val >> 32) as u16);
+    }
+
+    pub fn flags(&self) -> u32 {
+        le32(&self.raw, 0x20)
+    }
+
+    pub fn set_flags(&mut self, val: u32) {
+        set_le32(&mut self.raw, 0x20, val)
+    }
+
+    pub fn i_block(&self) -> &[u8] {
+        &self.raw[0x28..0x28 + N_BLOCKS * 4]
+    }
+
+    pub fn i_block_mut(&mut self) -> &mut [u8] {
+        &mut self.raw[0x28..0x28 + N_BLOCKS * 4]
+    }
+
+    pub fn generation(&self) -> u32 {
+        le32(&self.raw, 0x64)
+    }
+
+    pub fn file_acl(&self) -> u64 {
+        (le16(&self.raw, 0x76) as u64) << 32 | le32(&self.raw, 0x68) as u64
+    }
+
+    pub fn set_file_acl(&mut self, val: u64) {
+        set_le32(&mut self.raw, 0x68, val as u32);
+        set_le16(&mut self.raw, 0x76, (val >> 32) as u16);
+    }
+
+    pub fn set_dtime(&mut self, val: u32) {
+        set_le32(&mut self.raw, 0x14, val)
+    }
+
+    pub fn extra_isize(&self) -> u16 {
+        if self.raw.len() > GOOD_OLD_INODE_SIZE {
+            le16(&self.raw, 0x80)
+        } else {
+            0
+        }
+    }
+
+    pub fn set_extra_isize(&mut self, val: u16) {
+        set_le16(&mut self.raw, 0x80, val)
+    }
+
+    /// Whether the inode has room for the high 16 bits of its checksum.
+    pub fn has_checksum_hi(&self) -> bool {
+        self.has_extra(0x84)
+    }
+
+    pub fn set_checksum(&mut self, val: u32) {
+        set_le16(&mut self.raw, 0x7c, val as u16);
+        if self.has_checksum_hi() {
+            set_le16(&mut self.raw, 0x82, (val >> 16) as u16);
+        }
+    }
+
+    /// Returns the access, modification, change and creation times.
+    pub fn times(&self) -> [Timestamp; 4] {
+        [(0x8, 0x8c), (0x10, 0x88), (0xc, 0x84), (0x90, 0x94)].map(|(base, extra)| {
+            if base >= GOOD_OLD_INODE_SIZE && !self.has_extra(base + 4) {
+                return Timestamp::default();
+            }
+            let secs = le32(&self.raw, base) as i32 as i64;
+            if self.has_extra(extra + 4) {
+                let extra = le32(&self.raw, extra);
+                Timestamp {
+                    secs: secs + (((extra & 0x3) as i64) << 32),
+                    nanos: extra >> 2,
+                }
+            } else {
+                Timestamp { secs, nanos: 0 }
+            }
+        })
+    }
+
+    fn set_time(&mut self, base: usize, extra: usize, ts: Timestamp) {
+        if base >= GOOD_OLD_INODE_SIZE && !self.has_extra(base + 4) {
+            return;
+        }
+        set_le32(&mut self.raw, base, ts.secs as u32);
+        if self.has_extra(extra + 4) {
+            let epoch = ((ts.secs - ts.secs as i32 as i64) >> 32) as u32 & 0x3;
+            set_le32(&mut self.raw, extra, ts.nanos << 2 | epoch);
+        }
+    }
+
+    pub fn set_atime(&mut self, ts: Timestamp) {
+        self.set_time(0x8, 0x8c, ts)
+    }
+
+    pub fn set_mtime(&mut self, ts: Timestamp) {
+        self.set_time(0x10, 0x88, ts)
+    }
+
+    pub fn set_ctime(&mut self, ts: Timestamp) {
+        self.set_time(0xc, 0x84, ts)
+    }
+
+    pub fn set_crtime(&mut self, ts: Timestamp) {
+        self.set_time(0x90, 0x94, ts)
+    }
+}
+
+/// A timestamp of an inode, in seconds and nanoseconds since the Unix epoch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timestamp {
+    pub secs: i64,
+    pub nanos: u32,
+}
+
+impl From<Duration> for Timestamp {
+    fn from(time: Duration) -> Self {
+        Self {
+            secs: time.as_secs() as i64,
+            nanos: time.subsec_nanos(),
+        }
+    }
+}
+
+impl From<Timestamp> for Duration {
+    /// Times before the epoch are clamped to it.
+    fn from(ts: Timestamp) -> Self {
+        if ts.secs < 0 {
+            Duration::ZERO
+        } else {
+            Duration::new(ts.secs as u64, ts.nanos)
+        }
+    }
+}
+
+pub fn mode_to_file_type(mode: u16) -> u8 {
+    match mode & S_IFMT {
+        S_IFREG => FT_REG_FILE,
+        S_IFDIR => FT_DIR,
+        S_IFCHR => FT_CHRDEV,
+        S_IFBLK => FT_BLKDEV,
+        S_IFIFO => FT_FIFO,
+        S_IFSOCK => FT_SOCK,
+        S_IFLNK => FT_SYMLINK,
+        _ => FT_UNKNOWN,
+    }
+}
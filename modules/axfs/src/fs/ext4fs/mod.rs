@@ -0,0 +1,680 @@
+//! A read-write [ext2/3/4] filesystem.
+//!
+//! Files are mapped by extents or by indirect blocks, directories may be
+//! indexed by hash trees, and metadata checksums are kept up to date. The
+//! journal is not used, so the filesystem must be cleanly unmounted before,
+//! and writes are not atomic on power loss. Filesystems with features we
+//! can't keep consistent (quota, bigalloc, ...) or whose journal needs
+//! recovery are mounted read-only.
+//!
+//! [ext2/3/4]: https://docs.kernel.org/filesystems/ext4/index.html
+
+mod crc;
+mod dir;
+mod hash;
+mod layout;
+mod volume;
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+use axerrno::{ax_err, AxResult};
+use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsOps, VfsResult};
+use axsync::Mutex;
+
+use self::layout::*;
+use self::volume::{is_fast_symlink, Volume};
+use crate::dev::Disk;
+
+pub use self::layout::Timestamp;
+
+/// Maximum number of symlinks followed in a lookup, as Linux does.
+const MAX_SYMLINK_FOLLOWS: usize = 40;
+/// Directories with more links than this have a link count of 1.
+const LINK_MAX: u16 = 65000;
+
+fn now() -> Timestamp {
+    axhal::time::wall_time().into()
+}
+
+pub struct Ext4FileSystem {
+    vol: Arc<Mutex<Volume>>,
+}
+
+impl Ext4FileSystem {
+    pub fn new(disk: Disk) -> Self {
+        let vol = Volume::load(disk).expect("failed to initialize ext4 filesystem");
+        info!(
+            "ext4: {} of {} blocks and {} of {} inodes free{}",
+            vol.free_blocks_count(),
+            vol.blocks_count(),
+            vol.free_inodes_count(),
+            vol.inodes_count(),
+            if vol.read_only { ", read-only" } else { "" }
+        );
+        Self {
+            vol: Arc::new(Mutex::new(vol)),
+        }
+    }
+}
+
+impl VfsOps for Ext4FileSystem {
+    fn umount(&self) -> VfsResult {
+        self.vol.lock().sync()
+    }
+
+    fn root_dir(&self) -> VfsNodeRef {
+        Arc::new(DirNode::new(self.vol.clone(), ROOT_INO))
+    }
+}
+
+/// A regular file, or a device, FIFO or socket whose data is not stored.
+pub struct FileNode {
+    vol: Arc<Mutex<Volume>>,
+    ino: u32,
+}
+
+/// A directory.
+pub struct DirNode {
+    vol: Arc<Mutex<Volume>>,
+    ino: u32,
+}
+
+/// A symbolic link, whose data is its target.
+pub struct SymlinkNode {
+    vol: Arc<Mutex<Volume>>,
+    ino: u32,
+}
+
+fn new_node(vol: &Arc<Mutex<Volume>>, ino: u32, inode: &Inode) -> VfsNodeRef {
+    let vol = vol.clone();
+    match inode.file_type() {
+        S_IFDIR => Arc::new(DirNode::new(vol, ino)),
+        S_IFLNK => Arc::new(SymlinkNode { vol, ino }),
+        _ => Arc::new(FileNode { vol, ino }),
+    }
+}
+
+fn node_type(inode: &Inode) -> VfsNodeType {
+    match inode.file_type() {
+        S_IFDIR => VfsNodeType::Dir,
+        S_IFLNK => VfsNodeType::SymLink,
+        S_IFCHR => VfsNodeType::CharDevice,
+        S_IFBLK => VfsNodeType::BlockDevice,
+        S_IFIFO => VfsNodeType::Fifo,
+        S_IFSOCK => VfsNodeType::Socket,
+        _ => VfsNodeType::File,
+    }
+}
+
+fn dir_entry_type(ft: u8) -> Option<VfsNodeType> {
+    Some(match ft {
+        FT_REG_FILE => VfsNodeType::File,
+        FT_DIR => VfsNodeType::Dir,
+        FT_CHRDEV => VfsNodeType::CharDevice,
+        FT_BLKDEV => VfsNodeType::BlockDevice,
+        FT_FIFO => VfsNodeType::Fifo,
+        FT_SOCK => VfsNodeType::Socket,
+        FT_SYMLINK => VfsNodeType::SymLink,
+        _ => return None,
+    })
+}
+
+/// Extra attributes of an inode that are not part of [`VfsNodeAttr`].
+#[derive(Debug, Clone, Copy)]
+pub struct InodeInfo {
+    pub ino: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub nlink: u16,
+    pub atime: Timestamp,
+    pub mtime: Timestamp,
+    pub ctime: Timestamp,
+    /// Creation time, zero if the inode is too small to record it.
+    pub crtime: Timestamp,
+}
+
+macro_rules! impl_inode_info {
+    ($($ty:ty),*) => {$(
+        impl $ty {
+            /// Returns the address of the volume and the inode number, which
+            /// identify the inode.
+            pub fn id(&self) -> (usize, u64) {
+                (Arc::as_ptr(&self.vol) as usize, self.ino as u64)
+            }
+
+            /// Returns the owner, link count and timestamps of the inode.
+            pub fn info(&self) -> VfsResult<InodeInfo> {
+                let inode = self.vol.lock().read_inode(self.ino)?;
+                let [atime, mtime, ctime, crtime] = inode.times();
+                Ok(InodeInfo {
+                    ino: self.ino,
+                    uid: inode.uid(),
+                    gid: inode.gid(),
+                    nlink: inode.links_count(),
+                    atime,
+                    mtime,
+                    ctime,
+                    crtime,
+                })
+            }
+
+            /// Sets the access and modification times of the inode, leaving
+            /// those of `None` unchanged, and the change time to now.
+            pub fn set_times(
+                &self,
+                atime: Option<Timestamp>,
+                mtime: Option<Timestamp>,
+            ) -> VfsResult {
+                let mut vol = self.vol.lock();
+                vol.check_writable()?;
+                let mut inode = read_live_inode(&mut vol, self.ino)?;
+                if let Some(atime) = atime {
+                    inode.set_atime(atime);
+                }
+                if let Some(mtime) = mtime {
+                    inode.set_mtime(mtime);
+                }
+                inode.set_ctime(now());
+                vol.write_inode(self.ino, &mut inode)
+            }
+        }
+    )*};
+}
+
+impl_inode_info!(FileNode, DirNode, SymlinkNode);
+
+fn get_attr(vol: &Mutex<Volume>, ino: u32) -> VfsResult<VfsNodeAttr> {
+    let mut vol = vol.lock();
+    let inode = vol.read_inode(ino)?;
+    let perm = VfsNodePerm::from_bits_truncate(inode.mode() & 0o777);
+    let blocks = if inode.flags() & INODE_FLAG_HUGE_FILE != 0 {
+        inode.blocks() * vol.block_size as u64 / 512
+    } else {
+        inode.blocks()
+    };
+    Ok(VfsNodeAttr::new(
+        perm,
+        node_type(&inode),
+        inode.size(),
+        blocks,
+    ))
+}
+
+/// Reads an inode that must still be linked, as a node of a removed file
+/// may outlive it.
+fn read_live_inode(vol: &mut Volume, ino: u32) -> AxResult<Inode> {
+    let inode = vol.read_inode(ino)?;
+    if inode.links_count() == 0 {
+        return ax_err!(NotFound, "ext4 inode removed");
+    }
+    Ok(inode)
+}
+
+fn read_link(vol: &mut Volume, inode: &Inode) -> AxResult<Vec<u8>> {
+    let size = inode.size() as usize;
+    if is_fast_symlink(inode) {
+        return Ok(inode.i_block()[..size].to_vec());
+    }
+    let mut target = alloc::vec![0; size];
+    let len = vol.read_data(inode, 0, &mut target)?;
+    target.truncate(len);
+    Ok(target)
+}
+
+/// Resolves `path` relative to the directory `dir`, following symlinks on
+/// the way, and the last component too if `follow` is true. Absolute targets
+/// of symlinks are resolved from the root of this filesystem.
+fn resolve(vol: &mut Volume, dir: u32, path: &str, follow: bool) -> AxResult<(u32, Inode)> {
+    let mut components: Vec<Vec<u8>> = path
+        .split('/')
+        .rev()
+        .map(|c| c.as_bytes().to_vec())
+        .collect();
+    let mut ino = dir;
+    let mut inode = read_live_inode(vol, ino)?;
+    let mut follows = 0;
+    while let Some(name) = components.pop() {
+        if name.is_empty() || name == b"." {
+            continue;
+        }
+        if !inode.is_dir() {
+            return ax_err!(NotADirectory);
+        }
+        if name == b".." && ino == ROOT_INO {
+            // like other filesystems here, `/..` doesn't exist
+            return ax_err!(NotFound);
+        }
+        let Some((child, _)) = vol.dir_lookup(&inode, &name)? else {
+            return ax_err!(NotFound);
+        };
+        let child_inode = vol.read_inode(child)?;
+        if child_inode.file_type() == S_IFLNK && (follow || !components.is_empty()) {
+            follows += 1;
+            if follows > MAX_SYMLINK_FOLLOWS {
+                return ax_err!(InvalidData, "too many levels of symbolic links");
+            }
+            let target = read_link(vol, &child_inode)?;
+            if target.first() == Some(&b'/') {
+                ino = ROOT_INO;
+                inode = vol.read_inode(ino)?;
+            }
+            components.extend(target.split(|&c| c == b'/').rev().map(<[u8]>::to_vec));
+            continue;
+        }
+        ino = child;
+        inode = child_inode;
+    }
+    Ok((ino, inode))
+}
+
+/// Resolves the parent directory of `path`, returning it and the last
+/// component of `path`.
+fn resolve_parent<'a>(
+    vol: &mut Volume,
+    dir: u32,
+    path: &'a str,
+) -> AxResult<(u32, Inode, &'a str)> {
+    let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+    let (ino, inode) = resolve(vol, dir, parent, true)?;
+    if !inode.is_dir() {
+        return ax_err!(NotADirectory);
+    }
+    Ok((ino, inode, name))
+}
+
+fn inc_dir_links(inode: &mut Inode) {
+    let links = inode.links_count();
+    if links != 1 {
+        inode.set_links_count(if links >= LINK_MAX { 1 } else { links + 1 });
+    }
+}
+
+fn dec_dir_links(inode: &mut Inode) {
+    let links = inode.links_count();
+    if links > 2 {
+        inode.set_links_count(links - 1);
+    }
+}
+
+fn touch(inode: &mut Inode) {
+    let now = now();
+    inode.set_mtime(now);
+    inode.set_ctime(now);
+}
+
+/// Drops a link to an inode whose directory entry has been removed, and
+/// frees it if there are no links left.
+fn unlink_inode(vol: &mut Volume, ino: u32, mut inode: Inode) -> AxResult {
+    let is_dir = inode.is_dir();
+    let links = if is_dir {
+        0
+    } else {
+        inode.links_count().saturating_sub(1)
+    };
+    inode.set_links_count(links);
+    inode.set_ctime(now());
+    if links == 0 {
+        vol.free_inode_blocks(ino, &mut inode)?;
+        inode.set_dtime(now().secs as u32);
+        vol.write_inode(ino, &mut inode)?;
+        vol.free_inode(ino, is_dir)
+    } else {
+        vol.write_inode(ino, &mut inode)
+    }
+}
+
+/// Creates an inode of `mode` linked as `name` in the directory `parent`.
+fn create_node(
+    vol: &mut Volume,
+    parent: u32,
+    parent_inode: &mut Inode,
+    name: &str,
+    mode: u16,
+    symlink: Option<&[u8]>,
+) -> AxResult<u32> {
+    let is_dir = mode & S_IFMT == S_IFDIR;
+    let ino = vol.alloc_inode(parent, is_dir)?;
+    let mut inode = vol.new_inode(mode);
+    inode.set_links_count(if is_dir { 2 } else { 1 });
+
+    let result = (|| {
+        if is_dir {
+            vol.dir_init(ino, &mut inode, parent)?;
+        } else if let Some(target) = symlink {
+            if target.len() <= FAST_SYMLINK_MAX {
+                inode.set_flags(inode.flags() & !INODE_FLAG_EXTENTS);
+                inode.i_block_mut().fill(0);
+                inode.i_block_mut()[..target.len()].copy_from_slice(target);
+                inode.set_size(target.len() as u64);
+            } else {
+                vol.write_data(ino, &mut inode, 0, target)?;
+            }
+        }
+        vol.write_inode(ino, &mut inode)?;
+        let ft = mode_to_file_type(mode);
+        vol.dir_add(parent, parent_inode, name.as_bytes(), ino, ft)
+    })();
+    if let Err(err) = result {
+        inode.set_links_count(0);
+        vol.free_inode_blocks(ino, &mut inode)?;
+        vol.write_inode(ino, &mut inode)?;
+        vol.free_inode(ino, is_dir)?;
+        return Err(err);
+    }
+
+    if is_dir {
+        inc_dir_links(parent_inode);
+    }
+    touch(parent_inode);
+    vol.write_inode(parent, parent_inode)?;
+    Ok(ino)
+}
+
+impl FileNode {
+    fn is_regular(&self, vol: &mut Volume) -> AxResult<Inode> {
+        let inode = read_live_inode(vol, self.ino)?;
+        if inode.file_type() != S_IFREG {
+            return ax_err!(Unsupported, "not a regular file");
+        }
+        Ok(inode)
+    }
+}
+
+impl VfsNodeOps for FileNode {
+    axfs_vfs::impl_vfs_non_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        get_attr(&self.vol, self.ino)
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let mut vol = self.vol.lock();
+        let inode = self.is_regular(&mut vol)?;
+        vol.read_data(&inode, offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let mut vol = self.vol.lock();
+        let mut inode = self.is_regular(&mut vol)?;
+        let result = vol.write_data(self.ino, &mut inode, offset, buf);
+        // blocks may have been allocated even if the write failed
+        vol.write_inode(self.ino, &mut inode)?;
+        vol.flush()?;
+        result
+    }
+
+    fn fsync(&self) -> VfsResult {
+        self.vol.lock().sync()
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        let mut vol = self.vol.lock();
+        let mut inode = self.is_regular(&mut vol)?;
+        vol.truncate(self.ino, &mut inode, size)?;
+        vol.write_inode(self.ino, &mut inode)?;
+        vol.flush()
+    }
+}
+
+impl SymlinkNode {
+    /// Returns the target of the symlink.
+    pub fn read_link(&self) -> VfsResult<Vec<u8>> {
+        let mut vol = self.vol.lock();
+        let inode = read_live_inode(&mut vol, self.ino)?;
+        read_link(&mut vol, &inode)
+    }
+}
+
+impl VfsNodeOps for SymlinkNode {
+    axfs_vfs::impl_vfs_non_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        get_attr(&self.vol, self.ino)
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let target = self.read_link()?;
+        let start = (offset as usize).min(target.len());
+        let len = buf.len().min(target.len() - start);
+        buf[..len].copy_from_slice(&target[start..start + len]);
+        Ok(len)
+    }
+}
+
+impl DirNode {
+    fn new(vol: Arc<Mutex<Volume>>, ino: u32) -> Self {
+        Self { vol, ino }
+    }
+
+    /// Creates a symlink at `path` pointing to `target`.
+    pub fn symlink(&self, path: &str, target: &str) -> VfsResult {
+        debug!("symlink at ext4: {} -> {}", path, target);
+        let path = path.trim_matches('/');
+        if path.is_empty() || target.is_empty() || target.len() >= 4096 {
+            return ax_err!(InvalidInput);
+        }
+        let mut vol = self.vol.lock();
+        vol.check_writable()?;
+        let (parent, mut parent_inode, name) = resolve_parent(&mut vol, self.ino, path)?;
+        if name == "." || name == ".." || vol.dir_lookup(&parent_inode, name.as_bytes())?.is_some()
+        {
+            return ax_err!(AlreadyExists);
+        }
+        let mode = S_IFLNK | 0o777;
+        create_node(
+            &mut vol,
+            parent,
+            &mut parent_inode,
+            name,
+            mode,
+            Some(target.as_bytes()),
+        )?;
+        vol.flush()
+    }
+
+    /// Creates a hard link at `new_path` to the file or symlink at
+    /// `old_path`.
+    pub fn link(&self, old_path: &str, new_path: &str) -> VfsResult {
+        debug!("link at ext4: {} -> {}", new_path, old_path);
+        let (old_path, new_path) = (old_path.trim_matches('/'), new_path.trim_matches('/'));
+        let mut vol = self.vol.lock();
+        vol.check_writable()?;
+        let (ino, mut inode) = resolve(&mut vol, self.ino, old_path, false)?;
+        if inode.is_dir() {
+            return ax_err!(PermissionDenied, "cannot link a directory");
+        }
+        if inode.links_count() >= LINK_MAX {
+            return ax_err!(InvalidInput, "too many links");
+        }
+        let (parent, mut parent_inode, name) = resolve_parent(&mut vol, self.ino, new_path)?;
+        if name.is_empty()
+            || name == "."
+            || name == ".."
+            || vol.dir_lookup(&parent_inode, name.as_bytes())?.is_some()
+        {
+            return ax_err!(AlreadyExists);
+        }
+        let ft = mode_to_file_type(inode.mode());
+        vol.dir_add(parent, &mut parent_inode, name.as_bytes(), ino, ft)?;
+        touch(&mut parent_inode);
+        vol.write_inode(parent, &mut parent_inode)?;
+        inode.set_links_count(inode.links_count() + 1);
+        inode.set_ctime(now());
+        vol.write_inode(ino, &mut inode)?;
+        vol.flush()
+    }
+}
+
+impl VfsNodeOps for DirNode {
+    axfs_vfs::impl_vfs_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        get_attr(&self.vol, self.ino)
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        if self.ino == ROOT_INO {
+            return None;
+        }
+        let mut vol = self.vol.lock();
+        let (ino, inode) = resolve(&mut vol, self.ino, "..", false).ok()?;
+        Some(new_node(&self.vol, ino, &inode))
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        debug!("lookup at ext4: {}", path);
+        let path = path.trim_matches('/');
+        if path.is_empty() || path == "." {
+            return Ok(self.clone());
+        }
+        let mut vol = self.vol.lock();
+        // a symlink at the last component is followed by the caller, as its
+        // target may be in another filesystem
+        let (ino, inode) = resolve(&mut vol, self.ino, path, false)?;
+        Ok(new_node(&self.vol, ino, &inode))
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        debug!("create {:?} at ext4: {}", ty, path);
+        let path = path.trim_matches('/');
+        if path.is_empty() || path == "." {
+            return Ok(());
+        }
+        let mut vol = self.vol.lock();
+        let (parent, mut parent_inode, name) = resolve_parent(&mut vol, self.ino, path)?;
+        if name == "." || name == ".." {
+            return Ok(());
+        }
+        if let Some((ino, _)) = vol.dir_lookup(&parent_inode, name.as_bytes())? {
+            // like FAT, creating an existing node of the same type succeeds
+            let inode = vol.read_inode(ino)?;
+            return if node_type(&inode) == ty {
+                Ok(())
+            } else {
+                ax_err!(AlreadyExists)
+            };
+        }
+        let mode = match ty {
+            VfsNodeType::File => S_IFREG | 0o644,
+            VfsNodeType::Dir => S_IFDIR | 0o755,
+            _ => return ax_err!(Unsupported),
+        };
+        vol.check_writable()?;
+        create_node(&mut vol, parent, &mut parent_inode, name, mode, None)?;
+        vol.flush()
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        debug!("remove at ext4: {}", path);
+        let path = path.trim_matches('/');
+        let mut vol = self.vol.lock();
+        vol.check_writable()?;
+        let (parent, mut parent_inode, name) = resolve_parent(&mut vol, self.ino, path)?;
+        if name.is_empty() || name == "." || name == ".." {
+            return ax_err!(InvalidInput);
+        }
+        let Some((ino, _)) = vol.dir_lookup(&parent_inode, name.as_bytes())? else {
+            return ax_err!(NotFound);
+        };
+        let inode = vol.read_inode(ino)?;
+        if inode.is_dir() {
+            if !vol.dir_is_empty(&inode)? {
+                return ax_err!(DirectoryNotEmpty);
+            }
+            dec_dir_links(&mut parent_inode);
+        }
+        vol.dir_remove(parent, &parent_inode, name.as_bytes())?;
+        touch(&mut parent_inode);
+        vol.write_inode(parent, &mut parent_inode)?;
+        unlink_inode(&mut vol, ino, inode)?;
+        vol.flush()
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let mut vol = self.vol.lock();
+        let inode = read_live_inode(&mut vol, self.ino)?;
+        let entries = vol.dir_entries(&inode)?;
+        let mut count = 0;
+        for (entry, out) in entries.iter().skip(start_idx).zip(dirents.iter_mut()) {
+            let ty = match dir_entry_type(entry.file_type) {
+                Some(ty) => ty,
+                None => node_type(&vol.read_inode(entry.ino)?),
+            };
+            *out = VfsDirEntry::new(&String::from_utf8_lossy(&entry.name), ty);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        debug!("rename at ext4: {} -> {}", src_path, dst_path);
+        let (src_path, dst_path) = (src_path.trim_matches('/'), dst_path.trim_matches('/'));
+        let mut vol = self.vol.lock();
+        vol.check_writable()?;
+        let (src_dir, mut src_inode, src_name) = resolve_parent(&mut vol, self.ino, src_path)?;
+        let (dst_dir, mut dst_inode, dst_name) = resolve_parent(&mut vol, self.ino, dst_path)?;
+        for name in [src_name, dst_name] {
+            if name.is_empty() || name == "." || name == ".." {
+                return ax_err!(InvalidInput);
+            }
+        }
+        let Some((ino, ft)) = vol.dir_lookup(&src_inode, src_name.as_bytes())? else {
+            return ax_err!(NotFound);
+        };
+        let mut inode = vol.read_inode(ino)?;
+        let is_dir = inode.is_dir();
+        if is_dir {
+            // a directory can't be moved into itself
+            let mut cur = dst_dir;
+            while cur != ROOT_INO {
+                if cur == ino {
+                    return ax_err!(InvalidInput, "cannot move a directory into itself");
+                }
+                cur = resolve(&mut vol, cur, "..", false)?.0;
+            }
+        }
+
+        let replaced = vol.dir_lookup(&dst_inode, dst_name.as_bytes())?;
+        if let Some((old, _)) = replaced {
+            if old == ino {
+                return Ok(());
+            }
+            let old_inode = vol.read_inode(old)?;
+            if old_inode.is_dir() {
+                if !is_dir {
+                    return ax_err!(IsADirectory);
+                }
+                if !vol.dir_is_empty(&old_inode)? {
+                    return ax_err!(DirectoryNotEmpty);
+                }
+                dec_dir_links(&mut dst_inode);
+            } else if is_dir {
+                return ax_err!(NotADirectory);
+            }
+            vol.dir_set(dst_dir, &dst_inode, dst_name.as_bytes(), ino, ft)?;
+            unlink_inode(&mut vol, old, old_inode)?;
+        } else {
+            vol.dir_add(dst_dir, &mut dst_inode, dst_name.as_bytes(), ino, ft)?;
+        }
+
+        if src_dir == dst_dir {
+            src_inode = dst_inode.clone();
+        }
+        vol.dir_remove(src_dir, &src_inode, src_name.as_bytes())?;
+        if is_dir && src_dir != dst_dir {
+            vol.dir_set(ino, &inode, b"..", dst_dir, FT_DIR)?;
+            dec_dir_links(&mut src_inode);
+            inc_dir_links(&mut dst_inode);
+        }
+        inode.set_ctime(now());
+        vol.write_inode(ino, &mut inode)?;
+        touch(&mut src_inode);
+        vol.write_inode(src_dir, &mut src_inode)?;
+        if src_dir != dst_dir {
+            touch(&mut dst_inode);
+            vol.write_inode(dst_dir, &mut dst_inode)?;
+        }
+        vol.flush()
+    }
+}
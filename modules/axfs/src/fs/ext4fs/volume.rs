@@ -0,0 +1,1414 @@
+//! Block and inode management of an ext2/3/4 volume: the superblock, group
+//! descriptors, allocation bitmaps, inode tables and the mapping of file
+//! blocks by extents or indirect blocks.
+
+use alloc::{vec, vec::Vec};
+
+use axerrno::{ax_err, ax_err_type, AxResult};
+
+use super::crc::{crc16, crc32c};
+use super::layout::*;
+use crate::dev::Disk;
+
+const EXTENT_MAGIC: u16 = 0xf30a;
+const EXTENT_HEADER_SIZE: usize = 12;
+const EXTENT_ENTRY_SIZE: usize = 12;
+/// Maximum length of an initialized extent, longer ones are uninitialized.
+const EXTENT_INIT_MAX_LEN: u32 = 1 << 15;
+const EXTENT_UNINIT_MAX_LEN: u32 = EXTENT_INIT_MAX_LEN - 1;
+/// Extents held by the root node in `i_block`.
+const EXTENT_ROOT_ENTRIES: usize = (N_BLOCKS * 4 - EXTENT_HEADER_SIZE) / EXTENT_ENTRY_SIZE;
+/// Maximum depth of an extent tree.
+const EXTENT_MAX_DEPTH: u16 = 5;
+
+/// Direct blocks in `i_block`, followed by the single, double and triple
+/// indirect blocks.
+const DIRECT_BLOCKS: usize = 12;
+
+/// How a logical block of a file is mapped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mapping {
+    /// `len` blocks are mapped contiguously starting at `pblk`. The data of
+    /// uninitialized blocks reads as zeros.
+    Mapped { pblk: u64, len: u32, init: bool },
+    /// `len` blocks are not allocated.
+    Hole { len: u32 },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Extent {
+    lblk: u32,
+    len: u32,
+    pblk: u64,
+    init: bool,
+}
+
+impl Extent {
+    fn end(&self) -> u32 {
+        self.lblk + self.len
+    }
+
+    fn max_len(&self) -> u32 {
+        if self.init {
+            EXTENT_INIT_MAX_LEN
+        } else {
+            EXTENT_UNINIT_MAX_LEN
+        }
+    }
+}
+
+/// Extents of a file, and the blocks of the tree that held them.
+struct ExtentList {
+    extents: Vec<Extent>,
+    nodes: Vec<u64>,
+}
+
+impl ExtentList {
+    fn map(&self, lblk: u32) -> Mapping {
+        let idx = self.extents.partition_point(|ext| ext.end() <= lblk);
+        match self.extents.get(idx) {
+            Some(ext) if ext.lblk <= lblk => Mapping::Mapped {
+                pblk: ext.pblk + (lblk - ext.lblk) as u64,
+                len: ext.end() - lblk,
+                init: ext.init,
+            },
+            Some(ext) => Mapping::Hole {
+                len: ext.lblk - lblk,
+            },
+            None => Mapping::Hole {
+                len: u32::MAX - lblk,
+            },
+        }
+    }
+
+    /// Maps `new.lblk..new.end()` to `new`, splitting the extents it
+    /// overlaps and merging it with its neighbors.
+    fn set(&mut self, new: Extent) {
+        let mut extents = Vec::with_capacity(self.extents.len() + 2);
+        for &ext in &self.extents {
+            if ext.end() <= new.lblk || ext.lblk >= new.end() {
+                extents.push(ext);
+                continue;
+            }
+            if ext.lblk < new.lblk {
+                extents.push(Extent {
+                    len: new.lblk - ext.lblk,
+                    ..ext
+                });
+            }
+            if ext.end() > new.end() {
+                let skip = new.end() - ext.lblk;
+                extents.push(Extent {
+                    lblk: new.end(),
+                    len: ext.len - skip,
+                    pblk: ext.pblk + skip as u64,
+                    init: ext.init,
+                });
+            }
+        }
+        let idx = extents.partition_point(|ext| ext.lblk < new.lblk);
+        extents.insert(idx, new);
+
+        let mut merged: Vec<Extent> = Vec::with_capacity(extents.len());
+        for ext in extents {
+            if let Some(last) = merged.last_mut() {
+                if last.end() == ext.lblk
+                    && last.pblk + last.len as u64 == ext.pblk
+                    && last.init == ext.init
+                    && last.len + ext.len <= last.max_len()
+                {
+                    last.len += ext.len;
+                    continue;
+                }
+            }
+            merged.push(ext);
+        }
+        self.extents = merged;
+    }
+}
+
+/// An ext2/3/4 volume on a disk.
+pub struct Volume {
+    disk: Disk,
+    sb: Superblock,
+    sb_dirty: bool,
+    pub block_size: usize,
+    groups: u32,
+    blocks_per_group: u32,
+    inodes_per_group: u32,
+    inode_size: usize,
+    desc_size: usize,
+    first_data_block: u32,
+    /// All group descriptors.
+    gdt: Vec<u8>,
+    /// Seed of the metadata checksums, if `metadata_csum` is enabled.
+    csum_seed: Option<u32>,
+    gdt_csum: bool,
+    /// Whether the filesystem has features we can read but not write.
+    pub read_only: bool,
+    /// Whether new files use extents rather than indirect blocks.
+    extents: bool,
+    /// Whether directory entries record the file type.
+    pub filetype: bool,
+    pub hash_seed: [u32; 4],
+    pub unsigned_hash: bool,
+    /// Group to start looking for free blocks from.
+    alloc_group: u32,
+}
+
+impl Volume {
+    /// Loads the volume from its superblock and group descriptors.
+    pub fn load(mut disk: Disk) -> AxResult<Self> {
+        let mut raw = vec![0; SUPERBLOCK_SIZE];
+        disk_read(&mut disk, SUPERBLOCK_OFFSET, &mut raw)?;
+        let sb = Superblock::new(raw);
+        if sb.magic() != EXT4_MAGIC {
+            return ax_err!(InvalidData, "not an ext2/3/4 filesystem");
+        }
+        if sb.log_block_size() > 6 {
+            return ax_err!(InvalidData, "invalid block size");
+        }
+        let incompat = sb.feature_incompat();
+        if incompat & !INCOMPAT_SUPPORTED != 0 {
+            warn!(
+                "ext4: unsupported incompatible features {:#x}",
+                incompat & !INCOMPAT_SUPPORTED
+            );
+            return ax_err!(Unsupported, "unsupported ext4 features");
+        }
+
+        let mut read_only = false;
+        let ro_compat = sb.feature_ro_compat();
+        if ro_compat & !RO_COMPAT_SUPPORTED != 0 {
+            warn!(
+                "ext4: unsupported read-only features {:#x}, mounting read-only",
+                ro_compat & !RO_COMPAT_SUPPORTED
+            );
+            read_only = true;
+        }
+        if incompat & INCOMPAT_RECOVER != 0 {
+            warn!("ext4: the journal needs recovery, mounting read-only");
+            read_only = true;
+        }
+
+        let block_size = 1024 << sb.log_block_size();
+        let blocks_per_group = sb.blocks_per_group();
+        let inodes_per_group = sb.inodes_per_group();
+        let first_data_block = sb.first_data_block();
+        let desc_size = sb.desc_size();
+        if blocks_per_group == 0
+            || blocks_per_group as usize > block_size * 8
+            || inodes_per_group == 0
+            || inodes_per_group as usize > block_size * 8
+            || desc_size < 32
+            || !desc_size.is_power_of_two()
+            || sb.inode_size() < GOOD_OLD_INODE_SIZE
+            || sb.inode_size() > block_size
+            || sb.blocks_count() * block_size as u64 > disk.size()
+        {
+            return ax_err!(InvalidData, "invalid ext4 superblock");
+        }
+        let groups =
+            (sb.blocks_count() - first_data_block as u64).div_ceil(blocks_per_group as u64);
+
+        let mut gdt = vec![0; groups as usize * desc_size];
+        let gdt_pos = (first_data_block as u64 + 1) * block_size as u64;
+        disk_read(&mut disk, gdt_pos, &mut gdt)?;
+
+        let csum_seed = if ro_compat & RO_COMPAT_METADATA_CSUM != 0 {
+            Some(if incompat & INCOMPAT_CSUM_SEED != 0 {
+                sb.checksum_seed()
+            } else {
+                crc32c(!0, sb.uuid())
+            })
+        } else {
+            None
+        };
+
+        let mut hash_version = sb.def_hash_version();
+        let unsigned_hash = sb.unsigned_hash();
+        if unsigned_hash && hash_version <= 2 {
+            hash_version += 3;
+        }
+        debug!(
+            "ext4: {} blocks of {} bytes, {} groups, hash version {}",
+            sb.blocks_count(),
+            block_size,
+            groups,
+            hash_version
+        );
+
+        Ok(Self {
+            disk,
+            block_size,
+            groups: groups as u32,
+            blocks_per_group,
+            inodes_per_group,
+            inode_size: sb.inode_size(),
+            desc_size,
+            first_data_block,
+            gdt,
+            csum_seed,
+            gdt_csum: ro_compat & RO_COMPAT_GDT_CSUM != 0,
+            read_only,
+            extents: incompat & INCOMPAT_EXTENTS != 0,
+            filetype: incompat & INCOMPAT_FILETYPE != 0,
+            hash_seed: sb.hash_seed(),
+            unsigned_hash,
+            alloc_group: 0,
+            sb,
+            sb_dirty: false,
+        })
+    }
+
+    pub fn blocks_count(&self) -> u64 {
+        self.sb.blocks_count()
+    }
+
+    pub fn free_blocks_count(&self) -> u64 {
+        self.sb.free_blocks_count()
+    }
+
+    pub fn inodes_count(&self) -> u32 {
+        self.sb.inodes_count()
+    }
+
+    pub fn free_inodes_count(&self) -> u32 {
+        self.sb.free_inodes_count()
+    }
+
+    pub fn has_metadata_csum(&self) -> bool {
+        self.csum_seed.is_some()
+    }
+
+    pub fn check_writable(&self) -> AxResult {
+        if self.read_only {
+            return ax_err!(PermissionDenied, "ext4 filesystem is read-only");
+        }
+        Ok(())
+    }
+
+    /// Writes the superblock back if the free counts have changed.
+    pub fn flush(&mut self) -> AxResult {
+        if !self.sb_dirty {
+            return Ok(());
+        }
+        self.sb.set_wtime(super::now().secs as u32);
+        if self.csum_seed.is_some() {
+            let csum = crc32c(!0, &self.sb.raw[..0x3fc]);
+            self.sb.set_checksum(csum);
+        }
+        disk_write(&mut self.disk, SUPERBLOCK_OFFSET, &self.sb.raw)?;
+        self.sb_dirty = false;
+        Ok(())
+    }
+
+    /// Writes the superblock and all cached blocks back to the device.
+    pub fn sync(&mut self) -> AxResult {
+        self.flush()?;
+        self.disk.flush().map_err(|_| ax_err_type!(Io))
+    }
+
+    // ---------------------------------------------------------------------
+    // Blocks
+
+    fn check_block(&self, block: u64) -> AxResult {
+        if block < self.first_data_block as u64 || block >= self.sb.blocks_count() {
+            warn!("ext4: block {} out of range", block);
+            return ax_err!(InvalidData, "corrupted ext4 filesystem");
+        }
+        Ok(())
+    }
+
+    /// Reads `buf.len()` bytes from `offset` of a block.
+    pub fn read_block_at(&mut self, block: u64, offset: usize, buf: &mut [u8]) -> AxResult {
+        self.check_block(block)?;
+        let pos = block * self.block_size as u64 + offset as u64;
+        disk_read(&mut self.disk, pos, buf)
+    }
+
+    pub fn read_block(&mut self, block: u64, buf: &mut [u8]) -> AxResult {
+        self.read_block_at(block, 0, buf)
+    }
+
+    /// Writes `buf` to `offset` of a block.
+    pub fn write_block_at(&mut self, block: u64, offset: usize, buf: &[u8]) -> AxResult {
+        self.check_block(block)?;
+        let pos = block * self.block_size as u64 + offset as u64;
+        disk_write(&mut self.disk, pos, buf)
+    }
+
+    pub fn write_block(&mut self, block: u64, buf: &[u8]) -> AxResult {
+        self.write_block_at(block, 0, buf)
+    }
+
+    // ---------------------------------------------------------------------
+    // Group descriptors
+
+    fn gd(&mut self, group: u32) -> GroupDesc<'_> {
+        let off = group as usize * self.desc_size;
+        GroupDesc::new(&mut self.gdt[off..off + self.desc_size])
+    }
+
+    fn gd_checksum(&self, group: u32) -> u16 {
+        let off = group as usize * self.desc_size;
+        let raw = &self.gdt[off..off + self.desc_size];
+        if let Some(seed) = self.csum_seed {
+            let mut csum = crc32c(seed, &group.to_le_bytes());
+            csum = crc32c(csum, &raw[..0x1e]);
+            csum = crc32c(csum, &[0; 2]);
+            csum = crc32c(csum, &raw[0x20..]);
+            csum as u16
+        } else if self.gdt_csum {
+            let mut csum = crc16(!0, self.sb.uuid());
+            csum = crc16(csum, &group.to_le_bytes());
+            csum = crc16(csum, &raw[..0x1e]);
+            crc16(csum, &raw[0x20..])
+        } else {
+            0
+        }
+    }
+
+    fn write_gd(&mut self, group: u32) -> AxResult {
+        let csum = self.gd_checksum(group);
+        self.gd(group).set_checksum(csum);
+        let off = group as usize * self.desc_size;
+        let pos = (self.first_data_block as u64 + 1) * self.block_size as u64 + off as u64;
+        disk_write(&mut self.disk, pos, &self.gdt[off..off + self.desc_size])
+    }
+
+    fn group_first_block(&self, group: u32) -> u64 {
+        self.first_data_block as u64 + group as u64 * self.blocks_per_group as u64
+    }
+
+    fn blocks_in_group(&self, group: u32) -> u32 {
+        let start = self.group_first_block(group);
+        (self.sb.blocks_count() - start).min(self.blocks_per_group as u64) as u32
+    }
+
+    fn group_of_block(&self, block: u64) -> u32 {
+        ((block - self.first_data_block as u64) / self.blocks_per_group as u64) as u32
+    }
+
+    /// Whether the group has a backup of the superblock and descriptors.
+    fn group_has_super(&self, group: u32) -> bool {
+        fn is_power_of(mut n: u32, base: u32) -> bool {
+            while n > 1 && n.is_multiple_of(base) {
+                n /= base;
+            }
+            n == 1
+        }
+        self.sb.feature_ro_compat() & RO_COMPAT_SPARSE_SUPER == 0
+            || group <= 1
+            || is_power_of(group, 3)
+            || is_power_of(group, 5)
+            || is_power_of(group, 7)
+    }
+
+    fn bitmap_csum(&self, bitmap: &[u8], bits: u32) -> u32 {
+        self.csum_seed
+            .map_or(0, |seed| crc32c(seed, &bitmap[..bits as usize / 8]))
+    }
+
+    // ---------------------------------------------------------------------
+    // Block allocation
+
+    fn read_block_bitmap(&mut self, group: u32) -> AxResult<Vec<u8>> {
+        let mut bitmap = vec![0; self.block_size];
+        if self.gd(group).flags() & BG_BLOCK_UNINIT == 0 {
+            let block = self.gd(group).block_bitmap();
+            self.read_block(block, &mut bitmap)?;
+            return Ok(bitmap);
+        }
+
+        // Not initialized yet, only the metadata in the group is in use.
+        let start = self.group_first_block(group);
+        let count = self.blocks_in_group(group);
+        let mut mark = |block: u64, len: u64| {
+            for b in block..block + len {
+                if b >= start && b < start + count as u64 {
+                    set_bit(&mut bitmap, (b - start) as usize);
+                }
+            }
+        };
+        if self.group_has_super(group) {
+            let gdt_blocks = (self.gdt.len() as u64).div_ceil(self.block_size as u64);
+            mark(start, 1 + gdt_blocks + self.sb.reserved_gdt_blocks() as u64);
+        }
+        let itable_blocks = (self.inodes_per_group as u64 * self.inode_size as u64)
+            .div_ceil(self.block_size as u64);
+        for g in 0..self.groups {
+            let gd = self.gd(g);
+            let (bb, ib, it) = (gd.block_bitmap(), gd.inode_bitmap(), gd.inode_table());
+            mark(bb, 1);
+            mark(ib, 1);
+            mark(it, itable_blocks);
+        }
+        for bit in count as usize..self.block_size * 8 {
+            set_bit(&mut bitmap, bit);
+        }
+        Ok(bitmap)
+    }
+
+    fn write_block_bitmap(&mut self, group: u32, bitmap: &[u8]) -> AxResult {
+        let block = self.gd(group).block_bitmap();
+        self.write_block(block, bitmap)?;
+        let csum = self.bitmap_csum(bitmap, self.blocks_per_group);
+        let mut gd = self.gd(group);
+        gd.set_block_bitmap_csum(csum);
+        let flags = gd.flags();
+        gd.set_flags(flags & !BG_BLOCK_UNINIT);
+        Ok(())
+    }
+
+    /// Allocates up to `max` contiguous blocks, near `goal` if possible.
+    /// Returns the first block and the number of blocks allocated.
+    pub fn alloc_blocks(&mut self, goal: u64, max: u32) -> AxResult<(u64, u32)> {
+        self.check_writable()?;
+        let goal_group = if goal >= self.first_data_block as u64 && goal < self.sb.blocks_count() {
+            self.group_of_block(goal)
+        } else {
+            self.alloc_group
+        };
+        for i in 0..self.groups {
+            let group = (goal_group + i) % self.groups;
+            if self.gd(group).free_blocks_count() == 0 {
+                continue;
+            }
+            let mut bitmap = self.read_block_bitmap(group)?;
+            let count = self.blocks_in_group(group) as usize;
+            let start = if group == goal_group {
+                (goal - self.group_first_block(group).min(goal)) as usize
+            } else {
+                0
+            };
+            let Some(first) =
+                find_zero(&bitmap, start, count).or_else(|| find_zero(&bitmap, 0, count))
+            else {
+                continue;
+            };
+            let mut len = 0;
+            while len < max as usize && first + len < count && !test_bit(&bitmap, first + len) {
+                set_bit(&mut bitmap, first + len);
+                len += 1;
+            }
+
+            self.write_block_bitmap(group, &bitmap)?;
+            let mut gd = self.gd(group);
+            let free = gd.free_blocks_count();
+            gd.set_free_blocks_count(free.saturating_sub(len as u32));
+            self.write_gd(group)?;
+            let free = self.sb.free_blocks_count();
+            self.sb
+                .set_free_blocks_count(free.saturating_sub(len as u64));
+            self.sb_dirty = true;
+            self.alloc_group = group;
+            return Ok((self.group_first_block(group) + first as u64, len as u32));
+        }
+        ax_err!(StorageFull, "no free blocks in ext4 filesystem")
+    }
+
+    /// Allocates a block for metadata, and fills it with zeros.
+    fn alloc_zeroed_block(&mut self, goal: u64) -> AxResult<u64> {
+        let (block, _) = self.alloc_blocks(goal, 1)?;
+        self.write_block(block, &vec![0; self.block_size])?;
+        Ok(block)
+    }
+
+    /// Frees `count` blocks starting at `block`.
+    pub fn free_blocks(&mut self, mut block: u64, mut count: u64) -> AxResult {
+        while count > 0 {
+            self.check_block(block)?;
+            let group = self.group_of_block(block);
+            let first = (block - self.group_first_block(group)) as usize;
+            let len = count.min((self.blocks_in_group(group) as usize - first) as u64) as usize;
+            let mut bitmap = self.read_block_bitmap(group)?;
+            let mut freed = 0;
+            for bit in first..first + len {
+                if test_bit(&bitmap, bit) {
+                    clear_bit(&mut bitmap, bit);
+                    freed += 1;
+                } else {
+                    warn!(
+                        "ext4: freeing free block {}",
+                        self.group_first_block(group) + bit as u64
+                    );
+                }
+            }
+            self.write_block_bitmap(group, &bitmap)?;
+            let mut gd = self.gd(group);
+            let free = gd.free_blocks_count();
+            gd.set_free_blocks_count(free + freed);
+            self.write_gd(group)?;
+            let free = self.sb.free_blocks_count();
+            self.sb.set_free_blocks_count(free + freed as u64);
+            self.sb_dirty = true;
+            block += len as u64;
+            count -= len as u64;
+        }
+        Ok(())
+    }
+
+    // ---------------------------------------------------------------------
+    // Inodes
+
+    pub fn group_of_inode(&self, ino: u32) -> u32 {
+        (ino - 1) / self.inodes_per_group
+    }
+
+    fn inode_pos(&mut self, ino: u32) -> AxResult<u64> {
+        if ino == 0 || ino > self.sb.inodes_count() {
+            warn!("ext4: inode {} out of range", ino);
+            return ax_err!(InvalidData, "corrupted ext4 filesystem");
+        }
+        let group = self.group_of_inode(ino);
+        let index = (ino - 1) % self.inodes_per_group;
+        let table = self.gd(group).inode_table();
+        Ok(table * self.block_size as u64 + index as u64 * self.inode_size as u64)
+    }
+
+    pub fn read_inode(&mut self, ino: u32) -> AxResult<Inode> {
+        let pos = self.inode_pos(ino)?;
+        let mut inode = Inode::new(self.inode_size);
+        disk_read(&mut self.disk, pos, &mut inode.raw)?;
+        Ok(inode)
+    }
+
+    pub fn write_inode(&mut self, ino: u32, inode: &mut Inode) -> AxResult {
+        if self.csum_seed.is_some() {
+            let csum = self.inode_checksum(ino, inode);
+            inode.set_checksum(csum);
+        }
+        let pos = self.inode_pos(ino)?;
+        disk_write(&mut self.disk, pos, &inode.raw)
+    }
+
+    /// Returns the seed of the checksums of the inode and its blocks.
+    pub fn inode_csum_seed(&self, ino: u32, inode: &Inode) -> u32 {
+        let seed = self.csum_seed.unwrap_or(0);
+        let csum = crc32c(seed, &ino.to_le_bytes());
+        crc32c(csum, &inode.generation().to_le_bytes())
+    }
+
+    fn inode_checksum(&self, ino: u32, inode: &Inode) -> u32 {
+        let raw = &inode.raw;
+        let mut csum = self.inode_csum_seed(ino, inode);
+        csum = crc32c(csum, &raw[..0x7c]);
+        csum = crc32c(csum, &[0; 2]);
+        csum = crc32c(csum, &raw[0x7e..GOOD_OLD_INODE_SIZE]);
+        if raw.len() > GOOD_OLD_INODE_SIZE {
+            csum = crc32c(csum, &raw[GOOD_OLD_INODE_SIZE..0x82]);
+            if inode.has_checksum_hi() {
+                csum = crc32c(csum, &[0; 2]);
+                csum = crc32c(csum, &raw[0x84..]);
+            } else {
+                csum = crc32c(csum, &raw[0x82..]);
+            }
+        }
+        csum
+    }
+
+    /// Allocates an inode, preferring the group of the parent directory.
+    pub fn alloc_inode(&mut self, parent: u32, is_dir: bool) -> AxResult<u32> {
+        self.check_writable()?;
+        let start_group = self.group_of_inode(parent);
+        let first_ino = self.sb.first_ino();
+        let ipg = self.inodes_per_group;
+        for i in 0..self.groups {
+            let group = (start_group + i) % self.groups;
+            if self.gd(group).free_inodes_count() == 0 {
+                continue;
+            }
+            let block = self.gd(group).inode_bitmap();
+            let mut bitmap = vec![0; self.block_size];
+            if self.gd(group).flags() & BG_INODE_UNINIT != 0 {
+                for bit in ipg as usize..self.block_size * 8 {
+                    set_bit(&mut bitmap, bit);
+                }
+            } else {
+                self.read_block(block, &mut bitmap)?;
+            }
+            let min = if group == 0 {
+                first_ino as usize - 1
+            } else {
+                0
+            };
+            let Some(index) = find_zero(&bitmap, min, ipg as usize) else {
+                continue;
+            };
+            set_bit(&mut bitmap, index);
+            self.write_block(block, &bitmap)?;
+
+            let csum = self.bitmap_csum(&bitmap, ipg);
+            let gdt_csum = self.gdt_csum || self.csum_seed.is_some();
+            let mut gd = self.gd(group);
+            gd.set_inode_bitmap_csum(csum);
+            let flags = gd.flags();
+            gd.set_flags(flags & !BG_INODE_UNINIT);
+            let free = gd.free_inodes_count();
+            gd.set_free_inodes_count(free - 1);
+            if is_dir {
+                let dirs = gd.used_dirs_count();
+                gd.set_used_dirs_count(dirs + 1);
+            }
+            if gdt_csum {
+                // inodes after the unused part of the table must be zeroed
+                let unused = gd.itable_unused();
+                if index as u32 >= ipg - unused {
+                    gd.set_itable_unused(ipg - index as u32 - 1);
+                }
+            }
+            self.write_gd(group)?;
+            let free = self.sb.free_inodes_count();
+            self.sb.set_free_inodes_count(free - 1);
+            self.sb_dirty = true;
+            return Ok(group * ipg + index as u32 + 1);
+        }
+        ax_err!(StorageFull, "no free inodes in ext4 filesystem")
+    }
+
+    pub fn free_inode(&mut self, ino: u32, is_dir: bool) -> AxResult {
+        let group = self.group_of_inode(ino);
+        let index = ((ino - 1) % self.inodes_per_group) as usize;
+        let block = self.gd(group).inode_bitmap();
+        let mut bitmap = vec![0; self.block_size];
+        self.read_block(block, &mut bitmap)?;
+        if !test_bit(&bitmap, index) {
+            warn!("ext4: freeing free inode {}", ino);
+            return Ok(());
+        }
+        clear_bit(&mut bitmap, index);
+        self.write_block(block, &bitmap)?;
+
+        let csum = self.bitmap_csum(&bitmap, self.inodes_per_group);
+        let mut gd = self.gd(group);
+        gd.set_inode_bitmap_csum(csum);
+        let free = gd.free_inodes_count();
+        gd.set_free_inodes_count(free + 1);
+        if is_dir {
+            let dirs = gd.used_dirs_count();
+            gd.set_used_dirs_count(dirs.saturating_sub(1));
+        }
+        self.write_gd(group)?;
+        let free = self.sb.free_inodes_count();
+        self.sb.set_free_inodes_count(free + 1);
+        self.sb_dirty = true;
+        Ok(())
+    }
+
+    /// Returns a fresh inode of `mode`, with an empty block map.
+    pub fn new_inode(&self, mode: u16) -> Inode {
+        let mut inode = Inode::new(self.inode_size);
+        inode.set_mode(mode);
+        if self.inode_size > GOOD_OLD_INODE_SIZE {
+            inode.set_extra_isize(32.min(self.inode_size - GOOD_OLD_INODE_SIZE) as u16);
+        }
+        let now = super::now();
+        inode.set_atime(now);
+        inode.set_ctime(now);
+        inode.set_mtime(now);
+        inode.set_crtime(now);
+        if self.extents {
+            inode.set_flags(INODE_FLAG_EXTENTS);
+            write_extent_header(inode.i_block_mut(), 0, EXTENT_ROOT_ENTRIES, 0);
+        }
+        inode
+    }
+
+    /// Adds `count` (possibly negative) filesystem blocks to `i_blocks`.
+    fn add_blocks(&self, inode: &mut Inode, count: i64) {
+        let unit = if inode.flags() & INODE_FLAG_HUGE_FILE != 0 {
+            1
+        } else {
+            self.block_size as i64 / 512
+        };
+        inode.set_blocks((inode.blocks() as i64 + count * unit).max(0) as u64);
+    }
+
+    // ---------------------------------------------------------------------
+    // Block mapping
+
+    /// Returns how the logical block `lblk` of the file is mapped.
+    pub fn map(&mut self, inode: &Inode, lblk: u32) -> AxResult<Mapping> {
+        if inode.flags() & INODE_FLAG_EXTENTS != 0 {
+            self.extent_map(inode, lblk)
+        } else {
+            let pblk = self.indirect_map(inode, lblk)?;
+            Ok(if pblk == 0 {
+                Mapping::Hole { len: 1 }
+            } else {
+                Mapping::Mapped {
+                    pblk,
+                    len: 1,
+                    init: true,
+                }
+            })
+        }
+    }
+
+    fn extent_map(&mut self, inode: &Inode, lblk: u32) -> AxResult<Mapping> {
+        let mut node = inode.i_block().to_vec();
+        let mut upper = u32::MAX;
+        loop {
+            let (entries, depth) = parse_extent_header(&node)?;
+            if depth == 0 {
+                for i in 0..entries {
+                    let ext = parse_extent(&node, i);
+                    if lblk < ext.lblk {
+                        return Ok(Mapping::Hole {
+                            len: ext.lblk.min(upper) - lblk,
+                        });
+                    }
+                    if lblk < ext.end() {
+                        return Ok(Mapping::Mapped {
+                            pblk: ext.pblk + (lblk - ext.lblk) as u64,
+                            len: ext.end() - lblk,
+                            init: ext.init,
+                        });
+                    }
+                }
+                return Ok(Mapping::Hole {
+                    len: upper.saturating_sub(lblk).max(1),
+                });
+            }
+            let idx = (1..entries)
+                .find(|&i| parse_index(&node, i).0 > lblk)
+                .unwrap_or(entries);
+            if idx == 0 {
+                return Ok(Mapping::Hole { len: 1 });
+            }
+            if idx < entries {
+                upper = upper.min(parse_index(&node, idx).0);
+            }
+            let (first, child) = parse_index(&node, idx - 1);
+            if lblk < first {
+                return Ok(Mapping::Hole { len: first - lblk });
+            }
+            node = vec![0; self.block_size];
+            self.read_block(child, &mut node)?;
+        }
+    }
+
+    fn load_extents(&mut self, inode: &Inode) -> AxResult<ExtentList> {
+        let mut list = ExtentList {
+            extents: Vec::new(),
+            nodes: Vec::new(),
+        };
+        self.load_extent_node(inode.i_block(), &mut list, EXTENT_MAX_DEPTH)?;
+        list.extents.sort_by_key(|ext| ext.lblk);
+        Ok(list)
+    }
+
+    fn load_extent_node(&mut self, node: &[u8], list: &mut ExtentList, max_depth: u16) -> AxResult {
+        let (entries, depth) = parse_extent_header(node)?;
+        if depth > max_depth {
+            return ax_err!(InvalidData, "corrupted ext4 extent tree");
+        }
+        for i in 0..entries {
+            if depth == 0 {
+                list.extents.push(parse_extent(node, i));
+            } else {
+                let child = parse_index(node, i).1;
+                let mut buf = vec![0; self.block_size];
+                self.read_block(child, &mut buf)?;
+                list.nodes.push(child);
+                self.load_extent_node(&buf, list, depth - 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the extents back as a tree, reusing the blocks of the old tree.
+    fn store_extents(&mut self, ino: u32, inode: &mut Inode, list: ExtentList) -> AxResult {
+        let ExtentList {
+            extents,
+            nodes: mut free_nodes,
+        } = list;
+        let cap = (self.block_size - EXTENT_HEADER_SIZE) / EXTENT_ENTRY_SIZE;
+        let seed = self.inode_csum_seed(ino, inode);
+        let goal = extents.first().map_or(0, |ext| ext.pblk);
+
+        // Entries of the current level, as (first logical block, raw entry).
+        let mut level: Vec<(u32, [u8; EXTENT_ENTRY_SIZE])> = extents
+            .iter()
+            .map(|ext| (ext.lblk, encode_extent(ext)))
+            .collect();
+        let mut depth = 0;
+        while level.len() > EXTENT_ROOT_ENTRIES {
+            let mut upper = Vec::new();
+            for chunk in level.chunks(cap) {
+                let block = match free_nodes.pop() {
+                    Some(block) => block,
+                    None => {
+                        let block = self.alloc_zeroed_block(goal)?;
+                        self.add_blocks(inode, 1);
+                        block
+                    }
+                };
+                let mut buf = vec![0; self.block_size];
+                write_extent_header(&mut buf, chunk.len(), cap, depth);
+                for (i, (_, entry)) in chunk.iter().enumerate() {
+                    let off = EXTENT_HEADER_SIZE + i * EXTENT_ENTRY_SIZE;
+                    buf[off..off + EXTENT_ENTRY_SIZE].copy_from_slice(entry);
+                }
+                if self.csum_seed.is_some() {
+                    let tail = EXTENT_HEADER_SIZE + cap * EXTENT_ENTRY_SIZE;
+                    let csum = crc32c(seed, &buf[..tail]);
+                    set_le32(&mut buf, tail, csum);
+                }
+                self.write_block(block, &buf)?;
+                upper.push((chunk[0].0, encode_index(chunk[0].0, block)));
+            }
+            level = upper;
+            depth += 1;
+        }
+
+        let root = inode.i_block_mut();
+        root.fill(0);
+        write_extent_header(root, level.len(), EXTENT_ROOT_ENTRIES, depth);
+        for (i, (_, entry)) in level.iter().enumerate() {
+            let off = EXTENT_HEADER_SIZE + i * EXTENT_ENTRY_SIZE;
+            root[off..off + EXTENT_ENTRY_SIZE].copy_from_slice(entry);
+        }
+        for block in free_nodes {
+            self.free_blocks(block, 1)?;
+            self.add_blocks(inode, -1);
+        }
+        Ok(())
+    }
+
+    fn indirect_map(&mut self, inode: &Inode, lblk: u32) -> AxResult<u64> {
+        let Some((root, path)) = self.indirect_path(lblk) else {
+            return Ok(0);
+        };
+        let mut block = le32(inode.i_block(), root * 4) as u64;
+        for idx in path.into_iter().flatten() {
+            if block == 0 {
+                break;
+            }
+            let mut entry = [0; 4];
+            self.read_block_at(block, idx * 4, &mut entry)?;
+            block = u32::from_le_bytes(entry) as u64;
+        }
+        Ok(block)
+    }
+
+    /// Returns the index in `i_block` and in each level of indirect blocks
+    /// that map `lblk`.
+    fn indirect_path(&self, lblk: u32) -> Option<(usize, [Option<usize>; 3])> {
+        let per = (self.block_size / 4) as u64;
+        let mut lblk = lblk as u64;
+        if lblk < DIRECT_BLOCKS as u64 {
+            return Some((lblk as usize, [None; 3]));
+        }
+        lblk -= DIRECT_BLOCKS as u64;
+        let mut span = per;
+        for level in 1..=3 {
+            if lblk < span {
+                let mut path = [None; 3];
+                for (i, slot) in path.iter_mut().take(level).enumerate() {
+                    let unit = per.pow((level - 1 - i) as u32);
+                    *slot = Some((lblk / unit % per) as usize);
+                }
+                return Some((DIRECT_BLOCKS + level - 1, path));
+            }
+            lblk -= span;
+            span *= per;
+        }
+        None
+    }
+
+    /// Maps `lblk` to a newly allocated block near `goal`, allocating the
+    /// indirect blocks on the way.
+    fn indirect_alloc(&mut self, inode: &mut Inode, lblk: u32, goal: u64) -> AxResult<u64> {
+        let Some((root, path)) = self.indirect_path(lblk) else {
+            return ax_err!(InvalidInput, "file too large for indirect blocks");
+        };
+        let mut block = le32(inode.i_block(), root * 4) as u64;
+        let path: Vec<usize> = path.into_iter().flatten().collect();
+        if block == 0 {
+            block = if path.is_empty() {
+                self.alloc_blocks(goal, 1)?.0
+            } else {
+                self.alloc_zeroed_block(goal)?
+            };
+            self.add_blocks(inode, 1);
+            set_le32(inode.i_block_mut(), root * 4, block as u32);
+        }
+        for (i, &idx) in path.iter().enumerate() {
+            let mut entry = [0; 4];
+            self.read_block_at(block, idx * 4, &mut entry)?;
+            let mut next = u32::from_le_bytes(entry) as u64;
+            if next == 0 {
+                next = if i + 1 == path.len() {
+                    self.alloc_blocks(goal, 1)?.0
+                } else {
+                    self.alloc_zeroed_block(goal)?
+                };
+                self.add_blocks(inode, 1);
+                self.write_block_at(block, idx * 4, &(next as u32).to_le_bytes())?;
+            }
+            block = next;
+        }
+        Ok(block)
+    }
+
+    /// Frees the blocks mapped by an indirect block at `level` (1 for single
+    /// indirect) from index `keep` on. Returns whether the whole indirect
+    /// block became empty.
+    fn indirect_truncate(
+        &mut self,
+        inode: &mut Inode,
+        block: u64,
+        level: u32,
+        keep: u64,
+    ) -> AxResult<bool> {
+        let per = (self.block_size / 4) as u64;
+        let unit = per.pow(level - 1);
+        let mut buf = vec![0; self.block_size];
+        self.read_block(block, &mut buf)?;
+        let mut changed = false;
+        for i in 0..per as usize {
+            let child = le32(&buf, i * 4) as u64;
+            let first = i as u64 * unit;
+            if child == 0 || first + unit <= keep {
+                continue;
+            }
+            let empty = if level == 1 {
+                true
+            } else {
+                self.indirect_truncate(inode, child, level - 1, keep.saturating_sub(first))?
+            };
+            if empty {
+                self.free_blocks(child, 1)?;
+                self.add_blocks(inode, -1);
+                set_le32(&mut buf, i * 4, 0);
+                changed = true;
+            }
+        }
+        if changed && keep > 0 {
+            self.write_block(block, &buf)?;
+        }
+        Ok(keep == 0)
+    }
+
+    // ---------------------------------------------------------------------
+    // File data
+
+    /// Reads the data of a file at `offset`, up to its size.
+    pub fn read_data(&mut self, inode: &Inode, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+        let size = inode.size();
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = buf.len().min((size - offset) as usize);
+        let bs = self.block_size as u64;
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let lblk = (pos / bs) as u32;
+            let in_block = (pos % bs) as usize;
+            let mapping = self.map(inode, lblk)?;
+            let run = match mapping {
+                Mapping::Mapped { len, .. } | Mapping::Hole { len } => len as u64,
+            };
+            let chunk = ((run * bs - in_block as u64) as usize).min(len - done);
+            let dst = &mut buf[done..done + chunk];
+            match mapping {
+                Mapping::Mapped {
+                    pblk, init: true, ..
+                } => self.read_block_at(pblk, in_block, dst)?,
+                _ => dst.fill(0),
+            }
+            done += chunk;
+        }
+        Ok(len)
+    }
+
+    /// Writes the data of a file at `offset`, allocating blocks as needed
+    /// and extending its size. The inode is updated but not written back.
+    pub fn write_data(
+        &mut self,
+        ino: u32,
+        inode: &mut Inode,
+        offset: u64,
+        buf: &[u8],
+    ) -> AxResult<usize> {
+        self.check_writable()?;
+        let bs = self.block_size as u64;
+        let end = offset
+            .checked_add(buf.len() as u64)
+            .filter(|&end| end.div_ceil(bs) <= u32::MAX as u64)
+            .ok_or_else(|| ax_err_type!(InvalidInput, "file too large"))?;
+        let use_extents = inode.flags() & INODE_FLAG_EXTENTS != 0;
+        let mut list = if use_extents {
+            Some(self.load_extents(inode)?)
+        } else {
+            None
+        };
+        let mut changed = false;
+
+        let mut done = 0;
+        let result = (|| {
+            while done < buf.len() {
+                let pos = offset + done as u64;
+                let lblk = (pos / bs) as u32;
+                let in_block = (pos % bs) as usize;
+                let mapping = match &list {
+                    Some(list) => list.map(lblk),
+                    None => self.map(inode, lblk)?,
+                };
+                let remaining_blocks = (in_block as u64 + (buf.len() - done) as u64).div_ceil(bs);
+                let (pblk, run, fresh) = match mapping {
+                    Mapping::Mapped {
+                        pblk,
+                        len,
+                        init: true,
+                    } => (pblk, len as u64, false),
+                    Mapping::Mapped {
+                        pblk,
+                        len,
+                        init: false,
+                    } => {
+                        let len = (len as u64).min(remaining_blocks) as u32;
+                        list.as_mut().unwrap().set(Extent {
+                            lblk,
+                            len,
+                            pblk,
+                            init: true,
+                        });
+                        changed = true;
+                        (pblk, len as u64, true)
+                    }
+                    Mapping::Hole { len } => {
+                        let want = (len as u64).min(remaining_blocks) as u32;
+                        let goal = self.alloc_goal(ino, inode, list.as_ref(), lblk)?;
+                        let (pblk, got) = match &mut list {
+                            Some(list) => {
+                                let (pblk, got) =
+                                    self.alloc_blocks(goal, want.min(EXTENT_INIT_MAX_LEN))?;
+                                list.set(Extent {
+                                    lblk,
+                                    len: got,
+                                    pblk,
+                                    init: true,
+                                });
+                                (pblk, got)
+                            }
+                            None => (self.indirect_alloc(inode, lblk, goal)?, 1),
+                        };
+                        if use_extents {
+                            self.add_blocks(inode, got as i64);
+                        }
+                        changed = true;
+                        (pblk, got as u64, true)
+                    }
+                };
+
+                let chunk = ((run * bs - in_block as u64) as usize).min(buf.len() - done);
+                let src = &buf[done..done + chunk];
+                if fresh {
+                    self.write_fresh(pblk, in_block, src)?;
+                } else {
+                    self.write_block_at(pblk, in_block, src)?;
+                }
+                done += chunk;
+            }
+            Ok(())
+        })();
+
+        if let Some(list) = list.filter(|_| changed) {
+            self.store_extents(ino, inode, list)?;
+        }
+        result?;
+        if end > inode.size() {
+            inode.set_size(end);
+        }
+        let now = super::now();
+        inode.set_mtime(now);
+        inode.set_ctime(now);
+        Ok(buf.len())
+    }
+
+    /// Writes `src` at `offset` of newly allocated blocks starting at `pblk`,
+    /// filling the rest of the partially written blocks with zeros.
+    fn write_fresh(&mut self, pblk: u64, offset: usize, src: &[u8]) -> AxResult {
+        let bs = self.block_size;
+        let mut data = vec![0; bs];
+        let head = (bs - offset).min(src.len());
+        if offset != 0 || head < bs {
+            data[offset..offset + head].copy_from_slice(&src[..head]);
+            self.write_block(pblk, &data)?;
+        } else {
+            self.write_block(pblk, &src[..bs])?;
+        }
+        let rest = &src[head..];
+        let whole = rest.len() / bs * bs;
+        if whole > 0 {
+            self.write_block_at(pblk + 1, 0, &rest[..whole])?;
+        }
+        let tail = &rest[whole..];
+        if !tail.is_empty() {
+            data.fill(0);
+            data[..tail.len()].copy_from_slice(tail);
+            self.write_block(pblk + 1 + (whole / bs) as u64, &data)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the block to allocate `lblk` of a file near.
+    fn alloc_goal(
+        &mut self,
+        ino: u32,
+        inode: &Inode,
+        list: Option<&ExtentList>,
+        lblk: u32,
+    ) -> AxResult<u64> {
+        if let Some(list) = list {
+            let idx = list.extents.partition_point(|ext| ext.lblk < lblk);
+            if let Some(prev) = idx.checked_sub(1).map(|i| list.extents[i]) {
+                return Ok(prev.pblk + (lblk - prev.lblk) as u64);
+            }
+        } else if lblk > 0 {
+            let prev = self.indirect_map(inode, lblk - 1)?;
+            if prev != 0 {
+                return Ok(prev + 1);
+            }
+        }
+        Ok(self.group_first_block(self.group_of_inode(ino)))
+    }
+
+    /// Changes the size of a file, freeing the blocks beyond it. The inode
+    /// is updated but not written back.
+    pub fn truncate(&mut self, ino: u32, inode: &mut Inode, size: u64) -> AxResult {
+        self.check_writable()?;
+        let bs = self.block_size as u64;
+        if size.div_ceil(bs) > u32::MAX as u64 {
+            return ax_err!(InvalidInput, "file too large");
+        }
+        let old_size = inode.size();
+        if size < old_size {
+            let keep = size.div_ceil(bs);
+            if inode.flags() & INODE_FLAG_EXTENTS != 0 {
+                let mut list = self.load_extents(inode)?;
+                let mut extents = Vec::with_capacity(list.extents.len());
+                for mut ext in list.extents.drain(..) {
+                    if ext.end() as u64 <= keep {
+                        extents.push(ext);
+                        continue;
+                    }
+                    let kept = keep.saturating_sub(ext.lblk as u64) as u32;
+                    self.free_blocks(ext.pblk + kept as u64, (ext.len - kept) as u64)?;
+                    self.add_blocks(inode, -((ext.len - kept) as i64));
+                    if kept > 0 {
+                        ext.len = kept;
+                        extents.push(ext);
+                    }
+                }
+                list.extents = extents;
+                self.store_extents(ino, inode, list)?;
+            } else if !is_fast_symlink(inode) {
+                self.truncate_indirect(inode, keep)?;
+            }
+
+            // Bytes after the end of file in the last block must be zeros.
+            let tail = (size % bs) as usize;
+            if tail != 0 {
+                if let Mapping::Mapped {
+                    pblk, init: true, ..
+                } = self.map(inode, (size / bs) as u32)?
+                {
+                    self.write_block_at(pblk, tail, &vec![0; bs as usize - tail])?;
+                }
+            }
+        }
+        inode.set_size(size);
+        let now = super::now();
+        inode.set_mtime(now);
+        inode.set_ctime(now);
+        Ok(())
+    }
+
+    fn truncate_indirect(&mut self, inode: &mut Inode, keep: u64) -> AxResult {
+        let per = (self.block_size / 4) as u64;
+        for i in (keep as usize).min(DIRECT_BLOCKS)..DIRECT_BLOCKS {
+            let block = le32(inode.i_block(), i * 4) as u64;
+            if block != 0 {
+                self.free_blocks(block, 1)?;
+                self.add_blocks(inode, -1);
+                set_le32(inode.i_block_mut(), i * 4, 0);
+            }
+        }
+        let mut first = DIRECT_BLOCKS as u64;
+        for level in 1..=3 {
+            let span = per.pow(level);
+            let slot = DIRECT_BLOCKS + level as usize - 1;
+            let block = le32(inode.i_block(), slot * 4) as u64;
+            if block != 0 && keep < first + span {
+                let empty =
+                    self.indirect_truncate(inode, block, level, keep.saturating_sub(first))?;
+                if empty {
+                    self.free_blocks(block, 1)?;
+                    self.add_blocks(inode, -1);
+                    set_le32(inode.i_block_mut(), slot * 4, 0);
+                }
+            }
+            first += span;
+        }
+        Ok(())
+    }
+
+    /// Frees all blocks of a deleted inode, including its extended
+    /// attribute block.
+    pub fn free_inode_blocks(&mut self, ino: u32, inode: &mut Inode) -> AxResult {
+        if !is_fast_symlink(inode) {
+            self.truncate(ino, inode, 0)?;
+        }
+        let acl = inode.file_acl();
+        if acl != 0 {
+            let mut buf = vec![0; self.block_size];
+            self.read_block(acl, &mut buf)?;
+            let refs = le32(&buf, 4);
+            if refs <= 1 {
+                self.free_blocks(acl, 1)?;
+            } else {
+                set_le32(&mut buf, 4, refs - 1);
+                if let Some(seed) = self.csum_seed {
+                    let mut csum = crc32c(seed, &acl.to_le_bytes());
+                    csum = crc32c(csum, &buf[..0x10]);
+                    csum = crc32c(csum, &[0; 4]);
+                    csum = crc32c(csum, &buf[0x14..]);
+                    set_le32(&mut buf, 0x10, csum);
+                }
+                self.write_block(acl, &buf)?;
+            }
+            self.add_blocks(inode, -1);
+            inode.set_file_acl(0);
+        }
+        Ok(())
+    }
+}
+
+/// Whether the target of a symlink is stored in its inode.
+pub fn is_fast_symlink(inode: &Inode) -> bool {
+    inode.file_type() == S_IFLNK
+        && inode.flags() & INODE_FLAG_EXTENTS == 0
+        && inode.size() <= FAST_SYMLINK_MAX as u64
+}
+
+fn parse_extent_header(node: &[u8]) -> AxResult<(usize, u16)> {
+    let (magic, entries, max, depth) = (le16(node, 0), le16(node, 2), le16(node, 4), le16(node, 6));
+    if magic != EXTENT_MAGIC
+        || entries > max
+        || EXTENT_HEADER_SIZE + max as usize * EXTENT_ENTRY_SIZE > node.len()
+        || depth > EXTENT_MAX_DEPTH
+    {
+        return ax_err!(InvalidData, "corrupted ext4 extent tree");
+    }
+    Ok((entries as usize, depth))
+}
+
+fn write_extent_header(node: &mut [u8], entries: usize, max: usize, depth: u16) {
+    set_le16(node, 0, EXTENT_MAGIC);
+    set_le16(node, 2, entries as u16);
+    set_le16(node, 4, max as u16);
+    set_le16(node, 6, depth);
+    set_le32(node, 8, 0);
+}
+
+fn parse_extent(node: &[u8], idx: usize) -> Extent {
+    let off = EXTENT_HEADER_SIZE + idx * EXTENT_ENTRY_SIZE;
+    let len = le16(node, off + 4) as u32;
+    let (len, init) = if len > EXTENT_INIT_MAX_LEN {
+        (len - EXTENT_INIT_MAX_LEN, false)
+    } else {
+        (len, true)
+    };
+    Extent {
+        lblk: le32(node, off),
+        len,
+        pblk: (le16(node, off + 6) as u64) << 32 | le32(node, off + 8) as u64,
+        init,
+    }
+}
+
+fn encode_extent(ext: &Extent) -> [u8; EXTENT_ENTRY_SIZE] {
+    let mut raw = [0; EXTENT_ENTRY_SIZE];
+    let len = if ext.init {
+        ext.len
+    } else {
+        ext.len + EXTENT_INIT_MAX_LEN
+    };
+    set_le32(&mut raw, 0, ext.lblk);
+    set_le16(&mut raw, 4, len as u16);
+    set_le16(&mut raw, 6, (ext.pblk >> 32) as u16);
+    set_le32(&mut raw, 8, ext.pblk as u32);
+    raw
+}
+
+/// Returns the first logical block and the child block of an index entry.
+fn parse_index(node: &[u8], idx: usize) -> (u32, u64) {
+    let off = EXTENT_HEADER_SIZE + idx * EXTENT_ENTRY_SIZE;
+    let leaf = (le16(node, off + 8) as u64) << 32 | le32(node, off + 4) as u64;
+    (le32(node, off), leaf)
+}
+
+fn encode_index(lblk: u32, block: u64) -> [u8; EXTENT_ENTRY_SIZE] {
+    let mut raw = [0; EXTENT_ENTRY_SIZE];
+    set_le32(&mut raw, 0, lblk);
+    set_le32(&mut raw, 4, block as u32);
+    set_le16(&mut raw, 8, (block >> 32) as u16);
+    raw
+}
+
+fn test_bit(bitmap: &[u8], bit: usize) -> bool {
+    bitmap[bit / 8] & (1 << (bit % 8)) != 0
+}
+
+fn set_bit(bitmap: &mut [u8], bit: usize) {
+    bitmap[bit / 8] |= 1 << (bit % 8);
+}
+
+fn clear_bit(bitmap: &mut [u8], bit: usize) {
+    bitmap[bit / 8] &= !(1 << (bit % 8));
+}
+
+/// Finds the first zero bit in `start..end`.
+fn find_zero(bitmap: &[u8], start: usize, end: usize) -> Option<usize> {
+    let mut bit = start;
+    while bit < end {
+        if bit.is_multiple_of(8) && bit + 8 <= end && bitmap[bit / 8] == 0xff {
+            bit += 8;
+            continue;
+        }
+        if !test_bit(bitmap, bit) {
+            return Some(bit);
+        }
+        bit += 1;
+    }
+    None
+}
+
+fn disk_read(disk: &mut Disk, pos: u64, mut buf: &mut [u8]) -> AxResult {
+    disk.set_position(pos);
+    while !buf.is_empty() {
+        match disk.read_one(buf) {
+            Ok(0) => return ax_err!(UnexpectedEof),
+            Ok(n) => buf = &mut buf[n..],
+            Err(_) => return ax_err!(Io),
+        }
+    }
+    Ok(())
+}
+
+fn disk_write(disk: &mut Disk, pos: u64, mut buf: &[u8]) -> AxResult {
+    disk.set_position(pos);
+    while !buf.is_empty() {
+        match disk.write_one(buf) {
+            Ok(0) => return ax_err!(WriteZero),
+            Ok(n) => buf = &buf[n..],
+            Err(_) => return ax_err!(Io),
+        }
+    }
+    Ok(())
+}
@@ -1,22 +1,58 @@
-use alloc::sync::Arc;
-use core::cell::UnsafeCell;
+use alloc::sync::{Arc, Weak};
+use core::time::Duration;
 
 use axfs_vfs::{VfsDirEntry, VfsError, VfsNodePerm, VfsResult};
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps};
 use axsync::Mutex;
-use fatfs::{Dir, File, LossyOemCpConverter, NullTimeProvider, Read, Seek, SeekFrom, Write};
+use fatfs::{Date, DateTime, Dir, File, LossyOemCpConverter, Time, TimeProvider};
+use fatfs::{Read, Seek, SeekFrom, Write};
 
 use crate::dev::Disk;
 
 const BLOCK_SIZE: usize = 512;
 
+type FileSystem = fatfs::FileSystem<Disk, WallTimeProvider, LossyOemCpConverter>;
+
+/// A FAT filesystem, which is freed after it's unmounted and all its nodes
+/// are dropped.
 pub struct FatFileSystem {
-    inner: fatfs::FileSystem<Disk, NullTimeProvider, LossyOemCpConverter>,
-    root_dir: UnsafeCell<Option<VfsNodeRef>>,
+    inner: FileSystem,
+    this: Weak<FatFileSystem>,
+}
+
+/// Provides the wall-clock time to FAT. FAT keeps local times without a time
+/// zone, and we take them as UTC.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WallTimeProvider;
+
+impl TimeProvider for WallTimeProvider {
+    fn get_current_date(&self) -> Date {
+        to_fat_time(axhal::time::wall_time()).date
+    }
+
+    fn get_current_date_time(&self) -> DateTime {
+        to_fat_time(axhal::time::wall_time())
+    }
 }
 
-pub struct FileWrapper<'a>(Mutex<File<'a, Disk, NullTimeProvider, LossyOemCpConverter>>);
-pub struct DirWrapper<'a>(Dir<'a, Disk, NullTimeProvider, LossyOemCpConverter>);
+/// The access, modification and change times of a node, since `fatfs::File`
+/// and `fatfs::Dir` don't keep those of their directory entries. FAT has no
+/// change time, so it's the modification time.
+type Times = [Duration; 3];
+
+// The nodes borrow the filesystem, and hold it so that it outlives them.
+// Fields are dropped in order, so the borrow ends before the filesystem may
+// be freed.
+pub struct FileWrapper<'a>(
+    Mutex<File<'a, Disk, WallTimeProvider, LossyOemCpConverter>>,
+    Mutex<Times>,
+    Arc<FatFileSystem>,
+);
+pub struct DirWrapper<'a>(
+    Dir<'a, Disk, WallTimeProvider, LossyOemCpConverter>,
+    Times,
+    Arc<FatFileSystem>,
+);
 
 unsafe impl Sync for FatFileSystem {}
 unsafe impl Send for FatFileSystem {}
@@ -27,38 +63,116 @@ unsafe impl<'a> Sync for DirWrapper<'a> {}
 
 impl FatFileSystem {
     #[cfg(feature = "use-ramdisk")]
-    pub fn new(mut disk: Disk) -> Self {
-        let opts = fatfs::FormatVolumeOptions::new();
+    pub fn new(mut disk: Disk) -> Arc<Self> {
+        let opts = fatfs::FormatVolumeOptions::new().bytes_per_sector(disk.block_size() as u16);
         fatfs::format_volume(&mut disk, opts).expect("failed to format volume");
-        let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new())
-            .expect("failed to initialize FAT filesystem");
-        Self {
+        let inner = fatfs::FileSystem::new(
+            disk,
+            fatfs::FsOptions::new().time_provider(WallTimeProvider),
+        )
+        .expect("failed to initialize FAT filesystem");
+        Arc::new_cyclic(|this| Self {
             inner,
-            root_dir: UnsafeCell::new(None),
-        }
+            this: this.clone(),
+        })
     }
 
     #[cfg(not(feature = "use-ramdisk"))]
-    pub fn new(disk: Disk) -> Self {
-        let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new())
-            .expect("failed to initialize FAT filesystem");
-        Self {
+    pub fn new(disk: Disk) -> Arc<Self> {
+        let inner = fatfs::FileSystem::new(
+            disk,
+            fatfs::FsOptions::new().time_provider(WallTimeProvider),
+        )
+        .expect("failed to initialize FAT filesystem");
+        Arc::new_cyclic(|this| Self {
             inner,
-            root_dir: UnsafeCell::new(None),
+            this: this.clone(),
+        })
+    }
+
+    /// Borrows the filesystem for a node holding `fs`.
+    fn borrow(fs: &Arc<Self>) -> &'static FileSystem {
+        // SAFETY: the node holds `fs` until the borrow ends.
+        unsafe { &*(&fs.inner as *const FileSystem) }
+    }
+
+    fn new_file(
+        file: File<'static, Disk, WallTimeProvider, LossyOemCpConverter>,
+        times: Times,
+        fs: Arc<Self>,
+    ) -> Arc<FileWrapper<'static>> {
+        Arc::new(FileWrapper(Mutex::new(file), Mutex::new(times), fs))
+    }
+
+    fn new_dir(
+        dir: Dir<'static, Disk, WallTimeProvider, LossyOemCpConverter>,
+        times: Times,
+        fs: Arc<Self>,
+    ) -> Arc<DirWrapper<'static>> {
+        Arc::new(DirWrapper(dir, times, fs))
+    }
+}
+
+impl FileWrapper<'_> {
+    /// Returns the access, modification and change times.
+    pub fn times(&self) -> Times {
+        *self.1.lock()
+    }
+
+    /// Sets the access and modification times, leaving those of `None`
+    /// unchanged. Only the date of the access time is kept.
+    pub fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> VfsResult {
+        let mut file = self.0.lock();
+        let mut times = self.1.lock();
+        if let Some(atime) = atime {
+            let date = to_fat_time(atime).date;
+            file.set_accessed(date);
+            times[0] = from_fat_date(date);
         }
+        if let Some(mtime) = mtime {
+            let mtime = to_fat_time(mtime);
+            file.set_modified(mtime);
+            times[1] = from_fat_time(mtime);
+            times[2] = times[1];
+        }
+        file.flush().map_err(as_vfs_err)
     }
 
-    pub fn init(&'static self) {
-        // must be called before later operations
-        unsafe { *self.root_dir.get() = Some(Self::new_dir(self.inner.root_dir())) }
+    /// Updates the cached times after the file is modified, as `fatfs` does
+    /// for its directory entry.
+    fn touch(&self) {
+        let mut times = self.1.lock();
+        times[1] = from_fat_time(WallTimeProvider.get_current_date_time());
+        times[2] = times[1];
     }
+}
 
-    fn new_file(file: File<'_, Disk, NullTimeProvider, LossyOemCpConverter>) -> Arc<FileWrapper> {
-        Arc::new(FileWrapper(Mutex::new(file)))
+impl DirWrapper<'_> {
+    /// Returns the access, modification and change times.
+    pub fn times(&self) -> Times {
+        self.1
     }
 
-    fn new_dir(dir: Dir<'_, Disk, NullTimeProvider, LossyOemCpConverter>) -> Arc<DirWrapper> {
-        Arc::new(DirWrapper(dir))
+    /// Fails as `fatfs::Dir` can't update its directory entry.
+    pub fn set_times(&self, _atime: Option<Duration>, _mtime: Option<Duration>) -> VfsResult {
+        Err(VfsError::Unsupported)
+    }
+
+    /// Returns the times in the directory entry at `path`.
+    fn entry_times(&self, path: &str) -> Times {
+        let (dir, name) = match path.rsplit_once('/') {
+            Some((dir, name)) => (self.0.open_dir(dir).ok(), name),
+            None => (Some(self.0.clone()), path),
+        };
+        dir.and_then(|dir| {
+            dir.iter()
+                .filter_map(Result::ok)
+                .find(|entry| entry.file_name().eq_ignore_ascii_case(name))
+        })
+        .map_or_else(Times::default, |entry| {
+            let mtime = from_fat_time(entry.modified());
+            [from_fat_date(entry.accessed()), mtime, mtime]
+        })
     }
 }
 
@@ -82,13 +196,23 @@ impl VfsNodeOps for FileWrapper<'static> {
     fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
         let mut file = self.0.lock();
         file.seek(SeekFrom::Start(offset)).map_err(as_vfs_err)?; // TODO: more efficient
-        file.write(buf).map_err(as_vfs_err)
+        let len = file.write(buf).map_err(as_vfs_err)?;
+        self.touch();
+        Ok(len)
     }
 
     fn truncate(&self, size: u64) -> VfsResult {
         let mut file = self.0.lock();
         file.seek(SeekFrom::Start(size)).map_err(as_vfs_err)?; // TODO: more efficient
-        file.truncate().map_err(as_vfs_err)
+        file.truncate().map_err(as_vfs_err)?;
+        file.set_modified(WallTimeProvider.get_current_date_time());
+        self.touch();
+        Ok(())
+    }
+
+    fn fsync(&self) -> VfsResult {
+        // writes the directory entry, and flushes the disk
+        self.0.lock().flush().map_err(as_vfs_err)
     }
 }
 
@@ -106,9 +230,13 @@ impl VfsNodeOps for DirWrapper<'static> {
     }
 
     fn parent(&self) -> Option<VfsNodeRef> {
-        self.0
-            .open_dir("..")
-            .map_or(None, |dir| Some(FatFileSystem::new_dir(dir)))
+        self.0.open_dir("..").map_or(None, |dir| {
+            Some(FatFileSystem::new_dir(
+                dir,
+                self.entry_times(".."),
+                self.2.clone(),
+            ))
+        })
     }
 
     fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
@@ -122,10 +250,11 @@ impl VfsNodeOps for DirWrapper<'static> {
         }
 
         // TODO: use `fatfs::Dir::find_entry`, but it's not public.
+        let fs = self.2.clone();
         if let Ok(file) = self.0.open_file(path) {
-            Ok(FatFileSystem::new_file(file))
+            Ok(FatFileSystem::new_file(file, self.entry_times(path), fs))
         } else if let Ok(dir) = self.0.open_dir(path) {
-            Ok(FatFileSystem::new_dir(dir))
+            Ok(FatFileSystem::new_dir(dir, self.entry_times(path), fs))
         } else {
             Err(VfsError::NotFound)
         }
@@ -200,8 +329,10 @@ impl VfsNodeOps for DirWrapper<'static> {
 
 impl VfsOps for FatFileSystem {
     fn root_dir(&self) -> VfsNodeRef {
-        let root_dir = unsafe { (*self.root_dir.get()).as_ref().unwrap() };
-        root_dir.clone()
+        let fs = self.this.upgrade().unwrap();
+        // the root directory has no entry, nor times
+        let root_dir = Self::borrow(&fs).root_dir();
+        Self::new_dir(root_dir, Times::default(), fs)
     }
 }
 
@@ -243,7 +374,7 @@ impl Write for Disk {
         Ok(write_len)
     }
     fn flush(&mut self) -> Result<(), Self::Error> {
-        Ok(())
+        Disk::flush(self).map_err(|_| ())
     }
 }
 
@@ -264,6 +395,67 @@ impl Seek for Disk {
     }
 }
 
+/// Converts a time since the Unix epoch to a FAT date and time, clamped to the
+/// years FAT can represent (1980 to 2107).
+fn to_fat_time(time: Duration) -> DateTime {
+    const MIN_SECS: u64 = 315_532_800; // 1980-01-01 00:00:00
+    const MAX_SECS: u64 = 4_354_819_199; // 2107-12-31 23:59:59
+    let secs = time.as_secs().clamp(MIN_SECS, MAX_SECS);
+    let millis = if secs == time.as_secs() {
+        time.subsec_millis() as u16
+    } else {
+        0
+    };
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let secs = secs % 86400;
+    DateTime::new(
+        Date::new(year as u16, month as u16, day as u16),
+        Time::new(
+            (secs / 3600) as u16,
+            (secs / 60 % 60) as u16,
+            (secs % 60) as u16,
+            millis,
+        ),
+    )
+}
+
+fn from_fat_date(date: Date) -> Duration {
+    let days = days_from_civil(date.year as i64, date.month as u32, date.day as u32);
+    Duration::from_secs(days.max(0) as u64 * 86400)
+}
+
+fn from_fat_time(dt: DateTime) -> Duration {
+    let time = dt.time;
+    let secs = time.hour as u64 * 3600 + time.min as u64 * 60 + time.sec as u64;
+    from_fat_date(dt.date) + Duration::from_millis(secs * 1000 + time.millis as u64)
+}
+
+/// Returns the year, month and day of the `days`-th day since 1970-01-01, see
+/// <https://howardhinnant.github.io/date_algorithms.html>.
+const fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+/// The inverse of [`civil_from_days`].
+const fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
 const fn as_vfs_err(err: fatfs::Error<()>) -> VfsError {
     use fatfs::Error::*;
     match err {
@@ -1,13 +1,291 @@
-cfg_if::cfg_if! {
-    if #[cfg(feature = "myfs")] {
-        pub mod myfs;
-    } else if #[cfg(feature = "fatfs")] {
-        pub mod fatfs;
-    }
-}
+#[cfg(feature = "myfs")]
+pub mod myfs;
+
+#[cfg(feature = "ext4fs")]
+pub mod ext4fs;
+
+#[cfg(feature = "fatfs")]
+pub mod fatfs;
+
+#[cfg(feature = "procfs")]
+pub mod procfs;
+
+#[cfg(any(feature = "procfs", feature = "sysfs", feature = "devfs"))]
+pub mod pseudo;
 
 #[cfg(feature = "devfs")]
 pub use axfs_devfs as devfs;
 
 #[cfg(feature = "ramfs")]
-pub use axfs_ramfs as ramfs;
+pub mod ramfs;
+
+#[cfg(feature = "overlayfs")]
+pub mod overlayfs;
+
+use alloc::{string::String, sync::Arc};
+use axerrno::{ax_err, AxResult};
+use axfs_vfs::{VfsNodeRef, VfsOps};
+use core::time::Duration;
+
+use crate::dev::Disk;
+use crate::fops::FileAttr;
+use crate::lock::FileId;
+use crate::partition::FsType;
+
+/// The filesystem created on a root volume whose filesystem is not recognized,
+/// e.g. a RAM disk to be formatted.
+#[cfg(not(feature = "myfs"))]
+const DEFAULT_FS_TYPE: FsType = if cfg!(feature = "ext4fs") {
+    FsType::Ext4
+} else {
+    FsType::Fat
+};
+
+/// Whether the filesystem of `fs_type` can be mounted.
+pub(crate) fn is_supported(fs_type: FsType) -> bool {
+    match fs_type {
+        FsType::Fat => cfg!(feature = "fatfs"),
+        FsType::Ext4 => cfg!(feature = "ext4fs"),
+        FsType::Unknown => false,
+    }
+}
+
+/// Creates the filesystem of `fs_type` on `disk`, or returns `None` if it's
+/// not supported.
+pub(crate) fn new_fs(disk: Disk, fs_type: FsType) -> Option<Arc<dyn VfsOps>> {
+    match fs_type {
+        #[cfg(feature = "ext4fs")]
+        FsType::Ext4 => Some(Arc::new(ext4fs::Ext4FileSystem::new(disk))),
+        #[cfg(feature = "fatfs")]
+        FsType::Fat => Some(fatfs::FatFileSystem::new(disk)),
+        _ => {
+            let _ = disk;
+            None
+        }
+    }
+}
+
+/// Creates the root filesystem on `disk`, returns it with its type name.
+pub(crate) fn new_root_fs(disk: Disk, fs_type: FsType) -> (Arc<dyn VfsOps>, &'static str) {
+    #[cfg(feature = "myfs")] // override the default filesystem
+    {
+        let _ = fs_type;
+        (myfs::new_myfs(disk), "myfs")
+    }
+    #[cfg(not(feature = "myfs"))]
+    {
+        let fs_type = if is_supported(fs_type) {
+            fs_type
+        } else {
+            DEFAULT_FS_TYPE
+        };
+        let fs = new_fs(disk, fs_type).expect("no filesystem enabled for the root");
+        (fs, fs_type.name())
+    }
+}
+
+/// Creates a symlink at `path` relative to the directory `dir` pointing to
+/// `target`, if the filesystem of `dir` supports symlinks.
+pub(crate) fn symlink(dir: &VfsNodeRef, path: &str, target: &str) -> AxResult {
+    #[cfg(feature = "ext4fs")]
+    if let Some(dir) = dir.as_any().downcast_ref::<ext4fs::DirNode>() {
+        return dir.symlink(path, target);
+    }
+    #[cfg(feature = "ramfs")]
+    if let Some(dir) = dir.as_any().downcast_ref::<ramfs::DirNode>() {
+        return dir.symlink(path, target);
+    }
+    #[cfg(feature = "overlayfs")]
+    if let Some(dir) = dir.as_any().downcast_ref::<overlayfs::OverlayNode>() {
+        return dir.symlink(path, target);
+    }
+    let _ = (dir, path, target);
+    ax_err!(PermissionDenied, "symlinks are not supported")
+}
+
+/// Creates a hard link at `new_path` to the node at `old_path`, both relative
+/// to the directory `dir`, if the filesystem of `dir` supports hard links.
+pub(crate) fn link(dir: &VfsNodeRef, old_path: &str, new_path: &str) -> AxResult {
+    #[cfg(feature = "ext4fs")]
+    if let Some(dir) = dir.as_any().downcast_ref::<ext4fs::DirNode>() {
+        return dir.link(old_path, new_path);
+    }
+    #[cfg(feature = "ramfs")]
+    if let Some(dir) = dir.as_any().downcast_ref::<ramfs::DirNode>() {
+        return dir.link(old_path, new_path);
+    }
+    #[cfg(feature = "overlayfs")]
+    if let Some(dir) = dir.as_any().downcast_ref::<overlayfs::OverlayNode>() {
+        return dir.link(old_path, new_path);
+    }
+    let _ = (dir, old_path, new_path);
+    ax_err!(PermissionDenied, "hard links are not supported")
+}
+
+/// Attributes of nodes that are not part of [`axfs_vfs::VfsNodeOps`], kept
+/// by some filesystems.
+trait NodeAttr {
+    /// Fills the owner, link count and timestamps in `attr`.
+    fn fill_attr(&self, attr: &mut FileAttr) -> AxResult;
+
+    /// Sets the access and modification times, leaving those of `None`
+    /// unchanged.
+    fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> AxResult;
+}
+
+#[cfg(feature = "ext4fs")]
+macro_rules! impl_ext4_node_attr {
+    ($($ty:ty),*) => {$(
+        impl NodeAttr for $ty {
+            fn fill_attr(&self, attr: &mut FileAttr) -> AxResult {
+                let info = self.info()?;
+                attr.ino = info.ino as u64;
+                attr.uid = info.uid;
+                attr.gid = info.gid;
+                attr.nlink = info.nlink as u32;
+                attr.atime = info.atime.into();
+                attr.mtime = info.mtime.into();
+                attr.ctime = info.ctime.into();
+                Ok(())
+            }
+
+            fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> AxResult {
+                self.set_times(atime.map(Into::into), mtime.map(Into::into))
+            }
+        }
+    )*};
+}
+
+#[cfg(feature = "ext4fs")]
+impl_ext4_node_attr!(ext4fs::FileNode, ext4fs::DirNode, ext4fs::SymlinkNode);
+
+#[cfg(feature = "fatfs")]
+macro_rules! impl_fat_node_attr {
+    ($($ty:ty),*) => {$(
+        impl NodeAttr for $ty {
+            fn fill_attr(&self, attr: &mut FileAttr) -> AxResult {
+                [attr.atime, attr.mtime, attr.ctime] = self.times();
+                Ok(())
+            }
+
+            fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> AxResult {
+                self.set_times(atime, mtime)
+            }
+        }
+    )*};
+}
+
+#[cfg(feature = "fatfs")]
+impl_fat_node_attr!(fatfs::FileWrapper<'static>, fatfs::DirWrapper<'static>);
+
+#[cfg(feature = "ramfs")]
+macro_rules! impl_ram_node_attr {
+    ($($ty:ty),*) => {$(
+        impl NodeAttr for $ty {
+            fn fill_attr(&self, attr: &mut FileAttr) -> AxResult {
+                [attr.atime, attr.mtime, attr.ctime] = self.times();
+                Ok(())
+            }
+
+            fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> AxResult {
+                self.set_times(atime, mtime);
+                Ok(())
+            }
+        }
+    )*};
+}
+
+#[cfg(feature = "ramfs")]
+impl_ram_node_attr!(ramfs::FileNode, ramfs::DirNode, ramfs::SymlinkNode);
+
+#[cfg(feature = "overlayfs")]
+impl NodeAttr for overlayfs::OverlayNode {
+    fn fill_attr(&self, attr: &mut FileAttr) -> AxResult {
+        *attr = get_attr(&self.current())?;
+        Ok(())
+    }
+
+    fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> AxResult {
+        set_times(&self.copy_up()?, atime, mtime)
+    }
+}
+
+/// Returns `node` as a [`NodeAttr`] if its filesystem keeps such attributes.
+fn node_attr(node: &VfsNodeRef) -> Option<&dyn NodeAttr> {
+    let any = node.as_any();
+    macro_rules! downcast {
+        ($($ty:ty),*) => {$(
+            if let Some(node) = any.downcast_ref::<$ty>() {
+                return Some(node);
+            }
+        )*};
+    }
+    #[cfg(feature = "ext4fs")]
+    downcast!(ext4fs::FileNode, ext4fs::DirNode, ext4fs::SymlinkNode);
+    #[cfg(feature = "fatfs")]
+    downcast!(fatfs::FileWrapper<'static>, fatfs::DirWrapper<'static>);
+    #[cfg(feature = "ramfs")]
+    downcast!(ramfs::FileNode, ramfs::DirNode, ramfs::SymlinkNode);
+    #[cfg(feature = "overlayfs")]
+    downcast!(overlayfs::OverlayNode);
+    let _ = any;
+    None
+}
+
+/// Returns the attributes of `node`, with the owner, link count and
+/// timestamps if its filesystem keeps them.
+pub(crate) fn get_attr(node: &VfsNodeRef) -> AxResult<FileAttr> {
+    let mut attr = FileAttr::from(node.get_attr()?);
+    if let Some(node) = node_attr(node) {
+        node.fill_attr(&mut attr)?;
+    }
+    Ok(attr)
+}
+
+/// Sets the access and modification times of `node`, leaving those of `None`
+/// unchanged, if its filesystem keeps them.
+pub(crate) fn set_times(
+    node: &VfsNodeRef,
+    atime: Option<Duration>,
+    mtime: Option<Duration>,
+) -> AxResult {
+    match node_attr(node) {
+        Some(node) => node.set_times(atime, mtime),
+        None => ax_err!(PermissionDenied, "timestamps are not supported"),
+    }
+}
+
+/// Returns the identity of the file of `node` for its locks, where `path` is
+/// the absolute path of `node` if it's known.
+///
+/// ext4 files are identified by their inode numbers. FAT creates nodes on
+/// each lookup and has no inode numbers, so its files are identified by their
+/// paths, or by the node if the path is unknown. Nodes of other filesystems
+/// live as long as their files, and identify them.
+pub(crate) fn file_id(node: &VfsNodeRef, path: Option<String>) -> FileId {
+    let any = node.as_any();
+    #[cfg(feature = "ext4fs")]
+    {
+        macro_rules! ext4_id {
+            ($($ty:ty),*) => {$(
+                if let Some(node) = any.downcast_ref::<$ty>() {
+                    let (vol, ino) = node.id();
+                    return FileId::Node(vol, ino);
+                }
+            )*};
+        }
+        ext4_id!(ext4fs::FileNode, ext4fs::DirNode, ext4fs::SymlinkNode);
+    }
+    #[cfg(feature = "fatfs")]
+    if any.is::<fatfs::FileWrapper<'static>>() || any.is::<fatfs::DirWrapper<'static>>() {
+        if let Some(path) = path {
+            return FileId::Path(path);
+        }
+    }
+    #[cfg(feature = "overlayfs")]
+    if let Some(node) = any.downcast_ref::<overlayfs::OverlayNode>() {
+        return file_id(&node.current(), path);
+    }
+    let _ = path;
+    FileId::Node(any as *const dyn core::any::Any as *const () as usize, 0)
+}
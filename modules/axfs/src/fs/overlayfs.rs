@@ -0,0 +1,592 @@
+//! An overlay filesystem, which merges a writable upper layer over a
+//! read-only lower layer.
+//!
+//! Each layer is a directory of another filesystem. Files of the lower layer
+//! are copied up to the upper layer before they are modified, and only the
+//! upper layer is ever written. Like AUFS, a removed file of the lower layer
+//! is hidden by a whiteout, an empty file named `.wh.<name>` in the upper
+//! layer, and a directory of the upper layer which replaces one of the lower
+//! layer is made opaque by a `.wh..wh..opq` file in it, so any filesystem
+//! can be the upper layer. Directories of the lower layer can't be renamed.
+
+use alloc::collections::BTreeSet;
+use alloc::sync::{Arc, Weak};
+use alloc::{format, string::String, vec::Vec};
+use axerrno::{ax_err, AxError};
+use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsOps, VfsResult};
+use axsync::Mutex;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+const WHITEOUT_PREFIX: &str = ".wh.";
+const OPAQUE_MARKER: &str = ".wh..wh..opq";
+
+/// A filesystem that merges the directory `upper` over `lower`.
+pub struct OverlayFileSystem {
+    /// The filesystems of the layers, if they are owned by the overlay.
+    fs: Option<[Arc<dyn VfsOps>; 2]>,
+    root: Arc<OverlayNode>,
+}
+
+/// The layers shared by all nodes of an overlay.
+struct Layers {
+    upper: VfsNodeRef,
+    /// The parent of the mount point.
+    parent: Mutex<Weak<dyn VfsNodeOps>>,
+    /// The number of copy-ups so far, with which nodes see the copies made
+    /// through other nodes of the same path.
+    copy_ups: AtomicUsize,
+    /// Serializes copy-ups.
+    copy_up_lock: Mutex<()>,
+}
+
+/// The node of the upper layer, if it's there.
+struct Upper {
+    node: Option<VfsNodeRef>,
+    /// The number of copy-ups when `node` was looked up.
+    copy_ups: usize,
+}
+
+/// A file, directory or symlink of an overlay.
+pub struct OverlayNode {
+    this: Weak<OverlayNode>,
+    layers: Arc<Layers>,
+    parent: Option<Arc<OverlayNode>>,
+    /// The path relative to the roots of the layers, empty for the root.
+    path: String,
+    ty: VfsNodeType,
+    upper: Mutex<Upper>,
+    /// The node of the lower layer if it's seen, i.e. not covered by the
+    /// upper layer, or merged with a directory of the upper layer.
+    lower: Option<VfsNodeRef>,
+    /// Whether the lower layer has an entry of the path, which is hidden by
+    /// a whiteout when the node is removed.
+    in_lower: bool,
+}
+
+impl OverlayFileSystem {
+    /// Creates an overlay of the directory `upper` over the directory
+    /// `lower`.
+    pub fn new(lower: VfsNodeRef, upper: VfsNodeRef) -> Self {
+        let lower = (!is_opaque(&upper)).then_some(lower);
+        let layers = Arc::new(Layers {
+            upper: upper.clone(),
+            parent: Mutex::new(Weak::<OverlayNode>::new()),
+            copy_ups: AtomicUsize::new(0),
+            copy_up_lock: Mutex::new(()),
+        });
+        Self {
+            fs: None,
+            root: OverlayNode::new(layers, None, String::new(), Some(upper), lower, true),
+        }
+    }
+
+    /// Creates an overlay of the filesystem `upper` over the filesystem
+    /// `lower`, which are unmounted with the overlay.
+    pub fn from_fs(lower: Arc<dyn VfsOps>, upper: Arc<dyn VfsOps>) -> Self {
+        Self {
+            fs: Some([lower.clone(), upper.clone()]),
+            ..Self::new(lower.root_dir(), upper.root_dir())
+        }
+    }
+}
+
+impl VfsOps for OverlayFileSystem {
+    fn mount(&self, _path: &str, mount_point: VfsNodeRef) -> VfsResult {
+        if let Some(parent) = mount_point.parent() {
+            *self.root.layers.parent.lock() = Arc::downgrade(&parent);
+        }
+        Ok(())
+    }
+
+    fn umount(&self) -> VfsResult {
+        for fs in self.fs.iter().flatten() {
+            fs.umount()?;
+        }
+        Ok(())
+    }
+
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
+
+fn whiteout_name(name: &str) -> String {
+    format!("{}{}", WHITEOUT_PREFIX, name)
+}
+
+fn join_path(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.into()
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+/// Looks up `name` in `dir`, returning `None` if it doesn't exist.
+fn lookup_opt(dir: &VfsNodeRef, name: &str) -> VfsResult<Option<VfsNodeRef>> {
+    match dir.clone().lookup(name) {
+        Ok(node) => Ok(Some(node)),
+        Err(AxError::NotFound) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn is_opaque(dir: &VfsNodeRef) -> bool {
+    dir.clone().lookup(OPAQUE_MARKER).is_ok()
+}
+
+/// Reads all entries of `dir`.
+fn read_dir_all(dir: &VfsNodeRef) -> VfsResult<Vec<(String, VfsNodeType)>> {
+    const EMPTY: VfsDirEntry = VfsDirEntry::default();
+    let mut buf = [EMPTY; 16];
+    let mut entries = Vec::new();
+    loop {
+        let n = dir.read_dir(entries.len(), &mut buf)?;
+        if n == 0 {
+            return Ok(entries);
+        }
+        for ent in &buf[..n] {
+            let name = String::from_utf8_lossy(ent.name_as_bytes()).into_owned();
+            entries.push((name, ent.entry_type()));
+        }
+    }
+}
+
+/// Removes the whiteout of `name` in `dir`, returning whether it existed.
+fn remove_whiteout(dir: &VfsNodeRef, name: &str) -> VfsResult<bool> {
+    match dir.remove(&whiteout_name(name)) {
+        Ok(()) => Ok(true),
+        Err(AxError::NotFound) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+impl OverlayNode {
+    fn new(
+        layers: Arc<Layers>,
+        parent: Option<Arc<OverlayNode>>,
+        path: String,
+        upper: Option<VfsNodeRef>,
+        lower: Option<VfsNodeRef>,
+        in_lower: bool,
+    ) -> Arc<Self> {
+        let ty = upper
+            .as_ref()
+            .or(lower.as_ref())
+            .and_then(|node| node.get_attr().ok())
+            .map_or(VfsNodeType::Dir, |attr| attr.file_type());
+        let copy_ups = layers.copy_ups.load(Ordering::Acquire);
+        Arc::new_cyclic(|this| Self {
+            this: this.clone(),
+            layers,
+            parent,
+            path,
+            ty,
+            upper: Mutex::new(Upper {
+                node: upper,
+                copy_ups,
+            }),
+            lower,
+            in_lower,
+        })
+    }
+
+    fn this(&self) -> Arc<Self> {
+        self.this.upgrade().unwrap()
+    }
+
+    /// Returns the node of the upper layer, which may have been copied up
+    /// through another node.
+    fn upper(&self) -> Option<VfsNodeRef> {
+        let mut upper = self.upper.lock();
+        let copy_ups = self.layers.copy_ups.load(Ordering::Acquire);
+        if upper.node.is_none() && upper.copy_ups != copy_ups {
+            upper.node = self.layers.upper.clone().lookup(&self.path).ok();
+            upper.copy_ups = copy_ups;
+        }
+        upper.node.clone()
+    }
+
+    /// Returns the node of the upper layer if it's there, or the lower one.
+    pub(crate) fn current(&self) -> VfsNodeRef {
+        match self.upper() {
+            Some(node) => node,
+            None => self.lower.clone().unwrap(),
+        }
+    }
+
+    /// Copies the node and its parents up to the upper layer if they are only
+    /// in the lower layer, returning the node of the upper layer.
+    pub(crate) fn copy_up(&self) -> VfsResult<VfsNodeRef> {
+        if let Some(upper) = self.upper() {
+            return Ok(upper);
+        }
+        let _guard = self.layers.copy_up_lock.lock();
+        self.copy_up_locked()
+    }
+
+    fn copy_up_locked(&self) -> VfsResult<VfsNodeRef> {
+        if let Some(upper) = self.upper() {
+            return Ok(upper);
+        }
+        // the root is always in the upper layer
+        let (Some(parent), Some(lower)) = (&self.parent, &self.lower) else {
+            return ax_err!(NotFound);
+        };
+        parent.copy_up_locked()?;
+        let upper = &self.layers.upper;
+        match self.ty {
+            VfsNodeType::Dir => upper.create(&self.path, VfsNodeType::Dir)?,
+            VfsNodeType::File => {
+                upper.create(&self.path, VfsNodeType::File)?;
+                let node = upper.clone().lookup(&self.path)?;
+                let mut buf = alloc::vec![0; 4096];
+                let mut offset = 0;
+                loop {
+                    let n = lower.read_at(offset, &mut buf)?;
+                    if n == 0 {
+                        break;
+                    }
+                    node.write_at(offset, &buf[..n])?;
+                    offset += n as u64;
+                }
+            }
+            VfsNodeType::SymLink => {
+                let mut target = alloc::vec![0; lower.get_attr()?.size() as usize];
+                let len = lower.read_at(0, &mut target)?;
+                let target =
+                    core::str::from_utf8(&target[..len]).map_err(|_| AxError::InvalidData)?;
+                super::symlink(upper, &self.path, target)?;
+            }
+            _ => return ax_err!(Unsupported, "cannot copy up special files"),
+        }
+        let node = upper.clone().lookup(&self.path)?;
+        if let Ok(attr) = super::get_attr(lower) {
+            // the upper layer may have no timestamps
+            super::set_times(&node, Some(attr.atime()), Some(attr.mtime())).ok();
+        }
+        self.layers.copy_ups.fetch_add(1, Ordering::Release);
+        self.upper.lock().node = Some(node.clone());
+        Ok(node)
+    }
+
+    /// Looks up the entry `name` of this directory.
+    fn child(&self, name: &str) -> VfsResult<Arc<Self>> {
+        if !self.ty.is_dir() {
+            return ax_err!(NotADirectory);
+        } else if name.starts_with(WHITEOUT_PREFIX) {
+            return ax_err!(NotFound);
+        }
+        let mut upper = None;
+        let mut whiteout = false;
+        if let Some(dir) = self.upper() {
+            upper = lookup_opt(&dir, name)?;
+            whiteout = upper.is_none() && lookup_opt(&dir, &whiteout_name(name))?.is_some();
+        }
+        let mut lower = match &self.lower {
+            Some(dir) if !whiteout => lookup_opt(dir, name)?,
+            _ => None,
+        };
+        let in_lower = lower.is_some();
+        if let Some(upper) = &upper {
+            // only directories are merged
+            let merged = upper.get_attr()?.is_dir()
+                && lower
+                    .as_ref()
+                    .is_some_and(|l| l.get_attr().is_ok_and(|a| a.is_dir()))
+                && !is_opaque(upper);
+            if !merged {
+                lower = None;
+            }
+        } else if lower.is_none() {
+            return ax_err!(NotFound);
+        }
+        let path = join_path(&self.path, name);
+        Ok(Self::new(
+            self.layers.clone(),
+            Some(self.this()),
+            path,
+            upper,
+            lower,
+            in_lower,
+        ))
+    }
+
+    fn has_child(&self, name: &str) -> VfsResult<bool> {
+        match self.child(name) {
+            Ok(_) => Ok(true),
+            Err(AxError::NotFound) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Looks up the directory at `path` relative to this directory.
+    fn lookup_dir(&self, path: &str) -> VfsResult<Arc<Self>> {
+        let mut node = self.this();
+        for name in path.split('/') {
+            node = match name {
+                "" | "." => node,
+                ".." => node.parent.clone().ok_or(AxError::NotFound)?,
+                _ => node.child(name)?,
+            };
+        }
+        if node.ty.is_dir() {
+            Ok(node)
+        } else {
+            ax_err!(NotADirectory)
+        }
+    }
+
+    /// Returns the directory of the last component of `path` and its name.
+    fn parent_of<'a>(&self, path: &'a str) -> VfsResult<(Arc<Self>, &'a str)> {
+        let path = path.trim_matches('/');
+        let (dir, name) = match path.rsplit_once('/') {
+            Some((dir, name)) => (self.lookup_dir(dir)?, name),
+            None => (self.this(), path),
+        };
+        if name.is_empty() || name == "." || name == ".." {
+            return ax_err!(InvalidInput);
+        } else if name.starts_with(WHITEOUT_PREFIX) {
+            return ax_err!(InvalidInput, "reserved name in overlay");
+        }
+        Ok((dir, name))
+    }
+
+    /// Returns the merged entries of this directory.
+    fn entries(&self) -> VfsResult<Vec<(String, VfsNodeType)>> {
+        let mut entries = Vec::new();
+        let mut hidden = BTreeSet::new();
+        if let Some(upper) = self.upper() {
+            for (name, ty) in read_dir_all(&upper)? {
+                if let Some(name) = name.strip_prefix(WHITEOUT_PREFIX) {
+                    hidden.insert(String::from(name));
+                } else {
+                    hidden.insert(name.clone());
+                    entries.push((name, ty));
+                }
+            }
+        }
+        if let Some(lower) = &self.lower {
+            for (name, ty) in read_dir_all(lower)? {
+                if !hidden.contains(&name) {
+                    entries.push((name, ty));
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Copies this directory up, and removes the whiteout of `name` in it to
+    /// create an entry, returning the directory of the upper layer and
+    /// whether the whiteout existed.
+    fn prepare_create(&self, name: &str) -> VfsResult<(VfsNodeRef, bool)> {
+        let upper = self.copy_up()?;
+        let whiteout = remove_whiteout(&upper, name)?;
+        Ok((upper, whiteout))
+    }
+
+    /// Makes the directory `name` of the upper directory `dir` opaque.
+    fn make_opaque(dir: &VfsNodeRef, name: &str) -> VfsResult {
+        dir.clone()
+            .lookup(name)?
+            .create(OPAQUE_MARKER, VfsNodeType::File)
+    }
+
+    fn create_child(&self, name: &str, ty: VfsNodeType) -> VfsResult {
+        match self.child(name) {
+            // like other filesystems, creating an existing node of the same
+            // type succeeds
+            Ok(node) if node.ty == ty => return Ok(()),
+            Ok(_) => return ax_err!(AlreadyExists),
+            Err(AxError::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+        let (upper, whiteout) = self.prepare_create(name)?;
+        upper.create(name, ty)?;
+        if ty.is_dir() && whiteout {
+            // hide the removed directory of the lower layer
+            Self::make_opaque(&upper, name)?;
+        }
+        Ok(())
+    }
+
+    fn remove_child(&self, name: &str) -> VfsResult {
+        let node = self.child(name)?;
+        if node.ty.is_dir() && node.entries()?.iter().any(|(n, _)| n != "." && n != "..") {
+            return ax_err!(DirectoryNotEmpty);
+        }
+        let upper = self.copy_up()?;
+        if let Some(node_upper) = node.upper() {
+            if node.ty.is_dir() {
+                for (name, _) in read_dir_all(&node_upper)? {
+                    if name.starts_with(WHITEOUT_PREFIX) {
+                        node_upper.remove(&name)?;
+                    }
+                }
+            }
+            upper.remove(name)?;
+        }
+        if node.in_lower {
+            upper.create(&whiteout_name(name), VfsNodeType::File)?;
+        }
+        Ok(())
+    }
+
+    /// Creates a symlink at `path` pointing to `target`.
+    pub fn symlink(&self, path: &str, target: &str) -> VfsResult {
+        let (dir, name) = self.parent_of(path)?;
+        if dir.has_child(name)? {
+            return ax_err!(AlreadyExists);
+        }
+        dir.prepare_create(name)?;
+        super::symlink(&self.layers.upper, &join_path(&dir.path, name), target)
+    }
+
+    /// Creates a hard link at `new_path` to the file or symlink at
+    /// `old_path`, after copying it up.
+    pub fn link(&self, old_path: &str, new_path: &str) -> VfsResult {
+        let (old_dir, old_name) = self.parent_of(old_path)?;
+        let node = old_dir.child(old_name)?;
+        if node.ty.is_dir() {
+            return ax_err!(PermissionDenied);
+        }
+        let (dir, name) = self.parent_of(new_path)?;
+        if dir.has_child(name)? {
+            return ax_err!(AlreadyExists);
+        }
+        node.copy_up()?;
+        dir.prepare_create(name)?;
+        super::link(&self.layers.upper, &node.path, &join_path(&dir.path, name))
+    }
+}
+
+impl VfsNodeOps for OverlayNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        self.current().get_attr()
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        self.current().read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        if self.ty.is_dir() {
+            return ax_err!(IsADirectory);
+        }
+        self.copy_up()?.write_at(offset, buf)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        match self.upper() {
+            Some(node) => node.fsync(),
+            None => Ok(()),
+        }
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        if self.ty.is_dir() {
+            return ax_err!(IsADirectory);
+        }
+        self.copy_up()?.truncate(size)
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        match &self.parent {
+            Some(parent) => Some(parent.clone()),
+            None => self.layers.parent.lock().upgrade(),
+        }
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let path = path.trim_start_matches('/');
+        let (name, rest) = match path.split_once('/') {
+            Some((name, rest)) => (name, Some(rest).filter(|r| !r.is_empty())),
+            None => (path, None),
+        };
+        let node: VfsNodeRef = match name {
+            "" | "." => self.clone(),
+            ".." => self.parent().ok_or(AxError::NotFound)?,
+            _ => self.child(name)?,
+        };
+        match rest {
+            Some(rest) => node.lookup(rest),
+            None => Ok(node),
+        }
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        debug!("create {:?} at overlayfs: {}", ty, path);
+        let path = path.trim_matches('/');
+        let (dir, name) = match path.rsplit_once('/') {
+            Some((dir, name)) => (self.lookup_dir(dir)?, name),
+            None => (self.this(), path),
+        };
+        match name {
+            "" | "." | ".." => Ok(()),
+            _ if name.starts_with(WHITEOUT_PREFIX) => {
+                ax_err!(InvalidInput, "reserved name in overlay")
+            }
+            _ => dir.create_child(name, ty),
+        }
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        debug!("remove at overlayfs: {}", path);
+        let (dir, name) = self.parent_of(path)?;
+        dir.remove_child(name)
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        if !self.ty.is_dir() {
+            return ax_err!(NotADirectory);
+        }
+        let entries = self.entries()?;
+        let entries = entries.get(start_idx..).unwrap_or_default();
+        for (ent, (name, ty)) in dirents.iter_mut().zip(entries) {
+            *ent = VfsDirEntry::new(name, *ty);
+        }
+        Ok(entries.len().min(dirents.len()))
+    }
+
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        debug!("rename at overlayfs: {} -> {}", src_path, dst_path);
+        let (src_dir, src_name) = self.parent_of(src_path)?;
+        let (dst_dir, dst_name) = self.parent_of(dst_path)?;
+        let node = src_dir.child(src_name)?;
+        if node.ty.is_dir() && node.in_lower {
+            return ax_err!(Unsupported, "cannot rename directories of the lower layer");
+        }
+        let dst_path = join_path(&dst_dir.path, dst_name);
+        if dst_path == node.path {
+            return Ok(());
+        }
+        match dst_dir.child(dst_name) {
+            Ok(dst) if dst.ty.is_dir() != node.ty.is_dir() => {
+                return if dst.ty.is_dir() {
+                    ax_err!(IsADirectory)
+                } else {
+                    ax_err!(NotADirectory)
+                };
+            }
+            Ok(_) => dst_dir.remove_child(dst_name)?,
+            Err(AxError::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+        node.copy_up()?;
+        let (dst_upper, whiteout) = dst_dir.prepare_create(dst_name)?;
+        self.layers.upper.rename(&node.path, &dst_path)?;
+        if node.ty.is_dir() && whiteout {
+            Self::make_opaque(&dst_upper, dst_name)?;
+        }
+        if node.in_lower {
+            let src_upper = src_dir.copy_up()?;
+            src_upper.create(&whiteout_name(src_name), VfsNodeType::File)?;
+        }
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+}
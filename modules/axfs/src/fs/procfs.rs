@@ -0,0 +1,316 @@
+//! The process information filesystem mounted at `/proc`.
+//!
+//! All files are generated from the kernel state when they are read:
+//!
+//! - `meminfo`: memory usage of the global allocator.
+//! - `cpuinfo`: the configured CPUs.
+//! - `uptime`: seconds since boot.
+//! - `interrupts`: the number of times each IRQ has occurred (with the `irq`
+//!   feature).
+//! - `mounts`: the mount table.
+//! - `<tid>/{status,stat,comm}`: information of each task, and `self` for the
+//!   current task.
+//! - `sys/vm/overcommit_memory`: a writable parameter.
+//!
+//! Other modules can add files with [`add_file`], [`add_file_rw`] and
+//! [`add_binary_file`], e.g. `net/capture` for the packet capture and
+//! `sys/net/core/somaxconn` for the TCP listen backlog, before or after the
+//! filesystems are initialized.
+
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+use core::fmt::Write;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use axerrno::{ax_err, AxError, AxResult};
+use axfs_vfs::{VfsNodeRef, VfsOps, VfsResult};
+use axsync::Mutex;
+
+use super::pseudo::{PseudoDir, PseudoFile};
+
+const PAGE_SIZE: usize = 0x1000;
+
+static OVERCOMMIT_MEMORY: AtomicUsize = AtomicUsize::new(0);
+
+static PROCFS_ROOT: Mutex<Option<Arc<PseudoDir>>> = Mutex::new(None);
+
+/// The process information filesystem.
+pub struct ProcFileSystem {
+    root: Arc<PseudoDir>,
+}
+
+impl ProcFileSystem {
+    /// Creates a new procfs with all the files.
+    pub fn new() -> Self {
+        Self { root: root() }
+    }
+}
+
+impl VfsOps for ProcFileSystem {
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
+
+impl Default for ProcFileSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn root() -> Arc<PseudoDir> {
+    PROCFS_ROOT
+        .lock()
+        .get_or_insert_with(|| {
+            let root = PseudoDir::new_generated(None, task_entries);
+            add_builtin_files(&root);
+            root
+        })
+        .clone()
+}
+
+fn add_builtin_files(root: &Arc<PseudoDir>) {
+    root.add("meminfo", Arc::new(PseudoFile::new(meminfo)));
+    root.add("cpuinfo", Arc::new(PseudoFile::new(cpuinfo)));
+    root.add("uptime", Arc::new(PseudoFile::new(uptime)));
+    #[cfg(feature = "irq")]
+    root.add("interrupts", Arc::new(PseudoFile::new(interrupts)));
+    root.add(
+        "mounts",
+        Arc::new(PseudoFile::new(|| Ok(crate::root::mount_table()))),
+    );
+
+    root.mkdir("sys")
+        .mkdir("vm")
+        .add("overcommit_memory", param(&OVERCOMMIT_MEMORY));
+}
+
+/// Adds a read-only file at `path` relative to `/proc`, whose content is
+/// generated by `read` each time it is read.
+///
+/// Missing directories are created, and a file at the same path is
+/// replaced.
+pub fn add_file<R>(path: &str, read: R) -> AxResult
+where
+    R: Fn() -> AxResult<String> + Send + Sync + 'static,
+{
+    let (dir, name) = root().parent_of(path, true)?;
+    dir.add(name, Arc::new(PseudoFile::new(read)));
+    Ok(())
+}
+
+/// Adds a read-only file at `path` relative to `/proc` like [`add_file`],
+/// whose content may not be text.
+pub fn add_binary_file<R>(path: &str, read: R) -> AxResult
+where
+    R: Fn() -> AxResult<Vec<u8>> + Send + Sync + 'static,
+{
+    let (dir, name) = root().parent_of(path, true)?;
+    dir.add(name, Arc::new(PseudoFile::new_binary(read)));
+    Ok(())
+}
+
+/// Adds a writable file at `path` relative to `/proc`, like [`add_file`].
+///
+/// Each write to the file is passed to `write` as a string.
+pub fn add_file_rw<R, W>(path: &str, read: R, write: W) -> AxResult
+where
+    R: Fn() -> AxResult<String> + Send + Sync + 'static,
+    W: Fn(&str) -> AxResult + Send + Sync + 'static,
+{
+    let (dir, name) = root().parent_of(path, true)?;
+    dir.add(name, Arc::new(PseudoFile::new_rw(read, write)));
+    Ok(())
+}
+
+/// A writable file holding a number.
+fn param(value: &'static AtomicUsize) -> VfsNodeRef {
+    Arc::new(PseudoFile::new_rw(
+        || Ok(format!("{}\n", value.load(Ordering::Relaxed))),
+        |s| {
+            let v = s.trim().parse().map_err(|_| AxError::InvalidInput)?;
+            value.store(v, Ordering::Relaxed);
+            Ok(())
+        },
+    ))
+}
+
+fn meminfo() -> VfsResult<String> {
+    let allocator = axalloc::global_allocator();
+    let total_pages = allocator.used_pages() + allocator.available_pages();
+    // free bytes in the byte allocator come from the used pages
+    let free = allocator.available_pages() * PAGE_SIZE + allocator.available_bytes();
+    let mut s = String::new();
+    let mut kb = |key: &str, bytes: usize| writeln!(s, "{:<16}{:>8} kB", key, bytes / 1024);
+    kb("MemTotal:", total_pages * PAGE_SIZE).ok();
+    kb("MemFree:", free).ok();
+    kb("MemAvailable:", free).ok();
+    kb("Buffers:", 0).ok();
+    kb("Cached:", 0).ok();
+    kb("SwapTotal:", 0).ok();
+    kb("SwapFree:", 0).ok();
+    kb("HeapUsed:", allocator.used_bytes()).ok();
+    kb("HeapFree:", allocator.available_bytes()).ok();
+    Ok(s)
+}
+
+fn cpuinfo() -> VfsResult<String> {
+    let mut s = String::new();
+    for cpu in 0..axconfig::SMP {
+        writeln!(s, "processor\t: {}", cpu).ok();
+        writeln!(s, "arch\t\t: {}", axconfig::ARCH).ok();
+        writeln!(s, "platform\t: {}", axconfig::PLATFORM).ok();
+        writeln!(s, "timer freq\t: {}", axconfig::TIMER_FREQUENCY).ok();
+        writeln!(s).ok();
+    }
+    Ok(s)
+}
+
+fn uptime() -> VfsResult<String> {
+    let up = axhal::time::monotonic_time();
+    // the idle time is not tracked
+    Ok(format!(
+        "{}.{:02} 0.00\n",
+        up.as_secs(),
+        up.subsec_millis() / 10
+    ))
+}
+
+#[cfg(feature = "irq")]
+fn interrupts() -> VfsResult<String> {
+    let mut s = format!("{:>4}  {:>10}\n", "IRQ", "COUNT");
+    for (irq, count) in axhal::irq::irq_counts() {
+        writeln!(s, "{:>4}: {:>10}", irq, count).ok();
+    }
+    Ok(s)
+}
+
+/// The entries of `/proc` for tasks: a directory for each task named by its
+/// ID, and `self` for the current task.
+fn task_entries(root: &Arc<PseudoDir>) -> Vec<(String, VfsNodeRef)> {
+    let mut entries: Vec<(String, VfsNodeRef)> = task::all_tids()
+        .into_iter()
+        .map(|tid| (format!("{}", tid), task_dir(root, tid)))
+        .collect();
+    entries.push(("self".into(), task_dir(root, task::current_tid())));
+    entries
+}
+
+fn task_dir(root: &Arc<PseudoDir>, tid: u64) -> VfsNodeRef {
+    let dir = PseudoDir::new(Some(root));
+    dir.add(
+        "status",
+        Arc::new(PseudoFile::new(move || task_status(tid))),
+    );
+    dir.add("stat", Arc::new(PseudoFile::new(move || task_stat(tid))));
+    dir.add("comm", Arc::new(PseudoFile::new(move || task_comm(tid))));
+    dir
+}
+
+fn task_status(tid: u64) -> VfsResult<String> {
+    let task = task::info(tid).ok_or(AxError::NotFound)?;
+    let mut s = String::new();
+    writeln!(s, "Name:\t{}", task.name).ok();
+    writeln!(s, "State:\t{} ({})", task.state.0, task.state.1).ok();
+    writeln!(s, "Tgid:\t{}", tid).ok();
+    writeln!(s, "Pid:\t{}", tid).ok();
+    writeln!(s, "PPid:\t0").ok();
+    writeln!(s, "Threads:\t1").ok();
+    let cpus: Vec<_> = task.cpus.iter().map(|cpu| format!("{}", cpu)).collect();
+    writeln!(s, "Cpus_allowed_list:\t{}", cpus.join(",")).ok();
+    if let Some(code) = task.exit_code {
+        writeln!(s, "ExitCode:\t{}", code).ok();
+    }
+    Ok(s)
+}
+
+fn task_stat(tid: u64) -> VfsResult<String> {
+    let task = task::info(tid).ok_or(AxError::NotFound)?;
+    // pid (comm) state ppid pgrp session tty_nr tpgid flags
+    let mut s = format!(
+        "{} ({}) {} 0 {} {} 0 -1 0",
+        tid, task.name, task.state.0, tid, tid
+    );
+    // minflt .. cstime, priority, nice, num_threads, itrealvalue, starttime
+    s += " 0 0 0 0 0 0 0 0 20 0 1 0 0";
+    // vsize and the remaining 29 fields, which are not tracked
+    for _ in 0..30 {
+        s += " 0";
+    }
+    s.push('\n');
+    Ok(s)
+}
+
+fn task_comm(tid: u64) -> VfsResult<String> {
+    match task::info(tid) {
+        Some(task) => Ok(format!("{}\n", task.name)),
+        None => ax_err!(NotFound),
+    }
+}
+
+mod task {
+    use alloc::{string::String, vec::Vec};
+
+    pub struct TaskInfo {
+        pub name: String,
+        /// The state letter and name, as in `/proc/<tid>/status` of Linux.
+        pub state: (char, &'static str),
+        pub cpus: Vec<usize>,
+        pub exit_code: Option<i32>,
+    }
+
+    #[cfg(feature = "multitask")]
+    pub fn all_tids() -> Vec<u64> {
+        axtask::tasks()
+            .iter()
+            .map(|task| task.id().as_u64())
+            .collect()
+    }
+
+    #[cfg(feature = "multitask")]
+    pub fn current_tid() -> u64 {
+        axtask::current().id().as_u64()
+    }
+
+    #[cfg(feature = "multitask")]
+    pub fn info(tid: u64) -> Option<TaskInfo> {
+        use axtask::TaskState;
+        let task = axtask::tasks()
+            .into_iter()
+            .find(|task| task.id().as_u64() == tid)?;
+        let state = match task.state() {
+            _ if task.is_idle() => ('I', "idle"),
+            TaskState::Running | TaskState::Ready => ('R', "running"),
+            TaskState::Blocked => ('S', "sleeping"),
+            TaskState::Exited => ('Z', "zombie"),
+        };
+        let cpumask = task.cpumask();
+        Some(TaskInfo {
+            name: task.name().into(),
+            state,
+            cpus: (0..axconfig::SMP).filter(|&cpu| cpumask.get(cpu)).collect(),
+            exit_code: task.exit_code(),
+        })
+    }
+
+    // Without `multitask`, there is only the main task.
+
+    #[cfg(not(feature = "multitask"))]
+    pub fn all_tids() -> Vec<u64> {
+        alloc::vec![1]
+    }
+
+    #[cfg(not(feature = "multitask"))]
+    pub fn current_tid() -> u64 {
+        1
+    }
+
+    #[cfg(not(feature = "multitask"))]
+    pub fn info(tid: u64) -> Option<TaskInfo> {
+        (tid == 1).then(|| TaskInfo {
+            name: "main".into(),
+            state: ('R', "running"),
+            cpus: (0..axconfig::SMP).collect(),
+            exit_code: None,
+        })
+    }
+}
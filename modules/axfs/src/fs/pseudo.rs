@@ -0,0 +1,307 @@
+//! Nodes of pseudo filesystems (e.g. procfs), which have no backing storage.
+//!
+//! The content of a [`PseudoFile`] is generated by a callback each time it's
+//! read, and writes are passed to another callback. A [`PseudoDir`] holds
+//! fixed entries, and optionally entries generated on each lookup.
+
+use alloc::sync::{Arc, Weak};
+use alloc::{boxed::Box, collections::BTreeMap, string::String, vec::Vec};
+use axerrno::{ax_err, AxError};
+use axfs_vfs::VfsResult;
+use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType};
+use axsync::Mutex;
+
+type ReadFn = Box<dyn Fn() -> VfsResult<Vec<u8>> + Send + Sync>;
+type WriteFn = Box<dyn Fn(&str) -> VfsResult + Send + Sync>;
+type EntriesFn = Box<dyn Fn(&Arc<PseudoDir>) -> Vec<(String, VfsNodeRef)> + Send + Sync>;
+
+/// A file whose content is generated on each read.
+pub struct PseudoFile {
+    read: ReadFn,
+    write: Option<WriteFn>,
+}
+
+impl PseudoFile {
+    /// Creates a read-only file whose content is returned by `read`.
+    pub fn new<R>(read: R) -> Self
+    where
+        R: Fn() -> VfsResult<String> + Send + Sync + 'static,
+    {
+        Self::new_binary(move || read().map(String::into_bytes))
+    }
+
+    /// Creates a read-only file whose content is returned by `read`, which
+    /// may not be text.
+    pub fn new_binary<R>(read: R) -> Self
+    where
+        R: Fn() -> VfsResult<Vec<u8>> + Send + Sync + 'static,
+    {
+        Self {
+            read: Box::new(read),
+            write: None,
+        }
+    }
+
+    /// Creates a writable file whose content is returned by `read`.
+    ///
+    /// Each write is passed to `write` as a whole, regardless of the file
+    /// offset. Data that is not valid UTF-8 is rejected.
+    pub fn new_rw<R, W>(read: R, write: W) -> Self
+    where
+        R: Fn() -> VfsResult<String> + Send + Sync + 'static,
+        W: Fn(&str) -> VfsResult + Send + Sync + 'static,
+    {
+        Self {
+            read: Box::new(move || read().map(String::into_bytes)),
+            write: Some(Box::new(write)),
+        }
+    }
+}
+
+impl VfsNodeOps for PseudoFile {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let perm = if self.write.is_some() { 0o644 } else { 0o444 };
+        // the size is unknown until the content is generated
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(perm),
+            VfsNodeType::File,
+            0,
+            0,
+        ))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let content = (self.read)()?;
+        let start = content.len().min(offset as usize);
+        let end = content.len().min(start + buf.len());
+        let src = &content[start..end];
+        buf[..src.len()].copy_from_slice(src);
+        Ok(src.len())
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let Some(write) = &self.write else {
+            return ax_err!(PermissionDenied);
+        };
+        let Ok(value) = core::str::from_utf8(buf) else {
+            return ax_err!(InvalidInput);
+        };
+        write(value)?;
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        // allow opening with `O_TRUNC` before writing
+        if self.write.is_some() {
+            Ok(())
+        } else {
+            ax_err!(PermissionDenied)
+        }
+    }
+
+    fn fsync(&self) -> VfsResult {
+        Ok(())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+/// A directory of a pseudo filesystem.
+pub struct PseudoDir {
+    this: Weak<PseudoDir>,
+    parent: Option<Weak<PseudoDir>>,
+    entries: Mutex<BTreeMap<String, VfsNodeRef>>,
+    generated: Option<EntriesFn>,
+}
+
+impl PseudoDir {
+    /// Creates an empty directory under `parent`, or a root directory if
+    /// `parent` is `None`.
+    pub fn new(parent: Option<&Arc<PseudoDir>>) -> Arc<Self> {
+        Arc::new_cyclic(|this| Self {
+            this: this.clone(),
+            parent: parent.map(Arc::downgrade),
+            entries: Mutex::new(BTreeMap::new()),
+            generated: None,
+        })
+    }
+
+    /// Creates a directory like [`PseudoDir::new`], which also contains the
+    /// entries returned by `entries`.
+    ///
+    /// `entries` is called with this directory on each lookup and listing,
+    /// so the entries can change over time, e.g. one for each task.
+    #[cfg(feature = "procfs")]
+    pub fn new_generated<F>(parent: Option<&Arc<PseudoDir>>, entries: F) -> Arc<Self>
+    where
+        F: Fn(&Arc<PseudoDir>) -> Vec<(String, VfsNodeRef)> + Send + Sync + 'static,
+    {
+        Arc::new_cyclic(|this| Self {
+            this: this.clone(),
+            parent: parent.map(Arc::downgrade),
+            entries: Mutex::new(BTreeMap::new()),
+            generated: Some(Box::new(entries)),
+        })
+    }
+
+    /// Adds a node to this directory, replacing the one with the same name.
+    pub fn add(&self, name: &str, node: VfsNodeRef) {
+        self.entries.lock().insert(name.into(), node);
+    }
+
+    /// Creates a subdirectory, or returns the existing one. A file with the
+    /// same name is replaced.
+    pub fn mkdir(self: &Arc<Self>, name: &str) -> Arc<PseudoDir> {
+        let mut entries = self.entries.lock();
+        let existing = entries.get(name).and_then(|node| {
+            let dir = node.as_any().downcast_ref::<PseudoDir>()?;
+            dir.this.upgrade()
+        });
+        existing.unwrap_or_else(|| {
+            let dir = Self::new(Some(self));
+            entries.insert(name.into(), dir.clone());
+            dir
+        })
+    }
+
+    /// Adds a node to this directory unless the name is taken. Returns
+    /// whether it's added.
+    #[cfg(feature = "devfs")]
+    pub fn try_add(&self, name: &str, node: VfsNodeRef) -> bool {
+        let mut entries = self.entries.lock();
+        if entries.contains_key(name) {
+            return false;
+        }
+        entries.insert(name.into(), node);
+        true
+    }
+
+    /// Returns the subdirectory named `name`, if it exists.
+    pub fn subdir(&self, name: &str) -> Option<Arc<PseudoDir>> {
+        let entries = self.entries.lock();
+        let dir = entries.get(name)?.as_any().downcast_ref::<PseudoDir>()?;
+        dir.this.upgrade()
+    }
+
+    /// Removes the node named `name` from this directory.
+    #[cfg(any(feature = "sysfs", feature = "devfs"))]
+    pub fn remove_entry(&self, name: &str) -> Option<VfsNodeRef> {
+        self.entries.lock().remove(name)
+    }
+
+    /// Splits `path` relative to this directory into the directory of the
+    /// last component and its name. Missing directories are created if
+    /// `create` is set.
+    pub fn parent_of<'a>(
+        self: &Arc<Self>,
+        path: &'a str,
+        create: bool,
+    ) -> VfsResult<(Arc<PseudoDir>, &'a str)> {
+        let mut comps = path.split('/').filter(|c| !c.is_empty());
+        let Some(name) = comps.next_back() else {
+            return ax_err!(InvalidInput);
+        };
+        if name == "." || name == ".." {
+            return ax_err!(InvalidInput);
+        }
+        let mut dir = self.clone();
+        for c in comps {
+            if c == "." || c == ".." {
+                return ax_err!(InvalidInput);
+            }
+            dir = match dir.subdir(c) {
+                Some(subdir) => subdir,
+                None if create => dir.mkdir(c),
+                None => return ax_err!(NotFound),
+            };
+        }
+        Ok((dir, name))
+    }
+
+    fn all_entries(&self) -> Vec<(String, VfsNodeRef)> {
+        let mut entries: Vec<_> = self
+            .entries
+            .lock()
+            .iter()
+            .map(|(name, node)| (name.clone(), node.clone()))
+            .collect();
+        if let (Some(generated), Some(this)) = (&self.generated, self.this.upgrade()) {
+            entries.extend(generated(&this));
+        }
+        entries
+    }
+
+    fn child(self: &Arc<Self>, name: &str) -> Option<VfsNodeRef> {
+        if let Some(node) = self.entries.lock().get(name) {
+            return Some(node.clone());
+        }
+        let generated = self.generated.as_ref()?;
+        generated(self)
+            .into_iter()
+            .find_map(|(n, node)| (n == name).then_some(node))
+    }
+}
+
+impl VfsNodeOps for PseudoDir {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o555),
+            VfsNodeType::Dir,
+            0,
+            0,
+        ))
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        self.parent
+            .as_ref()?
+            .upgrade()
+            .map(|parent| parent as VfsNodeRef)
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let path = path.trim_start_matches('/');
+        let (name, rest) = match path.find('/') {
+            Some(n) => (&path[..n], Some(&path[n + 1..])),
+            None => (path, None),
+        };
+        let node = match name {
+            "" | "." => self.clone() as VfsNodeRef,
+            ".." => self.parent().unwrap_or_else(|| self.clone()),
+            _ => self.child(name).ok_or(AxError::NotFound)?,
+        };
+        match rest {
+            Some(rest) => node.lookup(rest),
+            None => Ok(node),
+        }
+    }
+
+    fn create(&self, _path: &str, _ty: VfsNodeType) -> VfsResult {
+        // nodes are only added by the kernel
+        ax_err!(PermissionDenied)
+    }
+
+    fn remove(&self, _path: &str) -> VfsResult {
+        ax_err!(PermissionDenied)
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let entries = self.all_entries();
+        let mut iter = entries.iter().skip(start_idx.max(2) - 2);
+        for (i, ent) in dirents.iter_mut().enumerate() {
+            match i + start_idx {
+                0 => *ent = VfsDirEntry::new(".", VfsNodeType::Dir),
+                1 => *ent = VfsDirEntry::new("..", VfsNodeType::Dir),
+                _ => {
+                    let Some((name, node)) = iter.next() else {
+                        return Ok(i);
+                    };
+                    *ent = VfsDirEntry::new(name, node.get_attr()?.file_type());
+                }
+            }
+        }
+        Ok(dirents.len())
+    }
+
+    axfs_vfs::impl_vfs_dir_default! {}
+}
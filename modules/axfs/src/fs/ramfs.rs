@@ -0,0 +1,432 @@
+//! A filesystem in memory, with symbolic and hard links.
+//!
+//! Hard links of a file share its [`FileNode`], and a [`SymlinkNode`] holds
+//! its target. Symlinks are not followed here, but by the path resolution of
+//! the root directory, since their targets may be in other filesystems.
+
+use alloc::sync::{Arc, Weak};
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use axerrno::{ax_err, AxError};
+use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsOps, VfsResult};
+use axsync::Mutex;
+use core::time::Duration;
+
+/// A filesystem that keeps all files in memory, mounted on `/tmp` by
+/// default.
+pub struct RamFileSystem {
+    root: Arc<DirNode>,
+}
+
+impl RamFileSystem {
+    /// Creates an empty filesystem.
+    pub fn new() -> Self {
+        Self {
+            root: DirNode::new(None),
+        }
+    }
+
+    /// Returns the root directory.
+    pub fn root_dir_node(&self) -> Arc<DirNode> {
+        self.root.clone()
+    }
+}
+
+impl Default for RamFileSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VfsOps for RamFileSystem {
+    fn mount(&self, _path: &str, mount_point: VfsNodeRef) -> VfsResult {
+        if let Some(parent) = mount_point.parent() {
+            *self.root.parent.lock() = Arc::downgrade(&parent);
+        }
+        Ok(())
+    }
+
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
+
+/// The access, modification and change times of a node.
+#[derive(Clone, Copy)]
+struct Times([Duration; 3]);
+
+impl Times {
+    fn now() -> Self {
+        Self([axhal::time::wall_time(); 3])
+    }
+
+    /// Updates the times after the content is modified.
+    fn modify(&mut self) {
+        let now = axhal::time::wall_time();
+        self.0[1] = now;
+        self.0[2] = now;
+    }
+}
+
+/// A regular file, which may have multiple links.
+pub struct FileNode {
+    content: Mutex<Vec<u8>>,
+    times: Mutex<Times>,
+}
+
+/// A symbolic link.
+pub struct SymlinkNode {
+    target: String,
+    times: Mutex<Times>,
+}
+
+/// A directory.
+pub struct DirNode {
+    this: Weak<DirNode>,
+    parent: Mutex<Weak<dyn VfsNodeOps>>,
+    children: Mutex<BTreeMap<String, VfsNodeRef>>,
+    times: Mutex<Times>,
+}
+
+macro_rules! impl_node_times {
+    ($($ty:ty),*) => {$(
+        impl $ty {
+            /// Returns the access, modification and change times.
+            pub fn times(&self) -> [Duration; 3] {
+                self.times.lock().0
+            }
+
+            /// Sets the access and modification times, leaving those of
+            /// `None` unchanged, and the change time to now.
+            pub fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) {
+                let mut times = self.times.lock();
+                if let Some(atime) = atime {
+                    times.0[0] = atime;
+                }
+                if let Some(mtime) = mtime {
+                    times.0[1] = mtime;
+                }
+                times.0[2] = axhal::time::wall_time();
+            }
+        }
+    )*};
+}
+
+impl_node_times!(FileNode, SymlinkNode, DirNode);
+
+impl FileNode {
+    fn new() -> Self {
+        Self {
+            content: Mutex::new(Vec::new()),
+            times: Mutex::new(Times::now()),
+        }
+    }
+}
+
+impl VfsNodeOps for FileNode {
+    axfs_vfs::impl_vfs_non_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let size = self.content.lock().len() as u64;
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o644),
+            VfsNodeType::File,
+            size,
+            size.div_ceil(512),
+        ))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let content = self.content.lock();
+        let start = (offset as usize).min(content.len());
+        let len = buf.len().min(content.len() - start);
+        buf[..len].copy_from_slice(&content[start..start + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let offset = offset as usize;
+        let mut content = self.content.lock();
+        if content.len() < offset + buf.len() {
+            content.resize(offset + buf.len(), 0);
+        }
+        content[offset..offset + buf.len()].copy_from_slice(buf);
+        self.times.lock().modify();
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        self.content.lock().resize(size as usize, 0);
+        self.times.lock().modify();
+        Ok(())
+    }
+}
+
+impl SymlinkNode {
+    /// Returns the target of the symlink.
+    pub fn read_link(&self) -> &str {
+        &self.target
+    }
+}
+
+impl VfsNodeOps for SymlinkNode {
+    axfs_vfs::impl_vfs_non_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o777),
+            VfsNodeType::SymLink,
+            self.target.len() as u64,
+            0,
+        ))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let target = self.target.as_bytes();
+        let start = (offset as usize).min(target.len());
+        let len = buf.len().min(target.len() - start);
+        buf[..len].copy_from_slice(&target[start..start + len]);
+        Ok(len)
+    }
+}
+
+/// Splits `path` into its first component and the rest, if any.
+fn split_path(path: &str) -> (&str, Option<&str>) {
+    let path = path.trim_start_matches('/');
+    match path.find('/') {
+        Some(n) => (&path[..n], Some(&path[n + 1..]).filter(|r| !r.is_empty())),
+        None => (path, None),
+    }
+}
+
+impl DirNode {
+    fn new(parent: Option<Weak<dyn VfsNodeOps>>) -> Arc<Self> {
+        Arc::new_cyclic(|this| Self {
+            this: this.clone(),
+            parent: Mutex::new(parent.unwrap_or_else(|| Weak::<Self>::new())),
+            children: Mutex::new(BTreeMap::new()),
+            times: Mutex::new(Times::now()),
+        })
+    }
+
+    fn this(&self) -> Arc<Self> {
+        self.this.upgrade().unwrap()
+    }
+
+    /// Returns the directory of the last component of `path` and its name.
+    fn parent_of<'a>(&self, path: &'a str) -> VfsResult<(Arc<DirNode>, &'a str)> {
+        let path = path.trim_matches('/');
+        let (dir, name) = match path.rsplit_once('/') {
+            Some((dir, name)) => (self.this().lookup(dir)?, name),
+            None => (self.this() as VfsNodeRef, path),
+        };
+        match dir.as_any().downcast_ref::<DirNode>() {
+            Some(dir) => Ok((dir.this(), name)),
+            None => ax_err!(NotADirectory),
+        }
+    }
+
+    /// Whether this directory is `dir` or one of its ancestors.
+    fn is_ancestor_of(&self, dir: &DirNode) -> bool {
+        let mut cur = dir.this();
+        loop {
+            if core::ptr::eq(Arc::as_ptr(&cur), self) {
+                return true;
+            }
+            let Some(parent) = cur.parent.lock().upgrade() else {
+                return false;
+            };
+            match parent.as_any().downcast_ref::<DirNode>() {
+                Some(parent) => cur = parent.this(),
+                None => return false, // the parent of the mount point
+            }
+        }
+    }
+
+    /// Adds `node` as `name` if the name is not taken.
+    fn add(&self, name: &str, node: VfsNodeRef) -> VfsResult {
+        if name.is_empty() || name == "." || name == ".." {
+            return ax_err!(AlreadyExists);
+        }
+        let mut children = self.children.lock();
+        if children.contains_key(name) {
+            return ax_err!(AlreadyExists);
+        }
+        children.insert(name.into(), node);
+        self.times.lock().modify();
+        Ok(())
+    }
+
+    /// Creates a symlink at `path` pointing to `target`.
+    pub fn symlink(&self, path: &str, target: &str) -> VfsResult {
+        debug!("symlink at ramfs: {} -> {}", path, target);
+        if target.is_empty() {
+            return ax_err!(InvalidInput);
+        }
+        let (dir, name) = self.parent_of(path)?;
+        let node = Arc::new(SymlinkNode {
+            target: target.into(),
+            times: Mutex::new(Times::now()),
+        });
+        dir.add(name, node)
+    }
+
+    /// Creates a hard link at `new_path` to the file or symlink at
+    /// `old_path`.
+    pub fn link(&self, old_path: &str, new_path: &str) -> VfsResult {
+        debug!("link at ramfs: {} -> {}", new_path, old_path);
+        let (old_dir, old_name) = self.parent_of(old_path)?;
+        let node = match old_dir.children.lock().get(old_name) {
+            Some(node) => node.clone(),
+            None => return ax_err!(NotFound),
+        };
+        if node.get_attr()?.is_dir() {
+            return ax_err!(PermissionDenied);
+        }
+        let (dir, name) = self.parent_of(new_path)?;
+        dir.add(name, node)
+    }
+}
+
+impl VfsNodeOps for DirNode {
+    axfs_vfs::impl_vfs_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o755),
+            VfsNodeType::Dir,
+            4096,
+            0,
+        ))
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        self.parent.lock().upgrade()
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        debug!("lookup at ramfs: {}", path);
+        let (name, rest) = split_path(path);
+        let node = match name {
+            "" | "." => self.clone() as VfsNodeRef,
+            ".." => self.parent().ok_or(AxError::NotFound)?,
+            _ => self
+                .children
+                .lock()
+                .get(name)
+                .cloned()
+                .ok_or(AxError::NotFound)?,
+        };
+        match rest {
+            Some(rest) => node.lookup(rest),
+            None => Ok(node),
+        }
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        debug!("create {:?} at ramfs: {}", ty, path);
+        let (dir, name) = self.parent_of(path)?;
+        if name.is_empty() || name == "." || name == ".." {
+            return Ok(());
+        }
+        if let Some(node) = dir.children.lock().get(name) {
+            // like other filesystems, creating an existing node of the same
+            // type succeeds
+            return if node.get_attr()?.file_type() == ty {
+                Ok(())
+            } else {
+                ax_err!(AlreadyExists)
+            };
+        }
+        let node: VfsNodeRef = match ty {
+            VfsNodeType::File => Arc::new(FileNode::new()),
+            VfsNodeType::Dir => {
+                let parent: Weak<dyn VfsNodeOps> = dir.this.clone();
+                DirNode::new(Some(parent))
+            }
+            _ => return ax_err!(Unsupported),
+        };
+        dir.add(name, node)
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        debug!("remove at ramfs: {}", path);
+        let (dir, name) = self.parent_of(path)?;
+        if name.is_empty() || name == "." || name == ".." {
+            return ax_err!(InvalidInput);
+        }
+        let mut children = dir.children.lock();
+        let Some(node) = children.get(name) else {
+            return ax_err!(NotFound);
+        };
+        if let Some(subdir) = node.as_any().downcast_ref::<DirNode>() {
+            if !subdir.children.lock().is_empty() {
+                return ax_err!(DirectoryNotEmpty);
+            }
+        }
+        children.remove(name);
+        dir.times.lock().modify();
+        Ok(())
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let children = self.children.lock();
+        let mut iter = children.iter().skip(start_idx.max(2) - 2);
+        for (i, ent) in dirents.iter_mut().enumerate() {
+            match i + start_idx {
+                0 => *ent = VfsDirEntry::new(".", VfsNodeType::Dir),
+                1 => *ent = VfsDirEntry::new("..", VfsNodeType::Dir),
+                _ => {
+                    let Some((name, node)) = iter.next() else {
+                        return Ok(i);
+                    };
+                    *ent = VfsDirEntry::new(name, node.get_attr()?.file_type());
+                }
+            }
+        }
+        Ok(dirents.len())
+    }
+
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        debug!("rename at ramfs: {} -> {}", src_path, dst_path);
+        let (src_dir, src_name) = self.parent_of(src_path)?;
+        let (dst_dir, dst_name) = self.parent_of(dst_path)?;
+        for name in [src_name, dst_name] {
+            if name.is_empty() || name == "." || name == ".." {
+                return ax_err!(InvalidInput);
+            }
+        }
+        let Some(node) = src_dir.children.lock().get(src_name).cloned() else {
+            return ax_err!(NotFound);
+        };
+        let moved_dir = node.as_any().downcast_ref::<DirNode>();
+        if let Some(dir) = moved_dir {
+            if dir.is_ancestor_of(&dst_dir) {
+                return ax_err!(InvalidInput, "cannot move a directory into itself");
+            }
+        }
+        if let Some(old) = dst_dir.children.lock().get(dst_name) {
+            if Arc::ptr_eq(old, &node) {
+                return Ok(());
+            }
+            match (old.as_any().downcast_ref::<DirNode>(), moved_dir) {
+                (Some(old), Some(_)) if !old.children.lock().is_empty() => {
+                    return ax_err!(DirectoryNotEmpty);
+                }
+                (Some(_), None) => return ax_err!(IsADirectory),
+                (None, Some(_)) => return ax_err!(NotADirectory),
+                _ => {}
+            }
+        }
+
+        src_dir.children.lock().remove(src_name);
+        if let Some(dir) = moved_dir {
+            let parent: Weak<dyn VfsNodeOps> = dst_dir.this.clone();
+            *dir.parent.lock() = parent;
+        }
+        dst_dir.children.lock().insert(dst_name.into(), node);
+        src_dir.times.lock().modify();
+        dst_dir.times.lock().modify();
+        Ok(())
+    }
+}
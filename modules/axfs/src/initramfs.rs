@@ -0,0 +1,319 @@
+//! Unpacking of the initramfs, an archive that becomes the root filesystem in
+//! ramfs.
+//!
+//! Uncompressed archives of the "newc" cpio format (as used by Linux) and of
+//! the ustar format (with GNU or pax long names) are supported. Directories,
+//! regular files, symlinks and hard links are unpacked with their
+//! modification times, while device nodes and FIFOs are skipped, and ramfs
+//! doesn't keep permissions or owners.
+
+use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
+use core::time::Duration;
+
+use axerrno::{ax_err, ax_err_type, AxResult};
+use axfs_vfs::{VfsNodeRef, VfsNodeType};
+
+use crate::fs;
+
+/// The archive embedded at build time from the path in the `AX_INITRAMFS`
+/// environment variable, empty if there is none.
+pub(crate) static EMBEDDED: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/initramfs.img"));
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+
+const CPIO_HEADER_SIZE: usize = 110;
+const CPIO_TRAILER: &str = "TRAILER!!!";
+const TAR_BLOCK_SIZE: usize = 512;
+
+/// The type and content of an archive entry.
+enum Kind<'a> {
+    Dir,
+    File(&'a [u8]),
+    Symlink(&'a str),
+    /// A hard link to the entry at the path.
+    HardLink(&'a str),
+    /// Device nodes, FIFOs, ...
+    Other,
+}
+
+/// Unpacks `archive` into the directory `root`, detecting its format.
+pub(crate) fn unpack(archive: &[u8], root: &VfsNodeRef) -> AxResult {
+    let mut unpacker = Unpacker {
+        root,
+        dir_times: Vec::new(),
+        cpio_links: BTreeMap::new(),
+    };
+    if archive.starts_with(b"070701") || archive.starts_with(b"070702") {
+        unpacker.unpack_cpio(archive)?;
+    } else if archive.get(257..262) == Some(b"ustar") {
+        unpacker.unpack_tar(archive)?;
+    } else if archive.starts_with(&[0x1f, 0x8b]) {
+        return ax_err!(Unsupported, "compressed initramfs is not supported");
+    } else {
+        return ax_err!(InvalidData, "unknown initramfs format");
+    }
+    // directories are modified by adding their entries, so their times are
+    // set at last
+    for (path, mtime) in unpacker.dir_times.iter().rev() {
+        let node = root.clone().lookup(path)?;
+        fs::set_times(&node, Some(*mtime), Some(*mtime))?;
+    }
+    Ok(())
+}
+
+struct Unpacker<'a> {
+    root: &'a VfsNodeRef,
+    /// Modification times of unpacked directories.
+    dir_times: Vec<(String, Duration)>,
+    /// The first path of each cpio inode with multiple links, by the device
+    /// and inode numbers.
+    cpio_links: BTreeMap<(u32, u32, u32), String>,
+}
+
+impl Unpacker<'_> {
+    fn unpack_cpio(&mut self, mut data: &[u8]) -> AxResult {
+        loop {
+            if data.len() < CPIO_HEADER_SIZE {
+                return ax_err!(InvalidData, "truncated cpio header");
+            }
+            if !data.starts_with(b"070701") && !data.starts_with(b"070702") {
+                return ax_err!(InvalidData, "bad cpio magic");
+            }
+            let field = |i: usize| parse_hex(&data[6 + i * 8..14 + i * 8]);
+            let (ino, mode, nlink, mtime) = (field(0)?, field(1)?, field(4)?, field(5)?);
+            let (dev, file_size, name_size) = ((field(7)?, field(8)?), field(6)?, field(11)?);
+
+            let name_end = CPIO_HEADER_SIZE + name_size as usize;
+            let data_start = name_end.next_multiple_of(4);
+            let data_end = data_start + file_size as usize;
+            if name_size == 0 || data_end > data.len() {
+                return ax_err!(InvalidData, "truncated cpio entry");
+            }
+            let name = parse_str(&data[CPIO_HEADER_SIZE..name_end])?;
+            if name == CPIO_TRAILER {
+                return Ok(());
+            }
+            let content = &data[data_start..data_end];
+            let mtime = Duration::from_secs(mtime as u64);
+
+            let result = match mode & S_IFMT {
+                S_IFREG if nlink > 1 => {
+                    // the data is kept by the last link only
+                    match self.cpio_links.get(&(dev.0, dev.1, ino)).cloned() {
+                        Some(first) => self
+                            .add(name, Kind::HardLink(&first), mtime)
+                            .and_then(|_| self.write_file(name, content)),
+                        None => {
+                            self.cpio_links.insert((dev.0, dev.1, ino), name.into());
+                            self.add(name, Kind::File(content), mtime)
+                        }
+                    }
+                }
+                S_IFREG => self.add(name, Kind::File(content), mtime),
+                S_IFDIR => self.add(name, Kind::Dir, mtime),
+                S_IFLNK => self.add(name, Kind::Symlink(parse_str(content)?), mtime),
+                _ => self.add(name, Kind::Other, mtime),
+            };
+            if let Err(e) = result {
+                warn!("initramfs: cannot unpack {:?}: {:?}", name, e);
+            }
+            data = &data[data_end.next_multiple_of(4).min(data.len())..];
+        }
+    }
+
+    fn unpack_tar(&mut self, mut data: &[u8]) -> AxResult {
+        let mut long_name = None;
+        let mut long_link = None;
+        while data.len() >= TAR_BLOCK_SIZE {
+            let (header, rest) = data.split_at(TAR_BLOCK_SIZE);
+            if header.iter().all(|&b| b == 0) {
+                return Ok(()); // end of archive
+            }
+            let checksum: u64 = header
+                .iter()
+                .enumerate()
+                .map(|(i, &b)| if (148..156).contains(&i) { b' ' } else { b } as u64)
+                .sum();
+            if parse_octal(&header[148..156])? != checksum {
+                return ax_err!(InvalidData, "bad tar header checksum");
+            }
+            let size = parse_octal(&header[124..136])? as usize;
+            let mtime = Duration::from_secs(parse_octal(&header[136..148])?);
+            if size > rest.len() {
+                return ax_err!(InvalidData, "truncated tar entry");
+            }
+            let content = &rest[..size];
+            data = &rest[size.next_multiple_of(TAR_BLOCK_SIZE).min(rest.len())..];
+
+            match header[156] {
+                // GNU long names
+                b'L' => long_name = Some(parse_cstr(content)?.into()),
+                b'K' => long_link = Some(parse_cstr(content)?.into()),
+                b'x' => {
+                    for (key, value) in parse_pax(content)? {
+                        match key {
+                            "path" => long_name = Some(value.into()),
+                            "linkpath" => long_link = Some(value.into()),
+                            _ => {}
+                        }
+                    }
+                }
+                b'g' => {} // global pax header
+                ty => {
+                    let name: String = match long_name.take() {
+                        Some(name) => name,
+                        None => tar_name(header)?,
+                    };
+                    let link: String = match long_link.take() {
+                        Some(link) => link,
+                        None => parse_cstr(&header[157..257])?.into(),
+                    };
+                    let kind = match ty {
+                        b'0' | b'\0' | b'7' => Kind::File(content),
+                        b'1' => Kind::HardLink(&link),
+                        b'2' => Kind::Symlink(&link),
+                        b'5' => Kind::Dir,
+                        _ => Kind::Other,
+                    };
+                    if let Err(e) = self.add(&name, kind, mtime) {
+                        warn!("initramfs: cannot unpack {:?}: {:?}", name, e);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the entry at `path` of `kind`, and the directories of `path` if
+    /// they don't exist.
+    fn add(&mut self, path: &str, kind: Kind, mtime: Duration) -> AxResult {
+        let Some(path) = normalize(path)? else {
+            return Ok(()); // the root directory
+        };
+        if let Kind::Other = kind {
+            warn!("initramfs: skip {:?} of unsupported type", path);
+            return Ok(());
+        }
+        for (i, _) in path.match_indices('/') {
+            self.root.create(&path[..i], VfsNodeType::Dir)?;
+        }
+        match kind {
+            Kind::Dir => {
+                self.root.create(&path, VfsNodeType::Dir)?;
+                self.dir_times.push((path, mtime));
+                return Ok(());
+            }
+            Kind::File(content) => {
+                self.root.create(&path, VfsNodeType::File)?;
+                self.write_file(&path, content)?;
+            }
+            Kind::Symlink(target) => fs::symlink(self.root, &path, target)?,
+            Kind::HardLink(target) => {
+                let Some(target) = normalize(target)? else {
+                    return ax_err!(InvalidInput, "cannot link a directory");
+                };
+                fs::link(self.root, &target, &path)?;
+            }
+            Kind::Other => unreachable!(),
+        }
+        let node = self.root.clone().lookup(&path)?;
+        fs::set_times(&node, Some(mtime), Some(mtime))
+    }
+
+    fn write_file(&self, path: &str, content: &[u8]) -> AxResult {
+        if content.is_empty() {
+            return Ok(());
+        }
+        let Some(path) = normalize(path)? else {
+            return ax_err!(IsADirectory);
+        };
+        let node = self.root.clone().lookup(&path)?;
+        node.truncate(0)?;
+        let mut offset = 0;
+        while offset < content.len() {
+            match node.write_at(offset as u64, &content[offset..])? {
+                0 => return ax_err!(WriteZero),
+                n => offset += n,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns `path` relative to the root without `.` components, or `None` for
+/// the root itself. Paths with `..` are rejected.
+fn normalize(path: &str) -> AxResult<Option<String>> {
+    let mut comps = Vec::new();
+    for comp in path.split('/') {
+        match comp {
+            "" | "." => {}
+            ".." => return ax_err!(InvalidInput, "`..` in initramfs path"),
+            _ => comps.push(comp),
+        }
+    }
+    Ok((!comps.is_empty()).then(|| comps.join("/")))
+}
+
+/// Returns the name of a ustar entry, which may have a prefix.
+fn tar_name(header: &[u8]) -> AxResult<String> {
+    let name = parse_cstr(&header[..100])?;
+    // the prefix field is used differently by old GNU tar
+    if &header[257..263] == b"ustar\0" {
+        let prefix = parse_cstr(&header[345..500])?;
+        if !prefix.is_empty() {
+            return Ok(format!("{}/{}", prefix, name));
+        }
+    }
+    Ok(name.into())
+}
+
+/// Parses the records of a pax extended header, as `<len> <key>=<value>\n`.
+fn parse_pax(mut data: &[u8]) -> AxResult<Vec<(&str, &str)>> {
+    let mut records = Vec::new();
+    while !data.is_empty() {
+        let bad_record = || ax_err_type!(InvalidData, "bad pax record");
+        let space = data
+            .iter()
+            .position(|&b| b == b' ')
+            .ok_or_else(bad_record)?;
+        let len: usize = parse_str(&data[..space])?
+            .parse()
+            .map_err(|_| bad_record())?;
+        if len <= space + 1 || len > data.len() || data[len - 1] != b'\n' {
+            return Err(bad_record());
+        }
+        let record = parse_str(&data[space + 1..len - 1])?;
+        records.push(record.split_once('=').ok_or_else(bad_record)?);
+        data = &data[len..];
+    }
+    Ok(records)
+}
+
+fn parse_str(bytes: &[u8]) -> AxResult<&str> {
+    let bytes = bytes.strip_suffix(b"\0").unwrap_or(bytes);
+    core::str::from_utf8(bytes).map_err(|_| ax_err_type!(InvalidData, "non-UTF-8 name"))
+}
+
+/// Parses a NUL-terminated string of a fixed-size field.
+fn parse_cstr(bytes: &[u8]) -> AxResult<&str> {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    parse_str(&bytes[..len])
+}
+
+fn parse_hex(field: &[u8]) -> AxResult<u32> {
+    parse_str(field)
+        .ok()
+        .and_then(|s| u32::from_str_radix(s, 16).ok())
+        .ok_or_else(|| ax_err_type!(InvalidData, "bad cpio header field"))
+}
+
+fn parse_octal(field: &[u8]) -> AxResult<u64> {
+    let s = parse_cstr(field)?.trim_matches(' ');
+    if s.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(s, 8).map_err(|_| ax_err_type!(InvalidData, "bad tar header field"))
+}
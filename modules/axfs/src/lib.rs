@@ -4,11 +4,28 @@
 //!
 //! # Cargo Features
 //!
-//! - `fatfs`: Use [FAT] as the main filesystem and mount it on `/`. This feature
-//!    is **enabled** by default.
-//! - `devfs`: Mount [`axfs_devfs::DeviceFileSystem`] on `/dev`. This feature is
+//! - `fatfs`: Support [FAT] volumes. This feature is **enabled** by default.
+//! - `ext4fs`: Support [ext2/3/4][ext4] volumes. This feature is **disabled**
+//!    by default. If both are enabled, the filesystem of each volume is
+//!    detected, and a root volume with no known filesystem is used as ext4.
+//! - `devfs`: Mount a filesystem of device nodes on `/dev`, which are
+//!    registered by drivers with the [`devfs`] module. This feature is
 //!    **enabled** by default.
-//! - `ramfs`: Mount [`axfs_ramfs::RamFileSystem`] on `/tmp`. This feature is
+//! - `ramfs`: Mount a filesystem in memory on `/tmp`, which supports symbolic
+//!    and hard links. This feature is **enabled** by default.
+//! - `overlayfs`: Support overlay filesystems, which merge a writable upper
+//!    layer over a read-only lower layer, see [Overlays](#overlays). This
+//!    feature is **enabled** by default.
+//! - `initramfs`: Allow a ramfs unpacked from an archive to be mounted on `/`,
+//!    see [Initramfs](#initramfs). This feature is **disabled** by default.
+//! - `procfs`: Mount a process information filesystem on `/proc`, whose files
+//!    (`meminfo`, `uptime`, `mounts`, ...) are generated from the kernel
+//!    state. This feature is **enabled** by default. With the `multitask`
+//!    feature, it has a directory for each task, and with the `irq` feature,
+//!    it has `/proc/interrupts`. Other modules can add files with the
+//!    [`procfs`] module.
+//! - `sysfs`: Mount a filesystem of device attributes on `/sys`, which are
+//!    published by drivers with the [`sysfs`] module. This feature is
 //!    **enabled** by default.
 //! - `myfs`: Allow users to define their custom filesystems to override the
 //!    default. In this case, [`MyFileSystemIf`] is required to be implemented
@@ -16,7 +33,74 @@
 //!    by default, but it will override other filesystem selection features if
 //!    both are enabled.
 //!
+//! # Volumes
+//!
+//! Each block device and each partition of it (MBR or GPT) is a volume. The
+//! root volume is selected by the `AX_ROOT_DEV` environment variable at build
+//! time (e.g. `LABEL=rootfs`), and other volumes can be mounted at the paths
+//! given by `AX_MOUNTS` (e.g. `blk0p1:/boot`). See [`init_filesystems_with`]
+//! for details. Filesystems can also be mounted and unmounted at runtime with
+//! [`api::mount`] and [`api::umount`], and the mount table is shown in
+//! `/proc/mounts`.
+//!
+//! # Overlays
+//!
+//! With the `overlayfs` feature, a directory can be merged over another one
+//! with [`api::mount_overlay`], and the root filesystem can be put under an
+//! overlay by the `AX_ROOT_OVERLAY` environment variable at build time, which
+//! is `ramfs` to keep changes in memory, or a volume to persist them (e.g.
+//! `LABEL=overlay`). Changed files are copied up to the upper layer, removed
+//! ones are hidden by whiteouts (`.wh.<name>` files in the upper layer), and
+//! directories of the lower layer can't be renamed.
+//!
+//! # Initramfs
+//!
+//! With the `initramfs` feature, a cpio ("newc") or tar archive can be given
+//! by the absolute path in the `AX_INITRAMFS` environment variable at build
+//! time, which is embedded in the kernel, or loaded by the bootloader and
+//! passed to [`init_filesystems_with_initramfs`], as `axruntime` does with the
+//! initrd in the `/chosen` node of the device tree. The archive is unpacked to a ramfs
+//! mounted on `/` if `AX_ROOT_DEV` is `initramfs`, or if it's empty and there
+//! is an archive or no block device. Other volumes can still be mounted by
+//! `AX_MOUNTS`.
+//!
+//! # Block cache
+//!
+//! Blocks of each device are cached in memory, with up to 1024 blocks per
+//! device evicted in LRU order. Sequential reads are read ahead, and writes
+//! are kept in the cache until the blocks are evicted, the file is flushed
+//! (`fsync`), the filesystem is unmounted, or [`api::sync`] is called. The
+//! effect of the cache is shown by [`cache_stats`].
+//!
+//! # Links
+//!
+//! Symbolic links are followed in all paths, across mounted filesystems,
+//! with up to 40 links in a path. Symlinks and hard links can be created in
+//! ext4 and ramfs, see [`api::symlink`] and [`api::hard_link`].
+//!
+//! # Timestamps
+//!
+//! Files in ext4, FAT and ramfs have access, modification and change times
+//! from the wall-clock time of `axhal`, see [`fops::FileAttr`] and
+//! [`api::set_times`]. FAT keeps local times without a time zone, which are
+//! taken as UTC, only the date of the last access, and no change time.
+//!
+//! # Locks
+//!
+//! Opened files can take advisory locks on the whole file (like `flock`), or
+//! on byte ranges for an owner (like `fcntl` record locks), through their
+//! [`fops::LockHandle`]. Locks are tracked per file, by the
+//! inode in ext4 and by the path in FAT. Waiting for a lock blocks the task
+//! with the `multitask` feature, and fails as a deadlock otherwise.
+//!
+//! # Watches
+//!
+//! A [`fops::Watcher`] queues events when files or directories at watched
+//! paths, or entries in watched directories, are created, modified, removed
+//! or renamed, like inotify.
+//!
 //! [FAT]: https://en.wikipedia.org/wiki/File_Allocation_Table
+//! [ext4]: https://en.wikipedia.org/wiki/Ext4
 //! [`MyFileSystemIf`]: fops::MyFileSystemIf
 
 #![cfg_attr(all(not(test), not(doc)), no_std)]
@@ -26,21 +110,106 @@
 extern crate log;
 extern crate alloc;
 
+mod cache;
 mod dev;
 mod fs;
+#[cfg(feature = "initramfs")]
+mod initramfs;
+mod lock;
 mod mounts;
+mod partition;
 mod root;
+mod volumes;
+mod watch;
 
 pub mod api;
+#[cfg(feature = "devfs")]
+pub mod devfs;
 pub mod fops;
+#[cfg(feature = "sysfs")]
+pub mod sysfs;
+
+pub use self::cache::CacheStats;
+pub use self::partition::FsType;
+pub use self::volumes::{cache_stats, volumes, VolumeInfo};
+
+#[cfg(feature = "procfs")]
+pub use self::fs::procfs;
 
 use axdriver::{prelude::*, AxDeviceContainer};
 
+macro_rules! env_or_default {
+    ($key:literal) => {
+        match option_env!($key) {
+            Some(val) => val,
+            None => "",
+        }
+    };
+}
+
+const ROOT_DEV: &str = env_or_default!("AX_ROOT_DEV");
+const MOUNTS: &str = env_or_default!("AX_MOUNTS");
+const ROOT_OVERLAY: &str = env_or_default!("AX_ROOT_OVERLAY");
+
 /// Initializes filesystems by block devices.
-pub fn init_filesystems(mut blk_devs: AxDeviceContainer<AxBlockDevice>) {
+///
+/// The root volume and other volumes to mount are given by the `AX_ROOT_DEV`
+/// and `AX_MOUNTS` environment variables at build time, see
+/// [`init_filesystems_with`].
+pub fn init_filesystems(blk_devs: AxDeviceContainer<AxBlockDevice>) {
+    init_filesystems_with(blk_devs, ROOT_DEV, MOUNTS);
+}
+
+/// Initializes filesystems by block devices, mounting the volume selected by
+/// `root` on `/`, and other volumes at the paths given by `mounts`.
+///
+/// A volume is a block device or a partition of it (MBR or GPT), selected by
+/// its name (`blk0`, `blk0p1`, ...), or by `LABEL=<label>`, `UUID=<uuid>`,
+/// `PARTLABEL=<name>` or `PARTUUID=<uuid>`. If `root` is empty, the first
+/// volume with a supported filesystem is used. `mounts` is a comma-separated
+/// list of `<volume>:<path>`, e.g. `blk0p1:/boot,LABEL=data:/mnt/sd1`.
+///
+/// With the `initramfs` feature, `root` can also be `initramfs`, and an empty
+/// `root` selects the initramfs if it's embedded or there is no volume.
+pub fn init_filesystems_with(blk_devs: AxDeviceContainer<AxBlockDevice>, root: &str, mounts: &str) {
+    #[cfg(feature = "initramfs")]
+    init(blk_devs, root, mounts, self::initramfs::EMBEDDED);
+    #[cfg(not(feature = "initramfs"))]
+    init(blk_devs, root, mounts, &[]);
+}
+
+/// Initializes filesystems by block devices like [`init_filesystems`], with
+/// `archive` as the initramfs instead of the embedded one, e.g. an archive
+/// loaded by the bootloader.
+#[cfg(feature = "initramfs")]
+pub fn init_filesystems_with_initramfs(blk_devs: AxDeviceContainer<AxBlockDevice>, archive: &[u8]) {
+    init(blk_devs, ROOT_DEV, MOUNTS, archive);
+}
+
+fn init(blk_devs: AxDeviceContainer<AxBlockDevice>, root: &str, mounts: &str, initramfs: &[u8]) {
     info!("Initialize filesystems...");
 
-    let dev = blk_devs.take_one().expect("No block device found!");
-    info!("  use block device 0: {:?}", dev.device_name());
-    self::root::init_rootfs(self::dev::Disk::new(dev));
+    let volumes = self::volumes::scan(blk_devs);
+    #[cfg(feature = "devfs")]
+    self::devfs::add_volumes(volumes);
+    let use_initramfs = cfg!(feature = "initramfs")
+        && (root == "initramfs"
+            || (root.is_empty() && (!initramfs.is_empty() || volumes.is_empty())));
+    if use_initramfs {
+        #[cfg(feature = "initramfs")]
+        self::root::init_initramfs(initramfs, ROOT_OVERLAY);
+    } else {
+        let root_idx = match self::volumes::select_root(volumes, root) {
+            Some(idx) => idx,
+            None if volumes.is_empty() => panic!("No block device found!"),
+            None => panic!("Root volume {:?} not found!", root),
+        };
+        self::root::init_rootfs(&volumes[root_idx], ROOT_OVERLAY);
+    }
+    for (spec, path) in self::volumes::parse_mounts(mounts) {
+        match self::root::mount(spec, path, "auto") {
+            Ok(()) => info!("  mount {} on {}", spec, path),
+            Err(e) => warn!("cannot mount {} on {}: {:?}", spec, path, e),
+        }
+    }
 }
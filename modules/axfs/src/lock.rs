@@ -0,0 +1,323 @@
+//! Advisory locks of files, on whole files (like `flock`) and on byte ranges
+//! (like `fcntl` record locks).
+//!
+//! Locks are tracked per file, see [`FileId`], and the two kinds don't
+//! conflict with each other. A whole-file lock belongs to an opened
+//! [`File`](crate::fops::File) and is released when it's closed, while
+//! byte-range locks belong to owners chosen by the caller, such as tasks, and
+//! are released when the last opened file of the file is closed.
+//! Waiting for a byte-range lock fails if the owners would wait for each
+//! other forever.
+//!
+//! The table of locks also counts the opened files of each file, so that a
+//! written file is flushed when the last of them is closed.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use axerrno::{ax_err, AxResult};
+use axsync::spin::SpinNoIrq;
+
+/// The kind of a lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockKind {
+    /// A shared (read) lock, which may be held by many owners at once.
+    Shared,
+    /// An exclusive (write) lock.
+    Exclusive,
+}
+
+/// A lock on a range of bytes of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RangeLock {
+    /// The owner of the lock.
+    pub owner: u64,
+    /// The kind of the lock.
+    pub kind: LockKind,
+    /// The offset of the first byte.
+    pub start: u64,
+    /// The offset after the last byte, or [`u64::MAX`] to lock up to the end
+    /// of the file however it grows.
+    pub end: u64,
+}
+
+impl RangeLock {
+    fn conflicts(&self, other: &RangeLock) -> bool {
+        self.owner != other.owner
+            && self.start < other.end
+            && other.start < self.end
+            && (self.kind == LockKind::Exclusive || other.kind == LockKind::Exclusive)
+    }
+}
+
+/// Identifies a file for its locks, the same for all nodes of the file as
+/// long as any of them is open.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum FileId {
+    /// The address of the filesystem and the inode number, or the address of
+    /// the node itself and zero.
+    Node(usize, u64),
+    /// The absolute path.
+    Path(String),
+}
+
+#[derive(Default)]
+struct FileLocks {
+    /// The number of opened files of the file.
+    opened: usize,
+    /// Whether any of the opened files was written.
+    written: bool,
+    /// Whole-file locks, by their owners.
+    whole: BTreeMap<u64, LockKind>,
+    /// Byte-range locks, where those of the same owner never overlap.
+    ranges: Vec<RangeLock>,
+}
+
+impl FileLocks {
+    fn whole_conflicts(&self, owner: u64, kind: LockKind) -> bool {
+        self.whole
+            .iter()
+            .any(|(&o, &k)| o != owner && (k == LockKind::Exclusive || kind == LockKind::Exclusive))
+    }
+
+    fn range_conflict(&self, lock: &RangeLock) -> Option<&RangeLock> {
+        self.ranges.iter().find(|l| l.conflicts(lock))
+    }
+
+    /// Sets the range of `owner` from `start` to `end` to be locked with
+    /// `kind`, or unlocked if it's `None`, splitting and merging the locks of
+    /// the owner.
+    fn set_range(&mut self, owner: u64, kind: Option<LockKind>, mut start: u64, mut end: u64) {
+        let mut ranges = Vec::with_capacity(self.ranges.len() + 2);
+        for lock in self.ranges.drain(..) {
+            if lock.owner != owner || lock.end < start || lock.start > end {
+                ranges.push(lock);
+            } else if Some(lock.kind) == kind {
+                // overlapping or adjacent, merge them
+                start = start.min(lock.start);
+                end = end.max(lock.end);
+            } else {
+                // keep the parts out of the range
+                if lock.start < start {
+                    ranges.push(RangeLock { end: start, ..lock });
+                }
+                if lock.end > end {
+                    ranges.push(RangeLock { start: end, ..lock });
+                }
+            }
+        }
+        if let Some(kind) = kind {
+            ranges.push(RangeLock {
+                owner,
+                kind,
+                start,
+                end,
+            });
+        }
+        self.ranges = ranges;
+    }
+
+    fn is_empty(&self) -> bool {
+        self.opened == 0 && self.whole.is_empty() && self.ranges.is_empty()
+    }
+}
+
+struct LockTable {
+    files: BTreeMap<FileId, FileLocks>,
+    /// The byte-range locks that owners are waiting for.
+    waiting: BTreeMap<u64, (FileId, RangeLock)>,
+}
+
+impl LockTable {
+    fn locks(&mut self, file: &FileId) -> &mut FileLocks {
+        if !self.files.contains_key(file) {
+            self.files.insert(file.clone(), FileLocks::default());
+        }
+        self.files.get_mut(file).unwrap()
+    }
+
+    fn remove_if_empty(&mut self, file: &FileId) {
+        if self.files.get(file).is_some_and(FileLocks::is_empty) {
+            self.files.remove(file);
+        }
+    }
+
+    /// Whether waiting for `lock` would deadlock, i.e. an owner of the
+    /// conflicting locks is waiting for its own lock, directly or through
+    /// other owners.
+    fn would_deadlock(&self, file: &FileId, lock: &RangeLock) -> bool {
+        let blockers = |file: &FileId, lock: &RangeLock| -> Vec<u64> {
+            self.files.get(file).map_or(Vec::new(), |locks| {
+                let conflicts = locks.ranges.iter().filter(|l| l.conflicts(lock));
+                conflicts.map(|l| l.owner).collect()
+            })
+        };
+        let mut owners = blockers(file, lock);
+        let mut visited = BTreeSet::new();
+        while let Some(owner) = owners.pop() {
+            if owner == lock.owner {
+                return true;
+            }
+            if visited.insert(owner) {
+                if let Some((file, lock)) = self.waiting.get(&owner) {
+                    owners.extend(blockers(file, lock));
+                }
+            }
+        }
+        false
+    }
+}
+
+static LOCKS: SpinNoIrq<LockTable> = SpinNoIrq::new(LockTable {
+    files: BTreeMap::new(),
+    waiting: BTreeMap::new(),
+});
+
+#[cfg(feature = "multitask")]
+static WAIT_QUEUE: axtask::WaitQueue = axtask::WaitQueue::new();
+
+/// Blocks the current task until `is_free` returns true, which is checked
+/// with the lock table locked. Without `multitask`, nothing could release
+/// the lock, so it fails with `ResourceBusy` as a deadlock.
+fn wait_for(is_free: impl Fn(&LockTable) -> bool) -> AxResult {
+    #[cfg(feature = "multitask")]
+    {
+        WAIT_QUEUE.wait_until(|| is_free(&LOCKS.lock()));
+        Ok(())
+    }
+    #[cfg(not(feature = "multitask"))]
+    {
+        let _ = is_free;
+        ax_err!(ResourceBusy, "waiting for a lock held by the only task")
+    }
+}
+
+fn notify_waiters() {
+    #[cfg(feature = "multitask")]
+    WAIT_QUEUE.notify_all(false);
+}
+
+/// Records that the file is opened, so its byte-range locks are kept until
+/// it's closed.
+pub(crate) fn open(file: &FileId) {
+    LOCKS.lock().locks(file).opened += 1;
+}
+
+/// Records that an opened file of the file is closed, releasing the
+/// whole-file lock of `owner`, and all byte-range locks if it was the last
+/// one.
+///
+/// Returns whether it was the last one and the file was written through any
+/// of them since the last one before was closed, so it should be flushed.
+pub(crate) fn close(file: &FileId, owner: u64, written: bool) -> bool {
+    let mut table = LOCKS.lock();
+    let Some(locks) = table.files.get_mut(file) else {
+        return written;
+    };
+    locks.whole.remove(&owner);
+    locks.opened -= 1;
+    locks.written |= written;
+    let flush = locks.opened == 0 && core::mem::take(&mut locks.written);
+    if locks.opened == 0 {
+        locks.ranges.clear();
+    }
+    table.remove_if_empty(file);
+    drop(table);
+    notify_waiters();
+    flush
+}
+
+/// Acquires the whole-file lock of `kind` for `owner`, or converts the one
+/// it holds.
+pub(crate) fn lock_whole(file: &FileId, owner: u64, kind: LockKind, wait: bool) -> AxResult {
+    loop {
+        let mut table = LOCKS.lock();
+        let locks = table.locks(file);
+        if !locks.whole_conflicts(owner, kind) {
+            let downgraded = locks.whole.insert(owner, kind) == Some(LockKind::Exclusive);
+            drop(table);
+            if downgraded {
+                notify_waiters();
+            }
+            return Ok(());
+        }
+        table.remove_if_empty(file);
+        drop(table);
+        if !wait {
+            return ax_err!(WouldBlock);
+        }
+        wait_for(|table| {
+            let locks = table.files.get(file);
+            locks.is_none_or(|locks| !locks.whole_conflicts(owner, kind))
+        })?;
+    }
+}
+
+/// Releases the whole-file lock of `owner`, if any.
+pub(crate) fn unlock_whole(file: &FileId, owner: u64) {
+    let mut table = LOCKS.lock();
+    let Some(locks) = table.files.get_mut(file) else {
+        return;
+    };
+    if locks.whole.remove(&owner).is_some() {
+        table.remove_if_empty(file);
+        drop(table);
+        notify_waiters();
+    }
+}
+
+/// Returns a byte-range lock that conflicts with `lock`, if any.
+pub(crate) fn test_range(file: &FileId, lock: &RangeLock) -> Option<RangeLock> {
+    let table = LOCKS.lock();
+    table.files.get(file)?.range_conflict(lock).copied()
+}
+
+/// Acquires the byte-range `lock`, replacing the locks of its owner in the
+/// range.
+///
+/// It fails with `ResourceBusy` if waiting for it would deadlock.
+pub(crate) fn lock_range(file: &FileId, lock: RangeLock, wait: bool) -> AxResult {
+    loop {
+        let mut table = LOCKS.lock();
+        let locks = table.locks(file);
+        if locks.range_conflict(&lock).is_none() {
+            locks.set_range(lock.owner, Some(lock.kind), lock.start, lock.end);
+            table.waiting.remove(&lock.owner);
+            drop(table);
+            // parts of the range may be downgraded
+            notify_waiters();
+            return Ok(());
+        }
+        table.remove_if_empty(file);
+        if !wait {
+            return ax_err!(WouldBlock);
+        }
+        if table.would_deadlock(file, &lock) {
+            table.waiting.remove(&lock.owner);
+            return ax_err!(ResourceBusy, "waiting for the lock would deadlock");
+        }
+        table.waiting.insert(lock.owner, (file.clone(), lock));
+        drop(table);
+        let waited = wait_for(|table| {
+            let locks = table.files.get(file);
+            locks.is_none_or(|locks| locks.range_conflict(&lock).is_none())
+        });
+        if waited.is_err() {
+            LOCKS.lock().waiting.remove(&lock.owner);
+            return waited;
+        }
+    }
+}
+
+/// Unlocks the range of `owner` from `start` to `end`.
+pub(crate) fn unlock_range(file: &FileId, owner: u64, start: u64, end: u64) {
+    let mut table = LOCKS.lock();
+    let Some(locks) = table.files.get_mut(file) else {
+        return;
+    };
+    locks.set_range(owner, None, start, end);
+    table.remove_if_empty(file);
+    drop(table);
+    notify_waiters();
+}
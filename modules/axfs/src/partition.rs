@@ -0,0 +1,340 @@
+//! Partition tables (MBR and GPT) and detection of filesystems on volumes.
+
+use alloc::{format, string::String, vec, vec::Vec};
+
+use crate::dev::Disk;
+
+/// Size of an MBR, an EBR or a GPT header, which is at the start of a block
+/// of the disk.
+const SECTOR_SIZE: usize = 512;
+
+const MBR_SIGNATURE: u16 = 0xaa55;
+const MBR_DISK_ID: usize = 440;
+const MBR_ENTRIES: usize = 446;
+const MBR_TYPE_GPT: u8 = 0xee;
+const MBR_TYPE_EXTENDED: [u8; 3] = [0x05, 0x0f, 0x85];
+/// Maximum number of logical partitions in an extended partition, to stop
+/// at loops in the chain of extended boot records.
+const MAX_LOGICAL_PARTITIONS: u32 = 128;
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+const GPT_MAX_ENTRIES: u32 = 1024;
+
+/// A partition of a disk.
+#[derive(Debug, Clone)]
+pub struct Partition {
+    /// Number of the partition, starting from 1. Logical partitions of MBR
+    /// disks are numbered from 5, as Linux does.
+    pub number: u32,
+    /// First block of the partition, in blocks of the disk.
+    pub start: u64,
+    /// Number of blocks of the partition.
+    pub num_blocks: u64,
+    /// Unique partition GUID of GPT, or `<disk signature>-<number>` of MBR.
+    pub uuid: Option<String>,
+    /// Partition name of GPT.
+    pub name: Option<String>,
+}
+
+/// Type of the filesystem found on a volume.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsType {
+    /// FAT12/16/32.
+    Fat,
+    /// ext2/3/4.
+    Ext4,
+    /// No known filesystem.
+    Unknown,
+}
+
+impl FsType {
+    /// Returns the name of the filesystem type, as in `/proc/mounts`.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Fat => "vfat",
+            Self::Ext4 => "ext4",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    /// Returns the filesystem type of `name`, accepting common aliases like
+    /// `fat32` and `ext2`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "vfat" | "fat" | "fat12" | "fat16" | "fat32" | "msdos" => Some(Self::Fat),
+            "ext2" | "ext3" | "ext4" => Some(Self::Ext4),
+            _ => None,
+        }
+    }
+}
+
+/// Identification of the filesystem found on a volume.
+#[derive(Debug, Clone)]
+pub struct FsId {
+    pub fs_type: FsType,
+    pub label: Option<String>,
+    pub uuid: Option<String>,
+}
+
+fn le16(buf: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes(buf[off..off + 2].try_into().unwrap())
+}
+
+fn le32(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(buf[off..off + 4].try_into().unwrap())
+}
+
+fn le64(buf: &[u8], off: usize) -> u64 {
+    u64::from_le_bytes(buf[off..off + 8].try_into().unwrap())
+}
+
+fn read_sector(disk: &mut Disk, lba: u64) -> Option<[u8; SECTOR_SIZE]> {
+    let mut buf = [0; SECTOR_SIZE];
+    disk.read_exact_at(lba * disk.block_size() as u64, &mut buf)
+        .ok()?;
+    Some(buf)
+}
+
+/// CRC32 (IEEE 802.3), used by GPT headers and partition entries.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Formats a GUID stored in the mixed-endian layout of GPT.
+fn format_guid(raw: &[u8]) -> String {
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+        le32(raw, 0),
+        le16(raw, 4),
+        le16(raw, 6),
+        raw[8],
+        raw[9],
+        raw[10],
+        raw[11],
+        raw[12],
+        raw[13],
+        raw[14],
+        raw[15]
+    )
+}
+
+/// Formats a UUID stored as big-endian bytes, as ext4 does.
+fn format_uuid(raw: &[u8]) -> String {
+    let hex = |range: core::ops::Range<usize>| {
+        raw[range]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+    };
+    format!(
+        "{}-{}-{}-{}-{}",
+        hex(0..4),
+        hex(4..6),
+        hex(6..8),
+        hex(8..10),
+        hex(10..16)
+    )
+}
+
+/// Returns the label stored in a fixed-size, zero or space padded field.
+fn trim_label(raw: &[u8]) -> Option<String> {
+    let len = raw.iter().position(|&c| c == 0).unwrap_or(raw.len());
+    let label = String::from_utf8_lossy(&raw[..len]);
+    let label = label.trim_end_matches(' ');
+    (!label.is_empty()).then(|| label.into())
+}
+
+/// Reads the partition table of a disk. Returns an empty list if the disk
+/// has no MBR or GPT.
+pub fn read_partitions(disk: &mut Disk) -> Vec<Partition> {
+    let Some(mbr) = read_sector(disk, 0) else {
+        return Vec::new();
+    };
+    if le16(&mbr, 510) != MBR_SIGNATURE {
+        return Vec::new();
+    }
+    let entries: Vec<&[u8]> = mbr[MBR_ENTRIES..MBR_ENTRIES + 64].chunks(16).collect();
+    if entries.iter().any(|e| e[4] == MBR_TYPE_GPT) {
+        if let Some(parts) = read_gpt(disk) {
+            return parts;
+        }
+        warn!("invalid GPT, falling back to the protective MBR");
+    }
+    // a boot sector of FAT also ends with the MBR signature
+    if entries.iter().any(|e| e[0] != 0 && e[0] != 0x80) {
+        return Vec::new();
+    }
+
+    let disk_id = le32(&mbr, MBR_DISK_ID);
+    let total = disk.size() / disk.block_size() as u64;
+    let mut parts = Vec::new();
+    let mut add = |number: u32, start: u64, num_blocks: u64| {
+        if num_blocks == 0 || start == 0 || start.saturating_add(num_blocks) > total {
+            warn!("ignore invalid MBR partition {}", number);
+            return;
+        }
+        parts.push(Partition {
+            number,
+            start,
+            num_blocks,
+            uuid: (disk_id != 0).then(|| format!("{:08x}-{:02x}", disk_id, number)),
+            name: None,
+        });
+    };
+
+    for (i, entry) in entries.iter().enumerate() {
+        let ty = entry[4];
+        let start = le32(entry, 8) as u64;
+        let len = le32(entry, 12) as u64;
+        if ty == 0 {
+            continue;
+        }
+        if !MBR_TYPE_EXTENDED.contains(&ty) {
+            add(i as u32 + 1, start, len);
+            continue;
+        }
+        // walk the chain of extended boot records
+        let mut ebr_lba = start;
+        for number in 5..5 + MAX_LOGICAL_PARTITIONS {
+            let Some(ebr) = read_sector(disk, ebr_lba) else {
+                break;
+            };
+            if le16(&ebr, 510) != MBR_SIGNATURE {
+                break;
+            }
+            let logical = &ebr[MBR_ENTRIES..MBR_ENTRIES + 16];
+            if logical[4] != 0 {
+                add(
+                    number,
+                    ebr_lba + le32(logical, 8) as u64,
+                    le32(logical, 12) as u64,
+                );
+            }
+            let next = &ebr[MBR_ENTRIES + 16..MBR_ENTRIES + 32];
+            if next[4] == 0 || le32(next, 8) == 0 {
+                break;
+            }
+            ebr_lba = start + le32(next, 8) as u64;
+        }
+    }
+    parts
+}
+
+/// Reads the GPT, from the backup header if the primary one is corrupted.
+fn read_gpt(disk: &mut Disk) -> Option<Vec<Partition>> {
+    let last_lba = (disk.size() / disk.block_size() as u64).checked_sub(1)?;
+    read_gpt_at(disk, 1).or_else(|| read_gpt_at(disk, last_lba))
+}
+
+fn read_gpt_at(disk: &mut Disk, lba: u64) -> Option<Vec<Partition>> {
+    let mut header = read_sector(disk, lba)?;
+    let header_size = le32(&header, 12) as usize;
+    if &header[..8] != GPT_SIGNATURE || !(92..=SECTOR_SIZE).contains(&header_size) {
+        return None;
+    }
+    let header_crc = le32(&header, 16);
+    header[16..20].fill(0);
+    if crc32(&header[..header_size]) != header_crc || le64(&header, 24) != lba {
+        return None;
+    }
+
+    let entries_lba = le64(&header, 72);
+    let num_entries = le32(&header, 80);
+    let entry_size = le32(&header, 84) as usize;
+    if num_entries > GPT_MAX_ENTRIES || entry_size < 128 || !entry_size.is_multiple_of(8) {
+        return None;
+    }
+    let mut entries = vec![0; num_entries as usize * entry_size];
+    disk.read_exact_at(entries_lba * disk.block_size() as u64, &mut entries)
+        .ok()?;
+    if crc32(&entries) != le32(&header, 88) {
+        return None;
+    }
+
+    let first_usable = le64(&header, 40);
+    let last_usable = le64(&header, 48);
+    let mut parts = Vec::new();
+    for (i, entry) in entries.chunks(entry_size).enumerate() {
+        if entry[..16].iter().all(|&b| b == 0) {
+            continue; // unused entry
+        }
+        let first = le64(entry, 32);
+        let last = le64(entry, 40);
+        if first < first_usable || last > last_usable || first > last {
+            warn!("ignore invalid GPT partition {}", i + 1);
+            continue;
+        }
+        let name: String = char::decode_utf16(
+            entry[56..128]
+                .chunks(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .take_while(|&c| c != 0),
+        )
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect();
+        parts.push(Partition {
+            number: i as u32 + 1,
+            start: first,
+            num_blocks: last - first + 1,
+            uuid: Some(format_guid(&entry[16..32])),
+            name: (!name.is_empty()).then_some(name),
+        });
+    }
+    Some(parts)
+}
+
+/// Detects the filesystem on a volume from its superblock or boot sector.
+pub fn probe_fs(disk: &mut Disk) -> FsId {
+    let mut sb = [0; 1024];
+    if disk.read_exact_at(1024, &mut sb).is_ok() && le16(&sb, 0x38) == 0xef53 {
+        return FsId {
+            fs_type: FsType::Ext4,
+            label: trim_label(&sb[0x78..0x88]),
+            uuid: Some(format_uuid(&sb[0x68..0x78])),
+        };
+    }
+
+    if let Some(bs) = read_sector(disk, 0) {
+        let bytes_per_sector = le16(&bs, 11);
+        let is_fat = matches!(bs[0], 0xeb | 0xe9)
+            && le16(&bs, 510) == MBR_SIGNATURE
+            && bytes_per_sector.is_power_of_two()
+            && (512..=4096).contains(&bytes_per_sector)
+            && bs[13] != 0;
+        if is_fat {
+            // FAT32 has no fixed-size FAT16 table, and its extended BPB is
+            // further in the boot sector
+            let ebpb = if le16(&bs, 22) == 0 { 0x40 } else { 0x24 };
+            let (label, uuid) = if bs[ebpb + 2] == 0x29 {
+                let serial = le32(&bs, ebpb + 3);
+                let label = trim_label(&bs[ebpb + 7..ebpb + 18]).filter(|l| l != "NO NAME");
+                let uuid = format!("{:04X}-{:04X}", serial >> 16, serial & 0xffff);
+                (label, Some(uuid))
+            } else {
+                (None, None)
+            };
+            return FsId {
+                fs_type: FsType::Fat,
+                label,
+                uuid,
+            };
+        }
+    }
+
+    FsId {
+        fs_type: FsType::Unknown,
+        label: None,
+        uuid: None,
+    }
+}
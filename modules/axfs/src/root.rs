@@ -1,103 +1,379 @@
-//! Root directory of the filesystem
-//!
-//! TODO: it doesn't work very well if the mount points have containment relationships.
+//! Root directory of the filesystem, and the table of mounted filesystems.
 
-use alloc::{string::String, sync::Arc, vec::Vec};
+use alloc::{borrow::Cow, format, string::String, sync::Arc, vec::Vec};
 use axerrno::{ax_err, AxError, AxResult};
-use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
+use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
 use axsync::Mutex;
 use lazyinit::LazyInit;
 
-use crate::{api::FileType, fs, mounts};
+use crate::watch::{self, EventKind};
+use crate::{api::FileType, fs, mounts, partition::FsType, volumes};
+
+/// Maximum number of symlinks followed in a path resolution, as Linux does.
+const MAX_SYMLINK_FOLLOWS: usize = 40;
 
 static CURRENT_DIR_PATH: Mutex<String> = Mutex::new(String::new());
+/// The node of the current directory, which keeps its filesystem busy.
 static CURRENT_DIR: LazyInit<Mutex<VfsNodeRef>> = LazyInit::new();
 
 struct MountPoint {
-    path: &'static str,
+    path: String,
+    source: String,
+    fs_type: &'static str,
     fs: Arc<dyn VfsOps>,
+    /// The volumes of the layers of an overlay, which are in use with it.
+    layers: Vec<String>,
 }
 
 struct RootDirectory {
-    main_fs: Arc<dyn VfsOps>,
-    mounts: Vec<MountPoint>,
+    main: Arc<MountPoint>,
+    mounts: Mutex<Vec<Arc<MountPoint>>>,
+    /// Serializes mounts, so that a mount point is checked to be free before
+    /// the filesystem is mounted on it.
+    mount_lock: Mutex<()>,
+}
+
+/// A node of a mounted filesystem, which keeps the filesystem from being
+/// unmounted while the node is in use.
+struct MountedNode {
+    node: VfsNodeRef,
+    mount: Arc<MountPoint>,
 }
 
 static ROOT_DIR: LazyInit<Arc<RootDirectory>> = LazyInit::new();
 
 impl MountPoint {
-    pub fn new(path: &'static str, fs: Arc<dyn VfsOps>) -> Self {
-        Self { path, fs }
+    pub fn new(path: String, source: &str, fs_type: &'static str, fs: Arc<dyn VfsOps>) -> Self {
+        Self {
+            path,
+            source: source.into(),
+            fs_type,
+            fs,
+            layers: Vec::new(),
+        }
+    }
+
+    /// Whether the volume named `source` is used by this filesystem.
+    fn uses(&self, source: &str) -> bool {
+        self.source == source || self.layers.iter().any(|l| l == source)
+    }
+
+    /// Returns the number of leading components of `comps` that are the path
+    /// of this mount point, or `None` if `comps` is not under it.
+    fn match_len(&self, comps: &[&str]) -> Option<usize> {
+        let mut len = 0;
+        for c in self.path.split('/').filter(|c| !c.is_empty()) {
+            if comps.get(len) != Some(&c) {
+                return None;
+            }
+            len += 1;
+        }
+        Some(len)
+    }
+
+    /// Whether this mount point is strictly under `path`.
+    fn is_under(&self, path: &str) -> bool {
+        self.path
+            .strip_prefix(path)
+            .is_some_and(|rest| rest.starts_with('/'))
     }
 }
 
 impl Drop for MountPoint {
     fn drop(&mut self) {
         self.fs.umount().ok();
+        for source in core::iter::once(&self.source).chain(&self.layers) {
+            if let Some(vol) = volumes::find(source) {
+                vol.disk.flush().ok();
+            }
+        }
+    }
+}
+
+/// Splits `path` into its components, resolving `.` and `..` lexically.
+///
+/// As in POSIX, `..` of the root directory is the root directory itself.
+fn components(path: &str) -> Vec<&str> {
+    let mut comps = Vec::new();
+    for c in path.split('/') {
+        match c {
+            "" | "." => {}
+            ".." => {
+                comps.pop();
+            }
+            _ => comps.push(c),
+        }
+    }
+    comps
+}
+
+/// Escapes a field of `/proc/mounts`.
+fn escape_mount_field(field: &str) -> Cow<'_, str> {
+    if field.contains([' ', '\t', '\n', '\\']) {
+        field
+            .replace('\\', "\\134")
+            .replace(' ', "\\040")
+            .replace('\t', "\\011")
+            .replace('\n', "\\012")
+            .into()
+    } else {
+        field.into()
     }
 }
 
 impl RootDirectory {
-    pub const fn new(main_fs: Arc<dyn VfsOps>) -> Self {
+    pub fn new(main: MountPoint) -> Self {
         Self {
-            main_fs,
-            mounts: Vec::new(),
+            main: Arc::new(main),
+            mounts: Mutex::new(Vec::new()),
+            mount_lock: Mutex::new(()),
         }
     }
 
-    pub fn mount(&mut self, path: &'static str, fs: Arc<dyn VfsOps>) -> AxResult {
-        if path == "/" {
-            return ax_err!(InvalidInput, "cannot mount root filesystem");
-        }
+    /// Mounts `fs` at the absolute `path`, creating the mount point and its
+    /// parents if they do not exist.
+    pub fn mount(
+        &self,
+        path: &str,
+        source: &str,
+        fs_type: &'static str,
+        fs: Arc<dyn VfsOps>,
+    ) -> AxResult {
         if !path.starts_with('/') {
             return ax_err!(InvalidInput, "mount path must start with '/'");
         }
-        if self.mounts.iter().any(|mp| mp.path == path) {
-            return ax_err!(InvalidInput, "mount point already exists");
+        let comps = components(path);
+        if comps.is_empty() {
+            return ax_err!(InvalidInput, "cannot mount root filesystem");
+        }
+        let path = format!("/{}", comps.join("/"));
+        let _guard = self.mount_lock.lock();
+        if self.contains(&path) {
+            return ax_err!(ResourceBusy, "mount point already exists");
         }
-        // create the mount point in the main filesystem if it does not exist
-        self.main_fs.root_dir().create(path, FileType::Dir)?;
-        fs.mount(path, self.main_fs.root_dir().lookup(path)?)?;
-        self.mounts.push(MountPoint::new(path, fs));
+        for i in 1..=comps.len() {
+            let dir = &path[..comps[..i].iter().map(|c| c.len() + 1).sum()];
+            match self.lookup_node(dir) {
+                Ok(node) if node.get_attr()?.is_dir() => {}
+                Ok(_) => return ax_err!(NotADirectory),
+                Err(AxError::NotFound) => self.create(dir, FileType::Dir)?,
+                Err(e) => return Err(e),
+            }
+        }
+        fs.mount(&path, self.lookup_node(&path)?)?;
+
+        let mp = Arc::new(MountPoint::new(path, source, fs_type, fs));
+        self.mounts.lock().push(mp);
         Ok(())
     }
 
-    pub fn _umount(&mut self, path: &str) {
-        self.mounts.retain(|mp| mp.path != path);
+    /// Unmounts the filesystem at the absolute `path`.
+    ///
+    /// It fails with [`AxError::ResourceBusy`] if any node of the filesystem
+    /// is in use, or other filesystems are mounted under it, unless `detach`
+    /// is set. In that case, the filesystem and those under it are removed
+    /// from the namespace at once, and the filesystem is unmounted when it's
+    /// no longer in use.
+    pub fn umount(&self, path: &str, detach: bool) -> AxResult {
+        let path = format!("/{}", components(path).join("/"));
+        if path == "/" {
+            return ax_err!(ResourceBusy, "cannot unmount root filesystem");
+        }
+        let mut mounts = self.mounts.lock();
+        let Some(idx) = mounts.iter().position(|mp| mp.path == path) else {
+            return ax_err!(InvalidInput, "not a mount point");
+        };
+        let removed: Vec<_> = if detach {
+            let (removed, kept) = mounts
+                .drain(..)
+                .partition(|mp| mp.path == path || mp.is_under(&path));
+            *mounts = kept;
+            removed
+        } else {
+            if mounts.iter().any(|mp| mp.is_under(&path)) {
+                return ax_err!(ResourceBusy, "other filesystems are mounted under it");
+            }
+            if Arc::strong_count(&mounts[idx]) > 1 {
+                return ax_err!(ResourceBusy, "filesystem is in use");
+            }
+            alloc::vec![mounts.remove(idx)]
+        };
+        drop(mounts);
+        drop(removed); // unmount outside the lock
+        Ok(())
     }
 
     pub fn contains(&self, path: &str) -> bool {
-        self.mounts.iter().any(|mp| mp.path == path)
+        self.mounts.lock().iter().any(|mp| mp.path == path)
+    }
+
+    /// Whether the volume named `source` is mounted.
+    pub fn is_mounted(&self, source: &str) -> bool {
+        self.main.uses(source) || self.mounts.lock().iter().any(|mp| mp.uses(source))
+    }
+
+    /// Returns the mount table in the format of `/proc/mounts`.
+    pub fn mount_table(&self) -> String {
+        let mut table = String::new();
+        for mp in core::iter::once(&self.main).chain(self.mounts.lock().iter()) {
+            table += &format!(
+                "{} {} {} rw 0 0\n",
+                escape_mount_field(&mp.source),
+                escape_mount_field(&mp.path),
+                mp.fs_type
+            );
+        }
+        table
     }
 
     fn lookup_mounted_fs<F, T>(&self, path: &str, f: F) -> AxResult<T>
     where
-        F: FnOnce(Arc<dyn VfsOps>, &str) -> AxResult<T>,
+        F: FnOnce(&Arc<MountPoint>, &str) -> AxResult<T>,
     {
         debug!("lookup at root: {}", path);
-        let path = path.trim_matches('/');
-        if let Some(rest) = path.strip_prefix("./") {
-            return self.lookup_mounted_fs(rest, f);
-        }
-
-        let mut idx = 0;
-        let mut max_len = 0;
+        let comps = components(path);
 
         // Find the filesystem that has the longest mounted path match
         // TODO: more efficient, e.g. trie
-        for (i, mp) in self.mounts.iter().enumerate() {
-            // skip the first '/'
-            if path.starts_with(&mp.path[1..]) && mp.path.len() - 1 > max_len {
-                max_len = mp.path.len() - 1;
-                idx = i;
-            }
+        let matched = self
+            .mounts
+            .lock()
+            .iter()
+            .filter_map(|mp| Some((mp.match_len(&comps)?, mp)))
+            .max_by_key(|(len, _)| *len)
+            .map(|(len, mp)| (len, mp.clone()));
+
+        match matched {
+            Some((len, mp)) => f(&mp, &comps[len..].join("/")),
+            None => f(&self.main, &comps.join("/")), // not matched any mount point
         }
+    }
 
-        if max_len == 0 {
-            f(self.main_fs.clone(), path) // not matched any mount point
-        } else {
-            f(self.mounts[idx].fs.clone(), &path[max_len..]) // matched at `idx`
+    /// Looks up the absolute `path`, which must have no symlinks.
+    fn lookup_node(&self, path: &str) -> AxResult<VfsNodeRef> {
+        self.lookup_mounted_fs(path, |mp, rest_path| {
+            let node = mp.fs.root_dir().lookup(rest_path)?;
+            if Arc::ptr_eq(mp, &self.main) {
+                Ok(node)
+            } else {
+                Ok(Arc::new(MountedNode {
+                    node,
+                    mount: mp.clone(),
+                }))
+            }
+        })
+    }
+
+    /// Resolves `path` from the directory `dir`, or from the root directory if
+    /// `dir` is `None` or `path` is absolute, following symlinks on the way, and
+    /// the last component too if `follow` is true.
+    ///
+    /// Absolute targets of symlinks are resolved from the root directory, and
+    /// relative ones from the directory of the symlink. Filesystems mounted on
+    /// the way are entered unless the path is resolved from `dir` only, as the
+    /// absolute path is unknown then. Returns the node and its absolute path if
+    /// it's known.
+    fn walk(
+        &self,
+        dir: Option<&VfsNodeRef>,
+        path: &str,
+        follow: bool,
+    ) -> AxResult<(VfsNodeRef, Option<String>)> {
+        let root = self.main.fs.root_dir();
+        // the absolute path of `node` without the trailing '/', if it's known
+        let (mut node, mut abs_path, mut comps): (_, Option<String>, Vec<String>) = match dir {
+            Some(dir) if !path.starts_with('/') => {
+                let comps = path.split('/').rev().map(String::from).collect();
+                (dir.clone(), None, comps)
+            }
+            _ => {
+                let path = if path.starts_with('/') {
+                    path.into()
+                } else {
+                    CURRENT_DIR_PATH.lock().clone() + path
+                };
+                let comps = components(&path).into_iter().rev().map(String::from);
+                (root.clone(), Some(String::new()), comps.collect())
+            }
+        };
+
+        let mut follows = 0;
+        while let Some(name) = comps.pop() {
+            match name.as_str() {
+                "" | "." => continue,
+                ".." => {
+                    node = match &mut abs_path {
+                        Some(abs_path) => {
+                            // `..` of the root is the root itself
+                            abs_path.truncate(abs_path.rfind('/').unwrap_or(0));
+                            self.lookup_node(abs_path)?
+                        }
+                        None => node.lookup("..")?,
+                    };
+                    continue;
+                }
+                _ => {}
+            }
+            if !node.get_attr()?.is_dir() {
+                return ax_err!(NotADirectory);
+            }
+            let mounted = abs_path
+                .as_ref()
+                .and_then(|p| self.mounted_root(&format!("{}/{}", p, name)));
+            let child = match mounted {
+                Some(root) => root,
+                None => node.clone().lookup(&name)?,
+            };
+            if (follow || !comps.is_empty())
+                && child.get_attr()?.file_type() == VfsNodeType::SymLink
+            {
+                follows += 1;
+                if follows > MAX_SYMLINK_FOLLOWS {
+                    return ax_err!(InvalidData, "too many levels of symbolic links");
+                }
+                let target = read_link_node(&child)?;
+                if target.starts_with('/') {
+                    node = root.clone();
+                    abs_path = Some(String::new());
+                }
+                comps.extend(target.split('/').rev().map(String::from));
+                continue;
+            }
+            node = child;
+            if let Some(abs_path) = &mut abs_path {
+                abs_path.push('/');
+                abs_path.push_str(&name);
+            }
         }
+        let abs_path = abs_path.map(|p| if p.is_empty() { "/".into() } else { p });
+        Ok((node, abs_path))
+    }
+
+    /// Resolves the symlinks in the parent directories of the absolute `path`,
+    /// returning the absolute path of its last component.
+    fn resolve_parent(&self, path: &str) -> AxResult<String> {
+        let comps = components(path);
+        let Some((name, parent)) = comps.split_last() else {
+            return Ok("/".into());
+        };
+        let (_, parent) = self.walk(None, &format!("/{}", parent.join("/")), true)?;
+        let parent = parent.unwrap_or_default();
+        Ok(format!("{}/{}", parent.trim_end_matches('/'), name))
+    }
+
+    /// Returns the root directory of the filesystem mounted at the absolute
+    /// `path`, if any.
+    fn mounted_root(&self, path: &str) -> Option<VfsNodeRef> {
+        let mp = self
+            .mounts
+            .lock()
+            .iter()
+            .find(|mp| mp.path == path)?
+            .clone();
+        Some(Arc::new(MountedNode {
+            node: mp.fs.root_dir(),
+            mount: mp,
+        }))
     }
 }
 
@@ -105,78 +381,206 @@ impl VfsNodeOps for RootDirectory {
     axfs_vfs::impl_vfs_dir_default! {}
 
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        self.main_fs.root_dir().get_attr()
+        self.main.fs.root_dir().get_attr()
     }
 
     fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
-        self.lookup_mounted_fs(path, |fs, rest_path| fs.root_dir().lookup(rest_path))
+        Ok(self.walk(None, &format!("/{}", path), true)?.0)
     }
 
     fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
-        self.lookup_mounted_fs(path, |fs, rest_path| {
+        self.lookup_mounted_fs(&self.resolve_parent(path)?, |mp, rest_path| {
             if rest_path.is_empty() {
                 Ok(()) // already exists
             } else {
-                fs.root_dir().create(rest_path, ty)
+                mp.fs.root_dir().create(rest_path, ty)
             }
         })
     }
 
     fn remove(&self, path: &str) -> VfsResult {
-        self.lookup_mounted_fs(path, |fs, rest_path| {
+        self.lookup_mounted_fs(&self.resolve_parent(path)?, |mp, rest_path| {
             if rest_path.is_empty() {
                 ax_err!(PermissionDenied) // cannot remove mount points
             } else {
-                fs.root_dir().remove(rest_path)
+                mp.fs.root_dir().remove(rest_path)
             }
         })
     }
 
     fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
-        self.lookup_mounted_fs(src_path, |fs, rest_path| {
-            if rest_path.is_empty() {
-                ax_err!(PermissionDenied) // cannot rename mount points
-            } else {
-                fs.root_dir().rename(rest_path, dst_path)
-            }
+        let src_path = self.resolve_parent(src_path)?;
+        let dst_path = self.resolve_parent(dst_path)?;
+        self.lookup_mounted_fs(&src_path, |src_mp, src_rest| {
+            self.lookup_mounted_fs(&dst_path, |dst_mp, dst_rest| {
+                if src_rest.is_empty() || dst_rest.is_empty() {
+                    ax_err!(PermissionDenied) // cannot rename mount points
+                } else if !Arc::ptr_eq(src_mp, dst_mp) {
+                    ax_err!(Unsupported, "cannot rename across filesystems")
+                } else {
+                    src_mp.fs.root_dir().rename(src_rest, dst_rest)
+                }
+            })
         })
     }
 }
 
-pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
-    cfg_if::cfg_if! {
-        if #[cfg(feature = "myfs")] { // override the default filesystem
-            let main_fs = fs::myfs::new_myfs(disk);
-        } else if #[cfg(feature = "fatfs")] {
-            static FAT_FS: LazyInit<Arc<fs::fatfs::FatFileSystem>> = LazyInit::new();
-            FAT_FS.init_once(Arc::new(fs::fatfs::FatFileSystem::new(disk)));
-            FAT_FS.init();
-            let main_fs = FAT_FS.clone();
+impl VfsNodeOps for MountedNode {
+    fn open(&self) -> VfsResult {
+        self.node.open()
+    }
+
+    fn release(&self) -> VfsResult {
+        self.node.release()
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        self.node.get_attr()
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        self.node.read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        self.node.write_at(offset, buf)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        self.node.fsync()
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        self.node.truncate(size)
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        self.node.parent()
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let node = self.node.clone().lookup(path)?;
+        Ok(Arc::new(MountedNode {
+            node,
+            mount: self.mount.clone(),
+        }))
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        self.node.create(path, ty)
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        self.node.remove(path)
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        self.node.read_dir(start_idx, dirents)
+    }
+
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        self.node.rename(src_path, dst_path)
+    }
+
+    fn as_any(&self) -> &dyn core::any::Any {
+        self.node.as_any()
+    }
+}
+
+/// Mounts the filesystem of the volume `root` on `/`, under the overlay
+/// selected by `overlay` if it's not empty, see [`root_mount_point`].
+pub(crate) fn init_rootfs(root: &volumes::Volume, overlay: &str) {
+    info!("  mount {} on /", root.info.name);
+    let (main_fs, fs_type) = fs::new_root_fs(root.disk.slice(0, u64::MAX), root.info.fs_type);
+    init_root_dir(root_mount_point(&root.info.name, fs_type, main_fs, overlay));
+}
+
+/// Mounts a ramfs on `/`, with the files unpacked from the initramfs
+/// `archive` (if not empty), under the overlay selected by `overlay` if it's
+/// not empty.
+#[cfg(feature = "initramfs")]
+pub(crate) fn init_initramfs(archive: &[u8], overlay: &str) {
+    info!("  mount initramfs on / ({} bytes)", archive.len());
+    let main_fs = mounts::ramfs();
+    if !archive.is_empty() {
+        if let Err(e) = crate::initramfs::unpack(archive, &main_fs.root_dir()) {
+            error!("failed to unpack initramfs: {:?}", e);
         }
     }
+    init_root_dir(root_mount_point("initramfs", "ramfs", main_fs, overlay));
+}
 
-    let mut root_dir = RootDirectory::new(main_fs);
+/// Returns the mount point of `fs` from `source` on `/`.
+///
+/// If `overlay` is not empty, `fs` becomes the lower layer of an overlay,
+/// whose upper layer is a ramfs if `overlay` is `ramfs`, or the filesystem of
+/// the volume selected by `overlay` to persist the changes.
+fn root_mount_point(
+    source: &str,
+    fs_type: &'static str,
+    fs: Arc<dyn VfsOps>,
+    overlay: &str,
+) -> MountPoint {
+    #[cfg(feature = "overlayfs")]
+    if !overlay.is_empty() {
+        info!("  mount {} over {} on /", overlay, source);
+        let (upper, upper_source) = overlay_upper(overlay, source)
+            .unwrap_or_else(|e| panic!("Root overlay {:?} not available: {:?}", overlay, e));
+        let fs = fs::overlayfs::OverlayFileSystem::from_fs(fs, upper);
+        let mut mp = MountPoint::new("/".into(), "overlay", "overlay", Arc::new(fs));
+        mp.layers.push(source.into());
+        mp.layers.extend(upper_source);
+        return mp;
+    }
+    let _ = overlay;
+    MountPoint::new("/".into(), source, fs_type, fs)
+}
+
+/// Creates the upper layer of the root overlay selected by `spec` over the
+/// filesystem from `root`, with the name of its volume if it's on a volume.
+#[cfg(feature = "overlayfs")]
+fn overlay_upper(spec: &str, root: &str) -> AxResult<(Arc<dyn VfsOps>, Option<String>)> {
+    if matches!(spec, "ramfs" | "tmpfs") {
+        #[cfg(feature = "ramfs")]
+        return Ok((mounts::ramfs(), None));
+        #[cfg(not(feature = "ramfs"))]
+        return ax_err!(Unsupported, "ramfs is not enabled");
+    }
+    let Some(vol) = volumes::find(spec) else {
+        return ax_err!(NotFound, "volume not found");
+    };
+    if vol.info.name == root {
+        return ax_err!(ResourceBusy, "volume is the lower layer");
+    }
+    let Some(fs) = fs::new_fs(vol.disk.slice(0, u64::MAX), vol.info.fs_type) else {
+        return ax_err!(Unsupported, "unsupported filesystem");
+    };
+    Ok((fs, Some(vol.info.name.clone())))
+}
+
+fn init_root_dir(main: MountPoint) {
+    let root_dir = RootDirectory::new(main);
 
     #[cfg(feature = "devfs")]
     root_dir
-        .mount("/dev", mounts::devfs())
+        .mount("/dev", "devfs", "devfs", mounts::devfs())
         .expect("failed to mount devfs at /dev");
 
     #[cfg(feature = "ramfs")]
     root_dir
-        .mount("/tmp", mounts::ramfs())
+        .mount("/tmp", "ramfs", "ramfs", mounts::ramfs())
         .expect("failed to mount ramfs at /tmp");
 
-    // Mount another ramfs as procfs
+    // Mount procfs
     #[cfg(feature = "procfs")]
     root_dir // should not fail
-        .mount("/proc", mounts::procfs().unwrap())
+        .mount("/proc", "proc", "proc", mounts::procfs())
         .expect("fail to mount procfs at /proc");
 
-    // Mount another ramfs as sysfs
+    // Mount sysfs
     #[cfg(feature = "sysfs")]
     root_dir // should not fail
-        .mount("/sys", mounts::sysfs().unwrap())
+        .mount("/sys", "sysfs", "sysfs", mounts::sysfs())
         .expect("fail to mount sysfs at /sys");
 
     ROOT_DIR.init_once(Arc::new(root_dir));
@@ -184,11 +588,70 @@ pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
     *CURRENT_DIR_PATH.lock() = "/".into();
 }
 
-fn parent_node_of(dir: Option<&VfsNodeRef>, path: &str) -> VfsNodeRef {
-    if path.starts_with('/') {
-        ROOT_DIR.clone()
-    } else {
-        dir.cloned().unwrap_or_else(|| CURRENT_DIR.lock().clone())
+/// Mounts the filesystem of `fs_type` from `source` at `target`.
+pub(crate) fn mount(source: &str, target: &str, fs_type: &str) -> AxResult {
+    let target = absolute_path(target)?;
+    if matches!(fs_type, "ramfs" | "tmpfs") {
+        #[cfg(feature = "ramfs")]
+        return ROOT_DIR.mount(&target, source, "ramfs", mounts::ramfs());
+        #[cfg(not(feature = "ramfs"))]
+        return ax_err!(Unsupported, "ramfs is not enabled");
+    }
+
+    let Some(vol) = volumes::find(source) else {
+        return ax_err!(NotFound, "volume not found");
+    };
+    let vol_type = vol.info.fs_type;
+    if !matches!(fs_type, "" | "auto") && FsType::from_name(fs_type) != Some(vol_type) {
+        return ax_err!(InvalidInput, "wrong filesystem type");
+    }
+    if ROOT_DIR.is_mounted(&vol.info.name) {
+        return ax_err!(ResourceBusy, "volume is already mounted");
+    }
+    let Some(fs) = fs::new_fs(vol.disk.slice(0, u64::MAX), vol_type) else {
+        return ax_err!(Unsupported, "unsupported filesystem");
+    };
+    ROOT_DIR.mount(&target, &vol.info.name, vol_type.name(), fs)
+}
+
+/// Mounts an overlay of the directory `upper` over the directory `lower` at
+/// `target`.
+#[cfg(feature = "overlayfs")]
+pub(crate) fn mount_overlay(lower: &str, upper: &str, target: &str) -> AxResult {
+    let target = absolute_path(target)?;
+    let lower = lookup(None, lower)?;
+    let upper = lookup(None, upper)?;
+    if !lower.get_attr()?.is_dir() || !upper.get_attr()?.is_dir() {
+        return ax_err!(NotADirectory);
+    }
+    let fs = fs::overlayfs::OverlayFileSystem::new(lower, upper);
+    ROOT_DIR.mount(&target, "overlay", "overlay", Arc::new(fs))
+}
+
+/// Returns the mount table in the format of `/proc/mounts`.
+#[cfg(feature = "procfs")]
+pub(crate) fn mount_table() -> String {
+    ROOT_DIR
+        .get()
+        .map(|root| root.mount_table())
+        .unwrap_or_default()
+}
+
+/// Unmounts the filesystem at `target`, see [`RootDirectory::umount`].
+pub(crate) fn umount(target: &str, detach: bool) -> AxResult {
+    ROOT_DIR.umount(&absolute_path(target)?, detach)
+}
+
+fn parent_node_of<'a>(dir: Option<&VfsNodeRef>, path: &'a str) -> (VfsNodeRef, Cow<'a, str>) {
+    match dir {
+        _ if path.starts_with('/') => (ROOT_DIR.clone() as VfsNodeRef, path.into()),
+        Some(dir) => (dir.clone(), path.into()),
+        // resolve from the root to see the filesystems mounted under the
+        // current directory
+        None => {
+            let path = CURRENT_DIR_PATH.lock().clone() + path;
+            (ROOT_DIR.clone() as VfsNodeRef, path.into())
+        }
     }
 }
 
@@ -201,46 +664,106 @@ pub(crate) fn absolute_path(path: &str) -> AxResult<String> {
     }
 }
 
-pub(crate) fn lookup(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRef> {
+/// Reads the target of the symlink `node`.
+fn read_link_node(node: &VfsNodeRef) -> AxResult<String> {
+    let mut target = alloc::vec![0; node.get_attr()?.size() as usize];
+    let len = node.read_at(0, &mut target)?;
+    target.truncate(len);
+    String::from_utf8(target).map_err(|_| AxError::InvalidData)
+}
+
+/// Resolves `path` like [`RootDirectory::walk`], and checks that a path with a trailing '/'
+/// is a directory, which is always followed.
+fn resolve(
+    dir: Option<&VfsNodeRef>,
+    path: &str,
+    follow: bool,
+) -> AxResult<(VfsNodeRef, Option<String>)> {
     if path.is_empty() {
         return ax_err!(NotFound);
     }
-    let node = parent_node_of(dir, path).lookup(path)?;
+    let (node, abs_path) = ROOT_DIR.walk(dir, path, follow || path.ends_with('/'))?;
     if path.ends_with('/') && !node.get_attr()?.is_dir() {
         ax_err!(NotADirectory)
     } else {
-        Ok(node)
+        Ok((node, abs_path))
     }
 }
 
+/// Returns the absolute path of the entry at `path` relative to `dir` as
+/// watches see it, or `None` if nothing is watched or the path is unknown.
+fn watch_path(dir: Option<&VfsNodeRef>, path: &str) -> Option<String> {
+    if !watch::is_watched() || (dir.is_some() && !path.starts_with('/')) {
+        return None;
+    }
+    ROOT_DIR.resolve_parent(&absolute_path(path).ok()?).ok()
+}
+
+/// Reports an event of `kind` for the entry at `path` relative to `dir`.
+fn notify(dir: Option<&VfsNodeRef>, path: &str, kind: EventKind) {
+    if let Some(path) = watch_path(dir, path) {
+        watch::notify(kind, &path, 0);
+    }
+}
+
+/// Looks up `path` relative to `dir` (or the current directory if `dir` is
+/// `None`), following all symlinks.
+pub(crate) fn lookup(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRef> {
+    resolve(dir, path, true).map(|(node, _)| node)
+}
+
+/// Looks up `path` like [`lookup`], and returns the absolute path of the node
+/// too if it's known.
+pub(crate) fn lookup_with_path(
+    dir: Option<&VfsNodeRef>,
+    path: &str,
+) -> AxResult<(VfsNodeRef, Option<String>)> {
+    resolve(dir, path, true)
+}
+
+/// Looks up `path` like [`lookup`], but returns a symlink at the last
+/// component itself.
+pub(crate) fn lookup_no_follow(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRef> {
+    resolve(dir, path, false).map(|(node, _)| node)
+}
+
 pub(crate) fn create_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRef> {
     if path.is_empty() {
         return ax_err!(NotFound);
     } else if path.ends_with('/') {
         return ax_err!(NotADirectory);
     }
-    let parent = parent_node_of(dir, path);
-    parent.create(path, VfsNodeType::File)?;
-    parent.lookup(path)
+    let (parent, rel_path) = parent_node_of(dir, path);
+    parent.create(&rel_path, VfsNodeType::File)?;
+    notify(dir, path, EventKind::Create);
+    parent.lookup(&rel_path)
 }
 
 pub(crate) fn create_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
     match lookup(dir, path) {
         Ok(_) => ax_err!(AlreadyExists),
-        Err(AxError::NotFound) => parent_node_of(dir, path).create(path, VfsNodeType::Dir),
+        Err(AxError::NotFound) => {
+            let (parent, rel_path) = parent_node_of(dir, path);
+            parent.create(&rel_path, VfsNodeType::Dir)?;
+            notify(dir, path, EventKind::Create);
+            Ok(())
+        }
         Err(e) => Err(e),
     }
 }
 
 pub(crate) fn remove_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
-    let node = lookup(dir, path)?;
+    let node = lookup_no_follow(dir, path)?;
     let attr = node.get_attr()?;
     if attr.is_dir() {
         ax_err!(IsADirectory)
     } else if !attr.perm().owner_writable() {
         ax_err!(PermissionDenied)
     } else {
-        parent_node_of(dir, path).remove(path)
+        let (parent, rel_path) = parent_node_of(dir, path);
+        parent.remove(&rel_path)?;
+        notify(dir, path, EventKind::Delete);
+        Ok(())
     }
 }
 
@@ -262,14 +785,17 @@ pub(crate) fn remove_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
         return ax_err!(PermissionDenied);
     }
 
-    let node = lookup(dir, path)?;
+    let node = lookup_no_follow(dir, path)?;
     let attr = node.get_attr()?;
     if !attr.is_dir() {
         ax_err!(NotADirectory)
     } else if !attr.perm().owner_writable() {
         ax_err!(PermissionDenied)
     } else {
-        parent_node_of(dir, path).remove(path)
+        let (parent, rel_path) = parent_node_of(dir, path);
+        parent.remove(&rel_path)?;
+        notify(dir, path, EventKind::Delete);
+        Ok(())
     }
 }
 
@@ -288,23 +814,86 @@ pub(crate) fn set_current_dir(path: &str) -> AxResult {
         return Ok(());
     }
 
-    let node = lookup(None, &abs_path)?;
+    // the current directory is kept with symlinks resolved
+    let (node, abs_path) = ROOT_DIR.walk(None, &abs_path, true)?;
     let attr = node.get_attr()?;
     if !attr.is_dir() {
         ax_err!(NotADirectory)
     } else if !attr.perm().owner_executable() {
         ax_err!(PermissionDenied)
     } else {
+        let abs_path = abs_path.unwrap_or_default();
         *CURRENT_DIR.lock() = node;
-        *CURRENT_DIR_PATH.lock() = abs_path;
+        *CURRENT_DIR_PATH.lock() = abs_path.trim_end_matches('/').to_owned() + "/";
         Ok(())
     }
 }
 
 pub(crate) fn rename(old: &str, new: &str) -> AxResult {
-    if parent_node_of(None, new).lookup(new).is_ok() {
+    let (_, new_path) = parent_node_of(None, new);
+    if lookup_no_follow(None, new).is_ok() {
         warn!("dst file already exist, now remove it");
         remove_file(None, new)?;
     }
-    parent_node_of(None, old).rename(old, new)
+    let (parent, old_path) = parent_node_of(None, old);
+    parent.rename(&old_path, &new_path)?;
+    if let (Some(old), Some(new)) = (watch_path(None, old), watch_path(None, new)) {
+        watch::notify_rename(&old, &new);
+    }
+    Ok(())
+}
+
+/// Reads the target of the symlink at `path`.
+pub(crate) fn read_link(path: &str) -> AxResult<String> {
+    let node = lookup_no_follow(None, path)?;
+    if node.get_attr()?.file_type() != VfsNodeType::SymLink {
+        return ax_err!(InvalidInput, "not a symlink");
+    }
+    read_link_node(&node)
+}
+
+/// Creates a symlink at `path` pointing to `target`.
+pub(crate) fn symlink(target: &str, path: &str) -> AxResult {
+    if target.is_empty() {
+        return ax_err!(NotFound);
+    }
+    if lookup_no_follow(None, path).is_ok() {
+        return ax_err!(AlreadyExists);
+    }
+    let path = ROOT_DIR.resolve_parent(&absolute_path(path)?)?;
+    ROOT_DIR.lookup_mounted_fs(&path, |mp, rest_path| {
+        if rest_path.is_empty() {
+            ax_err!(AlreadyExists)
+        } else {
+            fs::symlink(&mp.fs.root_dir(), rest_path, target)
+        }
+    })?;
+    watch::notify(EventKind::Create, &path, 0);
+    Ok(())
+}
+
+/// Creates a hard link at `new` to the file at `old`, which is not followed
+/// if it's a symlink.
+pub(crate) fn link(old: &str, new: &str) -> AxResult {
+    if lookup_no_follow(None, old)?.get_attr()?.is_dir() {
+        return ax_err!(PermissionDenied, "cannot link a directory");
+    }
+    if lookup_no_follow(None, new).is_ok() {
+        return ax_err!(AlreadyExists);
+    }
+    let old = ROOT_DIR.resolve_parent(&absolute_path(old)?)?;
+    let new = ROOT_DIR.resolve_parent(&absolute_path(new)?)?;
+    ROOT_DIR.lookup_mounted_fs(&old, |old_mp, old_rest| {
+        ROOT_DIR.lookup_mounted_fs(&new, |new_mp, new_rest| {
+            if old_rest.is_empty() || new_rest.is_empty() {
+                ax_err!(PermissionDenied)
+            } else if !Arc::ptr_eq(old_mp, new_mp) {
+                ax_err!(Unsupported, "cannot link across filesystems")
+            } else {
+                fs::link(&old_mp.fs.root_dir(), old_rest, new_rest)
+            }
+        })
+    })?;
+    watch::notify(EventKind::Create, &new, 0);
+    Ok(())
 }
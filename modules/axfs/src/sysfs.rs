@@ -0,0 +1,128 @@
+//! Device attributes published in `/sys`.
+//!
+//! Each attribute is a file backed by callbacks, which read or change the
+//! live state of a device when the file is read or written, e.g.
+//!
+//! ```ignore
+//! axfs::sysfs::add_attr_rw(
+//!     "class/gpio/gpio3/value",
+//!     || Ok(format!("{}\n", gpio_get(3) as u8)),
+//!     |s| gpio_set(3, s.trim() == "1"),
+//! )?;
+//! ```
+//!
+//! Attributes can be added before or after the filesystems are initialized.
+
+use alloc::{format, string::String, sync::Arc};
+use axerrno::{ax_err, AxResult};
+use axfs_vfs::{VfsNodeRef, VfsOps};
+use axsync::Mutex;
+
+use crate::fs::pseudo::{PseudoDir, PseudoFile};
+
+static SYSFS_ROOT: Mutex<Option<Arc<PseudoDir>>> = Mutex::new(None);
+
+/// The filesystem mounted at `/sys`.
+pub(crate) struct SysFileSystem {
+    root: Arc<PseudoDir>,
+}
+
+impl SysFileSystem {
+    pub fn new() -> Self {
+        Self { root: root() }
+    }
+}
+
+impl VfsOps for SysFileSystem {
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
+
+fn root() -> Arc<PseudoDir> {
+    SYSFS_ROOT
+        .lock()
+        .get_or_insert_with(|| {
+            let root = PseudoDir::new(None);
+            add_builtin_attrs(&root);
+            root
+        })
+        .clone()
+}
+
+/// Adds the attributes of the kernel and the CPUs.
+fn add_builtin_attrs(root: &Arc<PseudoDir>) {
+    let clocksource = if cfg!(target_arch = "x86_64") {
+        "tsc"
+    } else if cfg!(target_arch = "aarch64") {
+        "arch_sys_counter"
+    } else if cfg!(target_arch = "riscv64") {
+        "riscv_clocksource"
+    } else {
+        "jiffies"
+    };
+    let cpus = match axconfig::SMP {
+        1 => String::from("0\n"),
+        n => format!("0-{}\n", n - 1),
+    };
+    let system = root.mkdir("devices").mkdir("system");
+    system.mkdir("clocksource").mkdir("clocksource0").add(
+        "current_clocksource",
+        constant(format!("{}\n", clocksource)),
+    );
+    let cpu = system.mkdir("cpu");
+    cpu.add("possible", constant(cpus.clone()));
+    cpu.add("online", constant(cpus));
+    // huge pages are not supported
+    root.mkdir("kernel")
+        .mkdir("mm")
+        .mkdir("transparent_hugepage")
+        .add("enabled", constant("always madvise [never]\n".into()));
+}
+
+fn constant(value: String) -> VfsNodeRef {
+    Arc::new(PseudoFile::new(move || Ok(value.clone())))
+}
+
+/// Adds a read-only attribute at `path` relative to `/sys`, whose content
+/// is returned by `show`.
+///
+/// Missing directories are created, and an attribute at the same path is
+/// replaced.
+pub fn add_attr<R>(path: &str, show: R) -> AxResult
+where
+    R: Fn() -> AxResult<String> + Send + Sync + 'static,
+{
+    let (dir, name) = root().parent_of(path, true)?;
+    dir.add(name, Arc::new(PseudoFile::new(show)));
+    Ok(())
+}
+
+/// Adds a writable attribute at `path` relative to `/sys`, like
+/// [`add_attr`].
+///
+/// Each write to the file is passed to `store` as a string. Errors returned
+/// by `store` are returned by the write, e.g. [`InvalidInput`] for a bad
+/// value.
+///
+/// [`InvalidInput`]: axerrno::AxError::InvalidInput
+pub fn add_attr_rw<R, W>(path: &str, show: R, store: W) -> AxResult
+where
+    R: Fn() -> AxResult<String> + Send + Sync + 'static,
+    W: Fn(&str) -> AxResult + Send + Sync + 'static,
+{
+    let (dir, name) = root().parent_of(path, true)?;
+    dir.add(name, Arc::new(PseudoFile::new_rw(show, store)));
+    Ok(())
+}
+
+/// Removes the attribute or directory at `path` relative to `/sys`.
+///
+/// Open files of the removed attributes can still be used.
+pub fn remove(path: &str) -> AxResult {
+    let (dir, name) = root().parent_of(path, false)?;
+    match dir.remove_entry(name) {
+        Some(_) => Ok(()),
+        None => ax_err!(NotFound),
+    }
+}
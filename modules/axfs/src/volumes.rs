@@ -0,0 +1,171 @@
+//! Volumes of block devices: whole disks and their partitions, and the
+//! selection of those mounted at boot.
+
+use alloc::{format, string::String, vec::Vec};
+use axdriver::{prelude::*, AxDeviceContainer};
+use axerrno::{ax_err, AxResult};
+use lazyinit::LazyInit;
+
+use crate::cache::CacheStats;
+use crate::dev::Disk;
+use crate::partition::{self, FsType};
+
+static VOLUMES: LazyInit<Vec<Volume>> = LazyInit::new();
+
+/// Information about a volume, i.e. a block device or a partition of it.
+#[derive(Debug, Clone)]
+pub struct VolumeInfo {
+    /// Name of the volume, `blk<N>` for the N-th block device and
+    /// `blk<N>p<M>` for its M-th partition.
+    pub name: String,
+    /// Size of the volume in bytes.
+    pub size: u64,
+    /// Type of the filesystem on the volume.
+    pub fs_type: FsType,
+    /// Label of the filesystem.
+    pub label: Option<String>,
+    /// UUID of the filesystem.
+    pub uuid: Option<String>,
+    /// Unique ID of the partition.
+    pub part_uuid: Option<String>,
+    /// Name of the partition (GPT only).
+    pub part_label: Option<String>,
+}
+
+pub(crate) struct Volume {
+    pub info: VolumeInfo,
+    pub disk: Disk,
+}
+
+impl VolumeInfo {
+    /// Whether the volume is selected by `spec`, which is either the name of
+    /// the volume (optionally prefixed by `/dev/`), or one of `LABEL=<label>`,
+    /// `UUID=<uuid>`, `PARTLABEL=<name>` and `PARTUUID=<uuid>`.
+    pub fn matches(&self, spec: &str) -> bool {
+        let eq = |field: &Option<String>, val: &str| field.as_deref() == Some(val);
+        let eq_uuid = |field: &Option<String>, val: &str| {
+            field
+                .as_deref()
+                .is_some_and(|uuid| uuid.eq_ignore_ascii_case(val))
+        };
+        if let Some(label) = spec.strip_prefix("LABEL=") {
+            eq(&self.label, label)
+        } else if let Some(uuid) = spec.strip_prefix("UUID=") {
+            eq_uuid(&self.uuid, uuid)
+        } else if let Some(name) = spec.strip_prefix("PARTLABEL=") {
+            eq(&self.part_label, name)
+        } else if let Some(uuid) = spec.strip_prefix("PARTUUID=") {
+            eq_uuid(&self.part_uuid, uuid)
+        } else {
+            self.name == spec.strip_prefix("/dev/").unwrap_or(spec)
+        }
+    }
+}
+
+fn probe(name: String, mut disk: Disk, part: Option<&partition::Partition>) -> Volume {
+    let id = partition::probe_fs(&mut disk);
+    let info = VolumeInfo {
+        name,
+        size: disk.size(),
+        fs_type: id.fs_type,
+        label: id.label,
+        uuid: id.uuid,
+        part_uuid: part.and_then(|p| p.uuid.clone()),
+        part_label: part.and_then(|p| p.name.clone()),
+    };
+    info!(
+        "  {}: {} bytes, {:?}, label={:?}, uuid={:?}",
+        info.name, info.size, info.fs_type, info.label, info.uuid
+    );
+    Volume { info, disk }
+}
+
+/// Finds all volumes on the block devices: each device as a whole, and its
+/// partitions if it has no filesystem on the whole device.
+pub(crate) fn scan(mut blk_devs: AxDeviceContainer<AxBlockDevice>) -> &'static [Volume] {
+    let mut volumes = Vec::new();
+    let mut idx = 0;
+    while let Some(dev) = blk_devs.take_one() {
+        info!("  use block device {}: {:?}", idx, dev.device_name());
+        let name = format!("blk{}", idx);
+        let mut whole = probe(name.clone(), Disk::new(dev), None);
+        let parts = if whole.info.fs_type == FsType::Unknown {
+            partition::read_partitions(&mut whole.disk)
+        } else {
+            Vec::new()
+        };
+        let part_volumes: Vec<_> = parts
+            .iter()
+            .map(|part| {
+                let disk = whole.disk.slice(part.start, part.num_blocks);
+                probe(format!("{}p{}", name, part.number), disk, Some(part))
+            })
+            .collect();
+        volumes.push(whole);
+        volumes.extend(part_volumes);
+        idx += 1;
+    }
+    VOLUMES.init_once(volumes);
+    &VOLUMES
+}
+
+/// Selects the root volume by `spec`, or if it's empty, the first volume
+/// with a supported filesystem, falling back to the first block device.
+pub(crate) fn select_root(volumes: &[Volume], spec: &str) -> Option<usize> {
+    if !spec.is_empty() {
+        return volumes.iter().position(|v| v.info.matches(spec));
+    }
+    if cfg!(feature = "myfs") {
+        return (!volumes.is_empty()).then_some(0);
+    }
+    volumes
+        .iter()
+        .position(|v| crate::fs::is_supported(v.info.fs_type))
+        .or((!volumes.is_empty()).then_some(0))
+}
+
+/// Parses a comma-separated list of `<volume>:<path>`, where `<volume>` is
+/// a spec accepted by [`VolumeInfo::matches`].
+pub(crate) fn parse_mounts(mounts: &str) -> impl Iterator<Item = (&str, &str)> {
+    mounts
+        .split(',')
+        .map(str::trim)
+        .filter(|m| !m.is_empty())
+        .filter_map(|m| {
+            let res = m.rsplit_once(':');
+            if res.is_none() {
+                warn!("invalid mount {:?}, expected `<volume>:<path>`", m);
+            }
+            res
+        })
+}
+
+/// Finds the volume selected by `spec`, see [`VolumeInfo::matches`].
+pub(crate) fn find(spec: &str) -> Option<&'static Volume> {
+    VOLUMES.get()?.iter().find(|v| v.info.matches(spec))
+}
+
+/// Returns all volumes found at initialization.
+pub fn volumes() -> Vec<VolumeInfo> {
+    VOLUMES
+        .get()
+        .map(|vols| vols.iter().map(|v| v.info.clone()).collect())
+        .unwrap_or_default()
+}
+
+/// Writes the cached blocks of all volumes back to their devices.
+pub(crate) fn sync() -> AxResult {
+    let mut result = Ok(());
+    for vol in VOLUMES.get().into_iter().flatten() {
+        if vol.disk.flush().is_err() {
+            result = ax_err!(Io);
+        }
+    }
+    result
+}
+
+/// Returns the statistics of the block cache of the device of the volume
+/// selected by `spec`, which is shared by all volumes on the device.
+pub fn cache_stats(spec: &str) -> Option<CacheStats> {
+    find(spec).map(|v| v.disk.cache_stats())
+}
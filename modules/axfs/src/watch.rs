@@ -0,0 +1,251 @@
+//! Watches of paths for changes, like inotify.
+//!
+//! A watch on a path reports the changes of the file at the path, and of the
+//! entries in it if it's a directory. Watches follow paths rather than files:
+//! a file created at a watched path after the previous one was removed is
+//! watched too, while a watched directory that is renamed is no longer
+//! watched at its new path.
+//!
+//! Paths of events have the symlinks of their parents resolved. Changes made
+//! through a [`Directory`](crate::fops::Directory) with a relative path, and
+//! by writing files opened that way, are not reported.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use axerrno::{ax_err, AxResult};
+use axsync::spin::SpinNoIrq;
+
+/// The maximum number of events queued in a watcher, after which they're
+/// dropped and an [`Overflow`](EventKind::Overflow) event is queued.
+const MAX_QUEUED_EVENTS: usize = 1024;
+
+/// The kind of an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// A file or directory was created.
+    Create,
+    /// A file was written or truncated.
+    Modify,
+    /// A file or directory was removed.
+    Delete,
+    /// A file or directory was renamed from the path.
+    MovedFrom,
+    /// A file or directory was renamed to the path.
+    MovedTo,
+    /// Events were dropped as too many are queued. It's always reported.
+    Overflow,
+}
+
+impl EventKind {
+    /// The mask of all kinds of events that can be watched.
+    pub const ALL: u32 = 0x1f;
+
+    /// Returns the bit of the kind in the masks of watches.
+    pub const fn mask(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+/// An event of a watched path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchEvent {
+    /// The watch descriptor, or 0 for [`Overflow`](EventKind::Overflow).
+    pub wd: usize,
+    /// The kind of the event.
+    pub kind: EventKind,
+    /// The name of the entry in the watched directory, or empty if it's the
+    /// watched path itself.
+    pub name: String,
+    /// The same nonzero number for the two events of a rename, or zero.
+    pub cookie: u32,
+}
+
+struct Watches {
+    /// Watched paths and masks of events, by watch descriptors.
+    paths: BTreeMap<usize, (String, u32)>,
+    next_wd: usize,
+    events: VecDeque<WatchEvent>,
+}
+
+impl Watches {
+    fn push(&mut self, event: WatchEvent) {
+        // identical events not read yet are reported once
+        if self.events.back() == Some(&event) {
+            return;
+        }
+        if self.events.len() >= MAX_QUEUED_EVENTS {
+            if self.events.back().map(|e| e.kind) != Some(EventKind::Overflow) {
+                self.events.push_back(WatchEvent {
+                    wd: 0,
+                    kind: EventKind::Overflow,
+                    name: String::new(),
+                    cookie: 0,
+                });
+            }
+            return;
+        }
+        self.events.push_back(event);
+    }
+}
+
+struct WatcherInner {
+    watches: SpinNoIrq<Watches>,
+    #[cfg(feature = "multitask")]
+    wait_queue: axtask::WaitQueue,
+}
+
+impl Drop for WatcherInner {
+    fn drop(&mut self) {
+        WATCHERS.lock().retain(|w| w.strong_count() > 0);
+    }
+}
+
+static WATCHERS: SpinNoIrq<Vec<Weak<WatcherInner>>> = SpinNoIrq::new(Vec::new());
+
+/// A queue of events of watched paths, like an inotify instance.
+///
+/// Clones of a watcher share its watches and events.
+#[derive(Clone)]
+pub struct Watcher(Arc<WatcherInner>);
+
+impl Watcher {
+    /// Creates a watcher without watches.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        let inner = Arc::new(WatcherInner {
+            watches: SpinNoIrq::new(Watches {
+                paths: BTreeMap::new(),
+                next_wd: 1,
+                events: VecDeque::new(),
+            }),
+            #[cfg(feature = "multitask")]
+            wait_queue: axtask::WaitQueue::new(),
+        });
+        WATCHERS.lock().push(Arc::downgrade(&inner));
+        Self(inner)
+    }
+
+    /// Watches the file or directory at `path` (relative to the current
+    /// directory, with symlinks followed) for the kinds of events in `mask`,
+    /// see [`EventKind::mask`]. Returns the watch descriptor.
+    ///
+    /// If the path is already watched, its mask is replaced and the same
+    /// descriptor is returned.
+    pub fn add_watch(&self, path: &str, mask: u32) -> AxResult<usize> {
+        if mask & EventKind::ALL == 0 {
+            return ax_err!(InvalidInput);
+        }
+        let (_, abs_path) = crate::root::lookup_with_path(None, path)?;
+        let path = abs_path.unwrap_or_default();
+        let mut watches = self.0.watches.lock();
+        if let Some((&wd, watch)) = watches.paths.iter_mut().find(|(_, w)| w.0 == path) {
+            watch.1 = mask;
+            return Ok(wd);
+        }
+        let wd = watches.next_wd;
+        watches.next_wd += 1;
+        watches.paths.insert(wd, (path, mask));
+        Ok(wd)
+    }
+
+    /// Removes the watch of the descriptor `wd`.
+    pub fn remove_watch(&self, wd: usize) -> AxResult {
+        match self.0.watches.lock().paths.remove(&wd) {
+            Some(_) => Ok(()),
+            None => ax_err!(InvalidInput, "no such watch"),
+        }
+    }
+
+    /// Whether there are events to read.
+    pub fn has_events(&self) -> bool {
+        !self.0.watches.lock().events.is_empty()
+    }
+
+    /// Takes the first queued event.
+    ///
+    /// If there is none, it waits for one if `wait` is true, or fails with
+    /// [`WouldBlock`](axerrno::AxError::WouldBlock) otherwise. Without
+    /// `multitask`, waiting fails with `WouldBlock` too, as nothing else
+    /// could change the files.
+    pub fn next_event(&self, wait: bool) -> AxResult<WatchEvent> {
+        loop {
+            if let Some(event) = self.0.watches.lock().events.pop_front() {
+                return Ok(event);
+            }
+            if !wait {
+                return ax_err!(WouldBlock);
+            }
+            self.wait_for_events()?;
+        }
+    }
+
+    fn wait_for_events(&self) -> AxResult {
+        #[cfg(feature = "multitask")]
+        {
+            self.0.wait_queue.wait_until(|| self.has_events());
+            Ok(())
+        }
+        #[cfg(not(feature = "multitask"))]
+        ax_err!(WouldBlock, "waiting for events of the only task")
+    }
+}
+
+/// Whether any watcher exists, so that events may be reported.
+pub(crate) fn is_watched() -> bool {
+    !WATCHERS.lock().is_empty()
+}
+
+/// Reports an event of `kind` for the absolute `path` to the watchers of it
+/// and of its parent directory.
+pub(crate) fn notify(kind: EventKind, path: &str, cookie: u32) {
+    let watchers: Vec<_> = WATCHERS.lock().iter().filter_map(Weak::upgrade).collect();
+    let (parent, name) = match path.rfind('/') {
+        Some(0) => ("/", &path[1..]),
+        Some(n) => (&path[..n], &path[n + 1..]),
+        None => ("", path),
+    };
+    for watcher in watchers {
+        let mut watches = watcher.watches.lock();
+        let events: Vec<_> = watches
+            .paths
+            .iter()
+            .filter(|(_, (_, mask))| mask & kind.mask() != 0)
+            .filter_map(|(&wd, (watched, _))| {
+                let name = if watched == path {
+                    ""
+                } else if watched == parent && !name.is_empty() {
+                    name
+                } else {
+                    return None;
+                };
+                Some(WatchEvent {
+                    wd,
+                    kind,
+                    name: name.into(),
+                    cookie,
+                })
+            })
+            .collect();
+        if events.is_empty() {
+            continue;
+        }
+        for event in events {
+            watches.push(event);
+        }
+        drop(watches);
+        #[cfg(feature = "multitask")]
+        watcher.wait_queue.notify_all(false);
+    }
+}
+
+/// Reports the rename of the absolute path `old` to `new`.
+pub(crate) fn notify_rename(old: &str, new: &str) {
+    static NEXT_COOKIE: AtomicU32 = AtomicU32::new(1);
+    let cookie = NEXT_COOKIE.fetch_add(1, Ordering::Relaxed).max(1);
+    notify(EventKind::MovedFrom, old, cookie);
+    notify(EventKind::MovedTo, new, cookie);
+}
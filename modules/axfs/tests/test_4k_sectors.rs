@@ -0,0 +1,86 @@
+#![cfg(not(feature = "myfs"))]
+
+mod test_common;
+
+use axdriver::ramdisk::RamDisk;
+use axdriver::AxDeviceContainer;
+use axfs::api as fs;
+use axio::{Read, Seek, SeekFrom, Write};
+
+const IMG_PATH: &str = if cfg!(feature = "ext4fs") {
+    "resources/ext4.img"
+} else {
+    "resources/fat16.img"
+};
+
+const BLOCK_SIZE: usize = 4096;
+
+fn make_disk() -> std::io::Result<RamDisk> {
+    let path = std::env::current_dir()?.join(IMG_PATH);
+    println!("Loading disk image from {:?} ...", path);
+    let data = std::fs::read(path)?;
+    println!("size = {} bytes", data.len());
+    Ok(RamDisk::from(&data).with_block_size(BLOCK_SIZE))
+}
+
+fn test_unaligned_io() -> axio::Result<()> {
+    println!("test unaligned I/O:");
+    let data: Vec<u8> = (0..3 * BLOCK_SIZE).map(|i| (i % 253) as u8).collect();
+    let mut file = fs::File::create("/unaligned.bin")?;
+    file.write_all(&data)?;
+    // writes across the blocks of the device
+    file.seek(SeekFrom::Start(BLOCK_SIZE as u64 - 100))?;
+    file.write_all(&[0xaa; 200])?;
+    file.seek(SeekFrom::Start(2 * BLOCK_SIZE as u64 + 1))?;
+    file.write_all(&[0x55; 3])?;
+    file.flush()?;
+    drop(file);
+
+    let mut expected = data;
+    expected[BLOCK_SIZE - 100..BLOCK_SIZE + 100].fill(0xaa);
+    expected[2 * BLOCK_SIZE + 1..2 * BLOCK_SIZE + 4].fill(0x55);
+    assert!(fs::read("/unaligned.bin")? == expected);
+
+    let mut file = fs::File::open("/unaligned.bin")?;
+    let mut buf = [0; 300];
+    file.seek(SeekFrom::Start(BLOCK_SIZE as u64 - 150))?;
+    file.read_exact(&mut buf)?;
+    assert_eq!(buf[..], expected[BLOCK_SIZE - 150..BLOCK_SIZE + 150]);
+    drop(file);
+    fs::remove_file("/unaligned.bin")?;
+    assert_eq!(axfs::cache_stats("blk0").unwrap().dirty, 0);
+    println!("test_unaligned_io() OK!");
+    Ok(())
+}
+
+#[cfg(feature = "devfs")]
+fn test_block_size() -> axio::Result<()> {
+    use axfs::fops;
+
+    println!("test block size:");
+    let mut opts = fops::OpenOptions::new();
+    opts.read(true);
+    let disk = fops::File::open("/dev/sda", &opts)?;
+    let mut block_size = 0i32;
+    assert_eq!(
+        disk.ioctl(0x1268, &mut block_size as *mut i32 as usize),
+        Ok(0)
+    ); // BLKSSZGET
+    assert_eq!(block_size, BLOCK_SIZE as i32);
+    println!("test_block_size() OK!");
+    Ok(())
+}
+
+#[test]
+fn test_4k_sectors() {
+    println!("Testing 4K sectors with ramdisk ...");
+
+    let disk = make_disk().expect("failed to load disk image");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    axfs::init_filesystems(AxDeviceContainer::from_one(disk));
+
+    test_common::test_all();
+    test_unaligned_io().expect("test_unaligned_io() failed");
+    #[cfg(feature = "devfs")]
+    test_block_size().expect("test_block_size() failed");
+}
@@ -0,0 +1,123 @@
+#![cfg(not(any(feature = "myfs", feature = "ext4fs")))]
+
+use axdriver::ramdisk::RamDisk;
+use axdriver::AxDeviceContainer;
+use axfs::api as fs;
+use axio::{Read, Seek, SeekFrom, Write};
+
+const IMG_PATH: &str = "resources/fat16.img";
+
+/// Larger than the cache of 1024 blocks, so that blocks are evicted.
+const FILE_SIZE: usize = 1024 * 1024;
+const FILE_BLOCKS: u64 = FILE_SIZE as u64 / 512;
+
+fn make_disk() -> std::io::Result<RamDisk> {
+    let path = std::env::current_dir()?.join(IMG_PATH);
+    println!("Loading disk image from {:?} ...", path);
+    let data = std::fs::read(path)?;
+    println!("size = {} bytes", data.len());
+    Ok(RamDisk::from(&data))
+}
+
+fn stats() -> axfs::CacheStats {
+    axfs::cache_stats("blk0").expect("no stats of blk0")
+}
+
+fn test_write_back(data: &[u8]) {
+    println!("test write-back:");
+    let s0 = stats();
+    let mut file = fs::File::create("/big.bin").unwrap();
+    for chunk in data.chunks(4096) {
+        file.write_all(chunk).unwrap();
+    }
+    file.flush().unwrap();
+    let s1 = stats();
+    println!("  {:?}", s1);
+    assert_eq!(s1.dirty, 0);
+    assert!(s1.evictions > s0.evictions);
+    // consecutive blocks are written at once
+    assert!(s1.dev_writes - s0.dev_writes < FILE_BLOCKS / 16);
+    drop(file);
+
+    // rewriting cached blocks does not touch the device
+    let mut file = fs::OpenOptions::new().write(true).open("/big.bin").unwrap();
+    file.seek(SeekFrom::Start(FILE_SIZE as u64 - 4096)).unwrap();
+    file.write_all(&data[FILE_SIZE - 4096..]).unwrap();
+    let s2 = stats();
+    assert_eq!(s2.dev_writes, s1.dev_writes);
+    assert_eq!(s2.dev_reads, s1.dev_reads);
+    assert!(s2.dirty > 0);
+    fs::sync().unwrap();
+    assert_eq!(stats().dirty, 0);
+    println!("test_write_back() OK!");
+}
+
+fn test_read_ahead(data: &[u8]) {
+    println!("test read-ahead:");
+    let s0 = stats();
+    let mut file = fs::File::open("/big.bin").unwrap();
+    let mut buf = vec![0; 4096];
+    let mut read = Vec::with_capacity(FILE_SIZE);
+    loop {
+        let n = file.read(&mut buf).unwrap();
+        if n == 0 {
+            break;
+        }
+        read.extend_from_slice(&buf[..n]);
+    }
+    assert!(read == data);
+    let s1 = stats();
+    println!("  {:?}", s1);
+    assert!(s1.read_ahead > s0.read_ahead);
+    // the head of the file was evicted, but read ahead in large requests
+    assert!(s1.dev_reads - s0.dev_reads < FILE_BLOCKS / 8);
+    assert!(s1.hits - s0.hits > FILE_BLOCKS / 2);
+
+    // the tail of the file is still cached
+    let s1 = stats();
+    file.seek(SeekFrom::End(-4096)).unwrap();
+    file.read_exact(&mut buf).unwrap();
+    assert_eq!(buf, data[FILE_SIZE - 4096..]);
+    assert_eq!(stats().dev_reads, s1.dev_reads);
+    println!("test_read_ahead() OK!");
+}
+
+fn test_flush_on_close() {
+    println!("test flush on close:");
+    const TEXT: &[u8] = b"written back when the last opened file is closed\n";
+    let mut file = fs::File::create("/close.txt").unwrap();
+    let reader = fs::File::open("/close.txt").unwrap();
+    file.write_all(TEXT).unwrap();
+    drop(file);
+    drop(reader);
+    assert_eq!(stats().dirty, 0);
+
+    // read back from the disk, after dropping its cache
+    let mut opts = axfs::fops::OpenOptions::new();
+    opts.read(true);
+    let disk = axfs::fops::File::open("/dev/sda", &opts).unwrap();
+    assert_eq!(disk.ioctl(0x1261, 0), Ok(0)); // BLKFLSBUF
+    assert_eq!(stats().cached, 0);
+    let mut raw = fs::File::open("/dev/sda").unwrap();
+    let mut data = Vec::new();
+    raw.read_to_end(&mut data).unwrap();
+    assert!(data.windows(TEXT.len()).any(|w| w == TEXT));
+    fs::remove_file("/close.txt").unwrap();
+    println!("test_flush_on_close() OK!");
+}
+
+#[test]
+fn test_cache() {
+    println!("Testing block cache with ramdisk ...");
+
+    let disk = make_disk().expect("failed to load disk image");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    axfs::init_filesystems(AxDeviceContainer::from_one(disk));
+
+    let data: Vec<u8> = (0..FILE_SIZE).map(|i| (i % 251) as u8).collect();
+    test_write_back(&data);
+    test_read_ahead(&data);
+    test_flush_on_close();
+    fs::remove_file("/big.bin").unwrap();
+    fs::sync().unwrap();
+}
@@ -242,7 +242,7 @@ fn test_devfs_ramfs() -> Result<()> {
     assert_eq!(fs::read_dir("tmp").unwrap().count(), 1);
     assert_eq!(fs::write(".///tmp///dir//.///test.txt", "test"), Ok(()));
     assert_eq!(fs::read("tmp//././/dir//.///test.txt"), Ok("test".into()));
-    // assert_err!(fs::remove_dir("dev/../tmp//dir"), DirectoryNotEmpty); // TODO
+    assert_err!(fs::remove_dir("dev/../tmp//dir"), DirectoryNotEmpty);
     assert_err!(fs::remove_dir("/tmp/dir/../dir"), DirectoryNotEmpty);
     assert_eq!(fs::remove_file("./tmp//dir//test.txt"), Ok(()));
     assert_eq!(fs::remove_dir("tmp/dir/.././dir///"), Ok(()));
@@ -252,6 +252,659 @@ fn test_devfs_ramfs() -> Result<()> {
     Ok(())
 }
 
+#[cfg(feature = "devfs")]
+fn test_devfs() -> Result<()> {
+    use axfs::devfs::{self, DeviceOps};
+    use axfs::fops;
+    use std::sync::{Arc, Mutex};
+
+    println!("test devfs ...");
+
+    // random
+    let mut buf1 = [0; 16];
+    let mut buf2 = [0; 16];
+    let mut file = File::open("/dev/urandom")?;
+    file.read_exact(&mut buf1)?;
+    file.read_exact(&mut buf2)?;
+    assert_ne!(buf1, buf2);
+
+    // the disk
+    let md = fs::metadata("/dev/sda")?;
+    assert_eq!(md.file_type(), FileType::BlockDevice);
+    let mut opts = fops::OpenOptions::new();
+    opts.read(true);
+    let disk = fops::File::open("/dev/sda", &opts)?;
+    let mut size = 0u64;
+    assert_eq!(
+        disk.ioctl(0x8008_1272, &mut size as *mut u64 as usize),
+        Ok(0)
+    ); // BLKGETSIZE64
+    assert_eq!(size, md.len());
+    assert_err!(disk.ioctl(0x5401, 0), Unsupported);
+
+    // devices registered by drivers
+    struct Led(Mutex<Vec<u8>>);
+    impl DeviceOps for Led {
+        fn write_at(&self, _offset: u64, buf: &[u8]) -> Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn ioctl(&self, cmd: u32, _arg: usize) -> Result<usize> {
+            Ok(cmd as usize + 1)
+        }
+    }
+    let led = Arc::new(Led(Mutex::new(Vec::new())));
+    devfs::register_char("leds/led0", led.clone())?;
+    assert_err!(
+        devfs::register_char("leds/led0", led.clone()),
+        AlreadyExists
+    );
+    assert_eq!(
+        fs::metadata("/dev/leds/led0")?.file_type(),
+        FileType::CharDevice
+    );
+    fs::write("/dev/leds/led0", "1")?;
+    assert_eq!(*led.0.lock().unwrap(), b"1");
+    opts.write(true);
+    let file = fops::File::open("/dev/leds/led0", &opts)?;
+    assert_eq!(file.ioctl(41, 0), Ok(42));
+    assert_err!(fs::read("/dev/leds/led0"), InvalidInput);
+    assert_err!(fs::remove_file("/dev/leds/led0"), PermissionDenied);
+
+    devfs::unregister("leds")?;
+    assert_err!(fs::metadata("/dev/leds/led0"), NotFound);
+    assert_err!(devfs::unregister("leds"), NotFound);
+
+    println!("test_devfs() OK!");
+    Ok(())
+}
+
+fn test_links() -> Result<()> {
+    println!("test links:");
+    fs::create_dir("/tmp/links")?;
+    fs::write("/tmp/links/file.txt", "Rust is cool!\n")?;
+
+    // symlinks, also to other filesystems
+    fs::symlink("file.txt", "/tmp/links/rel")?;
+    fs::symlink("/tmp/links", "/tmp/abs")?;
+    fs::symlink("/dev", "/tmp/links/dev")?;
+    assert_eq!(fs::read_to_string("/tmp/links/rel")?, "Rust is cool!\n");
+    assert_eq!(fs::read_to_string("/tmp/abs/rel")?, "Rust is cool!\n");
+    assert_eq!(fs::read_link("/tmp/abs")?, "/tmp/links");
+    assert!(fs::metadata("/tmp/abs")?.is_dir());
+    assert!(fs::symlink_metadata("/tmp/abs")?.is_symlink());
+    assert_eq!(fs::symlink_metadata("/tmp/links/rel")?.len(), 8);
+    assert_eq!(
+        fs::metadata("/tmp/links/dev/null")?.file_type(),
+        FileType::CharDevice
+    );
+    fs::write("/tmp/abs/new.txt", "new")?;
+    assert_eq!(fs::read_to_string("/tmp/links/new.txt")?, "new");
+    fs::set_current_dir("/tmp/abs")?;
+    assert_eq!(fs::current_dir()?, "/tmp/links/");
+    assert_eq!(fs::read_to_string("rel")?, "Rust is cool!\n");
+    fs::set_current_dir("/")?;
+
+    // error cases
+    assert_err!(fs::read_link("/tmp/links/file.txt"), InvalidInput);
+    assert_err!(fs::symlink("file.txt", "/tmp/abs"), AlreadyExists);
+    assert_err!(fs::metadata("/tmp/links/rel/"), NotADirectory);
+    fs::symlink("loop2", "/tmp/links/loop1")?;
+    fs::symlink("loop1", "/tmp/links/loop2")?;
+    assert_err!(fs::metadata("/tmp/links/loop1"), InvalidData);
+    fs::symlink("missing", "/tmp/links/dangling")?;
+    assert_err!(fs::metadata("/tmp/links/dangling"), NotFound);
+    assert!(fs::symlink_metadata("/tmp/links/dangling")?.is_symlink());
+
+    // hard links
+    fs::hard_link("/tmp/links/file.txt", "/tmp/links/hard")?;
+    fs::write("/tmp/links/hard", "changed")?;
+    assert_eq!(fs::read_to_string("/tmp/links/file.txt")?, "changed");
+    fs::remove_file("/tmp/links/file.txt")?;
+    assert_eq!(fs::read_to_string("/tmp/links/hard")?, "changed");
+    assert_err!(fs::hard_link("/tmp/links", "/tmp/dir"), PermissionDenied);
+    assert_err!(fs::hard_link("/tmp/links/hard", "/hard"), Unsupported);
+
+    // removing a symlink keeps its target
+    fs::remove_file("/tmp/abs")?;
+    assert!(fs::metadata("/tmp/links")?.is_dir());
+    for name in [
+        "rel", "dev", "new.txt", "loop1", "loop2", "dangling", "hard",
+    ] {
+        fs::remove_file(&format!("/tmp/links/{}", name))?;
+    }
+    fs::remove_dir("/tmp/links")?;
+    println!("test_links() OK!");
+    Ok(())
+}
+
+fn test_times() -> Result<()> {
+    use core::time::Duration;
+    println!("test times:");
+    // FAT keeps the date of the last access, and even seconds
+    let atime = Duration::from_secs(1_699_920_000);
+    let mtime = Duration::from_secs(1_700_000_000);
+    // the root of `myfs` is `axfs_ramfs`, which has no timestamps
+    let paths: &[&str] = if cfg!(feature = "myfs") {
+        &["/tmp/times.txt"]
+    } else {
+        &["/times.txt", "/tmp/times.txt"]
+    };
+    for path in paths {
+        fs::write(path, "time")?;
+        let times = fs::FileTimes::new().set_accessed(atime).set_modified(mtime);
+        fs::set_times(path, times)?;
+        let md = fs::metadata(path)?;
+        assert_eq!((md.accessed(), md.modified()), (atime, mtime));
+
+        let file = File::open(path)?;
+        file.set_modified(mtime + Duration::from_secs(2))?;
+        let md = file.metadata()?;
+        assert_eq!(md.accessed(), atime);
+        assert_eq!(md.modified(), mtime + Duration::from_secs(2));
+        fs::remove_file(path)?;
+    }
+    #[cfg(feature = "procfs")]
+    assert_err!(
+        fs::set_times("/proc/uptime", fs::FileTimes::new()),
+        PermissionDenied
+    );
+    println!("test_times() OK!");
+    Ok(())
+}
+
+fn test_mount() -> Result<()> {
+    println!("test runtime mount:");
+
+    // nested mounts
+    fs::mount("ram0", "/mnt/a", "ramfs")?;
+    fs::write("/mnt/a/x.txt", "a")?;
+    fs::mount("ram1", "./mnt//a/../a/b/", "ramfs")?;
+    fs::write("/mnt/a/b/y.txt", "b")?;
+    assert_eq!(fs::read_to_string("/mnt/a/b/y.txt")?, "b");
+    assert_err!(fs::metadata("/mnt/a/y.txt"), NotFound);
+    assert_err!(fs::mount("ram2", "/mnt/a", "ramfs"), ResourceBusy);
+    assert_err!(fs::mount("ram2", "/../mnt/a", "ramfs"), ResourceBusy);
+    assert_eq!(fs::read_to_string("/../mnt/a/x.txt")?, "a");
+    assert!(fs::metadata("/..")?.is_dir());
+    assert_err!(fs::rename("/mnt/a/x.txt", "/mnt/a/b/x.txt"), Unsupported);
+
+    // mount points are matched by whole components
+    fs::create_dir("/tmpfoo")?;
+    fs::write("/mnt/ab", "ab")?;
+    assert_eq!(fs::read_dir("/tmp")?.count(), 0);
+    assert_err!(fs::metadata("/mnt/a/b/../../ab/"), NotADirectory);
+    assert_eq!(fs::read_to_string("/mnt/ab")?, "ab");
+
+    #[cfg(feature = "procfs")]
+    {
+        let mounts = fs::read_to_string("/proc/mounts")?;
+        print!("{}", mounts);
+        assert!(mounts.contains("ram0 /mnt/a ramfs rw 0 0\n"));
+        assert!(mounts.contains("ram1 /mnt/a/b ramfs rw 0 0\n"));
+    }
+
+    // busy filesystems
+    assert_err!(fs::umount("/mnt/a"), ResourceBusy);
+    let file = File::open("/mnt/a/b/y.txt")?;
+    assert_err!(fs::umount("/mnt/a/b"), ResourceBusy);
+    drop(file);
+    fs::set_current_dir("/mnt/a/b")?;
+    assert_eq!(fs::read_to_string("y.txt")?, "b");
+    assert_eq!(fs::read_to_string("../x.txt")?, "a");
+    assert_err!(fs::umount("."), ResourceBusy);
+    fs::set_current_dir("/")?;
+    fs::umount("/mnt/a/b")?;
+    assert_err!(fs::umount("/mnt/a/b"), InvalidInput);
+    assert!(fs::metadata("/mnt/a/b")?.is_dir());
+    assert_err!(fs::metadata("/mnt/a/b/y.txt"), NotFound);
+
+    // detached filesystems are still usable by open files
+    let mut file = File::open("/mnt/a/x.txt")?;
+    fs::umount_detach("/mnt/a")?;
+    assert_err!(fs::metadata("/mnt/a/x.txt"), NotFound);
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    assert_eq!(contents, "a");
+    drop(file);
+    #[cfg(feature = "procfs")]
+    assert!(!fs::read_to_string("/proc/mounts")?.contains("/mnt/a"));
+
+    fs::remove_dir("/tmpfoo")?;
+    fs::remove_file("/mnt/ab")?;
+    fs::remove_dir("/mnt/a")?;
+    fs::remove_dir("/mnt")?;
+    println!("test_mount() OK!");
+    Ok(())
+}
+
+fn test_locks() -> Result<()> {
+    use axfs::fops::{self, LockKind, RangeLock};
+    println!("test file locks:");
+
+    let lock = |owner, kind, start, end| RangeLock {
+        owner,
+        kind,
+        start,
+        end,
+    };
+    let mut opts = fops::OpenOptions::new();
+    opts.read(true);
+    opts.write(true);
+    opts.create(true);
+    for path in ["/lock.txt", "/tmp/lock.txt"] {
+        let file_a = fops::File::open(path, &opts)?;
+        let file_b = fops::File::open(&format!("/./{}", path), &opts)?;
+        let (a, b) = (file_a.lock_handle(), file_b.lock_handle());
+
+        // whole-file locks
+        a.lock(LockKind::Shared, false)?;
+        b.lock(LockKind::Shared, false)?;
+        assert_err!(b.lock(LockKind::Exclusive, false), WouldBlock);
+        a.unlock();
+        b.lock(LockKind::Exclusive, false)?;
+        assert_err!(a.lock(LockKind::Shared, false), WouldBlock);
+        drop(file_b); // released on close
+        a.lock(LockKind::Exclusive, false)?;
+
+        // byte-range locks don't conflict with whole-file ones
+        let file_b = fops::File::open(path, &opts)?;
+        let b = file_b.lock_handle();
+        a.lock_range(lock(1, LockKind::Exclusive, 0, 10), false)?;
+        b.lock_range(lock(1, LockKind::Shared, 5, 20), false)?;
+        let conflict = b.test_lock_range(&lock(2, LockKind::Shared, 0, 100));
+        assert_eq!(conflict, Some(lock(1, LockKind::Exclusive, 0, 5)));
+        assert_err!(
+            b.lock_range(lock(2, LockKind::Exclusive, 19, u64::MAX), false),
+            WouldBlock
+        );
+        b.lock_range(lock(2, LockKind::Exclusive, 20, u64::MAX), false)?;
+        a.unlock_range(1, 0, 3);
+        b.lock_range(lock(2, LockKind::Shared, 0, 3), false)?;
+        a.unlock_range(1, 0, u64::MAX);
+        let conflict = a.test_lock_range(&lock(3, LockKind::Exclusive, 3, 4));
+        assert_eq!(conflict, None);
+        b.unlock_range(2, 0, u64::MAX);
+
+        // byte-range locks are kept until the last opened file is closed
+        a.lock_range(lock(3, LockKind::Exclusive, 0, 1), false)?;
+        drop(file_a);
+        let conflict = b.test_lock_range(&lock(4, LockKind::Shared, 0, 1));
+        assert_eq!(conflict.map(|l| l.owner), Some(3));
+        drop(file_b);
+        let file_a = fops::File::open(path, &opts)?;
+        let conflict = file_a
+            .lock_handle()
+            .test_lock_range(&lock(4, LockKind::Shared, 0, 1));
+        assert_eq!(conflict, None);
+        drop(file_a);
+        fs::remove_file(path)?;
+    }
+
+    // exclusive locks need write access
+    fs::write("/tmp/lock.txt", "")?;
+    let mut opts = fops::OpenOptions::new();
+    opts.read(true);
+    let file = fops::File::open("/tmp/lock.txt", &opts)?;
+    let handle = file.lock_handle();
+    assert_err!(
+        handle.lock_range(lock(4, LockKind::Exclusive, 0, 1), false),
+        PermissionDenied
+    );
+    handle.lock_range(lock(4, LockKind::Shared, 0, 1), false)?;
+    handle.unlock_range(4, 0, 1);
+    drop(file);
+    fs::remove_file("/tmp/lock.txt")?;
+
+    test_lock_waits()?;
+    println!("test_locks() OK!");
+    Ok(())
+}
+
+fn test_lock_waits() -> Result<()> {
+    use axfs::fops::{self, LockKind, RangeLock};
+
+    let lock = |owner, start, end| RangeLock {
+        owner,
+        kind: LockKind::Exclusive,
+        start,
+        end,
+    };
+    let mut opts = fops::OpenOptions::new();
+    opts.read(true);
+    opts.write(true);
+    opts.create(true);
+    let file_a = fops::File::open("/lock.txt", &opts)?;
+    let file_b = fops::File::open("/lock.txt", &opts)?;
+    let a = file_a.lock_handle();
+    a.lock_range(lock(1, 0, 10), false)?;
+
+    #[cfg(not(feature = "multitask"))]
+    {
+        // nothing else could release the lock
+        let b = file_b.lock_handle();
+        assert_err!(b.lock_range(lock(2, 5, 15), true), ResourceBusy);
+        a.unlock_range(1, 0, 10);
+    }
+    #[cfg(feature = "multitask")]
+    {
+        use core::sync::atomic::{AtomicBool, Ordering};
+        static LOCKED: AtomicBool = AtomicBool::new(false);
+        static HELD: AtomicBool = AtomicBool::new(false);
+
+        // unlocking wakes up the waiting task
+        let b = file_b.lock_handle();
+        let task = axtask::spawn(move || {
+            b.lock_range(lock(2, 5, 15), true).unwrap();
+            LOCKED.store(true, Ordering::Release);
+            b.unlock_range(2, 0, u64::MAX);
+        });
+        for _ in 0..10 {
+            axtask::yield_now();
+        }
+        assert!(!LOCKED.load(Ordering::Acquire));
+        a.unlock_range(1, 0, 10);
+        task.join();
+        assert!(LOCKED.load(Ordering::Acquire));
+
+        // two owners waiting for each other
+        a.lock_range(lock(1, 0, 10), false)?;
+        let b = file_b.lock_handle();
+        let task = axtask::spawn(move || {
+            b.lock_range(lock(2, 10, 20), false).unwrap();
+            HELD.store(true, Ordering::Release);
+            // blocks until the main task gives up
+            b.lock_range(lock(2, 0, 10), true).unwrap();
+            b.unlock_range(2, 0, u64::MAX);
+        });
+        while !HELD.load(Ordering::Acquire) {
+            axtask::yield_now();
+        }
+        assert_err!(a.lock_range(lock(1, 10, 20), true), ResourceBusy);
+        a.unlock_range(1, 0, 10);
+        task.join();
+    }
+
+    // all released
+    a.lock_range(lock(1, 0, u64::MAX), false)?;
+    a.unlock_range(1, 0, u64::MAX);
+    drop((file_a, file_b));
+    fs::remove_file("/lock.txt")
+}
+
+fn test_watches() -> Result<()> {
+    use axfs::fops::{EventKind, WatchEvent, Watcher};
+    println!("test watches:");
+
+    let event = |wd, kind, name: &str, cookie| WatchEvent {
+        wd,
+        kind,
+        name: name.into(),
+        cookie,
+    };
+    for dir in ["/watched", "/tmp/watched"] {
+        fs::create_dir(dir)?;
+        let watcher = Watcher::new();
+        let wd = watcher.add_watch(dir, EventKind::ALL)?;
+        assert_eq!(watcher.add_watch(&format!("{}/", dir), EventKind::ALL)?, wd);
+        assert_err!(watcher.next_event(false), WouldBlock);
+
+        let path = format!("{}/a.txt", dir);
+        let new_path = format!("{}/b.txt", dir);
+        fs::write(&path, "hello")?;
+        fs::rename(&path, &new_path)?;
+        fs::remove_file(&new_path)?;
+        assert_eq!(
+            watcher.next_event(false)?,
+            event(wd, EventKind::Create, "a.txt", 0)
+        );
+        assert_eq!(
+            watcher.next_event(false)?,
+            event(wd, EventKind::Modify, "a.txt", 0)
+        );
+        let moved = watcher.next_event(false)?;
+        assert_eq!(
+            moved,
+            event(wd, EventKind::MovedFrom, "a.txt", moved.cookie)
+        );
+        assert_ne!(moved.cookie, 0);
+        let cookie = moved.cookie;
+        assert_eq!(
+            watcher.next_event(false)?,
+            event(wd, EventKind::MovedTo, "b.txt", cookie)
+        );
+        assert_eq!(
+            watcher.next_event(false)?,
+            event(wd, EventKind::Delete, "b.txt", 0)
+        );
+        assert!(!watcher.has_events());
+
+        // only the kinds in the mask are reported
+        watcher.add_watch(dir, EventKind::Delete.mask())?;
+        fs::write(&path, "hello")?;
+        fs::remove_file(&path)?;
+        assert_eq!(
+            watcher.next_event(false)?,
+            event(wd, EventKind::Delete, "a.txt", 0)
+        );
+        assert!(!watcher.has_events());
+
+        watcher.remove_watch(wd)?;
+        assert_err!(watcher.remove_watch(wd), InvalidInput);
+        fs::remove_dir(dir)?;
+        assert!(!watcher.has_events());
+    }
+    println!("test_watches() OK!");
+    Ok(())
+}
+
+#[cfg(feature = "overlayfs")]
+fn test_overlay() -> Result<()> {
+    println!("test overlay:");
+    fs::mount("lower", "/tmp/lower", "ramfs")?;
+    fs::create_dir("/tmp/lower/dir")?;
+    fs::write("/tmp/lower/dir/a.txt", "lower a")?;
+    fs::write("/tmp/lower/dir/b.txt", "lower b")?;
+    fs::write("/tmp/lower/c.txt", "lower c")?;
+    fs::create_dir("/tmp/upper")?;
+    fs::mount_overlay("/tmp/lower", "/tmp/upper", "/mnt/merged")?;
+    assert_eq!(fs::read_to_string("/mnt/merged/dir/a.txt")?, "lower a");
+
+    // copy-up
+    fs::write("/mnt/merged/dir/a.txt", "upper a")?;
+    assert_eq!(fs::read_to_string("/mnt/merged/dir/a.txt")?, "upper a");
+    assert_eq!(fs::read_to_string("/tmp/upper/dir/a.txt")?, "upper a");
+    assert_eq!(fs::read_to_string("/tmp/lower/dir/a.txt")?, "lower a");
+    fs::write("/mnt/merged/new.txt", "new")?;
+    assert_eq!(fs::read_to_string("/tmp/upper/new.txt")?, "new");
+    assert_err!(fs::metadata("/tmp/lower/new.txt"), NotFound);
+
+    // whiteouts
+    fs::remove_file("/mnt/merged/dir/b.txt")?;
+    assert_err!(fs::metadata("/mnt/merged/dir/b.txt"), NotFound);
+    assert!(fs::metadata("/tmp/upper/dir/.wh.b.txt")?.is_file());
+    assert_eq!(fs::read_to_string("/tmp/lower/dir/b.txt")?, "lower b");
+    let names: Vec<_> = fs::read_dir("/mnt/merged/dir")?
+        .map(|e| e.unwrap().file_name())
+        .collect();
+    assert_eq!(names, ["a.txt"]);
+    fs::write("/mnt/merged/dir/b.txt", "again")?;
+    assert_eq!(fs::read_to_string("/mnt/merged/dir/b.txt")?, "again");
+
+    // opaque directories
+    assert_err!(fs::remove_dir("/mnt/merged/dir"), DirectoryNotEmpty);
+    fs::remove_file("/mnt/merged/dir/a.txt")?;
+    fs::remove_file("/mnt/merged/dir/b.txt")?;
+    fs::remove_dir("/mnt/merged/dir")?;
+    fs::create_dir("/mnt/merged/dir")?;
+    assert_eq!(fs::read_dir("/mnt/merged/dir")?.count(), 0);
+    assert!(fs::metadata("/tmp/lower/dir/a.txt")?.is_file());
+
+    // renames and links
+    fs::rename("/mnt/merged/c.txt", "/mnt/merged/dir/d.txt")?;
+    assert_eq!(fs::read_to_string("/mnt/merged/dir/d.txt")?, "lower c");
+    assert_err!(fs::metadata("/mnt/merged/c.txt"), NotFound);
+    assert!(fs::metadata("/tmp/lower/c.txt")?.is_file());
+    fs::symlink("dir/d.txt", "/mnt/merged/link")?;
+    assert_eq!(fs::read_to_string("/mnt/merged/link")?, "lower c");
+    fs::create_dir("/mnt/merged/dir2")?;
+    fs::rename("/mnt/merged/dir2", "/mnt/merged/dir3")?;
+    #[cfg(feature = "procfs")]
+    assert!(fs::read_to_string("/proc/mounts")?.contains("overlay /mnt/merged overlay rw 0 0\n"));
+
+    // the layers are busy
+    assert_err!(fs::umount("/tmp/lower"), ResourceBusy);
+    fs::umount("/mnt/merged")?;
+    fs::umount("/tmp/lower")?;
+    for path in ["/mnt/merged", "/mnt", "/tmp/lower"] {
+        fs::remove_dir(path)?;
+    }
+    remove_all("/tmp/upper")?;
+    println!("test_overlay() OK!");
+    Ok(())
+}
+
+#[cfg(feature = "overlayfs")]
+fn remove_all(path: &str) -> Result<()> {
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type().is_dir() {
+            remove_all(&path)?;
+        } else {
+            fs::remove_file(&path)?;
+        }
+    }
+    fs::remove_dir(path)
+}
+
+#[cfg(feature = "procfs")]
+fn test_procfs() -> Result<()> {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    println!("test procfs ...");
+
+    let meminfo = fs::read_to_string("/proc/meminfo")?;
+    assert!(meminfo.starts_with("MemTotal:"));
+    assert!(meminfo.contains("\nMemFree:"));
+    let uptime = fs::read_to_string("/proc/uptime")?;
+    assert!(uptime.split(' ').next().unwrap().parse::<f64>().is_ok());
+    assert!(fs::read_to_string("/proc/cpuinfo")?.starts_with("processor\t: 0\n"));
+    assert!(fs::read_to_string("/proc/mounts")?.contains("proc /proc proc rw 0 0\n"));
+
+    // the content is generated each time
+    fs::mount("ram0", "/mnt", "ramfs")?;
+    assert!(fs::read_to_string("/proc/mounts")?.contains("ram0 /mnt ramfs"));
+    fs::umount("/mnt")?;
+    fs::remove_dir("/mnt")?;
+    assert!(!fs::read_to_string("/proc/mounts")?.contains("ram0 /mnt ramfs"));
+
+    // tasks
+    let status = fs::read_to_string("/proc/self/status")?;
+    let pid_line = status.lines().find(|l| l.starts_with("Pid:")).unwrap();
+    let tid = pid_line["Pid:".len()..].trim();
+    assert_eq!(
+        fs::read_to_string(&format!("/proc/{}/status", tid))?,
+        status
+    );
+    assert!(fs::read_dir("/proc")?.any(|e| e.unwrap().file_name() == tid));
+    let stat = fs::read_to_string("/proc/self/stat")?;
+    assert!(stat.starts_with(&format!("{} (", tid)));
+    assert_eq!(stat.split(' ').count(), 52);
+    assert_err!(fs::read_to_string("/proc/0/status"), NotFound);
+
+    // parameters
+    assert_eq!(fs::read_to_string("/proc/sys/vm/overcommit_memory")?, "0\n");
+    fs::write("/proc/sys/vm/overcommit_memory", "1\n")?;
+    assert_eq!(fs::read_to_string("/proc/sys/vm/overcommit_memory")?, "1\n");
+    assert_err!(
+        fs::write("/proc/sys/vm/overcommit_memory", "x"),
+        InvalidInput
+    );
+    fs::write("/proc/sys/vm/overcommit_memory", "0")?;
+
+    // files added by other modules
+    static VALUE: AtomicUsize = AtomicUsize::new(4096);
+    axfs::procfs::add_file_rw(
+        "sys/net/core/somaxconn",
+        || Ok(format!("{}\n", VALUE.load(Ordering::Relaxed))),
+        |s| {
+            let v = s.trim().parse().map_err(|_| Error::InvalidInput)?;
+            VALUE.store(v, Ordering::Relaxed);
+            Ok(())
+        },
+    )?;
+    assert_eq!(
+        fs::read_to_string("/proc/sys/net/core/somaxconn")?,
+        "4096\n"
+    );
+    fs::write("/proc/sys/net/core/somaxconn", "128\n")?;
+    assert_eq!(VALUE.load(Ordering::Relaxed), 128);
+    assert_err!(fs::write("/proc/sys/net/core/somaxconn", "x"), InvalidInput);
+    assert_err!(fs::write("/proc/uptime", "0"), PermissionDenied);
+    assert_err!(fs::write("/proc/foo", "0"), PermissionDenied);
+
+    // binary files
+    axfs::procfs::add_binary_file("net/binary", || Ok(vec![0xd4, 0xc3, 0xb2, 0xa1, 0xff]))?;
+    assert_eq!(
+        fs::read("/proc/net/binary")?,
+        [0xd4, 0xc3, 0xb2, 0xa1, 0xff]
+    );
+    assert_err!(fs::write("/proc/net/binary", "0"), PermissionDenied);
+
+    println!("test_procfs() OK!");
+    Ok(())
+}
+
+#[cfg(feature = "sysfs")]
+fn test_sysfs() -> Result<()> {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    println!("test sysfs ...");
+
+    let online = fs::read_to_string("/sys/devices/system/cpu/online")?;
+    assert!(online.starts_with('0'));
+    assert!(!fs::read_to_string(
+        "/sys/devices/system/clocksource/clocksource0/current_clocksource"
+    )?
+    .is_empty());
+
+    // attributes published by a driver
+    static VALUE: AtomicUsize = AtomicUsize::new(7);
+    axfs::sysfs::add_attr_rw(
+        "class/test/test0/value",
+        || Ok(format!("{}\n", VALUE.load(Ordering::Relaxed))),
+        |s| {
+            let v = s.trim().parse().map_err(|_| Error::InvalidInput)?;
+            VALUE.store(v, Ordering::Relaxed);
+            Ok(())
+        },
+    )?;
+    axfs::sysfs::add_attr("class/test/test0/name", || Ok("test\n".into()))?;
+    assert_eq!(fs::read_to_string("/sys/class/test/test0/value")?, "7\n");
+    fs::write("/sys/class/test/test0/value", "42\n")?;
+    assert_eq!(VALUE.load(Ordering::Relaxed), 42);
+    assert_eq!(fs::read_to_string("/sys/class/test/test0/value")?, "42\n");
+    assert_err!(fs::write("/sys/class/test/test0/value", "x"), InvalidInput);
+    assert_err!(
+        fs::write("/sys/class/test/test0/name", "x"),
+        PermissionDenied
+    );
+    let names: Vec<_> = fs::read_dir("/sys/class/test/test0")?
+        .map(|e| e.unwrap().file_name())
+        .collect();
+    assert_eq!(names, ["name", "value"]);
+
+    axfs::sysfs::remove("class/test/test0/value")?;
+    assert_err!(fs::read_to_string("/sys/class/test/test0/value"), NotFound);
+    assert_err!(axfs::sysfs::remove("class/test/test0/value"), NotFound);
+    axfs::sysfs::remove("class/test")?;
+    assert_err!(fs::metadata("/sys/class/test"), NotFound);
+
+    println!("test_sysfs() OK!");
+    Ok(())
+}
+
 pub fn test_all() {
     test_read_write_file().expect("test_read_write_file() failed");
     test_read_dir().expect("test_read_dir() failed");
@@ -259,4 +912,17 @@ pub fn test_all() {
     test_create_file_dir().expect("test_create_file_dir() failed");
     test_remove_file_dir().expect("test_remove_file_dir() failed");
     test_devfs_ramfs().expect("test_devfs_ramfs() failed");
+    #[cfg(feature = "devfs")]
+    test_devfs().expect("test_devfs() failed");
+    test_links().expect("test_links() failed");
+    test_times().expect("test_times() failed");
+    test_locks().expect("test_locks() failed");
+    test_watches().expect("test_watches() failed");
+    test_mount().expect("test_mount() failed");
+    #[cfg(feature = "overlayfs")]
+    test_overlay().expect("test_overlay() failed");
+    #[cfg(feature = "procfs")]
+    test_procfs().expect("test_procfs() failed");
+    #[cfg(feature = "sysfs")]
+    test_sysfs().expect("test_sysfs() failed");
 }
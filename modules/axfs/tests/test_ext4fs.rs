@@ -0,0 +1,116 @@
+#![cfg(all(feature = "ext4fs", not(feature = "myfs")))]
+
+mod test_common;
+
+use axdriver::ramdisk::RamDisk;
+use axdriver::AxDeviceContainer;
+use axfs::api::{self as fs, File};
+use axio::{Read, Result, Write};
+
+const IMG_PATH: &str = "resources/ext4.img";
+
+fn make_disk() -> std::io::Result<RamDisk> {
+    let path = std::env::current_dir()?.join(IMG_PATH);
+    println!("Loading disk image from {:?} ...", path);
+    let data = std::fs::read(path)?;
+    println!("size = {} bytes", data.len());
+    Ok(RamDisk::from(&data))
+}
+
+fn test_symlinks() -> Result<()> {
+    println!("test symlinks:");
+    assert_eq!(fs::read_to_string("/link.txt")?, "Rust is cool!\n");
+    assert_eq!(
+        fs::read_to_string("/abs-link/very-long-file-name.txt")?,
+        "Rust is cool!\n"
+    );
+    assert!(fs::metadata("/abs-link")?.is_dir());
+    assert_eq!(fs::read_link("/abs-link")?, "/very-long-dir-name");
+    assert!(fs::symlink_metadata("/link.txt")?.is_symlink());
+
+    fs::symlink("short.txt", "/new-link")?;
+    assert_eq!(fs::read_to_string("/new-link")?, "Rust is cool!\n");
+    fs::symlink("/tmp", "/tmp-link")?;
+    fs::write("/tmp-link/test.txt", "test")?;
+    assert_eq!(fs::read_to_string("/tmp/test.txt")?, "test");
+    fs::remove_file("/tmp/test.txt")?;
+    fs::remove_file("/tmp-link")?;
+    fs::remove_file("/new-link")?;
+
+    fs::hard_link("/short.txt", "/hard.txt")?;
+    fs::write("/hard.txt", "changed")?;
+    assert_eq!(fs::read_to_string("/short.txt")?, "changed");
+    fs::remove_file("/hard.txt")?;
+    assert_eq!(fs::read_to_string("/short.txt")?, "changed");
+    fs::write("/short.txt", "Rust is cool!\n")?;
+    println!("test_symlinks() OK!");
+    Ok(())
+}
+
+fn test_indexed_dir() -> Result<()> {
+    println!("test indexed directory:");
+    for i in 1..=300 {
+        let content = fs::read_to_string(&format!("/many/file-with-a-long-name-{}", i))?;
+        assert_eq!(content, format!("{}\n", i));
+    }
+    assert_eq!(fs::read_dir("/many")?.count(), 300);
+
+    for i in (1..=300).step_by(2) {
+        fs::remove_file(&format!("/many/file-with-a-long-name-{}", i))?;
+    }
+    for i in 0..500 {
+        fs::write(&format!("/many/new-{}", i), format!("{}", i))?;
+    }
+    for i in (2..=300).step_by(2) {
+        assert!(fs::metadata(&format!("/many/file-with-a-long-name-{}", i)).is_ok());
+    }
+    assert_eq!(fs::read_to_string("/many/new-499")?, "499");
+    assert_eq!(fs::read_dir("/many")?.count(), 650);
+    println!("test_indexed_dir() OK!");
+    Ok(())
+}
+
+fn test_large_file() -> Result<()> {
+    let fname = "/large.bin";
+    println!("test large file {:?}:", fname);
+    let chunk: Vec<u8> = (0..4099u32).map(|i| (i % 251) as u8).collect();
+    let mut file = File::create(fname)?;
+    for _ in 0..256 {
+        file.write_all(&chunk)?;
+    }
+    drop(file);
+    assert_eq!(fs::metadata(fname)?.len(), 4099 * 256);
+
+    let mut file = File::open(fname)?;
+    let mut buf = vec![0; chunk.len()];
+    for _ in 0..256 {
+        file.read_exact(&mut buf)?;
+        assert_eq!(buf, chunk);
+    }
+    drop(file);
+
+    let file = File::options().write(true).open(fname)?;
+    file.set_len(5000)?;
+    drop(file);
+    let contents = fs::read(fname)?;
+    assert_eq!(contents.len(), 5000);
+    assert_eq!(&contents[..4099], &chunk[..]);
+    assert_eq!(&contents[4099..], &chunk[..901]);
+    fs::remove_file(fname)?;
+    println!("test_large_file() OK!");
+    Ok(())
+}
+
+#[test]
+fn test_ext4fs() {
+    println!("Testing ext4fs with ramdisk ...");
+
+    let disk = make_disk().expect("failed to load disk image");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    axfs::init_filesystems(AxDeviceContainer::from_one(disk));
+
+    test_common::test_all();
+    test_symlinks().expect("test_symlinks() failed");
+    test_indexed_dir().expect("test_indexed_dir() failed");
+    test_large_file().expect("test_large_file() failed");
+}
@@ -1,9 +1,9 @@
-#![cfg(not(feature = "myfs"))]
+#![cfg(not(any(feature = "myfs", feature = "ext4fs")))]
 
 mod test_common;
 
+use axdriver::ramdisk::RamDisk;
 use axdriver::AxDeviceContainer;
-use axdriver_block::ramdisk::RamDisk;
 
 const IMG_PATH: &str = "resources/fat16.img";
 
@@ -0,0 +1,133 @@
+#![cfg(feature = "initramfs")]
+
+mod test_common;
+
+use std::time::Duration;
+
+use axdriver::ramdisk::RamDisk;
+use axdriver::AxDeviceContainer;
+use axfs::api as fs;
+use axio::{Error, Result};
+
+const MTIME: u32 = 1_700_000_000;
+
+/// Appends an entry of the "newc" cpio format to `archive`.
+fn add_entry(archive: &mut Vec<u8>, ino: u32, mode: u32, nlink: u32, name: &str, data: &[u8]) {
+    let name_size = name.len() as u32 + 1;
+    let fields = [
+        ino,
+        mode,
+        0,
+        0,
+        nlink,
+        MTIME,
+        data.len() as u32,
+        0,
+        0,
+        0,
+        0,
+        name_size,
+        0,
+    ];
+    archive.extend_from_slice(b"070701");
+    for field in fields {
+        archive.extend_from_slice(format!("{:08x}", field).as_bytes());
+    }
+    archive.extend_from_slice(name.as_bytes());
+    archive.push(0);
+    archive.resize(archive.len().next_multiple_of(4), 0);
+    archive.extend_from_slice(data);
+    archive.resize(archive.len().next_multiple_of(4), 0);
+}
+
+fn make_archive() -> Vec<u8> {
+    let text = b"Rust is cool!\n";
+    let mut archive = Vec::new();
+    add_entry(&mut archive, 1, 0o40755, 2, ".", b"");
+    add_entry(&mut archive, 2, 0o100644, 1, "short.txt", text);
+    add_entry(
+        &mut archive,
+        3,
+        0o100644,
+        1,
+        "./long.txt",
+        &text.repeat(100),
+    );
+    add_entry(&mut archive, 4, 0o40755, 2, "very-long-dir-name", b"");
+    add_entry(
+        &mut archive,
+        5,
+        0o100644,
+        1,
+        "very-long-dir-name/very-long-file-name.txt",
+        text,
+    );
+    // missing parents are created
+    add_entry(
+        &mut archive,
+        6,
+        0o100644,
+        1,
+        "very/long/path/test.txt",
+        text,
+    );
+    add_entry(&mut archive, 7, 0o120777, 1, "etc/motd", b"/short.txt");
+    // hard links, whose data is in the last one
+    add_entry(&mut archive, 8, 0o100644, 2, "etc/hosts", b"");
+    add_entry(
+        &mut archive,
+        8,
+        0o100644,
+        2,
+        "etc/hosts.bak",
+        b"127.0.0.1\n",
+    );
+    // FIFOs are skipped
+    add_entry(&mut archive, 9, 0o10644, 1, "run/initctl", b"");
+    add_entry(&mut archive, 0, 0, 1, "TRAILER!!!", b"");
+    archive
+}
+
+fn test_unpacked() -> Result<()> {
+    println!("test unpacked initramfs:");
+    assert_eq!(
+        fs::read_to_string("/long.txt")?,
+        "Rust is cool!\n".repeat(100)
+    );
+    assert_eq!(fs::read_link("/etc/motd")?, "/short.txt");
+    assert_eq!(fs::read_to_string("/etc/motd")?, "Rust is cool!\n");
+    assert_eq!(fs::read_to_string("/etc/hosts")?, "127.0.0.1\n");
+    fs::write("/etc/hosts.bak", "changed")?;
+    assert_eq!(fs::read_to_string("/etc/hosts")?, "changed");
+    assert_eq!(fs::metadata("/run/initctl").err(), Some(Error::NotFound));
+
+    // times of directories are kept after adding their entries
+    let mtime = Duration::from_secs(MTIME as u64);
+    assert_eq!(fs::metadata("/very-long-dir-name")?.modified(), mtime);
+    assert_eq!(fs::metadata("/short.txt")?.modified(), mtime);
+    assert_eq!(fs::symlink_metadata("/etc/motd")?.modified(), mtime);
+
+    #[cfg(feature = "procfs")]
+    assert!(fs::read_to_string("/proc/mounts")?.contains("initramfs / ramfs rw 0 0\n"));
+
+    fs::remove_file("/etc/motd")?;
+    fs::remove_file("/etc/hosts")?;
+    fs::remove_file("/etc/hosts.bak")?;
+    fs::remove_dir("/etc")?;
+    println!("test_unpacked() OK!");
+    Ok(())
+}
+
+#[test]
+fn test_initramfs() {
+    println!("Testing initramfs ...");
+
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    axfs::init_filesystems_with_initramfs(
+        AxDeviceContainer::from_one(RamDisk::default()), // dummy disk, actually not used.
+        &make_archive(),
+    );
+
+    test_unpacked().expect("test_unpacked() failed");
+    test_common::test_all();
+}
@@ -0,0 +1,123 @@
+#![cfg(all(feature = "fatfs", feature = "ext4fs", not(feature = "myfs")))]
+
+use axdriver::ramdisk::RamDisk;
+use axdriver::AxDeviceContainer;
+use axfs::{api as fs, FsType};
+use axio::{Error, Result};
+
+const IMG_PATH: &str = "resources/gpt.img";
+
+fn make_disk() -> std::io::Result<RamDisk> {
+    let path = std::env::current_dir()?.join(IMG_PATH);
+    println!("Loading disk image from {:?} ...", path);
+    let data = std::fs::read(path)?;
+    println!("size = {} bytes", data.len());
+    Ok(RamDisk::from(&data))
+}
+
+fn test_volumes() {
+    println!("test volumes:");
+    let volumes = axfs::volumes();
+    let names: Vec<_> = volumes.iter().map(|v| v.name.as_str()).collect();
+    assert_eq!(names, ["blk0", "blk0p1", "blk0p2"]);
+
+    assert_eq!(volumes[0].fs_type, FsType::Unknown);
+    assert_eq!(volumes[0].size, 6 * 1024 * 1024);
+
+    let boot = &volumes[1];
+    assert_eq!(boot.fs_type, FsType::Fat);
+    assert_eq!(boot.size, 5000 * 512);
+    assert_eq!(boot.label.as_deref(), Some("Test!"));
+    assert_eq!(boot.uuid.as_deref(), Some("1234-5678"));
+    assert_eq!(boot.part_label.as_deref(), Some("boot"));
+    assert!(boot.matches("PARTLABEL=boot"));
+    assert!(boot.matches("UUID=1234-5678"));
+    assert!(boot.matches("/dev/blk0p1"));
+
+    let root = &volumes[2];
+    assert_eq!(root.fs_type, FsType::Ext4);
+    assert_eq!(root.size, 1024 * 1024);
+    assert_eq!(root.label.as_deref(), Some("rootfs"));
+    assert_eq!(
+        root.part_uuid.as_deref(),
+        Some("aaaaaaaa-0000-0000-0000-000000000002")
+    );
+    assert!(root.matches("UUID=87654321-4321-4321-4321-210987654321"));
+    assert!(root.matches("PARTUUID=AAAAAAAA-0000-0000-0000-000000000002"));
+    assert!(!root.matches("LABEL=boot"));
+    println!("test_volumes() OK!");
+}
+
+fn test_mounts() -> Result<()> {
+    println!("test mounts:");
+    assert_eq!(
+        fs::read_to_string("/hello.txt")?,
+        "Hello from the root partition!\n"
+    );
+    assert_eq!(
+        fs::read_to_string("/boot/efi/short.txt")?,
+        "Rust is cool!\n"
+    );
+    assert!(fs::metadata("/boot/efi/very/long/path")?.is_dir());
+
+    // writes go to the right partition
+    fs::write("/boot/efi/new.txt", "on the boot partition")?;
+    fs::write("/new.txt", "on the root partition")?;
+    assert_eq!(
+        fs::read_to_string("/boot/efi/new.txt")?,
+        "on the boot partition"
+    );
+    assert_eq!(fs::read_to_string("/new.txt")?, "on the root partition");
+    assert_eq!(fs::read_to_string("/boot/efi/long.txt")?.len(), 14000);
+    println!("test_mounts() OK!");
+    Ok(())
+}
+
+fn test_runtime_mount() -> Result<()> {
+    println!("test runtime mount:");
+    fs::umount("/boot/efi")?;
+    assert!(fs::metadata("/boot/efi/short.txt").is_err());
+
+    fs::mount("UUID=1234-5678", "/mnt/boot", "vfat")?;
+    assert_eq!(
+        fs::read_to_string("/mnt/boot/new.txt")?,
+        "on the boot partition"
+    );
+    let err = |res: Result<()>| res.err();
+    assert_eq!(
+        err(fs::mount("blk0p2", "/mnt/root", "auto")),
+        Some(Error::ResourceBusy)
+    );
+    assert_eq!(
+        err(fs::mount("blk0p1", "/mnt/boot2", "auto")),
+        Some(Error::ResourceBusy)
+    );
+    assert_eq!(
+        err(fs::mount("blk0p1", "/mnt/boot2", "ext4")),
+        Some(Error::InvalidInput)
+    );
+    assert_eq!(
+        err(fs::mount("LABEL=none", "/mnt/boot2", "auto")),
+        Some(Error::NotFound)
+    );
+    fs::umount("/mnt/boot")?;
+    println!("test_runtime_mount() OK!");
+    Ok(())
+}
+
+#[test]
+fn test_partitions() {
+    println!("Testing partitions with ramdisk ...");
+
+    let disk = make_disk().expect("failed to load disk image");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    axfs::init_filesystems_with(
+        AxDeviceContainer::from_one(disk),
+        "LABEL=rootfs",
+        "PARTLABEL=boot:/boot/efi",
+    );
+
+    test_volumes();
+    test_mounts().expect("test_mounts() failed");
+    test_runtime_mount().expect("test_runtime_mount() failed");
+}
@@ -4,8 +4,8 @@ mod test_common;
 
 use std::sync::Arc;
 
+use axdriver::ramdisk::RamDisk;
 use axdriver::AxDeviceContainer;
-use axdriver_block::ramdisk::RamDisk;
 use axfs::api::{self as fs, File};
 use axfs::fops::{Disk, MyFileSystemIf};
 use axfs_ramfs::RamFileSystem;
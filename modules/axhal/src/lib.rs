@@ -43,6 +43,7 @@ pub mod trap;
 pub mod arch;
 pub mod cpu;
 pub mod mem;
+pub mod random;
 pub mod time;
 
 #[cfg(feature = "tls")]
@@ -69,6 +70,30 @@ pub mod console {
 /// Miscellaneous operation, e.g. terminate the system.
 pub mod misc {
     pub use super::platform::misc::*;
+
+    use kspin::SpinNoIrq;
+
+    static TERMINATE_HOOK: SpinNoIrq<Option<fn()>> = SpinNoIrq::new(None);
+
+    /// Sets the function called once by [`terminate`] before the system is
+    /// shut down.
+    ///
+    /// It is usually used to write cached data back to devices, such as the
+    /// blocks cached by filesystems.
+    pub fn set_terminate_hook(hook: fn()) {
+        *TERMINATE_HOOK.lock() = Some(hook);
+    }
+
+    /// Shutdown the whole system, after calling the hook set by
+    /// [`set_terminate_hook`].
+    pub fn terminate() -> ! {
+        // taken, so a panic in the hook doesn't call it again
+        let hook = TERMINATE_HOOK.lock().take();
+        if let Some(hook) = hook {
+            hook();
+        }
+        super::platform::misc::terminate()
+    }
 }
 
 /// Multi-core operations.
@@ -27,6 +27,16 @@ impl PhitiumGpioDrv {
         let gpio = self.get_gpio();
         gpio.set_pin_data(pin, data);
     }
+
+    /// Whether the pin is an output.
+    pub fn pin_dir(&self, pin: GpioPins) -> bool {
+        self.get_gpio().dir.0.contains(pin)
+    }
+
+    /// The level of the pin.
+    pub fn pin_data(&self, pin: GpioPins) -> bool {
+        self.get_gpio().data.0.contains(pin)
+    }
 }
 
 impl PhitiumGpio {
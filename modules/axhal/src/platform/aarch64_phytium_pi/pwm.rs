@@ -106,6 +106,11 @@ impl PwmCtrl {
         }
     }
 
+    // 获取周期（时钟周期数）
+    pub fn get_period(&self) -> u32 {
+        self.regs().period.get()
+    }
+
     // 获取当前占空比
     pub fn get_duty(&self) -> u32 {
         let t = self.regs().period.get();
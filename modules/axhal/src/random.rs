@@ -0,0 +1,339 @@
+//! Cryptographically secure random number generation.
+//!
+//! Random numbers come from a ChaCha20 generator with fast key erasure: the
+//! key is replaced by the first output block on every request, so earlier
+//! outputs can't be recovered from the state.
+//!
+//! The generator must be seeded with 256 bits of entropy before use. It
+//! seeds itself from the CPU on the first request on x86_64 CPUs with
+//! `RDSEED` or `RDRAND` and on AArch64 CPUs with `RNDR` (FEAT_RNG). Until
+//! it's seeded, requests fail with [`NoEntropy`] rather than returning
+//! predictable numbers.
+//!
+//! Other sources can seed it by [`add_entropy`], but no driver does so yet
+//! (e.g., there is no virtio-rng driver). So on RISC-V and LoongArch, and on
+//! CPUs without the instructions above, such as the default `qemu64` CPU of
+//! QEMU (use `ACCEL=y` or `-cpu max` instead), requests keep failing, and
+//! so do TLS handshakes.
+//!
+//! Users that only need numbers which are hard to guess from the network,
+//! such as DNS transaction IDs, can use [`weak_random_u64`] instead, which
+//! never fails: without entropy, it falls back to a second generator seeded
+//! from the time and from bytes unique to the machine ([`add_weak_seed`]).
+
+use core::fmt;
+
+use kspin::SpinNoIrq;
+
+/// The error of requests for random numbers before the generator is seeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoEntropy;
+
+impl fmt::Display for NoEntropy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("no entropy source available")
+    }
+}
+
+/// The number of bytes of entropy needed to seed the generator.
+const SEED_LEN: usize = 32;
+
+struct ChaChaRng {
+    key: [u32; 8],
+    /// The number of bytes of entropy added so far, up to [`SEED_LEN`].
+    entropy: usize,
+}
+
+static RNG: SpinNoIrq<ChaChaRng> = SpinNoIrq::new(ChaChaRng {
+    key: [0; 8],
+    entropy: 0,
+});
+
+/// The fallback generator of [`weak_random_u64`], whose entropy count is
+/// meaningless.
+static WEAK_RNG: SpinNoIrq<ChaChaRng> = SpinNoIrq::new(ChaChaRng {
+    key: [0; 8],
+    entropy: 0,
+});
+
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+/// Computes the ChaCha20 block (RFC 8439) of `key` at block `counter`, with
+/// the nonce used for domain separation.
+fn chacha20_block(key: &[u32; 8], counter: u32, nonce: [u32; 3]) -> [u32; 16] {
+    let mut init = [0; 16];
+    init[..4].copy_from_slice(&[0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574]);
+    init[4..12].copy_from_slice(key);
+    init[12] = counter;
+    init[13..].copy_from_slice(&nonce);
+    let mut s = init;
+    for _ in 0..10 {
+        quarter_round(&mut s, 0, 4, 8, 12);
+        quarter_round(&mut s, 1, 5, 9, 13);
+        quarter_round(&mut s, 2, 6, 10, 14);
+        quarter_round(&mut s, 3, 7, 11, 15);
+        quarter_round(&mut s, 0, 5, 10, 15);
+        quarter_round(&mut s, 1, 6, 11, 12);
+        quarter_round(&mut s, 2, 7, 8, 13);
+        quarter_round(&mut s, 3, 4, 9, 14);
+    }
+    for (s, init) in s.iter_mut().zip(init) {
+        *s = s.wrapping_add(init);
+    }
+    s
+}
+
+impl ChaChaRng {
+    /// Nonce of the blocks of random output.
+    const OUTPUT: [u32; 3] = [0, 0, 0];
+    /// Nonce of the blocks mixing entropy into the key.
+    const MIX: [u32; 3] = [1, 0, 0];
+
+    fn mix(&mut self, seed: &[u8]) {
+        for chunk in seed.chunks(SEED_LEN) {
+            for (i, b) in chunk.iter().enumerate() {
+                self.key[i / 4] ^= (*b as u32) << (i % 4 * 8);
+            }
+            let block = chacha20_block(&self.key, 0, Self::MIX);
+            self.key.copy_from_slice(&block[..8]);
+        }
+        self.entropy = (self.entropy + seed.len()).min(SEED_LEN);
+    }
+
+    fn fill_bytes(&mut self, buf: &mut [u8]) {
+        let next_key = chacha20_block(&self.key, 0, Self::OUTPUT);
+        for (counter, chunk) in (1..).zip(buf.chunks_mut(64)) {
+            let block = chacha20_block(&self.key, counter, Self::OUTPUT);
+            for (dst, word) in chunk.chunks_mut(4).zip(block) {
+                dst.copy_from_slice(&word.to_le_bytes()[..dst.len()]);
+            }
+        }
+        self.key.copy_from_slice(&next_key[..8]);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn hw_random() -> Option<u64> {
+    use core::arch::x86_64::{_rdrand64_step, _rdseed64_step};
+    use core::sync::atomic::{AtomicU8, Ordering};
+
+    const RDSEED: u8 = 1 << 1;
+    const RDRAND: u8 = 1 << 2;
+    // 0: unknown, otherwise a set of the flags above with bit 0 set
+    static FEATURES: AtomicU8 = AtomicU8::new(0);
+    if FEATURES.load(Ordering::Relaxed) == 0 {
+        let cpuid = raw_cpuid::CpuId::new();
+        let mut features = 1;
+        if cpuid
+            .get_extended_feature_info()
+            .is_some_and(|info| info.has_rdseed())
+        {
+            features |= RDSEED;
+        }
+        if cpuid
+            .get_feature_info()
+            .is_some_and(|info| info.has_rdrand())
+        {
+            features |= RDRAND;
+        }
+        FEATURES.store(features, Ordering::Relaxed);
+    }
+    let features = FEATURES.load(Ordering::Relaxed);
+    let mut val = 0;
+    for _ in 0..10 {
+        // SAFETY: the instructions are only used if supported as checked above.
+        unsafe {
+            if features & RDSEED != 0 && _rdseed64_step(&mut val) == 1 {
+                return Some(val);
+            }
+            if features & RDRAND != 0 && _rdrand64_step(&mut val) == 1 {
+                return Some(val);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(target_arch = "aarch64")]
+fn hw_random() -> Option<u64> {
+    let isar0: u64;
+    // SAFETY: `ID_AA64ISAR0_EL1` is always readable at EL1.
+    unsafe { core::arch::asm!("mrs {}, id_aa64isar0_el1", out(reg) isar0) };
+    if (isar0 >> 60) & 0xf == 0 {
+        return None; // no FEAT_RNG
+    }
+    for _ in 0..10 {
+        let (val, ok): (u64, u64);
+        // SAFETY: `RNDR` is supported as checked above. It sets the Z flag on
+        // failure.
+        unsafe {
+            core::arch::asm!(
+                "mrs {val}, s3_3_c2_c4_0",
+                "cset {ok}, ne",
+                val = out(reg) val,
+                ok = out(reg) ok,
+            )
+        };
+        if ok != 0 {
+            return Some(val);
+        }
+    }
+    None
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn hw_random() -> Option<u64> {
+    None
+}
+
+/// Seeds the generator from the CPU if it's not seeded yet.
+fn seed(rng: &mut ChaChaRng) -> Result<(), NoEntropy> {
+    while rng.entropy < SEED_LEN {
+        let val = hw_random().ok_or(NoEntropy)?;
+        rng.mix(&val.to_le_bytes());
+    }
+    Ok(())
+}
+
+/// Mixes `seed` into the state of the generator, crediting every byte as a
+/// byte of entropy.
+///
+/// It's for entropy sources other than the CPU, such as a virtio-rng device
+/// or a hardware RNG of the platform, and must only be given unpredictable
+/// bytes.
+pub fn add_entropy(seed: &[u8]) {
+    RNG.lock().mix(seed);
+}
+
+/// Mixes `data` into the state of the generator without crediting any
+/// entropy, so it can come from untrusted sources, such as writes to
+/// `/dev/random`.
+pub fn add_randomness(data: &[u8]) {
+    let mut rng = RNG.lock();
+    let entropy = rng.entropy;
+    rng.mix(data);
+    rng.entropy = entropy;
+}
+
+/// Whether the generator is seeded, or can be seeded from the CPU.
+pub fn has_entropy() -> bool {
+    seed(&mut RNG.lock()).is_ok()
+}
+
+/// Fills the buffer with random bytes.
+pub fn fill_bytes(buf: &mut [u8]) -> Result<(), NoEntropy> {
+    let mut rng = RNG.lock();
+    seed(&mut rng)?;
+    rng.fill_bytes(buf);
+    Ok(())
+}
+
+/// Returns a random 64-bit number.
+pub fn random_u64() -> Result<u64, NoEntropy> {
+    let mut buf = [0; 8];
+    fill_bytes(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// Mixes bytes that are unique to this machine but not secret, such as MAC
+/// addresses, into the fallback generator of [`weak_random_u64`].
+pub fn add_weak_seed(seed: &[u8]) {
+    WEAK_RNG.lock().mix(seed);
+}
+
+/// Returns a random 64-bit number, which is not predictable without access
+/// to the machine, but not suitable for keys either.
+///
+/// It comes from the secure generator if it's seeded. Otherwise it comes from
+/// a generator seeded from the time of every request and from
+/// [`add_weak_seed`], so it never fails.
+pub fn weak_random_u64() -> u64 {
+    if let Ok(val) = random_u64() {
+        return val;
+    }
+    let mut rng = WEAK_RNG.lock();
+    rng.mix(&crate::time::monotonic_time_nanos().to_le_bytes());
+    rng.mix(&crate::time::wall_time_nanos().to_le_bytes());
+    let mut buf = [0; 8];
+    rng.fill_bytes(&mut buf);
+    u64::from_le_bytes(buf)
+}
+
+/// Returns a random 128-bit number.
+pub fn random() -> Result<u128, NoEntropy> {
+    let mut buf = [0; 16];
+    fill_bytes(&mut buf)?;
+    Ok(u128::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RFC 8439, section 2.1.1.
+    #[test]
+    fn test_quarter_round() {
+        let mut s = [0; 16];
+        s[..4].copy_from_slice(&[0x1111_1111, 0x0102_0304, 0x9b8d_6f43, 0x0123_4567]);
+        quarter_round(&mut s, 0, 1, 2, 3);
+        assert_eq!(s[..4], [0xea2a_92f4, 0xcb1c_f8ce, 0x4581_472e, 0x5881_c4bb]);
+    }
+
+    /// RFC 8439, section 2.3.2.
+    #[test]
+    fn test_chacha20_block() {
+        let mut key = [0; 8];
+        for (i, word) in key.iter_mut().enumerate() {
+            let b = i as u8 * 4;
+            *word = u32::from_le_bytes([b, b + 1, b + 2, b + 3]);
+        }
+        let block = chacha20_block(&key, 1, [0x0900_0000, 0x4a00_0000, 0]);
+        assert_eq!(
+            block[..4],
+            [0xe4e7_f110, 0x1559_3bd1, 0x1fdd_0f50, 0xc471_20a3]
+        );
+        assert_eq!(
+            block[4..8],
+            [0xc7f4_d1c7, 0x0368_c033, 0x9aaa_2204, 0x4e6c_d4c3]
+        );
+        assert_eq!(
+            block[8..12],
+            [0x4664_82d2, 0x09aa_9f07, 0x05d7_c214, 0xa202_8bd9]
+        );
+        assert_eq!(
+            block[12..],
+            [0xd19c_12b5, 0xb94e_16de, 0xe883_d0cb, 0x4e3c_50a2]
+        );
+    }
+
+    #[test]
+    fn test_fast_key_erasure() {
+        let mut rng = ChaChaRng {
+            key: [0; 8],
+            entropy: 0,
+        };
+        rng.mix(&[0x5a; SEED_LEN]);
+        assert_eq!(rng.entropy, SEED_LEN);
+        let key = rng.key;
+
+        let mut first = [0; 100];
+        rng.fill_bytes(&mut first);
+        // the output is the key stream after the block of the next key
+        let block = chacha20_block(&key, 1, ChaChaRng::OUTPUT);
+        assert_eq!(first[..4], block[0].to_le_bytes());
+        assert_eq!(rng.key[..], chacha20_block(&key, 0, ChaChaRng::OUTPUT)[..8]);
+
+        let mut second = [0; 100];
+        rng.fill_bytes(&mut second);
+        assert_ne!(first, second);
+    }
+}
@@ -8,7 +8,23 @@
 //!
 //! - [`TcpSocket`]: A TCP socket that provides POSIX-like APIs.
 //! - [`UdpSocket`]: A UDP socket that provides POSIX-like APIs.
-//! - [`dns_query`]: Function for DNS query.
+//! - [`TcpSocket::recv_with`], [`TcpSocket::send_with`]: Access to the socket
+//!   buffers without copying. Data is still copied between the socket buffers
+//!   and the NIC buffers, as the NIC drivers take one buffer per frame and
+//!   have no scatter-gather transmit.
+//! - [`dns_query`]: Function for DNS query, with a cache and a hosts table.
+//!   The name servers are set by [`dns_set_servers`] or
+//!   [`dns_load_resolv_conf`].
+//! - [`add_vlan_interface`]: Creates an 802.1Q VLAN sub-interface with its own
+//!   IP address.
+//! - [`mdns_start`], [`mdns_add_service`]: An mDNS responder advertising the
+//!   host name and services on the local link.
+//! - [`bridge_start`]: A learning bridge forwarding frames between the NICs,
+//!   which requires the `dyn` feature of [axdriver] to use more than one NIC.
+//! - [`ip_forward_start`], [`nat_set_masquerade`]: IPv4 forwarding between the
+//!   NICs, with source NAT and port forwarding.
+//! - [`start_capture`], [`dump_capture`]: Packet capture on the NIC in the
+//!   pcap format.
 //!
 //! # Cargo Features
 //!
@@ -16,6 +32,7 @@
 //!   by default.
 //!
 //! [smoltcp]: https://github.com/smoltcp-rs/smoltcp
+//! [axdriver]: https://arceos-org.github.io/arceos/axdriver/index.html
 
 #![no_std]
 #![feature(new_uninit)]
@@ -33,16 +50,35 @@ cfg_if::cfg_if! {
 
 pub use self::net_impl::TcpSocket;
 pub use self::net_impl::UdpSocket;
-pub use self::net_impl::{bench_receive, bench_transmit};
-pub use self::net_impl::{dns_query, poll_interfaces};
+pub use self::net_impl::{add_vlan_interface, interfaces, remove_vlan_interface, InterfaceConfig};
+pub use self::net_impl::{bench_receive, bench_tcp_receive, bench_transmit};
+pub use self::net_impl::{bridge_start, bridge_stats, bridge_stop, BridgeStats};
+pub use self::net_impl::{capture_stats, dump_capture, is_capturing, start_capture, stop_capture};
+pub use self::net_impl::{dns_flush_cache, dns_load_hosts, dns_load_resolv_conf};
+pub use self::net_impl::{dns_query, dns_servers, dns_set_servers, poll_interfaces};
+pub use self::net_impl::{ip_forward_start, ip_forward_stats, ip_forward_stop, ForwardStats};
+pub use self::net_impl::{mdns_add_service, mdns_start, mdns_stop, MdnsService};
+pub use self::net_impl::{nat_add_port_forward, nat_connections, nat_remove_port_forward};
+pub use self::net_impl::{nat_set_masquerade, NatConnection, NatProtocol, PortForward};
+pub use self::net_impl::{set_somaxconn, somaxconn, tcp_listeners, ListenStats};
+pub use self::net_impl::{CaptureDirection, CaptureFilter, CaptureStats, DEFAULT_CAPTURE_CAPACITY};
 
+use alloc::vec::Vec;
 use axdriver::{prelude::*, AxDeviceContainer};
 
 /// Initializes the network subsystem by NIC devices.
+///
+/// The first NIC is `eth0`, and the others are only used by the bridge and
+/// IP forwarding.
 pub fn init_network(mut net_devs: AxDeviceContainer<AxNetDevice>) {
     info!("Initialize network subsystem...");
 
     let dev = net_devs.take_one().expect("No NIC device found!");
     info!("  use NIC 0: {:?}", dev.device_name());
-    net_impl::init(dev);
+    let mut ports = Vec::new();
+    while let Some(port) = net_devs.take_one() {
+        info!("  use NIC {}: {:?}", ports.len() + 1, port.device_name());
+        ports.push(port);
+    }
+    net_impl::init(dev, ports);
 }
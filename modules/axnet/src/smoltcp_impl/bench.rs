@@ -1,7 +1,11 @@
-use super::{AxNetRxToken, AxNetTxToken, STANDARD_MTU};
-use super::{DeviceWrapper, InterfaceWrapper};
+use core::net::{Ipv4Addr, SocketAddr};
+
+use axerrno::AxResult;
 use smoltcp::phy::{Device, RxToken, TxToken};
 
+use super::{AxNetRxToken, AxNetTxToken, STANDARD_MTU};
+use super::{DeviceWrapper, InterfaceWrapper, TcpSocket};
+
 const GB: usize = 1000 * MB;
 const MB: usize = 1000 * KB;
 const KB: usize = 1000;
@@ -72,3 +76,56 @@ impl DeviceWrapper {
         }
     }
 }
+
+/// Accepts one TCP connection on `port` and receives from it until the peer
+/// closes, reporting the bandwidth every second.
+///
+/// If `zero_copy` is true, data is consumed in place with
+/// [`TcpSocket::recv_with`], otherwise it is copied out with
+/// [`TcpSocket::recv`], so that the two receive paths can be compared.
+pub fn bench_tcp_receive(port: u16, zero_copy: bool) -> AxResult {
+    let listener = TcpSocket::new();
+    listener.bind(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), port))?;
+    listener.listen(1)?;
+    info!(
+        "TCP receive benchmark ({}) listening on port {}",
+        if zero_copy { "zero-copy" } else { "copy" },
+        port
+    );
+    let socket = listener.accept()?;
+
+    let mut buf = [0u8; STANDARD_MTU];
+    let mut receive_bytes: usize = 0;
+    let mut past_receive_bytes: usize = 0;
+    let mut past_time = InterfaceWrapper::current_time();
+    loop {
+        let n = if zero_copy {
+            socket.recv_with(|data| (data.len(), data.len()))?
+        } else {
+            socket.recv(&mut buf)?
+        };
+        if n == 0 {
+            break;
+        }
+        receive_bytes += n;
+
+        let current_time = InterfaceWrapper::current_time();
+        if (current_time - past_time).secs() >= 1 {
+            let gb = ((receive_bytes - past_receive_bytes) * 8) / GB;
+            let mb = (((receive_bytes - past_receive_bytes) * 8) % GB) / MB;
+            let gib = (receive_bytes - past_receive_bytes) / GB;
+            let mib = ((receive_bytes - past_receive_bytes) % GB) / MB;
+            info!(
+                "TCP receive: {}.{:03}GBytes, Bandwidth: {}.{:03}Gbits/sec.",
+                gib, mib, gb, mb
+            );
+            past_time = current_time;
+            past_receive_bytes = receive_bytes;
+        }
+    }
+    info!(
+        "TCP receive benchmark done: {} bytes in total",
+        receive_bytes
+    );
+    Ok(())
+}
@@ -0,0 +1,366 @@
+//! A learning software bridge between the NICs.
+//!
+//! When the bridge is started, every NIC becomes a port of it. The source MAC
+//! address of each received frame is learned on its port, and the frame is
+//! forwarded to the port its destination was learned on. Broadcast and
+//! multicast frames are flooded to all other ports, and so are frames to
+//! unknown destinations if flooding is enabled. Frames to the MAC address of
+//! `eth0`, broadcast and multicast frames are also delivered to the local
+//! network stack, whose frames are sent to the port of their destination.
+
+use alloc::collections::BTreeMap;
+
+use axerrno::{ax_err, AxResult};
+use axhal::time::{monotonic_time_nanos, NANOS_PER_SEC};
+use smoltcp::wire::EthernetAddress;
+
+use super::ETH0;
+
+/// Maximum number of ports, as ports are selected by a bitmask.
+pub(super) const MAX_PORTS: usize = 64;
+/// Maximum number of learned addresses.
+const MAX_FDB_ENTRIES: usize = 1024;
+/// Learned addresses expire after this time, like the default of 802.1D.
+const AGEING_TIME_NANOS: u64 = 300 * NANOS_PER_SEC;
+
+/// Statistics of the bridge.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BridgeStats {
+    /// Frames delivered to the local network stack.
+    pub local: u64,
+    /// Frames forwarded to the port of their destination.
+    pub forwarded: u64,
+    /// Frames flooded to all other ports.
+    pub flooded: u64,
+    /// Frames dropped, as their destination is on the port they came from or
+    /// is unknown.
+    pub dropped: u64,
+    /// Number of learned addresses.
+    pub learned: usize,
+}
+
+struct FdbEntry {
+    port: usize,
+    last_seen: u64,
+}
+
+/// Where a received frame goes.
+pub(super) struct Forward {
+    /// The ports to forward the frame to, as a bitmask.
+    pub ports: u64,
+    /// Whether to deliver the frame to the local network stack.
+    pub local: bool,
+}
+
+pub(super) struct Bridge {
+    ether_addr: EthernetAddress,
+    flood_unknown: bool,
+    /// The filtering database, from MAC addresses to ports.
+    fdb: BTreeMap<[u8; 6], FdbEntry>,
+    stats: BridgeStats,
+}
+
+fn all_ports(num_ports: usize) -> u64 {
+    if num_ports >= MAX_PORTS {
+        u64::MAX
+    } else {
+        (1 << num_ports) - 1
+    }
+}
+
+fn other_ports(num_ports: usize, port: usize) -> u64 {
+    all_ports(num_ports) & !(1 << port)
+}
+
+fn is_multicast(mac: &[u8]) -> bool {
+    mac[0] & 0x01 != 0
+}
+
+/// Returns whether the destination is reserved for link-local protocols such
+/// as STP and LACP, which bridges must not forward.
+fn is_link_local(mac: &[u8]) -> bool {
+    mac[..5] == [0x01, 0x80, 0xc2, 0x00, 0x00] && mac[5] & 0xf0 == 0
+}
+
+impl Bridge {
+    fn new(ether_addr: EthernetAddress, flood_unknown: bool) -> Self {
+        Self {
+            ether_addr,
+            flood_unknown,
+            fdb: BTreeMap::new(),
+            stats: BridgeStats::default(),
+        }
+    }
+
+    fn lookup(&self, mac: &[u8], now: u64) -> Option<usize> {
+        let entry = self.fdb.get(mac)?;
+        (now - entry.last_seen < AGEING_TIME_NANOS).then_some(entry.port)
+    }
+
+    fn learn(&mut self, mac: [u8; 6], port: usize, now: u64) {
+        if is_multicast(&mac) || mac == self.ether_addr.0 {
+            return;
+        }
+        if self.fdb.len() >= MAX_FDB_ENTRIES && !self.fdb.contains_key(&mac) {
+            self.fdb
+                .retain(|_, entry| now - entry.last_seen < AGEING_TIME_NANOS);
+            if self.fdb.len() >= MAX_FDB_ENTRIES {
+                let oldest = self.fdb.iter().min_by_key(|(_, entry)| entry.last_seen);
+                let oldest = *oldest.unwrap().0;
+                self.fdb.remove(&oldest);
+            }
+        }
+        if let Some(entry) = self.fdb.get(&mac) {
+            if entry.port != port {
+                debug!("bridge: {} moved to port {}", EthernetAddress(mac), port);
+            }
+        }
+        self.fdb.insert(
+            mac,
+            FdbEntry {
+                port,
+                last_seen: now,
+            },
+        );
+    }
+
+    /// Learns the source of a frame received on `port`, and returns where the
+    /// frame goes.
+    pub fn input(&mut self, port: usize, num_ports: usize, frame: &[u8]) -> Forward {
+        self.input_at(port, num_ports, frame, monotonic_time_nanos())
+    }
+
+    fn input_at(&mut self, port: usize, num_ports: usize, frame: &[u8], now: u64) -> Forward {
+        let drop = Forward {
+            ports: 0,
+            local: false,
+        };
+        if frame.len() < 14 {
+            self.stats.dropped += 1;
+            return drop;
+        }
+        let (dst, src) = (&frame[0..6], &frame[6..12]);
+        self.learn(src.try_into().unwrap(), port, now);
+
+        let fwd = if dst == self.ether_addr.as_bytes() || is_link_local(dst) {
+            Forward {
+                ports: 0,
+                local: true,
+            }
+        } else if is_multicast(dst) {
+            self.stats.flooded += 1;
+            Forward {
+                ports: other_ports(num_ports, port),
+                local: true,
+            }
+        } else {
+            match self.lookup(dst, now) {
+                Some(p) if p == port => {
+                    self.stats.dropped += 1;
+                    drop
+                }
+                Some(p) => {
+                    self.stats.forwarded += 1;
+                    Forward {
+                        ports: 1 << p,
+                        local: false,
+                    }
+                }
+                None if self.flood_unknown => {
+                    self.stats.flooded += 1;
+                    Forward {
+                        ports: other_ports(num_ports, port),
+                        local: false,
+                    }
+                }
+                None => {
+                    self.stats.dropped += 1;
+                    drop
+                }
+            }
+        };
+        if fwd.local {
+            self.stats.local += 1;
+        }
+        fwd
+    }
+
+    /// Returns the ports to send a frame of the local network stack to.
+    pub fn output(&self, num_ports: usize, frame: &[u8]) -> u64 {
+        self.output_at(num_ports, frame, monotonic_time_nanos())
+    }
+
+    fn output_at(&self, num_ports: usize, frame: &[u8], now: u64) -> u64 {
+        let dst = &frame[0..6];
+        if is_multicast(dst) {
+            return all_ports(num_ports);
+        }
+        match self.lookup(dst, now) {
+            Some(p) => 1 << p,
+            None => all_ports(num_ports),
+        }
+    }
+}
+
+/// Starts bridging all NICs, with `eth0` as the local interface of the
+/// bridge.
+///
+/// Frames to unknown destinations are flooded to all ports if
+/// `flood_unknown` is true, otherwise they are dropped.
+pub fn bridge_start(flood_unknown: bool) -> AxResult {
+    let mut dev = ETH0.dev.lock();
+    if dev.ports.is_empty() {
+        return ax_err!(Unsupported, "no other NIC to bridge");
+    }
+    if dev.bridge.is_some() {
+        return ax_err!(AlreadyExists, "bridge already started");
+    }
+    if dev.router.is_some() {
+        return ax_err!(BadState, "IP forwarding started");
+    }
+    dev.bridge = Some(Bridge::new(ETH0.ethernet_address(), flood_unknown));
+    info!("bridge: started with {} ports", dev.num_ports());
+    Ok(())
+}
+
+/// Stops bridging, forgetting all learned addresses.
+pub fn bridge_stop() -> AxResult {
+    let mut dev = ETH0.dev.lock();
+    if dev.bridge.take().is_none() {
+        return ax_err!(BadState, "bridge not started");
+    }
+    info!("bridge: stopped");
+    Ok(())
+}
+
+/// Returns the statistics of the bridge, or `None` if it is not started.
+pub fn bridge_stats() -> Option<BridgeStats> {
+    let dev = ETH0.dev.lock();
+    dev.bridge.as_ref().map(|bridge| BridgeStats {
+        learned: bridge.fdb.len(),
+        ..bridge.stats
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCAL: [u8; 6] = [0x52, 0x54, 0, 0, 0, 1];
+    const HOST_A: [u8; 6] = [0x52, 0x54, 0, 0, 0, 0xa];
+    const HOST_B: [u8; 6] = [0x52, 0x54, 0, 0, 0, 0xb];
+    const HOST_C: [u8; 6] = [0x52, 0x54, 0, 0, 0, 0xc];
+    const BROADCAST: [u8; 6] = [0xff; 6];
+    const PORTS: usize = 3;
+
+    fn frame(dst: [u8; 6], src: [u8; 6]) -> [u8; 60] {
+        let mut frame = [0; 60];
+        frame[0..6].copy_from_slice(&dst);
+        frame[6..12].copy_from_slice(&src);
+        frame[12..14].copy_from_slice(&[0x08, 0x00]);
+        frame
+    }
+
+    fn input(
+        bridge: &mut Bridge,
+        port: usize,
+        dst: [u8; 6],
+        src: [u8; 6],
+        now: u64,
+    ) -> (u64, bool) {
+        let fwd = bridge.input_at(port, PORTS, &frame(dst, src), now);
+        (fwd.ports, fwd.local)
+    }
+
+    #[test]
+    fn test_learn_and_forward() {
+        let mut bridge = Bridge::new(EthernetAddress(LOCAL), false);
+        // unknown destinations are dropped without flooding
+        assert_eq!(input(&mut bridge, 0, HOST_B, HOST_A, 0), (0, false));
+        assert_eq!(input(&mut bridge, 1, HOST_A, HOST_B, 1), (1 << 0, false));
+        assert_eq!(input(&mut bridge, 0, HOST_B, HOST_A, 2), (1 << 1, false));
+        // a destination on the port the frame came from
+        assert_eq!(input(&mut bridge, 1, HOST_B, HOST_C, 3), (0, false));
+        // multicast sources and our own address are not learned
+        input(&mut bridge, 2, HOST_A, BROADCAST, 4);
+        input(&mut bridge, 2, HOST_A, LOCAL, 5);
+        assert_eq!(bridge.fdb.len(), 3);
+
+        // a host moving to another port
+        input(&mut bridge, 2, BROADCAST, HOST_A, 6);
+        assert_eq!(input(&mut bridge, 1, HOST_A, HOST_B, 7), (1 << 2, false));
+
+        // frames of the local network stack
+        assert_eq!(bridge.output_at(PORTS, &frame(HOST_B, LOCAL), 8), 1 << 1);
+        assert_eq!(bridge.output_at(PORTS, &frame(BROADCAST, LOCAL), 8), 0b111);
+        let unknown = [0x52, 0x54, 0, 0, 0, 0xd];
+        assert_eq!(bridge.output_at(PORTS, &frame(unknown, LOCAL), 8), 0b111);
+
+        let stats = bridge.stats;
+        assert_eq!((stats.forwarded, stats.flooded, stats.dropped), (5, 1, 2));
+    }
+
+    #[test]
+    fn test_flood_and_local() {
+        let mut bridge = Bridge::new(EthernetAddress(LOCAL), true);
+        // broadcast and multicast frames go to the other ports and to us
+        assert_eq!(input(&mut bridge, 1, BROADCAST, HOST_A, 0), (0b101, true));
+        let mdns = [0x01, 0x00, 0x5e, 0x00, 0x00, 0xfb];
+        assert_eq!(input(&mut bridge, 0, mdns, HOST_B, 0), (0b110, true));
+        // unknown destinations are flooded, but not delivered locally
+        assert_eq!(input(&mut bridge, 2, HOST_C, HOST_B, 0), (0b011, false));
+        // frames to us and to link-local protocols are not forwarded
+        assert_eq!(input(&mut bridge, 2, LOCAL, HOST_C, 0), (0, true));
+        let stp = [0x01, 0x80, 0xc2, 0x00, 0x00, 0x00];
+        assert_eq!(input(&mut bridge, 2, stp, HOST_C, 0), (0, true));
+        // runt frames are dropped
+        let runt = bridge.input_at(0, PORTS, &[0xff; 13], 0);
+        assert_eq!((runt.ports, runt.local), (0, false));
+
+        let stats = bridge.stats;
+        assert_eq!((stats.local, stats.flooded, stats.dropped), (4, 3, 1));
+    }
+
+    #[test]
+    fn test_age_out() {
+        let mut bridge = Bridge::new(EthernetAddress(LOCAL), true);
+        input(&mut bridge, 1, BROADCAST, HOST_A, 0);
+        let last = AGEING_TIME_NANOS - 1;
+        assert_eq!(input(&mut bridge, 0, HOST_A, HOST_B, last), (1 << 1, false));
+        // expired, so flooded again
+        let expired = AGEING_TIME_NANOS;
+        assert_eq!(
+            input(&mut bridge, 0, HOST_A, HOST_B, expired),
+            (0b110, false)
+        );
+        assert_eq!(
+            bridge.output_at(PORTS, &frame(HOST_A, LOCAL), expired),
+            0b111
+        );
+        // but refreshed by a frame from it
+        input(&mut bridge, 1, HOST_B, HOST_A, expired);
+        assert_eq!(
+            bridge.output_at(PORTS, &frame(HOST_A, LOCAL), expired),
+            1 << 1
+        );
+    }
+
+    #[test]
+    fn test_fdb_full() {
+        let mut bridge = Bridge::new(EthernetAddress(LOCAL), false);
+        for i in 0..MAX_FDB_ENTRIES as u64 {
+            let mut mac = [0x02, 0, 0, 0, 0, 0];
+            mac[2..].copy_from_slice(&(i as u32).to_be_bytes());
+            bridge.learn(mac, 0, i);
+        }
+        // the oldest entry is evicted
+        bridge.learn(HOST_A, 1, MAX_FDB_ENTRIES as u64);
+        assert_eq!(bridge.fdb.len(), MAX_FDB_ENTRIES);
+        assert!(!bridge.fdb.contains_key(&[0x02, 0, 0, 0, 0, 0]));
+        assert_eq!(bridge.lookup(&HOST_A, MAX_FDB_ENTRIES as u64), Some(1));
+        // expired entries are evicted first
+        let later = AGEING_TIME_NANOS + 2;
+        bridge.learn(HOST_B, 1, later);
+        assert_eq!(bridge.fdb.len(), MAX_FDB_ENTRIES - 1);
+    }
+}
@@ -0,0 +1,154 @@
+//! A pool of frame buffers for the frames built in memory before they are
+//! copied to the NICs, i.e., the frames sent when VLAN sub-interfaces exist
+//! or the bridge is started, and the packets forwarded by the router.
+//!
+//! Buffers are reused instead of being allocated for every frame, and leave
+//! headroom before the frame, so that the Ethernet header or the VLAN tag is
+//! prepended in place instead of copying the frame again.
+//!
+//! The frames sent by the sockets are still copied into the socket buffers
+//! of smoltcp, which keeps them for retransmission, and the NIC drivers take
+//! a single buffer per frame, so there is no scatter-gather transmit from the
+//! application buffers to the NICs.
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::{Deref, DerefMut};
+
+use spin::Mutex;
+
+/// Size of the pooled buffers, enough for the largest Ethernet frame with a
+/// VLAN tag.
+const FRAME_BUF_LEN: usize = 2048;
+/// Maximum number of free buffers kept in the pool.
+const MAX_FREE_BUFS: usize = 256;
+
+static FREE_BUFS: Mutex<Vec<Box<[u8]>>> = Mutex::new(Vec::new());
+
+/// A frame in a buffer from the pool, which is returned to the pool when
+/// dropped.
+pub(super) struct FrameBuf {
+    buf: Box<[u8]>,
+    start: usize,
+    end: usize,
+}
+
+impl FrameBuf {
+    /// Takes a buffer of at least `size` bytes, with stale contents.
+    fn take_buf(size: usize) -> Box<[u8]> {
+        if size > FRAME_BUF_LEN {
+            // larger than any Ethernet frame, not pooled
+            return vec![0; size].into_boxed_slice();
+        }
+        let free = FREE_BUFS.lock().pop();
+        free.unwrap_or_else(|| vec![0; FRAME_BUF_LEN].into_boxed_slice())
+    }
+
+    /// Allocates a zeroed frame of `len` bytes, with `headroom` bytes before
+    /// it to be claimed by [`prepend`](Self::prepend).
+    pub fn alloc(headroom: usize, len: usize) -> Self {
+        let end = headroom + len;
+        let mut buf = Self::take_buf(end);
+        buf[headroom..end].fill(0);
+        Self {
+            buf,
+            start: headroom,
+            end,
+        }
+    }
+
+    /// Allocates a copy of `data`, with `headroom` bytes before it.
+    pub fn copy_from(headroom: usize, data: &[u8]) -> Self {
+        let end = headroom + data.len();
+        let mut buf = Self::take_buf(end);
+        buf[headroom..end].copy_from_slice(data);
+        Self {
+            buf,
+            start: headroom,
+            end,
+        }
+    }
+
+    /// Extends the frame by `len` bytes at its start into the headroom, and
+    /// returns the new bytes.
+    ///
+    /// Panics if the headroom is shorter than `len`.
+    pub fn prepend(&mut self, len: usize) -> &mut [u8] {
+        assert!(len <= self.start, "not enough headroom");
+        self.start -= len;
+        &mut self.buf[self.start..self.start + len]
+    }
+}
+
+impl Deref for FrameBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf[self.start..self.end]
+    }
+}
+
+impl DerefMut for FrameBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.buf[self.start..self.end]
+    }
+}
+
+impl Drop for FrameBuf {
+    fn drop(&mut self) {
+        if self.buf.len() != FRAME_BUF_LEN {
+            return;
+        }
+        let mut free = FREE_BUFS.lock();
+        if free.len() < MAX_FREE_BUFS {
+            free.push(core::mem::take(&mut self.buf));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headroom() {
+        let mut frame = FrameBuf::copy_from(4, b"payload");
+        assert_eq!(&frame[..], b"payload");
+        frame.prepend(2).copy_from_slice(b"ab");
+        frame.prepend(2).copy_from_slice(b"cd");
+        assert_eq!(&frame[..], b"cdabpayload");
+        frame[0] = b'x';
+        assert_eq!(&frame[..3], b"xda");
+    }
+
+    #[test]
+    #[should_panic(expected = "not enough headroom")]
+    fn test_prepend_overflow() {
+        let mut frame = FrameBuf::alloc(2, 10);
+        frame.prepend(3);
+    }
+
+    #[test]
+    fn test_reuse() {
+        // reused buffers are zeroed by `alloc`
+        let mut frames: Vec<_> = (0..MAX_FREE_BUFS + 8)
+            .map(|_| FrameBuf::alloc(0, FRAME_BUF_LEN))
+            .collect();
+        frames.iter_mut().for_each(|frame| frame.fill(0xff));
+        drop(frames);
+        assert!(FREE_BUFS.lock().len() <= MAX_FREE_BUFS);
+        let frame = FrameBuf::alloc(18, 1500);
+        assert_eq!(frame.len(), 1500);
+        assert!(frame.iter().all(|&b| b == 0));
+
+        // larger buffers are not pooled
+        let frame = FrameBuf::alloc(0, FRAME_BUF_LEN + 1);
+        assert_eq!(frame.len(), FRAME_BUF_LEN + 1);
+        drop(frame);
+        assert!(FREE_BUFS
+            .lock()
+            .iter()
+            .all(|buf| buf.len() == FRAME_BUF_LEN));
+    }
+}
@@ -0,0 +1,442 @@
+//! Packet capture on the NIC device layer.
+//!
+//! When enabled, every frame passing through the device wrapper is recorded
+//! with a timestamp into an in-memory ring. The ring can be dumped in the
+//! classic [pcap] file format at any time and opened with Wireshark or
+//! `tcpdump -r`.
+//!
+//! [pcap]: https://wiki.wireshark.org/Development/LibpcapFileFormat
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use axerrno::{ax_err, AxResult};
+use axhal::time::{wall_time_nanos, NANOS_PER_MICROS, NANOS_PER_SEC};
+use axsync::Mutex;
+
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4; // microsecond resolution
+const PCAP_VERSION_MAJOR: u16 = 2;
+const PCAP_VERSION_MINOR: u16 = 4;
+const PCAP_LINKTYPE_ETHERNET: u32 = 1;
+const PCAP_SNAPLEN: usize = 1514;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+const IP_PROTO_ICMP: u8 = 1;
+const IP_PROTO_TCP: u8 = 6;
+const IP_PROTO_UDP: u8 = 17;
+
+/// Default size of the capture ring in bytes.
+pub const DEFAULT_CAPTURE_CAPACITY: usize = 1024 * 1024;
+
+static CAPTURE_ON: AtomicBool = AtomicBool::new(false);
+static CAPTURE: Mutex<Option<CaptureRing>> = Mutex::new(None);
+
+/// Direction of a captured frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureDirection {
+    /// Received from the NIC.
+    Rx,
+    /// Transmitted to the NIC.
+    Tx,
+}
+
+/// A BPF-like filter deciding which frames are captured.
+///
+/// All specified conditions must match. An empty filter matches everything.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CaptureFilter {
+    /// Only capture frames with this ethertype.
+    pub ethertype: Option<u16>,
+    /// Only capture IPv4 packets carrying this protocol.
+    pub ip_protocol: Option<u8>,
+    /// Only capture TCP or UDP segments with this source or destination port.
+    pub port: Option<u16>,
+    /// Only capture frames in this direction.
+    pub direction: Option<CaptureDirection>,
+}
+
+/// Statistics of the packet capture.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CaptureStats {
+    /// Number of frames recorded into the ring.
+    pub captured: u64,
+    /// Number of recorded frames evicted because the ring was full.
+    pub evicted: u64,
+    /// Number of frames rejected by the filter.
+    pub filtered: u64,
+    /// Number of recorded frames cut to the snapshot length.
+    pub truncated: u64,
+    /// Number of frames dropped because they were larger than the ring.
+    pub dropped: u64,
+    /// Number of frames currently held in the ring.
+    pub queued: usize,
+}
+
+struct CaptureRecord {
+    timestamp_nanos: u64,
+    orig_len: usize,
+    data: Vec<u8>,
+}
+
+struct CaptureRing {
+    filter: CaptureFilter,
+    capacity: usize,
+    used: usize,
+    records: VecDeque<CaptureRecord>,
+    stats: CaptureStats,
+}
+
+impl CaptureFilter {
+    /// Parses a filter expression.
+    ///
+    /// The expression is a whitespace-separated list of primitives, all of
+    /// which must match:
+    ///
+    /// - `arp`, `ip`, or `ether proto <type>`: match the ethertype.
+    /// - `tcp`, `udp`, or `icmp`: match the IPv4 protocol.
+    /// - `port <port>`: match the TCP/UDP source or destination port.
+    /// - `inbound` or `outbound`: match the direction.
+    ///
+    /// Numbers can be given in decimal or in hex with a `0x` prefix.
+    pub fn parse(expr: &str) -> AxResult<Self> {
+        let mut filter = Self::default();
+        let mut tokens = expr.split_whitespace();
+        while let Some(tok) = tokens.next() {
+            match tok {
+                "and" | "&&" => {}
+                "arp" => filter.ethertype = Some(ETHERTYPE_ARP),
+                "ip" => filter.ethertype = Some(ETHERTYPE_IPV4),
+                "tcp" => filter.set_ip_protocol(IP_PROTO_TCP),
+                "udp" => filter.set_ip_protocol(IP_PROTO_UDP),
+                "icmp" => filter.set_ip_protocol(IP_PROTO_ICMP),
+                "inbound" => filter.direction = Some(CaptureDirection::Rx),
+                "outbound" => filter.direction = Some(CaptureDirection::Tx),
+                "port" => {
+                    let port = parse_number(tokens.next())?;
+                    filter.port =
+                        Some(u16::try_from(port).or_else(|_| {
+                            ax_err!(InvalidInput, "capture filter: port out of range")
+                        })?);
+                }
+                "ether" => {
+                    if tokens.next() != Some("proto") {
+                        return ax_err!(InvalidInput, "capture filter: expect `ether proto`");
+                    }
+                    let ty = parse_number(tokens.next())?;
+                    filter.ethertype = Some(u16::try_from(ty).or_else(|_| {
+                        ax_err!(InvalidInput, "capture filter: ethertype out of range")
+                    })?);
+                }
+                _ => return ax_err!(InvalidInput, "capture filter: unknown primitive"),
+            }
+        }
+        Ok(filter)
+    }
+
+    fn set_ip_protocol(&mut self, proto: u8) {
+        self.ethertype = Some(ETHERTYPE_IPV4);
+        self.ip_protocol = Some(proto);
+    }
+
+    /// Whether the given Ethernet frame matches the filter.
+    pub fn matches(&self, frame: &[u8], dir: CaptureDirection) -> bool {
+        if self.direction.is_some_and(|d| d != dir) {
+            return false;
+        }
+        if self.ethertype.is_none() && self.ip_protocol.is_none() && self.port.is_none() {
+            return true;
+        }
+        if frame.len() < 14 {
+            return false;
+        }
+        let ethertype = u16::from_be_bytes([frame[12], frame[13]]);
+        if self.ethertype.is_some_and(|ty| ty != ethertype) {
+            return false;
+        }
+        if self.ip_protocol.is_none() && self.port.is_none() {
+            return true;
+        }
+        if ethertype != ETHERTYPE_IPV4 {
+            return false;
+        }
+
+        let ip = &frame[14..];
+        if ip.len() < 20 {
+            return false;
+        }
+        let proto = ip[9];
+        if self.ip_protocol.is_some_and(|p| p != proto) {
+            return false;
+        }
+        match self.port {
+            None => true,
+            Some(port) => {
+                if proto != IP_PROTO_TCP && proto != IP_PROTO_UDP {
+                    return false;
+                }
+                let ihl = ((ip[0] & 0x0f) as usize) * 4;
+                if ip.len() < ihl + 4 {
+                    return false;
+                }
+                let src_port = u16::from_be_bytes([ip[ihl], ip[ihl + 1]]);
+                let dst_port = u16::from_be_bytes([ip[ihl + 2], ip[ihl + 3]]);
+                src_port == port || dst_port == port
+            }
+        }
+    }
+}
+
+impl CaptureRing {
+    fn new(filter: CaptureFilter, capacity: usize) -> Self {
+        Self {
+            filter,
+            capacity,
+            used: 0,
+            records: VecDeque::new(),
+            stats: CaptureStats::default(),
+        }
+    }
+
+    fn record(&mut self, frame: &[u8], dir: CaptureDirection) {
+        if !self.filter.matches(frame, dir) {
+            self.stats.filtered += 1;
+            return;
+        }
+        let data = &frame[..frame.len().min(PCAP_SNAPLEN)];
+        if data.len() > self.capacity {
+            self.stats.dropped += 1;
+            return;
+        }
+        if data.len() < frame.len() {
+            self.stats.truncated += 1;
+        }
+        while self.used + data.len() > self.capacity {
+            let old = self.records.pop_front().unwrap();
+            self.used -= old.data.len();
+            self.stats.evicted += 1;
+        }
+        self.used += data.len();
+        self.records.push_back(CaptureRecord {
+            timestamp_nanos: wall_time_nanos(),
+            orig_len: frame.len(),
+            data: data.to_vec(),
+        });
+        self.stats.captured += 1;
+    }
+
+    fn dump(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(24 + self.used + self.records.len() * 16);
+        out.extend_from_slice(&PCAP_MAGIC.to_le_bytes());
+        out.extend_from_slice(&PCAP_VERSION_MAJOR.to_le_bytes());
+        out.extend_from_slice(&PCAP_VERSION_MINOR.to_le_bytes());
+        out.extend_from_slice(&0i32.to_le_bytes()); // thiszone
+        out.extend_from_slice(&0u32.to_le_bytes()); // sigfigs
+        out.extend_from_slice(&(PCAP_SNAPLEN as u32).to_le_bytes());
+        out.extend_from_slice(&PCAP_LINKTYPE_ETHERNET.to_le_bytes());
+        for rec in &self.records {
+            let secs = (rec.timestamp_nanos / NANOS_PER_SEC) as u32;
+            let micros = ((rec.timestamp_nanos % NANOS_PER_SEC) / NANOS_PER_MICROS) as u32;
+            out.extend_from_slice(&secs.to_le_bytes());
+            out.extend_from_slice(&micros.to_le_bytes());
+            out.extend_from_slice(&(rec.data.len() as u32).to_le_bytes());
+            out.extend_from_slice(&(rec.orig_len as u32).to_le_bytes());
+            out.extend_from_slice(&rec.data);
+        }
+        out
+    }
+}
+
+fn parse_number(tok: Option<&str>) -> AxResult<u32> {
+    let tok = match tok {
+        Some(tok) => tok,
+        None => return ax_err!(InvalidInput, "capture filter: missing number"),
+    };
+    let res = match tok.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => tok.parse(),
+    };
+    res.or_else(|_| ax_err!(InvalidInput, "capture filter: invalid number"))
+}
+
+/// Records a frame into the capture ring if capturing is enabled.
+#[inline]
+pub(crate) fn capture_frame(frame: &[u8], dir: CaptureDirection) {
+    if CAPTURE_ON.load(Ordering::Acquire) {
+        if let Some(ring) = CAPTURE.lock().as_mut() {
+            ring.record(frame, dir);
+        }
+    }
+}
+
+/// Starts capturing frames matching `filter` into a ring of `capacity` bytes.
+///
+/// Frames captured by a previous session are discarded.
+pub fn start_capture(filter: CaptureFilter, capacity: usize) {
+    info!(
+        "start packet capture: {:?}, capacity = {}",
+        filter, capacity
+    );
+    *CAPTURE.lock() = Some(CaptureRing::new(filter, capacity));
+    CAPTURE_ON.store(true, Ordering::Release);
+}
+
+/// Stops capturing frames.
+///
+/// The frames already captured are kept and can still be dumped.
+pub fn stop_capture() {
+    CAPTURE_ON.store(false, Ordering::Release);
+}
+
+/// Whether packet capture is in progress.
+pub fn is_capturing() -> bool {
+    CAPTURE_ON.load(Ordering::Acquire)
+}
+
+/// Returns the statistics of the current capture session.
+pub fn capture_stats() -> CaptureStats {
+    CAPTURE
+        .lock()
+        .as_ref()
+        .map_or_else(CaptureStats::default, |ring| CaptureStats {
+            queued: ring.records.len(),
+            ..ring.stats
+        })
+}
+
+/// Returns the captured frames in the pcap file format.
+///
+/// If `clear` is true, the returned frames are removed from the ring.
+pub fn dump_capture(clear: bool) -> Vec<u8> {
+    let mut capture = CAPTURE.lock();
+    match capture.as_mut() {
+        Some(ring) => {
+            let data = ring.dump();
+            if clear {
+                ring.records.clear();
+                ring.used = 0;
+            }
+            data
+        }
+        None => CaptureRing::new(CaptureFilter::default(), 0).dump(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds an Ethernet frame with an IPv4 header and the ports of a TCP
+    /// or UDP header, padded to `len` bytes.
+    fn ipv4_frame(proto: u8, src_port: u16, dst_port: u16, len: usize) -> Vec<u8> {
+        let mut frame = alloc::vec![0; len.max(14 + 20 + 4)];
+        frame[12..14].copy_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        frame[14] = 0x45; // version 4, IHL 5
+        frame[14 + 9] = proto;
+        frame[34..36].copy_from_slice(&src_port.to_be_bytes());
+        frame[36..38].copy_from_slice(&dst_port.to_be_bytes());
+        frame
+    }
+
+    fn arp_frame() -> Vec<u8> {
+        let mut frame = alloc::vec![0; 42];
+        frame[12..14].copy_from_slice(&ETHERTYPE_ARP.to_be_bytes());
+        frame
+    }
+
+    #[test]
+    fn test_filter_parse() {
+        let filter = CaptureFilter::parse("tcp and port 0x50 inbound").unwrap();
+        assert_eq!(filter.ethertype, Some(ETHERTYPE_IPV4));
+        assert_eq!(filter.ip_protocol, Some(IP_PROTO_TCP));
+        assert_eq!(filter.port, Some(80));
+        assert_eq!(filter.direction, Some(CaptureDirection::Rx));
+
+        let filter = CaptureFilter::parse("ether proto 0x86dd").unwrap();
+        assert_eq!(filter.ethertype, Some(0x86dd));
+        assert_eq!(CaptureFilter::parse("").unwrap(), CaptureFilter::default());
+
+        assert!(CaptureFilter::parse("port 65536").is_err());
+        assert!(CaptureFilter::parse("port").is_err());
+        assert!(CaptureFilter::parse("ether 0x0800").is_err());
+        assert!(CaptureFilter::parse("ip6").is_err());
+    }
+
+    #[test]
+    fn test_filter_ethertype() {
+        let rx = CaptureDirection::Rx;
+        let arp = CaptureFilter::parse("arp").unwrap();
+        assert!(arp.matches(&arp_frame(), rx));
+        assert!(!arp.matches(&ipv4_frame(IP_PROTO_TCP, 1, 2, 60), rx));
+        assert!(!arp.matches(&arp_frame()[..13], rx));
+
+        let ip = CaptureFilter::parse("ether proto 0x0800").unwrap();
+        assert!(ip.matches(&ipv4_frame(IP_PROTO_UDP, 1, 2, 60), rx));
+        assert!(!ip.matches(&arp_frame(), rx));
+
+        let any = CaptureFilter::default();
+        assert!(any.matches(&arp_frame(), rx));
+        assert!(any.matches(&[], CaptureDirection::Tx));
+    }
+
+    #[test]
+    fn test_filter_port() {
+        let (rx, tx) = (CaptureDirection::Rx, CaptureDirection::Tx);
+        let http = CaptureFilter::parse("tcp port 80").unwrap();
+        assert!(http.matches(&ipv4_frame(IP_PROTO_TCP, 80, 40000, 60), rx));
+        assert!(http.matches(&ipv4_frame(IP_PROTO_TCP, 40000, 80, 60), tx));
+        assert!(!http.matches(&ipv4_frame(IP_PROTO_TCP, 40000, 81, 60), rx));
+        assert!(!http.matches(&ipv4_frame(IP_PROTO_UDP, 40000, 80, 60), rx));
+
+        let dns = CaptureFilter::parse("port 53 outbound").unwrap();
+        assert!(dns.matches(&ipv4_frame(IP_PROTO_UDP, 5353, 53, 60), tx));
+        assert!(dns.matches(&ipv4_frame(IP_PROTO_TCP, 53, 5353, 60), tx));
+        assert!(!dns.matches(&ipv4_frame(IP_PROTO_UDP, 5353, 53, 60), rx));
+        // ICMP has no ports
+        assert!(!dns.matches(&ipv4_frame(IP_PROTO_ICMP, 53, 53, 60), tx));
+        assert!(!dns.matches(&arp_frame(), tx));
+        // truncated transport header
+        assert!(!dns.matches(&ipv4_frame(IP_PROTO_UDP, 53, 53, 60)[..36], tx));
+    }
+
+    #[test]
+    fn test_ring_wraparound() {
+        let rx = CaptureDirection::Rx;
+        let mut ring = CaptureRing::new(CaptureFilter::parse("udp").unwrap(), 300);
+        for port in 1..=5 {
+            ring.record(&ipv4_frame(IP_PROTO_UDP, port, 0, 100), rx);
+        }
+        ring.record(&arp_frame(), rx);
+        // the oldest frames are evicted to make room
+        assert_eq!(ring.used, 300);
+        assert_eq!(ring.stats.captured, 5);
+        assert_eq!(ring.stats.evicted, 2);
+        assert_eq!(ring.stats.filtered, 1);
+        let ports: Vec<_> = ring
+            .records
+            .iter()
+            .map(|rec| u16::from_be_bytes([rec.data[34], rec.data[35]]))
+            .collect();
+        assert_eq!(ports, [3, 4, 5]);
+
+        // frames larger than the ring are dropped
+        ring.record(&ipv4_frame(IP_PROTO_UDP, 6, 0, 301), rx);
+        assert_eq!(ring.stats.filtered, 1);
+        assert_eq!(ring.stats.dropped, 1);
+        assert_eq!(ring.records.len(), 3);
+
+        // frames are truncated to the snapshot length
+        let mut ring = CaptureRing::new(CaptureFilter::default(), 2 * PCAP_SNAPLEN);
+        ring.record(&ipv4_frame(IP_PROTO_UDP, 1, 0, 9000), rx);
+        assert_eq!(ring.records[0].data.len(), PCAP_SNAPLEN);
+        assert_eq!(ring.records[0].orig_len, 9000);
+        assert_eq!(ring.stats.truncated, 1);
+
+        let dump = ring.dump();
+        assert_eq!(dump.len(), 24 + 16 + PCAP_SNAPLEN);
+        assert_eq!(dump[..4], PCAP_MAGIC.to_le_bytes());
+        assert_eq!(dump[32..36], (PCAP_SNAPLEN as u32).to_le_bytes());
+        assert_eq!(dump[36..40], 9000u32.to_le_bytes());
+    }
+}
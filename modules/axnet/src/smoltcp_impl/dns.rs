@@ -1,90 +1,631 @@
+//! DNS stub resolver.
+//!
+//! Names are looked up in the hosts table (`/etc/hosts`) first, then in the
+//! cache of previous answers, and at last sent to the configured name
+//! servers. Answers are cached as long as their TTL allows.
+
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
-use axerrno::{ax_err_type, AxError, AxResult};
-use core::net::IpAddr;
-
-use smoltcp::iface::SocketHandle;
-use smoltcp::socket::dns::{self, GetQueryResultError, StartQueryError};
-use smoltcp::wire::DnsQueryType;
-
-use super::addr::into_core_ipaddr;
-use super::{SocketSetWrapper, ETH0, SOCKET_SET};
-
-/// A DNS socket.
-struct DnsSocket {
-    handle: Option<SocketHandle>,
-}
-
-impl DnsSocket {
-    #[allow(clippy::new_without_default)]
-    /// Creates a new DNS socket.
-    pub fn new() -> Self {
-        let socket = SocketSetWrapper::new_dns_socket();
-        let handle = Some(SOCKET_SET.add(socket));
-        Self { handle }
-    }
-
-    #[allow(dead_code)]
-    /// Update the list of DNS servers, will replace all existing servers.
-    pub fn update_servers(self, servers: &[smoltcp::wire::IpAddress]) {
-        SOCKET_SET.with_socket_mut::<dns::Socket, _, _>(self.handle.unwrap(), |socket| {
-            socket.update_servers(servers)
-        });
-    }
-
-    /// Query a address with given DNS query type.
-    pub fn query(&self, name: &str, query_type: DnsQueryType) -> AxResult<Vec<IpAddr>> {
-        // let local_addr = self.local_addr.unwrap_or_else(f);
-        let handle = self.handle.ok_or_else(|| ax_err_type!(InvalidInput))?;
-        let iface = &ETH0.iface;
-        let query_handle = SOCKET_SET
-            .with_socket_mut::<dns::Socket, _, _>(handle, |socket| {
-                socket.start_query(iface.lock().context(), name, query_type)
-            })
-            .map_err(|e| match e {
-                StartQueryError::NoFreeSlot => {
-                    ax_err_type!(ResourceBusy, "socket query() failed: no free slot")
-                }
-                StartQueryError::InvalidName => {
-                    ax_err_type!(InvalidInput, "socket query() failed: invalid name")
-                }
-                StartQueryError::NameTooLong => {
-                    ax_err_type!(InvalidInput, "socket query() failed: too long name")
-                }
-            })?;
-        loop {
-            SOCKET_SET.poll_interfaces();
-            match SOCKET_SET.with_socket_mut::<dns::Socket, _, _>(handle, |socket| {
-                socket.get_query_result(query_handle).map_err(|e| match e {
-                    GetQueryResultError::Pending => AxError::WouldBlock,
-                    GetQueryResultError::Failed => {
-                        ax_err_type!(ConnectionRefused, "socket query() failed")
+use core::net::{IpAddr, Ipv4Addr, SocketAddr};
+use core::time::Duration;
+
+use axerrno::{ax_err, AxError, AxResult};
+use axhal::time::{monotonic_time_nanos, NANOS_PER_SEC};
+use axsync::Mutex;
+
+use super::{UdpSocket, ETH0, SOCKET_SET};
+
+const DNS_PORT: u16 = 53;
+/// Source ports of queries are chosen at random from the ephemeral ports.
+const QUERY_PORT_START: u16 = 0xc000;
+const QUERY_PORT_MASK: u16 = 0x3fff;
+const MAX_PORT_ATTEMPTS: usize = 16;
+const DEFAULT_SERVER: Ipv4Addr = Ipv4Addr::new(8, 8, 8, 8);
+/// Maximum number of name servers, like `MAXNS` of glibc.
+const MAX_SERVERS: usize = 3;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+const DEFAULT_ATTEMPTS: usize = 2;
+
+const MAX_CACHE_ENTRIES: usize = 64;
+/// Cached answers expire after at most one day, whatever their TTL.
+const MAX_CACHE_TTL: u32 = 24 * 3600;
+
+const MAX_NAME_LEN: usize = 253;
+const MAX_LABEL_LEN: usize = 63;
+/// Maximum number of compression pointers followed in a name.
+const MAX_POINTERS: usize = 16;
+const MAX_MESSAGE_LEN: usize = 512;
+
+const TYPE_A: u16 = 1;
+const CLASS_IN: u16 = 1;
+const FLAG_RESPONSE: u16 = 0x8000;
+const FLAG_RECURSION_DESIRED: u16 = 0x0100;
+const RCODE_MASK: u16 = 0x000f;
+const RCODE_NAME_ERROR: u16 = 3;
+
+static CONFIG: Mutex<ResolverConfig> = Mutex::new(ResolverConfig::new());
+static HOSTS: Mutex<Vec<(String, IpAddr)>> = Mutex::new(Vec::new());
+static CACHE: Mutex<Vec<CacheEntry>> = Mutex::new(Vec::new());
+
+struct ResolverConfig {
+    /// Name servers, [`DEFAULT_SERVER`] is used if empty.
+    servers: Vec<IpAddr>,
+    /// Domains appended to names without dots.
+    search: Vec<String>,
+    /// Time to wait for the answer of a server.
+    timeout: Duration,
+    /// Number of rounds of queries to all servers.
+    attempts: usize,
+}
+
+struct CacheEntry {
+    name: String,
+    addrs: Vec<IpAddr>,
+    expires: u64,
+}
+
+/// The result of a query to a name server.
+enum Answer {
+    Addrs(Vec<IpAddr>, u32),
+    /// The name does not exist, or has no address.
+    NotFound,
+    /// The server failed, the next one should be asked.
+    Failed,
+}
+
+impl ResolverConfig {
+    const fn new() -> Self {
+        Self {
+            servers: Vec::new(),
+            search: Vec::new(),
+            timeout: DEFAULT_TIMEOUT,
+            attempts: DEFAULT_ATTEMPTS,
+        }
+    }
+
+    fn servers(&self) -> Vec<IpAddr> {
+        if self.servers.is_empty() {
+            alloc::vec![IpAddr::V4(DEFAULT_SERVER)]
+        } else {
+            self.servers.clone()
+        }
+    }
+}
+
+pub(super) fn be16(buf: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*buf.get(pos)?, *buf.get(pos + 1)?]))
+}
+
+fn be32(buf: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(buf.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+/// Writes a dotted name as a sequence of labels, without compression.
+pub(super) fn write_name(out: &mut Vec<u8>, name: &str) {
+    for label in name.split('.').filter(|l| !l.is_empty()) {
+        out.push(label.len() as u8);
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(0);
+}
+
+/// Reads the possibly compressed name at `pos` in the message, returns the
+/// dotted name and the position after it.
+pub(super) fn read_name(msg: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut name = String::new();
+    let mut end = None;
+    let mut pointers = 0;
+    loop {
+        let len = *msg.get(pos)? as usize;
+        if len == 0 {
+            return Some((name, end.unwrap_or(pos + 1)));
+        } else if len & 0xc0 == 0xc0 {
+            pointers += 1;
+            if pointers > MAX_POINTERS {
+                return None;
+            }
+            end.get_or_insert(pos + 2);
+            pos = ((len & 0x3f) << 8) | *msg.get(pos + 1)? as usize;
+        } else if len & 0xc0 != 0 {
+            return None;
+        } else {
+            let label = msg.get(pos + 1..pos + 1 + len)?;
+            if !name.is_empty() {
+                name.push('.');
+            }
+            name.push_str(&String::from_utf8_lossy(label));
+            if name.len() > MAX_NAME_LEN + 2 {
+                return None;
+            }
+            pos += 1 + len;
+        }
+    }
+}
+
+fn check_name(name: &str) -> AxResult {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .split('.')
+            .all(|l| !l.is_empty() && l.len() <= MAX_LABEL_LEN);
+    if !valid {
+        return ax_err!(InvalidInput, "dns_query() failed: invalid name");
+    }
+    Ok(())
+}
+
+fn build_query(id: u16, name: &str) -> Vec<u8> {
+    let mut query = Vec::with_capacity(18 + name.len());
+    query.extend_from_slice(&id.to_be_bytes());
+    query.extend_from_slice(&FLAG_RECURSION_DESIRED.to_be_bytes());
+    query.extend_from_slice(&1u16.to_be_bytes()); // one question
+    query.extend_from_slice(&[0; 6]);
+    write_name(&mut query, name);
+    query.extend_from_slice(&TYPE_A.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+    query
+}
+
+/// Parses the response to the query `id`, returns `None` if it is not.
+fn parse_response(msg: &[u8], id: u16) -> Option<Answer> {
+    if be16(msg, 0)? != id {
+        return None;
+    }
+    let flags = be16(msg, 2)?;
+    if flags & FLAG_RESPONSE == 0 {
+        return None;
+    }
+    match flags & RCODE_MASK {
+        0 => {}
+        RCODE_NAME_ERROR => return Some(Answer::NotFound),
+        _ => return Some(Answer::Failed),
+    }
+    let qdcount = be16(msg, 4)?;
+    let ancount = be16(msg, 6)?;
+
+    let mut pos = 12;
+    for _ in 0..qdcount {
+        pos = read_name(msg, pos)?.1 + 4;
+    }
+    // The addresses of the aliases (CNAME) are in the same answer section.
+    let mut addrs = Vec::new();
+    let mut ttl = MAX_CACHE_TTL;
+    for _ in 0..ancount {
+        pos = read_name(msg, pos)?.1;
+        let rtype = be16(msg, pos)?;
+        let class = be16(msg, pos + 2)?;
+        let rttl = be32(msg, pos + 4)?;
+        let rdlen = be16(msg, pos + 8)? as usize;
+        let rdata = msg.get(pos + 10..pos + 10 + rdlen)?;
+        if rtype == TYPE_A && class == CLASS_IN && rdlen == 4 {
+            addrs.push(IpAddr::V4(Ipv4Addr::new(
+                rdata[0], rdata[1], rdata[2], rdata[3],
+            )));
+            ttl = ttl.min(rttl);
+        }
+        pos += 10 + rdlen;
+    }
+    if addrs.is_empty() {
+        Some(Answer::NotFound)
+    } else {
+        Some(Answer::Addrs(addrs, ttl))
+    }
+}
+
+/// Returns a random number for transaction IDs and ports, which must not
+/// fail on machines without an entropy source.
+fn random_u16() -> u16 {
+    axhal::random::weak_random_u64() as u16
+}
+
+/// Binds the socket of the queries to a random free port, so that forged
+/// answers have to guess the port as well as the transaction ID.
+fn bind_random_port(socket: &UdpSocket) -> AxResult {
+    for _ in 0..MAX_PORT_ATTEMPTS {
+        let port = QUERY_PORT_START | (random_u16() & QUERY_PORT_MASK);
+        if !SOCKET_SET.udp_port_in_use(port) {
+            return socket.bind(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), port));
+        }
+    }
+    ax_err!(AddrInUse, "dns_query() failed: no free port")
+}
+
+/// Sends the query of `name` to the servers in turn, until one answers.
+fn query_servers(name: &str) -> AxResult<(Vec<IpAddr>, u32)> {
+    let (servers, timeout, attempts) = {
+        let config = CONFIG.lock();
+        (config.servers(), config.timeout, config.attempts)
+    };
+    let timeout = timeout.as_nanos() as u64;
+
+    let socket = UdpSocket::new();
+    bind_random_port(&socket)?;
+    socket.set_nonblocking(true);
+    let mut buf = [0; MAX_MESSAGE_LEN];
+    for attempt in 0..attempts {
+        for &server in &servers {
+            let server = SocketAddr::new(server, DNS_PORT);
+            let id = random_u16();
+            debug!(
+                "DNS: query {} from {} (attempt {})",
+                name,
+                server,
+                attempt + 1
+            );
+            if let Err(e) = socket.send_to(&build_query(id, name), server) {
+                warn!("DNS: failed to send the query to {}: {:?}", server, e);
+                continue;
+            }
+
+            let deadline = monotonic_time_nanos() + timeout;
+            let answer = loop {
+                SOCKET_SET.poll_interfaces();
+                match socket.recv_from(&mut buf) {
+                    Ok((len, from)) if from == server => {
+                        if let Some(answer) = parse_response(&buf[..len], id) {
+                            break Some(answer);
+                        }
                     }
-                })
-            }) {
-                Ok(n) => {
-                    let mut res = Vec::with_capacity(n.capacity());
-                    for ip in n {
-                        res.push(into_core_ipaddr(ip))
+                    Ok(_) => {} // a late answer, or from another host
+                    Err(AxError::WouldBlock) => {
+                        if monotonic_time_nanos() >= deadline {
+                            break None;
+                        }
+                        axtask::yield_now();
                     }
-                    return Ok(res);
+                    Err(e) => return Err(e),
+                }
+            };
+            match answer {
+                Some(Answer::Addrs(addrs, ttl)) => return Ok((addrs, ttl)),
+                Some(Answer::NotFound) => {
+                    return ax_err!(NotFound, "dns_query() failed: no such name")
                 }
-                Err(AxError::WouldBlock) => axtask::yield_now(),
+                Some(Answer::Failed) => warn!("DNS: server {} failed on {}", server, name),
+                None => debug!("DNS: query {} from {} timed out", name, server),
+            }
+        }
+    }
+    ax_err!(
+        ConnectionRefused,
+        "dns_query() failed: no answer from name servers"
+    )
+}
+
+fn lookup_hosts(name: &str) -> Option<Vec<IpAddr>> {
+    let addrs: Vec<IpAddr> = HOSTS
+        .lock()
+        .iter()
+        .filter(|(host, _)| host.eq_ignore_ascii_case(name))
+        .map(|(_, addr)| *addr)
+        .collect();
+    (!addrs.is_empty()).then_some(addrs)
+}
+
+fn lookup_cache(name: &str) -> Option<Vec<IpAddr>> {
+    let now = monotonic_time_nanos();
+    let mut cache = CACHE.lock();
+    cache.retain(|e| e.expires > now);
+    cache
+        .iter()
+        .find(|e| e.name.eq_ignore_ascii_case(name))
+        .map(|e| e.addrs.clone())
+}
+
+fn insert_cache(name: &str, addrs: &[IpAddr], ttl: u32) {
+    if ttl == 0 {
+        return;
+    }
+    let now = monotonic_time_nanos();
+    let expires = now + ttl.min(MAX_CACHE_TTL) as u64 * NANOS_PER_SEC;
+    let mut cache = CACHE.lock();
+    cache.retain(|e| e.expires > now && !e.name.eq_ignore_ascii_case(name));
+    if cache.len() >= MAX_CACHE_ENTRIES {
+        // evict the entry which expires first
+        let (idx, _) = cache
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, e)| e.expires)
+            .unwrap();
+        cache.swap_remove(idx);
+    }
+    cache.push(CacheEntry {
+        name: name.to_ascii_lowercase(),
+        addrs: addrs.to_vec(),
+        expires,
+    });
+}
+
+fn resolve(name: &str) -> AxResult<Vec<IpAddr>> {
+    if let Some(addrs) = lookup_hosts(name).or_else(|| lookup_cache(name)) {
+        return Ok(addrs);
+    }
+    let (addrs, ttl) = query_servers(name)?;
+    insert_cache(name, &addrs, ttl);
+    Ok(addrs)
+}
+
+/// Resolves the host name to a list of IP addresses.
+///
+/// Names without dots are tried with each search domain appended first.
+pub fn dns_query(name: &str) -> AxResult<Vec<IpAddr>> {
+    check_name(name)?;
+    let name = name.strip_suffix('.').unwrap_or(name);
+    if !name.contains('.') {
+        let search = CONFIG.lock().search.clone();
+        for domain in search {
+            let fqdn = alloc::format!("{}.{}", name, domain);
+            match resolve(&fqdn) {
+                Ok(addrs) => return Ok(addrs),
+                Err(AxError::NotFound) => {}
                 Err(e) => return Err(e),
             }
         }
     }
+    resolve(name)
+}
+
+/// Sets the name servers, which are queried in order.
+///
+/// At most 3 servers are used. If none is set, `8.8.8.8` is used.
+pub fn dns_set_servers(servers: &[IpAddr]) {
+    let servers: Vec<IpAddr> = servers
+        .iter()
+        .filter(|ip| ip.is_ipv4())
+        .take(MAX_SERVERS)
+        .copied()
+        .collect();
+    info!("DNS: use name servers {:?}", servers);
+    CONFIG.lock().servers = servers;
+}
+
+/// Returns the name servers.
+pub fn dns_servers() -> Vec<IpAddr> {
+    CONFIG.lock().servers()
+}
+
+/// Applies the configuration in the `resolv.conf(5)` format.
+///
+/// The `nameserver`, `search`, `domain` keywords and the `timeout:n` and
+/// `attempts:n` options are supported. The name servers are replaced only if
+/// any is listed.
+pub fn dns_load_resolv_conf(contents: &str) {
+    let mut servers = Vec::new();
+    let mut search = None;
+    let mut config = CONFIG.lock();
+    for line in contents.lines() {
+        let line = line.split(['#', ';']).next().unwrap_or("");
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("nameserver") => match words.next().map(str::parse::<IpAddr>) {
+                Some(Ok(ip)) => servers.push(ip),
+                _ => warn!("resolv.conf: invalid line {:?}", line),
+            },
+            Some("search") | Some("domain") => {
+                search = Some(words.map(|d| d.trim_end_matches('.').to_string()).collect())
+            }
+            Some("options") => {
+                for option in words {
+                    match option.split_once(':') {
+                        Some(("timeout", n)) => {
+                            if let Ok(n) = n.parse::<u64>() {
+                                config.timeout = Duration::from_secs(n.clamp(1, 30));
+                            }
+                        }
+                        Some(("attempts", n)) => {
+                            if let Ok(n) = n.parse::<usize>() {
+                                config.attempts = n.clamp(1, 5);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some(search) = search {
+        config.search = search;
+    }
+    drop(config);
+    if !servers.is_empty() {
+        dns_set_servers(&servers);
+    }
 }
 
-impl Drop for DnsSocket {
-    fn drop(&mut self) {
-        if let Some(handle) = self.handle {
-            SOCKET_SET.remove(handle);
+/// Replaces the hosts table with the entries in the `hosts(5)` format.
+///
+/// Lookups of the names in the table are answered without queries. The
+/// built-in `localhost` entry is kept unless `contents` gives the address of
+/// `localhost`.
+pub fn dns_load_hosts(contents: &str) {
+    let mut hosts = Vec::new();
+    for line in contents.lines() {
+        let mut words = line.split('#').next().unwrap_or("").split_whitespace();
+        let Some(addr) = words.next() else {
+            continue;
+        };
+        match addr.parse::<IpAddr>() {
+            Ok(ip) if ip.is_ipv4() => {
+                hosts.extend(words.map(|name| (name.to_string(), ip)));
+            }
+            Ok(_) => {} // IPv6 is not supported
+            Err(_) => warn!("hosts: invalid line {:?}", line),
         }
     }
+    add_default_hosts(&mut hosts);
+    *HOSTS.lock() = hosts;
+}
+
+/// Removes all cached answers.
+pub fn dns_flush_cache() {
+    CACHE.lock().clear();
+}
+
+/// Adds the built-in `localhost` entry unless the table has one.
+fn add_default_hosts(hosts: &mut Vec<(String, IpAddr)>) {
+    if !hosts
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case("localhost"))
+    {
+        hosts.push(("localhost".to_string(), Ipv4Addr::LOCALHOST.into()));
+    }
+}
+
+pub(super) fn init() {
+    add_default_hosts(&mut HOSTS.lock());
+    // tell the IDs and ports of machines without entropy apart
+    axhal::random::add_weak_seed(ETH0.ethernet_address().as_bytes());
 }
 
-/// Public function for DNS query.
-pub fn dns_query(name: &str) -> AxResult<alloc::vec::Vec<IpAddr>> {
-    let socket = DnsSocket::new();
-    socket.query(name, DnsQueryType::A)
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    const TYPE_CNAME: u16 = 5;
+
+    /// Builds a response to the query of `example.com` with `id`, with the
+    /// answers given as (type, TTL, rdata). Answers are owned by the name
+    /// of the question through a compression pointer.
+    fn response(id: u16, flags: u16, answers: &[(u16, u32, &[u8])]) -> Vec<u8> {
+        let mut msg = build_query(id, "example.com");
+        msg[2..4].copy_from_slice(&(FLAG_RESPONSE | flags).to_be_bytes());
+        msg[6..8].copy_from_slice(&(answers.len() as u16).to_be_bytes());
+        for (rtype, ttl, rdata) in answers {
+            msg.extend_from_slice(&[0xc0, 12]);
+            msg.extend_from_slice(&rtype.to_be_bytes());
+            msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+            msg.extend_from_slice(&ttl.to_be_bytes());
+            msg.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+            msg.extend_from_slice(rdata);
+        }
+        msg
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let alias = [3, b'w', b'w', b'w', 0xc0, 12];
+        let msg = response(
+            0x1234,
+            0,
+            &[
+                (TYPE_CNAME, 10, &alias),
+                (TYPE_A, 300, &[1, 2, 3, 4]),
+                (TYPE_A, 60, &[5, 6, 7, 8]),
+            ],
+        );
+        let Some(Answer::Addrs(addrs, ttl)) = parse_response(&msg, 0x1234) else {
+            panic!("no addresses");
+        };
+        let expected: [IpAddr; 2] = [[1, 2, 3, 4].into(), [5, 6, 7, 8].into()];
+        assert_eq!(addrs, expected);
+        assert_eq!(ttl, 60); // the CNAME does not count
+
+        // not the answer to the query
+        assert!(parse_response(&msg, 0x1235).is_none());
+        assert!(parse_response(&build_query(0x1234, "example.com"), 0x1234).is_none());
+        for len in [0, 11, 20, msg.len() - 1] {
+            assert!(parse_response(&msg[..len], 0x1234).is_none());
+        }
+
+        let msg = response(1, RCODE_NAME_ERROR, &[]);
+        assert!(matches!(parse_response(&msg, 1), Some(Answer::NotFound)));
+        let msg = response(1, 2, &[]); // server failure
+        assert!(matches!(parse_response(&msg, 1), Some(Answer::Failed)));
+        let msg = response(1, 0, &[(TYPE_CNAME, 10, &alias), (TYPE_A, 10, &[1, 2, 3])]);
+        assert!(matches!(parse_response(&msg, 1), Some(Answer::NotFound)));
+    }
+
+    #[test]
+    fn test_read_name() {
+        let mut msg = vec![0; 12];
+        write_name(&mut msg, "example.com");
+        let end = msg.len();
+        msg.extend_from_slice(&[3, b'w', b'w', b'w', 0xc0, 12]);
+        assert_eq!(read_name(&msg, 12), Some(("example.com".into(), end)));
+        assert_eq!(
+            read_name(&msg, end),
+            Some(("www.example.com".into(), msg.len()))
+        );
+        assert_eq!(read_name(&msg, 20), Some(("com".into(), end)));
+
+        // truncated labels, names and pointers
+        for len in 12..end {
+            assert_eq!(read_name(&msg[..len], 12), None);
+        }
+        for len in end..msg.len() {
+            assert_eq!(read_name(&msg[..len], end), None);
+        }
+        // a pointer to itself, and two pointing to each other
+        assert_eq!(read_name(&[0xc0, 0], 0), None);
+        assert_eq!(read_name(&[0xc0, 2, 0xc0, 0], 0), None);
+        // reserved label types
+        assert_eq!(read_name(&[0x40, 0], 0), None);
+        assert_eq!(read_name(&[0x80, 0], 0), None);
+        // a name longer than 255 bytes
+        let mut long = Vec::new();
+        write_name(&mut long, &vec!["a".repeat(MAX_LABEL_LEN); 5].join("."));
+        assert_eq!(read_name(&long, 0), None);
+    }
+
+    #[test]
+    fn test_load_resolv_conf() {
+        dns_load_resolv_conf(
+            "# comment\n\
+             nameserver 10.0.0.1\n\
+             nameserver ::1\n\
+             nameserver bogus\n\
+             nameserver 10.0.0.2 ; comment\n\
+             domain example.org\n\
+             search example.com. lan\n\
+             options ndots:2 timeout:0 attempts:9\n\
+             nameserver 10.0.0.3\n\
+             nameserver 10.0.0.4\n",
+        );
+        let expected: [IpAddr; 3] = [
+            [10, 0, 0, 1].into(),
+            [10, 0, 0, 2].into(),
+            [10, 0, 0, 3].into(),
+        ];
+        assert_eq!(dns_servers(), expected);
+        {
+            let config = CONFIG.lock();
+            assert_eq!(config.search, ["example.com", "lan"]);
+            assert_eq!(config.timeout, Duration::from_secs(1));
+            assert_eq!(config.attempts, 5);
+        }
+
+        // servers are kept if none is listed
+        dns_load_resolv_conf("options timeout:3\n");
+        assert_eq!(dns_servers(), expected);
+        assert_eq!(CONFIG.lock().timeout, Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_load_hosts() {
+        dns_load_hosts(
+            "10.0.0.1  router gw # comment\n\
+             10.0.0.2\tnas\n\
+             10.0.0.3  NAS\n\
+             ::1       ip6-localhost\n\
+             bogus     host\n",
+        );
+        let addr = |a: [u8; 4]| Some(vec![IpAddr::from(a)]);
+        assert_eq!(lookup_hosts("GW"), addr([10, 0, 0, 1]));
+        assert_eq!(
+            lookup_hosts("nas"),
+            Some(vec![[10, 0, 0, 2].into(), [10, 0, 0, 3].into()])
+        );
+        assert_eq!(lookup_hosts("localhost"), addr([127, 0, 0, 1]));
+        assert_eq!(lookup_hosts("ip6-localhost"), None);
+        assert_eq!(lookup_hosts("host"), None);
+
+        // the table is replaced, and `localhost` can be overridden
+        dns_load_hosts("10.0.0.9 localhost\n");
+        assert_eq!(lookup_hosts("router"), None);
+        assert_eq!(lookup_hosts("localhost"), addr([10, 0, 0, 9]));
+    }
 }
@@ -0,0 +1,727 @@
+//! IPv4 forwarding between the NICs.
+//!
+//! When forwarding is started, each NIC other than `eth0` becomes the routed
+//! interface `eth<N>` with its own IPv4 address. They are handled here
+//! instead of by the network stack: the router answers ARP requests and pings
+//! for their addresses, and forwards the packets received on any NIC by the
+//! connected subnets and the default gateway of `eth0`. Packets to the
+//! address of `eth0` are delivered to the network stack, unless they belong
+//! to NAT (see [`nat`](super::nat)).
+//!
+//! The TTL of the forwarded packets is decremented, and ICMP errors are sent
+//! back for expired TTLs and unreachable destinations. Fragmented packets are
+//! forwarded, but are not translated by NAT.
+
+use alloc::collections::btree_map::Entry;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::net::Ipv4Addr;
+
+use axerrno::{ax_err, AxResult};
+use axhal::time::{monotonic_time_nanos, NANOS_PER_SEC};
+use smoltcp::wire::{ArpHardware, ArpOperation, ArpPacket, EthernetAddress, EthernetProtocol};
+use smoltcp::wire::{Icmpv4Message, Icmpv4Packet, IpAddress, IpCidr, IpProtocol};
+use smoltcp::wire::{Ipv4Address, Ipv4Cidr, Ipv4Packet};
+
+use super::buf_pool::FrameBuf;
+use super::nat::{fill_checksums, Nat};
+use super::ETH0;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+const ETHERNET_HEADER_LEN: usize = 14;
+const IPV4_HEADER_LEN: usize = 20;
+/// TTL of the packets sent by the router.
+const DEFAULT_TTL: u8 = 64;
+
+const MAX_NEIGHBORS: usize = 256;
+const NEIGHBOR_TIMEOUT: u64 = 60 * NANOS_PER_SEC;
+/// Maximum number of packets waiting for ARP resolution.
+const MAX_PENDING: usize = 64;
+const ARP_RETRY_INTERVAL: u64 = NANOS_PER_SEC;
+const ARP_RESOLVE_TIMEOUT: u64 = 3 * NANOS_PER_SEC;
+const EXPIRE_INTERVAL: u64 = NANOS_PER_SEC;
+
+// ICMP codes of destination unreachable.
+const ICMP_NET_UNREACHABLE: u8 = 0;
+const ICMP_HOST_UNREACHABLE: u8 = 1;
+
+/// Statistics of IP forwarding.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ForwardStats {
+    /// Packets forwarded.
+    pub forwarded: u64,
+    /// Packets dropped.
+    pub dropped: u64,
+    /// ICMP errors sent.
+    pub icmp_errors: u64,
+    /// Number of connections tracked by NAT.
+    pub nat_connections: usize,
+}
+
+/// A NIC with an IPv4 address.
+struct RoutedInterface {
+    ether_addr: EthernetAddress,
+    cidr: Ipv4Cidr,
+}
+
+struct Neighbor {
+    ether_addr: EthernetAddress,
+    port: usize,
+    last_seen: u64,
+}
+
+/// A packet waiting for the next hop to be resolved.
+struct Pending {
+    port: usize,
+    next_hop: Ipv4Address,
+    packet: FrameBuf,
+    since: u64,
+}
+
+pub(super) struct Router {
+    /// The interfaces, by their ports.
+    ifaces: Vec<RoutedInterface>,
+    gateway: Option<Ipv4Address>,
+    neighbors: BTreeMap<Ipv4Address, Neighbor>,
+    pending: VecDeque<Pending>,
+    /// When the last ARP requests were sent, by the next hops.
+    arp_requests: BTreeMap<Ipv4Address, u64>,
+    last_expire: u64,
+    pub nat: Nat,
+    /// Frames to send, with their ports.
+    pub tx_queue: VecDeque<(usize, FrameBuf)>,
+    stats: ForwardStats,
+}
+
+fn be16(buf: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*buf.get(pos)?, *buf.get(pos + 1)?]))
+}
+
+impl Router {
+    fn new(ifaces: Vec<RoutedInterface>, gateway: Option<Ipv4Address>) -> Self {
+        Self {
+            ifaces,
+            gateway,
+            neighbors: BTreeMap::new(),
+            pending: VecDeque::new(),
+            arp_requests: BTreeMap::new(),
+            last_expire: 0,
+            nat: Nat::new(),
+            tx_queue: VecDeque::new(),
+            stats: ForwardStats::default(),
+        }
+    }
+
+    fn is_local_addr(&self, addr: Ipv4Address) -> bool {
+        self.ifaces.iter().any(|iface| iface.cidr.address() == addr)
+    }
+
+    fn is_broadcast(&self, addr: Ipv4Address) -> bool {
+        addr.is_broadcast()
+            || addr.is_multicast()
+            || addr.is_unspecified()
+            || self
+                .ifaces
+                .iter()
+                .any(|iface| iface.cidr.broadcast() == Some(addr))
+    }
+
+    /// Returns the port and the next hop to send a packet to `dst` through.
+    fn route(&self, dst: Ipv4Address) -> Option<(usize, Ipv4Address)> {
+        let connected = |addr: &Ipv4Address| {
+            let mut ifaces = self.ifaces.iter();
+            ifaces.position(|iface| iface.cidr.contains_addr(addr))
+        };
+        match connected(&dst) {
+            Some(port) => Some((port, dst)),
+            None => {
+                let gateway = self.gateway?;
+                Some((connected(&gateway)?, gateway))
+            }
+        }
+    }
+
+    /// Handles a frame received on `port`.
+    ///
+    /// Returns whether the frame should be delivered to the network stack.
+    pub fn input(&mut self, port: usize, frame: &[u8]) -> bool {
+        let to_stack = port == 0;
+        if port >= self.ifaces.len() {
+            return false;
+        }
+        let now = monotonic_time_nanos();
+        let payload = &frame[ETHERNET_HEADER_LEN.min(frame.len())..];
+        match be16(frame, 12) {
+            Some(ETHERTYPE_ARP) => {
+                self.arp_input(port, payload, now);
+                to_stack
+            }
+            Some(ETHERTYPE_IPV4) if frame[..6] == self.ifaces[port].ether_addr.0 => {
+                self.ip_input(port, payload, now)
+            }
+            _ => to_stack,
+        }
+    }
+
+    fn arp_input(&mut self, port: usize, payload: &[u8], now: u64) {
+        let Ok(arp) = ArpPacket::new_checked(payload) else {
+            return;
+        };
+        if arp.hardware_len() != 6 || arp.protocol_len() != 4 {
+            return;
+        }
+        let sender_mac = EthernetAddress::from_bytes(arp.source_hardware_addr());
+        let sender_ip = Ipv4Address::from_bytes(arp.source_protocol_addr());
+        let target_ip = Ipv4Address::from_bytes(arp.target_protocol_addr());
+        let iface = &self.ifaces[port];
+        if !iface.cidr.contains_addr(&sender_ip) || !sender_mac.is_unicast() {
+            return;
+        }
+        let for_us = target_ip == iface.cidr.address();
+        if for_us || self.neighbors.contains_key(&sender_ip) {
+            self.learn(port, sender_ip, sender_mac, now);
+        }
+        // the network stack answers for `eth0`
+        if port != 0 && for_us && arp.operation() == ArpOperation::Request {
+            self.send_arp(port, ArpOperation::Reply, sender_mac, sender_ip);
+        }
+    }
+
+    fn learn(&mut self, port: usize, ip: Ipv4Address, ether_addr: EthernetAddress, now: u64) {
+        if self.neighbors.len() >= MAX_NEIGHBORS && !self.neighbors.contains_key(&ip) {
+            self.neighbors
+                .retain(|_, n| now - n.last_seen < NEIGHBOR_TIMEOUT);
+            if self.neighbors.len() >= MAX_NEIGHBORS {
+                return;
+            }
+        }
+        let neighbor = Neighbor {
+            ether_addr,
+            port,
+            last_seen: now,
+        };
+        self.neighbors.insert(ip, neighbor);
+        self.arp_requests.remove(&ip);
+
+        // send the packets waiting for it
+        let mut i = 0;
+        while i < self.pending.len() {
+            if self.pending[i].next_hop == ip && self.pending[i].port == port {
+                let pending = self.pending.remove(i).unwrap();
+                self.send_ip(port, ether_addr, pending.packet);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    fn send_arp(
+        &mut self,
+        port: usize,
+        operation: ArpOperation,
+        target_mac: EthernetAddress,
+        target_ip: Ipv4Address,
+    ) {
+        let iface = &self.ifaces[port];
+        let dst_mac = match operation {
+            ArpOperation::Request => EthernetAddress::BROADCAST,
+            _ => target_mac,
+        };
+        let mut frame = FrameBuf::alloc(0, ETHERNET_HEADER_LEN + 28);
+        frame[..6].copy_from_slice(dst_mac.as_bytes());
+        frame[6..12].copy_from_slice(iface.ether_addr.as_bytes());
+        frame[12..14].copy_from_slice(&ETHERTYPE_ARP.to_be_bytes());
+        let mut arp = ArpPacket::new_unchecked(&mut frame[ETHERNET_HEADER_LEN..]);
+        arp.set_hardware_type(ArpHardware::Ethernet);
+        arp.set_protocol_type(EthernetProtocol::Ipv4);
+        arp.set_hardware_len(6);
+        arp.set_protocol_len(4);
+        arp.set_operation(operation);
+        arp.set_source_hardware_addr(iface.ether_addr.as_bytes());
+        arp.set_source_protocol_addr(iface.cidr.address().as_bytes());
+        arp.set_target_hardware_addr(target_mac.as_bytes());
+        arp.set_target_protocol_addr(target_ip.as_bytes());
+        self.tx_queue.push_back((port, frame));
+    }
+
+    /// Sends a packet with the Ethernet header prepended in its headroom.
+    fn send_ip(&mut self, port: usize, dst_mac: EthernetAddress, mut packet: FrameBuf) {
+        let header = packet.prepend(ETHERNET_HEADER_LEN);
+        header[..6].copy_from_slice(dst_mac.as_bytes());
+        header[6..12].copy_from_slice(self.ifaces[port].ether_addr.as_bytes());
+        header[12..14].copy_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        self.tx_queue.push_back((port, packet));
+    }
+
+    /// Sends a packet to the next hop, resolving its address first if needed.
+    fn output(&mut self, port: usize, next_hop: Ipv4Address, packet: FrameBuf, now: u64) {
+        let neighbor = self
+            .neighbors
+            .get(&next_hop)
+            .filter(|n| n.port == port && now - n.last_seen < NEIGHBOR_TIMEOUT)
+            .map(|n| n.ether_addr);
+        if let Some(ether_addr) = neighbor {
+            self.send_ip(port, ether_addr, packet);
+            return;
+        }
+        if self.pending.len() >= MAX_PENDING {
+            self.stats.dropped += 1;
+            return;
+        }
+        self.pending.push_back(Pending {
+            port,
+            next_hop,
+            packet,
+            since: now,
+        });
+        if let Entry::Vacant(entry) = self.arp_requests.entry(next_hop) {
+            entry.insert(now);
+            self.send_arp(
+                port,
+                ArpOperation::Request,
+                EthernetAddress([0; 6]),
+                next_hop,
+            );
+        }
+    }
+
+    /// Handles an IPv4 packet received on `port` for the MAC address of the
+    /// port.
+    ///
+    /// Returns whether the packet should be delivered to the network stack.
+    fn ip_input(&mut self, port: usize, payload: &[u8], now: u64) -> bool {
+        let to_stack = port == 0;
+        let Ok(ip) = Ipv4Packet::new_checked(payload) else {
+            return to_stack;
+        };
+        if ip.version() != 4 || !ip.verify_checksum() {
+            return to_stack;
+        }
+        let dst = ip.dst_addr();
+        // without the Ethernet padding
+        let packet = &payload[..ip.total_len() as usize];
+
+        if port == 0 && dst == self.ifaces[0].cidr.address() {
+            let mut packet = FrameBuf::copy_from(ETHERNET_HEADER_LEN, packet);
+            if !self.nat.inbound(&mut packet, now) {
+                return true;
+            }
+            self.forward(packet, now);
+            return false;
+        }
+        if self.is_local_addr(dst) {
+            self.local_input(packet, now);
+            return false;
+        }
+        if self.is_broadcast(dst) {
+            return to_stack;
+        }
+        // translated by NAT only if it leaves through `eth0`
+        self.forward_from(port, FrameBuf::copy_from(ETHERNET_HEADER_LEN, packet), now);
+        false
+    }
+
+    /// Answers the pings to the addresses of the router.
+    fn local_input(&mut self, packet: &[u8], now: u64) {
+        let ip = Ipv4Packet::new_unchecked(packet);
+        let is_echo = ip.next_header() == IpProtocol::Icmp
+            && !ip.more_frags()
+            && ip.frag_offset() == 0
+            && Icmpv4Packet::new_checked(ip.payload())
+                .is_ok_and(|icmp| icmp.msg_type() == Icmpv4Message::EchoRequest);
+        let Some((port, next_hop)) = self.route(ip.src_addr()).filter(|_| is_echo) else {
+            self.stats.dropped += 1;
+            return;
+        };
+        let mut reply = FrameBuf::copy_from(ETHERNET_HEADER_LEN, packet);
+        let mut ip = Ipv4Packet::new_unchecked(&mut reply[..]);
+        let (src, dst) = (ip.src_addr(), ip.dst_addr());
+        ip.set_src_addr(dst);
+        ip.set_dst_addr(src);
+        ip.set_hop_limit(DEFAULT_TTL);
+        Icmpv4Packet::new_unchecked(ip.payload_mut()).set_msg_type(Icmpv4Message::EchoReply);
+        fill_checksums(&mut reply);
+        self.output(port, next_hop, reply, now);
+    }
+
+    /// Forwards a packet received on `in_port`, with source NAT if it leaves
+    /// through `eth0`.
+    fn forward_from(&mut self, in_port: usize, mut packet: FrameBuf, now: u64) {
+        let ip = Ipv4Packet::new_unchecked(&packet[..]);
+        let out_port = self.route(ip.dst_addr()).map(|(port, _)| port);
+        // packets with expired TTLs are reported untranslated
+        if in_port != 0 && out_port == Some(0) && ip.hop_limit() > 1 {
+            let nat_addr = self.ifaces[0].cidr.address();
+            if !self.nat.outbound(&mut packet, nat_addr, now) {
+                self.stats.dropped += 1;
+                return;
+            }
+        }
+        self.forward(packet, now);
+    }
+
+    /// Forwards a packet by its destination address.
+    fn forward(&mut self, mut packet: FrameBuf, now: u64) {
+        let mut ip = Ipv4Packet::new_unchecked(&mut packet[..]);
+        let ttl = ip.hop_limit();
+        if ttl <= 1 {
+            self.icmp_error(&packet, Icmpv4Message::TimeExceeded, 0, now);
+            return;
+        }
+        let Some((port, next_hop)) = self.route(ip.dst_addr()) else {
+            self.icmp_error(
+                &packet,
+                Icmpv4Message::DstUnreachable,
+                ICMP_NET_UNREACHABLE,
+                now,
+            );
+            return;
+        };
+        ip.set_hop_limit(ttl - 1);
+        ip.fill_checksum();
+        self.stats.forwarded += 1;
+        self.output(port, next_hop, packet, now);
+    }
+
+    /// Sends an ICMP error about a packet back to its source.
+    fn icmp_error(&mut self, orig: &[u8], msg_type: Icmpv4Message, code: u8, now: u64) {
+        self.stats.dropped += 1;
+        let orig_ip = Ipv4Packet::new_unchecked(orig);
+        let src = orig_ip.src_addr();
+        // never about broadcasts, non-first fragments, or other ICMP errors
+        let is_error = orig_ip.next_header() == IpProtocol::Icmp
+            && !matches!(orig_ip.payload().first(), Some(0 | 8));
+        if self.is_broadcast(src) || orig_ip.frag_offset() != 0 || is_error {
+            return;
+        }
+        let Some((port, next_hop)) = self.route(src) else {
+            return;
+        };
+
+        // the IP header and the first 8 bytes of the payload are quoted
+        let quote_len = (orig_ip.header_len() as usize + 8).min(orig.len());
+        let total_len = IPV4_HEADER_LEN + 8 + quote_len;
+        let mut packet = FrameBuf::alloc(ETHERNET_HEADER_LEN, total_len);
+        let mut ip = Ipv4Packet::new_unchecked(&mut packet[..]);
+        ip.set_version(4);
+        ip.set_header_len(IPV4_HEADER_LEN as u8);
+        ip.set_total_len(total_len as u16);
+        ip.set_hop_limit(DEFAULT_TTL);
+        ip.set_next_header(IpProtocol::Icmp);
+        ip.set_src_addr(self.ifaces[port].cidr.address());
+        ip.set_dst_addr(src);
+        let mut icmp = Icmpv4Packet::new_unchecked(ip.payload_mut());
+        icmp.set_msg_type(msg_type);
+        icmp.set_msg_code(code);
+        icmp.data_mut()[..quote_len].copy_from_slice(&orig[..quote_len]);
+        fill_checksums(&mut packet);
+        self.stats.icmp_errors += 1;
+        self.output(port, next_hop, packet, now);
+    }
+
+    /// Retries or gives up the ARP resolutions, and expires the NAT
+    /// connections.
+    pub fn poll(&mut self) {
+        let now = monotonic_time_nanos();
+        if now - self.last_expire < EXPIRE_INTERVAL {
+            return;
+        }
+        self.last_expire = now;
+        self.nat.expire(now);
+
+        while let Some(pending) = self.pending.front() {
+            if now - pending.since < ARP_RESOLVE_TIMEOUT {
+                break;
+            }
+            let pending = self.pending.pop_front().unwrap();
+            self.arp_requests.remove(&pending.next_hop);
+            let code = ICMP_HOST_UNREACHABLE;
+            self.icmp_error(&pending.packet, Icmpv4Message::DstUnreachable, code, now);
+        }
+        let retries: Vec<_> = self
+            .arp_requests
+            .iter_mut()
+            .filter(|(_, sent)| now - **sent >= ARP_RETRY_INTERVAL)
+            .map(|(next_hop, sent)| {
+                *sent = now;
+                *next_hop
+            })
+            .collect();
+        for next_hop in retries {
+            let port = self.pending.iter().find(|p| p.next_hop == next_hop);
+            match port.map(|p| p.port) {
+                Some(port) => {
+                    let target_mac = EthernetAddress([0; 6]);
+                    self.send_arp(port, ArpOperation::Request, target_mac, next_hop);
+                }
+                None => {
+                    self.arp_requests.remove(&next_hop);
+                }
+            }
+        }
+    }
+}
+
+/// Starts forwarding IPv4 packets between `eth0` and the other NICs.
+///
+/// `addrs` are the addresses and the prefix lengths of `eth1`, `eth2` and so
+/// on. Packets are routed by the subnets of the NICs, and the default gateway
+/// of `eth0`.
+pub fn ip_forward_start(addrs: &[(Ipv4Addr, u8)]) -> AxResult {
+    let mut dev = ETH0.dev.lock();
+    let mut iface = ETH0.iface.lock();
+    if dev.router.is_some() {
+        return ax_err!(AlreadyExists, "IP forwarding already started");
+    }
+    if dev.bridge.is_some() {
+        return ax_err!(BadState, "NICs are bridged");
+    }
+    if addrs.is_empty() || addrs.len() > dev.ports.len() {
+        return ax_err!(InvalidInput, "invalid number of NIC addresses");
+    }
+    let Some(IpCidr::Ipv4(eth0_cidr)) = iface.ip_addrs().first().copied() else {
+        return ax_err!(BadState, "eth0 has no address");
+    };
+    let mut gateway = None;
+    iface.routes_mut().update(|routes| {
+        if let Some(r) = routes.iter().find(|r| r.cidr.prefix_len() == 0) {
+            let IpAddress::Ipv4(gw) = r.via_router;
+            gateway = Some(gw);
+        }
+    });
+
+    let mut ifaces = vec![RoutedInterface {
+        ether_addr: ETH0.ethernet_address(),
+        cidr: eth0_cidr,
+    }];
+    for (i, &(ip, prefix_len)) in addrs.iter().enumerate() {
+        if prefix_len > 32 || ip.is_unspecified() || ip.is_multicast() {
+            return ax_err!(InvalidInput, "invalid IP address");
+        }
+        let cidr = Ipv4Cidr::new(Ipv4Address(ip.octets()), prefix_len);
+        let overlaps = ifaces
+            .iter()
+            .any(|iface| iface.cidr.contains_subnet(&cidr) || cidr.contains_subnet(&iface.cidr));
+        if overlaps {
+            return ax_err!(InvalidInput, "overlapping subnets");
+        }
+        let ether_addr = EthernetAddress(dev.ports[i].borrow().mac_address().0);
+        ifaces.push(RoutedInterface { ether_addr, cidr });
+    }
+
+    info!("IP forwarding started:");
+    for (port, iface) in ifaces.iter().enumerate() {
+        info!("  eth{}:     {} {}", port, iface.cidr, iface.ether_addr);
+    }
+    if let Some(gw) = gateway {
+        info!("  gateway:  {}", gw);
+    }
+    dev.router = Some(Router::new(ifaces, gateway));
+    Ok(())
+}
+
+/// Stops forwarding IPv4 packets, dropping the NAT connections.
+pub fn ip_forward_stop() -> AxResult {
+    let mut dev = ETH0.dev.lock();
+    if dev.router.take().is_none() {
+        return ax_err!(BadState, "IP forwarding not started");
+    }
+    info!("IP forwarding stopped");
+    Ok(())
+}
+
+/// Returns the statistics of IP forwarding, or `None` if it is not started.
+pub fn ip_forward_stats() -> Option<ForwardStats> {
+    let dev = ETH0.dev.lock();
+    dev.router.as_ref().map(|router| ForwardStats {
+        nat_connections: router.nat.num_connections(),
+        ..router.stats
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ETH0_MAC: EthernetAddress = EthernetAddress([0x52, 0x54, 0, 0, 0, 0]);
+    const ETH1_MAC: EthernetAddress = EthernetAddress([0x52, 0x54, 0, 0, 0, 1]);
+    const GATEWAY_MAC: EthernetAddress = EthernetAddress([0x52, 0x54, 0, 0, 0, 0xfe]);
+    const HOST_MAC: EthernetAddress = EthernetAddress([0x52, 0x54, 0, 0, 0, 0x10]);
+    const ETH0_IP: Ipv4Address = Ipv4Address([10, 0, 2, 15]);
+    const ETH1_IP: Ipv4Address = Ipv4Address([192, 168, 1, 1]);
+    const GATEWAY: Ipv4Address = Ipv4Address([10, 0, 2, 2]);
+    const HOST: Ipv4Address = Ipv4Address([192, 168, 1, 10]);
+    const OUTSIDE: Ipv4Address = Ipv4Address([8, 8, 8, 8]);
+
+    fn router(gateway: Option<Ipv4Address>) -> Router {
+        let iface = |ether_addr, addr| RoutedInterface {
+            ether_addr,
+            cidr: Ipv4Cidr::new(addr, 24),
+        };
+        let ifaces = vec![iface(ETH0_MAC, ETH0_IP), iface(ETH1_MAC, ETH1_IP)];
+        Router::new(ifaces, gateway)
+    }
+
+    /// Builds an IPv4 packet with valid checksums.
+    fn ipv4(
+        src: Ipv4Address,
+        dst: Ipv4Address,
+        ttl: u8,
+        protocol: IpProtocol,
+        l4: &[u8],
+    ) -> Vec<u8> {
+        let total_len = IPV4_HEADER_LEN + l4.len();
+        let mut packet = vec![0; total_len];
+        let mut ip = Ipv4Packet::new_unchecked(&mut packet[..]);
+        ip.set_version(4);
+        ip.set_header_len(IPV4_HEADER_LEN as u8);
+        ip.set_total_len(total_len as u16);
+        ip.set_hop_limit(ttl);
+        ip.set_next_header(protocol);
+        ip.set_src_addr(src);
+        ip.set_dst_addr(dst);
+        ip.payload_mut().copy_from_slice(l4);
+        fill_checksums(&mut packet);
+        packet
+    }
+
+    fn udp(src: (Ipv4Address, u16), dst: (Ipv4Address, u16), ttl: u8) -> Vec<u8> {
+        let mut l4 = [0; 12];
+        l4[0..2].copy_from_slice(&src.1.to_be_bytes());
+        l4[2..4].copy_from_slice(&dst.1.to_be_bytes());
+        l4[4..8].copy_from_slice(&[0, 12, 0xff, 0xff]);
+        ipv4(src.0, dst.0, ttl, IpProtocol::Udp, &l4)
+    }
+
+    fn arp(
+        operation: ArpOperation,
+        mac: EthernetAddress,
+        ip: Ipv4Address,
+        target: Ipv4Address,
+    ) -> Vec<u8> {
+        let mut payload = vec![0; 28];
+        let mut arp = ArpPacket::new_unchecked(&mut payload[..]);
+        arp.set_hardware_type(ArpHardware::Ethernet);
+        arp.set_protocol_type(EthernetProtocol::Ipv4);
+        arp.set_hardware_len(6);
+        arp.set_protocol_len(4);
+        arp.set_operation(operation);
+        arp.set_source_hardware_addr(mac.as_bytes());
+        arp.set_source_protocol_addr(ip.as_bytes());
+        arp.set_target_protocol_addr(target.as_bytes());
+        payload
+    }
+
+    /// Takes the queued frames, checking their Ethernet source addresses.
+    fn take_tx(router: &mut Router) -> Vec<(usize, Vec<u8>)> {
+        let frames: Vec<_> = router
+            .tx_queue
+            .drain(..)
+            .map(|(p, f)| (p, f.to_vec()))
+            .collect();
+        for (port, frame) in &frames {
+            assert_eq!(frame[6..12], router.ifaces[*port].ether_addr.0);
+        }
+        frames
+    }
+
+    /// Returns the IPv4 packet in a frame sent to `mac`, after checking its
+    /// checksum.
+    fn ip_sent_to(frame: &[u8], mac: EthernetAddress) -> Ipv4Packet<&[u8]> {
+        assert_eq!(frame[..6], mac.0);
+        assert_eq!(be16(frame, 12), Some(ETHERTYPE_IPV4));
+        let ip = Ipv4Packet::new_checked(&frame[ETHERNET_HEADER_LEN..]).unwrap();
+        assert!(ip.verify_checksum());
+        ip
+    }
+
+    #[test]
+    fn test_forward_with_nat() {
+        let mut router = router(Some(GATEWAY));
+        router.nat.set_masquerade(true);
+        let packet = udp((HOST, 1234), (OUTSIDE, 53), 64);
+        assert!(!router.ip_input(1, &packet, 0));
+
+        // the gateway is resolved first
+        let frames = take_tx(&mut router);
+        assert_eq!(frames.len(), 1);
+        assert_eq!((frames[0].0, &frames[0].1[..6]), (0, &[0xff; 6][..]));
+        let request = ArpPacket::new_checked(&frames[0].1[ETHERNET_HEADER_LEN..]).unwrap();
+        assert_eq!(request.target_protocol_addr(), GATEWAY.as_bytes());
+        router.arp_input(
+            0,
+            &arp(ArpOperation::Reply, GATEWAY_MAC, GATEWAY, ETH0_IP),
+            1,
+        );
+
+        let frames = take_tx(&mut router);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].0, 0);
+        let ip = ip_sent_to(&frames[0].1, GATEWAY_MAC);
+        assert_eq!(
+            (ip.src_addr(), ip.dst_addr(), ip.hop_limit()),
+            (ETH0_IP, OUTSIDE, 63)
+        );
+        let nat_port = be16(ip.payload(), 0).unwrap();
+
+        // the reply is translated back, and sent to the inside host
+        router.arp_input(1, &arp(ArpOperation::Request, HOST_MAC, HOST, ETH1_IP), 1);
+        assert_eq!(take_tx(&mut router).len(), 1); // the ARP reply
+        let reply = udp((OUTSIDE, 53), (ETH0_IP, nat_port), 64);
+        assert!(!router.ip_input(0, &reply, 1));
+        let frames = take_tx(&mut router);
+        assert_eq!(frames[0].0, 1);
+        let ip = ip_sent_to(&frames[0].1, HOST_MAC);
+        assert_eq!((ip.src_addr(), ip.dst_addr()), (OUTSIDE, HOST));
+        assert_eq!(be16(ip.payload(), 2), Some(1234));
+        assert_eq!(router.stats.forwarded, 2);
+
+        // other packets to `eth0` go to the network stack
+        let packet = udp((OUTSIDE, 53), (ETH0_IP, 5000), 64);
+        assert!(router.ip_input(0, &packet, 1));
+    }
+
+    #[test]
+    fn test_icmp_errors() {
+        let mut router = router(None);
+        router.arp_input(1, &arp(ArpOperation::Request, HOST_MAC, HOST, ETH1_IP), 0);
+        take_tx(&mut router);
+
+        let expired = udp((HOST, 1234), (Ipv4Address([10, 0, 2, 20]), 53), 1);
+        let unreachable = udp((HOST, 1234), (OUTSIDE, 53), 64);
+        for (packet, msg_type) in [
+            (expired, Icmpv4Message::TimeExceeded),
+            (unreachable, Icmpv4Message::DstUnreachable),
+        ] {
+            assert!(!router.ip_input(1, &packet, 0));
+            let frames = take_tx(&mut router);
+            assert_eq!(frames[0].0, 1);
+            let ip = ip_sent_to(&frames[0].1, HOST_MAC);
+            assert_eq!((ip.src_addr(), ip.dst_addr()), (ETH1_IP, HOST));
+            let icmp = Icmpv4Packet::new_checked(ip.payload()).unwrap();
+            assert!(icmp.verify_checksum());
+            assert_eq!(icmp.msg_type(), msg_type);
+            // the IP header and 8 bytes of the payload are quoted
+            assert_eq!(icmp.data(), &packet[..IPV4_HEADER_LEN + 8]);
+        }
+        assert_eq!((router.stats.icmp_errors, router.stats.dropped), (2, 2));
+
+        // pings to the router are answered
+        let ping = ipv4(
+            HOST,
+            ETH1_IP,
+            64,
+            IpProtocol::Icmp,
+            &[8, 0, 0, 0, 0, 77, 0, 1],
+        );
+        assert!(!router.ip_input(1, &ping, 0));
+        let frames = take_tx(&mut router);
+        let ip = ip_sent_to(&frames[0].1, HOST_MAC);
+        assert_eq!((ip.src_addr(), ip.dst_addr()), (ETH1_IP, HOST));
+        let icmp = Icmpv4Packet::new_checked(ip.payload()).unwrap();
+        assert_eq!(icmp.msg_type(), Icmpv4Message::EchoReply);
+        assert!(icmp.verify_checksum());
+    }
+}
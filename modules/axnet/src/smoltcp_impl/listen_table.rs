@@ -1,5 +1,8 @@
-use alloc::{boxed::Box, collections::VecDeque};
-use core::ops::{Deref, DerefMut};
+use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
+use core::net::{IpAddr, Ipv4Addr, SocketAddr};
+use core::ops::DerefMut;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::Waker;
 
 use axerrno::{ax_err, AxError, AxResult};
 use axsync::Mutex;
@@ -7,20 +10,74 @@ use smoltcp::iface::{SocketHandle, SocketSet};
 use smoltcp::socket::tcp::{self, State};
 use smoltcp::wire::{IpAddress, IpEndpoint, IpListenEndpoint};
 
-use super::{SocketSetWrapper, LISTEN_QUEUE_SIZE, SOCKET_SET};
+use super::addr::into_core_ipaddr;
+use super::{SocketSetWrapper, LISTEN_QUEUE_SIZE, LISTEN_TABLE, SOCKET_SET};
 
 const PORT_NUM: usize = 65536;
 
+static SOMAXCONN: AtomicUsize = AtomicUsize::new(LISTEN_QUEUE_SIZE);
+
+/// Returns the maximum backlog of a listening TCP socket, like `somaxconn`
+/// on Linux.
+pub fn somaxconn() -> usize {
+    SOMAXCONN.load(Ordering::Relaxed)
+}
+
+/// Sets the maximum backlog of listening TCP sockets.
+///
+/// Sockets that are already listening keep their backlog.
+pub fn set_somaxconn(max_backlog: usize) -> AxResult {
+    if max_backlog == 0 {
+        return ax_err!(InvalidInput, "set_somaxconn() failed: zero backlog");
+    }
+    SOMAXCONN.store(max_backlog, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Statistics of a listening TCP socket.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ListenStats {
+    /// Maximum number of pending connections.
+    pub backlog: usize,
+    /// Number of connections still in the handshake.
+    pub syn_received: usize,
+    /// Number of established connections waiting to be accepted.
+    pub established: usize,
+    /// Number of incoming connections dropped because the queue was full.
+    pub overflows: usize,
+}
+
+/// Returns the local address and the statistics of every listening TCP
+/// socket, where the wildcard address is `0.0.0.0`.
+pub fn tcp_listeners() -> Vec<(SocketAddr, ListenStats)> {
+    LISTEN_TABLE
+        .all_stats()
+        .into_iter()
+        .map(|(endpoint, stats)| {
+            let addr = endpoint
+                .addr
+                .map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), into_core_ipaddr);
+            (SocketAddr::new(addr, endpoint.port), stats)
+        })
+        .collect()
+}
+
 struct ListenTableEntry {
     listen_endpoint: IpListenEndpoint,
+    backlog: usize,
+    overflows: usize,
     syn_queue: VecDeque<SocketHandle>,
+    waker: Option<Waker>,
 }
 
 impl ListenTableEntry {
-    pub fn new(listen_endpoint: IpListenEndpoint) -> Self {
+    pub fn new(listen_endpoint: IpListenEndpoint, backlog: usize) -> Self {
         Self {
             listen_endpoint,
-            syn_queue: VecDeque::with_capacity(LISTEN_QUEUE_SIZE),
+            backlog,
+            overflows: 0,
+            syn_queue: VecDeque::with_capacity(backlog.min(LISTEN_QUEUE_SIZE)),
+            waker: None,
         }
     }
 
@@ -31,6 +88,31 @@ impl ListenTableEntry {
             None => true,
         }
     }
+
+    /// Whether listening on `endpoint` would conflict with this entry.
+    ///
+    /// A wildcard address conflicts with every address on the same port.
+    #[inline]
+    fn conflicts_with(&self, endpoint: &IpListenEndpoint) -> bool {
+        match (self.listen_endpoint.addr, endpoint.addr) {
+            (Some(a), Some(b)) => a == b,
+            _ => true,
+        }
+    }
+
+    fn stats(&self) -> ListenStats {
+        let established = self
+            .syn_queue
+            .iter()
+            .filter(|&&handle| is_connected(handle))
+            .count();
+        ListenStats {
+            backlog: self.backlog,
+            syn_received: self.syn_queue.len() - established,
+            established,
+            overflows: self.overflows,
+        }
+    }
 }
 
 impl Drop for ListenTableEntry {
@@ -41,8 +123,12 @@ impl Drop for ListenTableEntry {
     }
 }
 
+/// All listening sockets on the same port, each bound to a different local
+/// address.
+type ListenPort = Vec<ListenTableEntry>;
+
 pub struct ListenTable {
-    tcp: Box<[Mutex<Option<Box<ListenTableEntry>>>]>,
+    tcp: Box<[Mutex<Option<Box<ListenPort>>>]>,
 }
 
 impl ListenTable {
@@ -57,37 +143,70 @@ impl ListenTable {
         Self { tcp }
     }
 
+    /// Whether no socket is listening on the port at any address.
     pub fn can_listen(&self, port: u16) -> bool {
         self.tcp[port as usize].lock().is_none()
     }
 
-    pub fn listen(&self, listen_endpoint: IpListenEndpoint) -> AxResult {
+    /// Whether listening on the given endpoint would succeed.
+    pub fn can_listen_on(&self, listen_endpoint: IpListenEndpoint) -> bool {
+        match self.tcp[listen_endpoint.port as usize].lock().as_ref() {
+            Some(entries) => !entries.iter().any(|e| e.conflicts_with(&listen_endpoint)),
+            None => true,
+        }
+    }
+
+    pub fn listen(&self, listen_endpoint: IpListenEndpoint, backlog: usize) -> AxResult {
         let port = listen_endpoint.port;
         assert_ne!(port, 0);
-        let mut entry = self.tcp[port as usize].lock();
-        if entry.is_none() {
-            *entry = Some(Box::new(ListenTableEntry::new(listen_endpoint)));
-            Ok(())
-        } else {
-            ax_err!(AddrInUse, "socket listen() failed")
+        let backlog = backlog.clamp(1, somaxconn());
+        let mut slot = self.tcp[port as usize].lock();
+        let entries = slot.get_or_insert_with(|| Box::new(Vec::new()));
+        if entries.iter().any(|e| e.conflicts_with(&listen_endpoint)) {
+            return ax_err!(AddrInUse, "socket listen() failed");
         }
+        entries.push(ListenTableEntry::new(listen_endpoint, backlog));
+        Ok(())
     }
 
-    pub fn unlisten(&self, port: u16) {
-        debug!("TCP socket unlisten on {}", port);
-        *self.tcp[port as usize].lock() = None;
+    pub fn unlisten(&self, listen_endpoint: IpListenEndpoint) {
+        debug!("TCP socket unlisten on {}", listen_endpoint);
+        let mut slot = self.tcp[listen_endpoint.port as usize].lock();
+        if let Some(entries) = slot.as_mut() {
+            entries.retain(|e| e.listen_endpoint != listen_endpoint);
+            if entries.is_empty() {
+                *slot = None;
+            }
+        }
     }
 
-    pub fn can_accept(&self, port: u16) -> AxResult<bool> {
-        if let Some(entry) = self.tcp[port as usize].lock().deref() {
-            Ok(entry.syn_queue.iter().any(|&handle| is_connected(handle)))
-        } else {
-            ax_err!(InvalidInput, "socket accept() failed: not listen")
+    fn with_entry<F, T>(&self, listen_endpoint: IpListenEndpoint, f: F) -> AxResult<T>
+    where
+        F: FnOnce(&mut ListenTableEntry) -> AxResult<T>,
+    {
+        let mut slot = self.tcp[listen_endpoint.port as usize].lock();
+        let entry = slot.deref_mut().as_mut().and_then(|entries| {
+            entries
+                .iter_mut()
+                .find(|e| e.listen_endpoint == listen_endpoint)
+        });
+        match entry {
+            Some(entry) => f(entry),
+            None => ax_err!(InvalidInput, "socket accept() failed: not listen"),
         }
     }
 
-    pub fn accept(&self, port: u16) -> AxResult<(SocketHandle, (IpEndpoint, IpEndpoint))> {
-        if let Some(entry) = self.tcp[port as usize].lock().deref_mut() {
+    pub fn can_accept(&self, listen_endpoint: IpListenEndpoint) -> AxResult<bool> {
+        self.with_entry(listen_endpoint, |entry| {
+            Ok(entry.syn_queue.iter().any(|&handle| is_connected(handle)))
+        })
+    }
+
+    pub fn accept(
+        &self,
+        listen_endpoint: IpListenEndpoint,
+    ) -> AxResult<(SocketHandle, (IpEndpoint, IpEndpoint))> {
+        self.with_entry(listen_endpoint, |entry| {
             let syn_queue = &mut entry.syn_queue;
             let (idx, addr_tuple) = syn_queue
                 .iter()
@@ -105,9 +224,36 @@ impl ListenTable {
             }
             let handle = syn_queue.swap_remove_front(idx).unwrap();
             Ok((handle, addr_tuple))
-        } else {
-            ax_err!(InvalidInput, "socket accept() failed: not listen")
+        })
+    }
+
+    /// Registers a waker to be woken when a queued connection becomes
+    /// established.
+    pub fn register_waker(&self, listen_endpoint: IpListenEndpoint, waker: &Waker) -> AxResult {
+        self.with_entry(listen_endpoint, |entry| {
+            for &handle in &entry.syn_queue {
+                SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+                    socket.register_recv_waker(waker)
+                });
+            }
+            entry.waker = Some(waker.clone());
+            Ok(())
+        })
+    }
+
+    pub fn stats(&self, listen_endpoint: IpListenEndpoint) -> AxResult<ListenStats> {
+        self.with_entry(listen_endpoint, |entry| Ok(entry.stats()))
+    }
+
+    /// Returns the statistics of all listening sockets, ordered by port.
+    pub fn all_stats(&self) -> Vec<(IpListenEndpoint, ListenStats)> {
+        let mut stats = Vec::new();
+        for slot in self.tcp.iter() {
+            if let Some(entries) = slot.lock().as_ref() {
+                stats.extend(entries.iter().map(|e| (e.listen_endpoint, e.stats())));
+            }
         }
+        stats
     }
 
     pub fn incoming_tcp_packet(
@@ -116,17 +262,29 @@ impl ListenTable {
         dst: IpEndpoint,
         sockets: &mut SocketSet<'_>,
     ) {
-        if let Some(entry) = self.tcp[dst.port as usize].lock().deref_mut() {
-            if !entry.can_accept(dst.addr) {
-                // not listening on this address
-                return;
-            }
-            if entry.syn_queue.len() >= LISTEN_QUEUE_SIZE {
+        if let Some(entries) = self.tcp[dst.port as usize].lock().deref_mut() {
+            // prefer the listener bound to the exact address over the wildcard one
+            let entry = match entries
+                .iter()
+                .position(|e| e.listen_endpoint.addr == Some(dst.addr))
+                .or_else(|| entries.iter().position(|e| e.can_accept(dst.addr)))
+            {
+                Some(idx) => &mut entries[idx],
+                None => return, // not listening on this address
+            };
+            if entry.syn_queue.len() >= entry.backlog {
                 // SYN queue is full, drop the packet
-                warn!("SYN queue overflow!");
+                entry.overflows += 1;
+                warn!(
+                    "SYN queue overflow on {}: backlog = {}, dropped = {}",
+                    entry.listen_endpoint, entry.backlog, entry.overflows
+                );
                 return;
             }
             let mut socket = SocketSetWrapper::new_tcp_socket();
+            if let Some(waker) = &entry.waker {
+                socket.register_recv_waker(waker);
+            }
             if socket.listen(entry.listen_endpoint).is_ok() {
                 let handle = sockets.add(socket);
                 debug!(
@@ -153,3 +311,108 @@ fn get_addr_tuple(handle: SocketHandle) -> (IpEndpoint, IpEndpoint) {
         )
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use smoltcp::wire::Ipv4Address;
+
+    use super::*;
+
+    fn init_socket_set() {
+        static INIT: spin::Once = spin::Once::new();
+        INIT.call_once(|| SOCKET_SET.init_once(SocketSetWrapper::new()));
+    }
+
+    fn endpoint(addr: Option<[u8; 4]>, port: u16) -> IpListenEndpoint {
+        IpListenEndpoint {
+            addr: addr.map(|a| IpAddress::Ipv4(Ipv4Address(a))),
+            port,
+        }
+    }
+
+    /// Simulates the first SYN from `10.0.0.100:src_port` to `dst`.
+    fn syn(table: &ListenTable, src_port: u16, dst: [u8; 4], dst_port: u16) {
+        let src = IpEndpoint::new(IpAddress::v4(10, 0, 0, 100), src_port);
+        let dst = IpEndpoint::new(IpAddress::Ipv4(Ipv4Address(dst)), dst_port);
+        table.incoming_tcp_packet(src, dst, &mut SOCKET_SET.0.lock());
+    }
+
+    #[test]
+    fn test_listen_per_address() {
+        init_socket_set();
+        let table = ListenTable::new();
+        let first = endpoint(Some([10, 0, 0, 1]), 80);
+        let second = endpoint(Some([10, 0, 0, 2]), 80);
+        table.listen(first, 8).unwrap();
+        table.listen(second, 8).unwrap();
+        assert!(!table.can_listen(80));
+        assert!(table.can_listen_on(endpoint(Some([10, 0, 0, 3]), 80)));
+
+        // each listener only gets the connections to its own address
+        syn(&table, 1000, [10, 0, 0, 2], 80);
+        syn(&table, 1001, [10, 0, 0, 2], 80);
+        syn(&table, 1002, [10, 0, 0, 3], 80);
+        assert_eq!(table.stats(first).unwrap().syn_received, 0);
+        assert_eq!(table.stats(second).unwrap().syn_received, 2);
+        assert_eq!(table.stats(second).unwrap().established, 0);
+        assert_eq!(
+            table.accept(second).err(),
+            Some(AxError::WouldBlock) // still in the handshake
+        );
+
+        let all = table.all_stats();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[1].0, second);
+        assert_eq!(all[1].1.syn_received, 2);
+
+        table.unlisten(first);
+        assert_eq!(table.stats(first).err(), Some(AxError::InvalidInput));
+        table.unlisten(second);
+        assert!(table.can_listen(80));
+        assert!(table.all_stats().is_empty());
+    }
+
+    #[test]
+    fn test_listen_addr_in_use() {
+        init_socket_set();
+        let table = ListenTable::new();
+        let wildcard = endpoint(None, 8080);
+        let specific = endpoint(Some([10, 0, 0, 1]), 8080);
+
+        table.listen(wildcard, 8).unwrap();
+        assert!(!table.can_listen_on(specific));
+        assert_eq!(table.listen(specific, 8).err(), Some(AxError::AddrInUse));
+        assert_eq!(table.listen(wildcard, 8).err(), Some(AxError::AddrInUse));
+        let other_port = endpoint(Some([10, 0, 0, 1]), 8081);
+        table.listen(other_port, 8).unwrap();
+        table.unlisten(wildcard);
+
+        table.listen(specific, 8).unwrap();
+        assert!(!table.can_listen_on(wildcard));
+        assert_eq!(table.listen(wildcard, 8).err(), Some(AxError::AddrInUse));
+        assert_eq!(table.listen(specific, 8).err(), Some(AxError::AddrInUse));
+    }
+
+    #[test]
+    fn test_backlog_overflow() {
+        init_socket_set();
+        let table = ListenTable::new();
+        let wildcard = endpoint(None, 443);
+        table.listen(wildcard, 2).unwrap();
+        for port in 2000..2005 {
+            syn(&table, port, [192, 168, 1, 1], 443);
+        }
+        let stats = table.stats(wildcard).unwrap();
+        assert_eq!(stats.backlog, 2);
+        assert_eq!(stats.syn_received, 2);
+        assert_eq!(stats.overflows, 3);
+
+        // the backlog is limited by `somaxconn`
+        let large = endpoint(None, 444);
+        table.listen(large, usize::MAX).unwrap();
+        assert_eq!(table.stats(large).unwrap().backlog, somaxconn());
+        let zero = endpoint(None, 445);
+        table.listen(zero, 0).unwrap();
+        assert_eq!(table.stats(zero).unwrap().backlog, 1);
+    }
+}
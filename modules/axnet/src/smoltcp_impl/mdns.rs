@@ -0,0 +1,617 @@
+//! A multicast DNS responder ([RFC 6762]) with DNS-based service discovery
+//! ([RFC 6763]).
+//!
+//! The responder owns the name `<hostname>.local` and answers `A` queries for
+//! it with the IPv4 address of the interface. Each registered service is
+//! advertised with the usual DNS-SD records:
+//!
+//! ```text
+//! _services._dns-sd._udp.local  PTR  _http._tcp.local
+//! _http._tcp.local              PTR  <instance>._http._tcp.local
+//! <instance>._http._tcp.local   SRV  0 0 <port> <hostname>.local
+//! <instance>._http._tcp.local   TXT  <key=value> ...
+//! ```
+//!
+//! Queries are answered while the network stack is polled, by multicast, or
+//! by unicast to the querier if it sets the unicast-response bit. Probing for
+//! name conflicts and known-answer suppression are not implemented.
+//!
+//! [RFC 6762]: https://www.rfc-editor.org/rfc/rfc6762
+//! [RFC 6763]: https://www.rfc-editor.org/rfc/rfc6763
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use axerrno::{ax_err, ax_err_type, AxResult};
+use axhal::time::{wall_time_nanos, NANOS_PER_SEC};
+use axsync::Mutex;
+use smoltcp::iface::{Interface, SocketHandle, SocketSet};
+use smoltcp::socket::udp;
+use smoltcp::wire::{IpAddress, IpEndpoint, IpListenEndpoint, Ipv4Address};
+
+use super::dns::{be16, read_name, write_name};
+use super::{SocketSetWrapper, ETH0, SOCKET_SET};
+
+const MDNS_PORT: u16 = 5353;
+const MDNS_GROUP: Ipv4Address = Ipv4Address([224, 0, 0, 251]);
+const MDNS_ENDPOINT: IpEndpoint = IpEndpoint::new(IpAddress::Ipv4(MDNS_GROUP), MDNS_PORT);
+
+/// The name enumerating all service types (RFC 6763, section 9).
+const SERVICES_NAME: &str = "_services._dns-sd._udp.local";
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+const CLASS_ANY: u16 = 255;
+/// Set in the class of records owned only by this host.
+const CLASS_CACHE_FLUSH: u16 = 0x8000;
+/// Set in the class of questions preferring a unicast response (RFC 6762,
+/// section 5.4).
+const CLASS_UNICAST_RESPONSE: u16 = 0x8000;
+
+const FLAG_RESPONSE: u16 = 0x8000;
+const FLAG_AUTHORITATIVE: u16 = 0x0400;
+const OPCODE_MASK: u16 = 0x7800;
+const RESPONSE_FLAGS: u16 = FLAG_RESPONSE | FLAG_AUTHORITATIVE;
+
+/// TTL of records containing the host name (RFC 6762, section 10).
+const HOST_TTL: u32 = 120;
+/// TTL of the other records.
+const OTHER_TTL: u32 = 4500;
+/// TTL limit of answers to legacy unicast queries (RFC 6762, section 6.7).
+const LEGACY_TTL: u32 = 10;
+
+/// Number of unsolicited announcements, sent one second apart.
+const ANNOUNCE_COUNT: u8 = 2;
+const MAX_LABEL_LEN: usize = 63;
+
+static MDNS_ON: AtomicBool = AtomicBool::new(false);
+static RESPONDER: Mutex<Option<Responder>> = Mutex::new(None);
+
+/// A service advertised by the mDNS responder.
+#[derive(Debug, Clone)]
+pub struct MdnsService {
+    /// The instance name, e.g. `My Web Server`. It must not contain dots.
+    pub instance: String,
+    /// The service type, e.g. `_http._tcp`.
+    pub service: String,
+    /// The port the service listens on.
+    pub port: u16,
+    /// The `key=value` strings of the TXT record.
+    pub txt: Vec<String>,
+}
+
+struct Record {
+    name: String,
+    rtype: u16,
+    /// Whether the record is owned only by this host, it is announced with
+    /// the cache-flush bit.
+    unique: bool,
+    ttl: u32,
+    rdata: Vec<u8>,
+    /// The name the record points to (PTR and SRV), whose records are sent
+    /// as additional records.
+    target: Option<String>,
+}
+
+struct Responder {
+    handle: SocketHandle,
+    /// The fully qualified host name, e.g. `device-42.local`.
+    hostname: String,
+    services: Vec<MdnsService>,
+    announcements: u8,
+    next_announce: u64,
+}
+
+impl Record {
+    fn matches(&self, name: &str, qtype: u16) -> bool {
+        (qtype == self.rtype || qtype == TYPE_ANY) && self.name.eq_ignore_ascii_case(name)
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>, ttl: u32, cache_flush: bool) {
+        write_name(out, &self.name);
+        out.extend_from_slice(&self.rtype.to_be_bytes());
+        let class = if cache_flush && self.unique {
+            CLASS_IN | CLASS_CACHE_FLUSH
+        } else {
+            CLASS_IN
+        };
+        out.extend_from_slice(&class.to_be_bytes());
+        out.extend_from_slice(&ttl.to_be_bytes());
+        out.extend_from_slice(&(self.rdata.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.rdata);
+    }
+}
+
+impl Responder {
+    /// Returns all records of the host and its services.
+    fn records(&self, ip: Ipv4Address) -> Vec<Record> {
+        let mut records = vec![Record {
+            name: self.hostname.clone(),
+            rtype: TYPE_A,
+            unique: true,
+            ttl: HOST_TTL,
+            rdata: ip.0.to_vec(),
+            target: None,
+        }];
+        let ptr = |name: &str, target: String| {
+            let mut rdata = Vec::new();
+            write_name(&mut rdata, &target);
+            Record {
+                name: name.to_string(),
+                rtype: TYPE_PTR,
+                unique: false,
+                ttl: OTHER_TTL,
+                rdata,
+                target: Some(target),
+            }
+        };
+        for (i, svc) in self.services.iter().enumerate() {
+            let svc_type = format!("{}.local", svc.service);
+            let instance = format!("{}.{}", svc.instance, svc_type);
+            if !self.services[..i].iter().any(|s| s.service == svc.service) {
+                records.push(ptr(SERVICES_NAME, svc_type.clone()));
+            }
+            records.push(ptr(&svc_type, instance.clone()));
+
+            let mut srv = Vec::new();
+            srv.extend_from_slice(&0u16.to_be_bytes()); // priority
+            srv.extend_from_slice(&0u16.to_be_bytes()); // weight
+            srv.extend_from_slice(&svc.port.to_be_bytes());
+            write_name(&mut srv, &self.hostname);
+            records.push(Record {
+                name: instance.clone(),
+                rtype: TYPE_SRV,
+                unique: true,
+                ttl: HOST_TTL,
+                rdata: srv,
+                target: Some(self.hostname.clone()),
+            });
+
+            let mut txt = Vec::new();
+            for s in &svc.txt {
+                let s = &s.as_bytes()[..s.len().min(255)];
+                txt.push(s.len() as u8);
+                txt.extend_from_slice(s);
+            }
+            if txt.is_empty() {
+                txt.push(0); // a TXT record contains at least one string
+            }
+            records.push(Record {
+                name: instance,
+                rtype: TYPE_TXT,
+                unique: true,
+                ttl: OTHER_TTL,
+                rdata: txt,
+                target: None,
+            });
+        }
+        records
+    }
+
+    /// Builds the response to a query from `src`, and returns it with its
+    /// destination.
+    fn answer(
+        &self,
+        query: &[u8],
+        src: IpEndpoint,
+        ip: Ipv4Address,
+    ) -> Option<(Vec<u8>, IpEndpoint)> {
+        let id = be16(query, 0)?;
+        let flags = be16(query, 2)?;
+        if flags & (FLAG_RESPONSE | OPCODE_MASK) != 0 {
+            return None;
+        }
+        let qdcount = be16(query, 4)?;
+
+        let records = self.records(ip);
+        let mut answers: Vec<&Record> = Vec::new();
+        let mut unicast = false;
+        let mut pos = 12;
+        for _ in 0..qdcount {
+            let (name, next) = read_name(query, pos)?;
+            let qtype = be16(query, next)?;
+            let qclass = be16(query, next + 2)?;
+            pos = next + 4;
+            if !matches!(qclass & !CLASS_UNICAST_RESPONSE, CLASS_IN | CLASS_ANY) {
+                continue;
+            }
+            for rec in records.iter().filter(|r| r.matches(&name, qtype)) {
+                unicast |= qclass & CLASS_UNICAST_RESPONSE != 0;
+                if !answers.iter().any(|a| core::ptr::eq(*a, rec)) {
+                    answers.push(rec);
+                }
+            }
+        }
+        if answers.is_empty() {
+            return None;
+        }
+
+        // Send the records the answers point to as well, so that resolving a
+        // service takes only one query.
+        let mut additionals: Vec<&Record> = Vec::new();
+        let mut i = 0;
+        while i < answers.len() + additionals.len() {
+            let rec = if i < answers.len() {
+                answers[i]
+            } else {
+                additionals[i - answers.len()]
+            };
+            if let Some(target) = &rec.target {
+                for r in records
+                    .iter()
+                    .filter(|r| r.rtype != TYPE_PTR && r.name.eq_ignore_ascii_case(target))
+                {
+                    if !answers
+                        .iter()
+                        .chain(&additionals)
+                        .any(|a| core::ptr::eq(*a, r))
+                    {
+                        additionals.push(r);
+                    }
+                }
+            }
+            i += 1;
+        }
+
+        // A query not from port 5353 comes from a simple resolver, which
+        // expects a conventional unicast DNS response.
+        let legacy = src.port != MDNS_PORT;
+        let mut out = Vec::new();
+        if legacy {
+            write_header(&mut out, id, RESPONSE_FLAGS, qdcount);
+            out.extend_from_slice(&query[12..pos]);
+        } else {
+            write_header(&mut out, 0, RESPONSE_FLAGS, 0);
+        }
+        set_counts(&mut out, answers.len(), additionals.len());
+        for rec in answers.iter().chain(&additionals) {
+            if legacy {
+                rec.write_to(&mut out, rec.ttl.min(LEGACY_TTL), false);
+            } else {
+                rec.write_to(&mut out, rec.ttl, true);
+            }
+        }
+        // Records are not remembered to have been multicast recently, so a
+        // unicast response is always sent when asked for.
+        let dst = if legacy || unicast {
+            src
+        } else {
+            MDNS_ENDPOINT
+        };
+        Some((out, dst))
+    }
+
+    /// Builds an unsolicited response with all records. Records with TTL 0
+    /// are sent if `goodbye` is set, removing them from caches.
+    fn announcement(&self, ip: Ipv4Address, goodbye: bool) -> Vec<u8> {
+        let records = self.records(ip);
+        let mut out = Vec::new();
+        write_header(&mut out, 0, RESPONSE_FLAGS, 0);
+        set_counts(&mut out, records.len(), 0);
+        for rec in &records {
+            rec.write_to(&mut out, if goodbye { 0 } else { rec.ttl }, true);
+        }
+        out
+    }
+}
+
+fn write_header(out: &mut Vec<u8>, id: u16, flags: u16, qdcount: u16) {
+    out.extend_from_slice(&id.to_be_bytes());
+    out.extend_from_slice(&flags.to_be_bytes());
+    out.extend_from_slice(&qdcount.to_be_bytes());
+    out.extend_from_slice(&[0; 6]);
+}
+
+fn set_counts(out: &mut [u8], ancount: usize, arcount: usize) {
+    out[6..8].copy_from_slice(&(ancount as u16).to_be_bytes());
+    out[10..12].copy_from_slice(&(arcount as u16).to_be_bytes());
+}
+
+fn check_label(label: &str) -> AxResult {
+    if label.is_empty() || label.len() > MAX_LABEL_LEN || label.contains('.') {
+        return ax_err!(InvalidInput, "invalid mDNS label");
+    }
+    Ok(())
+}
+
+/// Answers the queued queries and sends the pending announcements, returns
+/// whether any packet was queued for transmission.
+///
+/// Called after each poll of the interface.
+pub(super) fn process(iface: &Interface, sockets: &mut SocketSet<'_>) -> bool {
+    if !MDNS_ON.load(Ordering::Acquire) {
+        return false;
+    }
+    let mut responder = RESPONDER.lock();
+    let Some(responder) = responder.as_mut() else {
+        return false;
+    };
+    let Some(ip) = iface.ipv4_addr() else {
+        return false;
+    };
+    let socket = sockets.get_mut::<udp::Socket>(responder.handle);
+
+    let mut queued = false;
+    while let Ok((query, meta)) = socket.recv() {
+        let query = query.to_vec();
+        if let Some((resp, dst)) = responder.answer(&query, meta.endpoint, ip) {
+            trace!(
+                "mDNS: answering {} with {} bytes",
+                meta.endpoint,
+                resp.len()
+            );
+            queued |= socket.send_slice(&resp, dst).is_ok();
+        }
+    }
+
+    let now = wall_time_nanos();
+    if responder.announcements > 0 && now >= responder.next_announce {
+        let packet = responder.announcement(ip, false);
+        if socket.send_slice(&packet, MDNS_ENDPOINT).is_ok() {
+            responder.announcements -= 1;
+            responder.next_announce = now + NANOS_PER_SEC;
+            queued = true;
+        }
+    }
+    queued
+}
+
+/// Starts the mDNS responder for `<hostname>.local`.
+///
+/// The `.local` suffix of `hostname` is optional. The responder joins the
+/// mDNS multicast group, announces its records, and answers queries
+/// whenever the network stack is polled.
+pub fn mdns_start(hostname: &str) -> AxResult {
+    let hostname = hostname.trim_end_matches('.');
+    let hostname = hostname.strip_suffix(".local").unwrap_or(hostname);
+    check_label(hostname)?;
+
+    ETH0.join_multicast_group(MDNS_GROUP)?;
+    let mut socket = SocketSetWrapper::new_udp_socket();
+    socket
+        .bind(IpListenEndpoint {
+            addr: None,
+            port: MDNS_PORT,
+        })
+        .unwrap();
+    let handle = SOCKET_SET.add(socket);
+
+    let mut responder = RESPONDER.lock();
+    if responder.is_some() {
+        drop(responder);
+        SOCKET_SET.remove(handle);
+        ETH0.leave_multicast_group(MDNS_GROUP)?;
+        return ax_err!(AlreadyExists, "mDNS responder already started");
+    }
+    *responder = Some(Responder {
+        handle,
+        hostname: format!("{}.local", hostname),
+        services: Vec::new(),
+        announcements: ANNOUNCE_COUNT,
+        next_announce: 0,
+    });
+    drop(responder);
+    MDNS_ON.store(true, Ordering::Release);
+    info!("mDNS: responding as {}.local", hostname);
+
+    SOCKET_SET.poll_interfaces();
+    Ok(())
+}
+
+/// Advertises a service with DNS-SD.
+///
+/// The records of all services are announced again.
+pub fn mdns_add_service(service: MdnsService) -> AxResult {
+    check_label(&service.instance)?;
+    let mut labels = service.service.split('.');
+    let valid = matches!(
+        (labels.next(), labels.next(), labels.next()),
+        (Some(name), Some("_tcp" | "_udp"), None) if name.starts_with('_') && name.len() > 1
+    );
+    if !valid {
+        return ax_err!(InvalidInput, "invalid DNS-SD service type");
+    }
+
+    let mut responder = RESPONDER.lock();
+    let responder = responder
+        .as_mut()
+        .ok_or_else(|| ax_err_type!(BadState, "mDNS responder not started"))?;
+    let exists = responder.services.iter().any(|s| {
+        s.service == service.service && s.instance.eq_ignore_ascii_case(&service.instance)
+    });
+    if exists {
+        return ax_err!(AlreadyExists, "mDNS service already exists");
+    }
+    debug!(
+        "mDNS: add service {}.{} on port {}",
+        service.instance, service.service, service.port
+    );
+    responder.services.push(service);
+    responder.announcements = ANNOUNCE_COUNT;
+    responder.next_announce = 0;
+    Ok(())
+}
+
+/// Stops the mDNS responder.
+///
+/// A goodbye packet is sent so that other hosts remove the records from
+/// their caches.
+pub fn mdns_stop() -> AxResult {
+    MDNS_ON.store(false, Ordering::Release);
+    let responder = RESPONDER
+        .lock()
+        .take()
+        .ok_or_else(|| ax_err_type!(BadState, "mDNS responder not started"))?;
+    if let Some(ip) = ETH0.ipv4_addr() {
+        let packet = responder.announcement(ip, true);
+        SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(responder.handle, |socket| {
+            socket.send_slice(&packet, MDNS_ENDPOINT).ok();
+        });
+        SOCKET_SET.poll_interfaces();
+    }
+    SOCKET_SET.remove(responder.handle);
+    ETH0.leave_multicast_group(MDNS_GROUP)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IP: Ipv4Address = Ipv4Address([10, 0, 2, 15]);
+    const QUERIER: IpEndpoint = IpEndpoint::new(IpAddress::v4(10, 0, 2, 2), MDNS_PORT);
+
+    fn responder() -> Responder {
+        Responder {
+            handle: SocketHandle::default(),
+            hostname: "arceos.local".into(),
+            services: vec![MdnsService {
+                instance: "Web".into(),
+                service: "_http._tcp".into(),
+                port: 80,
+                txt: vec!["path=/".into()],
+            }],
+            announcements: 0,
+            next_announce: 0,
+        }
+    }
+
+    fn query(id: u16, questions: &[(&str, u16, u16)]) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_header(&mut out, id, 0, questions.len() as u16);
+        for (name, qtype, qclass) in questions {
+            write_name(&mut out, name);
+            out.extend_from_slice(&qtype.to_be_bytes());
+            out.extend_from_slice(&qclass.to_be_bytes());
+        }
+        out
+    }
+
+    /// Returns the (name, type, class, TTL) of the answer and additional
+    /// records of a response.
+    fn parse_records(resp: &[u8]) -> (Vec<(String, u16, u16, u32)>, usize) {
+        let qdcount = be16(resp, 4).unwrap();
+        let count = be16(resp, 6).unwrap() + be16(resp, 10).unwrap();
+        let mut pos = 12;
+        for _ in 0..qdcount {
+            pos = read_name(resp, pos).unwrap().1 + 4;
+        }
+        let mut records = Vec::new();
+        for _ in 0..count {
+            let (name, next) = read_name(resp, pos).unwrap();
+            let ttl = u32::from_be_bytes(resp[next + 4..next + 8].try_into().unwrap());
+            let rdlen = be16(resp, next + 8).unwrap() as usize;
+            let (rtype, class) = (be16(resp, next).unwrap(), be16(resp, next + 2).unwrap());
+            records.push((name, rtype, class, ttl));
+            pos = next + 10 + rdlen;
+        }
+        assert_eq!(pos, resp.len());
+        (records, be16(resp, 6).unwrap() as usize)
+    }
+
+    #[test]
+    fn test_answer_queries() {
+        let responder = responder();
+        let (resp, dst) = responder
+            .answer(
+                &query(7, &[("ARCEOS.local", TYPE_A, CLASS_IN)]),
+                QUERIER,
+                IP,
+            )
+            .unwrap();
+        assert_eq!(dst, MDNS_ENDPOINT);
+        assert_eq!(be16(&resp, 0), Some(0));
+        assert_eq!(be16(&resp, 2), Some(RESPONSE_FLAGS));
+        let (records, ancount) = parse_records(&resp);
+        assert_eq!(ancount, 1);
+        let class = CLASS_IN | CLASS_CACHE_FLUSH;
+        assert_eq!(records, [("arceos.local".into(), TYPE_A, class, HOST_TTL)]);
+        assert!(resp.ends_with(&IP.0));
+
+        // a PTR answer brings the SRV, TXT and A records along
+        let (resp, _) = responder
+            .answer(
+                &query(0, &[("_http._tcp.local", TYPE_PTR, CLASS_IN)]),
+                QUERIER,
+                IP,
+            )
+            .unwrap();
+        let (records, ancount) = parse_records(&resp);
+        assert_eq!(ancount, 1);
+        let types: Vec<u16> = records.iter().map(|r| r.1).collect();
+        assert_eq!(types, [TYPE_PTR, TYPE_SRV, TYPE_TXT, TYPE_A]);
+        assert_eq!(records[0].0, "_http._tcp.local");
+        assert_eq!(records[0].2, CLASS_IN); // shared, without cache-flush
+        assert_eq!(records[1].0, "Web._http._tcp.local");
+
+        let (resp, _) = responder
+            .answer(
+                &query(0, &[(SERVICES_NAME, TYPE_ANY, CLASS_ANY)]),
+                QUERIER,
+                IP,
+            )
+            .unwrap();
+        assert_eq!(parse_records(&resp).0[0].0, SERVICES_NAME);
+    }
+
+    #[test]
+    fn test_unicast_response() {
+        let responder = responder();
+        let qu = CLASS_IN | CLASS_UNICAST_RESPONSE;
+        let (resp, dst) = responder
+            .answer(&query(0, &[("arceos.local", TYPE_A, qu)]), QUERIER, IP)
+            .unwrap();
+        assert_eq!(dst, QUERIER);
+        assert_eq!(parse_records(&resp).0[0].3, HOST_TTL);
+
+        // the bit counts only on questions that are answered
+        let q = query(
+            0,
+            &[
+                ("arceos.local", TYPE_A, CLASS_IN),
+                ("other.local", TYPE_A, qu),
+            ],
+        );
+        assert_eq!(responder.answer(&q, QUERIER, IP).unwrap().1, MDNS_ENDPOINT);
+
+        // legacy unicast: the ID and the question are echoed, TTLs are capped
+        let legacy = IpEndpoint::new(IpAddress::v4(10, 0, 2, 2), 40000);
+        let q = query(0x1234, &[("arceos.local", TYPE_A, CLASS_IN)]);
+        let (resp, dst) = responder.answer(&q, legacy, IP).unwrap();
+        assert_eq!(dst, legacy);
+        assert_eq!(be16(&resp, 0), Some(0x1234));
+        assert_eq!(be16(&resp, 4), Some(1));
+        assert_eq!(resp[12..q.len()], q[12..]);
+        let class = CLASS_IN;
+        assert_eq!(
+            parse_records(&resp).0,
+            [("arceos.local".into(), TYPE_A, class, LEGACY_TTL)]
+        );
+    }
+
+    #[test]
+    fn test_no_answer() {
+        let responder = responder();
+        for q in [
+            query(0, &[("other.local", TYPE_A, CLASS_IN)]),
+            query(0, &[("arceos.local", TYPE_SRV, CLASS_IN)]),
+            query(0, &[("_ftp._tcp.local", TYPE_PTR, CLASS_IN)]),
+            query(0, &[("arceos.local", TYPE_A, 3)]), // CHAOS class
+            query(0, &[]),
+        ] {
+            assert!(responder.answer(&q, QUERIER, IP).is_none());
+        }
+
+        // responses and truncated queries are ignored
+        let mut q = query(0, &[("arceos.local", TYPE_A, CLASS_IN)]);
+        q[2] |= (FLAG_RESPONSE >> 8) as u8;
+        assert!(responder.answer(&q, QUERIER, IP).is_none());
+        let q = query(0, &[("arceos.local", TYPE_A, CLASS_IN)]);
+        assert!(responder.answer(&q[..q.len() - 1], QUERIER, IP).is_none());
+    }
+}
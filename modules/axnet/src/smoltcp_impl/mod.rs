@@ -1,30 +1,59 @@
 mod addr;
 mod bench;
+mod bridge;
+mod buf_pool;
+mod capture;
 mod dns;
+mod forward;
 mod listen_table;
+mod mdns;
+mod nat;
 mod tcp;
 mod udp;
+mod vlan;
 
 use alloc::vec;
+use alloc::vec::Vec;
 use core::cell::RefCell;
 use core::ops::DerefMut;
 
 use axdriver::prelude::*;
 use axdriver_net::{DevError, NetBufPtr};
+use axerrno::{ax_err, ax_err_type, AxResult};
 use axhal::time::{wall_time_nanos, NANOS_PER_MICROS};
 use axsync::Mutex;
 use lazyinit::LazyInit;
-use smoltcp::iface::{Config, Interface, SocketHandle, SocketSet};
+use smoltcp::iface::{Config, Interface, MulticastError, SocketHandle, SocketSet};
 use smoltcp::phy::{Device, DeviceCapabilities, Medium, RxToken, TxToken};
 use smoltcp::socket::{self, AnySocket};
 use smoltcp::time::Instant;
-use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr};
+use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr, Ipv4Address};
 
+use self::bridge::{Bridge, MAX_PORTS};
+use self::buf_pool::FrameBuf;
+use self::capture::capture_frame;
+use self::forward::Router;
 use self::listen_table::ListenTable;
-
-pub use self::dns::dns_query;
+use self::vlan::{TxRoute, VlanInterface, VLAN_TAG_LEN};
+
+pub use self::capture::{
+    capture_stats, dump_capture, is_capturing, start_capture, stop_capture, CaptureDirection,
+    CaptureFilter, CaptureStats, DEFAULT_CAPTURE_CAPACITY,
+};
+
+pub use self::bench::bench_tcp_receive;
+pub use self::bridge::{bridge_start, bridge_stats, bridge_stop, BridgeStats};
+pub use self::dns::{
+    dns_flush_cache, dns_load_hosts, dns_load_resolv_conf, dns_query, dns_servers, dns_set_servers,
+};
+pub use self::forward::{ip_forward_start, ip_forward_stats, ip_forward_stop, ForwardStats};
+pub use self::listen_table::{set_somaxconn, somaxconn, tcp_listeners, ListenStats};
+pub use self::mdns::{mdns_add_service, mdns_start, mdns_stop, MdnsService};
+pub use self::nat::{nat_add_port_forward, nat_connections, nat_remove_port_forward};
+pub use self::nat::{nat_set_masquerade, NatConnection, NatProtocol, PortForward};
 pub use self::tcp::TcpSocket;
 pub use self::udp::UdpSocket;
+pub use self::vlan::{add_vlan_interface, interfaces, remove_vlan_interface, InterfaceConfig};
 
 macro_rules! env_or_default {
     ($key:literal) => {
@@ -37,7 +66,8 @@ macro_rules! env_or_default {
 
 const IP: &str = env_or_default!("AX_IP");
 const GATEWAY: &str = env_or_default!("AX_GW");
-const DNS_SEVER: &str = "8.8.8.8";
+const MDNS_HOST: &str = env_or_default!("AX_MDNS_HOST");
+const VLANS: &str = env_or_default!("AX_VLANS");
 const IP_PREFIX: u8 = 24;
 
 const STANDARD_MTU: usize = 1500;
@@ -48,6 +78,7 @@ const TCP_RX_BUF_LEN: usize = 64 * 1024;
 const TCP_TX_BUF_LEN: usize = 64 * 1024;
 const UDP_RX_BUF_LEN: usize = 64 * 1024;
 const UDP_TX_BUF_LEN: usize = 64 * 1024;
+/// Default maximum backlog of a listening TCP socket, see [`set_somaxconn`].
 const LISTEN_QUEUE_SIZE: usize = 512;
 
 static LISTEN_TABLE: LazyInit<ListenTable> = LazyInit::new();
@@ -58,6 +89,16 @@ struct SocketSetWrapper<'a>(Mutex<SocketSet<'a>>);
 
 struct DeviceWrapper {
     inner: RefCell<AxNetDevice>, // use `RefCell` is enough since it's wrapped in `Mutex` in `InterfaceWrapper`.
+    /// The other NICs, which are only used as the ports of the bridge.
+    ports: Vec<RefCell<AxNetDevice>>,
+    /// The bridge between all NICs, if started.
+    bridge: Option<Bridge>,
+    /// The router forwarding IPv4 packets between all NICs, if started.
+    router: Option<Router>,
+    /// The port to receive from first, so that no port starves the others.
+    next_port: usize,
+    /// VLAN sub-interfaces on the NIC.
+    vlans: Vec<VlanInterface>,
 }
 
 struct InterfaceWrapper {
@@ -65,6 +106,8 @@ struct InterfaceWrapper {
     ether_addr: EthernetAddress,
     dev: Mutex<DeviceWrapper>,
     iface: Mutex<Interface>,
+    /// Joined multicast groups and the number of their members.
+    multicast_groups: Mutex<Vec<(Ipv4Address, usize)>>,
 }
 
 impl<'a> SocketSetWrapper<'a> {
@@ -90,11 +133,6 @@ impl<'a> SocketSetWrapper<'a> {
         socket::udp::Socket::new(udp_rx_buffer, udp_tx_buffer)
     }
 
-    pub fn new_dns_socket() -> socket::dns::Socket<'a> {
-        let server_addr = DNS_SEVER.parse().expect("invalid DNS server address");
-        socket::dns::Socket::new(&[server_addr], vec![])
-    }
-
     pub fn add<T: AnySocket<'a>>(&self, socket: T) -> SocketHandle {
         let handle = self.0.lock().add(socket);
         debug!("socket {}: created", handle);
@@ -119,6 +157,13 @@ impl<'a> SocketSetWrapper<'a> {
         f(socket)
     }
 
+    /// Whether a UDP socket is bound to `port`.
+    pub fn udp_port_in_use(&self, port: u16) -> bool {
+        self.0.lock().iter().any(|(_, socket)| {
+            socket::udp::Socket::downcast(socket).is_some_and(|s| s.endpoint().port == port)
+        })
+    }
+
     pub fn poll_interfaces(&self) {
         ETH0.poll(&self.0);
     }
@@ -130,17 +175,23 @@ impl<'a> SocketSetWrapper<'a> {
 }
 
 impl InterfaceWrapper {
-    fn new(name: &'static str, dev: AxNetDevice, ether_addr: EthernetAddress) -> Self {
+    fn new(
+        name: &'static str,
+        dev: AxNetDevice,
+        ports: Vec<AxNetDevice>,
+        ether_addr: EthernetAddress,
+    ) -> Self {
         let mut config = Config::new(HardwareAddress::Ethernet(ether_addr));
         config.random_seed = RANDOM_SEED;
 
-        let mut dev = DeviceWrapper::new(dev);
+        let mut dev = DeviceWrapper::new(dev, ports);
         let iface = Mutex::new(Interface::new(config, &mut dev, Self::current_time()));
         Self {
             name,
             ether_addr,
             dev: Mutex::new(dev),
             iface,
+            multicast_groups: Mutex::new(Vec::new()),
         }
     }
 
@@ -170,19 +221,145 @@ impl InterfaceWrapper {
         };
     }
 
+    pub fn ipv4_addr(&self) -> Option<Ipv4Address> {
+        self.iface.lock().ipv4_addr()
+    }
+
+    /// Joins the multicast group, sending an IGMP membership report if this
+    /// is its first member.
+    pub fn join_multicast_group(&self, addr: Ipv4Address) -> AxResult {
+        if !addr.is_multicast() {
+            return ax_err!(InvalidInput, "not a multicast address");
+        }
+        let mut groups = self.multicast_groups.lock();
+        if let Some((_, members)) = groups.iter_mut().find(|(group, _)| *group == addr) {
+            *members += 1;
+            return Ok(());
+        }
+        let mut dev = self.dev.lock();
+        let mut iface = self.iface.lock();
+        iface
+            .join_multicast_group(dev.deref_mut(), addr, Self::current_time())
+            .map_err(|e| match e {
+                MulticastError::Unaddressable => ax_err_type!(InvalidInput),
+                _ => ax_err_type!(NoMemory, "too many multicast groups"),
+            })?;
+        groups.push((addr, 1));
+        debug!("{}: joined multicast group {}", self.name, addr);
+        Ok(())
+    }
+
+    /// Leaves the multicast group, sending an IGMP leave message if this is
+    /// its last member.
+    pub fn leave_multicast_group(&self, addr: Ipv4Address) -> AxResult {
+        let mut groups = self.multicast_groups.lock();
+        let idx = groups
+            .iter()
+            .position(|(group, _)| *group == addr)
+            .ok_or_else(|| ax_err_type!(InvalidInput, "multicast group not joined"))?;
+        groups[idx].1 -= 1;
+        if groups[idx].1 == 0 {
+            groups.swap_remove(idx);
+            let mut dev = self.dev.lock();
+            let mut iface = self.iface.lock();
+            iface
+                .leave_multicast_group(dev.deref_mut(), addr, Self::current_time())
+                .ok();
+            debug!("{}: left multicast group {}", self.name, addr);
+        }
+        Ok(())
+    }
+
     pub fn poll(&self, sockets: &Mutex<SocketSet>) {
         let mut dev = self.dev.lock();
         let mut iface = self.iface.lock();
         let mut sockets = sockets.lock();
         let timestamp = Self::current_time();
         iface.poll(timestamp, dev.deref_mut(), &mut sockets);
+        if mdns::process(&iface, &mut sockets) {
+            // transmit the responses right away
+            iface.poll(timestamp, dev.deref_mut(), &mut sockets);
+        }
     }
 }
 
 impl DeviceWrapper {
-    fn new(inner: AxNetDevice) -> Self {
+    fn new(inner: AxNetDevice, ports: Vec<AxNetDevice>) -> Self {
         Self {
             inner: RefCell::new(inner),
+            ports: ports.into_iter().map(RefCell::new).collect(),
+            bridge: None,
+            router: None,
+            next_port: 0,
+            vlans: Vec::new(),
+        }
+    }
+
+    /// Returns the NIC of the port, where port 0 is `eth0`.
+    fn port(&self, idx: usize) -> &RefCell<AxNetDevice> {
+        match idx {
+            0 => &self.inner,
+            _ => &self.ports[idx - 1],
+        }
+    }
+
+    /// Returns the number of NICs in use.
+    fn num_ports(&self) -> usize {
+        if self.bridge.is_some() || self.router.is_some() {
+            self.ports.len() + 1
+        } else {
+            1
+        }
+    }
+
+    /// Sends the frames queued by the router.
+    fn send_routed(&mut self) {
+        let Some(router) = self.router.as_mut() else {
+            return;
+        };
+        let frames = core::mem::take(&mut router.tx_queue);
+        for (port, frame) in frames {
+            self.send_to_ports(1 << port, &frame);
+        }
+    }
+
+    /// Returns the ports to send a frame from the local host to.
+    fn output_ports(&self, frame: &[u8]) -> u64 {
+        match &self.bridge {
+            Some(bridge) => bridge.output(self.num_ports(), frame),
+            None => 1,
+        }
+    }
+
+    /// Sends an ARP request for the gateway of the VLAN sub-interface at the
+    /// index, from the MAC address `mac`.
+    fn resolve_gateway(&self, vlan: usize, mac: &[u8]) {
+        let mut request = FrameBuf::alloc(VLAN_TAG_LEN, vlan::ARP_REQUEST_LEN);
+        let vid = vlan::gateway_request(&self.vlans[vlan], mac, &mut request);
+        request.prepend(VLAN_TAG_LEN);
+        vlan::insert_tag(&mut request, vid);
+        self.send_to_ports(self.output_ports(&request), &request);
+    }
+
+    /// Sends a copy of the frame to each port in the bitmask.
+    fn send_to_ports(&self, ports: u64, frame: &[u8]) {
+        if ports != 0 {
+            capture_frame(frame, CaptureDirection::Tx);
+        }
+        for idx in (0..self.num_ports()).filter(|idx| ports & (1 << idx) != 0) {
+            let mut dev = self.port(idx).borrow_mut();
+            let mut tx_buf = match dev.alloc_tx_buffer(frame.len()) {
+                Ok(buf) => buf,
+                Err(e) => {
+                    warn!("port {}: alloc_tx_buffer failed: {:?}", idx, e);
+                    continue;
+                }
+            };
+            tx_buf.packet_mut().copy_from_slice(frame);
+            trace!("SEND {} bytes to port {}: {:02X?}", frame.len(), idx, frame);
+            if let Err(e) = dev.transmit(tx_buf) {
+                warn!("port {}: transmit failed: {:?}", idx, e);
+            }
         }
     }
 }
@@ -198,25 +375,70 @@ impl Device for DeviceWrapper {
         Self: 'a;
 
     fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
-        let mut dev = self.inner.borrow_mut();
-        if let Err(e) = dev.recycle_tx_buffers() {
-            warn!("recycle_tx_buffers failed: {:?}", e);
-            return None;
+        let num_ports = self.num_ports();
+        for idx in 0..num_ports {
+            if let Err(e) = self.port(idx).borrow_mut().recycle_tx_buffers() {
+                warn!("recycle_tx_buffers failed: {:?}", e);
+                return None;
+            }
         }
 
-        if !dev.can_transmit() {
+        if !self.inner.borrow().can_transmit() {
             return None;
         }
-        let rx_buf = match dev.receive() {
-            Ok(buf) => buf,
-            Err(err) => {
-                if !matches!(err, DevError::Again) {
-                    warn!("receive failed: {:?}", err);
+        if let Some(router) = self.router.as_mut() {
+            router.poll();
+            self.send_routed();
+        }
+        // stop after every port has nothing to receive
+        let mut idle_ports = 0;
+        while idle_ports < num_ports {
+            let idx = self.next_port % num_ports;
+            self.next_port = (idx + 1) % num_ports;
+            let mut rx_buf = match self.port(idx).borrow_mut().receive() {
+                Ok(buf) => buf,
+                Err(err) => {
+                    if !matches!(err, DevError::Again) {
+                        warn!("receive failed: {:?}", err);
+                    }
+                    idle_ports += 1;
+                    continue;
                 }
-                return None;
+            };
+            idle_ports = 0;
+            // including the frames forwarded by the bridge or the router
+            capture_frame(rx_buf.packet(), CaptureDirection::Rx);
+
+            let bridge = self.bridge.as_mut();
+            let fwd = bridge.map(|br| br.input(idx, num_ports, rx_buf.packet()));
+            if let Some(fwd) = fwd.as_ref().filter(|fwd| fwd.ports != 0) {
+                self.send_to_ports(fwd.ports, rx_buf.packet());
             }
-        };
-        Some((AxNetRxToken(&self.inner, rx_buf), AxNetTxToken(&self.inner)))
+            let router = self.router.as_mut();
+            let routed = router.map(|r| r.input(idx, rx_buf.packet()));
+            if routed.is_some() {
+                self.send_routed();
+            }
+            let local = fwd.map_or(true, |fwd| fwd.local) && routed.unwrap_or(true);
+            let offset = if local {
+                vlan::rx_demux(&mut self.vlans, rx_buf.packet_mut())
+            } else {
+                None
+            };
+            match offset {
+                Some(offset) => {
+                    let rx_token = AxNetRxToken(self.port(idx), rx_buf, offset);
+                    return Some((rx_token, AxNetTxToken(self)));
+                }
+                // not for us
+                None => self
+                    .port(idx)
+                    .borrow_mut()
+                    .recycle_rx_buffer(rx_buf)
+                    .unwrap(),
+            }
+        }
+        None
     }
 
     fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
@@ -226,7 +448,7 @@ impl Device for DeviceWrapper {
             return None;
         }
         if dev.can_transmit() {
-            Some(AxNetTxToken(&self.inner))
+            Some(AxNetTxToken(self))
         } else {
             None
         }
@@ -241,12 +463,19 @@ impl Device for DeviceWrapper {
     }
 }
 
-struct AxNetRxToken<'a>(&'a RefCell<AxNetDevice>, NetBufPtr);
-struct AxNetTxToken<'a>(&'a RefCell<AxNetDevice>);
+// The tokens lend the NIC buffers to smoltcp directly, so frames are never
+// copied between the device and the network stack, except for the frames
+// sent when VLAN sub-interfaces exist or the bridge is started, which are
+// built in a buffer from the pool first.
+//
+// A received frame starts at the given offset in the buffer, after its VLAN
+// tag was removed.
+struct AxNetRxToken<'a>(&'a RefCell<AxNetDevice>, NetBufPtr, usize);
+struct AxNetTxToken<'a>(&'a DeviceWrapper);
 
 impl<'a> RxToken for AxNetRxToken<'a> {
     fn preprocess(&self, sockets: &mut SocketSet<'_>) {
-        snoop_tcp_packet(self.1.packet(), sockets).ok();
+        snoop_tcp_packet(&self.1.packet()[self.2..], sockets).ok();
     }
 
     fn consume<R, F>(self, f: F) -> R
@@ -254,12 +483,9 @@ impl<'a> RxToken for AxNetRxToken<'a> {
         F: FnOnce(&mut [u8]) -> R,
     {
         let mut rx_buf = self.1;
-        trace!(
-            "RECV {} bytes: {:02X?}",
-            rx_buf.packet_len(),
-            rx_buf.packet()
-        );
-        let result = f(rx_buf.packet_mut());
+        let frame = &mut rx_buf.packet_mut()[self.2..];
+        trace!("RECV {} bytes: {:02X?}", frame.len(), frame);
+        let result = f(frame);
         self.0.borrow_mut().recycle_rx_buffer(rx_buf).unwrap();
         result
     }
@@ -270,11 +496,36 @@ impl<'a> TxToken for AxNetTxToken<'a> {
     where
         F: FnOnce(&mut [u8]) -> R,
     {
-        let mut dev = self.0.borrow_mut();
-        let mut tx_buf = dev.alloc_tx_buffer(len).unwrap();
-        let ret = f(tx_buf.packet_mut());
-        trace!("SEND {} bytes: {:02X?}", len, tx_buf.packet());
-        dev.transmit(tx_buf).unwrap();
+        let wrapper = self.0;
+        if wrapper.vlans.is_empty() && wrapper.bridge.is_none() {
+            let mut dev = wrapper.inner.borrow_mut();
+            let mut tx_buf = dev.alloc_tx_buffer(len).unwrap();
+            let ret = f(tx_buf.packet_mut());
+            trace!("SEND {} bytes: {:02X?}", len, tx_buf.packet());
+            capture_frame(tx_buf.packet(), CaptureDirection::Tx);
+            dev.transmit(tx_buf).unwrap();
+            return ret;
+        }
+
+        // the VLAN tag and the ports depend on the addresses in the frame
+        let mut frame = FrameBuf::alloc(VLAN_TAG_LEN, len);
+        let ret = f(&mut frame);
+        let vid = match vlan::tx_route(&wrapper.vlans, &mut frame) {
+            TxRoute::Untagged => None,
+            TxRoute::Tagged(vid) => Some(vid),
+            TxRoute::Resolve(idx) => {
+                // dropped like the packets smoltcp sends before their
+                // neighbor is resolved
+                wrapper.resolve_gateway(idx, &frame[6..12]);
+                return ret;
+            }
+            TxRoute::Drop => return ret,
+        };
+        if let Some(vid) = vid {
+            frame.prepend(VLAN_TAG_LEN);
+            vlan::insert_tag(&mut frame, vid);
+        }
+        wrapper.send_to_ports(wrapper.output_ports(&frame), &frame);
         ret
     }
 }
@@ -307,6 +558,10 @@ pub fn poll_interfaces() {
 }
 
 /// Benchmark raw socket transmit bandwidth.
+///
+/// When VLAN sub-interfaces exist or the bridge is started, the frames are
+/// built in pooled buffers and then copied to the NICs, so running it with
+/// and without them compares the two transmit paths.
 pub fn bench_transmit() {
     ETH0.dev.lock().bench_transmit_bandwidth();
 }
@@ -316,9 +571,13 @@ pub fn bench_receive() {
     ETH0.dev.lock().bench_receive_bandwidth();
 }
 
-pub(crate) fn init(net_dev: AxNetDevice) {
+pub(crate) fn init(net_dev: AxNetDevice, mut ports: Vec<AxNetDevice>) {
+    if ports.len() >= MAX_PORTS {
+        warn!("too many NICs, only {} are used", MAX_PORTS);
+        ports.truncate(MAX_PORTS - 1);
+    }
     let ether_addr = EthernetAddress(net_dev.mac_address().0);
-    let eth0 = InterfaceWrapper::new("eth0", net_dev, ether_addr);
+    let eth0 = InterfaceWrapper::new("eth0", net_dev, ports, ether_addr);
 
     let ip = IP.parse().expect("invalid IP address");
     let gateway = GATEWAY.parse().expect("invalid gateway IP address");
@@ -328,9 +587,17 @@ pub(crate) fn init(net_dev: AxNetDevice) {
     ETH0.init_once(eth0);
     SOCKET_SET.init_once(SocketSetWrapper::new());
     LISTEN_TABLE.init_once(ListenTable::new());
+    dns::init();
 
     info!("created net interface {:?}:", ETH0.name());
     info!("  ether:    {}", ETH0.ethernet_address());
     info!("  ip:       {}/{}", ip, IP_PREFIX);
     info!("  gateway:  {}", gateway);
+
+    vlan::setup_vlans(VLANS);
+    if !MDNS_HOST.is_empty() {
+        if let Err(e) = mdns_start(MDNS_HOST) {
+            warn!("failed to start the mDNS responder: {:?}", e);
+        }
+    }
 }
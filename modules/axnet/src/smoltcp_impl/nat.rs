@@ -0,0 +1,802 @@
+//! Source NAT (masquerade) and port forwarding of the forwarded packets.
+//!
+//! Packets from the other NICs leaving through `eth0` get the address of
+//! `eth0` as their source, with a port (or ICMP echo identifier) allocated
+//! from a range that local sockets never use. The connection-tracking table
+//! maps the replies, and the ICMP errors about the connections, back to the
+//! inside hosts.
+//!
+//! Port forwarding rules statically map a TCP or UDP port of `eth0` to an
+//! inside address. They take precedence over the local sockets bound to the
+//! same port.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::net::{Ipv4Addr, SocketAddrV4};
+
+use axerrno::{ax_err, ax_err_type, AxResult};
+use axhal::time::{monotonic_time_nanos, NANOS_PER_SEC};
+use smoltcp::wire::{Icmpv4Packet, TcpPacket, UdpPacket};
+use smoltcp::wire::{IpProtocol, Ipv4Address, Ipv4Packet};
+
+use super::ETH0;
+
+/// The range of the NAT ports, below the ephemeral ports of local sockets.
+const NAT_PORT_START: u16 = 0x8000;
+const NAT_PORT_END: u16 = 0xbfff;
+const MAX_CONNECTIONS: usize = 4096;
+
+// Timeouts of the connections, as recommended by RFC 5382 and RFC 4787.
+const TCP_ESTABLISHED_TIMEOUT: u64 = 7440 * NANOS_PER_SEC;
+const TCP_TRANSITORY_TIMEOUT: u64 = 240 * NANOS_PER_SEC;
+const UDP_TIMEOUT: u64 = 30 * NANOS_PER_SEC;
+const UDP_STREAM_TIMEOUT: u64 = 180 * NANOS_PER_SEC;
+const ICMP_TIMEOUT: u64 = 30 * NANOS_PER_SEC;
+
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMP_DST_UNREACHABLE: u8 = 3;
+const ICMP_ECHO_REQUEST: u8 = 8;
+const ICMP_TIME_EXCEEDED: u8 = 11;
+const ICMP_PARAM_PROBLEM: u8 = 12;
+
+const TCP_FIN: u8 = 0x01;
+const TCP_RST: u8 = 0x04;
+
+/// The transport protocol of a NAT mapping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum NatProtocol {
+    /// TCP.
+    Tcp,
+    /// UDP.
+    Udp,
+    /// ICMP echo, with the identifier as the port.
+    Icmp,
+}
+
+/// A rule forwarding a port of `eth0` to an inside address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortForward {
+    /// The protocol, either TCP or UDP.
+    pub protocol: NatProtocol,
+    /// The port of `eth0`.
+    pub external_port: u16,
+    /// The inside address to forward to.
+    pub internal: SocketAddrV4,
+}
+
+/// A connection tracked by NAT.
+#[derive(Debug, Clone, Copy)]
+pub struct NatConnection {
+    /// The protocol of the connection.
+    pub protocol: NatProtocol,
+    /// The address of the inside host.
+    pub inside: SocketAddrV4,
+    /// The address of the outside host.
+    pub outside: SocketAddrV4,
+    /// The port of `eth0` the connection is mapped to.
+    pub nat_port: u16,
+}
+
+struct Conn {
+    inside: SocketAddrV4,
+    outside: SocketAddrV4,
+    last_seen: u64,
+    /// Whether a reply was received.
+    replied: bool,
+    /// Whether a TCP FIN or RST was seen.
+    closing: bool,
+}
+
+impl Conn {
+    fn timeout(&self, protocol: NatProtocol) -> u64 {
+        match protocol {
+            NatProtocol::Tcp if self.replied && !self.closing => TCP_ESTABLISHED_TIMEOUT,
+            NatProtocol::Tcp => TCP_TRANSITORY_TIMEOUT,
+            NatProtocol::Udp if self.replied => UDP_STREAM_TIMEOUT,
+            NatProtocol::Udp => UDP_TIMEOUT,
+            NatProtocol::Icmp => ICMP_TIMEOUT,
+        }
+    }
+
+    fn expired(&self, protocol: NatProtocol, now: u64) -> bool {
+        now - self.last_seen >= self.timeout(protocol)
+    }
+
+    fn update(&mut self, l4: &[u8], protocol: NatProtocol, now: u64) {
+        self.last_seen = now;
+        if protocol == NatProtocol::Tcp && l4[13] & (TCP_FIN | TCP_RST) != 0 {
+            self.closing = true;
+        }
+    }
+}
+
+/// The addresses of a packet, with the ports of TCP/UDP or the identifier of
+/// ICMP echo.
+struct Flow {
+    protocol: NatProtocol,
+    src: SocketAddrV4,
+    dst: SocketAddrV4,
+}
+
+fn be16(buf: &[u8], pos: usize) -> u16 {
+    u16::from_be_bytes([buf[pos], buf[pos + 1]])
+}
+
+/// Updates the Internet checksum `sum` for the 16-bit words `old` replaced by
+/// `new` (RFC 1624), for packets that cannot be checksummed as a whole.
+fn adjust_checksum(sum: u16, old: &[u8], new: &[u8]) -> u16 {
+    let mut acc = !sum as u32;
+    for (old, new) in old.chunks(2).zip(new.chunks(2)) {
+        acc += !be16(old, 0) as u32 + be16(new, 0) as u32;
+    }
+    while acc > 0xffff {
+        acc = (acc & 0xffff) + (acc >> 16);
+    }
+    !(acc as u16)
+}
+
+fn sock_addr(ip: Ipv4Address, port: u16) -> SocketAddrV4 {
+    SocketAddrV4::new(Ipv4Addr::from(ip.0), port)
+}
+
+fn is_fragment(ip: &Ipv4Packet<&[u8]>) -> bool {
+    ip.more_frags() || ip.frag_offset() != 0
+}
+
+/// Returns the flow of a valid IPv4 packet, or `None` if NAT cannot track it.
+fn flow(packet: &[u8]) -> Option<Flow> {
+    let ip = Ipv4Packet::new_unchecked(packet);
+    if is_fragment(&ip) {
+        return None;
+    }
+    let l4 = ip.payload();
+    let (protocol, src_port, dst_port) = match ip.next_header() {
+        IpProtocol::Tcp => {
+            let tcp = TcpPacket::new_checked(l4).ok()?;
+            (NatProtocol::Tcp, tcp.src_port(), tcp.dst_port())
+        }
+        IpProtocol::Udp => {
+            let udp = UdpPacket::new_checked(l4).ok()?;
+            (NatProtocol::Udp, udp.src_port(), udp.dst_port())
+        }
+        IpProtocol::Icmp => {
+            if l4.len() < 8 || !matches!(l4[0], ICMP_ECHO_REQUEST | ICMP_ECHO_REPLY) {
+                return None;
+            }
+            let ident = be16(l4, 4);
+            (NatProtocol::Icmp, ident, ident)
+        }
+        _ => return None,
+    };
+    Some(Flow {
+        protocol,
+        src: sock_addr(ip.src_addr(), src_port),
+        dst: sock_addr(ip.dst_addr(), dst_port),
+    })
+}
+
+/// Sets the source or destination port of the TCP/UDP header, or the
+/// identifier of the ICMP echo header.
+fn set_port(protocol: NatProtocol, l4: &mut [u8], src: bool, port: u16) {
+    let pos = match protocol {
+        NatProtocol::Icmp => 4,
+        _ if src => 0,
+        _ => 2,
+    };
+    l4[pos..pos + 2].copy_from_slice(&port.to_be_bytes());
+}
+
+/// Rewrites the source or destination address of an unfragmented packet,
+/// and updates its checksums.
+fn rewrite(packet: &mut [u8], protocol: Option<NatProtocol>, src: bool, addr: SocketAddrV4) {
+    let mut ip = Ipv4Packet::new_unchecked(packet);
+    let ip_addr = Ipv4Address(addr.ip().octets());
+    if src {
+        ip.set_src_addr(ip_addr);
+    } else {
+        ip.set_dst_addr(ip_addr);
+    }
+    if let Some(protocol) = protocol {
+        set_port(protocol, ip.payload_mut(), src, addr.port());
+    }
+    fill_checksums(ip.into_inner());
+}
+
+/// Recomputes the checksums of an unfragmented packet.
+pub(super) fn fill_checksums(packet: &mut [u8]) {
+    let mut ip = Ipv4Packet::new_unchecked(packet);
+    let (src, dst) = (ip.src_addr().into(), ip.dst_addr().into());
+    match ip.next_header() {
+        IpProtocol::Tcp => TcpPacket::new_unchecked(ip.payload_mut()).fill_checksum(&src, &dst),
+        IpProtocol::Udp => {
+            let mut udp = UdpPacket::new_unchecked(ip.payload_mut());
+            // a zero checksum means no checksum
+            if udp.checksum() != 0 {
+                udp.fill_checksum(&src, &dst);
+            }
+        }
+        IpProtocol::Icmp => Icmpv4Packet::new_unchecked(ip.payload_mut()).fill_checksum(),
+        _ => {}
+    }
+    ip.fill_checksum();
+}
+
+pub(super) struct Nat {
+    masquerade: bool,
+    forwards: Vec<PortForward>,
+    /// The tracked connections by their NAT ports.
+    conns: BTreeMap<(NatProtocol, u16), Conn>,
+    /// The NAT ports of the tracked connections by their inside and outside
+    /// addresses.
+    ports: BTreeMap<(NatProtocol, SocketAddrV4, SocketAddrV4), u16>,
+    next_port: u16,
+}
+
+impl Nat {
+    pub fn new() -> Self {
+        Self {
+            masquerade: false,
+            forwards: Vec::new(),
+            conns: BTreeMap::new(),
+            ports: BTreeMap::new(),
+            next_port: NAT_PORT_START,
+        }
+    }
+
+    pub fn num_connections(&self) -> usize {
+        self.conns.len()
+    }
+
+    pub fn set_masquerade(&mut self, enabled: bool) {
+        self.masquerade = enabled;
+        info!("NAT: masquerade {}", if enabled { "on" } else { "off" });
+    }
+
+    /// Removes the expired connections.
+    pub fn expire(&mut self, now: u64) {
+        let ports = &mut self.ports;
+        self.conns.retain(|&(protocol, _), conn| {
+            let expired = conn.expired(protocol, now);
+            if expired {
+                ports.remove(&(protocol, conn.inside, conn.outside));
+            }
+            !expired
+        });
+    }
+
+    fn alloc_port(&mut self, protocol: NatProtocol) -> Option<u16> {
+        for _ in NAT_PORT_START..=NAT_PORT_END {
+            let port = self.next_port;
+            self.next_port = match port {
+                NAT_PORT_END => NAT_PORT_START,
+                _ => port + 1,
+            };
+            let forwarded = self
+                .forwards
+                .iter()
+                .any(|f| f.protocol == protocol && f.external_port == port);
+            if !forwarded && !self.conns.contains_key(&(protocol, port)) {
+                return Some(port);
+            }
+        }
+        None
+    }
+
+    /// Returns the NAT port of the connection, tracking it if it is new.
+    fn track(&mut self, flow: &Flow, now: u64) -> Option<u16> {
+        let key = (flow.protocol, flow.src, flow.dst);
+        if let Some(&port) = self.ports.get(&key) {
+            return Some(port);
+        }
+        if self.conns.len() >= MAX_CONNECTIONS {
+            self.expire(now);
+            if self.conns.len() >= MAX_CONNECTIONS {
+                warn!("NAT: too many connections");
+                return None;
+            }
+        }
+        let port = self.alloc_port(flow.protocol)?;
+        let conn = Conn {
+            inside: flow.src,
+            outside: flow.dst,
+            last_seen: now,
+            replied: false,
+            closing: false,
+        };
+        self.conns.insert((flow.protocol, port), conn);
+        self.ports.insert(key, port);
+        debug!(
+            "NAT: {:?} {} -> {} mapped to port {}",
+            flow.protocol, flow.src, flow.dst, port
+        );
+        Some(port)
+    }
+
+    /// Translates a packet from the other NICs leaving through `eth0`, whose
+    /// address is `nat_addr`.
+    ///
+    /// Returns `false` if the packet should be dropped.
+    pub fn outbound(&mut self, packet: &mut [u8], nat_addr: Ipv4Address, now: u64) -> bool {
+        let nat_ip = Ipv4Addr::from(nat_addr.0);
+        let Some(flow) = flow(packet) else {
+            if !self.masquerade {
+                return true;
+            }
+            let ip = Ipv4Packet::new_unchecked(&*packet);
+            if is_fragment(&ip) {
+                return false;
+            }
+            // only the address of the other protocols is translated
+            rewrite(packet, None, true, SocketAddrV4::new(nat_ip, 0));
+            return true;
+        };
+
+        // replies of the forwarded ports
+        let rule = self
+            .forwards
+            .iter()
+            .find(|f| f.protocol == flow.protocol && f.internal == flow.src);
+        if let Some(rule) = rule {
+            let addr = SocketAddrV4::new(nat_ip, rule.external_port);
+            rewrite(packet, Some(flow.protocol), true, addr);
+            return true;
+        }
+        if !self.masquerade {
+            return true;
+        }
+
+        let Some(port) = self.track(&flow, now) else {
+            return false;
+        };
+        let ip = Ipv4Packet::new_unchecked(&*packet);
+        let conn = self.conns.get_mut(&(flow.protocol, port)).unwrap();
+        conn.update(ip.payload(), flow.protocol, now);
+        rewrite(
+            packet,
+            Some(flow.protocol),
+            true,
+            SocketAddrV4::new(nat_ip, port),
+        );
+        true
+    }
+
+    /// Translates a packet received by `eth0` for its own address.
+    ///
+    /// Returns `false` if the packet does not belong to NAT, and should be
+    /// delivered to the local network stack.
+    pub fn inbound(&mut self, packet: &mut [u8], now: u64) -> bool {
+        let Some(flow) = flow(packet) else {
+            return self.inbound_icmp_error(packet);
+        };
+        if flow.protocol != NatProtocol::Icmp {
+            let rule = self
+                .forwards
+                .iter()
+                .find(|f| f.protocol == flow.protocol && f.external_port == flow.dst.port());
+            if let Some(rule) = rule {
+                let internal = rule.internal;
+                rewrite(packet, Some(flow.protocol), false, internal);
+                return true;
+            }
+        }
+        // only echo replies come back to an ICMP mapping
+        let ip = Ipv4Packet::new_unchecked(&*packet);
+        let l4 = ip.payload();
+        if flow.protocol == NatProtocol::Icmp && l4[0] != ICMP_ECHO_REPLY {
+            return false;
+        }
+
+        let Some(conn) = self.conns.get_mut(&(flow.protocol, flow.dst.port())) else {
+            return false;
+        };
+        let from_outside = match flow.protocol {
+            NatProtocol::Icmp => conn.outside.ip() == flow.src.ip(),
+            _ => conn.outside == flow.src,
+        };
+        if !from_outside || conn.expired(flow.protocol, now) {
+            return false;
+        }
+        conn.replied = true;
+        conn.update(l4, flow.protocol, now);
+        let inside = conn.inside;
+        rewrite(packet, Some(flow.protocol), false, inside);
+        true
+    }
+
+    /// Translates an ICMP error about a masqueraded packet, which quotes the
+    /// IP header and the ports of the packet.
+    fn inbound_icmp_error(&mut self, packet: &mut [u8]) -> bool {
+        let ip = Ipv4Packet::new_unchecked(&*packet);
+        if ip.next_header() != IpProtocol::Icmp || is_fragment(&ip) {
+            return false;
+        }
+        let icmp = ip.payload();
+        let is_error = matches!(
+            icmp.first(),
+            Some(&(ICMP_DST_UNREACHABLE | ICMP_TIME_EXCEEDED | ICMP_PARAM_PROBLEM))
+        );
+        if !is_error || icmp.len() < 8 {
+            return false;
+        }
+        let header_len = ip.header_len() as usize;
+        // the quoted packet is truncated, so its total length is not checked
+        let quote = &icmp[8..];
+        if quote.len() < 20 {
+            return false;
+        }
+        let quoted = Ipv4Packet::new_unchecked(quote);
+        let quoted_len = quoted.header_len() as usize;
+        if quoted.version() != 4 || quoted_len < 20 || quote.len() < quoted_len + 8 {
+            return false;
+        }
+        let ports = &quote[quoted_len..];
+        let protocol = match quoted.next_header() {
+            IpProtocol::Tcp => NatProtocol::Tcp,
+            IpProtocol::Udp => NatProtocol::Udp,
+            IpProtocol::Icmp if ports.first() == Some(&ICMP_ECHO_REQUEST) => NatProtocol::Icmp,
+            _ => return false,
+        };
+        let nat_port = match protocol {
+            NatProtocol::Icmp => be16(ports, 4),
+            _ => be16(ports, 0),
+        };
+        let Some(conn) = self.conns.get(&(protocol, nat_port)) else {
+            return false;
+        };
+        if Ipv4Addr::from(quoted.dst_addr().0) != *conn.outside.ip() {
+            return false;
+        }
+        let inside = conn.inside;
+        let inside_ip = Ipv4Address(inside.ip().octets());
+        let nat_ip = quoted.src_addr();
+
+        // the quoted packet was sent from the inside host
+        let quote = &mut packet[header_len + 8..];
+        let mut quoted = Ipv4Packet::new_unchecked(&mut quote[..]);
+        quoted.set_src_addr(inside_ip);
+        quoted.fill_checksum();
+        let l4 = &mut quote[quoted_len..];
+        set_port(protocol, l4, true, inside.port());
+        // The quoted transport header is usually truncated, so its checksum
+        // is adjusted for the new port and the address in the pseudo header.
+        let pos = match protocol {
+            NatProtocol::Tcp => 16,
+            NatProtocol::Udp => 6,
+            NatProtocol::Icmp => 2,
+        };
+        let sum = l4.get(pos..pos + 2).map(|sum| be16(sum, 0));
+        // a zero UDP checksum means no checksum
+        if let Some(mut sum) = sum.filter(|&sum| protocol != NatProtocol::Udp || sum != 0) {
+            sum = adjust_checksum(sum, &nat_port.to_be_bytes(), &inside.port().to_be_bytes());
+            if protocol != NatProtocol::Icmp {
+                sum = adjust_checksum(sum, &nat_ip.0, &inside_ip.0);
+            }
+            if protocol == NatProtocol::Udp && sum == 0 {
+                sum = 0xffff;
+            }
+            l4[pos..pos + 2].copy_from_slice(&sum.to_be_bytes());
+        }
+        let mut ip = Ipv4Packet::new_unchecked(packet);
+        ip.set_dst_addr(inside_ip);
+        fill_checksums(ip.into_inner());
+        true
+    }
+}
+
+fn with_nat<R>(f: impl FnOnce(&mut Nat) -> AxResult<R>) -> AxResult<R> {
+    let mut dev = ETH0.dev.lock();
+    let router = dev
+        .router
+        .as_mut()
+        .ok_or_else(|| ax_err_type!(BadState, "IP forwarding not started"))?;
+    f(&mut router.nat)
+}
+
+/// Enables or disables the source NAT of the packets leaving through `eth0`.
+///
+/// IP forwarding must be started by [`ip_forward_start`] first.
+///
+/// [`ip_forward_start`]: super::ip_forward_start
+pub fn nat_set_masquerade(enabled: bool) -> AxResult {
+    with_nat(|nat| {
+        nat.set_masquerade(enabled);
+        Ok(())
+    })
+}
+
+/// Adds a rule forwarding a TCP or UDP port of `eth0` to an inside address.
+pub fn nat_add_port_forward(rule: PortForward) -> AxResult {
+    if rule.protocol == NatProtocol::Icmp {
+        return ax_err!(InvalidInput, "only TCP and UDP ports can be forwarded");
+    }
+    with_nat(|nat| {
+        let exists = nat
+            .forwards
+            .iter()
+            .any(|f| f.protocol == rule.protocol && f.external_port == rule.external_port);
+        if exists {
+            return ax_err!(AlreadyExists, "port already forwarded");
+        }
+        info!(
+            "NAT: forwarding {:?} port {} to {}",
+            rule.protocol, rule.external_port, rule.internal
+        );
+        nat.forwards.push(rule);
+        Ok(())
+    })
+}
+
+/// Removes the rule forwarding the port of `eth0`.
+pub fn nat_remove_port_forward(protocol: NatProtocol, external_port: u16) -> AxResult {
+    with_nat(|nat| {
+        let idx = nat
+            .forwards
+            .iter()
+            .position(|f| f.protocol == protocol && f.external_port == external_port)
+            .ok_or_else(|| ax_err_type!(NotFound, "port not forwarded"))?;
+        nat.forwards.remove(idx);
+        Ok(())
+    })
+}
+
+/// Returns the connections tracked by NAT.
+pub fn nat_connections() -> Vec<NatConnection> {
+    let now = monotonic_time_nanos();
+    let dev = ETH0.dev.lock();
+    let Some(router) = dev.router.as_ref() else {
+        return Vec::new();
+    };
+    router
+        .nat
+        .conns
+        .iter()
+        .filter(|(&(protocol, _), conn)| !conn.expired(protocol, now))
+        .map(|(&(protocol, nat_port), conn)| NatConnection {
+            protocol,
+            inside: conn.inside,
+            outside: conn.outside,
+            nat_port,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    const INSIDE: [u8; 4] = [192, 168, 1, 10];
+    const OUTSIDE: [u8; 4] = [8, 8, 8, 8];
+    const NAT_ADDR: Ipv4Address = Ipv4Address([10, 0, 2, 15]);
+
+    fn addr(ip: [u8; 4], port: u16) -> SocketAddrV4 {
+        SocketAddrV4::new(ip.into(), port)
+    }
+
+    /// Builds an IPv4 packet with valid checksums.
+    fn ipv4(protocol: IpProtocol, src: [u8; 4], dst: [u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0; 20 + payload.len()];
+        let mut ip = Ipv4Packet::new_unchecked(&mut packet[..]);
+        ip.set_version(4);
+        ip.set_header_len(20);
+        ip.set_total_len((20 + payload.len()) as u16);
+        ip.set_hop_limit(64);
+        ip.set_next_header(protocol);
+        ip.set_src_addr(Ipv4Address(src));
+        ip.set_dst_addr(Ipv4Address(dst));
+        ip.payload_mut().copy_from_slice(payload);
+        fill_checksums(&mut packet);
+        packet
+    }
+
+    fn tcp(src: SocketAddrV4, dst: SocketAddrV4, flags: u8) -> Vec<u8> {
+        let mut tcp = vec![0; 20];
+        tcp[0..2].copy_from_slice(&src.port().to_be_bytes());
+        tcp[2..4].copy_from_slice(&dst.port().to_be_bytes());
+        tcp[12] = 5 << 4;
+        tcp[13] = flags;
+        ipv4(IpProtocol::Tcp, src.ip().octets(), dst.ip().octets(), &tcp)
+    }
+
+    fn udp(src: SocketAddrV4, dst: SocketAddrV4) -> Vec<u8> {
+        let mut udp = vec![0; 12];
+        udp[0..2].copy_from_slice(&src.port().to_be_bytes());
+        udp[2..4].copy_from_slice(&dst.port().to_be_bytes());
+        udp[4..6].copy_from_slice(&12u16.to_be_bytes());
+        udp[6..8].copy_from_slice(&[0xff, 0xff]); // to be filled
+        udp[8..].copy_from_slice(b"ping");
+        ipv4(IpProtocol::Udp, src.ip().octets(), dst.ip().octets(), &udp)
+    }
+
+    fn icmp(msg_type: u8, src: [u8; 4], dst: [u8; 4], rest: [u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut icmp = vec![msg_type, 0, 0, 0];
+        icmp.extend_from_slice(&rest);
+        icmp.extend_from_slice(data);
+        ipv4(IpProtocol::Icmp, src, dst, &icmp)
+    }
+
+    fn echo(msg_type: u8, src: [u8; 4], dst: [u8; 4], ident: u16) -> Vec<u8> {
+        let [hi, lo] = ident.to_be_bytes();
+        icmp(msg_type, src, dst, [hi, lo, 0, 1], b"data")
+    }
+
+    /// Checks the checksums of a packet, and returns its IP addresses.
+    fn verify(packet: &[u8]) -> (Ipv4Addr, Ipv4Addr) {
+        let ip = Ipv4Packet::new_checked(packet).unwrap();
+        assert!(ip.verify_checksum());
+        let (src, dst) = (ip.src_addr().into(), ip.dst_addr().into());
+        let valid = match ip.next_header() {
+            IpProtocol::Tcp => TcpPacket::new_unchecked(ip.payload()).verify_checksum(&src, &dst),
+            IpProtocol::Udp => UdpPacket::new_unchecked(ip.payload()).verify_checksum(&src, &dst),
+            IpProtocol::Icmp => Icmpv4Packet::new_unchecked(ip.payload()).verify_checksum(),
+            _ => true,
+        };
+        assert!(valid);
+        (ip.src_addr().0.into(), ip.dst_addr().0.into())
+    }
+
+    /// Returns the addresses of a packet after checking its checksums.
+    fn check(packet: &[u8]) -> (SocketAddrV4, SocketAddrV4) {
+        verify(packet);
+        let flow = flow(packet).unwrap();
+        (flow.src, flow.dst)
+    }
+
+    fn masquerade() -> Nat {
+        let mut nat = Nat::new();
+        nat.set_masquerade(true);
+        nat
+    }
+
+    #[test]
+    fn test_snat_round_trip() {
+        type Build = fn(SocketAddrV4, SocketAddrV4) -> Vec<u8>;
+        let mut nat = masquerade();
+        let (inside, outside) = (addr(INSIDE, 1234), addr(OUTSIDE, 53));
+        for (i, build) in [udp as Build, |src, dst| tcp(src, dst, 0)]
+            .iter()
+            .enumerate()
+        {
+            let mapped = addr(NAT_ADDR.0, NAT_PORT_START + i as u16);
+            let mut packet = build(inside, outside);
+            assert!(nat.outbound(&mut packet, NAT_ADDR, 0));
+            assert_eq!(check(&packet), (mapped, outside));
+            // the same connection keeps its port
+            let mut packet = build(inside, outside);
+            assert!(nat.outbound(&mut packet, NAT_ADDR, 0));
+            assert_eq!(check(&packet), (mapped, outside));
+
+            let mut reply = build(outside, mapped);
+            assert!(nat.inbound(&mut reply, 1));
+            assert_eq!(check(&reply), (outside, inside));
+            // not from the outside host of the connection
+            let mut other = build(addr([8, 8, 4, 4], 53), mapped);
+            assert!(!nat.inbound(&mut other, 1));
+        }
+        assert_eq!(nat.num_connections(), 2);
+
+        // a packet to an unmapped port belongs to the local network stack
+        let mut packet = udp(outside, addr(NAT_ADDR.0, 5000));
+        assert!(!nat.inbound(&mut packet, 2));
+        // other protocols get the address translated only
+        let mut packet = ipv4(IpProtocol::Unknown(47), INSIDE, OUTSIDE, &[0; 8]);
+        assert!(nat.outbound(&mut packet, NAT_ADDR, 2));
+        assert_eq!(verify(&packet), (NAT_ADDR.0.into(), OUTSIDE.into()));
+    }
+
+    #[test]
+    fn test_port_forward() {
+        let mut nat = Nat::new();
+        let internal = addr(INSIDE, 80);
+        nat.forwards.push(PortForward {
+            protocol: NatProtocol::Tcp,
+            external_port: 8080,
+            internal,
+        });
+        let client = addr([1, 2, 3, 4], 5555);
+        let mut packet = tcp(client, addr(NAT_ADDR.0, 8080), 0);
+        assert!(nat.inbound(&mut packet, 0));
+        assert_eq!(check(&packet), (client, internal));
+        // replies are translated back without masquerade
+        let mut reply = tcp(internal, client, 0);
+        assert!(nat.outbound(&mut reply, NAT_ADDR, 0));
+        assert_eq!(check(&reply), (addr(NAT_ADDR.0, 8080), client));
+        // and the port is never allocated to other connections
+        nat.next_port = 8080;
+        assert_eq!(nat.alloc_port(NatProtocol::Tcp), Some(8081));
+        assert_eq!(nat.num_connections(), 0);
+    }
+
+    #[test]
+    fn test_icmp_echo() {
+        let mut nat = masquerade();
+        let mut request = echo(ICMP_ECHO_REQUEST, INSIDE, OUTSIDE, 77);
+        assert!(nat.outbound(&mut request, NAT_ADDR, 0));
+        let (src, _) = check(&request);
+        assert_eq!(src, addr(NAT_ADDR.0, NAT_PORT_START));
+
+        // only echo replies come back
+        let mut request = echo(ICMP_ECHO_REQUEST, OUTSIDE, NAT_ADDR.0, src.port());
+        assert!(!nat.inbound(&mut request, 1));
+        let mut reply = echo(ICMP_ECHO_REPLY, OUTSIDE, NAT_ADDR.0, src.port());
+        assert!(nat.inbound(&mut reply, 1));
+        assert_eq!(check(&reply), (addr(OUTSIDE, 77), addr(INSIDE, 77)));
+    }
+
+    #[test]
+    fn test_expiry() {
+        let mut nat = masquerade();
+        let (inside, outside) = (addr(INSIDE, 1234), addr(OUTSIDE, 53));
+        let mut packet = udp(inside, outside);
+        nat.outbound(&mut packet, NAT_ADDR, 0);
+        let mapped = check(&packet).0;
+        let mut reply = udp(outside, mapped);
+        assert!(!nat.inbound(&mut reply.clone(), UDP_TIMEOUT));
+        assert!(nat.inbound(&mut reply, UDP_TIMEOUT - 1));
+        // replied connections last longer
+        nat.expire(UDP_TIMEOUT + UDP_STREAM_TIMEOUT - 2);
+        assert_eq!(nat.num_connections(), 1);
+        nat.expire(UDP_TIMEOUT + UDP_STREAM_TIMEOUT - 1);
+        assert_eq!(nat.num_connections(), 0);
+        assert!(nat.ports.is_empty());
+
+        // established TCP connections last until they are closed
+        let inside = addr(INSIDE, 2000);
+        nat.outbound(&mut tcp(inside, outside, 0), NAT_ADDR, 0);
+        let mapped = addr(NAT_ADDR.0, nat.next_port - 1);
+        assert!(nat.inbound(&mut tcp(outside, mapped, 0), 0));
+        nat.expire(TCP_TRANSITORY_TIMEOUT);
+        assert_eq!(nat.num_connections(), 1);
+        nat.outbound(&mut tcp(inside, outside, TCP_FIN), NAT_ADDR, 1);
+        nat.expire(TCP_TRANSITORY_TIMEOUT);
+        assert_eq!(nat.num_connections(), 1);
+        nat.expire(TCP_TRANSITORY_TIMEOUT + 1);
+        assert_eq!(nat.num_connections(), 0);
+    }
+
+    #[test]
+    fn test_icmp_error() {
+        let mut nat = masquerade();
+        let (inside, outside) = (addr(INSIDE, 1234), addr(OUTSIDE, 53));
+        let packets = [
+            (udp(inside, outside), 28),
+            (tcp(inside, outside, 0), 28),
+            (tcp(inside, outside, 0), 40), // quoted with the TCP checksum
+            (echo(ICMP_ECHO_REQUEST, INSIDE, OUTSIDE, 1234), 28),
+        ];
+        for (orig, quote_len) in packets {
+            let mut packet = orig.clone();
+            assert!(nat.outbound(&mut packet, NAT_ADDR, 0));
+            for msg_type in [ICMP_DST_UNREACHABLE, ICMP_TIME_EXCEEDED] {
+                let quote = &packet[..quote_len];
+                let mut error = icmp(msg_type, OUTSIDE, NAT_ADDR.0, [0; 4], quote);
+                assert!(nat.inbound(&mut error, 0));
+                assert_eq!(verify(&error), (OUTSIDE.into(), INSIDE.into()));
+                // the quote is the original packet, with its checksums
+                let quoted = &error[28..];
+                assert!(Ipv4Packet::new_unchecked(quoted).verify_checksum());
+                assert_eq!(quoted, &orig[..quote_len]);
+            }
+        }
+
+        // errors about unknown connections or from other hosts
+        let mut packet = udp(inside, addr([1, 1, 1, 1], 53));
+        nat.outbound(&mut packet, NAT_ADDR, 0);
+        let quote = &packet[..28];
+        let mut error = icmp(ICMP_DST_UNREACHABLE, OUTSIDE, NAT_ADDR.0, [0; 4], quote);
+        let mut other = error.clone();
+        assert!(nat.inbound(&mut error, 0)); // the router on the way
+        other[28 + 20..28 + 22].copy_from_slice(&0x9000u16.to_be_bytes());
+        fill_checksums(&mut other);
+        assert!(!nat.inbound(&mut other, 0));
+        let mut truncated = icmp(
+            ICMP_DST_UNREACHABLE,
+            OUTSIDE,
+            NAT_ADDR.0,
+            [0; 4],
+            &quote[..27],
+        );
+        assert!(!nat.inbound(&mut truncated, 0));
+    }
+}
@@ -1,6 +1,8 @@
 use core::cell::UnsafeCell;
 use core::net::SocketAddr;
-use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use core::task::{ready, Context, Poll};
+use core::time::Duration;
 
 use axerrno::{ax_err, ax_err_type, AxError, AxResult};
 use axio::PollState;
@@ -11,6 +13,7 @@ use smoltcp::socket::tcp::{self, ConnectError, State};
 use smoltcp::wire::{IpEndpoint, IpListenEndpoint};
 
 use super::addr::{from_core_sockaddr, into_core_sockaddr, is_unspecified, UNSPECIFIED_ENDPOINT};
+use super::listen_table::ListenStats;
 use super::{SocketSetWrapper, ETH0, LISTEN_TABLE, SOCKET_SET};
 
 // State transitions:
@@ -41,6 +44,8 @@ pub struct TcpSocket {
     local_addr: UnsafeCell<IpEndpoint>,
     peer_addr: UnsafeCell<IpEndpoint>,
     nonblock: AtomicBool,
+    /// Timeout of blocking receives in nanoseconds, or 0 for none.
+    recv_timeout: AtomicU64,
 }
 
 unsafe impl Sync for TcpSocket {}
@@ -54,6 +59,7 @@ impl TcpSocket {
             local_addr: UnsafeCell::new(UNSPECIFIED_ENDPOINT),
             peer_addr: UnsafeCell::new(UNSPECIFIED_ENDPOINT),
             nonblock: AtomicBool::new(false),
+            recv_timeout: AtomicU64::new(0),
         }
     }
 
@@ -69,6 +75,7 @@ impl TcpSocket {
             local_addr: UnsafeCell::new(local_addr),
             peer_addr: UnsafeCell::new(peer_addr),
             nonblock: AtomicBool::new(false),
+            recv_timeout: AtomicU64::new(0),
         }
     }
 
@@ -115,6 +122,34 @@ impl TcpSocket {
         self.nonblock.store(nonblocking, Ordering::Release);
     }
 
+    /// Returns the timeout of blocking receives, or `None` if they wait
+    /// forever.
+    pub fn recv_timeout(&self) -> Option<Duration> {
+        match self.recv_timeout.load(Ordering::Acquire) {
+            0 => None,
+            nanos => Some(Duration::from_nanos(nanos)),
+        }
+    }
+
+    /// Sets the timeout of blocking receives, after which they fail with
+    /// [`Err(WouldBlock)`](AxError::WouldBlock), or `None` to wait forever.
+    ///
+    /// A zero timeout is invalid.
+    pub fn set_recv_timeout(&self, timeout: Option<Duration>) -> AxResult {
+        let nanos = match timeout {
+            Some(t) if t.is_zero() => {
+                return ax_err!(
+                    InvalidInput,
+                    "socket set_recv_timeout() failed: zero timeout"
+                )
+            }
+            Some(t) => t.as_nanos().clamp(1, u64::MAX as u128) as u64,
+            None => 0,
+        };
+        self.recv_timeout.store(nanos, Ordering::Release);
+        Ok(())
+    }
+
     /// Connects to the given address and port.
     ///
     /// The local port is generated automatically.
@@ -175,16 +210,21 @@ impl TcpSocket {
 
     /// Binds an unbound socket to the given address and port.
     ///
-    /// If the given port is 0, it generates one automatically.
+    /// If the given port is 0, it generates one automatically. It returns
+    /// [`Err(AddrInUse)`](AxError::AddrInUse) if another socket is listening
+    /// on the same port with the same or a wildcard address.
     ///
     /// It's must be called before [`listen`](Self::listen) and
     /// [`accept`](Self::accept).
     pub fn bind(&self, mut local_addr: SocketAddr) -> AxResult {
         self.update_state(STATE_CLOSED, STATE_CLOSED, || {
-            // TODO: check addr is available
             if local_addr.port() == 0 {
                 local_addr.set_port(get_ephemeral_port()?);
             }
+            let local_endpoint = from_core_sockaddr(local_addr);
+            if !LISTEN_TABLE.can_listen_on(listen_endpoint_of(local_endpoint)) {
+                return ax_err!(AddrInUse, "socket bind() failed");
+            }
             // SAFETY: no other threads can read or write `self.local_addr` as we
             // have changed the state to `BUSY`.
             unsafe {
@@ -192,7 +232,7 @@ impl TcpSocket {
                 if old != UNSPECIFIED_ENDPOINT {
                     return ax_err!(InvalidInput, "socket bind() failed: already bound");
                 }
-                self.local_addr.get().write(from_core_sockaddr(local_addr));
+                self.local_addr.get().write(local_endpoint);
             }
             Ok(())
         })
@@ -201,21 +241,37 @@ impl TcpSocket {
 
     /// Starts listening on the bound address and port.
     ///
+    /// At most `backlog` incoming connections are queued, further connection
+    /// requests are dropped until [`accept`](Self::accept) is called. The
+    /// backlog is clamped to the range `1..=somaxconn`, see
+    /// [`set_somaxconn`](crate::set_somaxconn).
+    ///
     /// It's must be called after [`bind`](Self::bind) and before
     /// [`accept`](Self::accept).
-    pub fn listen(&self) -> AxResult {
+    pub fn listen(&self, backlog: usize) -> AxResult {
         self.update_state(STATE_CLOSED, STATE_LISTENING, || {
             let bound_endpoint = self.bound_endpoint()?;
             unsafe {
                 (*self.local_addr.get()).port = bound_endpoint.port;
             }
-            LISTEN_TABLE.listen(bound_endpoint)?;
-            debug!("TCP socket listening on {}", bound_endpoint);
+            LISTEN_TABLE.listen(bound_endpoint, backlog)?;
+            debug!(
+                "TCP socket listening on {}, backlog = {}",
+                bound_endpoint, backlog
+            );
             Ok(())
         })
         .unwrap_or(Ok(())) // ignore simultaneous `listen`s.
     }
 
+    /// Returns the statistics of the connection queue of a listening socket.
+    pub fn listen_stats(&self) -> AxResult<ListenStats> {
+        if !self.is_listening() {
+            return ax_err!(InvalidInput, "socket listen_stats() failed: not listen");
+        }
+        LISTEN_TABLE.stats(self.listen_endpoint())
+    }
+
     /// Accepts a new connection.
     ///
     /// This function will block the calling thread until a new TCP connection
@@ -227,10 +283,9 @@ impl TcpSocket {
             return ax_err!(InvalidInput, "socket accept() failed: not listen");
         }
 
-        // SAFETY: `self.local_addr` should be initialized after `bind()`.
-        let local_port = unsafe { self.local_addr.get().read().port };
+        let listen_endpoint = self.listen_endpoint();
         self.block_on(|| {
-            let (handle, (local_addr, peer_addr)) = LISTEN_TABLE.accept(local_port)?;
+            let (handle, (local_addr, peer_addr)) = LISTEN_TABLE.accept(listen_endpoint)?;
             debug!("TCP socket accepted a new connection {}", peer_addr);
             Ok(TcpSocket::new_connected(handle, local_addr, peer_addr))
         })
@@ -255,11 +310,9 @@ impl TcpSocket {
 
         // listener
         self.update_state(STATE_LISTENING, STATE_CLOSED, || {
-            // SAFETY: `self.local_addr` should be initialized in a listening socket,
-            // and no other threads can read or write it.
-            let local_port = unsafe { self.local_addr.get().read().port };
+            let listen_endpoint = self.listen_endpoint();
             unsafe { self.local_addr.get().write(UNSPECIFIED_ENDPOINT) }; // clear bound address
-            LISTEN_TABLE.unlisten(local_port);
+            LISTEN_TABLE.unlisten(listen_endpoint);
             SOCKET_SET.poll_interfaces();
             Ok(())
         })
@@ -277,23 +330,59 @@ impl TcpSocket {
             return ax_err!(NotConnected, "socket recv() failed");
         }
 
+        self.block_on_timeout(self.recv_timeout(), || self.try_recv(buf))
+    }
+
+    /// Transmits data in the given buffer.
+    pub fn send(&self, buf: &[u8]) -> AxResult<usize> {
+        if self.is_connecting() {
+            return Err(AxError::WouldBlock);
+        } else if !self.is_connected() {
+            return ax_err!(NotConnected, "socket send() failed");
+        }
+
+        self.block_on(|| self.try_send(buf))
+    }
+
+    /// Receives data from the socket without copying it out of the socket
+    /// buffer.
+    ///
+    /// `f` is called once with a view of the received data, and returns the
+    /// number of bytes it consumed together with a user-defined value. Bytes
+    /// not consumed stay in the socket for the next call. The view may be
+    /// shorter than the amount of queued data when it wraps around the end of
+    /// the ring buffer. If the connection has been closed by the peer, `f` is
+    /// called with an empty slice.
+    pub fn recv_with<F, R>(&self, f: F) -> AxResult<R>
+    where
+        F: FnOnce(&[u8]) -> (usize, R),
+    {
+        if self.is_connecting() {
+            return Err(AxError::WouldBlock);
+        } else if !self.is_connected() {
+            return ax_err!(NotConnected, "socket recv() failed");
+        }
+
         // SAFETY: `self.handle` should be initialized in a connected socket.
         let handle = unsafe { self.handle.get().read().unwrap() };
-        self.block_on(|| {
+        let mut f = Some(f);
+        self.block_on_timeout(self.recv_timeout(), || {
             SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
                 if !socket.is_active() {
                     // not open
                     ax_err!(ConnectionRefused, "socket recv() failed")
                 } else if !socket.may_recv() {
                     // connection closed
-                    Ok(0)
+                    Ok(f.take().unwrap()(&[]).1)
                 } else if socket.recv_queue() > 0 {
                     // data available
-                    // TODO: use socket.recv(|buf| {...})
-                    let len = socket
-                        .recv_slice(buf)
-                        .map_err(|_| ax_err_type!(BadState, "socket recv() failed"))?;
-                    Ok(len)
+                    let f = f.take().unwrap();
+                    socket
+                        .recv(|buf| {
+                            let (len, ret) = f(buf);
+                            (len.min(buf.len()), ret)
+                        })
+                        .map_err(|_| ax_err_type!(BadState, "socket recv() failed"))
                 } else {
                     // no more data
                     Err(AxError::WouldBlock)
@@ -302,8 +391,15 @@ impl TcpSocket {
         })
     }
 
-    /// Transmits data in the given buffer.
-    pub fn send(&self, buf: &[u8]) -> AxResult<usize> {
+    /// Transmits data by writing it directly into the socket buffer.
+    ///
+    /// `f` is called once with the free space of the transmit buffer, and
+    /// returns the number of bytes it filled together with a user-defined
+    /// value.
+    pub fn send_with<F, R>(&self, f: F) -> AxResult<R>
+    where
+        F: FnOnce(&mut [u8]) -> (usize, R),
+    {
         if self.is_connecting() {
             return Err(AxError::WouldBlock);
         } else if !self.is_connected() {
@@ -312,6 +408,7 @@ impl TcpSocket {
 
         // SAFETY: `self.handle` should be initialized in a connected socket.
         let handle = unsafe { self.handle.get().read().unwrap() };
+        let mut f = Some(f);
         self.block_on(|| {
             SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
                 if !socket.is_active() || !socket.may_send() {
@@ -319,11 +416,13 @@ impl TcpSocket {
                     ax_err!(ConnectionReset, "socket send() failed")
                 } else if socket.can_send() {
                     // connected, and the tx buffer is not full
-                    // TODO: use socket.send(|buf| {...})
-                    let len = socket
-                        .send_slice(buf)
-                        .map_err(|_| ax_err_type!(BadState, "socket send() failed"))?;
-                    Ok(len)
+                    let f = f.take().unwrap();
+                    socket
+                        .send(|buf| {
+                            let (len, ret) = f(buf);
+                            (len.min(buf.len()), ret)
+                        })
+                        .map_err(|_| ax_err_type!(BadState, "socket send() failed"))
                 } else {
                     // tx buffer is full
                     Err(AxError::WouldBlock)
@@ -332,6 +431,102 @@ impl TcpSocket {
         })
     }
 
+    /// Transmits data gathered from multiple buffers.
+    ///
+    /// The buffers are copied in order straight into the socket buffer, until
+    /// it is full, not handed to the NIC as a scatter-gather list. Returns the
+    /// total number of bytes sent.
+    pub fn send_vectored(&self, bufs: &[&[u8]]) -> AxResult<usize> {
+        self.send_with(|dst| {
+            let len = gather(bufs, dst);
+            (len, len)
+        })
+    }
+
+    /// Polls whether the connection initiated by a nonblocking
+    /// [`connect`](Self::connect) has been established.
+    ///
+    /// If the connection is still in progress, the waker of `cx` is
+    /// registered to be woken when it completes or fails.
+    pub fn poll_connected(&self, cx: &mut Context<'_>) -> Poll<AxResult> {
+        if self.is_connected() {
+            return Poll::Ready(Ok(()));
+        } else if !self.is_connecting() {
+            return Poll::Ready(ax_err!(NotConnected, "socket connect() failed"));
+        }
+        // SAFETY: `self.handle` should be initialized in a connecting socket.
+        let handle = unsafe { self.handle.get().read().unwrap() };
+        poll_io(
+            || {
+                let PollState { writable, .. } = self.poll_connect()?;
+                if !writable {
+                    Err(AxError::WouldBlock)
+                } else if self.get_state() == STATE_CONNECTED {
+                    Ok(())
+                } else {
+                    ax_err!(ConnectionRefused, "socket connect() failed")
+                }
+            },
+            || register_send_waker(handle, cx),
+        )
+    }
+
+    /// Attempts to accept a new connection without blocking.
+    ///
+    /// If no connection is ready, the waker of `cx` is registered to be woken
+    /// when one is established.
+    pub fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<AxResult<TcpSocket>> {
+        if !self.is_listening() {
+            return Poll::Ready(ax_err!(InvalidInput, "socket accept() failed: not listen"));
+        }
+
+        let listen_endpoint = self.listen_endpoint();
+        poll_io(
+            || {
+                let (handle, (local_addr, peer_addr)) = LISTEN_TABLE.accept(listen_endpoint)?;
+                debug!("TCP socket accepted a new connection {}", peer_addr);
+                Ok(TcpSocket::new_connected(handle, local_addr, peer_addr))
+            },
+            || {
+                LISTEN_TABLE
+                    .register_waker(listen_endpoint, cx.waker())
+                    .ok()
+            },
+        )
+    }
+
+    /// Attempts to receive data without blocking.
+    ///
+    /// If no data is available, the waker of `cx` is registered to be woken
+    /// when data arrives or the connection is closed.
+    pub fn poll_recv(&self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<AxResult<usize>> {
+        if self.is_connecting() {
+            ready!(self.poll_connected(cx))?;
+        }
+        if !self.is_connected() {
+            return Poll::Ready(ax_err!(NotConnected, "socket recv() failed"));
+        }
+        // SAFETY: `self.handle` should be initialized in a connected socket.
+        let handle = unsafe { self.handle.get().read().unwrap() };
+        poll_io(|| self.try_recv(buf), || register_recv_waker(handle, cx))
+    }
+
+    /// Attempts to transmit data without blocking.
+    ///
+    /// If the transmit buffer is full, the waker of `cx` is registered to be
+    /// woken when there is space again.
+    pub fn poll_send(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<AxResult<usize>> {
+        if self.is_connecting() {
+            ready!(self.poll_connected(cx))?;
+        }
+        if !self.is_connected() {
+            return Poll::Ready(ax_err!(NotConnected, "socket send() failed"));
+        }
+        // SAFETY: `self.handle` should be initialized in a connected socket.
+        let handle = unsafe { self.handle.get().read().unwrap() };
+        poll_io(|| self.try_send(buf), || register_send_waker(handle, cx))
+    }
+
     /// Whether the socket is readable or writable.
     pub fn poll(&self) -> AxResult<PollState> {
         match self.get_state() {
@@ -402,6 +597,11 @@ impl TcpSocket {
         self.get_state() == STATE_LISTENING
     }
 
+    fn listen_endpoint(&self) -> IpListenEndpoint {
+        // SAFETY: `self.local_addr` should be initialized in a listening socket.
+        listen_endpoint_of(unsafe { self.local_addr.get().read() })
+    }
+
     fn bound_endpoint(&self) -> AxResult<IpListenEndpoint> {
         // SAFETY: no other threads can read or write `self.local_addr`.
         let local_addr = unsafe { self.local_addr.get().read() };
@@ -419,6 +619,49 @@ impl TcpSocket {
         Ok(IpListenEndpoint { addr, port })
     }
 
+    fn try_recv(&self, buf: &mut [u8]) -> AxResult<usize> {
+        // SAFETY: `self.handle` should be initialized in a connected socket.
+        let handle = unsafe { self.handle.get().read().unwrap() };
+        SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+            if !socket.is_active() {
+                // not open
+                ax_err!(ConnectionRefused, "socket recv() failed")
+            } else if !socket.may_recv() {
+                // connection closed
+                Ok(0)
+            } else if socket.recv_queue() > 0 {
+                // data available
+                let len = socket
+                    .recv_slice(buf)
+                    .map_err(|_| ax_err_type!(BadState, "socket recv() failed"))?;
+                Ok(len)
+            } else {
+                // no more data
+                Err(AxError::WouldBlock)
+            }
+        })
+    }
+
+    fn try_send(&self, buf: &[u8]) -> AxResult<usize> {
+        // SAFETY: `self.handle` should be initialized in a connected socket.
+        let handle = unsafe { self.handle.get().read().unwrap() };
+        SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+            if !socket.is_active() || !socket.may_send() {
+                // closed by remote
+                ax_err!(ConnectionReset, "socket send() failed")
+            } else if socket.can_send() {
+                // connected, and the tx buffer is not full
+                let len = socket
+                    .send_slice(buf)
+                    .map_err(|_| ax_err_type!(BadState, "socket send() failed"))?;
+                Ok(len)
+            } else {
+                // tx buffer is full
+                Err(AxError::WouldBlock)
+            }
+        })
+    }
+
     fn poll_connect(&self) -> AxResult<PollState> {
         // SAFETY: `self.handle` should be initialized above.
         let handle = unsafe { self.handle.get().read().unwrap() };
@@ -461,10 +704,8 @@ impl TcpSocket {
     }
 
     fn poll_listener(&self) -> AxResult<PollState> {
-        // SAFETY: `self.local_addr` should be initialized in a listening socket.
-        let local_addr = unsafe { self.local_addr.get().read() };
         Ok(PollState {
-            readable: LISTEN_TABLE.can_accept(local_addr.port)?,
+            readable: LISTEN_TABLE.can_accept(self.listen_endpoint())?,
             writable: false,
         })
     }
@@ -474,20 +715,34 @@ impl TcpSocket {
     /// If the socket is non-blocking, it calls the function once and returns
     /// immediately. Otherwise, it may call the function multiple times if it
     /// returns [`Err(WouldBlock)`](AxError::WouldBlock).
-    fn block_on<F, T>(&self, mut f: F) -> AxResult<T>
+    fn block_on<F, T>(&self, f: F) -> AxResult<T>
+    where
+        F: FnMut() -> AxResult<T>,
+    {
+        self.block_on_timeout(None, f)
+    }
+
+    /// Like [`block_on`](Self::block_on), but gives up with
+    /// [`Err(WouldBlock)`](AxError::WouldBlock) after `timeout`.
+    fn block_on_timeout<F, T>(&self, timeout: Option<Duration>, mut f: F) -> AxResult<T>
     where
         F: FnMut() -> AxResult<T>,
     {
         if self.is_nonblocking() {
-            f()
-        } else {
-            loop {
-                SOCKET_SET.poll_interfaces();
-                match f() {
-                    Ok(t) => return Ok(t),
-                    Err(AxError::WouldBlock) => axtask::yield_now(),
-                    Err(e) => return Err(e),
+            return f();
+        }
+        let deadline = timeout.map(|t| axhal::time::monotonic_time() + t);
+        loop {
+            SOCKET_SET.poll_interfaces();
+            match f() {
+                Ok(t) => return Ok(t),
+                Err(AxError::WouldBlock) => {
+                    if deadline.is_some_and(|d| axhal::time::monotonic_time() >= d) {
+                        return Err(AxError::WouldBlock);
+                    }
+                    axtask::yield_now()
                 }
+                Err(e) => return Err(e),
             }
         }
     }
@@ -503,6 +758,64 @@ impl Drop for TcpSocket {
     }
 }
 
+/// Copies `bufs` in order into `dst` until it is full, returns the number of
+/// bytes copied.
+pub(crate) fn gather(bufs: &[&[u8]], dst: &mut [u8]) -> usize {
+    let mut len = 0;
+    for buf in bufs {
+        let n = buf.len().min(dst.len() - len);
+        dst[len..len + n].copy_from_slice(&buf[..n]);
+        len += n;
+        if len == dst.len() {
+            break;
+        }
+    }
+    len
+}
+
+/// Calls the nonblocking operation `f` on behalf of an async task.
+///
+/// If it would block, `register` is called to register the waker of the
+/// task, then `f` is retried once in case the event has happened in the
+/// meantime.
+///
+/// The interfaces are not polled here, but once per round by the executor,
+/// which wakes the registered wakers, so that pending futures don't poll all
+/// interfaces each time.
+pub(crate) fn poll_io<F, T, R>(mut f: F, register: impl FnOnce() -> R) -> Poll<AxResult<T>>
+where
+    F: FnMut() -> AxResult<T>,
+{
+    match f() {
+        Err(AxError::WouldBlock) => {}
+        res => return Poll::Ready(res),
+    }
+    register();
+    match f() {
+        Err(AxError::WouldBlock) => Poll::Pending,
+        res => Poll::Ready(res),
+    }
+}
+
+fn register_recv_waker(handle: SocketHandle, cx: &Context<'_>) {
+    SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+        socket.register_recv_waker(cx.waker())
+    });
+}
+
+fn register_send_waker(handle: SocketHandle, cx: &Context<'_>) {
+    SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+        socket.register_send_waker(cx.waker())
+    });
+}
+
+fn listen_endpoint_of(endpoint: IpEndpoint) -> IpListenEndpoint {
+    IpListenEndpoint {
+        addr: (!is_unspecified(endpoint.addr)).then_some(endpoint.addr),
+        port: endpoint.port,
+    }
+}
+
 fn get_ephemeral_port() -> AxResult<u16> {
     const PORT_START: u16 = 0xc000;
     const PORT_END: u16 = 0xffff;
@@ -1,5 +1,7 @@
-use core::net::SocketAddr;
+use alloc::vec::Vec;
+use core::net::{Ipv4Addr, SocketAddr};
 use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll};
 
 use axerrno::{ax_err, ax_err_type, AxError, AxResult};
 use axio::PollState;
@@ -8,10 +10,11 @@ use spin::RwLock;
 
 use smoltcp::iface::SocketHandle;
 use smoltcp::socket::udp::{self, BindError, SendError};
-use smoltcp::wire::{IpEndpoint, IpListenEndpoint};
+use smoltcp::wire::{IpEndpoint, IpListenEndpoint, Ipv4Address};
 
 use super::addr::{from_core_sockaddr, into_core_sockaddr, is_unspecified, UNSPECIFIED_ENDPOINT};
-use super::{SocketSetWrapper, SOCKET_SET};
+use super::tcp::{gather, poll_io};
+use super::{SocketSetWrapper, ETH0, SOCKET_SET};
 
 /// A UDP socket that provides POSIX-like APIs.
 pub struct UdpSocket {
@@ -19,6 +22,7 @@ pub struct UdpSocket {
     local_addr: RwLock<Option<IpEndpoint>>,
     peer_addr: RwLock<Option<IpEndpoint>>,
     nonblock: AtomicBool,
+    multicast_groups: Mutex<Vec<Ipv4Address>>,
 }
 
 impl UdpSocket {
@@ -32,6 +36,7 @@ impl UdpSocket {
             local_addr: RwLock::new(None),
             peer_addr: RwLock::new(None),
             nonblock: AtomicBool::new(false),
+            multicast_groups: Mutex::new(Vec::new()),
         }
     }
 
@@ -127,6 +132,57 @@ impl UdpSocket {
         })
     }
 
+    /// Receives a single datagram message on the socket without copying it
+    /// out of the socket buffer.
+    ///
+    /// `f` is called once with a view of the datagram and its origin, and its
+    /// return value is passed through. The datagram is removed from the queue
+    /// afterwards.
+    pub fn recv_from_with<F, R>(&self, f: F) -> AxResult<R>
+    where
+        F: FnOnce(&[u8], SocketAddr) -> R,
+    {
+        let mut f = Some(f);
+        self.recv_impl(|socket| match socket.recv() {
+            Ok((data, meta)) => Ok(f.take().unwrap()(data, into_core_sockaddr(meta.endpoint))),
+            Err(_) => ax_err!(BadState, "socket recv_from() failed"),
+        })
+    }
+
+    /// Sends a single datagram gathered from multiple buffers to the given
+    /// address. On success, returns the number of bytes written.
+    ///
+    /// The buffers are copied straight into the socket buffer.
+    pub fn send_to_vectored(&self, bufs: &[&[u8]], remote_addr: SocketAddr) -> AxResult<usize> {
+        if remote_addr.port() == 0 || remote_addr.ip().is_unspecified() {
+            return ax_err!(InvalidInput, "socket send_to() failed: invalid address");
+        }
+        if self.local_addr.read().is_none() {
+            return ax_err!(NotConnected, "socket send() failed");
+        }
+
+        let remote_endpoint = from_core_sockaddr(remote_addr);
+        let total_len = bufs.iter().map(|buf| buf.len()).sum();
+        self.block_on(|| {
+            SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+                if socket.can_send() {
+                    let dst = socket
+                        .send(total_len, remote_endpoint)
+                        .map_err(|e| match e {
+                            SendError::BufferFull => AxError::WouldBlock,
+                            SendError::Unaddressable => {
+                                ax_err_type!(ConnectionRefused, "socket send() failed")
+                            }
+                        })?;
+                    Ok(gather(bufs, dst))
+                } else {
+                    // tx buffer is full
+                    Err(AxError::WouldBlock)
+                }
+            })
+        })
+    }
+
     /// Connects this UDP socket to a remote address, allowing the `send` and
     /// `recv` to be used to send data and also applies filters to only receive
     /// data from the specified address.
@@ -170,6 +226,34 @@ impl UdpSocket {
         })
     }
 
+    /// Joins the multicast group `multiaddr` on the interface.
+    ///
+    /// Datagrams sent to the group are delivered to sockets bound to the
+    /// unspecified address.
+    pub fn join_multicast_v4(&self, multiaddr: Ipv4Addr) -> AxResult {
+        let addr = Ipv4Address(multiaddr.octets());
+        let mut groups = self.multicast_groups.lock();
+        if groups.contains(&addr) {
+            return ax_err!(AlreadyExists, "socket join_multicast_v4() failed");
+        }
+        ETH0.join_multicast_group(addr)?;
+        groups.push(addr);
+        Ok(())
+    }
+
+    /// Leaves the multicast group `multiaddr` joined by
+    /// [`join_multicast_v4`](Self::join_multicast_v4).
+    pub fn leave_multicast_v4(&self, multiaddr: Ipv4Addr) -> AxResult {
+        let addr = Ipv4Address(multiaddr.octets());
+        let mut groups = self.multicast_groups.lock();
+        let idx = groups
+            .iter()
+            .position(|group| *group == addr)
+            .ok_or_else(|| ax_err_type!(InvalidInput, "socket leave_multicast_v4() failed"))?;
+        groups.swap_remove(idx);
+        ETH0.leave_multicast_group(addr)
+    }
+
     /// Close the socket.
     pub fn shutdown(&self) -> AxResult {
         SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
@@ -180,6 +264,63 @@ impl UdpSocket {
         Ok(())
     }
 
+    /// Attempts to receive a single datagram without blocking.
+    ///
+    /// If no datagram is queued, the waker of `cx` is registered to be woken
+    /// when one arrives.
+    pub fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<AxResult<(usize, SocketAddr)>> {
+        if self.local_addr.read().is_none() {
+            return Poll::Ready(ax_err!(NotConnected, "socket recv_from() failed"));
+        }
+        poll_io(
+            || {
+                self.try_recv(|socket| match socket.recv_slice(buf) {
+                    Ok((len, meta)) => Ok((len, into_core_sockaddr(meta.endpoint))),
+                    Err(_) => ax_err!(BadState, "socket recv_from() failed"),
+                })
+            },
+            || {
+                SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+                    socket.register_recv_waker(cx.waker())
+                })
+            },
+        )
+    }
+
+    /// Attempts to send data to the given address without blocking.
+    ///
+    /// If the transmit buffer is full, the waker of `cx` is registered to be
+    /// woken when there is space again.
+    pub fn poll_send_to(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        remote_addr: SocketAddr,
+    ) -> Poll<AxResult<usize>> {
+        if remote_addr.port() == 0 || remote_addr.ip().is_unspecified() {
+            return Poll::Ready(ax_err!(
+                InvalidInput,
+                "socket send_to() failed: invalid address"
+            ));
+        }
+        if self.local_addr.read().is_none() {
+            return Poll::Ready(ax_err!(NotConnected, "socket send() failed"));
+        }
+        let remote_endpoint = from_core_sockaddr(remote_addr);
+        poll_io(
+            || self.try_send(buf, remote_endpoint),
+            || {
+                SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+                    socket.register_send_waker(cx.waker())
+                })
+            },
+        )
+    }
+
     /// Whether the socket is readable or writable.
     pub fn poll(&self) -> AxResult<PollState> {
         if self.local_addr.read().is_none() {
@@ -211,23 +352,25 @@ impl UdpSocket {
             return ax_err!(NotConnected, "socket send() failed");
         }
 
-        self.block_on(|| {
-            SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
-                if socket.can_send() {
-                    socket
-                        .send_slice(buf, remote_endpoint)
-                        .map_err(|e| match e {
-                            SendError::BufferFull => AxError::WouldBlock,
-                            SendError::Unaddressable => {
-                                ax_err_type!(ConnectionRefused, "socket send() failed")
-                            }
-                        })?;
-                    Ok(buf.len())
-                } else {
-                    // tx buffer is full
-                    Err(AxError::WouldBlock)
-                }
-            })
+        self.block_on(|| self.try_send(buf, remote_endpoint))
+    }
+
+    fn try_send(&self, buf: &[u8], remote_endpoint: IpEndpoint) -> AxResult<usize> {
+        SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+            if socket.can_send() {
+                socket
+                    .send_slice(buf, remote_endpoint)
+                    .map_err(|e| match e {
+                        SendError::BufferFull => AxError::WouldBlock,
+                        SendError::Unaddressable => {
+                            ax_err_type!(ConnectionRefused, "socket send() failed")
+                        }
+                    })?;
+                Ok(buf.len())
+            } else {
+                // tx buffer is full
+                Err(AxError::WouldBlock)
+            }
         })
     }
 
@@ -239,16 +382,21 @@ impl UdpSocket {
             return ax_err!(NotConnected, "socket send() failed");
         }
 
-        self.block_on(|| {
-            SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
-                if socket.can_recv() {
-                    // data available
-                    op(socket)
-                } else {
-                    // no more data
-                    Err(AxError::WouldBlock)
-                }
-            })
+        self.block_on(|| self.try_recv(&mut op))
+    }
+
+    fn try_recv<F, T>(&self, op: F) -> AxResult<T>
+    where
+        F: FnOnce(&mut udp::Socket) -> AxResult<T>,
+    {
+        SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+            if socket.can_recv() {
+                // data available
+                op(socket)
+            } else {
+                // no more data
+                Err(AxError::WouldBlock)
+            }
         })
     }
 
@@ -274,6 +422,9 @@ impl UdpSocket {
 impl Drop for UdpSocket {
     fn drop(&mut self) {
         self.shutdown().ok();
+        for group in self.multicast_groups.get_mut().drain(..) {
+            ETH0.leave_multicast_group(group).ok();
+        }
         SOCKET_SET.remove(self.handle);
     }
 }
@@ -0,0 +1,505 @@
+//! IEEE 802.1Q VLAN sub-interfaces.
+//!
+//! A sub-interface such as `eth0.100` is an IPv4 address of the interface
+//! bound to a VLAN ID. Frames sent from the address are tagged with the VLAN
+//! ID, and tagged frames received on the NIC are untagged and accepted only
+//! if they belong to the sub-interface. Untagged frames belong to the
+//! interface itself.
+//!
+//! Each sub-interface has its own default gateway: packets sent from its
+//! address to other subnets go to its gateway, whose MAC address is learned
+//! from the ARP packets of the gateway on the VLAN, instead of the gateway of
+//! the interface. The sub-interfaces share the neighbor cache of the
+//! interface, so their subnets must not overlap.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::net::{IpAddr, Ipv4Addr};
+
+use axerrno::{ax_err, ax_err_type, AxResult};
+use smoltcp::wire::{EthernetAddress, IpAddress, IpCidr, Ipv4Address, Ipv4Cidr};
+
+use super::ETH0;
+
+/// Tag protocol identifier of 802.1Q.
+const TPID_8021Q: u16 = 0x8100;
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+/// Size of the VLAN tag inserted after the MAC addresses.
+pub(super) const VLAN_TAG_LEN: usize = 4;
+const MAX_VLAN_ID: u16 = 4094;
+
+/// A VLAN sub-interface.
+pub(super) struct VlanInterface {
+    name: String,
+    vid: u16,
+    cidr: Ipv4Cidr,
+    gateway: Option<Ipv4Address>,
+    /// The MAC address of the gateway, once it's learned.
+    gateway_mac: Option<EthernetAddress>,
+    /// The address of the interface itself, which sends the ARP requests for
+    /// the gateway of the interface.
+    parent_ip: Ipv4Address,
+}
+
+/// How to send a frame.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum TxRoute {
+    /// Send the frame untagged.
+    Untagged,
+    /// Send the frame tagged with the VLAN ID.
+    Tagged(u16),
+    /// Drop the frame until the MAC address of the gateway of the
+    /// sub-interface at the index is resolved.
+    Resolve(usize),
+    /// Drop the frame, which has no route.
+    Drop,
+}
+
+/// The configuration of a network interface.
+#[derive(Debug, Clone)]
+pub struct InterfaceConfig {
+    /// The interface name, e.g. `eth0` or `eth0.100`.
+    pub name: String,
+    /// The VLAN ID of a sub-interface.
+    pub vlan_id: Option<u16>,
+    /// The MAC address, which is shared by the sub-interfaces.
+    pub mac: [u8; 6],
+    /// The IPv4 address.
+    pub ip: IpAddr,
+    /// The prefix length of the subnet.
+    pub prefix_len: u8,
+    /// The gateway, if the interface has one.
+    pub gateway: Option<IpAddr>,
+}
+
+fn be16(buf: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*buf.get(pos)?, *buf.get(pos + 1)?]))
+}
+
+fn ipv4_at(buf: &[u8], pos: usize) -> Option<Ipv4Address> {
+    Some(Ipv4Address::from_bytes(buf.get(pos..pos + 4)?))
+}
+
+/// Returns the IPv4 address a frame is sent from.
+fn source_ip(frame: &[u8]) -> Option<Ipv4Address> {
+    match be16(frame, 12)? {
+        ETHERTYPE_IPV4 => ipv4_at(frame, 14 + 12),
+        ETHERTYPE_ARP => ipv4_at(frame, 14 + 14), // sender protocol address
+        _ => None,
+    }
+}
+
+/// Returns the IPv4 address a frame is sent to, with the Ethernet header
+/// starting at `offset`.
+fn target_ip(frame: &[u8], offset: usize) -> Option<Ipv4Address> {
+    let payload = offset + 14;
+    match be16(frame, offset + 12)? {
+        ETHERTYPE_IPV4 => ipv4_at(frame, payload + 16),
+        ETHERTYPE_ARP => ipv4_at(frame, payload + 24), // target protocol address
+        _ => None,
+    }
+}
+
+/// Decides how to send a frame by its source address, and redirects the
+/// frame to the gateway of its sub-interface if it leaves the subnet.
+///
+/// The interface routes all packets to other subnets through its own
+/// gateway, so an ARP request for that gateway sent from the address of a
+/// sub-interface is sent from the interface instead.
+pub(super) fn tx_route(vlans: &[VlanInterface], frame: &mut [u8]) -> TxRoute {
+    let Some(src) = source_ip(frame) else {
+        return TxRoute::Untagged;
+    };
+    let Some(idx) = vlans.iter().position(|v| v.cidr.address() == src) else {
+        return TxRoute::Untagged;
+    };
+    let vlan = &vlans[idx];
+    let on_link = target_ip(frame, 0).is_none_or(|dst| {
+        dst.is_broadcast() || dst.is_multicast() || vlan.cidr.contains_addr(&dst)
+    });
+    if on_link {
+        return TxRoute::Tagged(vlan.vid);
+    }
+    if be16(frame, 12) == Some(ETHERTYPE_ARP) {
+        // sender protocol address
+        frame[14 + 14..14 + 18].copy_from_slice(vlan.parent_ip.as_bytes());
+        return TxRoute::Untagged;
+    }
+    match (vlan.gateway, vlan.gateway_mac) {
+        (None, _) => TxRoute::Drop,
+        (Some(_), None) => TxRoute::Resolve(idx),
+        (Some(_), Some(mac)) => {
+            frame[..6].copy_from_slice(mac.as_bytes());
+            TxRoute::Tagged(vlan.vid)
+        }
+    }
+}
+
+/// Size of an untagged ARP request.
+pub(super) const ARP_REQUEST_LEN: usize = 14 + 28;
+
+/// Builds the untagged ARP request for the gateway of the sub-interface, from
+/// the MAC address `mac`.
+///
+/// Returns the VLAN ID to tag the request with.
+pub(super) fn gateway_request(vlan: &VlanInterface, mac: &[u8], frame: &mut [u8]) -> u16 {
+    let target = vlan.gateway.unwrap_or(Ipv4Address::UNSPECIFIED);
+    frame[..6].fill(0xff);
+    frame[6..12].copy_from_slice(mac);
+    frame[12..14].copy_from_slice(&ETHERTYPE_ARP.to_be_bytes());
+    let arp = &mut frame[14..ARP_REQUEST_LEN];
+    arp[..8].copy_from_slice(&[0, 1, 8, 0, 6, 4, 0, 1]); // Ethernet, IPv4, request
+    arp[8..14].copy_from_slice(mac);
+    arp[14..18].copy_from_slice(vlan.cidr.address().as_bytes());
+    arp[18..24].fill(0);
+    arp[24..28].copy_from_slice(target.as_bytes());
+    vlan.vid
+}
+
+/// Inserts the VLAN tag into the frame in place.
+///
+/// The untagged frame starts at [`VLAN_TAG_LEN`] in `frame`, and the tagged
+/// frame fills all of `frame` afterwards.
+pub(super) fn insert_tag(frame: &mut [u8], vid: u16) {
+    // move the MAC addresses before the tag
+    frame.copy_within(VLAN_TAG_LEN..VLAN_TAG_LEN + 12, 0);
+    frame[12..14].copy_from_slice(&TPID_8021Q.to_be_bytes());
+    frame[14..16].copy_from_slice(&vid.to_be_bytes()); // priority 0
+}
+
+/// Demultiplexes a received frame, and learns the MAC address of the gateway
+/// of a sub-interface from its ARP packets.
+///
+/// Returns `None` if the frame should be dropped, otherwise the offset of
+/// the untagged frame in the buffer.
+pub(super) fn rx_demux(vlans: &mut [VlanInterface], frame: &mut [u8]) -> Option<usize> {
+    if vlans.is_empty() {
+        return Some(0);
+    }
+    if be16(frame, 12)? != TPID_8021Q {
+        // an untagged frame must not reach a sub-interface
+        let target = target_ip(frame, 0);
+        let to_vlan = target.is_some_and(|ip| vlans.iter().any(|v| v.cidr.address() == ip));
+        return (!to_vlan).then_some(0);
+    }
+
+    let vid = be16(frame, 14)? & 0x0fff;
+    let vlan = vlans.iter_mut().find(|v| v.vid == vid)?;
+    if be16(frame, VLAN_TAG_LEN + 12) == Some(ETHERTYPE_ARP) {
+        let payload = VLAN_TAG_LEN + 14;
+        let sender = ipv4_at(frame, payload + 14);
+        if sender.is_some() && sender == vlan.gateway {
+            let mac = EthernetAddress::from_bytes(&frame[payload + 8..payload + 14]);
+            if vlan.gateway_mac != Some(mac) {
+                debug!("{}: gateway is at {}", vlan.name, mac);
+                vlan.gateway_mac = Some(mac);
+            }
+        }
+    }
+    let accepted = match target_ip(frame, VLAN_TAG_LEN) {
+        Some(ip) => {
+            ip == vlan.cidr.address()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || Some(ip) == vlan.cidr.broadcast()
+        }
+        None => true,
+    };
+    if !accepted {
+        return None;
+    }
+    // move the MAC addresses over the tag
+    frame.copy_within(..12, VLAN_TAG_LEN);
+    Some(VLAN_TAG_LEN)
+}
+
+/// Creates the VLAN sub-interface `eth0.<vid>` with the IPv4 address
+/// `ip/prefix_len`.
+///
+/// Packets sent from `ip` to other subnets go through `gateway`, which must
+/// be in the subnet, or are dropped if there is no gateway. The subnet must
+/// not overlap the subnets of the interface and the other sub-interfaces.
+pub fn add_vlan_interface(
+    vid: u16,
+    ip: Ipv4Addr,
+    prefix_len: u8,
+    gateway: Option<Ipv4Addr>,
+) -> AxResult<String> {
+    if vid == 0 || vid > MAX_VLAN_ID {
+        return ax_err!(InvalidInput, "invalid VLAN ID");
+    }
+    if prefix_len > 32 || ip.is_unspecified() || ip.is_multicast() {
+        return ax_err!(InvalidInput, "invalid IP address");
+    }
+    let cidr = Ipv4Cidr::new(Ipv4Address(ip.octets()), prefix_len);
+    let gateway = gateway.map(|gw| Ipv4Address(gw.octets()));
+    if gateway.is_some_and(|gw| !cidr.contains_addr(&gw) || gw == cidr.address()) {
+        return ax_err!(InvalidInput, "gateway not in the subnet");
+    }
+    let name = format!("{}.{}", ETH0.name(), vid);
+
+    let mut dev = ETH0.dev.lock();
+    let mut iface = ETH0.iface.lock();
+    if dev.vlans.iter().any(|v| v.vid == vid) {
+        return ax_err!(AlreadyExists, "VLAN sub-interface already exists");
+    }
+    if iface.has_ip_addr(cidr.address()) {
+        return ax_err!(AddrInUse, "IP address already assigned");
+    }
+    let overlaps = iface.ip_addrs().iter().any(|addr| {
+        let IpCidr::Ipv4(other) = *addr;
+        other.contains_subnet(&cidr) || cidr.contains_subnet(&other)
+    });
+    if overlaps {
+        return ax_err!(InvalidInput, "overlapping subnets");
+    }
+    let Some(IpCidr::Ipv4(parent)) = iface.ip_addrs().first().copied() else {
+        return ax_err!(BadState, "the interface has no address");
+    };
+    let mut full = false;
+    iface.update_ip_addrs(|addrs| full = addrs.push(IpCidr::Ipv4(cidr)).is_err());
+    if full {
+        return ax_err!(NoMemory, "too many IP addresses");
+    }
+
+    info!("created VLAN interface {}:", name);
+    info!("  ip:       {}", cidr);
+    if let Some(gw) = gateway {
+        info!("  gateway:  {}", gw);
+    }
+    dev.vlans.push(VlanInterface {
+        name: name.clone(),
+        vid,
+        cidr,
+        gateway,
+        gateway_mac: None,
+        parent_ip: parent.address(),
+    });
+    Ok(name)
+}
+
+/// Removes the VLAN sub-interface with VLAN ID `vid`, with its address.
+pub fn remove_vlan_interface(vid: u16) -> AxResult {
+    let mut dev = ETH0.dev.lock();
+    let mut iface = ETH0.iface.lock();
+    let idx = dev
+        .vlans
+        .iter()
+        .position(|v| v.vid == vid)
+        .ok_or_else(|| ax_err_type!(NotFound, "no such VLAN sub-interface"))?;
+    let vlan = dev.vlans.remove(idx);
+    iface.update_ip_addrs(|addrs| addrs.retain(|a| *a != IpCidr::Ipv4(vlan.cidr)));
+    info!("removed VLAN interface {}", vlan.name);
+    Ok(())
+}
+
+/// Returns the configuration of the interface and its VLAN sub-interfaces.
+pub fn interfaces() -> Vec<InterfaceConfig> {
+    let dev = ETH0.dev.lock();
+    let mut iface = ETH0.iface.lock();
+    let mut default_gateway = None;
+    iface.routes_mut().update(|routes| {
+        if let Some(r) = routes.iter().find(|r| r.cidr.prefix_len() == 0) {
+            let IpAddress::Ipv4(gw) = r.via_router;
+            default_gateway = Some(gw);
+        }
+    });
+
+    let mut result = Vec::new();
+    for cidr in iface.ip_addrs() {
+        let IpCidr::Ipv4(cidr) = *cidr;
+        let vlan = dev.vlans.iter().find(|v| v.cidr == cidr);
+        let gateway = match vlan {
+            Some(v) => v.gateway,
+            None => default_gateway,
+        };
+        result.push(InterfaceConfig {
+            name: vlan.map_or_else(|| ETH0.name().into(), |v| v.name.clone()),
+            vlan_id: vlan.map(|v| v.vid),
+            mac: ETH0.ethernet_address().0,
+            ip: IpAddr::V4(Ipv4Addr::from(cidr.address().0)),
+            prefix_len: cidr.prefix_len(),
+            gateway: gateway.map(|gw| IpAddr::V4(Ipv4Addr::from(gw.0))),
+        });
+    }
+    result
+}
+
+/// Creates the sub-interfaces in the `<vid>:<ip>/<prefix>[:<gateway>]` list,
+/// separated by commas or spaces.
+pub(super) fn setup_vlans(config: &str) {
+    for entry in config.split([',', ' ']).filter(|s| !s.is_empty()) {
+        let parse = || -> Option<(u16, Ipv4Addr, u8, Option<Ipv4Addr>)> {
+            let mut parts = entry.split(':');
+            let vid = parts.next()?.parse().ok()?;
+            let (ip, prefix) = parts.next()?.split_once('/')?;
+            let gateway = match parts.next() {
+                Some(gw) => Some(gw.parse().ok()?),
+                None => None,
+            };
+            Some((vid, ip.parse().ok()?, prefix.parse().ok()?, gateway))
+        };
+        match parse() {
+            Some((vid, ip, prefix, gateway)) => {
+                if let Err(e) = add_vlan_interface(vid, ip, prefix, gateway) {
+                    warn!("failed to create VLAN interface {:?}: {:?}", entry, e);
+                }
+            }
+            None => warn!("invalid VLAN interface config {:?}", entry),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    const MAC: [u8; 6] = [0x52, 0x54, 0, 0x12, 0x34, 0x56];
+    const PEER_MAC: [u8; 6] = [0x52, 0x54, 0, 0xab, 0xcd, 0xef];
+
+    fn ip(a: u8, b: u8, c: u8, d: u8) -> Ipv4Address {
+        Ipv4Address::new(a, b, c, d)
+    }
+
+    /// `eth0.100` at 10.0.100.2/24 with gateway 10.0.100.1, and `eth0.200`
+    /// at 10.0.200.2/24 without a gateway, on `eth0` at 10.0.2.15.
+    fn vlans() -> Vec<VlanInterface> {
+        let vlan = |vid, cidr, gateway| VlanInterface {
+            name: format!("eth0.{}", vid),
+            vid,
+            cidr,
+            gateway,
+            gateway_mac: None,
+            parent_ip: ip(10, 0, 2, 15),
+        };
+        vec![
+            vlan(
+                100,
+                Ipv4Cidr::new(ip(10, 0, 100, 2), 24),
+                Some(ip(10, 0, 100, 1)),
+            ),
+            vlan(200, Ipv4Cidr::new(ip(10, 0, 200, 2), 24), None),
+        ]
+    }
+
+    fn ipv4_frame(src: Ipv4Address, dst: Ipv4Address) -> Vec<u8> {
+        let mut frame = vec![0; 14 + 20];
+        frame[..6].copy_from_slice(&PEER_MAC);
+        frame[6..12].copy_from_slice(&MAC);
+        frame[12..14].copy_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        frame[14] = 0x45;
+        frame[14 + 12..14 + 16].copy_from_slice(src.as_bytes());
+        frame[14 + 16..14 + 20].copy_from_slice(dst.as_bytes());
+        frame
+    }
+
+    fn arp_frame(sender_mac: &[u8], sender: Ipv4Address, target: Ipv4Address) -> Vec<u8> {
+        let mut frame = vec![0; ARP_REQUEST_LEN];
+        frame[..6].fill(0xff);
+        frame[6..12].copy_from_slice(sender_mac);
+        frame[12..14].copy_from_slice(&ETHERTYPE_ARP.to_be_bytes());
+        frame[14..22].copy_from_slice(&[0, 1, 8, 0, 6, 4, 0, 1]);
+        frame[22..28].copy_from_slice(sender_mac);
+        frame[28..32].copy_from_slice(sender.as_bytes());
+        frame[38..42].copy_from_slice(target.as_bytes());
+        frame
+    }
+
+    /// Tags an untagged frame as the NIC receives it.
+    fn tagged(frame: &[u8], vid: u16) -> Vec<u8> {
+        let mut buf = vec![0; VLAN_TAG_LEN];
+        buf.extend_from_slice(frame);
+        insert_tag(&mut buf, vid);
+        buf
+    }
+
+    #[test]
+    fn test_insert_tag() {
+        let frame = ipv4_frame(ip(10, 0, 100, 2), ip(10, 0, 100, 3));
+        let buf = tagged(&frame, 100);
+        assert_eq!(buf.len(), frame.len() + VLAN_TAG_LEN);
+        assert_eq!(buf[..12], frame[..12]);
+        assert_eq!(buf[12..16], [0x81, 0x00, 0x00, 100]);
+        assert_eq!(buf[16..], frame[12..]);
+        assert_eq!(tagged(&frame, MAX_VLAN_ID)[14..16], [0x0f, 0xfe]);
+    }
+
+    #[test]
+    fn test_rx_demux() {
+        let mut vlans = vlans();
+        // untagged frames belong to the interface itself
+        let mut frame = ipv4_frame(ip(10, 0, 2, 2), ip(10, 0, 2, 15));
+        assert_eq!(rx_demux(&mut vlans, &mut frame), Some(0));
+        let mut frame = ipv4_frame(ip(10, 0, 100, 3), ip(10, 0, 100, 2));
+        assert_eq!(rx_demux(&mut vlans, &mut frame), None);
+        assert_eq!(rx_demux(&mut [], &mut frame), Some(0));
+
+        // tagged frames are untagged in place
+        let untagged = ipv4_frame(ip(10, 0, 100, 3), ip(10, 0, 100, 2));
+        let mut frame = tagged(&untagged, 100);
+        assert_eq!(rx_demux(&mut vlans, &mut frame), Some(VLAN_TAG_LEN));
+        assert_eq!(frame[VLAN_TAG_LEN..], untagged[..]);
+        // broadcasts of the VLAN
+        let mut frame = tagged(&ipv4_frame(ip(10, 0, 100, 3), ip(10, 0, 100, 255)), 100);
+        assert_eq!(rx_demux(&mut vlans, &mut frame), Some(VLAN_TAG_LEN));
+        // to the address of another VLAN, or an unknown VLAN
+        let mut frame = tagged(&ipv4_frame(ip(10, 0, 200, 3), ip(10, 0, 200, 2)), 100);
+        assert_eq!(rx_demux(&mut vlans, &mut frame), None);
+        let mut frame = tagged(&ipv4_frame(ip(10, 0, 100, 3), ip(10, 0, 100, 2)), 300);
+        assert_eq!(rx_demux(&mut vlans, &mut frame), None);
+        // truncated
+        assert_eq!(rx_demux(&mut vlans, &mut [0; 13]), None);
+
+        // the MAC address of the gateway is learned from its ARP packets on
+        // the VLAN
+        let arp = arp_frame(&PEER_MAC, ip(10, 0, 100, 1), ip(10, 0, 100, 9));
+        let mut frame = tagged(&arp, 200);
+        rx_demux(&mut vlans, &mut frame);
+        assert_eq!(vlans[0].gateway_mac, None);
+        let mut frame = tagged(&arp, 100);
+        assert_eq!(rx_demux(&mut vlans, &mut frame), None);
+        assert_eq!(vlans[0].gateway_mac, Some(EthernetAddress(PEER_MAC)));
+    }
+
+    #[test]
+    fn test_tx_route() {
+        let mut vlans = vlans();
+        // from the interface itself
+        let mut frame = ipv4_frame(ip(10, 0, 2, 15), ip(1, 1, 1, 1));
+        assert_eq!(tx_route(&vlans, &mut frame), TxRoute::Untagged);
+        let mut frame = vec![0; 12];
+        assert_eq!(tx_route(&vlans, &mut frame), TxRoute::Untagged);
+
+        // in the subnet of the sub-interface
+        let mut frame = ipv4_frame(ip(10, 0, 100, 2), ip(10, 0, 100, 3));
+        assert_eq!(tx_route(&vlans, &mut frame), TxRoute::Tagged(100));
+        let mut frame = ipv4_frame(ip(10, 0, 200, 2), ip(255, 255, 255, 255));
+        assert_eq!(tx_route(&vlans, &mut frame), TxRoute::Tagged(200));
+        let mut frame = arp_frame(&MAC, ip(10, 0, 100, 2), ip(10, 0, 100, 1));
+        assert_eq!(tx_route(&vlans, &mut frame), TxRoute::Tagged(100));
+
+        // to other subnets, through the gateway of the sub-interface
+        let mut frame = ipv4_frame(ip(10, 0, 100, 2), ip(1, 1, 1, 1));
+        assert_eq!(tx_route(&vlans, &mut frame), TxRoute::Resolve(0));
+        let mut request = vec![0; ARP_REQUEST_LEN];
+        assert_eq!(gateway_request(&vlans[0], &MAC, &mut request), 100);
+        assert_eq!(
+            request,
+            arp_frame(&MAC, ip(10, 0, 100, 2), ip(10, 0, 100, 1))
+        );
+
+        vlans[0].gateway_mac = Some(EthernetAddress([2; 6]));
+        assert_eq!(tx_route(&vlans, &mut frame), TxRoute::Tagged(100));
+        assert_eq!(frame[..6], [2; 6]);
+        let mut frame = ipv4_frame(ip(10, 0, 200, 2), ip(1, 1, 1, 1));
+        assert_eq!(tx_route(&vlans, &mut frame), TxRoute::Drop);
+
+        // the gateway of the interface is resolved from the interface
+        let mut frame = arp_frame(&MAC, ip(10, 0, 100, 2), ip(10, 0, 2, 2));
+        assert_eq!(tx_route(&vlans, &mut frame), TxRoute::Untagged);
+        assert_eq!(frame, arp_frame(&MAC, ip(10, 0, 2, 15), ip(10, 0, 2, 2)));
+    }
+}
@@ -0,0 +1,5 @@
+fn main() {
+    // Board-specific devices are enabled by `platform_family`, as in `axhal`.
+    println!("cargo:rustc-cfg=platform_family=\"{}\"", axconfig::FAMILY);
+    println!("cargo::rustc-check-cfg=cfg(platform_family, values(any()))");
+}
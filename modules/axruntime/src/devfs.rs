@@ -0,0 +1,344 @@
+//! Device files of the probed devices in `/dev`.
+//!
+//! Block devices are registered by `axfs` itself.
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use axerrno::AxResult;
+use axfs::devfs::DeviceOps;
+
+/// Registers the console, the display and the platform devices.
+pub(crate) fn register_devices() {
+    register_char("ttyS0", Arc::new(Console));
+    #[cfg(feature = "display")]
+    register_char("fb0", Arc::new(fb::Framebuffer));
+    #[cfg(platform_family = "aarch64-phytium-pi")]
+    phytium::register();
+}
+
+fn register_char(path: &str, ops: Arc<dyn DeviceOps>) {
+    if let Err(e) = axfs::devfs::register_char(path, ops) {
+        warn!("failed to add /dev/{}: {:?}", path, e);
+    }
+}
+
+fn wait() {
+    #[cfg(feature = "multitask")]
+    axtask::yield_now();
+    #[cfg(not(feature = "multitask"))]
+    core::hint::spin_loop();
+}
+
+/// `/dev/ttyS0`, the console.
+struct Console;
+
+impl DeviceOps for Console {
+    /// Blocks until at least one byte is read.
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let mut read_len = 0;
+            while read_len < buf.len() {
+                match axhal::console::getchar() {
+                    Some(c) => {
+                        buf[read_len] = c;
+                        read_len += 1;
+                    }
+                    None => break,
+                }
+            }
+            if read_len > 0 {
+                return Ok(read_len);
+            }
+            wait();
+        }
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> AxResult<usize> {
+        axhal::console::write_bytes(buf);
+        Ok(buf.len())
+    }
+}
+
+/// `/dev/fb0`, the framebuffer of the main display.
+///
+/// Writes are shown on the screen immediately. The screen information can be
+/// queried with `FBIOGET_VSCREENINFO` and `FBIOGET_FSCREENINFO`.
+#[cfg(feature = "display")]
+mod fb {
+    use super::*;
+    use axerrno::ax_err;
+    use axfs::devfs::write_arg;
+
+    const FBIOGET_VSCREENINFO: u32 = 0x4600;
+    const FBIOGET_FSCREENINFO: u32 = 0x4602;
+
+    const FB_TYPE_PACKED_PIXELS: u32 = 0;
+    const FB_VISUAL_TRUECOLOR: u32 = 2;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct FbBitfield {
+        offset: u32,
+        length: u32,
+        msb_right: u32,
+    }
+
+    /// `struct fb_var_screeninfo` of Linux.
+    #[repr(C)]
+    #[derive(Default)]
+    struct FbVarScreenInfo {
+        xres: u32,
+        yres: u32,
+        xres_virtual: u32,
+        yres_virtual: u32,
+        xoffset: u32,
+        yoffset: u32,
+        bits_per_pixel: u32,
+        grayscale: u32,
+        red: FbBitfield,
+        green: FbBitfield,
+        blue: FbBitfield,
+        transp: FbBitfield,
+        nonstd: u32,
+        activate: u32,
+        height: u32,
+        width: u32,
+        accel_flags: u32,
+        timings: [u32; 10],
+        colorspace: u32,
+        reserved: [u32; 4],
+    }
+
+    /// `struct fb_fix_screeninfo` of Linux.
+    #[repr(C)]
+    #[derive(Default)]
+    struct FbFixScreenInfo {
+        id: [u8; 16],
+        smem_start: usize,
+        smem_len: u32,
+        type_: u32,
+        type_aux: u32,
+        visual: u32,
+        xpanstep: u16,
+        ypanstep: u16,
+        ywrapstep: u16,
+        line_length: u32,
+        mmio_start: usize,
+        mmio_len: u32,
+        accel: u32,
+        capabilities: u16,
+        reserved: [u16; 2],
+    }
+
+    pub struct Framebuffer;
+
+    impl Framebuffer {
+        /// Returns the address and the size of the framebuffer memory.
+        ///
+        /// It's also mapped by the display device, so it's only accessed by
+        /// raw copies rather than through references.
+        fn mem(&self) -> (*mut u8, usize) {
+            let info = axdisplay::framebuffer_info();
+            (info.fb_base_vaddr as *mut u8, info.fb_size)
+        }
+
+        fn var_info(&self) -> FbVarScreenInfo {
+            let info = axdisplay::framebuffer_info();
+            let bpp = (info.fb_size / (info.width * info.height).max(1) as usize * 8) as u32;
+            // BGRA with 8 bits for each channel, as virtio-gpu
+            let channel = |offset| FbBitfield {
+                offset,
+                length: 8,
+                msb_right: 0,
+            };
+            FbVarScreenInfo {
+                xres: info.width,
+                yres: info.height,
+                xres_virtual: info.width,
+                yres_virtual: info.height,
+                bits_per_pixel: bpp,
+                red: channel(16),
+                green: channel(8),
+                blue: channel(0),
+                transp: channel(24),
+                ..Default::default()
+            }
+        }
+
+        fn fix_info(&self) -> FbFixScreenInfo {
+            let info = axdisplay::framebuffer_info();
+            let mut id = [0; 16];
+            id[..6].copy_from_slice(b"axfb0\0");
+            FbFixScreenInfo {
+                id,
+                smem_start: info.fb_base_vaddr,
+                smem_len: info.fb_size as u32,
+                type_: FB_TYPE_PACKED_PIXELS,
+                visual: FB_VISUAL_TRUECOLOR,
+                line_length: (info.fb_size / info.height.max(1) as usize) as u32,
+                ..Default::default()
+            }
+        }
+    }
+
+    impl DeviceOps for Framebuffer {
+        fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+            let (base, size) = self.mem();
+            let start = size.min(offset as usize);
+            let len = buf.len().min(size - start);
+            // SAFETY: `start + len` is within the framebuffer memory, which
+            // is mapped for the lifetime of the kernel and never overlaps
+            // `buf`.
+            unsafe { core::ptr::copy_nonoverlapping(base.add(start), buf.as_mut_ptr(), len) };
+            Ok(len)
+        }
+
+        fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize> {
+            let (base, size) = self.mem();
+            let start = size.min(offset as usize);
+            let len = buf.len().min(size - start);
+            if len == 0 && !buf.is_empty() {
+                return ax_err!(StorageFull);
+            }
+            // SAFETY: as in `read_at`.
+            unsafe { core::ptr::copy_nonoverlapping(buf.as_ptr(), base.add(start), len) };
+            axdisplay::framebuffer_flush();
+            Ok(len)
+        }
+
+        fn ioctl(&self, cmd: u32, arg: usize) -> AxResult<usize> {
+            match cmd {
+                // SAFETY: `arg` points to a `struct fb_var_screeninfo`.
+                FBIOGET_VSCREENINFO => unsafe { write_arg(arg, self.var_info()) },
+                // SAFETY: `arg` points to a `struct fb_fix_screeninfo`.
+                FBIOGET_FSCREENINFO => unsafe { write_arg(arg, self.fix_info()) },
+                _ => ax_err!(Unsupported),
+            }
+        }
+
+        fn flush(&self) -> AxResult {
+            axdisplay::framebuffer_flush();
+            Ok(())
+        }
+
+        fn size(&self) -> u64 {
+            axdisplay::framebuffer_info().fb_size as u64
+        }
+    }
+}
+
+/// The UART and the I2C controller on the Phytium Pi:
+///
+/// - `/dev/ttyS1`: UART2 at 115200 baud.
+/// - `/dev/i2c-1`: the I2C master on MIO1. The address of the slave is set
+///   by the `I2C_SLAVE` request as in Linux, and the offset of reads and
+///   writes is the register address in the slave.
+#[cfg(platform_family = "aarch64-phytium-pi")]
+mod phytium {
+    use super::*;
+    use alloc::vec::Vec;
+    use axerrno::ax_err;
+    use axhal::misc::example::{fi2c_master_read, fi2c_master_write, fi2c_mio_master_init};
+    use axhal::misc::UART2;
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    const UART_CLOCK_HZ: u32 = 100_000_000;
+    const UART_BAUD_RATE: u32 = 115200;
+
+    const I2C_SLAVE: u32 = 0x0703;
+    const I2C_SLAVE_FORCE: u32 = 0x0706;
+    const I2C_SPEED_RATE: u32 = 100_000;
+    /// The registers of a slave are addressed by one byte.
+    const I2C_REG_SPACE: u64 = 256;
+
+    pub fn register() {
+        UART2.lock().init_no_irq(UART_CLOCK_HZ, UART_BAUD_RATE);
+        register_char("ttyS1", Arc::new(Uart2));
+        register_char("i2c-1", Arc::new(I2c1::default()));
+    }
+
+    struct Uart2;
+
+    impl DeviceOps for Uart2 {
+        /// Blocks until a byte is read, and returns it.
+        fn read_at(&self, _offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+            match buf.first_mut() {
+                Some(b) => {
+                    *b = UART2.lock().read_byte_poll();
+                    Ok(1)
+                }
+                None => Ok(0),
+            }
+        }
+
+        fn write_at(&self, _offset: u64, buf: &[u8]) -> AxResult<usize> {
+            let mut uart = UART2.lock();
+            for &b in buf {
+                uart.put_byte_poll(b);
+            }
+            Ok(buf.len())
+        }
+    }
+
+    #[derive(Default)]
+    struct I2c1 {
+        /// The address of the slave, or 0 if not set.
+        slave: AtomicU32,
+    }
+
+    impl I2c1 {
+        /// Returns the length of the access within the registers.
+        fn check(&self, offset: u64, len: usize) -> AxResult<u32> {
+            if self.slave.load(Ordering::Acquire) == 0 {
+                return ax_err!(BadState);
+            }
+            if offset >= I2C_REG_SPACE {
+                return ax_err!(InvalidInput);
+            }
+            Ok((len as u64).min(I2C_REG_SPACE - offset) as u32)
+        }
+    }
+
+    impl DeviceOps for I2c1 {
+        fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+            let len = self.check(offset, buf.len())?;
+            if len == 0 {
+                return Ok(0);
+            }
+            if !unsafe { fi2c_master_read(buf, len, offset as u32) } {
+                return ax_err!(Io);
+            }
+            Ok(len as usize)
+        }
+
+        fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize> {
+            let len = self.check(offset, buf.len())?;
+            let mut data: Vec<u8> = buf[..len as usize].into();
+            if len > 0 && !unsafe { fi2c_master_write(&mut data, len, offset as u32) } {
+                return ax_err!(Io);
+            }
+            Ok(len as usize)
+        }
+
+        fn ioctl(&self, cmd: u32, arg: usize) -> AxResult<usize> {
+            match cmd {
+                I2C_SLAVE | I2C_SLAVE_FORCE => {
+                    let addr = arg as u32;
+                    if addr == 0 || addr > 0x7f {
+                        return ax_err!(InvalidInput);
+                    }
+                    if !unsafe { fi2c_mio_master_init(addr, I2C_SPEED_RATE) } {
+                        return ax_err!(Io);
+                    }
+                    self.slave.store(addr, Ordering::Release);
+                    Ok(0)
+                }
+                _ => ax_err!(Unsupported),
+            }
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! The initial RAM disk loaded by the bootloader, found in the `/chosen` node
+//! of the device tree and used as the initramfs.
+
+use core::ops::Range;
+
+use axdriver::{prelude::AxBlockDevice, AxDeviceContainer};
+use axhal::mem::{memory_regions, phys_to_virt, MemoryAddr, PhysAddr};
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_HEADER_SIZE: usize = 40;
+
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+
+/// Returns the physical address range of the initrd, given by the
+/// `linux,initrd-start` and `linux,initrd-end` properties of `/chosen` in the
+/// device tree at `dtb`.
+///
+/// It must be called before the memory of the device tree and the initrd is
+/// given to the allocator.
+pub(crate) fn find(dtb: usize) -> Option<Range<usize>> {
+    if dtb == 0 {
+        return None;
+    }
+    let base = phys_to_virt(dtb.into()).as_ptr();
+    let header = unsafe { core::slice::from_raw_parts(base, FDT_HEADER_SIZE) };
+    if be32(header, 0)? != FDT_MAGIC {
+        return None;
+    }
+    let fdt = unsafe { core::slice::from_raw_parts(base, be32(header, 4)? as usize) };
+    let initrd = chosen_initrd(fdt)?;
+    let in_memory = memory_regions()
+        .any(|r| r.paddr.as_usize() <= initrd.start && initrd.end <= (r.paddr + r.size).as_usize());
+    if !in_memory {
+        warn!("initrd at {:#x?} is out of the memory, ignored", initrd);
+        return None;
+    }
+    info!("Found initrd at [{:#x}, {:#x}).", initrd.start, initrd.end);
+    Some(initrd)
+}
+
+/// Initializes filesystems with the initrd as the initramfs if there is one,
+/// then gives its memory to the allocator, as it's unpacked to the ramfs.
+pub(crate) fn init_filesystems(
+    blk_devs: AxDeviceContainer<AxBlockDevice>,
+    initrd: Option<Range<usize>>,
+) {
+    let Some(initrd) = initrd else {
+        axfs::init_filesystems(blk_devs);
+        return;
+    };
+    let archive = unsafe {
+        core::slice::from_raw_parts(phys_to_virt(initrd.start.into()).as_ptr(), initrd.len())
+    };
+    axfs::init_filesystems_with_initramfs(blk_devs, archive);
+
+    let start = PhysAddr::from(initrd.start).align_up_4k();
+    let end = PhysAddr::from(initrd.end).align_down_4k();
+    if start < end {
+        let size = end.as_usize() - start.as_usize();
+        axalloc::global_add_memory(phys_to_virt(start).as_usize(), size)
+            .expect("add initrd memory to heap failed");
+    }
+}
+
+/// Finds the initrd properties of `/chosen` in the structure block.
+fn chosen_initrd(fdt: &[u8]) -> Option<Range<usize>> {
+    let strings = be32(fdt, 12)? as usize;
+    let mut pos = be32(fdt, 8)? as usize;
+    let (mut start, mut end) = (None, None);
+    let mut depth = 0usize;
+    let mut in_chosen = false;
+    loop {
+        let token = be32(fdt, pos)?;
+        pos += 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                let name = cstr(fdt, pos)?;
+                pos = align4(pos + name.len() + 1);
+                depth += 1;
+                if depth == 2 {
+                    in_chosen = name == b"chosen";
+                }
+            }
+            FDT_END_NODE => {
+                if in_chosen && depth == 2 {
+                    break;
+                }
+                depth = depth.checked_sub(1)?;
+            }
+            FDT_PROP => {
+                let len = be32(fdt, pos)? as usize;
+                let name = cstr(fdt, strings + be32(fdt, pos + 4)? as usize)?;
+                let value = fdt.get(pos + 8..pos + 8 + len)?;
+                pos = align4(pos + 8 + len);
+                if in_chosen && depth == 2 {
+                    match name {
+                        b"linux,initrd-start" => start = cells(value),
+                        b"linux,initrd-end" => end = cells(value),
+                        _ => {}
+                    }
+                }
+            }
+            FDT_NOP => {}
+            _ => break, // `FDT_END`
+        }
+    }
+    let (start, end) = (start?, end?);
+    (start < end).then_some(start..end)
+}
+
+fn be32(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+/// A property value of one or two cells.
+fn cells(value: &[u8]) -> Option<usize> {
+    match value.len() {
+        4 => be32(value, 0).map(|v| v as usize),
+        8 => Some(u64::from_be_bytes(value.try_into().ok()?) as usize),
+        _ => None,
+    }
+}
+
+fn cstr(data: &[u8], pos: usize) -> Option<&[u8]> {
+    let s = data.get(pos..)?;
+    Some(&s[..s.iter().position(|&b| b == 0)?])
+}
+
+const fn align4(pos: usize) -> usize {
+    (pos + 3) & !3
+}
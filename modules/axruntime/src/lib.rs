@@ -28,6 +28,15 @@ mod lang_items;
 #[cfg(feature = "smp")]
 mod mp;
 
+#[cfg(feature = "fs")]
+mod devfs;
+#[cfg(feature = "initramfs")]
+mod initrd;
+#[cfg(all(feature = "fs", feature = "net"))]
+mod procfs;
+#[cfg(feature = "fs")]
+mod sysfs;
+
 #[cfg(feature = "smp")]
 pub use self::mp::rust_main_secondary;
 
@@ -141,8 +150,14 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
         );
     }
 
-    #[cfg(feature = "alloc")]
-    init_allocator();
+    // found before the allocator takes its memory
+    #[cfg(feature = "initramfs")]
+    let initrd = self::initrd::find(dtb);
+
+    #[cfg(all(feature = "alloc", not(feature = "initramfs")))]
+    init_allocator(None);
+    #[cfg(feature = "initramfs")]
+    init_allocator(initrd.clone());
 
     #[cfg(feature = "paging")]
     axmm::init_memory_management();
@@ -158,14 +173,31 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
         #[allow(unused_variables)]
         let all_devices = axdriver::init_drivers();
 
-        #[cfg(feature = "fs")]
+        #[cfg(all(feature = "fs", not(feature = "initramfs")))]
         axfs::init_filesystems(all_devices.block);
+        #[cfg(feature = "initramfs")]
+        self::initrd::init_filesystems(all_devices.block, initrd);
+        #[cfg(feature = "fs")]
+        axhal::misc::set_terminate_hook(|| {
+            axfs::api::sync().ok(); // write back the block caches
+        });
 
         #[cfg(feature = "net")]
         axnet::init_network(all_devices.net);
+        #[cfg(all(feature = "net", feature = "multitask"))]
+        axtask::executor::set_poll_hook(axnet::poll_interfaces); // drive socket wakers
+        #[cfg(all(feature = "fs", feature = "net"))]
+        {
+            load_resolver_config();
+            self::procfs::publish_files();
+        }
+        #[cfg(feature = "fs")]
+        self::sysfs::publish_attrs();
 
         #[cfg(feature = "display")]
         axdisplay::init_display(all_devices.display);
+        #[cfg(feature = "fs")]
+        self::devfs::register_devices();
     }
 
     #[cfg(feature = "smp")]
@@ -201,35 +233,63 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
     }
 }
 
+/// Gives the free memory regions to the allocator, except the `reserved`
+/// physical address range.
 #[cfg(feature = "alloc")]
-fn init_allocator() {
-    use axhal::mem::{memory_regions, phys_to_virt, MemRegionFlags};
+fn init_allocator(reserved: Option<core::ops::Range<usize>>) {
+    use axhal::mem::{memory_regions, phys_to_virt, MemRegionFlags, MemoryAddr, PhysAddr};
 
     info!("Initialize global memory allocator...");
     info!("  use {} allocator.", axalloc::global_allocator().name());
 
+    let reserved = reserved.map_or(0..0, |r| {
+        let start = PhysAddr::from(r.start).align_down_4k().as_usize();
+        start..PhysAddr::from(r.end).align_up_4k().as_usize()
+    });
+    // the free regions, split around the reserved range
+    let regions = || {
+        memory_regions()
+            .filter(|r| r.flags.contains(MemRegionFlags::FREE))
+            .flat_map(|r| {
+                let (start, end) = (r.paddr.as_usize(), r.paddr.as_usize() + r.size);
+                [
+                    (start, end.min(reserved.start)),
+                    (start.max(reserved.end), end),
+                ]
+            })
+            .filter(|(start, end)| start < end)
+            .map(|(start, end)| (PhysAddr::from(start), end - start))
+    };
+
     let mut max_region_size = 0;
     let mut max_region_paddr = 0.into();
-    for r in memory_regions() {
-        if r.flags.contains(MemRegionFlags::FREE) && r.size > max_region_size {
-            max_region_size = r.size;
-            max_region_paddr = r.paddr;
-        }
-    }
-    for r in memory_regions() {
-        if r.flags.contains(MemRegionFlags::FREE) && r.paddr == max_region_paddr {
-            axalloc::global_init(phys_to_virt(r.paddr).as_usize(), r.size);
-            break;
+    for (paddr, size) in regions() {
+        if size > max_region_size {
+            max_region_size = size;
+            max_region_paddr = paddr;
         }
     }
-    for r in memory_regions() {
-        if r.flags.contains(MemRegionFlags::FREE) && r.paddr != max_region_paddr {
-            axalloc::global_add_memory(phys_to_virt(r.paddr).as_usize(), r.size)
+    axalloc::global_init(phys_to_virt(max_region_paddr).as_usize(), max_region_size);
+    for (paddr, size) in regions() {
+        if paddr != max_region_paddr {
+            axalloc::global_add_memory(phys_to_virt(paddr).as_usize(), size)
                 .expect("add heap memory region failed");
         }
     }
 }
 
+#[cfg(all(feature = "fs", feature = "net"))]
+fn load_resolver_config() {
+    if let Ok(conf) = axfs::api::read_to_string("/etc/resolv.conf") {
+        info!("Load DNS resolver config from /etc/resolv.conf");
+        axnet::dns_load_resolv_conf(&conf);
+    }
+    if let Ok(hosts) = axfs::api::read_to_string("/etc/hosts") {
+        info!("Load hosts table from /etc/hosts");
+        axnet::dns_load_hosts(&hosts);
+    }
+}
+
 #[cfg(feature = "irq")]
 fn init_interrupt() {
     use axhal::time::TIMER_IRQ_NUM;
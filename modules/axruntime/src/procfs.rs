@@ -0,0 +1,92 @@
+//! Files of the network stack published in `/proc`.
+
+extern crate alloc;
+
+use alloc::{format, string::String, vec::Vec};
+use axerrno::{AxError, AxResult};
+
+/// Publishes the files of the network stack.
+pub(crate) fn publish_files() {
+    add_file("net/capture", capture);
+    add_binary_file("net/capture.pcap", capture_pcap);
+    add_file("net/tcp_listen", tcp_listen);
+    add_file_rw("sys/net/core/somaxconn", somaxconn, set_somaxconn);
+}
+
+fn add_file<R>(path: &str, read: R)
+where
+    R: Fn() -> AxResult<String> + Send + Sync + 'static,
+{
+    if let Err(e) = axfs::procfs::add_file(path, read) {
+        warn!("failed to add /proc/{}: {:?}", path, e);
+    }
+}
+
+fn add_binary_file<R>(path: &str, read: R)
+where
+    R: Fn() -> AxResult<Vec<u8>> + Send + Sync + 'static,
+{
+    if let Err(e) = axfs::procfs::add_binary_file(path, read) {
+        warn!("failed to add /proc/{}: {:?}", path, e);
+    }
+}
+
+fn add_file_rw<R, W>(path: &str, read: R, write: W)
+where
+    R: Fn() -> AxResult<String> + Send + Sync + 'static,
+    W: Fn(&str) -> AxResult + Send + Sync + 'static,
+{
+    if let Err(e) = axfs::procfs::add_file_rw(path, read, write) {
+        warn!("failed to add /proc/{}: {:?}", path, e);
+    }
+}
+
+/// `/proc/net/capture`: the state and the statistics of the packet capture.
+fn capture() -> AxResult<String> {
+    let state = if axnet::is_capturing() {
+        "running"
+    } else {
+        "stopped"
+    };
+    let stats = axnet::capture_stats();
+    Ok(format!(
+        "state:     {}\ncaptured:  {}\nevicted:   {}\nfiltered:  {}\ntruncated: {}\n\
+         dropped:   {}\nqueued:    {}\n",
+        state,
+        stats.captured,
+        stats.evicted,
+        stats.filtered,
+        stats.truncated,
+        stats.dropped,
+        stats.queued
+    ))
+}
+
+/// `/proc/net/capture.pcap`: the captured frames in the pcap file format,
+/// which are kept in the ring. The capture should be stopped before reading
+/// it, or new frames may change the file between reads.
+fn capture_pcap() -> AxResult<Vec<u8>> {
+    Ok(axnet::dump_capture(false))
+}
+
+/// `/proc/net/tcp_listen`: the connection queues of listening TCP sockets.
+fn tcp_listen() -> AxResult<String> {
+    let mut s = String::from("local_address          backlog  syn_recv  established  overflows\n");
+    for (addr, stats) in axnet::tcp_listeners() {
+        s += &format!(
+            "{:<21} {:>8} {:>9} {:>12} {:>10}\n",
+            addr, stats.backlog, stats.syn_received, stats.established, stats.overflows
+        );
+    }
+    Ok(s)
+}
+
+/// `/proc/sys/net/core/somaxconn`: the maximum backlog of listening TCP
+/// sockets.
+fn somaxconn() -> AxResult<String> {
+    Ok(format!("{}\n", axnet::somaxconn()))
+}
+
+fn set_somaxconn(s: &str) -> AxResult {
+    axnet::set_somaxconn(s.trim().parse().map_err(|_| AxError::InvalidInput)?)
+}
@@ -0,0 +1,143 @@
+//! Device attributes published in `/sys`.
+
+extern crate alloc;
+
+#[cfg(any(feature = "net", platform_family = "aarch64-phytium-pi"))]
+use {alloc::string::String, axerrno::AxResult};
+
+/// Publishes the attributes of the probed devices.
+pub(crate) fn publish_attrs() {
+    #[cfg(feature = "net")]
+    publish_net();
+    #[cfg(platform_family = "aarch64-phytium-pi")]
+    phytium::publish();
+}
+
+#[cfg(any(feature = "net", platform_family = "aarch64-phytium-pi"))]
+fn add_attr<R>(path: &str, show: R)
+where
+    R: Fn() -> AxResult<String> + Send + Sync + 'static,
+{
+    if let Err(e) = axfs::sysfs::add_attr(path, show) {
+        warn!("failed to add /sys/{}: {:?}", path, e);
+    }
+}
+
+/// `/sys/class/net/<iface>/address` of each network interface.
+#[cfg(feature = "net")]
+fn publish_net() {
+    use alloc::format;
+    for iface in axnet::interfaces() {
+        let m = iface.mac;
+        let address = format!(
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}\n",
+            m[0], m[1], m[2], m[3], m[4], m[5]
+        );
+        add_attr(&format!("class/net/{}/address", iface.name), move || {
+            Ok(address.clone())
+        });
+    }
+}
+
+/// The GPIO pins and the PWM channel on the Phytium Pi, in the layout of
+/// Linux:
+///
+/// - `/sys/class/gpio/gpio<N>/direction`: `in` or `out`. Writing `high` or
+///   `low` sets the pin as an output with the initial level.
+/// - `/sys/class/gpio/gpio<N>/value`: `0` or `1`.
+/// - `/sys/class/pwm/pwmchip0/pwm0/period`: the period in nanoseconds.
+/// - `/sys/class/pwm/pwmchip0/pwm0/duty_cycle`: the active time in
+///   nanoseconds, from 1% to 100% of the period.
+#[cfg(platform_family = "aarch64-phytium-pi")]
+mod phytium {
+    use super::*;
+    use alloc::format;
+    use axerrno::{ax_err, AxError};
+    use axhal::misc::{GpioPins, PwmCtrl, GPIO};
+
+    /// The PWM counter runs at 50MHz divided by 2.
+    const PWM_CYCLE_NS: u64 = 40;
+
+    const GPIO_PINS: usize = 16;
+
+    pub fn publish() {
+        for n in 0..GPIO_PINS {
+            let pin = GpioPins::from_bits_truncate(1 << n);
+            add_attr_rw(
+                &format!("class/gpio/gpio{}/direction", n),
+                move || {
+                    let out = GPIO.lock().pin_dir(pin);
+                    Ok(String::from(if out { "out\n" } else { "in\n" }))
+                },
+                move |s| {
+                    let mut gpio = GPIO.lock();
+                    match s.trim() {
+                        "in" => gpio.set_pin_dir(pin, false),
+                        "out" => gpio.set_pin_dir(pin, true),
+                        level @ ("high" | "low") => {
+                            gpio.set_pin_data(pin, level == "high");
+                            gpio.set_pin_dir(pin, true);
+                        }
+                        _ => return ax_err!(InvalidInput),
+                    }
+                    Ok(())
+                },
+            );
+            add_attr_rw(
+                &format!("class/gpio/gpio{}/value", n),
+                move || Ok(format!("{}\n", GPIO.lock().pin_data(pin) as u8)),
+                move |s| {
+                    let level = match s.trim() {
+                        "0" => false,
+                        "1" => true,
+                        _ => return ax_err!(InvalidInput),
+                    };
+                    GPIO.lock().set_pin_data(pin, level);
+                    Ok(())
+                },
+            );
+        }
+
+        if PwmCtrl::global().is_some() {
+            add_attr("class/pwm/pwmchip0/pwm0/period", || {
+                Ok(format!("{}\n", period_ns()))
+            });
+            add_attr_rw(
+                "class/pwm/pwmchip0/pwm0/duty_cycle",
+                || {
+                    let duty = pwm().get_duty() as u64;
+                    Ok(format!("{}\n", period_ns() * duty / 100))
+                },
+                |s| {
+                    let Ok(ns) = s.trim().parse::<u64>() else {
+                        return ax_err!(InvalidInput);
+                    };
+                    let Some(duty) = ns.saturating_mul(100).checked_div(period_ns()) else {
+                        return ax_err!(BadState);
+                    };
+                    pwm()
+                        .change_duty(duty.min(u32::MAX as u64) as u32)
+                        .map_err(|_| AxError::InvalidInput)
+                },
+            );
+        }
+    }
+
+    fn add_attr_rw<R, W>(path: &str, show: R, store: W)
+    where
+        R: Fn() -> AxResult<String> + Send + Sync + 'static,
+        W: Fn(&str) -> AxResult + Send + Sync + 'static,
+    {
+        if let Err(e) = axfs::sysfs::add_attr_rw(path, show, store) {
+            warn!("failed to add /sys/{}: {:?}", path, e);
+        }
+    }
+
+    fn pwm() -> &'static mut PwmCtrl {
+        PwmCtrl::global().unwrap()
+    }
+
+    fn period_ns() -> u64 {
+        pwm().get_period() as u64 * PWM_CYCLE_NS
+    }
+}
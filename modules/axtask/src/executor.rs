@@ -0,0 +1,191 @@
+//! A minimal executor for [`Future`]s.
+//!
+//! Futures spawned by [`spawn`] are put into a global ready queue, and are
+//! run by any task calling [`block_on`]. A future is polled again only after
+//! its [`Waker`] has been woken, so one task can multiplex a large number of
+//! pending futures.
+//!
+//! After each round of running the ready futures, the executor calls the poll
+//! hook set by [`set_poll_hook`] once (e.g., to poll the network interfaces,
+//! which wakes the futures waiting for sockets), however many futures are
+//! pending. When no future is ready, it also yields the CPU.
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::task::Wake;
+use core::future::Future;
+use core::pin::{pin, Pin};
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use core::task::{Context, Poll, Waker};
+
+use kspin::SpinNoIrq;
+
+type BoxFuture = Pin<alloc::boxed::Box<dyn Future<Output = ()> + Send + 'static>>;
+
+static READY_QUEUE: SpinNoIrq<VecDeque<Arc<AsyncTask>>> = SpinNoIrq::new(VecDeque::new());
+static POLL_HOOK: SpinNoIrq<Option<fn()>> = SpinNoIrq::new(None);
+
+/// Not queued nor running, waiting to be woken.
+const IDLE: u8 = 0;
+/// In the ready queue.
+const QUEUED: u8 = 1;
+/// Being polled.
+const RUNNING: u8 = 2;
+/// Being polled, and woken during the poll, so it's queued again after.
+const NOTIFIED: u8 = 3;
+/// Completed.
+const DONE: u8 = 4;
+
+/// A spawned future with its scheduling state.
+struct AsyncTask {
+    future: SpinNoIrq<Option<BoxFuture>>,
+    state: AtomicU8,
+}
+
+impl AsyncTask {
+    fn schedule(self: &Arc<Self>) {
+        let mut state = self.state.load(Ordering::Acquire);
+        loop {
+            let new = match state {
+                IDLE => QUEUED,
+                RUNNING => NOTIFIED,
+                _ => return, // already queued or notified, or completed
+            };
+            match self
+                .state
+                .compare_exchange(state, new, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => break,
+                Err(s) => state = s,
+            }
+        }
+        if state == IDLE {
+            READY_QUEUE.lock().push_back(self.clone());
+        }
+    }
+
+    fn run(self: Arc<Self>) {
+        if self
+            .state
+            .compare_exchange(QUEUED, RUNNING, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            return;
+        }
+        // Only the CPU that set `RUNNING` takes the future, and the lock is not
+        // held while polling.
+        let mut future = self.future.lock().take().unwrap();
+        let waker = Waker::from(self.clone());
+        let mut cx = Context::from_waker(&waker);
+        if future.as_mut().poll(&mut cx).is_ready() {
+            self.state.store(DONE, Ordering::Release);
+            return;
+        }
+        *self.future.lock() = Some(future);
+        if self
+            .state
+            .compare_exchange(RUNNING, IDLE, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            // woken during the poll
+            self.state.store(QUEUED, Ordering::Release);
+            READY_QUEUE.lock().push_back(self);
+        }
+    }
+}
+
+impl Wake for AsyncTask {
+    fn wake(self: Arc<Self>) {
+        self.schedule();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.schedule();
+    }
+}
+
+/// The waker of the future driven by [`block_on`].
+struct MainWaker {
+    woken: AtomicBool,
+}
+
+impl Wake for MainWaker {
+    fn wake(self: Arc<Self>) {
+        self.woken.store(true, Ordering::Release);
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::Release);
+    }
+}
+
+/// Runs all futures in the ready queue once. Returns `true` if any future
+/// has been polled.
+fn run_ready_tasks() -> bool {
+    let mut polled = false;
+    loop {
+        let task = READY_QUEUE.lock().pop_front();
+        match task {
+            Some(task) => {
+                task.run();
+                polled = true;
+            }
+            None => return polled,
+        }
+    }
+}
+
+fn poll_events() {
+    let hook = *POLL_HOOK.lock();
+    if let Some(hook) = hook {
+        hook();
+    }
+}
+
+/// Sets the function called by the executor once per round of running the
+/// ready futures.
+///
+/// It is usually used to poll the event sources that wake futures, such as
+/// network interfaces.
+pub fn set_poll_hook(hook: fn()) {
+    *POLL_HOOK.lock() = Some(hook);
+}
+
+/// Spawns a future onto the executor.
+///
+/// The future will be run by tasks calling [`block_on`].
+pub fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let task = Arc::new(AsyncTask {
+        future: SpinNoIrq::new(Some(alloc::boxed::Box::pin(future))),
+        state: AtomicU8::new(IDLE),
+    });
+    task.schedule();
+}
+
+/// Runs the executor on the current task until the given future completes,
+/// and returns its output.
+///
+/// Futures spawned by [`spawn`] are also run in the meantime.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let main = Arc::new(MainWaker {
+        woken: AtomicBool::new(true),
+    });
+    let waker = Waker::from(main.clone());
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if main.woken.swap(false, Ordering::AcqRel) {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+        let polled = run_ready_tasks();
+        poll_events();
+        if !polled && !main.woken.load(Ordering::Acquire) {
+            crate::yield_now();
+        }
+    }
+}
@@ -49,6 +49,8 @@ cfg_if::cfg_if! {
         #[cfg(feature = "irq")]
         mod timers;
 
+        #[doc(cfg(feature = "multitask"))]
+        pub mod executor;
         #[doc(cfg(feature = "multitask"))]
         pub use self::api::*;
         pub use self::api::{sleep, sleep_until, yield_now};
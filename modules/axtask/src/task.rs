@@ -1,10 +1,10 @@
-use alloc::{boxed::Box, string::String, sync::Arc};
+use alloc::collections::BTreeMap;
+use alloc::sync::Weak;
+use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
 use core::ops::Deref;
 use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicU8, Ordering};
 use core::{alloc::Layout, cell::UnsafeCell, fmt, ptr::NonNull};
 
-#[cfg(feature = "smp")]
-use alloc::sync::Weak;
 #[cfg(feature = "preempt")]
 use core::sync::atomic::AtomicUsize;
 
@@ -25,7 +25,7 @@ pub struct TaskId(u64);
 /// The possible states of a task.
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
-pub(crate) enum TaskState {
+pub enum TaskState {
     /// Task is running on some CPU.
     Running = 1,
     /// Task is ready to run on some scheduler's ready queue.
@@ -82,6 +82,19 @@ pub struct TaskInner {
     tls: TlsArea,
 }
 
+/// All tasks that have not been dropped, indexed by their IDs.
+static TASK_LIST: SpinNoIrq<BTreeMap<u64, Weak<AxTask>>> = SpinNoIrq::new(BTreeMap::new());
+
+/// Returns all tasks that have not been dropped (including exited ones that
+/// are waiting to be joined or recycled), sorted by their IDs.
+pub(crate) fn all_tasks() -> Vec<AxTaskRef> {
+    TASK_LIST
+        .lock()
+        .values()
+        .filter_map(|task| task.upgrade())
+        .collect()
+}
+
 impl TaskId {
     fn new() -> Self {
         static ID_COUNTER: AtomicU64 = AtomicU64::new(1);
@@ -144,6 +157,27 @@ impl TaskInner {
         self.name.as_str()
     }
 
+    /// Gets the current state of the task.
+    #[inline]
+    pub fn state(&self) -> TaskState {
+        self.state.load(Ordering::Acquire).into()
+    }
+
+    /// Whether the task is an idle task.
+    #[inline]
+    pub const fn is_idle(&self) -> bool {
+        self.is_idle
+    }
+
+    /// Gets the exit code of the task, or [`None`] if it has not exited.
+    pub fn exit_code(&self) -> Option<i32> {
+        if self.state() == TaskState::Exited {
+            Some(self.exit_code.load(Ordering::Acquire))
+        } else {
+            None
+        }
+    }
+
     /// Get a combined string of the task ID and name.
     pub fn id_name(&self) -> alloc::string::String {
         alloc::format!("Task({}, {:?})", self.id.as_u64(), self.name)
@@ -269,12 +303,10 @@ impl TaskInner {
     }
 
     pub(crate) fn into_arc(self) -> AxTaskRef {
-        Arc::new(AxTask::new(self))
-    }
-
-    #[inline]
-    pub(crate) fn state(&self) -> TaskState {
-        self.state.load(Ordering::Acquire).into()
+        let id = self.id.as_u64();
+        let task = Arc::new(AxTask::new(self));
+        TASK_LIST.lock().insert(id, Arc::downgrade(&task));
+        task
     }
 
     #[inline]
@@ -312,11 +344,6 @@ impl TaskInner {
         self.is_init
     }
 
-    #[inline]
-    pub(crate) const fn is_idle(&self) -> bool {
-        self.is_idle
-    }
-
     #[inline]
     pub(crate) fn in_wait_queue(&self) -> bool {
         self.in_wait_queue.load(Ordering::Acquire)
@@ -469,6 +496,7 @@ impl fmt::Debug for TaskInner {
 impl Drop for TaskInner {
     fn drop(&mut self) {
         debug!("task drop: {}", self.id_name());
+        TASK_LIST.lock().remove(&self.id.as_u64());
     }
 }
 
@@ -128,3 +128,147 @@ fn test_task_join() {
         assert_eq!(tasks[i].join(), Some(i as _));
     }
 }
+
+#[test]
+fn test_task_list() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    let task = axtask::spawn_raw(|| axtask::exit(7), "listed".into(), 0x1000);
+    let id = task.id().as_u64();
+    let listed = |id| axtask::tasks().iter().any(|t| t.id().as_u64() == id);
+
+    assert!(listed(current().id().as_u64()));
+    assert!(listed(id));
+    assert_eq!(task.exit_code(), None);
+    assert_eq!(task.join(), Some(7));
+    assert_eq!(task.state(), axtask::TaskState::Exited);
+    assert_eq!(task.exit_code(), Some(7));
+
+    // Still listed until the last reference is dropped.
+    assert!(listed(id));
+    drop(task);
+    while listed(id) {
+        axtask::yield_now();
+    }
+}
+
+#[test]
+fn test_executor_self_wake() {
+    use core::future::{poll_fn, Future};
+    use core::pin::Pin;
+    use core::sync::atomic::AtomicBool;
+    use core::task::{Context, Poll};
+
+    use crate::executor;
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static OTHER_RAN: AtomicBool = AtomicBool::new(false);
+    static DONE: AtomicBool = AtomicBool::new(false);
+
+    /// Wakes itself in its first poll, while another task runs the executor
+    /// before the poll returns.
+    struct SelfWake {
+        polls: usize,
+    }
+
+    impl Future for SelfWake {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            self.polls += 1;
+            if self.polls > 1 {
+                DONE.store(true, Ordering::Release);
+                return Poll::Ready(());
+            }
+            cx.waker().wake_by_ref();
+            axtask::spawn(|| {
+                // pending once, so that the ready queue is run
+                let mut yielded = false;
+                executor::block_on(poll_fn(|cx| {
+                    if yielded {
+                        return Poll::Ready(());
+                    }
+                    yielded = true;
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }));
+                OTHER_RAN.store(true, Ordering::Release);
+            });
+            while !OTHER_RAN.load(Ordering::Acquire) {
+                axtask::yield_now();
+            }
+            Poll::Pending
+        }
+    }
+
+    executor::spawn(SelfWake { polls: 0 });
+    let mut polls = 0;
+    let done = executor::block_on(poll_fn(|cx| {
+        polls += 1;
+        if DONE.load(Ordering::Acquire) {
+            Poll::Ready(true)
+        } else if polls > 1000 {
+            Poll::Ready(false)
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }));
+    assert!(done, "the wakeup during the poll was lost");
+}
+
+#[test]
+fn test_executor_poll_hook() {
+    use core::future::poll_fn;
+    use core::task::{Poll, Waker};
+
+    use crate::executor;
+
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    const NUM_FUTURES: usize = 100;
+    const READY_AFTER: usize = 10;
+    static HOOK_CALLS: AtomicUsize = AtomicUsize::new(0);
+    static POLLS: AtomicUsize = AtomicUsize::new(0);
+    static FINISHED: AtomicUsize = AtomicUsize::new(0);
+    static WAKERS: Mutex<Vec<Waker>> = Mutex::new(Vec::new());
+
+    /// Like polling a NIC, the event happens after some polls, then wakes
+    /// the waiting futures.
+    fn poll_hook() {
+        if HOOK_CALLS.fetch_add(1, Ordering::Relaxed) + 1 >= READY_AFTER {
+            let wakers = core::mem::take(&mut *WAKERS.lock().unwrap());
+            wakers.into_iter().for_each(Waker::wake);
+        }
+    }
+    executor::set_poll_hook(poll_hook);
+
+    for _ in 0..NUM_FUTURES {
+        executor::spawn(poll_fn(|cx| {
+            POLLS.fetch_add(1, Ordering::Relaxed);
+            if HOOK_CALLS.load(Ordering::Relaxed) >= READY_AFTER {
+                FINISHED.fetch_add(1, Ordering::Relaxed);
+                return Poll::Ready(());
+            }
+            WAKERS.lock().unwrap().push(cx.waker().clone());
+            Poll::Pending
+        }));
+    }
+    executor::block_on(poll_fn(|cx| {
+        if FINISHED.load(Ordering::Relaxed) == NUM_FUTURES {
+            return Poll::Ready(());
+        }
+        WAKERS.lock().unwrap().push(cx.waker().clone());
+        Poll::Pending
+    }));
+    executor::set_poll_hook(|| {});
+
+    // pending futures are polled again only after being woken
+    assert_eq!(POLLS.load(Ordering::Relaxed), NUM_FUTURES * 2);
+    // the hook is called once per round, not once per pending future
+    assert!(HOOK_CALLS.load(Ordering::Relaxed) <= READY_AFTER + 2);
+}
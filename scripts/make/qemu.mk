@@ -45,6 +45,12 @@ else
   $(error "NET_DEV" must be one of "user", "tap", or "bridge")
 endif
 
+# The NICs other than the first one are attached to existing TAP interfaces
+net_ports := $(shell seq 1 $$(($(NET_PORTS) - 1)))
+qemu_args-$(NET) += $(foreach i,$(net_ports), \
+  -device virtio-net-$(vdev-suffix),netdev=net$(i) \
+  -netdev tap,id=net$(i),ifname=axport$(i),script=no,downscript=no)
+
 ifneq ($(VFIO_PCI),)
   qemu_args-y += --device vfio-pci,host=$(VFIO_PCI)
   QEMU := sudo $(QEMU)
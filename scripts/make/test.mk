@@ -1,6 +1,11 @@
 # Test scripts
 
 define unit_test
+  $(call run_cmd,bash,modules/axfs/resources/create_ext4_img.sh)
+  $(call run_cmd,bash,modules/axfs/resources/create_gpt_img.sh)
   $(call run_cmd,cargo test,-p axfs $(1) --features "myfs" -- --nocapture)
+  $(call run_cmd,cargo test,-p axfs $(1) --features "ext4fs" -- --nocapture)
+  $(call run_cmd,cargo test,-p axfs $(1) --features "initramfs" -- --nocapture)
+  $(call run_cmd,cargo test,-p axfs $(1) --features "multitask" -- --nocapture)
   $(call run_cmd,cargo test,--workspace $(1) -- --nocapture)
 endef
@@ -0,0 +1,70 @@
+#!/bin/bash
+#
+# Test the bridge between two NICs of ArceOS.
+#
+# The first NIC is attached to the bridge `virbr0` (see create-bridge.sh), and
+# the second one to the TAP interface `axport1`, which is bridged to the
+# network namespace `axbr-ns` with the address 10.0.2.3. Pings from the
+# namespace reach the host and ArceOS only through the bridge of ArceOS.
+#
+# sudo ./create-bridge.sh
+# sudo ./test-bridge.sh setup
+# make A=examples/bridge NET=y NET_DEV=tap NET_PORTS=2 run
+# sudo ./test-bridge.sh test
+# sudo ./test-bridge.sh cleanup
+
+NS=axbr-ns
+TAP=axport1
+BR=axbr1
+VETH=axveth1
+NS_IP=10.0.2.3
+HOST_IP=10.0.2.2
+AX_IP=10.0.2.15
+
+setup() {
+    ip netns add $NS
+    ip tuntap add dev $TAP mode tap user "${SUDO_USER:-$(whoami)}"
+    ip link add $VETH type veth peer name eth0 netns $NS
+    ip link add $BR type bridge
+    ip link set $TAP master $BR
+    ip link set $VETH master $BR
+    ip link set $TAP up
+    ip link set $VETH up
+    ip link set $BR up
+    ip netns exec $NS ip addr add $NS_IP/24 dev eth0
+    ip netns exec $NS ip link set eth0 up
+    ip netns exec $NS ip link set lo up
+}
+
+run_test() {
+    local ret=0
+    for ip in $AX_IP $HOST_IP; do
+        echo "Pinging $ip from $NS ..."
+        if ! ip netns exec $NS ping -c 3 -W 2 $ip; then
+            ret=1
+        fi
+    done
+    if [ $ret -eq 0 ]; then
+        echo "Bridge test passed"
+    else
+        echo "Bridge test failed"
+    fi
+    return $ret
+}
+
+cleanup() {
+    ip link del $BR 2> /dev/null
+    ip link del $VETH 2> /dev/null
+    ip link del $TAP 2> /dev/null
+    ip netns del $NS 2> /dev/null
+}
+
+case "$1" in
+    setup) setup ;;
+    test) run_test ;;
+    cleanup) cleanup ;;
+    *)
+        echo "Usage: $0 setup|test|cleanup"
+        exit 1
+        ;;
+esac
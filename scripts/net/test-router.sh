@@ -0,0 +1,89 @@
+#!/bin/bash
+#
+# Test IP forwarding and NAT between two NICs of ArceOS.
+#
+# The first NIC is attached to the bridge `virbr0` (see create-bridge.sh) as
+# the outside, and the second one to the TAP interface `axport1`, which is
+# bridged to the network namespace `axrt-ns` as the inside network
+# 192.168.100.0/24 routed by ArceOS.
+#
+# sudo ./create-bridge.sh
+# sudo ./test-router.sh setup
+# make A=examples/router NET=y NET_DEV=tap NET_PORTS=2 run
+# sudo ./test-router.sh test
+# sudo ./test-router.sh cleanup
+
+NS=axrt-ns
+TAP=axport1
+BR=axbr1
+VETH=axveth1
+NS_IP=192.168.100.2
+ROUTER_IP=192.168.100.1
+HOST_IP=10.0.2.2
+AX_IP=10.0.2.15
+FORWARD_PORT=8080
+
+setup() {
+    ip netns add $NS
+    ip tuntap add dev $TAP mode tap user "${SUDO_USER:-$(whoami)}"
+    ip link add $VETH type veth peer name eth0 netns $NS
+    ip link add $BR type bridge
+    ip link set $TAP master $BR
+    ip link set $VETH master $BR
+    ip link set $TAP up
+    ip link set $VETH up
+    ip link set $BR up
+    ip netns exec $NS ip addr add $NS_IP/24 dev eth0
+    ip netns exec $NS ip link set eth0 up
+    ip netns exec $NS ip link set lo up
+    ip netns exec $NS ip route add default via $ROUTER_IP
+}
+
+check() {
+    local name=$1
+    shift
+    if "$@" > /dev/null 2>&1; then
+        echo "PASS: $name"
+    else
+        echo "FAIL: $name"
+        ret=1
+    fi
+}
+
+run_test() {
+    ret=0
+    check "ping the router" ip netns exec $NS ping -c 3 -W 2 $ROUTER_IP
+    check "ping the outside with masquerade" ip netns exec $NS ping -c 3 -W 2 $HOST_IP
+    check "TTL exceeded" sh -c \
+        "ip netns exec $NS ping -c 1 -W 2 -t 1 $HOST_IP | grep -q 'Time to live exceeded'"
+
+    ip netns exec $NS python3 -m http.server 80 --bind $NS_IP > /dev/null 2>&1 &
+    local server=$!
+    sleep 1
+    check "port forwarding" curl -sf --max-time 5 http://$AX_IP:$FORWARD_PORT/
+    kill $server
+
+    if [ $ret -eq 0 ]; then
+        echo "Router test passed"
+    else
+        echo "Router test failed"
+    fi
+    return $ret
+}
+
+cleanup() {
+    ip link del $BR 2> /dev/null
+    ip link del $VETH 2> /dev/null
+    ip link del $TAP 2> /dev/null
+    ip netns del $NS 2> /dev/null
+}
+
+case "$1" in
+    setup) setup ;;
+    test) run_test ;;
+    cleanup) cleanup ;;
+    *)
+        echo "Usage: $0 setup|test|cleanup"
+        exit 1
+        ;;
+esac
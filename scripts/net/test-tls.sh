@@ -0,0 +1,85 @@
+#!/bin/bash
+#
+# Test the TLS client of ArceOS against `openssl s_server` on the host, with
+# TLS 1.2 and TLS 1.3.
+#
+# The httpclient example (with the `https` feature) connects to the server at
+# 10.0.2.2:4433 through the bridge `virbr0` (see create-bridge.sh), and
+# verifies its certificate by a test CA at /etc/ssl/ca.pem in the initramfs.
+# KVM is used so that the CPU has RDRAND/RDSEED to seed the CSPRNG.
+#
+# sudo ./create-bridge.sh
+# ./test-tls.sh setup
+# sudo ./test-tls.sh test
+# ./test-tls.sh cleanup
+
+ROOT=$(realpath "$(dirname "$0")/../..")
+WORK=$ROOT/target/tls-test
+PORT=4433
+
+setup() {
+    set -e
+    mkdir -p $WORK/rootfs/etc/ssl
+    cd $WORK
+    openssl req -x509 -newkey ec -pkeyopt ec_paramgen_curve:prime256v1 -nodes -days 30 \
+        -subj "/CN=ArceOS Test CA" \
+        -addext "basicConstraints=critical,CA:TRUE" -addext "keyUsage=critical,keyCertSign" \
+        -keyout ca.key -out rootfs/etc/ssl/ca.pem
+    openssl req -newkey ec -pkeyopt ec_paramgen_curve:prime256v1 -nodes \
+        -subj "/CN=localhost" -keyout server.key -out server.csr
+    echo "subjectAltName=DNS:localhost" > server.ext
+    openssl x509 -req -in server.csr -CA rootfs/etc/ssl/ca.pem -CAkey ca.key -CAcreateserial \
+        -days 30 -extfile server.ext -out server.pem
+    (cd rootfs && find . | cpio -o -H newc --quiet) > initramfs.cpio
+    echo "Certificates and initramfs are created in $WORK"
+}
+
+check() {
+    local name=$1
+    shift
+    if "$@" > /dev/null 2>&1; then
+        echo "PASS: $name"
+    else
+        echo "FAIL: $name"
+        ret=1
+    fi
+}
+
+run_client() {
+    make -C $ROOT A=examples/httpclient APP_FEATURES=https NET=y NET_DEV=tap ACCEL=y \
+        INITRAMFS=$WORK/initramfs.cpio run 2>&1 | tee $WORK/client.log | grep -q "HTTP/1.0 200 ok"
+}
+
+run_test() {
+    ret=0
+    for version in tls1_2 tls1_3; do
+        openssl s_server -accept $PORT -cert $WORK/server.pem -key $WORK/server.key \
+            -$version -www > $WORK/s_server.log 2>&1 &
+        local server=$!
+        sleep 1
+        check "HTTPS request with ${version/_/.}" run_client
+        kill $server
+        wait $server 2> /dev/null
+    done
+
+    if [ $ret -eq 0 ]; then
+        echo "TLS test passed"
+    else
+        echo "TLS test failed, see $WORK/client.log"
+    fi
+    return $ret
+}
+
+cleanup() {
+    rm -rf $WORK
+}
+
+case "$1" in
+    setup) setup ;;
+    test) run_test ;;
+    cleanup) cleanup ;;
+    *)
+        echo "Usage: $0 setup|test|cleanup"
+        exit 1
+        ;;
+esac
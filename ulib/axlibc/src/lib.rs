@@ -97,10 +97,14 @@ pub use self::malloc::{free, malloc};
 pub use self::strftime::strftime;
 
 #[cfg(feature = "fd")]
-pub use self::fd_ops::{ax_fcntl, close, dup, dup2, dup3};
+pub use self::fd_ops::{ax_fcntl, ax_ioctl, close, dup, dup2, dup3};
 
 #[cfg(feature = "fs")]
-pub use self::fs::{ax_open, fstat, getcwd, lseek, lstat, rename, stat};
+pub use self::fs::{
+    ax_open, fdatasync, flock, fstat, fsync, futimens, getcwd, inotify_add_watch, inotify_init,
+    inotify_init1, inotify_rm_watch, link, lseek, lstat, mount, readlink, rename, stat, symlink,
+    sync, umount, umount2, utimensat,
+};
 
 #[cfg(feature = "net")]
 pub use self::net::{
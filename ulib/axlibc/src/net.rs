@@ -3,6 +3,7 @@ use arceos_posix_api::{
     sys_getsockname, sys_listen, sys_recv, sys_recvfrom, sys_send, sys_sendto, sys_shutdown,
     sys_socket,
 };
+use axerrno::LinuxError;
 use core::ffi::{c_char, c_int, c_void};
 
 use crate::{ctypes, utils::e};
@@ -145,8 +146,10 @@ pub unsafe extern "C" fn getaddrinfo(
     hints: *const ctypes::addrinfo,
     res: *mut *mut ctypes::addrinfo,
 ) -> c_int {
-    let ret = e(sys_getaddrinfo(nodename, servname, hints, res));
-    match ret {
+    let ret = sys_getaddrinfo(nodename, servname, hints, res);
+    match e(ret) {
+        // no answer from the name servers, try again later
+        _ if ret == -LinuxError::ECONNREFUSED.code() => ctypes::EAI_AGAIN,
         r if r < 0 => ctypes::EAI_FAIL,
         0 => ctypes::EAI_NONAME,
         _ => 0,
@@ -0,0 +1,30 @@
+//! A minimal executor for asynchronous tasks.
+//!
+//! Futures are run on the current thread by [`block_on`], along with the
+//! futures spawned by [`spawn`]. The executor polls the network stack once
+//! after each round of running the ready futures, and yields the CPU to other
+//! threads while no future is ready.
+
+use core::future::Future;
+
+use arceos_api::task as api;
+
+/// Spawns a future to run in the background.
+///
+/// The future is run by threads calling [`block_on`].
+pub fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    api::ax_spawn_async(future)
+}
+
+/// Runs the given future to completion on the current thread, and returns
+/// its output.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut output = None;
+    api::ax_block_on(async {
+        output = Some(future.await);
+    });
+    output.unwrap()
+}
@@ -1,5 +1,6 @@
 use crate::io::{prelude::*, Result, SeekFrom};
 use core::fmt;
+use core::time::Duration;
 
 use arceos_api::fs as api;
 
@@ -16,12 +17,43 @@ pub struct File {
 }
 
 /// Metadata information about a file.
-pub struct Metadata(api::AxFileAttr);
+pub struct Metadata(pub(super) api::AxFileAttr);
 
 /// Options and flags which can be used to configure how a file is opened.
 #[derive(Clone, Debug)]
 pub struct OpenOptions(api::AxOpenOptions);
 
+/// Representation of the timestamps of a file, to be set by
+/// [`File::set_times`]. Times are since the Unix epoch.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FileTimes {
+    accessed: Option<Duration>,
+    modified: Option<Duration>,
+}
+
+impl FileTimes {
+    /// Creates a new `FileTimes` with no times set, which leaves the times
+    /// of a file unchanged.
+    pub const fn new() -> Self {
+        Self {
+            accessed: None,
+            modified: None,
+        }
+    }
+
+    /// Sets the last access time of a file.
+    pub const fn set_accessed(mut self, t: Duration) -> Self {
+        self.accessed = Some(t);
+        self
+    }
+
+    /// Sets the last modified time of a file.
+    pub const fn set_modified(mut self, t: Duration) -> Self {
+        self.modified = Some(t);
+        self
+    }
+}
+
 impl OpenOptions {
     /// Creates a blank new set of options ready for configuration.
     pub const fn new() -> Self {
@@ -90,6 +122,12 @@ impl Metadata {
         self.0.is_file()
     }
 
+    /// Returns `true` if this metadata is for a symbolic link, which is only
+    /// possible if it's returned by [`symlink_metadata`](super::symlink_metadata).
+    pub const fn is_symlink(&self) -> bool {
+        matches!(self.0.file_type(), FileType::SymLink)
+    }
+
     /// Returns the size of the file, in bytes, this metadata is for.
     #[allow(clippy::len_without_is_empty)]
     pub const fn len(&self) -> u64 {
@@ -110,6 +148,20 @@ impl Metadata {
     pub const fn blocks(&self) -> u64 {
         self.0.blocks()
     }
+
+    /// Returns the last access time, since the Unix epoch.
+    ///
+    /// It's zero if the filesystem doesn't keep timestamps.
+    pub const fn accessed(&self) -> Duration {
+        self.0.atime()
+    }
+
+    /// Returns the last modification time, since the Unix epoch.
+    ///
+    /// It's zero if the filesystem doesn't keep timestamps.
+    pub const fn modified(&self) -> Duration {
+        self.0.mtime()
+    }
 }
 
 impl fmt::Debug for Metadata {
@@ -162,6 +214,16 @@ impl File {
     pub fn metadata(&self) -> Result<Metadata> {
         api::ax_file_attr(&self.inner).map(Metadata)
     }
+
+    /// Changes the timestamps of the underlying file.
+    pub fn set_times(&self, times: FileTimes) -> Result<()> {
+        api::ax_set_file_times(&self.inner, times.accessed, times.modified)
+    }
+
+    /// Changes the modification time of the underlying file.
+    pub fn set_modified(&self, time: Duration) -> Result<()> {
+        self.set_times(FileTimes::new().set_modified(time))
+    }
 }
 
 impl Read for File {
@@ -2,6 +2,7 @@
 
 mod dir;
 mod file;
+mod watch;
 
 use crate::io::{self, prelude::*};
 
@@ -9,7 +10,8 @@ use crate::io::{self, prelude::*};
 use alloc::{string::String, vec::Vec};
 
 pub use self::dir::{DirBuilder, DirEntry, ReadDir};
-pub use self::file::{File, FileType, Metadata, OpenOptions, Permissions};
+pub use self::file::{File, FileTimes, FileType, Metadata, OpenOptions, Permissions};
+pub use self::watch::{watcher, WatchEvent, WatchEventKind, WatchReceiver, Watcher};
 
 /// Read the entire contents of a file into a bytes vector.
 #[cfg(feature = "alloc")]
@@ -42,6 +44,27 @@ pub fn metadata(path: &str) -> io::Result<Metadata> {
     File::open(path)?.metadata()
 }
 
+/// Query the metadata about a file without following symlinks.
+pub fn symlink_metadata(path: &str) -> io::Result<Metadata> {
+    arceos_api::fs::ax_symlink_attr(path).map(Metadata)
+}
+
+/// Reads a symbolic link, returning the path that the link points to.
+#[cfg(feature = "alloc")]
+pub fn read_link(path: &str) -> io::Result<String> {
+    arceos_api::fs::ax_read_link(path)
+}
+
+/// Creates a new symbolic link at `link` pointing to `original`.
+pub fn symlink(original: &str, link: &str) -> io::Result<()> {
+    arceos_api::fs::ax_symlink(original, link)
+}
+
+/// Creates a new hard link at `link` to the file at `original`.
+pub fn hard_link(original: &str, link: &str) -> io::Result<()> {
+    arceos_api::fs::ax_hard_link(original, link)
+}
+
 /// Returns an iterator over the entries within a directory.
 pub fn read_dir(path: &str) -> io::Result<ReadDir> {
     ReadDir::new(path)
@@ -0,0 +1,65 @@
+use crate::io::Result;
+
+use arceos_api::fs as api;
+
+/// An event of a watched path, see [`watcher`].
+pub use arceos_api::fs::AxWatchEvent as WatchEvent;
+/// The kind of a [`WatchEvent`].
+pub use arceos_api::fs::AxWatchEventKind as WatchEventKind;
+
+/// Watches paths for changes, whose events are received from the
+/// [`WatchReceiver`] returned along with it by [`watcher`].
+pub struct Watcher(api::AxWatcherHandle);
+
+/// The receiving end of the events of a [`Watcher`], like that of a channel.
+pub struct WatchReceiver(api::AxWatcherHandle);
+
+/// Creates a watcher of paths and the receiving end of its events, like
+/// inotify.
+///
+/// Events are reported when files or directories at watched paths, or
+/// entries in watched directories, are created, modified, removed or
+/// renamed.
+pub fn watcher() -> (Watcher, WatchReceiver) {
+    let handle = api::ax_create_watcher();
+    (Watcher(handle.clone()), WatchReceiver(handle))
+}
+
+impl Watcher {
+    /// Watches the file or directory at `path` for the kinds of events in
+    /// `mask` (e.g. [`WatchEventKind::ALL`]), or replaces the mask if it's
+    /// already watched. Returns the watch descriptor of the events.
+    pub fn watch(&self, path: &str, mask: u32) -> Result<usize> {
+        api::ax_add_watch(&self.0, path, mask)
+    }
+
+    /// Stops watching the path of the watch descriptor `wd`.
+    pub fn unwatch(&self, wd: usize) -> Result<()> {
+        api::ax_remove_watch(&self.0, wd)
+    }
+}
+
+impl WatchReceiver {
+    /// Waits for the next event and returns it.
+    ///
+    /// Without the `multitask` feature, it fails with `WouldBlock` instead of
+    /// waiting if there is none.
+    pub fn recv(&self) -> Result<WatchEvent> {
+        api::ax_next_watch_event(&self.0, true)
+    }
+
+    /// Returns the next event if there is any, without waiting.
+    pub fn try_recv(&self) -> Option<WatchEvent> {
+        api::ax_next_watch_event(&self.0, false).ok()
+    }
+
+    /// Returns an iterator that waits for events.
+    pub fn iter(&self) -> impl Iterator<Item = WatchEvent> + '_ {
+        core::iter::from_fn(|| self.recv().ok())
+    }
+
+    /// Returns an iterator over the events queued, without waiting.
+    pub fn try_iter(&self) -> impl Iterator<Item = WatchEvent> + '_ {
+        core::iter::from_fn(|| self.try_recv())
+    }
+}
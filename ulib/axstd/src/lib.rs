@@ -30,8 +30,12 @@
 //! - Upperlayer stacks
 //!     - `fs`: Enable file system support.
 //!     - `myfs`: Allow users to define their custom filesystems to override the default.
+//!     - `ext4fs`: Use ext2/3/4 instead of FAT as the main filesystem.
+//!     - `initramfs`: Allow a cpio or tar archive unpacked to memory as the root filesystem.
 //!     - `net`: Enable networking support.
 //!     - `dns`: Enable DNS lookup support.
+//!     - `net-tls`: Enable TLS streams over TCP.
+//!     - `net-tls-rustcrypto`: Use the pre-release RustCrypto provider for TLS.
 //!     - `display`: Enable graphics support.
 //! - Device drivers
 //!     - `bus-mmio`: Use device tree to probe all MMIO devices.
@@ -39,6 +43,8 @@
 //!     - `driver-ramdisk`: Use the RAM disk to emulate the block device.
 //!     - `driver-ixgbe`: Enable the Intel 82599 10Gbit NIC driver.
 //!     - `driver-bcm2835-sdhci`: Enable the BCM2835 SDHCI driver (Raspberry Pi SD card).
+//!     - `driver-dyn`: Use the dynamic device model, which supports more than
+//!       one device of each type, e.g. NICs for the bridge.
 //! - Logging
 //!     - `log-level-off`: Disable all logging.
 //!     - `log-level-error`, `log-level-warn`, `log-level-info`, `log-level-debug`,
@@ -58,12 +64,14 @@ extern crate alloc;
 pub use alloc::{boxed, collections, format, string, vec};
 
 #[doc(no_inline)]
-pub use core::{arch, cell, cmp, hint, marker, mem, ops, ptr, slice, str};
+pub use core::{arch, cell, cmp, future, hint, marker, mem, ops, ptr, slice, str, task};
 
 #[macro_use]
 mod macros;
 
 pub mod env;
+#[cfg(feature = "multitask")]
+pub mod executor;
 pub mod io;
 pub mod os;
 pub mod process;
@@ -0,0 +1,73 @@
+use core::future::poll_fn;
+use core::task::{Context, Poll};
+
+use super::{SocketAddr, ToSocketAddrs};
+use crate::io;
+
+use arceos_api::net::{self as api, AxUdpSocketHandle};
+
+/// An asynchronous UDP socket.
+///
+/// Unlike [`UdpSocket`](super::UdpSocket), its operations never block the
+/// calling thread. Instead, they return futures that are woken by the
+/// network stack when the socket becomes ready.
+pub struct AsyncUdpSocket(AxUdpSocketHandle);
+
+impl AsyncUdpSocket {
+    /// Creates a UDP socket from the given address.
+    ///
+    /// If `addr` yields multiple addresses, `bind` will be attempted with
+    /// each of the addresses until one succeeds and returns the socket.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<AsyncUdpSocket> {
+        super::each_addr(addr, |addr: io::Result<&SocketAddr>| {
+            let addr = addr?;
+            let socket = api::ax_udp_socket();
+            api::ax_udp_bind(&socket, *addr)?;
+            Ok(AsyncUdpSocket(socket))
+        })
+    }
+
+    /// Returns the socket address that this socket was created from.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        api::ax_udp_socket_addr(&self.0)
+    }
+
+    /// Attempts to receive a single datagram, registering the waker of `cx`
+    /// if none is queued.
+    pub fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, SocketAddr)>> {
+        api::ax_udp_poll_recv_from(&self.0, cx, buf)
+    }
+
+    /// Attempts to send data to the given address, registering the waker of
+    /// `cx` if the send buffer is full.
+    pub fn poll_send_to(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        addr: SocketAddr,
+    ) -> Poll<io::Result<usize>> {
+        api::ax_udp_poll_send_to(&self.0, cx, buf, addr)
+    }
+
+    /// Receives a single datagram message on the socket. On success, returns
+    /// the number of bytes read and the origin.
+    pub async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        poll_fn(|cx| self.poll_recv_from(cx, buf)).await
+    }
+
+    /// Sends data on the socket to the given address. On success, returns the
+    /// number of bytes written.
+    ///
+    /// It is possible for `addr` to yield multiple addresses, but `send_to`
+    /// will only send data to the first address yielded by `addr`.
+    pub async fn send_to<A: ToSocketAddrs>(&self, buf: &[u8], addr: A) -> io::Result<usize> {
+        match addr.to_socket_addrs()?.next() {
+            Some(addr) => poll_fn(|cx| self.poll_send_to(cx, buf, addr)).await,
+            None => axerrno::ax_err!(InvalidInput, "no addresses to send data to"),
+        }
+    }
+}
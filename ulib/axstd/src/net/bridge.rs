@@ -0,0 +1,51 @@
+//! A learning bridge between the network devices.
+//!
+//! The bridge forwards Ethernet frames between all NICs by the MAC addresses
+//! learned on them, and delivers the frames to `eth0` to the local network
+//! stack. More than one NIC needs the `driver-dyn` feature.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::net::bridge;
+//!
+//! bridge::start(true).unwrap();
+//! loop {
+//!     bridge::poll().unwrap();
+//!     if let Some(stats) = bridge::stats() {
+//!         println!("forwarded {} frames", stats.forwarded);
+//!     }
+//! }
+//! ```
+
+use crate::io;
+use arceos_api::net as api;
+
+/// Statistics of the bridge.
+pub use arceos_api::net::AxBridgeStats as BridgeStats;
+
+/// Starts bridging all NICs.
+///
+/// Frames to unknown destinations are flooded to all NICs if `flood_unknown`
+/// is true, otherwise they are dropped until the destination is learned.
+pub fn start(flood_unknown: bool) -> io::Result<()> {
+    api::ax_net_bridge_start(flood_unknown)
+}
+
+/// Stops bridging the NICs.
+pub fn stop() -> io::Result<()> {
+    api::ax_net_bridge_stop()
+}
+
+/// Receives the pending frames from the NICs and forwards them.
+///
+/// Frames are also forwarded whenever the network stack is polled by socket
+/// operations.
+pub fn poll() -> io::Result<()> {
+    api::ax_poll_interfaces()
+}
+
+/// Returns the statistics of the bridge, or `None` if it is not started.
+pub fn stats() -> Option<BridgeStats> {
+    api::ax_net_bridge_stats()
+}
@@ -0,0 +1,54 @@
+//! Packet capture on the network device.
+//!
+//! Frames received from and transmitted to the NIC can be recorded into an
+//! in-memory ring, and retrieved in the pcap file format to be opened with
+//! Wireshark. The state of the capture is shown in `/proc/net/capture`, and
+//! the captured frames in `/proc/net/capture.pcap` (with the `fs` feature).
+//!
+//! Frames are recorded as they are on the wire: received frames before they
+//! are bridged or routed, and transmitted frames after the VLAN tag is
+//! inserted.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::net::capture;
+//!
+//! capture::start("tcp port 80", capture::DEFAULT_CAPACITY).unwrap();
+//! // ... do some networking ...
+//! capture::stop().unwrap();
+//! std::fs::write("/tmp/http.pcap", capture::dump(true)).unwrap();
+//! ```
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use crate::io;
+use arceos_api::net as api;
+
+/// Default size of the capture ring in bytes.
+pub use arceos_api::net::AX_NET_CAPTURE_DEFAULT_CAPACITY as DEFAULT_CAPACITY;
+
+/// Starts capturing frames matching the `filter` expression into a ring of
+/// `capacity` bytes.
+///
+/// The filter is a whitespace-separated list of primitives which all must
+/// match: `arp`, `ip`, `ether proto <type>`, `tcp`, `udp`, `icmp`,
+/// `port <port>`, `inbound` and `outbound`. An empty filter captures
+/// everything.
+pub fn start(filter: &str, capacity: usize) -> io::Result<()> {
+    api::ax_net_capture_start(filter, capacity)
+}
+
+/// Stops capturing frames. The frames already captured are kept.
+pub fn stop() -> io::Result<()> {
+    api::ax_net_capture_stop()
+}
+
+/// Returns the captured frames in the pcap file format.
+///
+/// If `clear` is true, the returned frames are removed from the ring.
+pub fn dump(clear: bool) -> Vec<u8> {
+    api::ax_net_capture_dump(clear)
+}
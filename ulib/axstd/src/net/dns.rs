@@ -0,0 +1,39 @@
+//! DNS resolver configuration.
+//!
+//! Host names given to [`ToSocketAddrs`](super::ToSocketAddrs) are looked up
+//! in `/etc/hosts`, then in the cache of previous answers, and at last sent
+//! to the name servers. The servers are read from `/etc/resolv.conf` at boot
+//! if it exists, and can be changed at runtime.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::net::{dns, IpAddr, Ipv4Addr};
+//!
+//! dns::set_servers(&[IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1))]).unwrap();
+//! ```
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use super::IpAddr;
+use crate::io;
+use arceos_api::net as api;
+
+/// Sets the name servers, which are queried in order.
+///
+/// At most 3 servers are used. If none is set, `8.8.8.8` is used.
+pub fn set_servers(servers: &[IpAddr]) -> io::Result<()> {
+    api::ax_dns_set_servers(servers)
+}
+
+/// Returns the name servers.
+pub fn servers() -> Vec<IpAddr> {
+    api::ax_dns_servers()
+}
+
+/// Removes all cached answers.
+pub fn flush_cache() -> io::Result<()> {
+    api::ax_dns_flush_cache()
+}
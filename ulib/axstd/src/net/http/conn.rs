@@ -0,0 +1,89 @@
+use alloc::{vec, vec::Vec};
+
+use crate::io::{self, prelude::*};
+use crate::net::TcpStream;
+
+const READ_BUF_LEN: usize = 4096;
+
+/// A buffered HTTP connection.
+///
+/// Unlike a `BufReader`, the stream stays accessible for writing responses.
+pub(super) struct Connection<S = TcpStream> {
+    stream: S,
+    buf: Vec<u8>,
+    pos: usize,
+    len: usize,
+}
+
+impl<S: Read + Write> Connection<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            buf: vec![0; READ_BUF_LEN],
+            pos: 0,
+            len: 0,
+        }
+    }
+
+    pub fn stream(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Refills the buffer if it is empty, returns the number of buffered
+    /// bytes, or 0 on end of stream.
+    fn fill(&mut self) -> io::Result<usize> {
+        if self.pos == self.len {
+            self.pos = 0;
+            self.len = self.stream.read(&mut self.buf)?;
+        }
+        Ok(self.len - self.pos)
+    }
+
+    /// Reads a line terminated by `\n` into `line`, without the line
+    /// terminator (`\n` or `\r\n`).
+    ///
+    /// Returns `false` on end of stream before any byte has been read.
+    pub fn read_line(&mut self, line: &mut Vec<u8>, limit: usize) -> io::Result<bool> {
+        line.clear();
+        loop {
+            if self.fill()? == 0 {
+                if line.is_empty() {
+                    return Ok(false);
+                }
+                return axerrno::ax_err!(UnexpectedEof, "http: truncated line");
+            }
+            let data = &self.buf[self.pos..self.len];
+            match data.iter().position(|&b| b == b'\n') {
+                Some(idx) => {
+                    line.extend_from_slice(&data[..idx]);
+                    self.pos += idx + 1;
+                    if line.last() == Some(&b'\r') {
+                        line.pop();
+                    }
+                    return Ok(true);
+                }
+                None => {
+                    line.extend_from_slice(data);
+                    self.pos = self.len;
+                }
+            }
+            if line.len() > limit {
+                return axerrno::ax_err!(InvalidData, "http: line too long");
+            }
+        }
+    }
+
+    /// Reads exactly `len` bytes and appends them to `out`.
+    pub fn read_to(&mut self, out: &mut Vec<u8>, mut len: usize) -> io::Result<()> {
+        while len > 0 {
+            if self.fill()? == 0 {
+                return axerrno::ax_err!(UnexpectedEof, "http: truncated body");
+            }
+            let n = len.min(self.len - self.pos);
+            out.extend_from_slice(&self.buf[self.pos..self.pos + n]);
+            self.pos += n;
+            len -= n;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+use alloc::format;
+use alloc::string::String;
+
+use super::{Request, Response};
+use crate::fs::{self, File};
+use crate::io::{Seek, SeekFrom};
+
+/// Returns the MIME type of a file by its extension.
+pub fn mime_type(path: &str) -> &'static str {
+    let ext = match path.rsplit_once('.') {
+        Some((_, ext)) if !ext.contains('/') => ext,
+        _ => return "application/octet-stream",
+    };
+    match ext.to_ascii_lowercase().as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "txt" | "log" => "text/plain; charset=utf-8",
+        "xml" => "application/xml",
+        "csv" => "text/csv; charset=utf-8",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "webp" => "image/webp",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "tar" => "application/x-tar",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Parses a single-range `Range` header against a file of `size` bytes.
+///
+/// Returns `None` for a header that should be ignored (invalid or multiple
+/// ranges), `Some(None)` for an unsatisfiable range, and the inclusive range
+/// otherwise.
+pub(super) fn parse_range(value: &str, size: u64) -> Option<Option<(u64, u64)>> {
+    let spec = value.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None; // multipart ranges are not supported
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+    let range = if start.is_empty() {
+        // suffix range: the last `end` bytes
+        let len: u64 = end.parse().ok()?;
+        if len == 0 || size == 0 {
+            return Some(None);
+        }
+        (size.saturating_sub(len), size - 1)
+    } else {
+        let start: u64 = start.parse().ok()?;
+        let end = if end.is_empty() {
+            size.saturating_sub(1)
+        } else {
+            end.parse::<u64>().ok()?.min(size.saturating_sub(1))
+        };
+        if start >= size || start > end {
+            return Some(None);
+        }
+        (start, end)
+    };
+    Some(Some(range))
+}
+
+/// Normalizes a relative URL path, rejecting `..` components.
+fn sanitize(rel_path: &str) -> Option<String> {
+    let mut path = String::new();
+    for seg in rel_path.split('/') {
+        match seg {
+            "" | "." => {}
+            ".." => return None,
+            seg => {
+                path.push('/');
+                path.push_str(seg);
+            }
+        }
+    }
+    Some(path)
+}
+
+/// Serves the file at `rel_path` under the directory `root`.
+///
+/// Directories are served by their `index.html`. A single-range `Range`
+/// header is answered with `206 Partial Content`.
+pub fn serve_file(req: &Request, root: &str, rel_path: &str) -> Response {
+    let mut path = match sanitize(rel_path) {
+        Some(rel) => format!("{}{}", root, rel),
+        None => return Response::error(403),
+    };
+    let mut meta = match fs::metadata(&path) {
+        Ok(meta) => meta,
+        Err(_) => return Response::not_found(),
+    };
+    if meta.is_dir() {
+        path = format!("{}/index.html", path.trim_end_matches('/'));
+        meta = match fs::metadata(&path) {
+            Ok(meta) if meta.is_file() => meta,
+            _ => return Response::not_found(),
+        };
+    }
+    let mut file = match File::open(&path) {
+        Ok(file) => file,
+        Err(_) => return Response::error(403),
+    };
+
+    let size = meta.len();
+    let (status, (start, end)) = match req.header("Range").and_then(|v| parse_range(v, size)) {
+        None => (200, (0, size.wrapping_sub(1))),
+        Some(Some(range)) => (206, range),
+        Some(None) => {
+            return Response::error(416).with_header("Content-Range", &format!("bytes */{}", size))
+        }
+    };
+    let len = end.wrapping_sub(start).wrapping_add(1); // 0 for an empty file
+    if start > 0 && file.seek(SeekFrom::Start(start)).is_err() {
+        return Response::error(500);
+    }
+    let resp = Response::new(status)
+        .with_header("Content-Type", mime_type(&path))
+        .with_header("Accept-Ranges", "bytes");
+    let resp = if status == 206 {
+        resp.with_header(
+            "Content-Range",
+            &format!("bytes {}-{}/{}", start, end, size),
+        )
+    } else {
+        resp
+    };
+    resp.with_reader(file, Some(len))
+}
@@ -0,0 +1,56 @@
+//! A minimal HTTP/1.1 server.
+//!
+//! The server parses requests from TCP connections, dispatches them to the
+//! handlers registered in a [`Router`], and writes back their [`Response`]s.
+//! It supports:
+//!
+//! - persistent connections (`Connection: keep-alive`, the default of
+//!   HTTP/1.1), closed after [`DEFAULT_READ_TIMEOUT`] of idleness (see
+//!   [`Server::with_read_timeout`]);
+//! - request bodies with `Content-Length` or `Transfer-Encoding: chunked`,
+//!   and chunked response bodies of unknown length;
+//! - routing by method and path, with `:name` parameters and trailing `*`
+//!   wildcards, and query string parsing;
+//! - static files from the file system with MIME types and `Range` requests
+//!   (with the `fs` feature);
+//! - one thread per connection (with the `multitask` feature).
+//!
+//! # Examples
+//!
+//! ```ignore
+//! use axstd::net::http::{Method, Response, Router, Server};
+//!
+//! let router = Router::new()
+//!     .route(Method::Get, "/hello/:name", |req| {
+//!         Response::text(format!("Hello, {}!", req.param("name").unwrap()))
+//!     })
+//!     .files("/static", "/www");
+//! Server::bind("0.0.0.0:80", router).unwrap().run().unwrap();
+//! ```
+
+mod conn;
+#[cfg(feature = "fs")]
+mod files;
+mod request;
+mod response;
+mod router;
+mod server;
+#[cfg(test)]
+mod tests;
+
+#[cfg(feature = "fs")]
+pub use self::files::{mime_type, serve_file};
+pub use self::request::{Method, Request};
+pub use self::response::{Body, Response};
+pub use self::router::{Handler, Router};
+pub use self::server::Server;
+
+/// Maximum size of the request line and headers.
+pub const MAX_HEADER_SIZE: usize = 8 * 1024;
+/// Maximum number of request headers.
+pub const MAX_HEADERS: usize = 64;
+/// Maximum size of a request body, beyond which requests are answered with
+/// `413 Content Too Large`.
+pub const MAX_BODY_SIZE: usize = 1024 * 1024;
+/// Default time a connection may stay idle before the server closes it.
+pub const DEFAULT_READ_TIMEOUT: core::time::Duration = core::time::Duration::from_secs(10);
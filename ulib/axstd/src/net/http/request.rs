@@ -0,0 +1,327 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::{fmt, str};
+
+use axerrno::{ax_err, ax_err_type};
+
+use super::conn::Connection;
+use super::{MAX_BODY_SIZE, MAX_HEADERS, MAX_HEADER_SIZE};
+use crate::io::{self, prelude::*};
+use crate::net::SocketAddr;
+
+/// An HTTP request method.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Method {
+    /// `GET`
+    Get,
+    /// `HEAD`
+    Head,
+    /// `POST`
+    Post,
+    /// `PUT`
+    Put,
+    /// `DELETE`
+    Delete,
+    /// `OPTIONS`
+    Options,
+    /// `PATCH`
+    Patch,
+    /// Any other method.
+    Other(String),
+}
+
+impl Method {
+    fn parse(s: &str) -> Self {
+        match s {
+            "GET" => Self::Get,
+            "HEAD" => Self::Head,
+            "POST" => Self::Post,
+            "PUT" => Self::Put,
+            "DELETE" => Self::Delete,
+            "OPTIONS" => Self::Options,
+            "PATCH" => Self::Patch,
+            _ => Self::Other(s.to_string()),
+        }
+    }
+
+    /// Returns the method name.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Get => "GET",
+            Self::Head => "HEAD",
+            Self::Post => "POST",
+            Self::Put => "PUT",
+            Self::Delete => "DELETE",
+            Self::Options => "OPTIONS",
+            Self::Patch => "PATCH",
+            Self::Other(s) => s,
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An HTTP request.
+#[derive(Debug)]
+pub struct Request {
+    method: Method,
+    path: String,
+    query: Vec<(String, String)>,
+    minor_version: u8,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    peer_addr: Option<SocketAddr>,
+    pub(super) params: Vec<(String, String)>,
+}
+
+impl Request {
+    /// Returns the request method.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// Returns the percent-decoded path, without the query string.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the decoded query parameters in order.
+    pub fn query_pairs(&self) -> &[(String, String)] {
+        &self.query
+    }
+
+    /// Returns the first query parameter with the given name.
+    pub fn query(&self, name: &str) -> Option<&str> {
+        find(&self.query, |k| k == name)
+    }
+
+    /// Returns the path parameter captured by a `:name` segment of the route.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        find(&self.params, |k| k == name)
+    }
+
+    /// Returns the first header with the given name, compared
+    /// case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find(&self.headers, |k| k.eq_ignore_ascii_case(name))
+    }
+
+    /// Returns all headers in order.
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// Returns the request body, with the chunked transfer coding removed.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Returns the address of the client.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// Whether the client wants to keep the connection open after the
+    /// response.
+    pub fn keep_alive(&self) -> bool {
+        match self.header("Connection") {
+            Some(v) if has_token(v, "close") => false,
+            Some(v) if has_token(v, "keep-alive") => true,
+            _ => self.minor_version >= 1,
+        }
+    }
+
+    /// Reads a request from the connection.
+    ///
+    /// Returns `None` if the connection is closed before a new request.
+    pub(super) fn read_from<S: Read + Write>(
+        conn: &mut Connection<S>,
+        peer_addr: Option<SocketAddr>,
+    ) -> io::Result<Option<Request>> {
+        let mut line = Vec::new();
+        // skip empty lines before the request line (RFC 9112, section 2.2)
+        loop {
+            if !conn.read_line(&mut line, MAX_HEADER_SIZE)? {
+                return Ok(None);
+            }
+            if !line.is_empty() {
+                break;
+            }
+        }
+
+        let request_line = utf8(&line)?;
+        let mut parts = request_line.split(' ');
+        let (method, target, version) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(m), Some(t), Some(v)) if parts.next().is_none() => (m, t, v),
+            _ => return ax_err!(InvalidData, "http: malformed request line"),
+        };
+        let minor_version = match version {
+            "HTTP/1.1" => 1,
+            "HTTP/1.0" => 0,
+            _ => return ax_err!(Unsupported, "http: unsupported version"),
+        };
+        let method = Method::parse(method);
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, parse_query(query)),
+            None => (target, Vec::new()),
+        };
+        let path = percent_decode(path, false)?;
+
+        let mut headers = Vec::new();
+        let mut header_size = line.len();
+        loop {
+            conn.read_line(&mut line, MAX_HEADER_SIZE)?;
+            if line.is_empty() {
+                break;
+            }
+            header_size += line.len();
+            if header_size > MAX_HEADER_SIZE || headers.len() >= MAX_HEADERS {
+                return ax_err!(InvalidData, "http: headers too large");
+            }
+            let header = utf8(&line)?;
+            let (name, value) = header
+                .split_once(':')
+                .ok_or_else(|| ax_err_type!(InvalidData, "http: malformed header"))?;
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+
+        let mut req = Request {
+            method,
+            path,
+            query,
+            minor_version,
+            headers,
+            body: Vec::new(),
+            peer_addr,
+            params: Vec::new(),
+        };
+        if req.minor_version >= 1
+            && req
+                .header("Expect")
+                .is_some_and(|v| v.eq_ignore_ascii_case("100-continue"))
+        {
+            conn.stream().write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+        }
+        req.read_body(conn)?;
+        Ok(Some(req))
+    }
+
+    fn read_body<S: Read + Write>(&mut self, conn: &mut Connection<S>) -> io::Result<()> {
+        if self
+            .header("Transfer-Encoding")
+            .is_some_and(|v| has_token(v, "chunked"))
+        {
+            return self.read_chunked_body(conn);
+        }
+        if let Some(len) = self.header("Content-Length") {
+            let len: usize = len
+                .parse()
+                .map_err(|_| ax_err_type!(InvalidData, "http: invalid Content-Length"))?;
+            if len > MAX_BODY_SIZE {
+                return ax_err!(StorageFull, "http: body too large");
+            }
+            conn.read_to(&mut self.body, len)?;
+        }
+        Ok(())
+    }
+
+    fn read_chunked_body<S: Read + Write>(&mut self, conn: &mut Connection<S>) -> io::Result<()> {
+        let mut line = Vec::new();
+        loop {
+            conn.read_line(&mut line, MAX_HEADER_SIZE)?;
+            let size = utf8(&line)?;
+            // ignore chunk extensions
+            let size = size.split(';').next().unwrap_or("").trim();
+            let size = usize::from_str_radix(size, 16)
+                .map_err(|_| ax_err_type!(InvalidData, "http: invalid chunk size"))?;
+            if size == 0 {
+                break;
+            }
+            // `size` is from the client, and adding to it could overflow
+            if size > MAX_BODY_SIZE - self.body.len() {
+                return ax_err!(StorageFull, "http: body too large");
+            }
+            conn.read_to(&mut self.body, size)?;
+            conn.read_line(&mut line, 2)?; // CRLF after the chunk data
+            if !line.is_empty() {
+                return ax_err!(InvalidData, "http: malformed chunk");
+            }
+        }
+        // skip the trailer section
+        loop {
+            conn.read_line(&mut line, MAX_HEADER_SIZE)?;
+            if line.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn find(pairs: &[(String, String)], f: impl Fn(&str) -> bool) -> Option<&str> {
+    pairs.iter().find(|(k, _)| f(k)).map(|(_, v)| v.as_str())
+}
+
+fn utf8(bytes: &[u8]) -> io::Result<&str> {
+    str::from_utf8(bytes).map_err(|_| ax_err_type!(InvalidData, "http: invalid UTF-8"))
+}
+
+/// Whether the comma-separated header value contains the token.
+pub(super) fn has_token(value: &str, token: &str) -> bool {
+    value
+        .split(',')
+        .any(|t| t.trim().eq_ignore_ascii_case(token))
+}
+
+/// Parses an `application/x-www-form-urlencoded` query string.
+pub(super) fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|s| !s.is_empty())
+        .filter_map(|pair| {
+            let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+            Some((percent_decode(k, true).ok()?, percent_decode(v, true).ok()?))
+        })
+        .collect()
+}
+
+/// Decodes `%XX` escapes, and `+` as space if `plus_as_space` is set.
+pub(super) fn percent_decode(s: &str, plus_as_space: bool) -> io::Result<String> {
+    fn hex(b: u8) -> Option<u8> {
+        match b {
+            b'0'..=b'9' => Some(b - b'0'),
+            b'a'..=b'f' => Some(b - b'a' + 10),
+            b'A'..=b'F' => Some(b - b'A' + 10),
+            _ => None,
+        }
+    }
+
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hi = bytes.get(i + 1).copied().and_then(hex);
+                let lo = bytes.get(i + 2).copied().and_then(hex);
+                match (hi, lo) {
+                    (Some(hi), Some(lo)) => out.push((hi << 4) | lo),
+                    _ => return ax_err!(InvalidData, "http: invalid percent-encoding"),
+                }
+                i += 3;
+            }
+            b'+' if plus_as_space => {
+                out.push(b' ');
+                i += 1;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(out).map_err(|_| ax_err_type!(InvalidData, "http: invalid UTF-8"))
+}
@@ -0,0 +1,229 @@
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::{format, vec, vec::Vec};
+
+use crate::io::{self, prelude::*};
+
+/// The body of a [`Response`].
+pub enum Body {
+    /// No body.
+    Empty,
+    /// A body held in memory.
+    Bytes(Vec<u8>),
+    /// A body read from a reader.
+    ///
+    /// If the length is known, it is sent with `Content-Length`, otherwise
+    /// with the chunked transfer coding.
+    Reader(Box<dyn Read + Send>, Option<u64>),
+}
+
+/// An HTTP response.
+pub struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Body,
+}
+
+impl Response {
+    /// Creates an empty response with the given status code.
+    pub fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Body::Empty,
+        }
+    }
+
+    /// Creates a `200 OK` response with a `text/plain` body.
+    pub fn text(body: impl Into<String>) -> Self {
+        Self::new(200)
+            .with_header("Content-Type", "text/plain; charset=utf-8")
+            .with_body(body.into().into_bytes())
+    }
+
+    /// Creates a `200 OK` response with a `text/html` body.
+    pub fn html(body: impl Into<String>) -> Self {
+        Self::new(200)
+            .with_header("Content-Type", "text/html; charset=utf-8")
+            .with_body(body.into().into_bytes())
+    }
+
+    /// Creates a `200 OK` response with an `application/json` body.
+    pub fn json(body: impl Into<String>) -> Self {
+        Self::new(200)
+            .with_header("Content-Type", "application/json")
+            .with_body(body.into().into_bytes())
+    }
+
+    /// Creates an error response with the status code and its reason phrase
+    /// as the body.
+    pub fn error(status: u16) -> Self {
+        Self::new(status)
+            .with_header("Content-Type", "text/plain; charset=utf-8")
+            .with_body(format!("{} {}\n", status, reason_phrase(status)).into_bytes())
+    }
+
+    /// Creates a `404 Not Found` response.
+    pub fn not_found() -> Self {
+        Self::error(404)
+    }
+
+    /// Creates a redirection to `location` with the given status code.
+    pub fn redirect(status: u16, location: &str) -> Self {
+        Self::new(status).with_header("Location", location)
+    }
+
+    /// Adds a header.
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Sets the body held in memory.
+    pub fn with_body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = Body::Bytes(body.into());
+        self
+    }
+
+    /// Sets a body read from `reader`, of `len` bytes if known.
+    ///
+    /// A body of unknown length is sent with the chunked transfer coding.
+    pub fn with_reader(mut self, reader: impl Read + Send + 'static, len: Option<u64>) -> Self {
+        self.body = Body::Reader(Box::new(reader), len);
+        self
+    }
+
+    /// Returns the status code.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Returns the first header with the given name, compared
+    /// case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Writes the response to `w`.
+    ///
+    /// If `head_only` is set (for `HEAD` requests), the body is omitted but
+    /// its headers are kept.
+    pub(super) fn write_to<W: Write>(
+        self,
+        w: &mut W,
+        keep_alive: bool,
+        head_only: bool,
+    ) -> io::Result<()> {
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\n",
+            self.status,
+            reason_phrase(self.status)
+        );
+        for (name, value) in &self.headers {
+            if !name.eq_ignore_ascii_case("Connection") {
+                head += &format!("{}: {}\r\n", name, value);
+            }
+        }
+        let no_body = matches!(self.status, 100..=199 | 204 | 304);
+        let chunked = match &self.body {
+            _ if no_body => false,
+            Body::Empty => {
+                head += "Content-Length: 0\r\n";
+                false
+            }
+            Body::Bytes(data) => {
+                head += &format!("Content-Length: {}\r\n", data.len());
+                false
+            }
+            Body::Reader(_, Some(len)) => {
+                head += &format!("Content-Length: {}\r\n", len);
+                false
+            }
+            Body::Reader(_, None) => {
+                head += "Transfer-Encoding: chunked\r\n";
+                true
+            }
+        };
+        head += if keep_alive {
+            "Connection: keep-alive\r\n\r\n"
+        } else {
+            "Connection: close\r\n\r\n"
+        };
+        w.write_all(head.as_bytes())?;
+        if head_only || no_body {
+            return Ok(());
+        }
+
+        match self.body {
+            Body::Empty => {}
+            Body::Bytes(data) => w.write_all(&data)?,
+            Body::Reader(mut reader, len) => {
+                let mut buf = vec![0; 4096];
+                let mut remaining = len.unwrap_or(u64::MAX);
+                while remaining > 0 {
+                    let max = buf.len().min(remaining.try_into().unwrap_or(usize::MAX));
+                    let n = reader.read(&mut buf[..max])?;
+                    if n == 0 {
+                        if len.is_some() {
+                            // the client would wait for the missing bytes forever
+                            return axerrno::ax_err!(
+                                UnexpectedEof,
+                                "http: body shorter than length"
+                            );
+                        }
+                        break;
+                    }
+                    if chunked {
+                        w.write_all(format!("{:x}\r\n", n).as_bytes())?;
+                        w.write_all(&buf[..n])?;
+                        w.write_all(b"\r\n")?;
+                    } else {
+                        w.write_all(&buf[..n])?;
+                    }
+                    remaining -= n as u64;
+                }
+                if chunked {
+                    w.write_all(b"0\r\n\r\n")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the reason phrase of a status code.
+pub(super) fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        101 => "Switching Protocols",
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        304 => "Not Modified",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        411 => "Length Required",
+        413 => "Content Too Large",
+        414 => "URI Too Long",
+        416 => "Range Not Satisfiable",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "Unknown",
+    }
+}
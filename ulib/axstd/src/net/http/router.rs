@@ -0,0 +1,135 @@
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use super::{Method, Request, Response};
+
+/// A request handler.
+pub type Handler = Arc<dyn Fn(&Request) -> Response + Send + Sync>;
+
+struct Route {
+    method: Method,
+    segments: Vec<String>,
+    /// Whether the pattern ends with `*`, matching any remaining segments.
+    wildcard: bool,
+    handler: Handler,
+}
+
+impl Route {
+    /// Matches the path against the pattern, returns the captured `:name`
+    /// parameters on success.
+    fn matches(&self, path: &str) -> Option<Vec<(String, String)>> {
+        let mut params = Vec::new();
+        let mut parts = path.split('/').filter(|s| !s.is_empty());
+        for seg in &self.segments {
+            let part = parts.next()?;
+            if let Some(name) = seg.strip_prefix(':') {
+                params.push((name.to_string(), part.to_string()));
+            } else if seg != part {
+                return None;
+            }
+        }
+        if self.wildcard {
+            let rest: Vec<&str> = parts.collect();
+            params.push(("*".to_string(), rest.join("/")));
+        } else if parts.next().is_some() {
+            return None;
+        }
+        Some(params)
+    }
+}
+
+/// Dispatches requests to handlers by method and path.
+///
+/// A path pattern is a list of `/`-separated segments. A segment starting
+/// with `:` matches any single segment and captures it as a parameter,
+/// available by [`Request::param`]. A trailing `*` segment matches the rest
+/// of the path, captured as the parameter `*`.
+///
+/// Routes are tried in the order they were added.
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+impl Router {
+    /// Creates an empty router.
+    pub fn new() -> Self {
+        Self { routes: Vec::new() }
+    }
+
+    /// Adds a route handling `method` requests on paths matching `pattern`.
+    pub fn route<F>(mut self, method: Method, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        let mut segments: Vec<String> = pattern
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+            .collect();
+        let wildcard = segments.last().is_some_and(|s| s == "*");
+        if wildcard {
+            segments.pop();
+        }
+        self.routes.push(Route {
+            method,
+            segments,
+            wildcard,
+            handler: Arc::new(handler),
+        });
+        self
+    }
+
+    /// Adds a `GET` route.
+    pub fn get<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.route(Method::Get, pattern, handler)
+    }
+
+    /// Adds a `POST` route.
+    pub fn post<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.route(Method::Post, pattern, handler)
+    }
+
+    /// Serves the files under the directory `root` at the URL `prefix`.
+    ///
+    /// Directories are served by their `index.html`.
+    #[cfg(feature = "fs")]
+    pub fn files(self, prefix: &str, root: &str) -> Self {
+        let root = root.trim_end_matches('/').to_string();
+        let pattern = alloc::format!("{}/*", prefix.trim_end_matches('/'));
+        self.route(Method::Get, &pattern, move |req| {
+            super::serve_file(req, &root, req.param("*").unwrap_or(""))
+        })
+    }
+
+    /// Dispatches the request to the first matching route.
+    ///
+    /// `HEAD` requests are handled by `GET` routes. If the path matches but
+    /// the method does not, a `405 Method Not Allowed` response is returned.
+    pub fn handle(&self, req: &mut Request) -> Response {
+        let mut path_matched = false;
+        for route in &self.routes {
+            if let Some(params) = route.matches(req.path()) {
+                let method_matches = route.method == *req.method()
+                    || (route.method == Method::Get && *req.method() == Method::Head);
+                if method_matches {
+                    req.params = params;
+                    return (route.handler)(req);
+                }
+                path_matched = true;
+            }
+        }
+        if path_matched {
+            Response::error(405)
+        } else {
+            Response::not_found()
+        }
+    }
+}
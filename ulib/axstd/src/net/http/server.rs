@@ -0,0 +1,107 @@
+use alloc::sync::Arc;
+use core::time::Duration;
+
+use super::conn::Connection;
+use super::request::has_token;
+use super::{Method, Request, Response, Router, DEFAULT_READ_TIMEOUT};
+use crate::io::{self, prelude::*};
+use crate::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+
+/// An HTTP/1.1 server.
+pub struct Server {
+    listener: TcpListener,
+    router: Arc<Router>,
+    read_timeout: Option<Duration>,
+}
+
+impl Server {
+    /// Creates a server listening on the given address, dispatching requests
+    /// with `router`.
+    pub fn bind<A: ToSocketAddrs>(addr: A, router: Router) -> io::Result<Server> {
+        Ok(Server {
+            listener: TcpListener::bind(addr)?,
+            router: Arc::new(router),
+            read_timeout: Some(DEFAULT_READ_TIMEOUT),
+        })
+    }
+
+    /// Sets how long a connection may stay idle, or send a request
+    /// partially, before it is closed. `None`, or a zero duration, waits
+    /// forever.
+    ///
+    /// Defaults to [`DEFAULT_READ_TIMEOUT`]. Without the `multitask`
+    /// feature, an idle client blocks the server until the timeout expires.
+    pub fn with_read_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.read_timeout = timeout.filter(|t| !t.is_zero());
+        self
+    }
+
+    /// Returns the local address the server is listening on.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts connections forever.
+    ///
+    /// With the `multitask` feature, each connection is served by a new
+    /// thread. Otherwise, connections are served one after another.
+    pub fn run(self) -> io::Result<()> {
+        loop {
+            let (stream, addr) = self.listener.accept()?;
+            // cannot fail, as the timeout is never zero
+            stream.set_read_timeout(self.read_timeout).ok();
+            let router = self.router.clone();
+            #[cfg(feature = "multitask")]
+            crate::thread::spawn(move || serve_connection(stream, addr, &router));
+            #[cfg(not(feature = "multitask"))]
+            serve_connection(stream, addr, &router);
+        }
+    }
+}
+
+/// Serves requests on a connection until it is closed by either side.
+fn serve_connection(stream: TcpStream, addr: SocketAddr, router: &Router) {
+    let mut conn = Connection::new(stream);
+    serve(&mut conn, Some(addr), router);
+    conn.stream().shutdown().ok();
+}
+
+/// Serves the requests read from `conn` until the end of the stream, a read
+/// timeout, an error, or a response that closes the connection.
+pub(super) fn serve<S: Read + Write>(
+    conn: &mut Connection<S>,
+    addr: Option<SocketAddr>,
+    router: &Router,
+) {
+    loop {
+        let mut req = match Request::read_from(conn, addr) {
+            Ok(Some(req)) => req,
+            Ok(None) => break,
+            Err(e) => {
+                let status = match e {
+                    io::Error::Unsupported => 505,
+                    io::Error::StorageFull => 413,
+                    // the peer has gone, or stayed idle for the read timeout
+                    io::Error::ConnectionReset
+                    | io::Error::UnexpectedEof
+                    | io::Error::WouldBlock => break,
+                    _ => 400,
+                };
+                Response::error(status)
+                    .write_to(conn.stream(), false, false)
+                    .ok();
+                break;
+            }
+        };
+        let keep_alive = req.keep_alive();
+        let head_only = *req.method() == Method::Head;
+        let resp = router.handle(&mut req);
+        let keep_alive = keep_alive
+            && !resp
+                .header("Connection")
+                .is_some_and(|v| has_token(v, "close"));
+        if resp.write_to(conn.stream(), keep_alive, head_only).is_err() || !keep_alive {
+            break;
+        }
+    }
+}
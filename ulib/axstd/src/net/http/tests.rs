@@ -0,0 +1,207 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::conn::Connection;
+use super::request::Request;
+use super::{Method, Response, Router, MAX_BODY_SIZE};
+use crate::io::{self, prelude::*};
+
+/// An in-memory stream, from which requests are read and to which responses
+/// are written.
+struct MockStream {
+    input: Vec<u8>,
+    pos: usize,
+    output: Vec<u8>,
+    /// Whether reads at the end of the input time out instead of returning
+    /// the end of the stream.
+    idle: bool,
+}
+
+impl MockStream {
+    fn new(input: &[u8]) -> Self {
+        Self {
+            input: input.to_vec(),
+            pos: 0,
+            output: Vec::new(),
+            idle: false,
+        }
+    }
+}
+
+impl Read for MockStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.idle && self.pos == self.input.len() {
+            return Err(io::Error::WouldBlock);
+        }
+        // return at most 7 bytes at a time to exercise partial reads
+        let n = buf.len().min(self.input.len() - self.pos).min(7);
+        buf[..n].copy_from_slice(&self.input[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl Write for MockStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn parse(input: &[u8]) -> io::Result<Option<Request>> {
+    Request::read_from(&mut Connection::new(MockStream::new(input)), None)
+}
+
+/// Serves `input` with a router echoing the method, path and body, and
+/// returns the raw responses.
+fn serve(input: &[u8]) -> String {
+    serve_stream(MockStream::new(input))
+}
+
+fn serve_stream(stream: MockStream) -> String {
+    let router = Router::new().route(Method::Post, "/echo", |req| {
+        Response::text(format!(
+            "{} {} {}",
+            req.method(),
+            req.path(),
+            String::from_utf8_lossy(req.body())
+        ))
+    });
+    let mut conn = Connection::new(stream);
+    super::server::serve(&mut conn, None, &router);
+    String::from_utf8(core::mem::take(&mut conn.stream().output)).unwrap()
+}
+
+#[test]
+fn request_line() {
+    let req = parse(b"\r\nGET /a%20b?x=1&y=a+b HTTP/1.1\r\nHost: h\r\n\r\n")
+        .unwrap()
+        .unwrap();
+    assert_eq!(*req.method(), Method::Get);
+    assert_eq!(req.path(), "/a b");
+    assert_eq!(req.query("x"), Some("1"));
+    assert_eq!(req.query("y"), Some("a b"));
+    assert_eq!(req.header("host"), Some("h"));
+    assert!(req.keep_alive());
+
+    assert!(parse(b"").unwrap().is_none());
+    for line in [
+        &b"GET /\r\n\r\n"[..],
+        b"GET  / HTTP/1.1\r\n\r\n",
+        b"GET / HTTP/1.1 x\r\n\r\n",
+        b"GET /%zz HTTP/1.1\r\n\r\n",
+        b"GET / HTTP/1.1\r\nno colon\r\n\r\n",
+    ] {
+        assert_eq!(parse(line).unwrap_err(), io::Error::InvalidData);
+    }
+    assert_eq!(
+        parse(b"GET / HTTP/2.0\r\n\r\n").unwrap_err(),
+        io::Error::Unsupported
+    );
+    assert_eq!(
+        parse(b"GET / HTTP/1.1\r\nHost: h").unwrap_err(),
+        io::Error::UnexpectedEof
+    );
+}
+
+#[test]
+fn chunked_body() {
+    let req = parse(
+        b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+          5;ext=1\r\nhello\r\n7\r\n, world\r\n0\r\nTrailer: x\r\n\r\n",
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(req.body(), b"hello, world");
+
+    assert_eq!(
+        parse(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n").unwrap_err(),
+        io::Error::InvalidData
+    );
+    assert_eq!(
+        parse(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nabc\r\n0\r\n\r\n")
+            .unwrap_err(),
+        io::Error::InvalidData
+    );
+}
+
+#[test]
+fn body_too_large() {
+    // a chunk size near `usize::MAX` must not overflow the size check
+    let huge = format!(
+        "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+         1\r\na\r\n{:x}\r\n",
+        usize::MAX
+    );
+    assert_eq!(parse(huge.as_bytes()).unwrap_err(), io::Error::StorageFull);
+    let long = format!(
+        "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+        MAX_BODY_SIZE + 1
+    );
+    assert_eq!(parse(long.as_bytes()).unwrap_err(), io::Error::StorageFull);
+    assert!(serve(huge.as_bytes()).starts_with("HTTP/1.1 413 "));
+}
+
+#[test]
+fn keep_alive() {
+    let out = serve(
+        b"POST /echo HTTP/1.1\r\nContent-Length: 3\r\n\r\nabc\
+          POST /echo HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nde\r\n0\r\n\r\n\
+          POST /echo HTTP/1.1\r\nConnection: close\r\nContent-Length: 0\r\n\r\n\
+          POST /echo HTTP/1.1\r\nContent-Length: 0\r\n\r\n",
+    );
+    let responses: Vec<&str> = out.split("HTTP/1.1 ").skip(1).collect();
+    assert_eq!(responses.len(), 3); // the last request follows a close
+    assert!(responses[0].contains("Connection: keep-alive\r\n"));
+    assert!(responses[0].ends_with("POST /echo abc"));
+    assert!(responses[1].ends_with("POST /echo de"));
+    assert!(responses[2].contains("Connection: close\r\n"));
+
+    // HTTP/1.0 closes by default
+    let out = serve(b"POST /echo HTTP/1.0\r\n\r\nPOST /echo HTTP/1.0\r\n\r\n");
+    assert_eq!(out.matches("HTTP/1.1 200 ").count(), 1);
+    assert!(out.contains("Connection: close\r\n"));
+
+    let out = serve(b"GET /missing HTTP/1.1\r\n\r\nGARBAGE\r\n\r\n");
+    assert!(out.starts_with("HTTP/1.1 404 "));
+    assert!(out.contains("HTTP/1.1 400 "));
+}
+
+#[test]
+fn read_timeout() {
+    // an idle keep-alive connection, or a request cut short by the read
+    // timeout, is closed without a response
+    for (input, count) in [
+        (&b"POST /echo HTTP/1.1\r\nContent-Length: 1\r\n\r\na"[..], 1),
+        (b"POST /echo HTTP/1.1\r\nContent-Length: 2\r\n\r\na", 0),
+        (b"POST /echo HTTP/1.1\r\nHost", 0),
+    ] {
+        let mut stream = MockStream::new(input);
+        stream.idle = true;
+        assert_eq!(serve_stream(stream).matches("HTTP/1.1 ").count(), count);
+    }
+}
+
+#[cfg(feature = "fs")]
+#[test]
+fn range() {
+    use super::files::parse_range;
+
+    assert_eq!(parse_range("bytes=0-9", 100), Some(Some((0, 9))));
+    assert_eq!(parse_range("bytes=90-", 100), Some(Some((90, 99))));
+    assert_eq!(parse_range("bytes=90-200", 100), Some(Some((90, 99))));
+    assert_eq!(parse_range("bytes=-10", 100), Some(Some((90, 99))));
+    assert_eq!(parse_range("bytes=-200", 100), Some(Some((0, 99))));
+    assert_eq!(parse_range("bytes=100-", 100), Some(None));
+    assert_eq!(parse_range("bytes=5-4", 100), Some(None));
+    assert_eq!(parse_range("bytes=-0", 100), Some(None));
+    assert_eq!(parse_range("bytes=0-", 0), Some(None));
+    assert_eq!(parse_range("bytes=0-1,5-6", 100), None);
+    assert_eq!(parse_range("items=0-1", 100), None);
+    assert_eq!(parse_range("bytes=a-b", 100), None);
+}
@@ -0,0 +1,55 @@
+//! Network interfaces and VLAN sub-interfaces.
+//!
+//! An 802.1Q VLAN sub-interface such as `eth0.100` has its own IPv4 address
+//! on the NIC. Frames sent from the address are tagged with the VLAN ID, and
+//! tagged frames received are delivered only to their sub-interface.
+//! Sub-interfaces can also be created at boot by the `VLANS` build option.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::net::{iface, Ipv4Addr};
+//!
+//! let name = iface::add_vlan(100, Ipv4Addr::new(192, 168, 100, 2), 24, None).unwrap();
+//! assert_eq!(name, "eth0.100");
+//! for config in iface::interfaces() {
+//!     println!("{}: {}/{}", config.name, config.ip, config.prefix_len);
+//! }
+//! ```
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::Ipv4Addr;
+use crate::io;
+use arceos_api::net as api;
+
+/// The configuration of a network interface.
+pub use arceos_api::net::AxNetIfConfig as InterfaceConfig;
+
+/// Returns the configuration of all network interfaces.
+pub fn interfaces() -> Vec<InterfaceConfig> {
+    api::ax_net_interfaces()
+}
+
+/// Creates the VLAN sub-interface with VLAN ID `vid` (1 to 4094) and the
+/// address `ip/prefix_len`, returns its name.
+///
+/// Packets sent from `ip` to other subnets go through `gateway`, which must
+/// be in the subnet, or are dropped if it's `None`. The subnet must not
+/// overlap the subnets of the other interfaces.
+pub fn add_vlan(
+    vid: u16,
+    ip: Ipv4Addr,
+    prefix_len: u8,
+    gateway: Option<Ipv4Addr>,
+) -> io::Result<String> {
+    api::ax_net_add_vlan(vid, ip, prefix_len, gateway)
+}
+
+/// Removes the VLAN sub-interface with VLAN ID `vid`.
+pub fn remove_vlan(vid: u16) -> io::Result<()> {
+    api::ax_net_remove_vlan(vid)
+}
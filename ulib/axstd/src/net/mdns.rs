@@ -0,0 +1,39 @@
+//! Multicast DNS responder.
+//!
+//! The responder makes the host reachable as `<hostname>.local` on the local
+//! link, and advertises services with DNS-SD, so that they are found by
+//! `avahi-browse` or Bonjour.
+//!
+//! The responder can also be started at boot by the `MDNS_HOST` build option.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::net::mdns;
+//!
+//! mdns::start("device-42").unwrap(); // ping device-42.local
+//! mdns::add_service("My Web Server", "_http._tcp", 80, &["path=/"]).unwrap();
+//! ```
+
+use crate::io;
+use arceos_api::net as api;
+
+/// Starts answering queries for `<hostname>.local`.
+///
+/// The `.local` suffix of `hostname` is optional.
+pub fn start(hostname: &str) -> io::Result<()> {
+    api::ax_mdns_start(hostname)
+}
+
+/// Advertises the service `instance` of type `service` (e.g. `_http._tcp`)
+/// listening on `port`.
+///
+/// `txt` is the list of `key=value` strings describing the service.
+pub fn add_service(instance: &str, service: &str, port: u16, txt: &[&str]) -> io::Result<()> {
+    api::ax_mdns_add_service(instance, service, port, txt)
+}
+
+/// Stops the responder, asking other hosts to forget its records.
+pub fn stop() -> io::Result<()> {
+    api::ax_mdns_stop()
+}
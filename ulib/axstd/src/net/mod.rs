@@ -7,22 +7,51 @@
 //!
 //! * [`TcpListener`] and [`TcpStream`] provide functionality for communication over TCP
 //! * [`UdpSocket`] provides functionality for communication over UDP
+//! * [`AsyncTcpListener`], [`AsyncTcpStream`] and [`AsyncUdpSocket`] are their
+//!   nonblocking counterparts returning futures, which can be run by
+//!   [`executor`](crate::executor)
 //! * [`IpAddr`] represents IP addresses of either IPv4 or IPv6; [`Ipv4Addr`] and
 //!   [`Ipv6Addr`] are respectively IPv4 and IPv6 addresses
 //! * [`SocketAddr`] represents socket addresses of either IPv4 or IPv6; [`SocketAddrV4`]
 //!   and [`SocketAddrV6`] are respectively IPv4 and IPv6 socket addresses
 //! * [`ToSocketAddrs`] is a trait that is used for generic address resolution when interacting
 //!   with networking objects like [`TcpListener`], [`TcpStream`] or [`UdpSocket`]
+//! * [`capture`] records frames on the network device in the pcap format
+//! * [`dns`] configures the name servers used to resolve host names
+//! * [`iface`] lists the network interfaces and manages VLAN sub-interfaces
+//! * [`mdns`] makes the host and its services discoverable on the local link
+//! * [`http`] is a minimal HTTP/1.1 server with routing and static files (with
+//!   the `alloc` feature)
+//! * [`TlsStream`] provides TLS 1.2/1.3 sessions over a [`TcpStream`] (with the
+//!   `net-tls` feature)
 
+mod async_tcp;
+mod async_udp;
 mod socket_addr;
 mod tcp;
 mod udp;
 
+pub mod bridge;
+pub mod capture;
+pub mod dns;
+#[cfg(feature = "alloc")]
+pub mod http;
+pub mod iface;
+pub mod mdns;
+pub mod router;
+#[cfg(feature = "net-tls")]
+pub mod tls;
+
+pub use self::async_tcp::{AsyncTcpListener, AsyncTcpStream};
+pub use self::async_udp::AsyncUdpSocket;
 pub use self::socket_addr::{IpAddr, Ipv4Addr, Ipv6Addr};
 pub use self::socket_addr::{SocketAddr, SocketAddrV4, SocketAddrV6, ToSocketAddrs};
 pub use self::tcp::{TcpListener, TcpStream};
 pub use self::udp::UdpSocket;
 
+#[cfg(feature = "net-tls")]
+pub use self::tls::TlsStream;
+
 use crate::io;
 
 fn each_addr<A: ToSocketAddrs, F, T>(addr: A, mut f: F) -> io::Result<T>
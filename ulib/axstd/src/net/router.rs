@@ -0,0 +1,88 @@
+//! IPv4 forwarding and NAT between the network devices.
+//!
+//! When forwarding is started, each NIC other than `eth0` gets its own IPv4
+//! address, and packets are routed between the NICs by their subnets and the
+//! default gateway of `eth0`. With masquerade, the packets leaving through
+//! `eth0` get its address, and port forwarding rules make inside hosts
+//! reachable through ports of `eth0`. More than one NIC needs the
+//! `driver-dyn` feature.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::net::{router, Ipv4Addr, SocketAddrV4};
+//!
+//! router::start(&[(Ipv4Addr::new(192, 168, 100, 1), 24)]).unwrap();
+//! router::set_masquerade(true).unwrap();
+//! router::add_port_forward(router::PortForward {
+//!     protocol: router::NatProtocol::Tcp,
+//!     external_port: 8080,
+//!     internal: SocketAddrV4::new(Ipv4Addr::new(192, 168, 100, 2), 80),
+//! })
+//! .unwrap();
+//! loop {
+//!     router::poll().unwrap();
+//! }
+//! ```
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use super::Ipv4Addr;
+use crate::io;
+use arceos_api::net as api;
+
+/// Statistics of IP forwarding.
+pub use arceos_api::net::AxForwardStats as ForwardStats;
+/// A connection tracked by NAT.
+pub use arceos_api::net::AxNatConnection as NatConnection;
+/// The transport protocol of a NAT mapping.
+pub use arceos_api::net::AxNatProtocol as NatProtocol;
+/// A rule forwarding a port of `eth0` to an inside address.
+pub use arceos_api::net::AxPortForward as PortForward;
+
+/// Starts forwarding IPv4 packets between the NICs.
+///
+/// `addrs` are the addresses and prefix lengths of `eth1`, `eth2` and so on.
+pub fn start(addrs: &[(Ipv4Addr, u8)]) -> io::Result<()> {
+    api::ax_ip_forward_start(addrs)
+}
+
+/// Stops forwarding IPv4 packets.
+pub fn stop() -> io::Result<()> {
+    api::ax_ip_forward_stop()
+}
+
+/// Receives the pending packets from the NICs and forwards them.
+///
+/// Packets are also forwarded whenever the network stack is polled by socket
+/// operations.
+pub fn poll() -> io::Result<()> {
+    api::ax_poll_interfaces()
+}
+
+/// Returns the statistics of IP forwarding, or `None` if it is not started.
+pub fn stats() -> Option<ForwardStats> {
+    api::ax_ip_forward_stats()
+}
+
+/// Enables or disables the source NAT of the packets leaving through `eth0`.
+pub fn set_masquerade(enabled: bool) -> io::Result<()> {
+    api::ax_nat_set_masquerade(enabled)
+}
+
+/// Forwards a TCP or UDP port of `eth0` to an inside address.
+pub fn add_port_forward(rule: PortForward) -> io::Result<()> {
+    api::ax_nat_add_port_forward(rule)
+}
+
+/// Stops forwarding the port of `eth0`.
+pub fn remove_port_forward(protocol: NatProtocol, external_port: u16) -> io::Result<()> {
+    api::ax_nat_remove_port_forward(protocol, external_port)
+}
+
+/// Returns the connections tracked by NAT.
+pub fn connections() -> Vec<NatConnection> {
+    api::ax_nat_connections()
+}
@@ -1,5 +1,6 @@
 use super::{SocketAddr, ToSocketAddrs};
 use crate::io::{self, prelude::*};
+use core::time::Duration;
 
 use arceos_api::net::{self as api, AxTcpSocketHandle};
 
@@ -43,6 +44,19 @@ impl TcpStream {
     pub fn shutdown(&self) -> io::Result<()> {
         api::ax_tcp_shutdown(&self.0)
     }
+
+    /// Sets the read timeout of this stream.
+    ///
+    /// If the value is `None`, [`read`] calls block indefinitely. Otherwise
+    /// they fail with [`WouldBlock`] after the timeout. A zero [`Duration`]
+    /// is rejected with [`InvalidInput`].
+    ///
+    /// [`read`]: Read::read
+    /// [`WouldBlock`]: io::Error::WouldBlock
+    /// [`InvalidInput`]: io::Error::InvalidInput
+    pub fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        api::ax_tcp_set_read_timeout(&self.0, dur)
+    }
 }
 
 impl Read for TcpStream {
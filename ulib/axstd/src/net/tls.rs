@@ -0,0 +1,448 @@
+//! TLS 1.2/1.3 streams on top of TCP, backed by [rustls].
+//!
+//! A [`TlsStream`] wraps a connected [`TcpStream`] and performs the TLS
+//! handshake as a client ([`TlsStream::connect`]) or as a server
+//! ([`TlsStream::accept`]). Once established, it implements [`Read`] and
+//! [`Write`] for the decrypted application data.
+//!
+//! Cryptography is provided by a rustls [`CryptoProvider`] chosen by the
+//! application. With the `net-tls-rustcrypto` feature, [`client_config`] and
+//! [`server_config`] use the pure-Rust [RustCrypto] provider, which is only
+//! released as a pre-release and hasn't been audited, so it must be opted
+//! into explicitly.
+//!
+//! Random numbers come from the CSPRNG of `axhal`, and certificate validity
+//! is checked against the wall clock. Without an entropy source to seed the
+//! CSPRNG (see `axhal::random` for the supported ones), handshakes fail.
+//!
+//! `scripts/net/test-tls.sh` tests the client against `openssl s_server` on
+//! the host with TLS 1.2 and 1.3.
+//!
+//! [rustls]: https://docs.rs/rustls
+//! [RustCrypto]: https://github.com/RustCrypto/rustls-rustcrypto
+
+extern crate alloc;
+
+use alloc::{string::ToString, sync::Arc, vec, vec::Vec};
+use core::fmt;
+use core::time::Duration;
+
+use axerrno::{ax_err, ax_err_type};
+use rustls::client::UnbufferedClientConnection;
+use rustls::pki_types::{ServerName, UnixTime};
+use rustls::server::UnbufferedServerConnection;
+use rustls::time_provider::TimeProvider;
+use rustls::unbuffered::{
+    AppDataRecord, ConnectionState, EncodeError, EncryptError, InsufficientSizeError,
+    UnbufferedStatus,
+};
+use rustls::RootCertStore;
+
+use super::TcpStream;
+use crate::io::{self, prelude::*};
+
+pub use rustls::crypto::CryptoProvider;
+pub use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+pub use rustls::{ClientConfig, ServerConfig};
+
+/// Size of the buffers holding TLS records, enough for one full record.
+const TLS_BUF_LEN: usize = 16 * 1024 + 512;
+
+fn random_bytes(buf: &mut [u8]) -> Result<(), getrandom::Error> {
+    // handshakes fail rather than use predictable keys without entropy
+    arceos_api::sys::ax_fill_random(buf).map_err(|_| getrandom::Error::UNSUPPORTED)
+}
+
+getrandom::register_custom_getrandom!(random_bytes);
+
+/// Supplies the wall clock to rustls for certificate validation.
+#[derive(Debug)]
+struct WallClock;
+
+impl TimeProvider for WallClock {
+    fn current_time(&self) -> Option<UnixTime> {
+        let now: Duration = arceos_api::time::ax_wall_time();
+        Some(UnixTime::since_unix_epoch(now))
+    }
+}
+
+fn tls_err<E: fmt::Display>(e: E) -> io::Error {
+    ax_err_type!(InvalidData, alloc::format!("TLS error: {}", e))
+}
+
+fn encode_err(e: EncodeError) -> Option<InsufficientSizeError> {
+    match e {
+        EncodeError::InsufficientSize(e) => Some(e),
+        _ => None,
+    }
+}
+
+fn encrypt_err(e: EncryptError) -> Option<InsufficientSizeError> {
+    match e {
+        EncryptError::InsufficientSize(e) => Some(e),
+        _ => None,
+    }
+}
+
+/// Builds a client configuration with the cryptography of `provider`,
+/// trusting the given root certificates.
+///
+/// Both TLS 1.2 and TLS 1.3 are offered to the server.
+pub fn client_config_with_provider(
+    provider: Arc<CryptoProvider>,
+    roots: Vec<CertificateDer<'static>>,
+) -> io::Result<Arc<ClientConfig>> {
+    let mut root_store = RootCertStore::empty();
+    for cert in roots {
+        root_store.add(cert).map_err(tls_err)?;
+    }
+    let config = ClientConfig::builder_with_details(provider, Arc::new(WallClock))
+        .with_protocol_versions(rustls::ALL_VERSIONS)
+        .map_err(tls_err)?
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+/// Builds a server configuration with the cryptography of `provider`,
+/// presenting the given certificate chain, whose first certificate matches
+/// `key`.
+///
+/// Both TLS 1.2 and TLS 1.3 are accepted from the client.
+pub fn server_config_with_provider(
+    provider: Arc<CryptoProvider>,
+    cert_chain: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+) -> io::Result<Arc<ServerConfig>> {
+    let config = ServerConfig::builder_with_details(provider, Arc::new(WallClock))
+        .with_protocol_versions(rustls::ALL_VERSIONS)
+        .map_err(tls_err)?
+        .with_no_client_auth()
+        .with_single_cert(cert_chain, key)
+        .map_err(tls_err)?;
+    Ok(Arc::new(config))
+}
+
+/// Builds a client configuration with the RustCrypto provider, see
+/// [`client_config_with_provider`].
+#[cfg(feature = "net-tls-rustcrypto")]
+pub fn client_config(roots: Vec<CertificateDer<'static>>) -> io::Result<Arc<ClientConfig>> {
+    client_config_with_provider(Arc::new(rustls_rustcrypto::provider()), roots)
+}
+
+/// Builds a server configuration with the RustCrypto provider, see
+/// [`server_config_with_provider`].
+#[cfg(feature = "net-tls-rustcrypto")]
+pub fn server_config(
+    cert_chain: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+) -> io::Result<Arc<ServerConfig>> {
+    server_config_with_provider(Arc::new(rustls_rustcrypto::provider()), cert_chain, key)
+}
+
+/// Parses all certificates in a PEM file.
+#[cfg(feature = "fs")]
+pub fn load_certs(path: &str) -> io::Result<Vec<CertificateDer<'static>>> {
+    use rustls::pki_types::pem::PemObject;
+
+    let pem = crate::fs::read(path)?;
+    let certs = CertificateDer::pem_slice_iter(&pem)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(tls_err)?;
+    if certs.is_empty() {
+        return ax_err!(InvalidData, "no certificate found");
+    }
+    Ok(certs)
+}
+
+/// Parses the first private key (PKCS#1, PKCS#8 or SEC1) in a PEM file.
+#[cfg(feature = "fs")]
+pub fn load_private_key(path: &str) -> io::Result<PrivateKeyDer<'static>> {
+    use rustls::pki_types::pem::PemObject;
+
+    let pem = crate::fs::read(path)?;
+    PrivateKeyDer::from_pem_slice(&pem).map_err(tls_err)
+}
+
+enum Connection {
+    Client(UnbufferedClientConnection),
+    Server(UnbufferedServerConnection),
+}
+
+/// What the application wants to do when the connection is writable.
+#[derive(Clone, Copy)]
+enum Action<'a> {
+    None,
+    Write(&'a [u8]),
+    Close,
+}
+
+/// Result of processing the connection once.
+enum Event {
+    /// Some records have been processed, the connection should be processed
+    /// again.
+    Progress,
+    /// More TLS records must be received from the peer.
+    WantRead,
+    /// The handshake is complete; holds the number of application bytes
+    /// encrypted and sent.
+    Ready(usize),
+    /// The peer has closed the connection.
+    Closed,
+}
+
+/// The TCP stream and buffers of a TLS connection, split from the rustls
+/// state so that both can be borrowed at the same time.
+struct TlsIo {
+    tcp: TcpStream,
+    outgoing: Vec<u8>,
+    outgoing_used: usize,
+    plaintext: Vec<u8>,
+    plaintext_pos: usize,
+}
+
+impl TlsIo {
+    fn flush(&mut self) -> io::Result<()> {
+        self.tcp.write_all(&self.outgoing[..self.outgoing_used])?;
+        self.outgoing_used = 0;
+        Ok(())
+    }
+
+    /// Calls `f` with the free space of the outgoing buffer, growing it as
+    /// required.
+    fn encode<F>(&mut self, mut f: F) -> io::Result<usize>
+    where
+        F: FnMut(&mut [u8]) -> Result<usize, Option<InsufficientSizeError>>,
+    {
+        loop {
+            match f(&mut self.outgoing[self.outgoing_used..]) {
+                Ok(n) => {
+                    self.outgoing_used += n;
+                    return Ok(n);
+                }
+                Err(Some(InsufficientSizeError { required_size })) => {
+                    self.outgoing.resize(self.outgoing_used + required_size, 0);
+                }
+                Err(None) => return ax_err!(BadState, "TLS encode failed"),
+            }
+        }
+    }
+
+    fn process<Data>(
+        &mut self,
+        status: UnbufferedStatus<'_, '_, Data>,
+        action: Action<'_>,
+    ) -> io::Result<(usize, Event)> {
+        let UnbufferedStatus { mut discard, state } = status;
+        let event = match state.map_err(tls_err)? {
+            ConnectionState::ReadTraffic(mut state) => {
+                while let Some(record) = state.next_record() {
+                    let AppDataRecord {
+                        discard: len,
+                        payload,
+                    } = record.map_err(tls_err)?;
+                    discard += len;
+                    self.plaintext.extend_from_slice(payload);
+                }
+                Event::Progress
+            }
+            ConnectionState::EncodeTlsData(mut state) => {
+                self.encode(|buf| state.encode(buf).map_err(encode_err))?;
+                Event::Progress
+            }
+            ConnectionState::TransmitTlsData(state) => {
+                self.flush()?;
+                state.done();
+                Event::Progress
+            }
+            ConnectionState::BlockedHandshake => Event::WantRead,
+            ConnectionState::WriteTraffic(mut state) => match action {
+                Action::None => Event::Ready(0),
+                Action::Write(data) => {
+                    self.encode(|buf| state.encrypt(data, buf).map_err(encrypt_err))?;
+                    self.flush()?;
+                    Event::Ready(data.len())
+                }
+                Action::Close => {
+                    self.encode(|buf| state.queue_close_notify(buf).map_err(encrypt_err))?;
+                    self.flush()?;
+                    Event::Ready(0)
+                }
+            },
+            ConnectionState::PeerClosed | ConnectionState::Closed => Event::Closed,
+            _ => return ax_err!(Unsupported, "unexpected TLS connection state"),
+        };
+        Ok((discard, event))
+    }
+}
+
+/// A TLS session over a TCP stream.
+pub struct TlsStream {
+    conn: Connection,
+    io: TlsIo,
+    incoming: Vec<u8>,
+    incoming_used: usize,
+    /// Whether the peer has sent `close_notify`.
+    peer_closed: bool,
+}
+
+impl TlsStream {
+    /// Performs the TLS handshake as a client over a connected TCP stream.
+    ///
+    /// `server_name` is the DNS name (or IP address) the server certificate
+    /// is verified against, it is also sent in the SNI extension.
+    pub fn connect(
+        tcp: TcpStream,
+        server_name: &str,
+        config: Arc<ClientConfig>,
+    ) -> io::Result<TlsStream> {
+        let name = ServerName::try_from(server_name.to_string())
+            .map_err(|_| ax_err_type!(InvalidInput, "invalid TLS server name"))?;
+        let conn = UnbufferedClientConnection::new(config, name).map_err(tls_err)?;
+        Self::handshake(tcp, Connection::Client(conn))
+    }
+
+    /// Performs the TLS handshake as a server over an accepted TCP stream.
+    pub fn accept(tcp: TcpStream, config: Arc<ServerConfig>) -> io::Result<TlsStream> {
+        let conn = UnbufferedServerConnection::new(config).map_err(tls_err)?;
+        Self::handshake(tcp, Connection::Server(conn))
+    }
+
+    /// Returns the underlying TCP stream.
+    pub fn get_ref(&self) -> &TcpStream {
+        &self.io.tcp
+    }
+
+    /// Sends a `close_notify` alert to the peer and shuts down the TCP
+    /// connection.
+    pub fn shutdown(&mut self) -> io::Result<()> {
+        loop {
+            match self.step(Action::Close)? {
+                Event::Progress => {}
+                Event::WantRead => {
+                    if self.fill_incoming()? == 0 {
+                        break;
+                    }
+                }
+                Event::Ready(_) | Event::Closed => break,
+            }
+        }
+        self.io.tcp.shutdown()
+    }
+
+    fn handshake(tcp: TcpStream, conn: Connection) -> io::Result<TlsStream> {
+        let mut stream = TlsStream {
+            conn,
+            io: TlsIo {
+                tcp,
+                outgoing: vec![0; TLS_BUF_LEN],
+                outgoing_used: 0,
+                plaintext: Vec::new(),
+                plaintext_pos: 0,
+            },
+            incoming: vec![0; TLS_BUF_LEN],
+            incoming_used: 0,
+            peer_closed: false,
+        };
+        loop {
+            match stream.step(Action::None)? {
+                Event::Progress => {}
+                Event::WantRead => {
+                    if stream.fill_incoming()? == 0 {
+                        return ax_err!(ConnectionReset, "TLS handshake failed: connection closed");
+                    }
+                }
+                Event::Ready(_) => return Ok(stream),
+                Event::Closed => {
+                    return ax_err!(ConnectionReset, "TLS handshake failed: connection closed")
+                }
+            }
+        }
+    }
+
+    /// Processes the received records once.
+    fn step(&mut self, action: Action<'_>) -> io::Result<Event> {
+        let incoming = &mut self.incoming[..self.incoming_used];
+        let (discard, event) = match &mut self.conn {
+            Connection::Client(conn) => self.io.process(conn.process_tls_records(incoming), action),
+            Connection::Server(conn) => self.io.process(conn.process_tls_records(incoming), action),
+        }?;
+        if discard > 0 {
+            self.incoming.copy_within(discard..self.incoming_used, 0);
+            self.incoming_used -= discard;
+        }
+        Ok(event)
+    }
+
+    /// Receives more TLS records from the peer, returns the number of bytes
+    /// received, or 0 if the TCP connection has been closed.
+    fn fill_incoming(&mut self) -> io::Result<usize> {
+        if self.incoming_used == self.incoming.len() {
+            self.incoming.resize(self.incoming.len() + TLS_BUF_LEN, 0);
+        }
+        let n = self.io.tcp.read(&mut self.incoming[self.incoming_used..])?;
+        self.incoming_used += n;
+        Ok(n)
+    }
+}
+
+/// Reads return 0 at the end of data only after the peer has sent
+/// `close_notify`, and fail with `UnexpectedEof` if the TCP connection is
+/// closed before.
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let io = &mut self.io;
+            if io.plaintext_pos < io.plaintext.len() {
+                let data = &io.plaintext[io.plaintext_pos..];
+                let n = data.len().min(buf.len());
+                buf[..n].copy_from_slice(&data[..n]);
+                io.plaintext_pos += n;
+                if io.plaintext_pos == io.plaintext.len() {
+                    io.plaintext.clear();
+                    io.plaintext_pos = 0;
+                }
+                return Ok(n);
+            }
+            if self.peer_closed {
+                return Ok(0);
+            }
+            match self.step(Action::None)? {
+                Event::Progress => {}
+                Event::WantRead | Event::Ready(_) => {
+                    if self.fill_incoming()? == 0 {
+                        // a truncation attack can't be told from the end of data
+                        return ax_err!(
+                            UnexpectedEof,
+                            "TLS read failed: connection closed without close_notify"
+                        );
+                    }
+                }
+                Event::Closed => self.peer_closed = true,
+            }
+        }
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            match self.step(Action::Write(buf))? {
+                Event::Progress => {}
+                Event::WantRead => {
+                    if self.fill_incoming()? == 0 {
+                        return ax_err!(ConnectionReset, "TLS write failed: connection closed");
+                    }
+                }
+                Event::Ready(n) => return Ok(n),
+                Event::Closed => {
+                    return ax_err!(ConnectionReset, "TLS write failed: connection closed")
+                }
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
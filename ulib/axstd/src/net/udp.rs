@@ -1,4 +1,4 @@
-use super::{SocketAddr, ToSocketAddrs};
+use super::{Ipv4Addr, SocketAddr, ToSocketAddrs};
 use crate::io;
 
 use arceos_api::net::{self as api, AxUdpSocketHandle};
@@ -93,4 +93,25 @@ impl UdpSocket {
     pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
         api::ax_udp_recv(&self.0, buf)
     }
+
+    /// Executes an operation of the `IP_ADD_MEMBERSHIP` type.
+    ///
+    /// This function specifies a new multicast group for this socket to join.
+    /// The address must be a valid multicast address. There is only one
+    /// network interface, so `interface` is ignored.
+    pub fn join_multicast_v4(&self, multiaddr: &Ipv4Addr, _interface: &Ipv4Addr) -> io::Result<()> {
+        api::ax_udp_join_multicast_v4(&self.0, *multiaddr)
+    }
+
+    /// Executes an operation of the `IP_DROP_MEMBERSHIP` type.
+    ///
+    /// For more information about this option, see
+    /// [`join_multicast_v4`](Self::join_multicast_v4).
+    pub fn leave_multicast_v4(
+        &self,
+        multiaddr: &Ipv4Addr,
+        _interface: &Ipv4Addr,
+    ) -> io::Result<()> {
+        api::ax_udp_leave_multicast_v4(&self.0, *multiaddr)
+    }
 }